@@ -1,5 +1,9 @@
+pub mod cohere;
+pub mod voyage;
+
+use crate::error::InvalidRequestError;
 use crate::events::{JsonValue, RecordResult, SPAN_OPENAI};
-use crate::model::error::ModelError;
+use crate::model::error::{AuthorizationError, ModelError};
 use crate::model::openai::openai_client;
 use crate::model::types::LLMFinishEvent;
 use crate::model::types::ModelEvent;
@@ -7,19 +11,26 @@ use crate::model::types::ModelEventType;
 use crate::model::types::ModelFinishReason;
 use crate::model::CredentialsIdent;
 use crate::types::credentials::ApiKeyCredentials;
-use crate::types::embed::OpenAiEmbeddingParams;
+use crate::types::embed::{EmbeddingInputType, NativeEmbeddingParams, OpenAiEmbeddingParams};
 use crate::types::gateway::CompletionModelUsage;
+use crate::types::provider::InferenceModelProvider;
 use crate::GatewayError;
 use crate::GatewayResult;
 use async_openai::config::OpenAIConfig;
 use async_openai::types::{CreateEmbeddingRequestArgs, CreateEmbeddingResponse, EmbeddingInput};
 use async_openai::Client;
+use cohere::CohereEmbed;
+use futures::future::Either;
 use futures::stream::TryReadyChunksError;
 use futures::{Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::Value;
+use std::time::Duration;
 use tracing::Instrument;
 use tracing::{field, Span};
 use valuable::Valuable;
+use voyage::VoyageEmbed;
 
 macro_rules! target {
     () => {
@@ -69,6 +80,10 @@ impl OpenAIEmbed {
         })
     }
 
+    pub fn credentials_ident(&self) -> &CredentialsIdent {
+        &self.credentials_ident
+    }
+
     async fn execute(
         &self,
         input: EmbeddingInput,
@@ -128,6 +143,7 @@ impl OpenAIEmbed {
                     finish_reason: ModelFinishReason::Stop,
                     tool_calls: vec![],
                     credentials_ident: self.credentials_ident.clone(),
+                    generation: None,
                 }),
             )))
             .await
@@ -183,3 +199,266 @@ impl Embed for OpenAIEmbed {
             })
     }
 }
+
+/// Embedding client of the model's inference provider. Cohere and Voyage use their own
+/// APIs, every other provider is expected to be OpenAI compatible.
+#[derive(Clone)]
+pub enum ProviderEmbed {
+    OpenAI(OpenAIEmbed),
+    Cohere(CohereEmbed),
+    Voyage(VoyageEmbed),
+}
+
+impl ProviderEmbed {
+    pub fn new(
+        provider: &InferenceModelProvider,
+        params: OpenAiEmbeddingParams,
+        input_type: Option<EmbeddingInputType>,
+        credentials: Option<&ApiKeyCredentials>,
+        endpoint: Option<&str>,
+    ) -> Result<Self, GatewayError> {
+        let native_params = |provider: &str| {
+            Ok::<_, GatewayError>(NativeEmbeddingParams {
+                model: native_model(provider, params.model.as_deref())?,
+                dimensions: params.dimensions,
+                input_type,
+            })
+        };
+
+        Ok(match provider {
+            InferenceModelProvider::Proxy(name) if name == "cohere" => Self::Cohere(
+                CohereEmbed::new(native_params(name)?, credentials, endpoint)?,
+            ),
+            InferenceModelProvider::Proxy(name) if name == "voyage" => Self::Voyage(
+                VoyageEmbed::new(native_params(name)?, credentials, endpoint)?,
+            ),
+            _ => Self::OpenAI(OpenAIEmbed::new(params, credentials, endpoint)?),
+        })
+    }
+
+    pub fn credentials_ident(&self) -> &CredentialsIdent {
+        match self {
+            Self::OpenAI(embed) => embed.credentials_ident(),
+            Self::Cohere(embed) => embed.credentials_ident(),
+            Self::Voyage(embed) => embed.credentials_ident(),
+        }
+    }
+
+    pub fn provider_name(&self) -> &'static str {
+        match self {
+            Self::OpenAI(_) => SPAN_OPENAI,
+            Self::Cohere(_) => crate::events::SPAN_COHERE,
+            Self::Voyage(_) => crate::events::SPAN_VOYAGE,
+        }
+    }
+}
+
+impl Embed for ProviderEmbed {
+    async fn invoke(
+        &self,
+        input_text: EmbeddingInput,
+        tx: Option<tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+    ) -> GatewayResult<CreateEmbeddingResponse> {
+        match self {
+            Self::OpenAI(embed) => embed.invoke(input_text, tx).await,
+            Self::Cohere(embed) => embed.invoke(input_text, tx).await,
+            Self::Voyage(embed) => embed.invoke(input_text, tx).await,
+        }
+    }
+
+    async fn batched_invoke(
+        &self,
+        inputs: impl Stream<Item = GatewayResult<(String, Vec<Value>)>>,
+    ) -> impl Stream<Item = GatewayResult<Vec<(Vec<f32>, Vec<Value>)>>> {
+        match self {
+            Self::OpenAI(embed) => Either::Left(embed.batched_invoke(inputs).await),
+            Self::Cohere(embed) => Either::Right(Either::Left(embed.batched_invoke(inputs).await)),
+            Self::Voyage(embed) => Either::Right(Either::Right(embed.batched_invoke(inputs).await)),
+        }
+    }
+}
+
+/// Texts of the input, native providers do not accept token arrays
+fn input_texts(input: EmbeddingInput) -> GatewayResult<Vec<String>> {
+    match input {
+        EmbeddingInput::String(text) => Ok(vec![text]),
+        EmbeddingInput::StringArray(texts) => Ok(texts),
+        _ => Err(ModelError::CustomError(
+            "Token array inputs are only supported by OpenAI compatible providers".to_string(),
+        )
+        .into()),
+    }
+}
+
+/// Limits of a native provider call, so a hung provider does not hold the request
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Client of a provider with its own API instead of an OpenAI compatible one
+#[derive(Clone)]
+pub(crate) struct NativeClient {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: String,
+    credentials_ident: CredentialsIdent,
+}
+
+impl NativeClient {
+    /// Without credentials, the API key is read from `api_key_var`
+    pub(crate) fn new(
+        api_url: &str,
+        api_key_var: &str,
+        credentials: Option<&ApiKeyCredentials>,
+        endpoint: Option<&str>,
+    ) -> Result<Self, ModelError> {
+        let api_key = match credentials {
+            Some(credentials) => credentials.api_key.clone(),
+            None => std::env::var(api_key_var).map_err(|_| AuthorizationError::InvalidApiKey)?,
+        };
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| ModelError::CustomError(e.to_string()))?;
+
+        Ok(Self {
+            client,
+            endpoint: endpoint
+                .unwrap_or(api_url)
+                .trim_end_matches('/')
+                .to_string(),
+            api_key,
+            credentials_ident: credentials
+                .map(|_| CredentialsIdent::Own)
+                .unwrap_or(CredentialsIdent::Langdb),
+        })
+    }
+
+    pub(crate) fn credentials_ident(&self) -> &CredentialsIdent {
+        &self.credentials_ident
+    }
+
+    /// Posts a JSON request to `path` of the provider API
+    pub(crate) async fn post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        payload: &impl Serialize,
+    ) -> GatewayResult<T> {
+        let response = self
+            .client
+            .post(format!("{}/{path}", self.endpoint))
+            .bearer_auth(&self.api_key)
+            .json(payload)
+            .send()
+            .await
+            .map_err(|e| ModelError::CustomError(e.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(ModelError::CustomError(format!("{status}: {message}")).into());
+        }
+
+        Ok(response
+            .json()
+            .await
+            .map_err(|e| ModelError::CustomError(e.to_string()))?)
+    }
+}
+
+/// Model name sent to a native provider API, which rejects requests without one
+pub(crate) fn native_model(provider: &str, model: Option<&str>) -> Result<String, GatewayError> {
+    match model.map(str::trim) {
+        Some(model) if !model.is_empty() => Ok(model.to_string()),
+        _ => Err(InvalidRequestError::new(
+            "model",
+            "missing_required_parameter",
+            format!("A model name is required by provider {provider}"),
+        )
+        .into()),
+    }
+}
+
+/// Reports the usage of a native provider call the same way as OpenAI embeddings
+async fn send_usage(
+    tx: Option<&tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+    provider_name: &str,
+    model_name: &str,
+    tokens: u32,
+    credentials_ident: &CredentialsIdent,
+) {
+    let Some(tx) = tx else {
+        return;
+    };
+
+    let _ = tx
+        .send(Some(ModelEvent::new(
+            &Span::current(),
+            ModelEventType::LlmStop(LLMFinishEvent {
+                provider_name: provider_name.to_string(),
+                model_name: model_name.to_string(),
+                output: None,
+                usage: Some(CompletionModelUsage {
+                    input_tokens: tokens,
+                    output_tokens: 0,
+                    total_tokens: tokens,
+                    ..Default::default()
+                }),
+                finish_reason: ModelFinishReason::Stop,
+                tool_calls: vec![],
+                credentials_ident: credentials_ident.clone(),
+                generation: None,
+            }),
+        )))
+        .await;
+}
+
+/// Embeds a stream of inputs in chunks of at most `chunk_size` texts
+fn batch_invoke<'a, E, S>(
+    embed: &'a E,
+    inputs: S,
+    chunk_size: usize,
+) -> impl Stream<Item = GatewayResult<Vec<(Vec<f32>, Vec<Value>)>>> + use<'a, E, S>
+where
+    E: Embed,
+    S: Stream<Item = GatewayResult<(String, Vec<Value>)>>,
+{
+    inputs
+        .try_ready_chunks(chunk_size)
+        .map_err(|TryReadyChunksError(_, e)| e)
+        .map_ok(move |chunk| {
+            let (texts, values): (Vec<String>, Vec<Vec<Value>>) = chunk.into_iter().unzip();
+            async move {
+                let embeddings = embed.invoke(texts.into(), None).await?;
+                Ok((embeddings, values))
+            }
+        })
+        .try_buffered(10)
+        .map_ok(|(embeddings, values)| {
+            embeddings
+                .data
+                .into_iter()
+                .map(|e| e.embedding)
+                .zip(values)
+                .collect()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_native_model() {
+        assert_eq!(
+            native_model("voyage", Some(" voyage-3 ")).unwrap(),
+            "voyage-3"
+        );
+        for model in [None, Some(""), Some(" ")] {
+            assert!(matches!(
+                native_model("voyage", model),
+                Err(GatewayError::InvalidRequest(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use async_openai::types::{CreateEmbeddingResponse, Embedding, EmbeddingInput, EmbeddingUsage};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{field, Instrument};
+
+use super::{batch_invoke, input_texts, send_usage, Embed, NativeClient};
+use crate::events::SPAN_COHERE;
+use crate::model::error::ModelError;
+use crate::model::types::ModelEvent;
+use crate::model::CredentialsIdent;
+use crate::types::credentials::ApiKeyCredentials;
+use crate::types::embed::{EmbeddingInputType, NativeEmbeddingParams};
+use crate::GatewayResult;
+
+const API_URL: &str = "https://api.cohere.com/v2";
+/// Cohere embeds at most 96 texts per request
+const MAX_BATCH_SIZE: usize = 96;
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    texts: Vec<String>,
+    input_type: EmbeddingInputType,
+    embedding_types: [&'static str; 1],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dimension: Option<u16>,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Embeddings,
+    #[serde(default)]
+    meta: Option<Meta>,
+}
+
+#[derive(Deserialize)]
+struct Embeddings {
+    float: Vec<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct Meta {
+    billed_units: Option<BilledUnits>,
+}
+
+#[derive(Deserialize)]
+struct BilledUnits {
+    #[serde(default)]
+    input_tokens: u32,
+}
+
+#[derive(Clone)]
+pub struct CohereEmbed {
+    params: NativeEmbeddingParams,
+    client: NativeClient,
+}
+
+impl CohereEmbed {
+    pub fn new(
+        params: NativeEmbeddingParams,
+        credentials: Option<&ApiKeyCredentials>,
+        endpoint: Option<&str>,
+    ) -> Result<Self, ModelError> {
+        Ok(Self {
+            params,
+            client: NativeClient::new(API_URL, "LANGDB_COHERE_API_KEY", credentials, endpoint)?,
+        })
+    }
+
+    pub fn credentials_ident(&self) -> &CredentialsIdent {
+        self.client.credentials_ident()
+    }
+
+    async fn execute(
+        &self,
+        texts: Vec<String>,
+        tx: Option<&tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+    ) -> GatewayResult<CreateEmbeddingResponse> {
+        let request = EmbedRequest {
+            model: &self.params.model,
+            texts,
+            // Cohere requires the input type, stored texts are the common case
+            input_type: self
+                .params
+                .input_type
+                .unwrap_or(EmbeddingInputType::SearchDocument),
+            embedding_types: ["float"],
+            output_dimension: self.params.dimensions,
+        };
+        let response: EmbedResponse = self.client.post("embed", &request).await?;
+
+        let tokens = response
+            .meta
+            .and_then(|m| m.billed_units)
+            .map_or(0, |b| b.input_tokens);
+        send_usage(
+            tx,
+            SPAN_COHERE,
+            &self.params.model,
+            tokens,
+            self.client.credentials_ident(),
+        )
+        .await;
+
+        Ok(CreateEmbeddingResponse {
+            object: "list".to_string(),
+            model: self.params.model.clone(),
+            data: response
+                .embeddings
+                .float
+                .into_iter()
+                .enumerate()
+                .map(|(index, embedding)| Embedding {
+                    index: index as u32,
+                    object: "embedding".to_string(),
+                    embedding,
+                })
+                .collect(),
+            usage: EmbeddingUsage {
+                prompt_tokens: tokens,
+                total_tokens: tokens,
+            },
+        })
+    }
+}
+
+impl Embed for CohereEmbed {
+    async fn invoke(
+        &self,
+        input_text: EmbeddingInput,
+        tx: Option<tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+    ) -> GatewayResult<CreateEmbeddingResponse> {
+        let input = serde_json::to_string(&input_text)?;
+        let call_span = tracing::info_span!(target: "langdb::user_tracing::models::cohere::embedding", SPAN_COHERE, input = input, output = field::Empty, error = field::Empty);
+
+        let texts = input_texts(input_text)?;
+        let mut data = vec![];
+        let mut usage = EmbeddingUsage {
+            prompt_tokens: 0,
+            total_tokens: 0,
+        };
+        for (batch, chunk) in texts.chunks(MAX_BATCH_SIZE).enumerate() {
+            let response = self
+                .execute(chunk.to_vec(), tx.as_ref())
+                .instrument(call_span.clone())
+                .await?;
+            usage.prompt_tokens += response.usage.prompt_tokens;
+            usage.total_tokens += response.usage.total_tokens;
+            data.extend(response.data.into_iter().map(|mut e| {
+                e.index += (batch * MAX_BATCH_SIZE) as u32;
+                e
+            }));
+        }
+
+        Ok(CreateEmbeddingResponse {
+            object: "list".to_string(),
+            model: self.params.model.clone(),
+            data,
+            usage,
+        })
+    }
+
+    async fn batched_invoke(
+        &self,
+        inputs: impl Stream<Item = GatewayResult<(String, Vec<Value>)>>,
+    ) -> impl Stream<Item = GatewayResult<Vec<(Vec<f32>, Vec<Value>)>>> {
+        batch_invoke(self, inputs, MAX_BATCH_SIZE)
+    }
+}
@@ -0,0 +1,171 @@
+use async_openai::types::{CreateEmbeddingResponse, Embedding, EmbeddingInput, EmbeddingUsage};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{field, Instrument};
+
+use super::{batch_invoke, input_texts, send_usage, Embed, NativeClient};
+use crate::events::SPAN_VOYAGE;
+use crate::model::error::ModelError;
+use crate::model::types::ModelEvent;
+use crate::model::CredentialsIdent;
+use crate::types::credentials::ApiKeyCredentials;
+use crate::types::embed::{EmbeddingInputType, NativeEmbeddingParams};
+use crate::GatewayResult;
+
+const API_URL: &str = "https://api.voyageai.com/v1";
+/// Voyage embeds at most 1000 texts per request
+const MAX_BATCH_SIZE: usize = 1000;
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dimension: Option<u16>,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    data: Vec<Embedding>,
+    usage: Usage,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    total_tokens: u32,
+}
+
+/// Voyage only distinguishes queries from documents
+fn input_type(input_type: Option<EmbeddingInputType>) -> Option<&'static str> {
+    match input_type? {
+        EmbeddingInputType::SearchQuery => Some("query"),
+        EmbeddingInputType::SearchDocument => Some("document"),
+        EmbeddingInputType::Classification | EmbeddingInputType::Clustering => None,
+    }
+}
+
+#[derive(Clone)]
+pub struct VoyageEmbed {
+    params: NativeEmbeddingParams,
+    client: NativeClient,
+}
+
+impl VoyageEmbed {
+    pub fn new(
+        params: NativeEmbeddingParams,
+        credentials: Option<&ApiKeyCredentials>,
+        endpoint: Option<&str>,
+    ) -> Result<Self, ModelError> {
+        Ok(Self {
+            params,
+            client: NativeClient::new(API_URL, "LANGDB_VOYAGE_API_KEY", credentials, endpoint)?,
+        })
+    }
+
+    pub fn credentials_ident(&self) -> &CredentialsIdent {
+        self.client.credentials_ident()
+    }
+
+    async fn execute(
+        &self,
+        input: Vec<String>,
+        tx: Option<&tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+    ) -> GatewayResult<CreateEmbeddingResponse> {
+        let request = EmbedRequest {
+            model: &self.params.model,
+            input,
+            input_type: input_type(self.params.input_type),
+            output_dimension: self.params.dimensions,
+        };
+        let response: EmbedResponse = self.client.post("embeddings", &request).await?;
+
+        let tokens = response.usage.total_tokens;
+        send_usage(
+            tx,
+            SPAN_VOYAGE,
+            &self.params.model,
+            tokens,
+            self.client.credentials_ident(),
+        )
+        .await;
+
+        let mut data = response.data;
+        data.sort_by_key(|e| e.index);
+
+        Ok(CreateEmbeddingResponse {
+            object: "list".to_string(),
+            model: self.params.model.clone(),
+            data,
+            usage: EmbeddingUsage {
+                prompt_tokens: tokens,
+                total_tokens: tokens,
+            },
+        })
+    }
+}
+
+impl Embed for VoyageEmbed {
+    async fn invoke(
+        &self,
+        input_text: EmbeddingInput,
+        tx: Option<tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+    ) -> GatewayResult<CreateEmbeddingResponse> {
+        let input = serde_json::to_string(&input_text)?;
+        let call_span = tracing::info_span!(target: "langdb::user_tracing::models::voyage::embedding", SPAN_VOYAGE, input = input, output = field::Empty, error = field::Empty);
+
+        let texts = input_texts(input_text)?;
+        let mut data = vec![];
+        let mut usage = EmbeddingUsage {
+            prompt_tokens: 0,
+            total_tokens: 0,
+        };
+        for (batch, chunk) in texts.chunks(MAX_BATCH_SIZE).enumerate() {
+            let response = self
+                .execute(chunk.to_vec(), tx.as_ref())
+                .instrument(call_span.clone())
+                .await?;
+            usage.prompt_tokens += response.usage.prompt_tokens;
+            usage.total_tokens += response.usage.total_tokens;
+            data.extend(response.data.into_iter().map(|mut e| {
+                e.index += (batch * MAX_BATCH_SIZE) as u32;
+                e
+            }));
+        }
+
+        Ok(CreateEmbeddingResponse {
+            object: "list".to_string(),
+            model: self.params.model.clone(),
+            data,
+            usage,
+        })
+    }
+
+    async fn batched_invoke(
+        &self,
+        inputs: impl Stream<Item = GatewayResult<(String, Vec<Value>)>>,
+    ) -> impl Stream<Item = GatewayResult<Vec<(Vec<f32>, Vec<Value>)>>> {
+        batch_invoke(self, inputs, MAX_BATCH_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_type() {
+        assert_eq!(
+            input_type(Some(EmbeddingInputType::SearchQuery)),
+            Some("query")
+        );
+        assert_eq!(
+            input_type(Some(EmbeddingInputType::SearchDocument)),
+            Some("document")
+        );
+        assert_eq!(input_type(Some(EmbeddingInputType::Clustering)), None);
+        assert_eq!(input_type(None), None);
+    }
+}
@@ -41,6 +41,51 @@ pub enum GatewayError {
     McpServerError(#[from] Box<McpServerError>),
     #[error(transparent)]
     SendError(#[from] Box<tokio::sync::mpsc::error::SendError<Option<ModelEvent>>>),
+    #[error(transparent)]
+    InvalidRequest(#[from] InvalidRequestError),
+}
+
+/// Request rejected because of a client input, rendered in the error envelope of OpenAI so
+/// clients can point at the offending field
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{message}")]
+pub struct InvalidRequestError {
+    /// Path of the field, `messages[2].tool_call_id` for fields of messages. Empty for
+    /// errors of the request as a whole, sent as `null`.
+    pub param: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl InvalidRequestError {
+    pub fn new(param: impl Into<String>, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            param: param.into(),
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl actix_web::error::ResponseError for InvalidRequestError {
+    fn error_response(&self) -> HttpResponse {
+        let json_error = json!({
+            "error": {
+                "message": self.message,
+                "type": "invalid_request_error",
+                "param": (!self.param.is_empty()).then_some(&self.param),
+                "code": self.code,
+            }
+        });
+
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .json(json_error)
+    }
+
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
 }
 
 impl From<ModelError> for GatewayError {
@@ -66,6 +111,7 @@ impl actix_web::error::ResponseError for GatewayError {
         tracing::error!("API error: {:?}", self);
         match self {
             GatewayError::GuardError(e) => e.error_response(),
+            GatewayError::InvalidRequest(e) => e.error_response(),
             e => {
                 let json_error = json!({
                     "error": e.to_string(),
@@ -83,7 +129,50 @@ impl actix_web::error::ResponseError for GatewayError {
             GatewayError::GuardError(GuardError::GuardNotPassed(_, _)) => {
                 GuardValidationFailed::status_code()
             }
+            GatewayError::GuardError(GuardError::ContentBlocked(_)) => StatusCode::BAD_REQUEST,
+            GatewayError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::to_bytes;
+    use actix_web::ResponseError;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_invalid_request_envelope() {
+        let error = GatewayError::from(InvalidRequestError::new(
+            "dimensions",
+            "invalid_value",
+            "Invalid `dimensions` 3072, must be between 1 and 1536",
+        ));
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+
+        let body = to_bytes(error.error_response().into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            json!({
+                "error": {
+                    "message": "Invalid `dimensions` 3072, must be between 1 and 1536",
+                    "type": "invalid_request_error",
+                    "param": "dimensions",
+                    "code": "invalid_value",
+                }
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_request_level_error_without_param() {
+        let error = InvalidRequestError::new("", "unsupported_value", "Batches are not enabled");
+
+        let body = to_bytes(error.error_response().into_body()).await.unwrap();
+        let body = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+        assert_eq!(body["error"]["param"], serde_json::Value::Null);
+        assert_eq!(body["error"]["code"], "unsupported_value");
+    }
+}
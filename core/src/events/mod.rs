@@ -26,6 +26,10 @@ pub const SPAN_ANTHROPIC: &str = "anthropic";
 
 pub const SPAN_BEDROCK: &str = "bedrock";
 
+pub const SPAN_COHERE: &str = "cohere";
+
+pub const SPAN_VOYAGE: &str = "voyage";
+
 pub const SPAN_CACHE: &str = "cache";
 
 pub const SPAN_TOOLS: &str = "tools";
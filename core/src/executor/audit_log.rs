@@ -0,0 +1,307 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::select;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::error::GatewayError;
+use crate::handler::{CallbackHandlerFn, ModelEventWithDetails};
+use crate::model::types::ModelEventType;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditLogConfig {
+    /// JSONL file the events are appended to
+    pub path: PathBuf,
+    /// Events waiting for the sink, further events are dropped
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+    /// Events written at once
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+}
+
+fn default_buffer_size() -> usize {
+    10_000
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+fn default_flush_interval_ms() -> u64 {
+    1000
+}
+
+/// Model event as persisted, records sharing a request id form the trace of one request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditRecord {
+    pub request_id: Option<String>,
+    pub trace_id: String,
+    pub span_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub model: Option<String>,
+    pub provider: Option<String>,
+    pub event: ModelEventType,
+}
+
+impl From<ModelEventWithDetails> for AuditRecord {
+    fn from(value: ModelEventWithDetails) -> Self {
+        let ModelEventWithDetails { event, model } = value;
+        Self {
+            request_id: event.request_id,
+            trace_id: event.trace_id,
+            span_id: event.span_id,
+            timestamp: event.timestamp,
+            model: model.as_ref().map(|m| m.name.clone()),
+            provider: model.map(|m| m.provider_name),
+            event: event.event,
+        }
+    }
+}
+
+/// Destination of the audit log, called with a batch of records at a time
+#[async_trait]
+pub trait AuditSink: Send + Sync {
+    async fn write(&self, records: &[AuditRecord]) -> Result<(), GatewayError>;
+}
+
+/// Default sink appending one JSON record per line to a file
+pub struct JsonlFileSink {
+    path: PathBuf,
+}
+
+impl JsonlFileSink {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl AuditSink for JsonlFileSink {
+    async fn write(&self, records: &[AuditRecord]) -> Result<(), GatewayError> {
+        let mut lines = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut lines, record)?;
+            lines.push(b'\n');
+        }
+
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+                .write_all(&lines)
+        })
+        .await
+        .map_err(|e| GatewayError::CustomError(e.to_string()))??;
+        Ok(())
+    }
+}
+
+/// Persists every event of the callback handler through a sink. Events are handed over
+/// without waiting, a slow sink loses events and counts them instead of holding up requests.
+pub struct AuditLog {
+    dropped: AtomicU64,
+}
+
+impl AuditLog {
+    pub fn start(
+        config: AuditLogConfig,
+        sink: Arc<dyn AuditSink>,
+        callback_handler: &mut CallbackHandlerFn,
+    ) -> Arc<Self> {
+        let audit_log = Arc::new(Self {
+            dropped: AtomicU64::new(0),
+        });
+        let (sender, receiver) = mpsc::channel(config.buffer_size.max(1));
+
+        tokio::spawn(
+            audit_log
+                .clone()
+                .forward(callback_handler.subscribe(), sender),
+        );
+        tokio::spawn(Self::write(config, sink, receiver));
+        audit_log
+    }
+
+    /// Events lost to a full buffer since the start
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Dropped events in the Prometheus text format, served with the gateway metrics
+    pub fn render(&self) -> String {
+        let name = "gateway_audit_log_dropped_events_total";
+        format!(
+            "# HELP {name} Audit events lost to a full buffer\n# TYPE {name} counter\n{name} {}\n",
+            self.dropped()
+        )
+    }
+
+    fn drop_events(&self, count: u64) {
+        let total = self.dropped.fetch_add(count, Ordering::Relaxed) + count;
+        tracing::warn!("Audit log is behind, dropped {count} events ({total} in total)");
+    }
+
+    async fn forward(
+        self: Arc<Self>,
+        mut events: broadcast::Receiver<ModelEventWithDetails>,
+        sender: mpsc::Sender<AuditRecord>,
+    ) {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if sender.try_send(event.into()).is_err() {
+                        self.drop_events(1);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => self.drop_events(count),
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    async fn write(
+        config: AuditLogConfig,
+        sink: Arc<dyn AuditSink>,
+        mut receiver: mpsc::Receiver<AuditRecord>,
+    ) {
+        let batch_size = config.batch_size.max(1);
+        // A zero period makes the interval panic
+        let mut interval =
+            tokio::time::interval(Duration::from_millis(config.flush_interval_ms.max(1)));
+        let mut batch = Vec::with_capacity(batch_size);
+        loop {
+            select! {
+                record = receiver.recv() => {
+                    let Some(record) = record else {
+                        break;
+                    };
+                    batch.push(record);
+                    if batch.len() >= batch_size {
+                        Self::flush(sink.as_ref(), &mut batch).await;
+                    }
+                }
+                _ = interval.tick() => {
+                    Self::flush(sink.as_ref(), &mut batch).await;
+                }
+            }
+        }
+        Self::flush(sink.as_ref(), &mut batch).await;
+    }
+
+    async fn flush(sink: &dyn AuditSink, batch: &mut Vec<AuditRecord>) {
+        if batch.is_empty() {
+            return;
+        }
+        if let Err(e) = sink.write(batch).await {
+            tracing::error!("Error writing {} audit records: {e}", batch.len());
+        }
+        batch.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::types::{LLMStartEvent, ModelEvent};
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct MemorySink {
+        batches: Mutex<Vec<Vec<AuditRecord>>>,
+    }
+
+    #[async_trait]
+    impl AuditSink for MemorySink {
+        async fn write(&self, records: &[AuditRecord]) -> Result<(), GatewayError> {
+            self.batches.lock().push(records.to_vec());
+            Ok(())
+        }
+    }
+
+    fn event(request_id: &str) -> ModelEventWithDetails {
+        ModelEventWithDetails::new(
+            ModelEvent::new(
+                &tracing::Span::none(),
+                ModelEventType::LlmStart(LLMStartEvent {
+                    provider_name: "openai".to_string(),
+                    model_name: "gpt-4o-mini".to_string(),
+                    input: "Hello".to_string(),
+                }),
+            )
+            .with_request_id(Some(request_id.to_string())),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_events_are_batched() {
+        let sink = Arc::new(MemorySink::default());
+        let mut callback_handler = CallbackHandlerFn::default();
+        let config = AuditLogConfig {
+            path: PathBuf::new(),
+            buffer_size: 100,
+            batch_size: 2,
+            flush_interval_ms: 60_000,
+        };
+        let audit_log = AuditLog::start(config, sink.clone(), &mut callback_handler);
+
+        for request_id in ["a", "a", "b"] {
+            callback_handler.on_message(event(request_id));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let batches = sink.batches.lock().clone();
+        assert_eq!(batches.len(), 1);
+        let request_ids: Vec<_> = batches[0].iter().map(|r| r.request_id.clone()).collect();
+        assert_eq!(request_ids, vec![Some("a".to_string()); 2]);
+        assert_eq!(audit_log.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_full_buffer_drops_events() {
+        let audit_log = Arc::new(AuditLog {
+            dropped: AtomicU64::new(0),
+        });
+        let (events_tx, events) = broadcast::channel(16);
+        let (sender, _receiver) = mpsc::channel(1);
+        let forward = tokio::spawn(audit_log.clone().forward(events, sender));
+
+        for _ in 0..3 {
+            events_tx.send(event("a")).unwrap();
+        }
+        drop(events_tx);
+        forward.await.unwrap();
+        assert_eq!(audit_log.dropped(), 2);
+        assert!(audit_log
+            .render()
+            .contains("gateway_audit_log_dropped_events_total 2\n"));
+    }
+
+    #[tokio::test]
+    async fn test_zero_flush_interval() {
+        let sink = Arc::new(MemorySink::default());
+        let mut callback_handler = CallbackHandlerFn::default();
+        let config = AuditLogConfig {
+            path: PathBuf::new(),
+            buffer_size: 100,
+            batch_size: 10,
+            flush_interval_ms: 0,
+        };
+        AuditLog::start(config, sink.clone(), &mut callback_handler);
+
+        callback_handler.on_message(event("a"));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(sink.batches.lock().concat().len(), 1);
+    }
+}
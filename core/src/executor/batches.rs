@@ -0,0 +1,517 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use either::Either::{Left, Right};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Semaphore;
+use tracing::Span;
+use tracing_futures::Instrument;
+use uuid::Uuid;
+
+use crate::error::InvalidRequestError;
+use crate::executor::chat_completion::basic_executor::BasicCacheContext;
+use crate::executor::chat_completion::execute;
+use crate::executor::chat_completion::stream_executor::StreamCacheContext;
+use crate::executor::chat_completion::temperature_sampling::add_usage;
+use crate::executor::context::ExecutorContext;
+use crate::handler::find_model_by_full_name;
+use crate::handler::middleware::key_rate_limit::{
+    estimate_body_tokens, limited_api_key, KeyRateLimiter, TokenReservation,
+};
+use crate::routing::RoutingStrategy;
+use crate::types::credentials::Credentials;
+use crate::types::gateway::{
+    ChatCompletionRequestWithTools, ChatCompletionResponse, ChatCompletionUsage,
+    CompletionModelUsage, Usage,
+};
+use crate::GatewayApiError;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchConfig {
+    /// Items of all batches running at the same time
+    #[serde(default = "default_max_concurrency")]
+    pub max_concurrency: usize,
+    #[serde(default = "default_max_items")]
+    pub max_items: usize,
+    /// Time a finished batch stays retrievable
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Batches kept at the same time, running or finished
+    #[serde(default = "default_max_batches")]
+    pub max_batches: usize,
+}
+
+fn default_max_concurrency() -> usize {
+    4
+}
+
+fn default_max_items() -> usize {
+    50_000
+}
+
+fn default_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_max_batches() -> usize {
+    1_000
+}
+
+/// Line of a JSONL batch upload, `method` and `url` of OpenAI batch files are ignored
+#[derive(Debug, Deserialize, Clone)]
+pub struct BatchRequestItem {
+    pub custom_id: String,
+    pub body: ChatCompletionRequestWithTools<RoutingStrategy>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    InProgress,
+    Completed,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct BatchRequestCounts {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BatchItemResult {
+    pub custom_id: String,
+    pub status: BatchItemStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<ChatCompletionResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Batch {
+    pub id: String,
+    pub object: String,
+    pub status: BatchStatus,
+    pub created_at: i64,
+    pub completed_at: Option<i64>,
+    pub request_counts: BatchRequestCounts,
+    /// Usage and cost of the succeeded items
+    pub usage: ChatCompletionUsage,
+    /// Results in the order the items finished
+    pub results: Vec<BatchItemResult>,
+    #[serde(skip)]
+    finished_at: Option<Instant>,
+    /// Hash of the API key that submitted the batch, the only one allowed to read it
+    #[serde(skip)]
+    owner: String,
+}
+
+/// Batches of chat requests run in the background, sharing one concurrency limit
+pub struct Batches {
+    config: BatchConfig,
+    batches: DashMap<String, Batch>,
+    permits: Arc<Semaphore>,
+}
+
+impl Batches {
+    pub fn new(config: BatchConfig) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(config.max_concurrency.max(1))),
+            config,
+            batches: DashMap::new(),
+        }
+    }
+
+    /// Batch submitted with the same API key, batches of other keys are not found
+    pub fn get(&self, id: &str, credentials: Option<&Credentials>) -> Option<Batch> {
+        let owner = owner(credentials);
+        self.batches
+            .get(id)
+            .filter(|b| b.owner == owner)
+            .map(|b| b.clone())
+    }
+
+    /// Registers the batch and runs its items in the background. Items wait for a free
+    /// slot of the concurrency limit, then for the rate limits of the API key, before
+    /// they start.
+    pub fn submit(
+        self: &Arc<Self>,
+        items: Vec<BatchRequestItem>,
+        executor_context: ExecutorContext,
+        limiter: Option<Arc<KeyRateLimiter>>,
+    ) -> Result<Batch, GatewayApiError> {
+        if items.is_empty() || items.len() > self.config.max_items {
+            return Err(InvalidRequestError::new(
+                "input_file_id",
+                "invalid_value",
+                format!(
+                    "A batch takes between 1 and {} requests, got {}",
+                    self.config.max_items,
+                    items.len()
+                ),
+            )
+            .into());
+        }
+        self.check_capacity()?;
+
+        let batch = Batch {
+            id: format!("batch_{}", Uuid::new_v4().simple()),
+            object: "batch".to_string(),
+            status: BatchStatus::InProgress,
+            created_at: chrono::Utc::now().timestamp(),
+            completed_at: None,
+            request_counts: BatchRequestCounts {
+                total: items.len(),
+                ..Default::default()
+            },
+            usage: ChatCompletionUsage::default(),
+            results: Vec::with_capacity(items.len()),
+            finished_at: None,
+            owner: owner(executor_context.key_credentials.as_ref()),
+        };
+        self.batches.insert(batch.id.clone(), batch.clone());
+
+        // Items outlive the HTTP request, its deadline does not apply. The upload is refunded,
+        // every item reserves its own tokens.
+        if let Some(reservation) = &executor_context.token_reservation {
+            reservation.record(0);
+        }
+        let api_key = limited_api_key(executor_context.key_credentials.as_ref());
+        let executor_context = ExecutorContext {
+            request_deadline: None,
+            token_reservation: None,
+            idempotency_key: None,
+            ..executor_context
+        };
+        // Spawned on the worker of the request, chat execution is not bound to be Send
+        let batches = self.clone();
+        let id = batch.id.clone();
+        actix_web::rt::spawn(
+            async move {
+                for item in items {
+                    let permit = batches
+                        .permits
+                        .clone()
+                        .acquire_owned()
+                        .await
+                        .expect("batch semaphore is never closed");
+                    let reservation = match &limiter {
+                        Some(limiter) => admit_item(limiter, &api_key, &item).await,
+                        None => None,
+                    };
+                    let batches = batches.clone();
+                    let id = id.clone();
+                    let executor_context = ExecutorContext {
+                        token_reservation: reservation.clone(),
+                        ..executor_context.clone()
+                    };
+                    actix_web::rt::spawn(async move {
+                        // A panicking item fails alone
+                        let custom_id = item.custom_id.clone();
+                        let result = actix_web::rt::spawn(run_item(item.body, executor_context))
+                            .await
+                            .unwrap_or_else(|e| Err(GatewayApiError::CustomError(e.to_string())));
+                        if let Some(reservation) = reservation {
+                            reservation.settle(result.is_ok());
+                        }
+                        batches.finish_item(&id, custom_id, result);
+                        drop(permit);
+                    });
+                }
+            }
+            .instrument(Span::current()),
+        );
+
+        Ok(batch)
+    }
+
+    fn finish_item(
+        &self,
+        id: &str,
+        custom_id: String,
+        result: Result<ChatCompletionResponse, GatewayApiError>,
+    ) {
+        let Some(mut batch) = self.batches.get_mut(id) else {
+            return;
+        };
+
+        let result = match result {
+            Ok(response) => {
+                batch.request_counts.completed += 1;
+                add_usage(&mut batch.usage, &response.usage);
+                BatchItemResult {
+                    custom_id,
+                    status: BatchItemStatus::Succeeded,
+                    response: Some(response),
+                    error: None,
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Batch {id} request {custom_id} failed: {e}");
+                batch.request_counts.failed += 1;
+                BatchItemResult {
+                    custom_id,
+                    status: BatchItemStatus::Failed,
+                    response: None,
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+        batch.results.push(result);
+
+        let counts = &batch.request_counts;
+        if counts.completed + counts.failed == counts.total {
+            batch.status = BatchStatus::Completed;
+            batch.completed_at = Some(chrono::Utc::now().timestamp());
+            batch.finished_at = Some(Instant::now());
+        }
+    }
+
+    /// Drops expired batches, rejects a new batch while `max_batches` are kept
+    fn check_capacity(&self) -> Result<(), GatewayApiError> {
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        self.batches
+            .retain(|_, batch| batch.finished_at.is_none_or(|f| f.elapsed() < ttl));
+
+        if self.batches.len() >= self.config.max_batches {
+            return Err(GatewayApiError::TooManyRequests(format!(
+                "{} batches are kept already, retry once finished batches expire",
+                self.config.max_batches
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn owner(credentials: Option<&Credentials>) -> String {
+    let api_key = limited_api_key(credentials);
+    format!("{:x}", Sha256::digest(api_key.as_bytes()))
+}
+
+/// Waits until the rate limits of the API key admit the item, batch items are throttled
+/// instead of rejected
+async fn admit_item(
+    limiter: &KeyRateLimiter,
+    api_key: &str,
+    item: &BatchRequestItem,
+) -> Option<Arc<TokenReservation>> {
+    let length = serde_json::to_vec(&item.body).map_or(0, |b| b.len() as u64);
+    loop {
+        match limiter.admit(api_key, estimate_body_tokens(length)).await {
+            Ok(reservation) => return reservation.map(Arc::new),
+            Err(wait) => tokio::time::sleep(wait).await,
+        }
+    }
+}
+
+/// Runs one item as a non streaming chat request and prices its usage
+async fn run_item(
+    mut request: ChatCompletionRequestWithTools<RoutingStrategy>,
+    executor_context: ExecutorContext,
+) -> Result<ChatCompletionResponse, GatewayApiError> {
+    request.request.stream = Some(false);
+    let llm_model =
+        find_model_by_full_name(&request.request.model, &executor_context.provided_models)?;
+
+    let span = Span::current();
+    let mut response = match execute(
+        &request,
+        &executor_context,
+        span.clone(),
+        StreamCacheContext::default(),
+        BasicCacheContext::default(),
+    )
+    .instrument(span)
+    .await?
+    {
+        Right(response) => response?,
+        Left(_) => {
+            return Err(GatewayApiError::CustomError(
+                "Batch requests are not streamed".to_string(),
+            ))
+        }
+    };
+
+    let usage = &response.usage;
+    let usage = Usage::CompletionModelUsage(CompletionModelUsage {
+        input_tokens: usage.prompt_tokens as u32,
+        output_tokens: usage.completion_tokens as u32,
+        total_tokens: usage.total_tokens as u32,
+        prompt_tokens_details: usage.prompt_tokens_details.clone(),
+        completion_tokens_details: usage.completion_tokens_details.clone(),
+        is_cache_used: response.is_cache_used.unwrap_or(false),
+        is_estimated: false,
+    });
+    match executor_context
+        .cost_calculator
+        .calculate_cost(
+            &llm_model.model,
+            &llm_model.inference_provider.provider.to_string(),
+            &usage,
+        )
+        .await
+    {
+        Ok(cost) => response.usage.cost = cost.cost,
+        Err(e) => tracing::error!("Error calculating cost of a batch request: {:?}", e),
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batches() -> Batches {
+        Batches::new(BatchConfig {
+            max_concurrency: 2,
+            max_items: 10,
+            ttl_secs: 60,
+            max_batches: 2,
+        })
+    }
+
+    fn response(cost: f64) -> ChatCompletionResponse {
+        serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4o-mini",
+            "choices": [],
+            "usage": {"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15, "cost": cost},
+        }))
+        .unwrap()
+    }
+
+    fn batch(id: &str, total: usize, credentials: Option<&Credentials>) -> Batch {
+        Batch {
+            id: id.to_string(),
+            object: "batch".to_string(),
+            status: BatchStatus::InProgress,
+            created_at: 0,
+            completed_at: None,
+            request_counts: BatchRequestCounts {
+                total,
+                ..Default::default()
+            },
+            usage: ChatCompletionUsage::default(),
+            results: vec![],
+            finished_at: None,
+            owner: owner(credentials),
+        }
+    }
+
+    fn api_key(api_key: &str) -> Credentials {
+        Credentials::ApiKey(crate::types::credentials::ApiKeyCredentials {
+            api_key: api_key.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_failed_item_does_not_fail_batch() {
+        let batches = batches();
+        batches
+            .batches
+            .insert("batch_1".to_string(), batch("batch_1", 3, None));
+
+        batches.finish_item("batch_1", "a".to_string(), Ok(response(0.01)));
+        batches.finish_item(
+            "batch_1",
+            "b".to_string(),
+            Err(InvalidRequestError::new("model", "invalid_value", "unknown model").into()),
+        );
+
+        // Partial progress is visible while items run
+        let batch = batches.get("batch_1", None).unwrap();
+        assert_eq!(batch.status, BatchStatus::InProgress);
+        assert_eq!(batch.results[1].status, BatchItemStatus::Failed);
+        assert_eq!(batch.results[1].error.as_deref(), Some("unknown model"));
+
+        batches.finish_item("batch_1", "c".to_string(), Ok(response(0.02)));
+        let batch = batches.get("batch_1", None).unwrap();
+        assert_eq!(batch.status, BatchStatus::Completed);
+        assert_eq!(
+            (batch.request_counts.completed, batch.request_counts.failed),
+            (2, 1)
+        );
+        assert_eq!(batch.usage.total_tokens, 30);
+        assert!((batch.usage.cost - 0.03).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parse_openai_batch_line() {
+        let item: BatchRequestItem = serde_json::from_str(
+            r#"{"custom_id": "request-1", "method": "POST", "url": "/v1/chat/completions", "body": {"model": "openai/gpt-4o-mini", "messages": [{"role": "user", "content": "Hello"}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(item.custom_id, "request-1");
+        assert_eq!(item.body.request.model, "openai/gpt-4o-mini");
+    }
+
+    #[test]
+    fn test_batches_of_other_keys_and_capacity() {
+        let batches = batches();
+        let key = api_key("team-a");
+        batches
+            .batches
+            .insert("batch_1".to_string(), batch("batch_1", 1, Some(&key)));
+
+        assert!(batches.get("batch_1", Some(&key)).is_some());
+        assert!(batches.get("batch_1", Some(&api_key("team-b"))).is_none());
+        assert!(batches.get("batch_1", None).is_none());
+
+        batches
+            .batches
+            .insert("batch_2".to_string(), batch("batch_2", 1, None));
+        assert!(matches!(
+            batches.check_capacity(),
+            Err(GatewayApiError::TooManyRequests(_))
+        ));
+
+        // Expired batches free their place
+        batches.batches.get_mut("batch_1").unwrap().finished_at =
+            Instant::now().checked_sub(Duration::from_secs(61));
+        assert!(batches.check_capacity().is_ok());
+        assert!(batches.get("batch_1", Some(&key)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_items_wait_for_rate_limit() {
+        use crate::handler::middleware::key_rate_limit::{
+            InMemoryBucketStore, KeyLimits, KeyRateLimitConfig,
+        };
+
+        let limiter = KeyRateLimiter::new(
+            KeyRateLimitConfig {
+                default: Some(KeyLimits {
+                    requests_per_minute: None,
+                    tokens_per_minute: Some(6000),
+                }),
+                ..Default::default()
+            },
+            Arc::new(InMemoryBucketStore::default()),
+        );
+        let item: BatchRequestItem = serde_json::from_str(
+            r#"{"custom_id": "request-1", "body": {"model": "openai/gpt-4o-mini", "messages": [{"role": "user", "content": "Hello"}]}}"#,
+        )
+        .unwrap();
+
+        // Drains the tokens of the key, the item waits for them to refill
+        let drained = limiter.admit("team-a", 6000).await.unwrap();
+        let start = Instant::now();
+        let reservation = admit_item(&limiter, "team-a", &item).await;
+        assert!(reservation.is_some());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        drop(drained);
+    }
+}
@@ -0,0 +1,311 @@
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::Span;
+
+use crate::error::GatewayError;
+use crate::model::types::{CustomEvent, ModelEvent, ModelEventType};
+use crate::model::DEFAULT_MAX_RETRIES;
+use crate::types::engine::ExecutionOptions;
+
+/// Retries rate limited (429) and unavailable (503) provider calls with exponential backoff
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Attempts including the first call
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every following one
+    #[serde(default = "default_base_delay_ms")]
+    pub base_delay_ms: u64,
+    /// Upper bound of the backoff delay
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+    /// Share of the delay randomly added or removed, between 0 and 1
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_base_delay_ms() -> u64 {
+    500
+}
+
+fn default_max_delay_ms() -> u64 {
+    10_000
+}
+
+fn default_jitter() -> f64 {
+    0.2
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            base_delay_ms: default_base_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
+            jitter: default_jitter(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay after the failed `attempt` (starting at 1). A Retry-After hint from the
+    /// provider replaces the backoff, bounded by `max_delay_ms` like the backoff.
+    pub fn delay<R: Rng>(
+        &self,
+        attempt: u32,
+        retry_after: Option<Duration>,
+        rng: &mut R,
+    ) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(Duration::from_millis(self.max_delay_ms));
+        }
+
+        let exponent = attempt.saturating_sub(1).min(16);
+        let backoff = self
+            .base_delay_ms
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay_ms);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            1.0 + rng.random_range(-jitter..=jitter)
+        } else {
+            1.0
+        };
+
+        Duration::from_millis((backoff as f64 * factor) as u64)
+    }
+}
+
+/// Retries of the failed provider calls of one tool calling loop. Only the failed call
+/// is repeated, transient errors wait for the backoff of the retry policy and every retry
+/// is charged to the shared retry budget.
+pub struct ProviderRetries<'a> {
+    options: &'a ExecutionOptions,
+    retries_left: u32,
+    attempt: u32,
+}
+
+impl<'a> ProviderRetries<'a> {
+    pub fn new(options: &'a ExecutionOptions) -> Self {
+        Self {
+            options,
+            retries_left: options.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+            attempt: 1,
+        }
+    }
+
+    pub fn retries_left(&self) -> u32 {
+        self.retries_left
+    }
+
+    /// Returns whether the call failed with `error` is made again, after the backoff
+    /// delay for transient errors
+    pub async fn retry(
+        &mut self,
+        error: &GatewayError,
+        span: &Span,
+        tx: &mpsc::Sender<Option<ModelEvent>>,
+    ) -> bool {
+        let delay = match (&self.options.retry_policy, error) {
+            (Some(policy), GatewayError::ModelError(e))
+                if e.is_transient() && self.attempt < policy.max_attempts =>
+            {
+                Some(policy.delay(self.attempt, e.retry_after(), &mut rand::rng()))
+            }
+            _ => None,
+        };
+        if delay.is_none() && self.retries_left == 0 {
+            return false;
+        }
+        if let Some(budget) = &self.options.retry_budget {
+            if !budget.try_retry() {
+                tracing::warn!("Retry budget exhausted, not retrying model call: {error}");
+                return false;
+            }
+        }
+
+        match delay {
+            Some(delay) => {
+                tracing::warn!(
+                    "Retrying model call after attempt {}: {error}",
+                    self.attempt
+                );
+                let event = ModelEvent::new(
+                    span,
+                    ModelEventType::Custom(CustomEvent::new(
+                        "model_retry".to_string(),
+                        serde_json::json!({
+                            "attempt": self.attempt,
+                            "delay_ms": delay.as_millis() as u64,
+                            "error": error.to_string(),
+                        }),
+                    )),
+                );
+                let _ = tx.send(Some(event)).await;
+                tokio::time::sleep(delay).await;
+                self.attempt += 1;
+            }
+            None => self.retries_left -= 1,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::executor::retry_budget::{RetryBudget, RetryBudgetConfig};
+    use crate::model::error::ModelError;
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay_ms: 0,
+            max_delay_ms: 0,
+            jitter: 0.0,
+        }
+    }
+
+    fn error(message: &str) -> GatewayError {
+        GatewayError::ModelError(Box::new(ModelError::StreamError(message.to_string())))
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay_ms: 100,
+            max_delay_ms: 300,
+            jitter: 0.0,
+        };
+        let mut rng = rand::rng();
+        assert_eq!(policy.delay(1, None, &mut rng), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, None, &mut rng), Duration::from_millis(200));
+        assert_eq!(policy.delay(3, None, &mut rng), Duration::from_millis(300));
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_millis(250)), &mut rng),
+            Duration::from_millis(250)
+        );
+
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+        let delay = policy.delay(1, None, &mut rng);
+        assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_retry_after_bounded_by_max_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay_ms: 100,
+            max_delay_ms: 300,
+            jitter: 0.0,
+        };
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(3600)), &mut rand::rng()),
+            Duration::from_millis(300)
+        );
+    }
+
+    fn options(policy: Option<RetryPolicy>, max_retries: u32) -> ExecutionOptions {
+        ExecutionOptions {
+            max_retries: Some(max_retries),
+            retry_policy: policy,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let span = Span::none();
+        let options = options(Some(policy(3)), 0);
+        let mut retries = ProviderRetries::new(&options);
+
+        assert!(
+            retries
+                .retry(&error("429 Too Many Requests"), &span, &tx)
+                .await
+        );
+        assert!(
+            retries
+                .retry(&error("503 Service Unavailable"), &span, &tx)
+                .await
+        );
+        assert!(
+            !retries
+                .retry(&error("Rate limit exceeded"), &span, &tx)
+                .await
+        );
+
+        let mut attempts = vec![];
+        while let Ok(Some(event)) = rx.try_recv() {
+            if let ModelEventType::Custom(event) = event.event {
+                attempts.push(event.value()["attempt"].as_u64().unwrap());
+            }
+        }
+        assert_eq!(attempts, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_use_max_retries() {
+        let (tx, _rx) = mpsc::channel(10);
+        let span = Span::none();
+        let options = options(Some(policy(3)), 1);
+        let mut retries = ProviderRetries::new(&options);
+
+        assert!(
+            retries
+                .retry(&error("Invalid JSON in response"), &span, &tx)
+                .await
+        );
+        assert_eq!(retries.retries_left(), 0);
+        assert!(
+            !retries
+                .retry(&error("Invalid JSON in response"), &span, &tx)
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retries_charge_the_budget() {
+        let (tx, _rx) = mpsc::channel(10);
+        let span = Span::none();
+        let options = ExecutionOptions {
+            retry_budget: Some(Arc::new(RetryBudget::new(RetryBudgetConfig {
+                ratio: 0.0,
+                window_secs: 10,
+                min_retries: 1,
+            }))),
+            ..options(Some(policy(5)), 5)
+        };
+        let mut retries = ProviderRetries::new(&options);
+
+        assert!(
+            retries
+                .retry(&error("429 Too Many Requests"), &span, &tx)
+                .await
+        );
+        assert!(
+            !retries
+                .retry(&error("429 Too Many Requests"), &span, &tx)
+                .await
+        );
+        assert!(
+            !retries
+                .retry(&error("Invalid JSON in response"), &span, &tx)
+                .await
+        );
+    }
+}
@@ -33,6 +33,23 @@ pub struct BasicCacheContext {
     pub cached_response: Option<ChatCompletionMessage>,
 }
 
+/// Keeps the last stop event of a tool calling loop with the usage of all its model calls,
+/// each call is still traced with its own event
+pub fn add_round_usage(stop_event: &mut Option<LLMFinishEvent>, event: &LLMFinishEvent) {
+    let mut event = event.clone();
+    if let Some(LLMFinishEvent {
+        usage: Some(mut total),
+        ..
+    }) = stop_event.take()
+    {
+        if let Some(usage) = &event.usage {
+            total.add(usage);
+        }
+        event.usage = Some(total);
+    }
+    *stop_event = Some(event);
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn execute(
     request: ChatCompletionRequest,
@@ -56,7 +73,7 @@ pub async fn execute(
     });
 
     let response = model
-        .invoke(input_vars.clone(), inner_tx, messages.clone(), tags.clone())
+        .invoke(input_vars, inner_tx, messages, tags)
         .instrument(span.clone())
         .await
         .map_err(|e| record_map_err(e, span.clone()))?;
@@ -110,10 +127,67 @@ pub async fn execute(
             index: 0,
             message: response.clone(),
             finish_reason: Some(finish_reason.clone()),
+            logprobs: None,
         }],
         usage,
         is_cache_used,
+        citations: None,
+        confidence: None,
+        system_fingerprint: None,
     };
 
     Ok(response)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::types::{ModelFinishReason, ModelToolCall};
+    use crate::model::CredentialsIdent;
+    use crate::types::gateway::{CompletionModelUsage, PromptTokensDetails};
+
+    fn stop(finish_reason: ModelFinishReason, input: u32, cached: u32) -> LLMFinishEvent {
+        LLMFinishEvent {
+            provider_name: "openai".to_string(),
+            model_name: "gpt-4o".to_string(),
+            output: None,
+            usage: Some(CompletionModelUsage {
+                input_tokens: input,
+                output_tokens: 10,
+                total_tokens: input + 10,
+                prompt_tokens_details: Some(PromptTokensDetails::new(Some(cached), None, None)),
+                ..Default::default()
+            }),
+            finish_reason,
+            tool_calls: vec![],
+            credentials_ident: CredentialsIdent::Langdb,
+            generation: None,
+        }
+    }
+
+    #[test]
+    fn test_usage_of_all_rounds() {
+        let mut tool_round = stop(ModelFinishReason::ToolCalls, 100, 0);
+        tool_round.tool_calls.push(ModelToolCall {
+            tool_id: "call_1".to_string(),
+            tool_name: "get_weather".to_string(),
+            input: "{}".to_string(),
+        });
+
+        let mut stop_event = None;
+        add_round_usage(&mut stop_event, &tool_round);
+        add_round_usage(
+            &mut stop_event,
+            &stop(ModelFinishReason::ToolCalls, 150, 100),
+        );
+        add_round_usage(&mut stop_event, &stop(ModelFinishReason::Stop, 200, 150));
+
+        let stop_event = stop_event.unwrap();
+        assert!(matches!(stop_event.finish_reason, ModelFinishReason::Stop));
+        let usage = stop_event.usage.unwrap();
+        assert_eq!(usage.input_tokens, 450);
+        assert_eq!(usage.output_tokens, 30);
+        assert_eq!(usage.total_tokens, 480);
+        assert_eq!(usage.cached_input_tokens(), 250);
+    }
+}
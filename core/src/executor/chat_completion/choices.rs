@@ -0,0 +1,251 @@
+use crate::error::InvalidRequestError;
+use crate::executor::chat_completion::temperature_sampling::merge_variants;
+use crate::types::gateway::{
+    ChatCompletionChoice, ChatCompletionRequestWithTools, ChatCompletionResponse,
+};
+use crate::types::provider::InferenceModelProvider;
+use crate::GatewayApiError;
+
+/// Upper bound of `n` for a single request
+pub const MAX_CHOICES: u32 = 16;
+
+/// Custom event carrying the choices after the first of a model call generating `n` choices
+pub const ADDITIONAL_CHOICES_EVENT: &str = "additional_choices";
+
+/// Number of choices requested with `n`. Providers without native `n` get one call per
+/// choice, so choices are supported for every provider but not for streamed responses.
+pub fn requested_choices<T>(
+    request: &ChatCompletionRequestWithTools<T>,
+) -> Result<u32, GatewayApiError> {
+    let n = request.request.n.unwrap_or(1);
+    if n == 0 {
+        return Err(InvalidRequestError::new(
+            "n",
+            "integer_below_min_value",
+            "n must be at least 1",
+        )
+        .into());
+    }
+    if n == 1 {
+        return Ok(1);
+    }
+
+    if n > MAX_CHOICES {
+        return Err(InvalidRequestError::new(
+            "n",
+            "integer_above_max_value",
+            format!("n must be at most {MAX_CHOICES}"),
+        )
+        .into());
+    }
+    if request.request.stream.unwrap_or(false) {
+        return Err(InvalidRequestError::new(
+            "n",
+            "unsupported_value",
+            "n greater than 1 does not support streaming",
+        )
+        .into());
+    }
+
+    let extra = request.extra.as_ref();
+    if extra.is_some_and(|e| e.temperature_sampling.is_some()) {
+        return Err(InvalidRequestError::new(
+            "n",
+            "unsupported_value",
+            "n can not be combined with temperature_sampling, which sets the number of choices",
+        )
+        .into());
+    }
+    if extra.is_some_and(|e| e.min_output.is_some()) {
+        return Err(InvalidRequestError::new(
+            "n",
+            "unsupported_value",
+            "n greater than 1 can not be combined with min_output",
+        )
+        .into());
+    }
+
+    Ok(n)
+}
+
+/// Whether the provider generates all choices in one call, billing the prompt once. Tools
+/// run by the gateway loop separately for every choice, so requests with tools fan out.
+pub fn native_choices<T>(
+    request: &ChatCompletionRequestWithTools<T>,
+    provider: &InferenceModelProvider,
+) -> bool {
+    let tools = request
+        .request
+        .tools
+        .as_ref()
+        .is_some_and(|t| !t.is_empty());
+    let functions = request
+        .request
+        .functions
+        .as_ref()
+        .is_some_and(|f| !f.is_empty());
+    let delegates = request
+        .extra
+        .as_ref()
+        .is_some_and(|e| !e.delegates.is_empty());
+    *provider == InferenceModelProvider::OpenAI
+        && !(tools || functions || delegates || request.mcp_servers.is_some())
+}
+
+/// Adds the choices reported with `ADDITIONAL_CHOICES_EVENT` after the first one
+pub fn append_choices(response: &mut ChatCompletionResponse, choices: Vec<ChatCompletionChoice>) {
+    response.choices.extend(choices);
+    for (index, choice) in response.choices.iter_mut().enumerate() {
+        choice.index = index as i32;
+    }
+}
+
+/// Merges the choices of the calls that succeeded and returns the errors of the failed
+/// ones, fails only when every call failed
+pub fn merge_choices(
+    results: Vec<Result<ChatCompletionResponse, GatewayApiError>>,
+) -> Result<(ChatCompletionResponse, Vec<GatewayApiError>), GatewayApiError> {
+    let mut responses = vec![];
+    let mut errors = vec![];
+    for result in results {
+        match result {
+            Ok(response) => responses.push(response),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    match merge_variants(responses) {
+        Some(merged) => Ok((merged, errors)),
+        None => Err(errors.into_iter().next().unwrap_or_else(|| {
+            GatewayApiError::CustomError("No choices were generated".to_string())
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing::RoutingStrategy;
+    use crate::types::gateway::{ChatCompletionMessage, ChatCompletionUsage};
+
+    fn request(value: serde_json::Value) -> ChatCompletionRequestWithTools<RoutingStrategy> {
+        let mut request = serde_json::json!({
+            "model": "anthropic/claude-3-5-sonnet",
+            "messages": [{"role": "user", "content": "Hello"}],
+        });
+        request
+            .as_object_mut()
+            .unwrap()
+            .extend(value.as_object().unwrap().clone());
+        serde_json::from_value(request).unwrap()
+    }
+
+    #[test]
+    fn test_requested_choices() {
+        assert_eq!(
+            requested_choices(&request(serde_json::json!({}))).unwrap(),
+            1
+        );
+        assert_eq!(
+            requested_choices(&request(serde_json::json!({"n": 3}))).unwrap(),
+            3
+        );
+        assert!(requested_choices(&request(serde_json::json!({"n": 0}))).is_err());
+        assert!(requested_choices(&request(serde_json::json!({"n": 17}))).is_err());
+
+        let error = requested_choices(&request(serde_json::json!({"n": 2, "stream": true})));
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "n greater than 1 does not support streaming"
+        );
+        // Streaming a single choice is unaffected
+        assert_eq!(
+            requested_choices(&request(serde_json::json!({"n": 1, "stream": true}))).unwrap(),
+            1
+        );
+    }
+
+    fn response(contents: &[&str]) -> ChatCompletionResponse {
+        ChatCompletionResponse {
+            id: "id".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "gpt-4o".to_string(),
+            choices: contents
+                .iter()
+                .map(|content| ChatCompletionChoice {
+                    index: 0,
+                    message: ChatCompletionMessage::new_text(
+                        "assistant".to_string(),
+                        content.to_string(),
+                    ),
+                    finish_reason: Some("stop".to_string()),
+                    logprobs: None,
+                })
+                .collect(),
+            usage: ChatCompletionUsage {
+                prompt_tokens: 10,
+                completion_tokens: 5,
+                total_tokens: 15,
+                cost: 0.1,
+                ..Default::default()
+            },
+            is_cache_used: None,
+            citations: None,
+            confidence: None,
+            system_fingerprint: None,
+        }
+    }
+
+    #[test]
+    fn test_native_choices() {
+        let openai = InferenceModelProvider::OpenAI;
+        assert!(native_choices(
+            &request(serde_json::json!({"n": 3})),
+            &openai
+        ));
+        assert!(!native_choices(
+            &request(serde_json::json!({"n": 3})),
+            &InferenceModelProvider::Anthropic
+        ));
+
+        let with_tools = request(serde_json::json!({
+            "n": 3,
+            "tools": [{"type": "function", "function": {"name": "lookup"}}],
+        }));
+        assert!(!native_choices(&with_tools, &openai));
+    }
+
+    #[test]
+    fn test_append_native_choices() {
+        let mut merged = response(&["a"]);
+        append_choices(&mut merged, response(&["b", "c"]).choices);
+
+        let indexes: Vec<_> = merged.choices.iter().map(|c| c.index).collect();
+        assert_eq!(indexes, vec![0, 1, 2]);
+        // A single call reports the usage of all its choices
+        assert_eq!(merged.usage.prompt_tokens, 10);
+    }
+
+    #[test]
+    fn test_partial_failure_keeps_choices() {
+        let (merged, errors) = merge_choices(vec![
+            Ok(response(&["a"])),
+            Err(GatewayApiError::CustomError("overloaded".to_string())),
+            Ok(response(&["c"])),
+        ])
+        .unwrap();
+
+        assert_eq!(merged.choices.len(), 2);
+        assert_eq!(merged.choices[1].index, 1);
+        assert_eq!(merged.usage.prompt_tokens, 20);
+        assert_eq!(errors.len(), 1);
+
+        let error = merge_choices(vec![
+            Err(GatewayApiError::CustomError("overloaded".to_string())),
+            Err(GatewayApiError::CustomError("timeout".to_string())),
+        ])
+        .unwrap_err();
+        assert_eq!(error.to_string(), "overloaded");
+    }
+}
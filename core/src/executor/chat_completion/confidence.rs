@@ -0,0 +1,127 @@
+use crate::error::InvalidRequestError;
+use crate::types::gateway::{
+    ChatCompletionRequest, ChatCompletionRequestWithTools, ConfidenceMethod, ConfidenceScore,
+};
+use crate::types::provider::InferenceModelProvider;
+use crate::GatewayApiError;
+
+/// Custom event carrying the log probabilities of the output tokens, sent by models that return them
+pub const TOKEN_LOGPROBS_EVENT: &str = "token_logprobs";
+
+impl ConfidenceMethod {
+    /// Aggregates token log probabilities, `None` without tokens
+    pub fn score(&self, logprobs: &[f32]) -> Option<ConfidenceScore> {
+        if logprobs.is_empty() {
+            return None;
+        }
+
+        let mean = logprobs.iter().map(|l| *l as f64).sum::<f64>() / logprobs.len() as f64;
+        let score = match self {
+            ConfidenceMethod::MeanLogprob => mean,
+            ConfidenceMethod::MinLogprob => logprobs
+                .iter()
+                .map(|l| *l as f64)
+                .fold(f64::INFINITY, f64::min),
+            ConfidenceMethod::Perplexity => (-mean).exp(),
+        };
+
+        Some(ConfidenceScore {
+            method: *self,
+            score,
+            tokens: logprobs.len(),
+        })
+    }
+}
+
+/// Enables log probabilities on requests that ask for a confidence score.
+/// Streams carry no score, so their log probabilities are left as requested
+pub fn with_logprobs<T: Clone>(
+    request: &ChatCompletionRequestWithTools<T>,
+) -> Option<ChatCompletionRequestWithTools<T>> {
+    let confidence = request.extra.as_ref().and_then(|e| e.confidence.as_ref());
+    if confidence.is_none()
+        || request.request.logprobs == Some(true)
+        || request.request.stream == Some(true)
+    {
+        return None;
+    }
+
+    let mut request = request.clone();
+    request.request.logprobs = Some(true);
+    Some(request)
+}
+
+/// Rejects requests for log probabilities on providers that do not return them
+pub fn check_logprobs_support(
+    request: &ChatCompletionRequest,
+    provider: &InferenceModelProvider,
+) -> Result<(), GatewayApiError> {
+    let requested = request.logprobs == Some(true) || request.top_logprobs.is_some();
+    match provider {
+        InferenceModelProvider::OpenAI | InferenceModelProvider::Proxy(_) => Ok(()),
+        _ if requested => Err(InvalidRequestError::new(
+            "logprobs",
+            "unsupported_value",
+            format!("Log probabilities are not supported by provider {provider}"),
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::{ConfidenceOptions, Extra};
+
+    #[test]
+    fn test_confidence_methods() {
+        let logprobs = [-0.1, -0.5, -0.3];
+
+        let mean = ConfidenceMethod::MeanLogprob.score(&logprobs).unwrap();
+        assert!((mean.score + 0.3).abs() < 1e-6);
+        assert_eq!(mean.tokens, 3);
+
+        let min = ConfidenceMethod::MinLogprob.score(&logprobs).unwrap();
+        assert!((min.score + 0.5).abs() < 1e-6);
+
+        let perplexity = ConfidenceMethod::Perplexity.score(&logprobs).unwrap();
+        assert!((perplexity.score - 0.3f64.exp()).abs() < 1e-6);
+
+        assert!(ConfidenceMethod::Perplexity.score(&[]).is_none());
+    }
+
+    #[test]
+    fn test_logprobs_support() {
+        let request = ChatCompletionRequest {
+            logprobs: Some(true),
+            ..Default::default()
+        };
+        assert!(check_logprobs_support(&request, &InferenceModelProvider::OpenAI).is_ok());
+        assert!(check_logprobs_support(&request, &InferenceModelProvider::Anthropic).is_err());
+        assert!(check_logprobs_support(
+            &ChatCompletionRequest::default(),
+            &InferenceModelProvider::Anthropic
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_confidence_without_provider_logprobs() {
+        let request = ChatCompletionRequestWithTools::<()> {
+            extra: Some(Extra {
+                confidence: Some(ConfidenceOptions::default()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        // Log probabilities are enabled for the score, not requested by the client
+        let injected = with_logprobs(&request).unwrap();
+        assert_eq!(injected.request.logprobs, Some(true));
+        assert!(
+            check_logprobs_support(&request.request, &InferenceModelProvider::Anthropic).is_ok()
+        );
+        assert!(check_logprobs_support(&request.request, &InferenceModelProvider::Gemini).is_ok());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::executor::chat_completion::temperature_sampling::add_usage;
+use crate::types::gateway::{
+    ChatCompletionContent, ChatCompletionMessage, ChatCompletionRequestWithTools,
+    ChatCompletionResponse, MinOutputTokens,
+};
+
+impl MinOutputTokens {
+    /// Whether the output finished normally with fewer completion tokens than the minimum
+    pub fn needs_continuation(&self, response: &ChatCompletionResponse) -> bool {
+        let Some(choice) = response.choices.first() else {
+            return false;
+        };
+
+        choice.finish_reason.as_deref() == Some("stop")
+            && choice.message.tool_calls.is_none()
+            && output_text(response).is_some_and(|text| !text.is_empty())
+            && response.usage.completion_tokens < self.min_tokens as i32
+    }
+}
+
+/// Text of the first choice, `None` for multi part or tool call outputs
+pub fn output_text(response: &ChatCompletionResponse) -> Option<&str> {
+    match response.choices.first()?.message.content.as_ref()? {
+        ChatCompletionContent::Text(text) => Some(text),
+        ChatCompletionContent::Content(_) => None,
+    }
+}
+
+/// Builds the follow up request with the output so far as assistant prefill
+pub fn continuation_request<T: Clone>(
+    request: &ChatCompletionRequestWithTools<T>,
+    output: &str,
+) -> ChatCompletionRequestWithTools<T> {
+    let mut continuation = request.clone();
+    continuation
+        .request
+        .messages
+        .push(ChatCompletionMessage::new_text(
+            "assistant".to_string(),
+            output.to_string(),
+        ));
+    if let Some(extra) = continuation.extra.as_mut() {
+        extra.min_output = None;
+    }
+    continuation
+}
+
+/// Appends the continued text to the first choice and sums usage
+pub fn append_continuation(
+    response: &mut ChatCompletionResponse,
+    continuation: ChatCompletionResponse,
+) {
+    add_usage(&mut response.usage, &continuation.usage);
+    let Some(choice) = response.choices.first_mut() else {
+        return;
+    };
+    let Some(next) = continuation.choices.into_iter().next() else {
+        return;
+    };
+
+    if let (Some(ChatCompletionContent::Text(text)), Some(ChatCompletionContent::Text(more))) =
+        (choice.message.content.as_mut(), next.message.content)
+    {
+        text.push_str(&more);
+    }
+    choice.finish_reason = next.finish_reason;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::{
+        ChatCompletionChoice, ChatCompletionRequest, ChatCompletionUsage, Extra,
+    };
+
+    fn response(content: &str, completion_tokens: i32) -> ChatCompletionResponse {
+        ChatCompletionResponse {
+            id: "id".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "gpt-4o".to_string(),
+            choices: vec![ChatCompletionChoice {
+                index: 0,
+                message: ChatCompletionMessage::new_text(
+                    "assistant".to_string(),
+                    content.to_string(),
+                ),
+                finish_reason: Some("stop".to_string()),
+                logprobs: None,
+            }],
+            usage: ChatCompletionUsage {
+                prompt_tokens: 10,
+                completion_tokens,
+                total_tokens: 10 + completion_tokens,
+                ..Default::default()
+            },
+            is_cache_used: None,
+            citations: None,
+            confidence: None,
+            system_fingerprint: None,
+        }
+    }
+
+    #[test]
+    fn test_needs_continuation() {
+        let min_output = MinOutputTokens {
+            min_tokens: 100,
+            max_continuations: 2,
+        };
+
+        assert!(min_output.needs_continuation(&response("Short", 20)));
+        assert!(!min_output.needs_continuation(&response("Long enough", 150)));
+
+        let mut truncated = response("Cut", 20);
+        truncated.choices[0].finish_reason = Some("length".to_string());
+        assert!(!min_output.needs_continuation(&truncated));
+    }
+
+    #[test]
+    fn test_continuation_appends_prefill_and_text() {
+        let request = ChatCompletionRequestWithTools::<()> {
+            request: ChatCompletionRequest {
+                messages: vec![ChatCompletionMessage::new_text(
+                    "user".to_string(),
+                    "Write an essay".to_string(),
+                )],
+                ..Default::default()
+            },
+            extra: Some(Extra {
+                min_output: Some(MinOutputTokens {
+                    min_tokens: 100,
+                    max_continuations: 2,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let next = continuation_request(&request, "First part.");
+        assert_eq!(next.request.messages.len(), 2);
+        assert_eq!(next.request.messages[1].role, "assistant");
+        assert!(next.extra.unwrap().min_output.is_none());
+
+        let mut merged = response("First part.", 20);
+        append_continuation(&mut merged, response(" Second part.", 30));
+        assert_eq!(output_text(&merged), Some("First part. Second part."));
+        assert_eq!(merged.usage.completion_tokens, 50);
+        assert_eq!(merged.usage.prompt_tokens, 20);
+    }
+}
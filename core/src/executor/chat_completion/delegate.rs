@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use either::Either::{Left, Right};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde_json::json;
+use tracing::Span;
+use tracing_futures::Instrument;
+
+use crate::error::GatewayError;
+use crate::executor::chat_completion::basic_executor::BasicCacheContext;
+use crate::executor::chat_completion::execute;
+use crate::executor::chat_completion::stream_executor::StreamCacheContext;
+use crate::executor::context::ExecutorContext;
+use crate::model::tools::Tool;
+use crate::routing::RoutingStrategy;
+use crate::types::gateway::{
+    ChatCompletionMessage, ChatCompletionRequest, ChatCompletionRequestWithTools,
+    ChatCompletionResponse, DelegateDefinition, Extra, FunctionParameters, Property, PropertyType,
+};
+
+/// Maximum nesting of delegated calls, models at this depth are not offered delegates
+pub const MAX_DELEGATION_DEPTH: usize = 3;
+
+/// Lets a model call another model through the gateway
+pub struct DelegateTool {
+    pub def: DelegateDefinition,
+    /// Delegates offered to the nested request
+    pub delegates: Vec<DelegateDefinition>,
+    pub executor_context: ExecutorContext,
+}
+
+impl DelegateTool {
+    /// Delegate tools for a request, empty once the delegation depth limit is reached
+    pub fn from_extra(extra: Option<&Extra>, executor_context: &ExecutorContext) -> Vec<Self> {
+        let delegates = extra.map(|e| e.delegates.clone()).unwrap_or_default();
+        if executor_context.delegation_depth >= MAX_DELEGATION_DEPTH {
+            return vec![];
+        }
+
+        let mut nested_context = executor_context.clone();
+        nested_context.delegation_depth += 1;
+
+        delegates
+            .iter()
+            .map(|def| Self {
+                def: def.clone(),
+                delegates: delegates.clone(),
+                executor_context: nested_context.clone(),
+            })
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl Tool for DelegateTool {
+    fn name(&self) -> String {
+        self.def.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.def
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("Delegates a task to the {} model", self.def.model))
+    }
+
+    fn get_function_parameters(&self) -> Option<FunctionParameters> {
+        Some(FunctionParameters {
+            properties: HashMap::from([(
+                "message".to_string(),
+                Property {
+                    r#type: PropertyType::Single("string".to_string()),
+                    description: Some("Task or question for the model".to_string()),
+                    items: None,
+                },
+            )]),
+            required: Some(vec!["message".to_string()]),
+            ..Default::default()
+        })
+    }
+
+    async fn run(
+        &self,
+        input: HashMap<String, serde_json::Value>,
+        _tags: HashMap<String, String>,
+    ) -> crate::GatewayResult<serde_json::Value> {
+        let message = input
+            .get("message")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| GatewayError::CustomError("Missing delegate message".to_string()))?;
+
+        let request = nested_request(&self.def, &self.delegates, message.to_string());
+        let response = execute_nested(&request, &self.executor_context).await?;
+
+        let content = response
+            .choices
+            .first()
+            .and_then(|c| c.message.content.as_ref())
+            .and_then(|c| c.as_string())
+            .unwrap_or_default();
+
+        Ok(json!({
+            "model": response.model,
+            "content": content,
+            "usage": response.usage,
+        }))
+    }
+}
+
+/// Runs a non-streaming request through the gateway from within another request.
+/// Nested model spans are children of the current span and usage is reported through the
+/// same callback handler, so nested cost is tracked like any other call.
+pub fn execute_nested<'a>(
+    request: &'a ChatCompletionRequestWithTools<RoutingStrategy>,
+    executor_context: &'a ExecutorContext,
+) -> BoxFuture<'a, Result<ChatCompletionResponse, GatewayError>> {
+    async move {
+        let span = Span::current();
+        let response = execute(
+            request,
+            executor_context,
+            span.clone(),
+            StreamCacheContext::default(),
+            BasicCacheContext::default(),
+        )
+        .instrument(span)
+        .await
+        .map_err(|e| GatewayError::CustomError(e.to_string()))?;
+
+        match response {
+            Right(result) => result.map_err(|e| GatewayError::CustomError(e.to_string())),
+            Left(_) => Err(GatewayError::CustomError(
+                "Nested calls do not stream".to_string(),
+            )),
+        }
+    }
+    .boxed()
+}
+
+fn nested_request(
+    def: &DelegateDefinition,
+    delegates: &[DelegateDefinition],
+    message: String,
+) -> ChatCompletionRequestWithTools<RoutingStrategy> {
+    let mut messages = vec![];
+    if let Some(instructions) = &def.instructions {
+        messages.push(ChatCompletionMessage::new_text(
+            "system".to_string(),
+            instructions.clone(),
+        ));
+    }
+    messages.push(ChatCompletionMessage::new_text("user".to_string(), message));
+
+    ChatCompletionRequestWithTools {
+        request: ChatCompletionRequest {
+            model: def.model.clone(),
+            messages,
+            max_tokens: def.max_tokens,
+            temperature: def.temperature,
+            stream: Some(false),
+            ..Default::default()
+        },
+        mcp_servers: None,
+        router: None,
+        max_retries: None,
+        extra: Some(Extra {
+            delegates: delegates.to_vec(),
+            ..Default::default()
+        }),
+        fallbacks: None,
+        provider_specific: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delegate(name: &str) -> DelegateDefinition {
+        DelegateDefinition {
+            name: name.to_string(),
+            model: "openai/gpt-4o-mini".to_string(),
+            description: None,
+            instructions: Some("You are a SQL expert".to_string()),
+            max_tokens: Some(256),
+            temperature: None,
+        }
+    }
+
+    #[test]
+    fn test_nested_request_carries_delegates() {
+        let request = nested_request(
+            &delegate("sql_expert"),
+            &[delegate("sql_expert"), delegate("reviewer")],
+            "Write a query".to_string(),
+        );
+        assert_eq!(request.request.model, "openai/gpt-4o-mini");
+        assert_eq!(request.request.max_tokens, Some(256));
+        assert_eq!(request.request.messages.len(), 2);
+        assert_eq!(request.request.messages[0].role, "system");
+        assert_eq!(request.extra.unwrap().delegates.len(), 2);
+    }
+}
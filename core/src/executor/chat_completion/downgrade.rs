@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::executor::chat_completion::summarization::estimate_tokens;
+use crate::types::gateway::{ChatCompletionContent, ChatCompletionRequestWithTools, ContentType};
+
+/// Routes simple requests to a cheaper model
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DowngradeConfig {
+    /// Policy by requested model name
+    #[serde(default)]
+    pub models: HashMap<String, DowngradePolicy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DowngradePolicy {
+    /// Model used for requests matching the policy
+    pub target: String,
+    /// Longest estimated input that is still downgraded
+    #[serde(default = "default_max_input_tokens")]
+    pub max_input_tokens: usize,
+    /// Downgrade requests with tools, MCP servers or delegates
+    #[serde(default)]
+    pub allow_tools: bool,
+    /// Downgrade requests with image or audio content
+    #[serde(default)]
+    pub allow_media: bool,
+}
+
+fn default_max_input_tokens() -> usize {
+    1000
+}
+
+impl DowngradeConfig {
+    /// Policy for the request when it does not need the capabilities of the requested model
+    pub fn policy_for<T>(
+        &self,
+        request: &ChatCompletionRequestWithTools<T>,
+    ) -> Option<(&DowngradePolicy, usize)> {
+        if request.extra.as_ref().is_some_and(|e| e.disable_downgrade) {
+            return None;
+        }
+
+        let policy = self.models.get(&request.request.model)?;
+        if !policy.allow_tools && has_tools(request) {
+            return None;
+        }
+        if !policy.allow_media && has_media(request) {
+            return None;
+        }
+
+        let input_tokens = estimate_tokens(&request.request.messages);
+        (input_tokens <= policy.max_input_tokens).then_some((policy, input_tokens))
+    }
+}
+
+fn has_tools<T>(request: &ChatCompletionRequestWithTools<T>) -> bool {
+    request
+        .request
+        .tools
+        .as_ref()
+        .is_some_and(|t| !t.is_empty())
+        || request.mcp_servers.as_ref().is_some_and(|s| !s.is_empty())
+        || request
+            .extra
+            .as_ref()
+            .is_some_and(|e| !e.delegates.is_empty())
+}
+
+fn has_media<T>(request: &ChatCompletionRequestWithTools<T>) -> bool {
+    request.request.messages.iter().any(|m| match &m.content {
+        Some(ChatCompletionContent::Content(parts)) => {
+            parts.iter().any(|p| p.r#type != ContentType::Text)
+        }
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::{
+        ChatCompletionMessage, ChatCompletionRequest, Content, Extra, ImageUrl,
+    };
+
+    fn config() -> DowngradeConfig {
+        DowngradeConfig {
+            models: HashMap::from([(
+                "openai/gpt-4o".to_string(),
+                DowngradePolicy {
+                    target: "openai/gpt-4o-mini".to_string(),
+                    max_input_tokens: 50,
+                    allow_tools: false,
+                    allow_media: false,
+                },
+            )]),
+        }
+    }
+
+    fn request(content: ChatCompletionContent) -> ChatCompletionRequestWithTools<()> {
+        ChatCompletionRequestWithTools {
+            request: ChatCompletionRequest {
+                model: "openai/gpt-4o".to_string(),
+                messages: vec![ChatCompletionMessage {
+                    role: "user".to_string(),
+                    content: Some(content),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_short_text_request_is_downgraded() {
+        let config = config();
+        let request = request(ChatCompletionContent::Text("Hi there".to_string()));
+        let (policy, _) = config.policy_for(&request).unwrap();
+        assert_eq!(policy.target, "openai/gpt-4o-mini");
+
+        let mut other_model = request.clone();
+        other_model.request.model = "openai/gpt-4o-mini".to_string();
+        assert!(config.policy_for(&other_model).is_none());
+    }
+
+    #[test]
+    fn test_downgrade_criteria() {
+        let config = config();
+
+        let long = request(ChatCompletionContent::Text("word ".repeat(500)));
+        assert!(config.policy_for(&long).is_none());
+
+        let image = request(ChatCompletionContent::Content(vec![Content {
+            r#type: ContentType::ImageUrl,
+            image_url: Some(ImageUrl {
+                url: "https://example.com/image.jpg".to_string(),
+            }),
+            ..Default::default()
+        }]));
+        assert!(config.policy_for(&image).is_none());
+
+        let mut disabled = request(ChatCompletionContent::Text("Hi".to_string()));
+        disabled.extra = Some(Extra {
+            disable_downgrade: true,
+            ..Default::default()
+        });
+        assert!(config.policy_for(&disabled).is_none());
+    }
+}
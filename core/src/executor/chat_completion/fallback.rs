@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use either::Either::{self, Left, Right};
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::executor::chat_completion::stream_wrapper::ChatCompletionStream;
+use crate::types::gateway::{
+    ChatCompletionRequestWithTools, ChatCompletionResponse, ModelNameOrTarget,
+};
+use crate::GatewayApiError;
+
+/// Comma separated models tried after the request `fallbacks`
+pub const FALLBACK_MODELS_HEADER: &str = "x-fallback-models";
+
+pub type ExecutionResult = Result<
+    Either<
+        Result<ChatCompletionStream, GatewayApiError>,
+        Result<ChatCompletionResponse, GatewayApiError>,
+    >,
+    GatewayApiError,
+>;
+
+/// Models tried in order when the requested model fails with a retryable error
+pub fn fallback_models<T>(
+    request: &ChatCompletionRequestWithTools<T>,
+    headers: &HashMap<String, String>,
+) -> Vec<ModelNameOrTarget> {
+    let mut fallbacks = request.fallbacks.clone().unwrap_or_default();
+    if let Some(header) = headers.get(FALLBACK_MODELS_HEADER) {
+        fallbacks.extend(
+            header
+                .split(',')
+                .map(str::trim)
+                .filter(|m| !m.is_empty())
+                .map(|m| ModelNameOrTarget::ModelName(m.to_string())),
+        );
+    }
+    fallbacks
+}
+
+/// Request for a fallback model, a target overrides the request fields it contains
+pub fn fallback_request<T: Serialize + DeserializeOwned + Clone>(
+    request: &ChatCompletionRequestWithTools<T>,
+    fallback: &ModelNameOrTarget,
+) -> Result<ChatCompletionRequestWithTools<T>, GatewayApiError> {
+    let mut fallback_request = match fallback {
+        ModelNameOrTarget::ModelName(model) => {
+            let mut fallback_request = request.clone();
+            fallback_request.request.model = model.clone();
+            fallback_request
+        }
+        ModelNameOrTarget::Target(target) => {
+            let mut value = serde_json::to_value(request)?;
+            if let Some(obj) = value.as_object_mut() {
+                for (key, value) in target.iter().filter(|(_, v)| !v.is_null()) {
+                    obj.insert(key.clone(), value.clone());
+                }
+            }
+            serde_json::from_value(value)?
+        }
+    };
+    fallback_request.fallbacks = None;
+    Ok(fallback_request)
+}
+
+/// Waits for the first streamed event so failures before the first token surface as errors
+pub async fn settle(
+    result: ExecutionResult,
+) -> Result<Either<ChatCompletionStream, ChatCompletionResponse>, GatewayApiError> {
+    match result? {
+        Right(response) => Ok(Right(response?)),
+        Left(stream) => {
+            let mut stream = stream?;
+            match stream.next().await {
+                Some(Err(e)) => Err(e),
+                Some(Ok(first)) => Ok(Left(Box::pin(
+                    futures::stream::once(async move { Ok(first) }).chain(stream),
+                ))),
+                None => Ok(Left(Box::pin(futures::stream::empty()))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::ChatCompletionRequest;
+
+    fn request() -> ChatCompletionRequestWithTools<()> {
+        ChatCompletionRequestWithTools {
+            request: ChatCompletionRequest {
+                model: "openai/gpt-4o".to_string(),
+                temperature: Some(0.2),
+                ..Default::default()
+            },
+            fallbacks: Some(vec![ModelNameOrTarget::ModelName(
+                "anthropic/claude-3-5-haiku".to_string(),
+            )]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fallback_models_from_request_and_header() {
+        let headers = HashMap::from([(
+            FALLBACK_MODELS_HEADER.to_string(),
+            "gemini/gemini-2.0-flash, ,openai/gpt-4o-mini".to_string(),
+        )]);
+        let models = fallback_models(&request(), &headers)
+            .into_iter()
+            .map(|m| match m {
+                ModelNameOrTarget::ModelName(m) => m,
+                ModelNameOrTarget::Target(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            models,
+            vec![
+                "anthropic/claude-3-5-haiku",
+                "gemini/gemini-2.0-flash",
+                "openai/gpt-4o-mini"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fallback_request_applies_target() {
+        let target = ModelNameOrTarget::Target(HashMap::from([
+            ("model".to_string(), serde_json::json!("openai/gpt-4o-mini")),
+            ("temperature".to_string(), serde_json::json!(0.7)),
+            ("top_p".to_string(), serde_json::Value::Null),
+        ]));
+        let fallback = fallback_request(&request(), &target).unwrap();
+
+        assert_eq!(fallback.request.model, "openai/gpt-4o-mini");
+        assert_eq!(fallback.request.temperature, Some(0.7));
+        assert!(fallback.fallbacks.is_none());
+    }
+}
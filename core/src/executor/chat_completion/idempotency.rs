@@ -0,0 +1,333 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::mapref::entry::Entry as MapEntry;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::watch;
+
+use crate::executor::chat_completion::basic_executor::BasicCacheContext;
+use crate::executor::chat_completion::response_cache::{
+    record_completion, replay_completion, CachedCompletion,
+};
+use crate::executor::chat_completion::stream_executor::StreamCacheContext;
+use crate::executor::context::ExecutorContext;
+use crate::types::credentials::Credentials;
+use crate::types::gateway::ChatCompletionRequestWithTools;
+use crate::GatewayApiError;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdempotencyConfig {
+    /// Time a key keeps answering with the stored response
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Time a repeated request waits for the request in flight with its key before it is
+    /// rejected with 409
+    #[serde(default = "default_wait_timeout_ms")]
+    pub wait_timeout_ms: u64,
+}
+
+fn default_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_wait_timeout_ms() -> u64 {
+    60_000
+}
+
+enum State {
+    /// Dropped by the first request once it finished or failed
+    InFlight(watch::Receiver<()>),
+    Done(CachedCompletion),
+}
+
+struct Entry {
+    body_hash: String,
+    state: State,
+    expires_at: Instant,
+}
+
+/// Responses of requests sent with an `Idempotency-Key` header, so retried requests are
+/// answered without calling the model again
+pub struct IdempotencyKeys {
+    config: IdempotencyConfig,
+    entries: DashMap<String, Entry>,
+}
+
+pub enum IdempotencyClaim {
+    /// The key already has a response
+    Replay(CachedCompletion),
+    /// The request is the first with the key and runs the model
+    Owner(IdempotencyGuard),
+}
+
+impl IdempotencyKeys {
+    pub fn new(config: IdempotencyConfig) -> Self {
+        Self {
+            config,
+            entries: DashMap::new(),
+        }
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.config.ttl_secs)
+    }
+
+    /// Claims the key for the request body, waiting up to `wait_timeout_ms` for a request in
+    /// flight with the same key. A key reused with another body is rejected.
+    pub async fn claim(
+        self: &Arc<Self>,
+        key: String,
+        body_hash: String,
+    ) -> Result<IdempotencyClaim, GatewayApiError> {
+        let now = Instant::now();
+        self.entries.retain(|_, entry| entry.expires_at > now);
+        let deadline = now + Duration::from_millis(self.config.wait_timeout_ms);
+
+        loop {
+            let mut in_flight = match self.entries.entry(key.clone()) {
+                MapEntry::Occupied(entry) if entry.get().expires_at > Instant::now() => {
+                    let entry = entry.get();
+                    if entry.body_hash != body_hash {
+                        return Err(GatewayApiError::UnprocessableEntity(
+                            "Idempotency-Key was already used with a different request body"
+                                .to_string(),
+                        ));
+                    }
+                    match &entry.state {
+                        State::Done(completion) => {
+                            return Ok(IdempotencyClaim::Replay(completion.clone()))
+                        }
+                        State::InFlight(receiver) => receiver.clone(),
+                    }
+                }
+                entry => {
+                    let (sender, receiver) = watch::channel(());
+                    entry.insert(Entry {
+                        body_hash,
+                        state: State::InFlight(receiver),
+                        expires_at: Instant::now() + self.ttl(),
+                    });
+                    return Ok(IdempotencyClaim::Owner(IdempotencyGuard {
+                        keys: self.clone(),
+                        key,
+                        _sender: sender,
+                    }));
+                }
+            };
+
+            // Wakes up once the first request drops its guard, a failed request leaves the
+            // key free for the next one
+            if tokio::time::timeout_at(deadline.into(), in_flight.changed())
+                .await
+                .is_err()
+            {
+                return Err(GatewayApiError::Conflict(
+                    "A request with this Idempotency-Key is still in progress".to_string(),
+                ));
+            }
+        }
+    }
+}
+
+/// Held by the request running the model, dropping it without a response frees the key
+pub struct IdempotencyGuard {
+    keys: Arc<IdempotencyKeys>,
+    key: String,
+    _sender: watch::Sender<()>,
+}
+
+impl IdempotencyGuard {
+    pub fn finish(self, completion: CachedCompletion) {
+        if let Some(mut entry) = self.keys.entries.get_mut(&self.key) {
+            entry.state = State::Done(completion);
+            entry.expires_at = Instant::now() + self.keys.ttl();
+        }
+    }
+}
+
+impl Drop for IdempotencyGuard {
+    fn drop(&mut self) {
+        self.keys.entries.remove_if(&self.key, |_, entry| {
+            matches!(entry.state, State::InFlight(_))
+        });
+    }
+}
+
+/// `Idempotency-Key` of a client request with the hash of its body
+#[derive(Debug, Clone)]
+pub struct IdempotencyKey {
+    key: String,
+    body_hash: String,
+}
+
+/// Key of the client request, `None` without the header or without idempotency configured
+pub fn idempotency_key<T: Serialize>(
+    request_with_tools: &ChatCompletionRequestWithTools<T>,
+    executor_context: &ExecutorContext,
+) -> Result<Option<IdempotencyKey>, GatewayApiError> {
+    if executor_context.idempotency.is_none() {
+        return Ok(None);
+    }
+    let Some(key) = executor_context.headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+
+    // Objects serialize with sorted keys, which keeps the hash stable
+    let body = serde_json::to_value(request_with_tools)?.to_string();
+    Ok(Some(IdempotencyKey {
+        key: scoped_key(executor_context.key_credentials.as_ref(), key),
+        body_hash: format!("{:x}", Sha256::digest(body.as_bytes())),
+    }))
+}
+
+/// Replays the stored response of a repeated `Idempotency-Key`, or records the response
+/// of the first request with the key
+pub async fn attach_idempotency<T>(
+    request_with_tools: &ChatCompletionRequestWithTools<T>,
+    executor_context: &ExecutorContext,
+    stream_cache_context: &mut StreamCacheContext,
+    basic_cache_context: &mut BasicCacheContext,
+) -> Result<(), GatewayApiError> {
+    let (Some(keys), Some(key)) = (
+        &executor_context.idempotency,
+        &executor_context.idempotency_key,
+    ) else {
+        return Ok(());
+    };
+
+    let stream = request_with_tools.request.stream.unwrap_or(false);
+    match keys.claim(key.key.clone(), key.body_hash.clone()).await? {
+        IdempotencyClaim::Replay(completion) => replay_completion(
+            completion,
+            stream,
+            stream_cache_context,
+            basic_cache_context,
+        ),
+        IdempotencyClaim::Owner(guard) => {
+            let completion = record_completion(
+                request_with_tools.request.model.clone(),
+                stream,
+                stream_cache_context,
+                basic_cache_context,
+            );
+            tokio::spawn(async move {
+                if let Ok(completion) = completion.await {
+                    guard.finish(completion);
+                }
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Keys of different API keys never collide
+fn scoped_key(credentials: Option<&Credentials>, key: &str) -> String {
+    let api_key = match credentials {
+        Some(Credentials::ApiKey(credentials)) => credentials.api_key.as_str(),
+        Some(Credentials::ApiKeyWithEndpoint { api_key, .. }) => api_key.as_str(),
+        _ => "anonymous",
+    };
+    let digest = Sha256::digest(format!("{api_key}:{key}").as_bytes());
+    format!("idempotency:{digest:x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::ChatCompletionMessage;
+
+    fn keys() -> Arc<IdempotencyKeys> {
+        Arc::new(IdempotencyKeys::new(IdempotencyConfig {
+            ttl_secs: 60,
+            wait_timeout_ms: 1_000,
+        }))
+    }
+
+    fn completion(content: &str) -> CachedCompletion {
+        CachedCompletion {
+            model: "openai/gpt-4o-mini".to_string(),
+            events: vec![],
+            response: ChatCompletionMessage::new_text("assistant".to_string(), content.to_string()),
+        }
+    }
+
+    fn content(claim: IdempotencyClaim) -> String {
+        match claim {
+            IdempotencyClaim::Replay(c) => c.response.content.unwrap().as_string().unwrap(),
+            IdempotencyClaim::Owner(_) => panic!("expected a replayed response"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_request_waits_for_first() {
+        let keys = keys();
+        let IdempotencyClaim::Owner(guard) = keys.claim("a".into(), "body".into()).await.unwrap()
+        else {
+            panic!("expected the first request to run the model");
+        };
+
+        let waiting = tokio::spawn({
+            let keys = keys.clone();
+            async move { keys.claim("a".into(), "body".into()).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+
+        guard.finish(completion("Hello"));
+        assert_eq!(content(waiting.await.unwrap().unwrap()), "Hello");
+        let claim = keys.claim("a".into(), "body".into()).await.unwrap();
+        assert_eq!(content(claim), "Hello");
+
+        assert!(matches!(
+            keys.claim("a".into(), "other body".into()).await,
+            Err(GatewayApiError::UnprocessableEntity(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_waiting_request_times_out() {
+        let keys = Arc::new(IdempotencyKeys::new(IdempotencyConfig {
+            ttl_secs: 60,
+            wait_timeout_ms: 20,
+        }));
+        let _guard = keys.claim("a".into(), "body".into()).await.unwrap();
+
+        assert!(matches!(
+            keys.claim("a".into(), "body".into()).await,
+            Err(GatewayApiError::Conflict(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failed_request_frees_key() {
+        let keys = keys();
+        let first = keys.claim("a".into(), "body".into()).await.unwrap();
+        assert!(matches!(first, IdempotencyClaim::Owner(_)));
+        drop(first);
+
+        let retry = keys.claim("a".into(), "body".into()).await.unwrap();
+        assert!(matches!(retry, IdempotencyClaim::Owner(_)));
+    }
+
+    #[test]
+    fn test_scoped_key() {
+        let credentials = |api_key: &str| {
+            Credentials::ApiKey(crate::types::credentials::ApiKeyCredentials {
+                api_key: api_key.to_string(),
+            })
+        };
+        assert_eq!(
+            scoped_key(Some(&credentials("k1")), "retry-1"),
+            scoped_key(Some(&credentials("k1")), "retry-1")
+        );
+        assert_ne!(
+            scoped_key(Some(&credentials("k1")), "retry-1"),
+            scoped_key(Some(&credentials("k2")), "retry-1")
+        );
+    }
+}
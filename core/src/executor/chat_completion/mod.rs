@@ -1,7 +1,24 @@
-use crate::error::GatewayError;
-use crate::executor::chat_completion::basic_executor::BasicCacheContext;
+use crate::error::{GatewayError, InvalidRequestError};
+use crate::executor::chat_completion::basic_executor::{add_round_usage, BasicCacheContext};
+use crate::executor::chat_completion::choices::{append_choices, ADDITIONAL_CHOICES_EVENT};
+use crate::executor::chat_completion::confidence::{
+    check_logprobs_support, with_logprobs, TOKEN_LOGPROBS_EVENT,
+};
+use crate::executor::chat_completion::delegate::DelegateTool;
+use crate::executor::chat_completion::fallback::{
+    fallback_models, fallback_request, settle, ExecutionResult,
+};
+use crate::executor::chat_completion::penalty_emulation::emulate_penalties;
+use crate::executor::chat_completion::response_cache::attach_response_cache;
+use crate::executor::chat_completion::retrieval::retrieve_context;
+use crate::executor::chat_completion::seed::{supports_seed, SYSTEM_FINGERPRINT_EVENT};
+use crate::executor::chat_completion::stop_sequences::normalize_stop;
 use crate::executor::chat_completion::stream_executor::{stream_chunks, StreamCacheContext};
-use crate::handler::{find_model_by_full_name, ModelEventWithDetails};
+use crate::executor::chat_completion::stream_transform::StreamTransformPipeline;
+use crate::executor::chat_completion::summarization::summarize_conversation;
+use crate::executor::circuit_breaker::{deployment_key, is_failure, CircuitTransition};
+use crate::executor::provider_limiter::hold_permit;
+use crate::handler::{CallbackHandlerFn, ModelEventWithDetails};
 use crate::llm_gateway::message_mapper::MessageMapper;
 use crate::llm_gateway::provider::Provider;
 use crate::model::cached::CachedModel;
@@ -11,48 +28,349 @@ use crate::model::types::ModelEvent;
 use crate::model::types::ModelEventType;
 use crate::model::{ModelInstance, ResponseCacheState};
 use crate::models::ModelMetadata;
+use crate::types::cache::CacheDirectives;
 use crate::types::engine::{
     CompletionModelDefinition, CompletionModelParams, ExecutionOptions, Model, ModelTool,
     ModelTools, ModelType, Prompt,
 };
 use crate::types::gateway::{
-    ChatCompletionMessage, ChatCompletionRequestWithTools, ChatCompletionResponse, Extra,
+    ChatCompletionChoice, ChatCompletionLogprobs, ChatCompletionMessage,
+    ChatCompletionRequestWithTools, ChatCompletionResponse, Extra, ReplaceRule,
+    StreamTransformDefinition, ToolChoice, ToolChoiceMode, Usage,
 };
 use crate::GatewayApiError;
 
-use either::Either::{self, Left, Right};
+use either::Either::{Left, Right};
+use parking_lot::Mutex;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::Arc;
 use tracing::Span;
 use tracing_futures::Instrument;
 use uuid::Uuid;
 
 use super::context::ExecutorContext;
+use super::deployments::resolve_deployment;
+use super::spend_budget::{estimate_request_usage, DEFAULT_MAX_OUTPUT_TOKENS};
 use super::{get_key_credentials, use_langdb_proxy};
 use crate::executor::chat_completion::stream_wrapper::ChatCompletionStream;
 
+pub mod backoff;
 pub mod basic_executor;
+pub mod choices;
+pub mod confidence;
+pub mod continuation;
+pub mod delegate;
+pub mod downgrade;
+pub mod fallback;
+pub mod idempotency;
+pub mod moderation;
+pub mod penalty_emulation;
+pub mod quirks;
+pub mod response_cache;
+pub mod retrieval;
 pub mod routed_executor;
+pub mod seed;
+pub mod stop_sequences;
+pub mod stream_collector;
 pub mod stream_executor;
+pub mod stream_transform;
 pub mod stream_wrapper;
-
+pub mod structured_output;
+pub mod summarization;
+pub mod temperature_sampling;
+pub mod tool_emulation;
+pub mod validation;
+
+/// Validates and executes the request with the configured content guardrail applied to
+/// the messages and to the completion
 pub async fn execute<T: Serialize + DeserializeOwned + Debug + Clone>(
     request_with_tools: &ChatCompletionRequestWithTools<T>,
     executor_context: &ExecutorContext,
     router_span: tracing::Span,
     stream_cache_context: StreamCacheContext,
     basic_cache_context: BasicCacheContext,
-) -> Result<
-    Either<
-        Result<ChatCompletionStream, GatewayApiError>,
-        Result<ChatCompletionResponse, GatewayApiError>,
-    >,
-    GatewayApiError,
-> {
+) -> ExecutionResult {
+    validation::validate_request(&request_with_tools.request)?;
+    let routed = route_by_tags(request_with_tools, executor_context, &router_span);
+    let request_with_tools = routed.as_ref().unwrap_or(request_with_tools);
+
+    let Some(moderation) = &executor_context.moderation else {
+        return execute_with_fallbacks(
+            request_with_tools,
+            executor_context,
+            router_span,
+            stream_cache_context,
+            basic_cache_context,
+        )
+        .await;
+    };
+
+    let moderated = moderation
+        .moderate_input(request_with_tools, executor_context)
+        .await?;
+    let request_with_tools = moderated.as_ref().unwrap_or(request_with_tools);
+
+    match execute_with_fallbacks(
+        request_with_tools,
+        executor_context,
+        router_span,
+        stream_cache_context,
+        basic_cache_context,
+    )
+    .await?
+    {
+        Left(Ok(stream)) => Ok(Left(Ok(moderation.moderate_stream(
+            stream,
+            executor_context.callbackhandler.clone(),
+            executor_context.request_id.clone(),
+        )))),
+        Right(Ok(mut response)) => {
+            moderation
+                .moderate_response(&mut response, executor_context)
+                .await?;
+            Ok(Right(Ok(response)))
+        }
+        result => Ok(result),
+    }
+}
+
+/// Replaces the requested model by the first matching tag routing rule. Applied once, before
+/// the response cache and the fallback models, which keep their own models.
+fn route_by_tags<T: Clone>(
+    request_with_tools: &ChatCompletionRequestWithTools<T>,
+    executor_context: &ExecutorContext,
+    router_span: &Span,
+) -> Option<ChatCompletionRequestWithTools<T>> {
+    let rule = executor_context
+        .tag_routing
+        .as_ref()
+        .and_then(|r| r.route(&request_with_tools.request.model, &executor_context.tags))?;
+
+    executor_context.emit_custom(
+        router_span,
+        "tag_routing_override",
+        serde_json::json!({
+            "tag": rule.tag,
+            "value": rule.value,
+            "requested_model": request_with_tools.request.model,
+            "model": rule.model,
+        }),
+    );
+
+    let mut routed = request_with_tools.clone();
+    routed.request.model = rule.model.clone();
+    Some(routed)
+}
+
+/// Executes the request, moving to the fallback models while attempts fail with retryable
+/// errors. Streams fall back only until the first event is received.
+async fn execute_with_fallbacks<T: Serialize + DeserializeOwned + Debug + Clone>(
+    request_with_tools: &ChatCompletionRequestWithTools<T>,
+    executor_context: &ExecutorContext,
+    router_span: tracing::Span,
+    mut stream_cache_context: StreamCacheContext,
+    mut basic_cache_context: BasicCacheContext,
+) -> ExecutionResult {
+    attach_response_cache(
+        request_with_tools,
+        executor_context,
+        &mut stream_cache_context,
+        &mut basic_cache_context,
+    )
+    .await;
+
+    let fallbacks = fallback_models(request_with_tools, &executor_context.headers);
+    if fallbacks.is_empty() {
+        return execute_model(
+            request_with_tools,
+            executor_context,
+            router_span,
+            stream_cache_context,
+            basic_cache_context,
+        )
+        .await;
+    }
+
+    // Each attempt would fetch the linked images again, they are fetched once for all
+    let mut inlined = None;
+    if MessageMapper::has_linked_images(&request_with_tools.request.messages) {
+        let mut request = request_with_tools.clone();
+        MessageMapper::inline_request_images(&mut request.request.messages).await?;
+        inlined = Some(request);
+    }
+    let request_with_tools = inlined.as_ref().unwrap_or(request_with_tools);
+
+    let mut model = request_with_tools.request.model.clone();
+    let mut result = settle(
+        execute_model(
+            request_with_tools,
+            executor_context,
+            router_span.clone(),
+            stream_cache_context,
+            basic_cache_context,
+        )
+        .await,
+    )
+    .await;
+
+    let mut attempt = 1;
+    for fallback in &fallbacks {
+        let error = match &result {
+            Err(e) if e.is_retryable() => e,
+            _ => break,
+        };
+        if !executor_context.acquire_retry("model_fallback") {
+            break;
+        }
+        let fallback_request = fallback_request(request_with_tools, fallback)?;
+        emit_model_attempt(executor_context, attempt, &model, Some(error));
+        tracing::warn!(
+            "Model {model} failed with {error}, falling back to {}",
+            fallback_request.request.model
+        );
+
+        attempt += 1;
+        model = fallback_request.request.model.clone();
+        // Cache contexts are consumed by the first attempt, fallback responses are not cached
+        result = settle(
+            execute_model(
+                &fallback_request,
+                executor_context,
+                router_span.clone(),
+                StreamCacheContext::default(),
+                BasicCacheContext::default(),
+            )
+            .await,
+        )
+        .await;
+    }
+
+    match result {
+        Ok(Left(stream)) => {
+            emit_model_attempt(executor_context, attempt, &model, None);
+            Ok(Left(Ok(stream)))
+        }
+        Ok(Right(response)) => {
+            emit_model_attempt(executor_context, attempt, &model, None);
+            Ok(Right(Ok(response)))
+        }
+        Err(e) => {
+            emit_model_attempt(executor_context, attempt, &model, Some(&e));
+            Err(e)
+        }
+    }
+}
+
+fn emit_circuit_transition(
+    callbackhandler: &CallbackHandlerFn,
+    request_id: Option<String>,
+    transition: CircuitTransition,
+) {
+    tracing::warn!(
+        "Circuit of deployment {} changed from {:?} to {:?}",
+        transition.deployment,
+        transition.from,
+        transition.to
+    );
+    callbackhandler.on_custom(
+        &Span::current(),
+        "circuit_breaker",
+        serde_json::to_value(&transition).unwrap_or_default(),
+        request_id,
+    );
+}
+
+fn emit_model_attempt(
+    executor_context: &ExecutorContext,
+    attempt: usize,
+    model: &str,
+    error: Option<&GatewayApiError>,
+) {
+    executor_context.emit_custom(
+        &Span::current(),
+        "model_attempt",
+        serde_json::json!({
+            "attempt": attempt,
+            "model": model,
+            "status": if error.is_some() { "failed" } else { "succeeded" },
+            "error": error.map(|e| e.to_string()),
+        }),
+    );
+}
+
+/// Transforms of the streamed content, with the stop sentinel removed first when stripped
+fn stream_transforms(extra: &Extra) -> StreamTransformPipeline {
+    let mut definitions = extra.transforms.clone();
+    // Streamed content is already sent when the sentinel is detected, strip it on the way out
+    if let Some(stop_sentinel) = extra.stop_sentinel.as_ref().filter(|s| s.strip) {
+        definitions.insert(
+            0,
+            StreamTransformDefinition::Replace {
+                rules: vec![ReplaceRule {
+                    from: stop_sentinel.sentinel.clone(),
+                    to: String::new(),
+                }],
+                case_insensitive: false,
+            },
+        );
+    }
+    StreamTransformPipeline::from_definitions(&definitions)
+}
+
+/// Rejects a tool choice that cannot be satisfied with the tools of the request
+fn check_tool_choice(
+    choice: &ToolChoice,
+    tools: &HashMap<String, Box<dyn Tool>>,
+) -> Result<(), GatewayApiError> {
+    if let Some(name) = choice.function_name() {
+        if !tools.contains_key(name) {
+            return Err(InvalidRequestError::new(
+                "tool_choice",
+                "invalid_value",
+                format!("tool_choice function {name} is not one of the provided tools"),
+            )
+            .into());
+        }
+    }
+    if *choice == ToolChoice::Mode(ToolChoiceMode::Required) && tools.is_empty() {
+        return Err(InvalidRequestError::new(
+            "tool_choice",
+            "invalid_value",
+            "tool_choice required needs at least one tool",
+        )
+        .into());
+    }
+    Ok(())
+}
+
+async fn execute_model<T: Serialize + DeserializeOwned + Debug + Clone>(
+    request_with_tools: &ChatCompletionRequestWithTools<T>,
+    executor_context: &ExecutorContext,
+    router_span: tracing::Span,
+    mut stream_cache_context: StreamCacheContext,
+    mut basic_cache_context: BasicCacheContext,
+) -> ExecutionResult {
     let span = Span::current();
 
+    let summarized = summarize_conversation(request_with_tools, executor_context).await?;
+    let request_with_tools = summarized.as_ref().unwrap_or(request_with_tools);
+
+    let retrieved = retrieve_context(request_with_tools, executor_context).await?;
+    let (request_with_tools, citations) = match &retrieved {
+        Some((request, citations)) => (request, citations.clone()),
+        None => (request_with_tools, None),
+    };
+
+    // Confidence scoring may enable log probabilities the caller did not ask for
+    // Support is checked against the client's request, confidence scores degrade to none
+    let client_request = &request_with_tools.request;
+    let return_logprobs = client_request.logprobs == Some(true);
+    let with_logprobs = with_logprobs(request_with_tools);
+    let request_with_tools = with_logprobs.as_ref().unwrap_or(request_with_tools);
+
     let mut request_tools = vec![];
     let mut tools_map = HashMap::new();
     if let Some(tools) = &request_with_tools.request.tools {
@@ -84,10 +402,43 @@ pub async fn execute<T: Serialize + DeserializeOwned + Debug + Clone>(
         }
     }
 
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<Option<ModelEvent>>(1000);
+    for tool in DelegateTool::from_extra(request_with_tools.extra.as_ref(), executor_context) {
+        request_tools.push(ModelTool {
+            name: tool.name(),
+            description: Some(tool.description()),
+            passed_args: vec![],
+        });
+        tools_map.insert(tool.name(), Box::new(tool) as Box<dyn Tool>);
+    }
+
+    if let Some(choice) = &request_with_tools.request.tool_choice {
+        check_tool_choice(choice, &tools_map)?;
+    }
+
+    let buffer_size = executor_context
+        .memory_pressure
+        .as_ref()
+        .map_or(1000, |m| m.buffer_size(1000));
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Option<ModelEvent>>(buffer_size);
 
     let tools = ModelTools(request_tools);
 
+    let cache_directives = executor_context
+        .headers
+        .get(CacheDirectives::HEADER)
+        .map(|h| CacheDirectives::parse(h))
+        .unwrap_or_default();
+    if cache_directives.skip_read {
+        stream_cache_context.cached_events = None;
+        basic_cache_context.cached_events = None;
+        basic_cache_context.cached_response = None;
+    }
+    if cache_directives.skip_write {
+        stream_cache_context.events_sender = None;
+        basic_cache_context.events_sender = None;
+        basic_cache_context.response_sender = None;
+    }
+
     let mut cached_instance = None;
     let mut cache_state = match request_with_tools.extra {
         Some(Extra { cache: Some(_), .. }) => Some(ResponseCacheState::Miss),
@@ -105,6 +456,21 @@ pub async fn execute<T: Serialize + DeserializeOwned + Debug + Clone>(
         }
     }
 
+    if !cache_directives.is_default() {
+        executor_context.emit_custom(
+            &span,
+            "cache_control",
+            serde_json::json!({
+                "directives": cache_directives,
+                "cache_state": cache_state.as_ref().map(|s| s.to_string()),
+            }),
+        );
+    }
+    if cache_directives.only_if_cached && cached_instance.is_none() {
+        return Err(GatewayApiError::CacheMiss);
+    }
+
+    let cache_hit = cached_instance.is_some();
     let resolved_model_context = resolve_model_instance(
         executor_context,
         request_with_tools,
@@ -119,14 +485,107 @@ pub async fn execute<T: Serialize + DeserializeOwned + Debug + Clone>(
     .await?;
 
     let mut request = request_with_tools.request.clone();
-    let llm_model = find_model_by_full_name(&request.model, &executor_context.provided_models)?;
+    let llm_model = resolved_model_context.deployment.clone();
+
+    // Responses replayed from the cache do not reach the deployment
+    let circuit = executor_context
+        .circuit_breakers
+        .clone()
+        .filter(|_| !cache_hit)
+        .map(|breakers| (breakers, deployment_key(&llm_model)));
+    if let Some((breakers, deployment)) = &circuit {
+        if let Some(transition) = breakers.acquire(deployment)? {
+            emit_circuit_transition(
+                &executor_context.callbackhandler,
+                executor_context.request_id.clone(),
+                transition,
+            );
+        }
+    }
     request.model = llm_model.inference_provider.model_name.clone();
+    check_logprobs_support(client_request, &llm_model.inference_provider.provider)?;
+    if let Some(seed) = request
+        .seed
+        .filter(|_| !supports_seed(&llm_model.inference_provider.provider))
+    {
+        executor_context.emit_custom(
+            &span,
+            "seed_ignored",
+            serde_json::json!({
+                "provider": llm_model.inference_provider.provider.to_string(),
+                "seed": seed,
+            }),
+        );
+    }
+
+    if let Some(account) = &executor_context.spend_budget {
+        let output_limit = llm_model
+            .limits
+            .max_output_tokens
+            .unwrap_or(DEFAULT_MAX_OUTPUT_TOKENS);
+        let usage = estimate_request_usage(&request, output_limit);
+        // A call whose cost cannot be estimated could exceed the budget unnoticed
+        let estimated_cost = executor_context
+            .cost_calculator
+            .calculate_cost(
+                &resolved_model_context.completion_model_definition.name,
+                &resolved_model_context.db_model.provider_name,
+                &Usage::CompletionModelUsage(usage),
+            )
+            .await?
+            .cost;
+        account.reserve(estimated_cost).await?;
+    }
+
+    let emulate = request_with_tools
+        .extra
+        .as_ref()
+        .is_some_and(|e| e.emulate_penalties);
+    if emulate {
+        if let Some(instruction) =
+            emulate_penalties(&mut request, &llm_model.inference_provider.provider)
+        {
+            executor_context.emit_custom(
+                &span,
+                "penalty_emulation",
+                serde_json::json!({
+                    "provider": llm_model.inference_provider.provider.to_string(),
+                    "frequency_penalty": request.frequency_penalty,
+                    "presence_penalty": request.presence_penalty,
+                    "instruction": instruction,
+                }),
+            );
+        }
+    }
+
+    // Added before the quirks so a profile merging system messages also covers it
+    if let Some(system_prompt) = &llm_model.system_prompt {
+        system_prompt.apply(&mut request.messages);
+    }
+
+    if let Some((profile_name, profile)) = executor_context.quirks.as_ref().and_then(|q| {
+        q.profile_for(
+            &request_with_tools.request.model,
+            &llm_model.inference_provider.model_name,
+        )
+    }) {
+        let applied = profile.apply(&mut request.messages);
+        executor_context.emit_custom(
+            &span,
+            "quirk_profile",
+            serde_json::json!({
+                "profile": profile_name,
+                "applied": applied,
+            }),
+        );
+    }
 
     let user: String = request
         .user
         .as_ref()
         .map_or(Uuid::new_v4().to_string(), |v| v.clone());
 
+    MessageMapper::check_image_support(&request.messages, &llm_model)?;
     let mut messages = vec![];
 
     for message in &request.messages {
@@ -136,18 +595,53 @@ pub async fn execute<T: Serialize + DeserializeOwned + Debug + Clone>(
             &user.to_string(),
         )?);
     }
+    let messages =
+        MessageMapper::map_for_provider(messages, &llm_model.inference_provider.provider);
+    let messages =
+        MessageMapper::inline_images(messages, &llm_model.inference_provider.provider).await?;
     let ch = executor_context.callbackhandler.clone();
     let db_model = resolved_model_context.db_model.clone();
+    let token_logprobs = Arc::new(Mutex::new(Vec::new()));
+    let captured_logprobs = token_logprobs.clone();
+    let system_fingerprint = Arc::new(Mutex::new(None));
+    let captured_fingerprint = system_fingerprint.clone();
+    let additional_choices = Arc::new(Mutex::new(Vec::new()));
+    let captured_choices = additional_choices.clone();
     let handle = tokio::spawn(async move {
         let mut stop_event = None;
         let mut tool_calls = None;
         while let Some(Some(msg)) = rx.recv().await {
+            // Token log probabilities are attached to the response, not traced
+            if let ModelEventType::Custom(event) = &msg.event {
+                if event.name() == TOKEN_LOGPROBS_EVENT {
+                    if let Ok(ChatCompletionLogprobs {
+                        content: Some(content),
+                    }) = serde_json::from_value(event.value().clone())
+                    {
+                        captured_logprobs.lock().extend(content);
+                    }
+                    continue;
+                }
+                if event.name() == SYSTEM_FINGERPRINT_EVENT {
+                    *captured_fingerprint.lock() = event.value().as_str().map(String::from);
+                }
+                // Choices are returned to the client, the stop event traces the usage of all
+                if event.name() == ADDITIONAL_CHOICES_EVENT {
+                    if let Ok(choices) =
+                        serde_json::from_value::<Vec<ChatCompletionChoice>>(event.value())
+                    {
+                        captured_choices.lock().extend(choices);
+                    }
+                    continue;
+                }
+            }
+
             if let ModelEvent {
                 event: ModelEventType::LlmStop(e),
                 ..
             } = &msg
             {
-                stop_event = Some(e.clone());
+                add_round_usage(&mut stop_event, e);
             }
 
             if let ModelEvent {
@@ -203,20 +697,72 @@ pub async fn execute<T: Serialize + DeserializeOwned + Debug + Clone>(
         .as_ref()
         .and_then(|e| e.variables.clone())
         .unwrap_or_default();
+    // Held until the provider call finished, for streams until the stream is dropped
+    let model_params = &resolved_model_context
+        .completion_model_definition
+        .model_params;
+    let provider_permit = match &executor_context.provider_limiter {
+        Some(limiter) => Some(
+            limiter
+                .acquire(&model_params.provider_name, &model_params.engine)
+                .await?,
+        ),
+        None => None,
+    };
     if is_stream {
-        Ok(Left(
-            stream_chunks(
-                resolved_model_context.completion_model_definition,
-                resolved_model_context.model_instance,
-                messages.clone(),
-                executor_context.callbackhandler.clone().into(),
-                executor_context.tags.clone(),
-                input_vars,
-                stream_cache_context,
-            )
-            .instrument(span)
-            .await,
-        ))
+        let transforms = request_with_tools
+            .extra
+            .as_ref()
+            .map(stream_transforms)
+            .unwrap_or_default();
+        let ordered_tool_calls = request_with_tools
+            .extra
+            .as_ref()
+            .is_some_and(|e| e.ordered_tool_calls);
+        let include_usage = request
+            .stream_options
+            .as_ref()
+            .is_some_and(|o| o.include_usage);
+        let result = stream_chunks(
+            resolved_model_context.completion_model_definition,
+            resolved_model_context.model_instance,
+            messages.clone(),
+            executor_context.callbackhandler.clone().into(),
+            executor_context.tags.clone(),
+            input_vars,
+            stream_cache_context,
+            transforms,
+            ordered_tool_calls,
+            include_usage,
+        )
+        .instrument(span)
+        .await;
+        let result = match provider_permit {
+            Some(permit) => result.map(|stream| hold_permit(stream, permit)),
+            None => result,
+        };
+
+        Ok(Left(match (circuit, result) {
+            (Some((breakers, deployment)), Ok(stream)) => {
+                let callbackhandler = executor_context.callbackhandler.clone();
+                let request_id = executor_context.request_id.clone();
+                let on_transition = move |transition| {
+                    emit_circuit_transition(&callbackhandler, request_id.clone(), transition)
+                };
+                Ok(breakers.record_stream(deployment, stream, on_transition))
+            }
+            (Some((breakers, deployment)), Err(e)) => {
+                if let Some(transition) = breakers.record(&deployment, e.is_retryable()) {
+                    emit_circuit_transition(
+                        &executor_context.callbackhandler,
+                        executor_context.request_id.clone(),
+                        transition,
+                    );
+                }
+                Err(e)
+            }
+            (None, result) => result,
+        }))
     } else {
         let result = basic_executor::execute(
             request,
@@ -229,8 +775,55 @@ pub async fn execute<T: Serialize + DeserializeOwned + Debug + Clone>(
             input_vars,
             basic_cache_context,
         )
-        .instrument(span)
-        .await;
+        .instrument(span.clone())
+        .await
+        .map(|mut response| {
+            let token_logprobs = std::mem::take(&mut *token_logprobs.lock());
+            response.citations = citations;
+            response.system_fingerprint = system_fingerprint.lock().take();
+            append_choices(
+                &mut response,
+                std::mem::take(&mut *additional_choices.lock()),
+            );
+            response.confidence = request_with_tools
+                .extra
+                .as_ref()
+                .and_then(|e| e.confidence.as_ref())
+                .and_then(|c| {
+                    let logprobs: Vec<f32> = token_logprobs.iter().map(|t| t.logprob).collect();
+                    c.method.score(&logprobs)
+                });
+            if return_logprobs && !token_logprobs.is_empty() {
+                if let Some(choice) = response.choices.first_mut() {
+                    choice.logprobs = Some(ChatCompletionLogprobs {
+                        content: Some(token_logprobs),
+                    });
+                }
+            }
+            response
+        });
+
+        if let Some((breakers, deployment)) = &circuit {
+            if let Some(transition) = breakers.record(deployment, is_failure(&result)) {
+                emit_circuit_transition(
+                    &executor_context.callbackhandler,
+                    executor_context.request_id.clone(),
+                    transition,
+                );
+            }
+        }
+
+        if let Ok(ChatCompletionResponse {
+            confidence: Some(confidence),
+            ..
+        }) = &result
+        {
+            executor_context.emit_custom(
+                &span,
+                "confidence_score",
+                serde_json::to_value(confidence)?,
+            );
+        }
 
         // if let Ok(completion_response) = &result {
         //     let ChatCompletionResponse { choices, .. } = completion_response;
@@ -262,29 +855,65 @@ pub async fn resolve_model_instance<T: Serialize + DeserializeOwned + Debug + Cl
     cached_model: Option<CachedModel>,
     cache_state: Option<ResponseCacheState>,
 ) -> Result<ResolvedModelContext, GatewayApiError> {
-    let llm_model =
-        find_model_by_full_name(&request.request.model, &executor_context.provided_models)?;
-    let (key_credentials, llm_model) = use_langdb_proxy(executor_context, llm_model.clone());
+    let deployment = resolve_deployment(
+        executor_context,
+        &request.request.model,
+        extra,
+        &router_span,
+    )?;
+    let (key_credentials, llm_model) = use_langdb_proxy(executor_context, deployment.clone());
 
+    // Deployments may point at their own providers config entry
+    let credentials_name = llm_model
+        .deployment
+        .as_ref()
+        .and_then(|d| d.credentials.clone())
+        .unwrap_or_else(|| llm_model.inference_provider.provider.to_string());
     let key = get_key_credentials(
         key_credentials.as_ref(),
         executor_context.providers_config.as_ref(),
-        &llm_model.inference_provider.provider.to_string(),
+        &credentials_name,
     );
     let provider_specific = request.provider_specific.clone();
-    let execution_options = request
-        .max_retries
-        .map(|retries| ExecutionOptions {
-            max_retries: Some(retries),
-        })
-        .unwrap_or_default();
+    let execution_options = ExecutionOptions {
+        max_retries: request.max_retries,
+        stop_sentinel: extra.and_then(|e| e.stop_sentinel.clone()),
+        response_validation: executor_context.response_validation.clone(),
+        retry_policy: executor_context.retry_policy.clone(),
+        retry_budget: executor_context.retry_budget.clone(),
+    };
 
-    let request = request.request.clone();
+    let mut request = request.request.clone();
+    // Raw user id stays in the request span, providers only receive its hash
+    if let Some(user_hashing) = &executor_context.user_hashing {
+        request.user = request.user.map(|user| user_hashing.hash(&user));
+    }
+    if let Some(stop) = request.stop.take() {
+        let policy = executor_context
+            .stop_sequences
+            .as_ref()
+            .map(|c| c.policy)
+            .unwrap_or_default();
+        let provider = &llm_model.inference_provider.provider;
+        let normalized = normalize_stop(stop, provider, policy)?;
+        if !normalized.dropped.is_empty() {
+            executor_context.emit_custom(
+                &router_span,
+                "stop_sequences_truncated",
+                serde_json::json!({
+                    "provider": provider.to_string(),
+                    "dropped": normalized.dropped,
+                }),
+            );
+        }
+        request.stop = normalized.stop;
+    }
 
     let engine = Provider::get_completion_engine_for_model(
         &llm_model,
         &request,
         key.clone(),
+        executor_context.provider_credentials.as_ref(),
         provider_specific.as_ref(),
         Some(execution_options.clone()),
     )?;
@@ -336,6 +965,7 @@ pub async fn resolve_model_instance<T: Serialize + DeserializeOwned + Debug + Cl
         model_instance,
         db_model,
         llm_model,
+        deployment,
     })
 }
 
@@ -344,4 +974,34 @@ pub struct ResolvedModelContext {
     pub model_instance: Box<dyn ModelInstance>,
     pub db_model: Model,
     pub llm_model: ModelMetadata,
+    /// Model selected for the request, before any proxy rewrite
+    pub deployment: ModelMetadata,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::engine::StopSentinel;
+
+    fn run(pipeline: &mut StreamTransformPipeline, chunks: &[&str]) -> String {
+        let mut output: String = chunks.iter().map(|c| pipeline.push(c)).collect();
+        output.push_str(&pipeline.finish());
+        output
+    }
+
+    #[test]
+    fn test_stop_sentinel_stripped_from_stream() {
+        let extra = |strip| Extra {
+            stop_sentinel: Some(StopSentinel {
+                sentinel: "<DONE>".to_string(),
+                strip,
+            }),
+            ..Default::default()
+        };
+
+        let mut pipeline = stream_transforms(&extra(true));
+        assert_eq!(run(&mut pipeline, &["All set <DO", "NE>"]), "All set ");
+
+        assert!(stream_transforms(&extra(false)).is_empty());
+    }
 }
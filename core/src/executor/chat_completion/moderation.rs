@@ -0,0 +1,543 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::Span;
+
+use crate::error::GatewayError;
+use crate::executor::chat_completion::stream_wrapper::{wrap_stream, ChatCompletionStream};
+use crate::executor::context::ExecutorContext;
+use crate::handler::chat::SSOChatEvent;
+use crate::handler::CallbackHandlerFn;
+use crate::types::gateway::{
+    ChatCompletionContent, ChatCompletionDelta, ChatCompletionMessage,
+    ChatCompletionRequestWithTools, ChatCompletionResponse,
+};
+use crate::types::guardrails::{GuardError, GuardStage};
+use crate::GatewayApiError;
+
+/// Outcome of a content guardrail check
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ModerationDecision {
+    Allow,
+    Block {
+        reason: String,
+    },
+    /// Passes the content on with `text` in place of the checked text
+    Redact {
+        text: String,
+    },
+}
+
+/// Moderates the messages sent to the model and the completions returned by it
+#[async_trait::async_trait]
+pub trait ContentGuardrail: Send + Sync {
+    async fn check(
+        &self,
+        text: &str,
+        stage: &GuardStage,
+    ) -> Result<ModerationDecision, GatewayError>;
+}
+
+/// Handling of content while the guardrail itself fails
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureMode {
+    /// Content passes unchecked
+    #[default]
+    Open,
+    /// Requests fail
+    Closed,
+}
+
+fn default_stream_chunk_size() -> usize {
+    16
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationConfig {
+    /// Endpoint receiving `{"text", "stage"}` and answering a decision,
+    /// e.g. `{"action": "block", "reason": "violence"}`
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub failure_mode: FailureMode,
+    /// Number of streamed content chunks held back and checked together
+    #[serde(default = "default_stream_chunk_size")]
+    pub stream_chunk_size: usize,
+}
+
+#[derive(Serialize)]
+struct CheckRequest<'a> {
+    text: &'a str,
+    stage: &'a GuardStage,
+}
+
+/// Guardrail backed by an external moderation service
+pub struct HttpContentGuardrail {
+    url: String,
+    headers: HashMap<String, String>,
+    client: reqwest::Client,
+}
+
+impl HttpContentGuardrail {
+    pub fn new(url: String, headers: HashMap<String, String>) -> Self {
+        Self {
+            url,
+            headers,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ContentGuardrail for HttpContentGuardrail {
+    async fn check(
+        &self,
+        text: &str,
+        stage: &GuardStage,
+    ) -> Result<ModerationDecision, GatewayError> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .json(&CheckRequest { text, stage });
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        let response = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| GatewayError::CustomError(format!("Moderation request failed: {e}")))?;
+        response
+            .json()
+            .await
+            .map_err(|e| GatewayError::CustomError(format!("Invalid moderation response: {e}")))
+    }
+}
+
+/// Content guardrail applied by the executor to every chat completion
+#[derive(Clone)]
+pub struct Moderation {
+    guardrail: Arc<dyn ContentGuardrail>,
+    failure_mode: FailureMode,
+    stream_chunk_size: usize,
+}
+
+impl Moderation {
+    pub fn new(
+        guardrail: Arc<dyn ContentGuardrail>,
+        failure_mode: FailureMode,
+        stream_chunk_size: usize,
+    ) -> Self {
+        Self {
+            guardrail,
+            failure_mode,
+            stream_chunk_size: stream_chunk_size.max(1),
+        }
+    }
+
+    pub fn from_config(config: ModerationConfig) -> Self {
+        Self::new(
+            Arc::new(HttpContentGuardrail::new(config.url, config.headers)),
+            config.failure_mode,
+            config.stream_chunk_size,
+        )
+    }
+
+    /// Checks the text and records the decision. Blocked content is returned as an error.
+    async fn check(
+        &self,
+        text: &str,
+        stage: GuardStage,
+        callback_handler: &CallbackHandlerFn,
+        request_id: Option<&String>,
+    ) -> Result<ModerationDecision, GatewayApiError> {
+        let (decision, error) = match self.guardrail.check(text, &stage).await {
+            Ok(decision) => (decision, None),
+            Err(e) if self.failure_mode == FailureMode::Open => {
+                tracing::warn!("Content guardrail failed, passing content unchecked: {e}");
+                (ModerationDecision::Allow, Some(e.to_string()))
+            }
+            Err(e) => {
+                record_decision(
+                    callback_handler,
+                    request_id,
+                    &stage,
+                    None,
+                    Some(e.to_string()),
+                );
+                return Err(GatewayError::GuardError(GuardError::GuardEvaluationError(
+                    e.to_string(),
+                ))
+                .into());
+            }
+        };
+
+        record_decision(callback_handler, request_id, &stage, Some(&decision), error);
+        match decision {
+            ModerationDecision::Block { reason } => {
+                Err(GatewayError::GuardError(GuardError::ContentBlocked(reason)).into())
+            }
+            decision => Ok(decision),
+        }
+    }
+
+    /// Returns the request with redacted user messages, `None` when all were allowed
+    pub async fn moderate_input<T: Clone>(
+        &self,
+        request_with_tools: &ChatCompletionRequestWithTools<T>,
+        executor_context: &ExecutorContext,
+    ) -> Result<Option<ChatCompletionRequestWithTools<T>>, GatewayApiError> {
+        let mut request = request_with_tools.clone();
+        let mut redacted = false;
+        for message in request
+            .request
+            .messages
+            .iter_mut()
+            .filter(|m| m.role == "user")
+        {
+            redacted |= self
+                .moderate_message(message, GuardStage::Input, executor_context)
+                .await?;
+        }
+
+        Ok(redacted.then_some(request))
+    }
+
+    pub async fn moderate_response(
+        &self,
+        response: &mut ChatCompletionResponse,
+        executor_context: &ExecutorContext,
+    ) -> Result<(), GatewayApiError> {
+        for choice in &mut response.choices {
+            self.moderate_message(&mut choice.message, GuardStage::Output, executor_context)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether any text of the message was redacted
+    async fn moderate_message(
+        &self,
+        message: &mut ChatCompletionMessage,
+        stage: GuardStage,
+        executor_context: &ExecutorContext,
+    ) -> Result<bool, GatewayApiError> {
+        let mut redacted = false;
+        for text in message_texts(message) {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let decision = self
+                .check(
+                    text,
+                    stage.clone(),
+                    &executor_context.callbackhandler,
+                    executor_context.request_id.as_ref(),
+                )
+                .await?;
+            if let ModerationDecision::Redact { text: replacement } = decision {
+                *text = replacement;
+                redacted = true;
+            }
+        }
+        Ok(redacted)
+    }
+
+    /// Holds back streamed content until a window of `stream_chunk_size` chunks, or the
+    /// content before a tool call or the finish chunk, passed the guardrail
+    pub fn moderate_stream(
+        &self,
+        stream: ChatCompletionStream,
+        callback_handler: CallbackHandlerFn,
+        request_id: Option<String>,
+    ) -> ChatCompletionStream {
+        let state = StreamModeration {
+            moderation: self.clone(),
+            callback_handler,
+            request_id,
+            window: vec![],
+            pending: VecDeque::new(),
+            done: false,
+        };
+
+        wrap_stream(futures::stream::unfold(
+            (stream, state),
+            |(mut stream, mut state)| async move {
+                loop {
+                    if let Some(event) = state.pending.pop_front() {
+                        return Some((event, (stream, state)));
+                    }
+                    if state.done {
+                        return None;
+                    }
+
+                    match stream.next().await {
+                        Some(Ok((Some(delta), None, None))) if is_content_delta(&delta) => {
+                            state.window.push(delta);
+                            if state.window.len() >= state.moderation.stream_chunk_size {
+                                state.flush().await;
+                            }
+                        }
+                        Some(event) => {
+                            state.flush().await;
+                            if !state.done {
+                                state.pending.push_back(event);
+                            }
+                        }
+                        None => {
+                            state.flush().await;
+                            state.done = true;
+                        }
+                    }
+                }
+            },
+        ))
+    }
+}
+
+struct StreamModeration {
+    moderation: Moderation,
+    callback_handler: CallbackHandlerFn,
+    request_id: Option<String>,
+    window: Vec<ChatCompletionDelta>,
+    pending: VecDeque<Result<SSOChatEvent, GatewayApiError>>,
+    /// Set once the stream ended or was blocked
+    done: bool,
+}
+
+impl StreamModeration {
+    /// Checks the held back content and releases it, redacted, or ends the stream with an error
+    async fn flush(&mut self) {
+        if self.window.is_empty() {
+            return;
+        }
+
+        let window = std::mem::take(&mut self.window);
+        let text = window
+            .iter()
+            .filter_map(|d| d.content.as_deref())
+            .collect::<String>();
+        let decision = self
+            .moderation
+            .check(
+                &text,
+                GuardStage::Output,
+                &self.callback_handler,
+                self.request_id.as_ref(),
+            )
+            .await;
+
+        match decision {
+            Ok(ModerationDecision::Redact { text }) => {
+                let mut delta = window.into_iter().next().expect("window is not empty");
+                delta.content = Some(text);
+                delta.logprobs = None;
+                self.pending.push_back(Ok((Some(delta), None, None)));
+            }
+            Ok(_) => self
+                .pending
+                .extend(window.into_iter().map(|d| Ok((Some(d), None, None)))),
+            Err(e) => {
+                self.pending.push_back(Err(e));
+                self.done = true;
+            }
+        }
+    }
+}
+
+fn is_content_delta(delta: &ChatCompletionDelta) -> bool {
+    delta.content.is_some() && delta.tool_calls.is_none()
+}
+
+fn message_texts(message: &mut ChatCompletionMessage) -> Vec<&mut String> {
+    match &mut message.content {
+        Some(ChatCompletionContent::Text(text)) => vec![text],
+        Some(ChatCompletionContent::Content(parts)) => {
+            parts.iter_mut().filter_map(|p| p.text.as_mut()).collect()
+        }
+        None => vec![],
+    }
+}
+
+fn record_decision(
+    callback_handler: &CallbackHandlerFn,
+    request_id: Option<&String>,
+    stage: &GuardStage,
+    decision: Option<&ModerationDecision>,
+    error: Option<String>,
+) {
+    let action = match decision {
+        Some(ModerationDecision::Allow) => "allow",
+        Some(ModerationDecision::Block { .. }) => "block",
+        Some(ModerationDecision::Redact { .. }) => "redact",
+        None => "error",
+    };
+    let reason = match decision {
+        Some(ModerationDecision::Block { reason }) => Some(reason.as_str()),
+        _ => None,
+    };
+
+    callback_handler.on_custom(
+        &Span::current(),
+        "guardrail_decision",
+        serde_json::json!({
+            "stage": stage,
+            "action": action,
+            "reason": reason,
+            "error": error,
+        }),
+        request_id.cloned(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Blocks text containing "forbidden" and masks "secret"
+    struct KeywordGuardrail;
+
+    #[async_trait::async_trait]
+    impl ContentGuardrail for KeywordGuardrail {
+        async fn check(
+            &self,
+            text: &str,
+            _stage: &GuardStage,
+        ) -> Result<ModerationDecision, GatewayError> {
+            if text.contains("unavailable") {
+                Err(GatewayError::CustomError("service down".to_string()))
+            } else if text.contains("forbidden") {
+                Ok(ModerationDecision::Block {
+                    reason: "forbidden topic".to_string(),
+                })
+            } else if text.contains("secret") {
+                Ok(ModerationDecision::Redact {
+                    text: text.replace("secret", "******"),
+                })
+            } else {
+                Ok(ModerationDecision::Allow)
+            }
+        }
+    }
+
+    fn moderation(failure_mode: FailureMode) -> Moderation {
+        Moderation::new(Arc::new(KeywordGuardrail), failure_mode, 2)
+    }
+
+    fn content(text: &str) -> Result<SSOChatEvent, GatewayApiError> {
+        Ok((
+            Some(ChatCompletionDelta {
+                role: Some("assistant".to_string()),
+                content: Some(text.to_string()),
+                tool_calls: None,
+                logprobs: None,
+            }),
+            None,
+            None,
+        ))
+    }
+
+    fn finish() -> Result<SSOChatEvent, GatewayApiError> {
+        Ok((None, None, Some("stop".to_string())))
+    }
+
+    async fn moderate(
+        moderation: Moderation,
+        events: Vec<Result<SSOChatEvent, GatewayApiError>>,
+    ) -> Vec<Result<SSOChatEvent, GatewayApiError>> {
+        moderation
+            .moderate_stream(
+                wrap_stream(futures::stream::iter(events)),
+                CallbackHandlerFn::default(),
+                None,
+            )
+            .collect()
+            .await
+    }
+
+    fn contents(events: &[Result<SSOChatEvent, GatewayApiError>]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|e| e.as_ref().ok())
+            .filter_map(|(delta, _, _)| delta.as_ref().and_then(|d| d.content.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stream_windows() {
+        let events = moderate(
+            moderation(FailureMode::Open),
+            vec![
+                content("Hello "),
+                content("there, "),
+                content("the sec"),
+                content("ret is out"),
+                content("!"),
+                finish(),
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            contents(&events),
+            vec!["Hello ", "there, ", "the ****** is out", "!"]
+        );
+        assert!(matches!(events.last(), Some(Ok((None, None, Some(_))))));
+    }
+
+    #[tokio::test]
+    async fn test_stream_blocked() {
+        let events = moderate(
+            moderation(FailureMode::Open),
+            vec![
+                content("Fine "),
+                content("text. "),
+                content("Now a forbidden"),
+                content(" topic"),
+                finish(),
+            ],
+        )
+        .await;
+
+        assert_eq!(contents(&events), vec!["Fine ", "text. "]);
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            events.last(),
+            Some(Err(GatewayApiError::GatewayError(
+                GatewayError::GuardError(GuardError::ContentBlocked(_))
+            )))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failure_modes() {
+        let events = vec![content("service unavailable"), finish()];
+
+        let open = moderate(moderation(FailureMode::Open), events).await;
+        assert_eq!(contents(&open), vec!["service unavailable"]);
+
+        let events = vec![content("service unavailable"), finish()];
+        let closed = moderate(moderation(FailureMode::Closed), events).await;
+        assert_eq!(closed.len(), 1);
+        assert!(closed[0].is_err());
+    }
+
+    #[test]
+    fn test_decision_format() {
+        let decision: ModerationDecision =
+            serde_json::from_value(serde_json::json!({"action": "block", "reason": "violence"}))
+                .unwrap();
+        assert_eq!(
+            decision,
+            ModerationDecision::Block {
+                reason: "violence".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use crate::types::gateway::{ChatCompletionMessage, ChatCompletionRequest};
+use crate::types::provider::InferenceModelProvider;
+
+/// Penalties above this magnitude get a stronger instruction
+const STRONG_PENALTY: f32 = 1.0;
+
+/// Providers that accept `frequency_penalty` and `presence_penalty` natively
+pub fn supports_penalties(provider: &InferenceModelProvider) -> bool {
+    match provider {
+        InferenceModelProvider::OpenAI
+        | InferenceModelProvider::Proxy(_)
+        | InferenceModelProvider::Gemini => true,
+        InferenceModelProvider::Anthropic | InferenceModelProvider::Bedrock => false,
+    }
+}
+
+/// Approximates the requested penalties with a system instruction
+pub fn penalty_instruction(request: &ChatCompletionRequest) -> Option<String> {
+    let mut instructions = vec![];
+
+    match request.frequency_penalty {
+        Some(p) if p >= STRONG_PENALTY => instructions
+            .push("Never repeat words or phrases you have already used, rephrase instead."),
+        Some(p) if p > 0.0 => instructions.push("Avoid repeating the same words and phrases."),
+        Some(p) if p < 0.0 => instructions.push("Repeating words and phrases is acceptable."),
+        _ => {}
+    }
+
+    match request.presence_penalty {
+        Some(p) if p >= STRONG_PENALTY => instructions.push(
+            "Always move on to new topics and ideas instead of revisiting ones already mentioned.",
+        ),
+        Some(p) if p > 0.0 => instructions
+            .push("Prefer introducing new topics and ideas over revisiting earlier ones."),
+        Some(p) if p < 0.0 => instructions.push("Stay focused on the topics already mentioned."),
+        _ => {}
+    }
+
+    if instructions.is_empty() {
+        None
+    } else {
+        Some(instructions.join(" "))
+    }
+}
+
+/// Prepends the penalty instruction for providers without native penalties.
+/// Returns the instruction when the request was changed.
+pub fn emulate_penalties(
+    request: &mut ChatCompletionRequest,
+    provider: &InferenceModelProvider,
+) -> Option<String> {
+    if supports_penalties(provider) {
+        return None;
+    }
+
+    let instruction = penalty_instruction(request)?;
+    request.messages.insert(
+        0,
+        ChatCompletionMessage::new_text("system".to_string(), instruction.clone()),
+    );
+    Some(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(
+        frequency_penalty: Option<f32>,
+        presence_penalty: Option<f32>,
+    ) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            frequency_penalty,
+            presence_penalty,
+            messages: vec![ChatCompletionMessage::new_text(
+                "user".to_string(),
+                "Write a poem".to_string(),
+            )],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_native_providers_pass_through() {
+        let mut r = request(Some(1.5), Some(0.5));
+        assert_eq!(
+            emulate_penalties(&mut r, &InferenceModelProvider::OpenAI),
+            None
+        );
+        assert_eq!(r.messages.len(), 1);
+    }
+
+    #[test]
+    fn test_emulated_with_instruction() {
+        let mut r = request(Some(1.5), Some(0.5));
+        let instruction = emulate_penalties(&mut r, &InferenceModelProvider::Anthropic).unwrap();
+        assert!(instruction.starts_with("Never repeat"));
+        assert_eq!(r.messages.len(), 2);
+        assert_eq!(r.messages[0].role, "system");
+    }
+
+    #[test]
+    fn test_zero_penalties_are_ignored() {
+        let mut r = request(Some(0.0), None);
+        assert_eq!(
+            emulate_penalties(&mut r, &InferenceModelProvider::Bedrock),
+            None
+        );
+        assert_eq!(r.messages.len(), 1);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::gateway::{ChatCompletionContent, ChatCompletionMessage, Content, ContentType};
+
+/// Message rewrites applied before a request is sent to models with provider quirks
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuirksConfig {
+    #[serde(default)]
+    pub profiles: HashMap<String, QuirkProfile>,
+    /// Profile name by model, matched against the full model name first
+    #[serde(default)]
+    pub models: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuirkProfile {
+    /// Moves system messages into the first user message
+    #[serde(default)]
+    pub merge_system_message: bool,
+    /// Merges consecutive user or assistant messages
+    #[serde(default)]
+    pub alternate_roles: bool,
+    /// Removes messages without content or tool calls
+    #[serde(default)]
+    pub drop_empty_messages: bool,
+}
+
+impl QuirksConfig {
+    pub fn profile_for(&self, model: &str, inference_model: &str) -> Option<(&str, &QuirkProfile)> {
+        let name = self
+            .models
+            .get(model)
+            .or_else(|| self.models.get(inference_model))?;
+        self.profiles
+            .get(name)
+            .map(|profile| (name.as_str(), profile))
+    }
+}
+
+impl QuirkProfile {
+    /// Rewrites the messages in place and returns the quirks that changed them
+    pub fn apply(&self, messages: &mut Vec<ChatCompletionMessage>) -> Vec<&'static str> {
+        let mut applied = vec![];
+        if self.drop_empty_messages && drop_empty_messages(messages) {
+            applied.push("drop_empty_messages");
+        }
+        if self.merge_system_message && merge_system_message(messages) {
+            applied.push("merge_system_message");
+        }
+        if self.alternate_roles && alternate_roles(messages) {
+            applied.push("alternate_roles");
+        }
+        applied
+    }
+}
+
+fn is_empty(message: &ChatCompletionMessage) -> bool {
+    let has_tool_calls = message.tool_calls.as_ref().is_some_and(|c| !c.is_empty());
+    let has_content = match &message.content {
+        Some(ChatCompletionContent::Text(text)) => !text.trim().is_empty(),
+        Some(ChatCompletionContent::Content(parts)) => !parts.is_empty(),
+        None => false,
+    };
+    // Tool results are kept, the call they answer would be left dangling
+    message.role != "tool" && !has_tool_calls && !has_content
+}
+
+fn drop_empty_messages(messages: &mut Vec<ChatCompletionMessage>) -> bool {
+    let count = messages.len();
+    messages.retain(|m| !is_empty(m));
+    messages.len() != count
+}
+
+fn merge_system_message(messages: &mut Vec<ChatCompletionMessage>) -> bool {
+    let (system, mut rest): (Vec<_>, Vec<_>) = messages.drain(..).partition(|m| m.role == "system");
+    if system.is_empty() {
+        *messages = rest;
+        return false;
+    }
+
+    let instructions = system
+        .into_iter()
+        .fold(None, |acc, m| join_content(acc, m.content));
+    match rest.iter_mut().find(|m| m.role == "user") {
+        Some(user) => user.content = join_content(instructions, user.content.take()),
+        None => rest.insert(
+            0,
+            ChatCompletionMessage {
+                role: "user".to_string(),
+                content: instructions,
+                ..Default::default()
+            },
+        ),
+    }
+    *messages = rest;
+    true
+}
+
+fn alternate_roles(messages: &mut Vec<ChatCompletionMessage>) -> bool {
+    let count = messages.len();
+    let mut merged: Vec<ChatCompletionMessage> = Vec::with_capacity(count);
+    for message in messages.drain(..) {
+        match merged.last_mut() {
+            Some(previous) if can_merge(previous, &message) => {
+                previous.content = join_content(previous.content.take(), message.content);
+            }
+            _ => merged.push(message),
+        }
+    }
+    *messages = merged;
+    messages.len() != count
+}
+
+fn can_merge(previous: &ChatCompletionMessage, message: &ChatCompletionMessage) -> bool {
+    previous.role == message.role
+        && matches!(message.role.as_str(), "user" | "assistant")
+        && previous.tool_calls.is_none()
+        && message.tool_calls.is_none()
+}
+
+fn join_content(
+    first: Option<ChatCompletionContent>,
+    second: Option<ChatCompletionContent>,
+) -> Option<ChatCompletionContent> {
+    match (first, second) {
+        (None, content) | (content, None) => content,
+        (Some(ChatCompletionContent::Text(a)), Some(ChatCompletionContent::Text(b))) => {
+            Some(ChatCompletionContent::Text(format!("{a}\n\n{b}")))
+        }
+        (Some(a), Some(b)) => {
+            let mut parts = content_parts(a);
+            parts.extend(content_parts(b));
+            Some(ChatCompletionContent::Content(parts))
+        }
+    }
+}
+
+fn content_parts(content: ChatCompletionContent) -> Vec<Content> {
+    match content {
+        ChatCompletionContent::Text(text) => vec![Content {
+            r#type: ContentType::Text,
+            text: Some(text),
+            ..Default::default()
+        }],
+        ChatCompletionContent::Content(parts) => parts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ChatCompletionMessage {
+        ChatCompletionMessage::new_text(role.to_string(), content.to_string())
+    }
+
+    fn text(message: &ChatCompletionMessage) -> Option<String> {
+        message.content.as_ref().and_then(|c| c.as_string())
+    }
+
+    #[test]
+    fn test_merge_system_message() {
+        let mut messages = vec![
+            message("system", "Be brief"),
+            message("user", "Hi"),
+            message("assistant", "Hello"),
+        ];
+        let profile = QuirkProfile {
+            merge_system_message: true,
+            ..Default::default()
+        };
+
+        assert_eq!(profile.apply(&mut messages), vec!["merge_system_message"]);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, "user");
+        assert_eq!(text(&messages[0]).as_deref(), Some("Be brief\n\nHi"));
+    }
+
+    #[test]
+    fn test_merge_system_message_without_user() {
+        let mut messages = vec![message("system", "Be brief")];
+        merge_system_message(&mut messages);
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].role, "user");
+    }
+
+    #[test]
+    fn test_alternate_roles() {
+        let mut messages = vec![
+            message("user", "First"),
+            message("user", "Second"),
+            message("assistant", "Answer"),
+            message("user", "Third"),
+        ];
+        let profile = QuirkProfile {
+            alternate_roles: true,
+            ..Default::default()
+        };
+
+        assert_eq!(profile.apply(&mut messages), vec!["alternate_roles"]);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(text(&messages[0]).as_deref(), Some("First\n\nSecond"));
+    }
+
+    #[test]
+    fn test_drop_empty_messages_keeps_tool_results() {
+        let mut messages = vec![
+            message("user", "Hi"),
+            message("assistant", "  "),
+            message("tool", ""),
+            ChatCompletionMessage {
+                role: "assistant".to_string(),
+                content: None,
+                ..Default::default()
+            },
+        ];
+        let profile = QuirkProfile {
+            drop_empty_messages: true,
+            ..Default::default()
+        };
+
+        assert_eq!(profile.apply(&mut messages), vec!["drop_empty_messages"]);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].role, "tool");
+    }
+
+    #[test]
+    fn test_join_mixed_content() {
+        let parts = ChatCompletionContent::Content(vec![Content {
+            r#type: ContentType::ImageUrl,
+            ..Default::default()
+        }]);
+        let joined = join_content(
+            Some(ChatCompletionContent::Text("Describe".to_string())),
+            Some(parts),
+        );
+
+        let Some(ChatCompletionContent::Content(parts)) = joined else {
+            panic!("Expected content parts");
+        };
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].text.as_deref(), Some("Describe"));
+    }
+
+    #[test]
+    fn test_profile_for_model() {
+        let config = QuirksConfig {
+            profiles: HashMap::from([("strict".to_string(), QuirkProfile::default())]),
+            models: HashMap::from([("mistral-large".to_string(), "strict".to_string())]),
+        };
+
+        assert!(config
+            .profile_for("mistral/mistral-large", "mistral-large")
+            .is_some());
+        assert!(config.profile_for("openai/gpt-4o", "gpt-4o").is_none());
+    }
+}
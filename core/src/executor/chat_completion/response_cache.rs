@@ -0,0 +1,451 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::Span;
+
+use crate::executor::chat_completion::basic_executor::BasicCacheContext;
+use crate::executor::chat_completion::stream_executor::StreamCacheContext;
+use crate::executor::context::ExecutorContext;
+use crate::executor::ttl_cache::TtlCache;
+use crate::model::types::{LLMContentEvent, ModelEvent, ModelEventType};
+use crate::types::cache::{CacheFlushFilter, FlushableCache, ResponseCacheAdapter};
+use crate::types::gateway::{
+    ChatCompletionMessage, ChatCompletionRequest, ChatCompletionRequestWithTools, Extra,
+};
+
+/// Server side settings of the response cache. Requests opt in with `extra.cache`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseCacheConfig {
+    /// Lifetime of entries when the request sets no `expiration_time`
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Entries kept in memory, expired and then oldest entries are evicted first
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+}
+
+fn default_ttl_secs() -> u64 {
+    60 * 60
+}
+
+fn default_max_entries() -> usize {
+    10_000
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: default_ttl_secs(),
+            max_entries: default_max_entries(),
+        }
+    }
+}
+
+/// Completion stored in the cache with the events needed to replay it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedCompletion {
+    pub model: String,
+    pub events: Vec<ModelEvent>,
+    pub response: ChatCompletionMessage,
+}
+
+impl CachedCompletion {
+    /// Builds the completion from the events of a finished stream
+    fn from_stream(model: String, events: Vec<ModelEvent>) -> Option<Self> {
+        let mut content = String::new();
+        let mut finished = false;
+        for event in &events {
+            match &event.event {
+                ModelEventType::LlmContent(e) => content.push_str(&e.content),
+                ModelEventType::LlmStop(_) => finished = true,
+                _ => {}
+            }
+        }
+
+        finished.then(|| Self {
+            model,
+            events,
+            response: ChatCompletionMessage::new_text("assistant".to_string(), content),
+        })
+    }
+
+    /// Events replayed to a streaming request. Completions cached from non streaming
+    /// requests carry no content events, so the response is split into synthetic deltas.
+    fn stream_events(&self) -> Vec<ModelEvent> {
+        let has_content = self
+            .events
+            .iter()
+            .any(|e| matches!(e.event, ModelEventType::LlmContent(_)));
+        let content = self.response.content.as_ref().and_then(|c| c.as_string());
+        let Some(content) = content.filter(|_| !has_content) else {
+            return self.events.clone();
+        };
+
+        let span = Span::current();
+        let deltas = content.split_inclusive(char::is_whitespace).map(|delta| {
+            ModelEvent::new(
+                &span,
+                ModelEventType::LlmContent(LLMContentEvent {
+                    content: delta.to_string(),
+                    logprobs: None,
+                }),
+            )
+        });
+
+        let stop = self
+            .events
+            .iter()
+            .rposition(|e| matches!(e.event, ModelEventType::LlmStop(_)))
+            .unwrap_or(self.events.len());
+        let mut events = self.events[..stop].to_vec();
+        events.extend(deltas);
+        events.extend_from_slice(&self.events[stop..]);
+        events
+    }
+}
+
+/// Storage backend of cached completions
+#[async_trait]
+pub trait ResponseCache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<CachedCompletion>;
+
+    /// Stores the completion, the backend default lifetime applies when `ttl` is empty
+    async fn set(&self, key: String, completion: CachedCompletion, ttl: Option<Duration>);
+}
+
+/// Default backend keeping completions in process memory
+pub struct InMemoryResponseCache {
+    config: ResponseCacheConfig,
+    entries: TtlCache<CachedCompletion>,
+}
+
+impl InMemoryResponseCache {
+    pub fn new(config: ResponseCacheConfig) -> Self {
+        Self {
+            entries: TtlCache::new(config.max_entries),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl ResponseCache for InMemoryResponseCache {
+    async fn get(&self, key: &str) -> Option<CachedCompletion> {
+        self.entries.get(key)
+    }
+
+    async fn set(&self, key: String, completion: CachedCompletion, ttl: Option<Duration>) {
+        let ttl = ttl.unwrap_or(Duration::from_secs(self.config.ttl_secs));
+        self.entries.insert(key, completion, ttl);
+    }
+}
+
+impl FlushableCache for InMemoryResponseCache {
+    fn kind(&self) -> &str {
+        "response"
+    }
+
+    fn flush(&self, filter: &CacheFlushFilter) -> usize {
+        self.entries
+            .retain(|key, completion| !filter.matches(key, &completion.model, &[]))
+    }
+}
+
+/// Stable key of a deterministic request, None when the response may differ between calls:
+/// requests with tools, several choices or a temperature other than 0 are never cached.
+pub fn cache_key<T: Serialize>(
+    request_with_tools: &ChatCompletionRequestWithTools<T>,
+) -> Option<String> {
+    let request = &request_with_tools.request;
+    let has_tools = request.tools.as_ref().is_some_and(|t| !t.is_empty())
+        || request.functions.as_ref().is_some_and(|f| !f.is_empty());
+    // Completions are stored with a single choice
+    let choices = request.n.is_some_and(|n| n > 1);
+    if has_tools || choices || request.temperature != Some(0.0) {
+        return None;
+    }
+
+    // Fields that do not change the completion are left out, so streaming and non
+    // streaming requests share entries
+    let normalized = ChatCompletionRequest {
+        stream: None,
+        stream_options: None,
+        user: None,
+        max_tokens: request.output_tokens_limit(),
+        max_completion_tokens: None,
+        ..request.clone()
+    };
+    // Guardrails, variables, transforms and routing change the completion as well
+    let extra = request_with_tools.extra.as_ref().map(|extra| Extra {
+        user: None,
+        cache: None,
+        ..extra.clone()
+    });
+    // Objects serialize with sorted keys, which keeps the hash stable
+    let value = serde_json::to_value((
+        &normalized,
+        &extra,
+        &request_with_tools.router,
+        &request_with_tools.fallbacks,
+        &request_with_tools.provider_specific,
+    ))
+    .ok()?;
+    let digest = Sha256::digest(value.to_string().as_bytes());
+
+    Some(format!("chat:{}:{digest:x}", request.model))
+}
+
+/// Fills the cache contexts of a cacheable request: a hit replays the stored completion,
+/// a miss stores the completion once the model finishes. Contexts already set by the
+/// caller are kept.
+pub async fn attach_response_cache<T: Serialize>(
+    request_with_tools: &ChatCompletionRequestWithTools<T>,
+    executor_context: &ExecutorContext,
+    stream_cache_context: &mut StreamCacheContext,
+    basic_cache_context: &mut BasicCacheContext,
+) {
+    let Some(cache) = &executor_context.response_cache else {
+        return;
+    };
+    let Some(extra) = &request_with_tools.extra else {
+        return;
+    };
+    let Some(options) = &extra.cache else {
+        return;
+    };
+    // Similarity lookups need a semantic cache, exact hashes can not serve them. MCP
+    // servers and delegates add tools to the request.
+    if !matches!(options.adapter, ResponseCacheAdapter::Exact)
+        || request_with_tools.mcp_servers.is_some()
+        || !extra.delegates.is_empty()
+        || stream_cache_context.cached_events.is_some()
+        || stream_cache_context.events_sender.is_some()
+        || basic_cache_context.cached_events.is_some()
+        || basic_cache_context.events_sender.is_some()
+    {
+        return;
+    }
+    let Some(key) = cache_key(request_with_tools) else {
+        return;
+    };
+
+    let stream = request_with_tools.request.stream.unwrap_or(false);
+    if let Some(completion) = cache.get(&key).await {
+        replay_completion(
+            completion,
+            stream,
+            stream_cache_context,
+            basic_cache_context,
+        );
+        return;
+    }
+
+    let cache = cache.clone();
+    let ttl = options
+        .expiration_time
+        .map(|secs| Duration::from_secs(secs as u64));
+    let completion = record_completion(
+        request_with_tools.request.model.clone(),
+        stream,
+        stream_cache_context,
+        basic_cache_context,
+    );
+    tokio::spawn(async move {
+        if let Ok(completion) = completion.await {
+            cache.set(key, completion, ttl).await;
+        }
+    });
+}
+
+/// Makes the executor answer with the stored completion instead of calling the model
+pub fn replay_completion(
+    completion: CachedCompletion,
+    stream: bool,
+    stream_cache_context: &mut StreamCacheContext,
+    basic_cache_context: &mut BasicCacheContext,
+) {
+    if stream {
+        stream_cache_context.cached_events = Some(completion.stream_events());
+    } else {
+        basic_cache_context.cached_events = Some(completion.events);
+        basic_cache_context.cached_response = Some(completion.response);
+    }
+}
+
+/// Collects the completion of the executed model. The receiver errors when the request
+/// fails or the stream does not finish.
+pub fn record_completion(
+    model: String,
+    stream: bool,
+    stream_cache_context: &mut StreamCacheContext,
+    basic_cache_context: &mut BasicCacheContext,
+) -> tokio::sync::oneshot::Receiver<CachedCompletion> {
+    let (completion_tx, completion_rx) = tokio::sync::oneshot::channel();
+    let (events_tx, events_rx) = tokio::sync::mpsc::channel(1000);
+    if stream {
+        stream_cache_context.events_sender = Some(events_tx);
+        tokio::spawn(async move {
+            let events = collect_events(events_rx).await;
+            if let Some(completion) = CachedCompletion::from_stream(model, events) {
+                let _ = completion_tx.send(completion);
+            }
+        });
+    } else {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        basic_cache_context.events_sender = Some(events_tx);
+        basic_cache_context.response_sender = Some(response_tx);
+        tokio::spawn(async move {
+            let events = collect_events(events_rx).await;
+            // The sender is dropped without a response when the request fails
+            if let Ok(response) = response_rx.await {
+                let _ = completion_tx.send(CachedCompletion {
+                    model,
+                    events,
+                    response,
+                });
+            }
+        });
+    }
+    completion_rx
+}
+
+/// Collects model events until the executor drops the sender. Custom events describe the
+/// original call and are not replayed.
+async fn collect_events(
+    mut rx: tokio::sync::mpsc::Receiver<Option<ModelEvent>>,
+) -> Vec<ModelEvent> {
+    let mut events = vec![];
+    while let Some(event) = rx.recv().await {
+        events.extend(event.filter(|e| !matches!(e.event, ModelEventType::Custom(_))));
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::types::gateway::{ChatCompletionTool, GuardOrName};
+
+    fn request(temperature: Option<f32>) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "openai/gpt-4o-mini".to_string(),
+            messages: vec![ChatCompletionMessage::new_text(
+                "user".to_string(),
+                "Hello".to_string(),
+            )],
+            temperature,
+            ..Default::default()
+        }
+    }
+
+    fn completion(content: &str) -> CachedCompletion {
+        CachedCompletion {
+            model: "openai/gpt-4o-mini".to_string(),
+            events: vec![],
+            response: ChatCompletionMessage::new_text("assistant".to_string(), content.to_string()),
+        }
+    }
+
+    fn with_tools(request: ChatCompletionRequest) -> ChatCompletionRequestWithTools<()> {
+        ChatCompletionRequestWithTools {
+            request,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let key = cache_key(&with_tools(request(Some(0.0)))).unwrap();
+        assert!(key.starts_with("chat:openai/gpt-4o-mini:"));
+
+        let streaming = ChatCompletionRequest {
+            stream: Some(true),
+            user: Some("user-1".to_string()),
+            ..request(Some(0.0))
+        };
+        assert_eq!(cache_key(&with_tools(streaming)), Some(key.clone()));
+
+        let longer = ChatCompletionRequest {
+            max_tokens: Some(100),
+            ..request(Some(0.0))
+        };
+        assert_ne!(cache_key(&with_tools(longer)), Some(key.clone()));
+
+        let mut guarded = with_tools(request(Some(0.0)));
+        guarded.extra = Some(Extra {
+            guards: vec![GuardOrName::GuardId("pii".to_string())],
+            ..Default::default()
+        });
+        assert_ne!(cache_key(&guarded), Some(key.clone()));
+
+        let mut with_variables = with_tools(request(Some(0.0)));
+        with_variables.extra = Some(Extra {
+            variables: Some(HashMap::from([(
+                "name".to_string(),
+                serde_json::json!("Ada"),
+            )])),
+            ..Default::default()
+        });
+        assert_ne!(cache_key(&with_variables), Some(key));
+    }
+
+    #[test]
+    fn test_cache_key_bypass() {
+        assert_eq!(cache_key(&with_tools(request(None))), None);
+        assert_eq!(cache_key(&with_tools(request(Some(0.7)))), None);
+
+        let with_tool_calls = ChatCompletionRequest {
+            tools: Some(vec![serde_json::from_value::<ChatCompletionTool>(
+                serde_json::json!({"type": "function", "function": {"name": "lookup"}}),
+            )
+            .unwrap()]),
+            ..request(Some(0.0))
+        };
+        assert_eq!(cache_key(&with_tools(with_tool_calls)), None);
+
+        let choices = ChatCompletionRequest {
+            n: Some(2),
+            ..request(Some(0.0))
+        };
+        assert_eq!(cache_key(&with_tools(choices)), None);
+    }
+
+    #[test]
+    fn test_stream_events_from_response() {
+        let events = completion("Hello there friend").stream_events();
+        let deltas: Vec<_> = events
+            .iter()
+            .filter_map(|e| match &e.event {
+                ModelEventType::LlmContent(c) => Some(c.content.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(deltas, vec!["Hello ", "there ", "friend"]);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_cache() {
+        let cache = InMemoryResponseCache::new(ResponseCacheConfig {
+            ttl_secs: 60,
+            max_entries: 2,
+        });
+        cache.set("a".to_string(), completion("a"), None).await;
+        cache.set("b".to_string(), completion("b"), None).await;
+        cache.set("c".to_string(), completion("c"), None).await;
+        assert!(cache.get("a").await.is_none());
+        assert!(cache.get("c").await.is_some());
+
+        cache
+            .set("d".to_string(), completion("d"), Some(Duration::ZERO))
+            .await;
+        assert!(cache.get("d").await.is_none());
+
+        let flushed = cache.flush(&CacheFlushFilter::default());
+        assert_eq!(flushed, 1);
+        assert!(cache.get("c").await.is_none());
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+use tracing::Span;
+
+use crate::error::{GatewayError, InvalidRequestError};
+use crate::executor::chat_completion::summarization::{estimate_tokens, message_text};
+use crate::executor::context::ExecutorContext;
+use crate::types::gateway::{ChatCompletionMessage, ChatCompletionRequestWithTools, Citation};
+use crate::GatewayApiError;
+
+const CONTEXT_INSTRUCTION: &str =
+    "Use the following context to answer. Cite it by number when you rely on it.";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RetrievedChunk {
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+}
+
+/// Source of the context injected for requests with `extra.retrieval`
+#[async_trait::async_trait]
+pub trait Retriever: Send + Sync {
+    async fn retrieve(
+        &self,
+        query: &str,
+        top_k: usize,
+    ) -> Result<Vec<RetrievedChunk>, GatewayError>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrieverConfig {
+    /// Endpoint receiving `{"query", "top_k"}` and answering `{"chunks": [...]}`
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct RetrieveRequest<'a> {
+    query: &'a str,
+    top_k: usize,
+}
+
+#[derive(Deserialize)]
+struct RetrieveResponse {
+    chunks: Vec<RetrievedChunk>,
+}
+
+/// Retriever backed by an external HTTP service, e.g. a vector store
+pub struct HttpRetriever {
+    config: RetrieverConfig,
+    client: reqwest::Client,
+}
+
+impl HttpRetriever {
+    pub fn new(config: RetrieverConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Retriever for HttpRetriever {
+    async fn retrieve(
+        &self,
+        query: &str,
+        top_k: usize,
+    ) -> Result<Vec<RetrievedChunk>, GatewayError> {
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .json(&RetrieveRequest { query, top_k });
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+
+        let response = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| GatewayError::CustomError(format!("Retriever request failed: {e}")))?;
+        let response: RetrieveResponse = response
+            .json()
+            .await
+            .map_err(|e| GatewayError::CustomError(format!("Invalid retriever response: {e}")))?;
+
+        Ok(response.chunks.into_iter().take(top_k).collect())
+    }
+}
+
+/// Text of the last user message
+pub fn retrieval_query(messages: &[ChatCompletionMessage]) -> Option<String> {
+    messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "user")
+        .map(message_text)
+        .filter(|q| !q.trim().is_empty())
+}
+
+/// Inserts the numbered chunks as a system message right before the last user message
+pub fn inject_context(messages: &mut Vec<ChatCompletionMessage>, chunks: &[RetrievedChunk]) {
+    let context = chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| match &chunk.source {
+            Some(source) => format!("[{}] ({source}) {}", i + 1, chunk.content),
+            None => format!("[{}] {}", i + 1, chunk.content),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let position = messages
+        .iter()
+        .rposition(|m| m.role == "user")
+        .unwrap_or(messages.len());
+    messages.insert(
+        position,
+        ChatCompletionMessage::new_text(
+            "system".to_string(),
+            format!("{CONTEXT_INSTRUCTION}\n\n{context}"),
+        ),
+    );
+}
+
+pub fn citations(chunks: &[RetrievedChunk]) -> Vec<Citation> {
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| Citation {
+            index: i + 1,
+            source: chunk.source.clone(),
+            content: chunk.content.clone(),
+        })
+        .collect()
+}
+
+/// Returns the request with the retrieved context and the citations to attach to the response
+pub async fn retrieve_context<T: Clone + Debug>(
+    request_with_tools: &ChatCompletionRequestWithTools<T>,
+    executor_context: &ExecutorContext,
+) -> Result<Option<(ChatCompletionRequestWithTools<T>, Option<Vec<Citation>>)>, GatewayApiError> {
+    let Some(options) = request_with_tools
+        .extra
+        .as_ref()
+        .and_then(|e| e.retrieval.as_ref())
+    else {
+        return Ok(None);
+    };
+    let Some(retriever) = &executor_context.retriever else {
+        return Err(InvalidRequestError::new(
+            "extra.retrieval",
+            "unsupported_value",
+            "Retrieval is not configured on this gateway",
+        )
+        .into());
+    };
+    let Some(query) = retrieval_query(&request_with_tools.request.messages) else {
+        return Ok(None);
+    };
+
+    let chunks = retriever.retrieve(&query, options.top_k).await?;
+    if chunks.is_empty() {
+        return Ok(None);
+    }
+
+    let mut request = request_with_tools.clone();
+    inject_context(&mut request.request.messages, &chunks);
+    // Injected context is billed as prompt tokens by the provider, report the estimated share
+    let context_tokens = estimate_tokens(&request.request.messages)
+        - estimate_tokens(&request_with_tools.request.messages);
+
+    executor_context.emit_custom(
+        &Span::current(),
+        "retrieval",
+        serde_json::json!({
+            "query": query,
+            "chunks": chunks,
+            "context_tokens": context_tokens,
+        }),
+    );
+
+    let citations = options.citations.then(|| citations(&chunks));
+    Ok(Some((request, citations)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(content: &str, source: Option<&str>) -> RetrievedChunk {
+        RetrievedChunk {
+            content: content.to_string(),
+            source: source.map(String::from),
+            score: None,
+        }
+    }
+
+    #[test]
+    fn test_inject_context_before_last_user_message() {
+        let mut messages = vec![
+            ChatCompletionMessage::new_text("system".to_string(), "Be brief".to_string()),
+            ChatCompletionMessage::new_text(
+                "user".to_string(),
+                "What is the refund policy?".into(),
+            ),
+        ];
+        assert_eq!(
+            retrieval_query(&messages).as_deref(),
+            Some("What is the refund policy?")
+        );
+
+        inject_context(
+            &mut messages,
+            &[
+                chunk("Refunds within 30 days", Some("policy.md")),
+                chunk("Contact support", None),
+            ],
+        );
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1].role, "system");
+        let context = message_text(&messages[1]);
+        assert!(context.contains("[1] (policy.md) Refunds within 30 days"));
+        assert!(context.contains("[2] Contact support"));
+        assert_eq!(messages[2].role, "user");
+    }
+
+    #[test]
+    fn test_citations_are_numbered() {
+        let citations = citations(&[chunk("a", Some("doc-a")), chunk("b", None)]);
+        assert_eq!(citations[1].index, 2);
+        assert_eq!(citations[0].source.as_deref(), Some("doc-a"));
+    }
+}
@@ -1,23 +1,46 @@
+use crate::error::InvalidRequestError;
 use crate::executor::chat_completion::basic_executor::BasicCacheContext;
 use crate::executor::context::ExecutorContext;
-use crate::handler::chat::map_sso_event;
+use crate::handler::chat::{map_sso_event, SSOChatEvent};
 use crate::routing::metrics::InMemoryMetricsRepository;
 use crate::routing::RoutingStrategy;
 use crate::usage::InMemoryStorage;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::executor::chat_completion::choices::{merge_choices, native_choices, requested_choices};
+use crate::executor::chat_completion::continuation::{
+    append_continuation, continuation_request, output_text,
+};
 use crate::executor::chat_completion::execute;
+use crate::executor::chat_completion::idempotency::{attach_idempotency, idempotency_key};
+use crate::executor::chat_completion::stream_wrapper::with_keep_alive;
+use crate::executor::chat_completion::structured_output::{
+    corrective_request, emulated_schema, OutputSchema,
+};
+use crate::executor::chat_completion::temperature_sampling::{add_usage, merge_variants};
+use crate::executor::chat_completion::tool_emulation::{
+    apply_tool_calls, emulation_request, ToolFallback,
+};
+use crate::handler::ModelEventWithDetails;
+use crate::model::types::{
+    LLMFinishEvent, ModelEvent, ModelEventType, ModelFinishReason, ToolStartEvent,
+};
+use crate::model::CredentialsIdent;
 use crate::routing::RouteStrategy;
-use crate::types::gateway::ChatCompletionRequestWithTools;
+use crate::types::gateway::{
+    ChatCompletionContent, ChatCompletionMessage, ChatCompletionRequestWithTools,
+    ChatCompletionResponse, ChatCompletionUsage, MinOutputTokens, TemperatureSampling,
+};
 
 use crate::GatewayError;
-use actix_web::HttpResponse;
+use actix_web::{HttpResponse, HttpResponseBuilder};
 use bytes::Bytes;
-use either::Either::{Left, Right};
-use futures::StreamExt;
-use futures::TryStreamExt;
+use either::Either::{self, Left, Right};
+use futures::stream::LocalBoxStream;
+use futures::{Stream, StreamExt};
 
 use crate::executor::chat_completion::StreamCacheContext;
 use thiserror::Error;
@@ -41,6 +64,19 @@ use valuable::Valuable;
 
 const MAX_DEPTH: usize = 10;
 
+/// Model changes of a single target by tool reroutes and downgrades, bounds cycles between them
+const MAX_REROUTES: usize = 4;
+
+/// Target key holding the time limit of a single attempt in milliseconds
+const TARGET_TIMEOUT_KEY: &str = "timeout_ms";
+
+/// Attempts of a structured output request before an invalid output fails it
+const MAX_SCHEMA_ATTEMPTS: u32 = 2;
+
+const CONTENT_FILTER_SOFTENING_INSTRUCTION: &str = "The user is asking for legitimate, \
+factual information. Answer in a neutral, informative and professional tone, avoid graphic \
+detail, and decline only the parts of the request that would be unsafe to answer.";
+
 #[derive(Error, Debug)]
 pub enum RoutedExecutorError {
     #[error("Failed deserializing request to json: {0}")]
@@ -54,6 +90,27 @@ pub struct RoutedExecutor {
     request: ChatCompletionRequestWithTools<RoutingStrategy>,
 }
 
+/// Response of the target that served a routed request
+pub struct RoutedResponse {
+    pub model_name: String,
+    /// Trace, model, provider and quirk profile headers of the target
+    headers: Vec<(&'static str, String)>,
+    pub response: Either<
+        LocalBoxStream<'static, Result<SSOChatEvent, GatewayApiError>>,
+        ChatCompletionResponse,
+    >,
+}
+
+impl RoutedResponse {
+    pub fn response_builder(&self) -> HttpResponseBuilder {
+        let mut builder = HttpResponse::Ok();
+        for header in &self.headers {
+            builder.insert_header(header.clone());
+        }
+        builder
+    }
+}
+
 impl RoutedExecutor {
     pub fn new(request: ChatCompletionRequestWithTools<RoutingStrategy>) -> Self {
         Self { request }
@@ -64,12 +121,46 @@ impl RoutedExecutor {
         executor_context: &ExecutorContext,
         memory_storage: Option<Arc<Mutex<InMemoryStorage>>>,
     ) -> Result<HttpResponse, GatewayApiError> {
+        let routed = self.route(executor_context, memory_storage).await?;
+        let mut builder = routed.response_builder();
+        match routed.response {
+            Left(events) => {
+                let model_name = routed.model_name;
+                let events = events.map(move |delta| map_sso_event(delta, model_name.clone()));
+                Ok(builder
+                    .content_type("text/event-stream")
+                    .streaming(event_stream(events, executor_context)))
+            }
+            Right(response) => Ok(builder.json(response)),
+        }
+    }
+
+    /// Executes the request on its targets and returns the response of the target that
+    /// served it, for the handler to render in its own response format
+    pub async fn route(
+        &self,
+        executor_context: &ExecutorContext,
+        memory_storage: Option<Arc<Mutex<InMemoryStorage>>>,
+    ) -> Result<RoutedResponse, GatewayApiError> {
         let span = Span::current();
 
-        let mut targets = vec![(self.request.clone(), None)];
+        // Targets and retries of the client request share its idempotency key
+        let executor_context = &ExecutorContext {
+            idempotency_key: idempotency_key(&self.request, executor_context)?,
+            ..executor_context.clone()
+        };
+
+        if let Some(retry_budget) = &executor_context.retry_budget {
+            retry_budget.record_request();
+        }
+
+        let mut targets = vec![(self.request.clone(), None, None)];
+        let mut deadline: Option<Instant> = executor_context.request_deadline;
 
         let mut depth = 0;
-        while let Some((mut request, target)) = targets.pop() {
+        // A content filter refusal is retried once per client request, whichever target it hit
+        let mut content_filter_retried = false;
+        while let Some((mut request, target, mut timeout)) = targets.pop() {
             depth += 1;
             if depth > MAX_DEPTH {
                 return Err(GatewayApiError::GatewayError(GatewayError::CustomError(
@@ -77,12 +168,20 @@ impl RoutedExecutor {
                 )));
             }
 
-            if let Some(t) = target {
+            if let Some(mut t) = target {
+                if let Some(ms) = t.remove(TARGET_TIMEOUT_KEY).and_then(|v| v.as_u64()) {
+                    timeout = Some(Duration::from_millis(ms));
+                }
                 request.router = None;
                 request = Self::merge_request_with_target(&request, &t)?;
             }
 
             if let Some(router) = &request.router {
+                if let Some(ms) = router.deadline_ms {
+                    let router_deadline = Instant::now() + Duration::from_millis(ms);
+                    deadline = Some(deadline.map_or(router_deadline, |d| d.min(router_deadline)));
+                }
+
                 let router_name = request
                     .request
                     .model
@@ -131,7 +230,7 @@ impl RoutedExecutor {
                 match executor_result {
                     Ok(executor_result) => {
                         for t in executor_result.iter().rev() {
-                            targets.push((request.clone(), Some(t.clone())));
+                            targets.push((request.clone(), Some(t.clone()), timeout));
                         }
                     }
                     Err(e) => {
@@ -139,12 +238,29 @@ impl RoutedExecutor {
                     }
                 }
             } else {
-                let result = Self::execute_request(&request, executor_context).await;
+                let result = match attempt_timeout(timeout, deadline, Instant::now()) {
+                    Some(limit) if limit.is_zero() => {
+                        return Err(GatewayApiError::Timeout(
+                            "Request deadline exceeded before trying all targets".to_string(),
+                        ));
+                    }
+                    Some(limit) => {
+                        Self::execute_request_with_timeout(&request, executor_context, limit).await
+                    }
+                    None => Self::execute_request(&request, executor_context, 0).await,
+                };
+                let result = match result {
+                    Err(e) if e.is_content_filter() && !content_filter_retried => {
+                        content_filter_retried = true;
+                        Self::retry_content_filter(&request, executor_context, e).await
+                    }
+                    result => result,
+                };
 
                 match result {
                     Ok(response) => return Ok(response),
                     Err(err) => {
-                        if targets.is_empty() {
+                        if targets.is_empty() || !executor_context.acquire_retry("fallback") {
                             return Err(err);
                         } else {
                             tracing::warn!(
@@ -160,10 +276,114 @@ impl RoutedExecutor {
         unreachable!()
     }
 
+    /// Executes a target within its time limit. Streaming requests are limited until the
+    /// first chunk arrives, as the response is returned from that point on.
+    async fn execute_request_with_timeout(
+        request: &ChatCompletionRequestWithTools<RoutingStrategy>,
+        executor_context: &ExecutorContext,
+        limit: Duration,
+    ) -> Result<RoutedResponse, GatewayApiError> {
+        let execution = Self::execute_request(request, executor_context, 0);
+        match tokio::time::timeout(limit, execution).await {
+            Ok(result) => result,
+            Err(_) => {
+                Self::timeout_stop(executor_context, &Span::current(), &request.request.model)();
+                executor_context.emit_custom(
+                    &Span::current(),
+                    "target_timeout",
+                    serde_json::json!({
+                        "model": request.request.model,
+                        "timeout_ms": limit.as_millis() as u64,
+                    }),
+                );
+
+                Err(GatewayApiError::Timeout(format!(
+                    "Request to {} timed out after {}ms",
+                    request.request.model,
+                    limit.as_millis()
+                )))
+            }
+        }
+    }
+
+    /// Returns a callback recording the model call as stopped by a timeout, so the aborted
+    /// call is kept in traces and usage like a finished one
+    fn timeout_stop(
+        executor_context: &ExecutorContext,
+        span: &Span,
+        model_name: &str,
+    ) -> impl FnOnce() + 'static {
+        let callbackhandler = executor_context.callbackhandler.clone();
+        let request_id = executor_context.request_id.clone();
+        let span = span.clone();
+        let provider_name = find_model_by_full_name(model_name, &executor_context.provided_models)
+            .map(|m| m.inference_provider.provider.to_string())
+            .unwrap_or_default();
+        let credentials_ident = match executor_context.key_credentials {
+            Some(_) => CredentialsIdent::Own,
+            None => CredentialsIdent::Langdb,
+        };
+        let model_name = model_name.to_string();
+
+        move || {
+            callbackhandler.on_message(ModelEventWithDetails::new(
+                ModelEvent::new(
+                    &span,
+                    ModelEventType::LlmStop(LLMFinishEvent {
+                        provider_name,
+                        model_name,
+                        output: None,
+                        usage: None,
+                        finish_reason: ModelFinishReason::Other("timeout".to_string()),
+                        tool_calls: vec![],
+                        credentials_ident,
+                        generation: None,
+                    }),
+                )
+                .with_request_id(request_id),
+                None,
+            ));
+        }
+    }
+
+    /// Retries a request refused by a content filter once, as configured in
+    /// `extra.content_filter_retry`. Requests without the option return the original error.
+    async fn retry_content_filter(
+        request: &ChatCompletionRequestWithTools<RoutingStrategy>,
+        executor_context: &ExecutorContext,
+        error: GatewayApiError,
+    ) -> Result<RoutedResponse, GatewayApiError> {
+        let Some((retry_request, strategy)) = content_filter_retry(request) else {
+            return Err(error);
+        };
+
+        if !executor_context.acquire_retry("content_filter_retry") {
+            return Err(error);
+        }
+
+        tracing::warn!(
+            "Request to {} was blocked by content filter, retrying with strategy {strategy}",
+            request.request.model
+        );
+        executor_context.emit_custom(
+            &Span::current(),
+            "content_filter_retry",
+            serde_json::json!({
+                "strategy": strategy,
+                "model": request.request.model,
+                "retry_model": retry_request.request.model,
+                "error": error.to_string(),
+            }),
+        );
+
+        Self::execute_request(&retry_request, executor_context, 0).await
+    }
+
     async fn execute_request(
         request: &ChatCompletionRequestWithTools<RoutingStrategy>,
         executor_context: &ExecutorContext,
-    ) -> Result<HttpResponse, GatewayApiError> {
+        reroutes: usize,
+    ) -> Result<RoutedResponse, GatewayApiError> {
         let span = tracing::Span::current();
         span.record("request", &serde_json::to_string(&request)?);
         let trace_id = span.context().span().span_context().trace_id();
@@ -172,61 +392,489 @@ impl RoutedExecutor {
 
         let llm_model =
             find_model_by_full_name(&request.request.model, &executor_context.provided_models)?;
-        let response = execute(
-            request,
-            executor_context,
-            span.clone(),
-            StreamCacheContext::default(),
-            BasicCacheContext::default(),
-        )
-        .instrument(span.clone())
-        .await?;
 
-        let mut response_builder = HttpResponse::Ok();
-        let builder = response_builder
-            .insert_header(("X-Trace-Id", trace_id_uuid(trace_id).to_string()))
-            .insert_header(("X-Model-Name", model_name.clone()))
-            .insert_header((
+        let tool_fallback = executor_context
+            .tool_support
+            .as_ref()
+            .and_then(|t| t.fallback_for(request, &llm_model));
+        if let Some(ToolFallback::Reroute { model }) = tool_fallback {
+            let reroutes = next_reroute(reroutes, &model_name)?;
+            Self::emit_tool_fallback(executor_context, &span, &model_name, Some(model));
+            let mut rerouted = request.clone();
+            rerouted.request.model = model.clone();
+            return Box::pin(Self::execute_request(&rerouted, executor_context, reroutes)).await;
+        }
+
+        if let Some((policy, input_tokens)) = executor_context
+            .downgrade
+            .as_ref()
+            .and_then(|d| d.policy_for(request))
+        {
+            let reroutes = next_reroute(reroutes, &model_name)?;
+            executor_context.emit_custom(
+                &span,
+                "model_downgrade",
+                serde_json::json!({
+                    "model": model_name,
+                    "target": policy.target,
+                    "estimated_input_tokens": input_tokens,
+                }),
+            );
+
+            let mut downgraded = request.clone();
+            downgraded.request.model = policy.target.clone();
+            downgraded
+                .extra
+                .get_or_insert_with(Default::default)
+                .disable_downgrade = true;
+            return Box::pin(Self::execute_request(
+                &downgraded,
+                executor_context,
+                reroutes,
+            ))
+            .await;
+        }
+
+        let model_permit = match &executor_context.model_limiter {
+            Some(limiter) => Some(
+                limiter
+                    .acquire(
+                        &llm_model,
+                        executor_context.cost_calculator.as_ref().as_ref(),
+                    )
+                    .await?,
+            ),
+            None => None,
+        };
+
+        let temperature_sampling = request
+            .extra
+            .as_ref()
+            .and_then(|e| e.temperature_sampling.as_ref());
+        let min_output = request.extra.as_ref().and_then(|e| e.min_output.as_ref());
+        let output_schema =
+            emulated_schema(&request.request, &llm_model.inference_provider.provider).map_err(
+                |message| InvalidRequestError::new("response_format", "invalid_value", message),
+            )?;
+        let choices = requested_choices(request)?;
+        let emulated = tool_fallback == Some(&ToolFallback::Emulate) || output_schema.is_some();
+        if choices > 1 && emulated {
+            return Err(InvalidRequestError::new(
+                "n",
+                "unsupported_value",
+                format!(
+                    "n greater than 1 is not supported for {model_name}, its tools or output schema are emulated"
+                ),
+            )
+            .into());
+        }
+        let response = match (temperature_sampling, min_output, output_schema) {
+            _ if tool_fallback == Some(&ToolFallback::Emulate) => Right(
+                Self::execute_with_tool_emulation(request, executor_context)
+                    .instrument(span.clone())
+                    .await,
+            ),
+            (_, _, Some(schema)) => Right(
+                Self::execute_with_output_schema(request, &schema, executor_context)
+                    .instrument(span.clone())
+                    .await,
+            ),
+            _ if choices > 1 => Right(
+                Self::execute_choices(
+                    request,
+                    choices,
+                    native_choices(request, &llm_model.inference_provider.provider),
+                    executor_context,
+                )
+                .instrument(span.clone())
+                .await,
+            ),
+            (Some(sampling), _, None) => Right(
+                Self::execute_variants(request, sampling, executor_context)
+                    .instrument(span.clone())
+                    .await,
+            ),
+            (None, Some(min_output), None) => Right(
+                Self::execute_with_continuations(request, min_output, executor_context)
+                    .instrument(span.clone())
+                    .await,
+            ),
+            (None, None, None) => {
+                let mut stream_cache_context = StreamCacheContext::default();
+                let mut basic_cache_context = BasicCacheContext::default();
+                attach_idempotency(
+                    request,
+                    executor_context,
+                    &mut stream_cache_context,
+                    &mut basic_cache_context,
+                )
+                .await?;
+
+                execute(
+                    request,
+                    executor_context,
+                    span.clone(),
+                    stream_cache_context,
+                    basic_cache_context,
+                )
+                .instrument(span.clone())
+                .await?
+            }
+        };
+
+        let mut headers = vec![
+            ("X-Trace-Id", trace_id_uuid(trace_id).to_string()),
+            ("X-Model-Name", model_name.clone()),
+            (
                 "X-Provider-Name",
                 llm_model.inference_provider.provider.to_string(),
-            ));
+            ),
+        ];
+        if let Some((profile_name, _)) = executor_context
+            .quirks
+            .as_ref()
+            .and_then(|q| q.profile_for(&model_name, &llm_model.inference_provider.model_name))
+        {
+            headers.push(("X-Quirk-Profile", profile_name.to_string()));
+        }
 
-        match response {
+        let response = match response {
             Left(result_stream) => {
-                let stream = result_stream?.map_err(|e| {
-                    GatewayApiError::GatewayError(GatewayError::CustomError(e.to_string()))
-                });
-
                 // Pin the stream to heap
-                let mut stream = Box::pin(stream);
+                let mut stream = Box::pin(result_stream?);
 
-                // Check first element for error
-                let first = match stream.as_mut().next().await {
-                    Some(Ok(delta)) => delta,
-                    Some(Err(e)) => {
+                // Check first element for error. With keep-alive the response starts once the
+                // first keep-alive comment is due, later errors are sent as events.
+                let keep_alive = executor_context
+                    .stream_keep_alive
+                    .as_ref()
+                    .map(|k| k.interval());
+                let first = match keep_alive {
+                    Some(interval) => tokio::time::timeout(interval, stream.as_mut().next())
+                        .await
+                        .ok(),
+                    None => Some(stream.as_mut().next().await),
+                };
+                let first = match first {
+                    Some(Some(Ok(delta))) => Some(delta),
+                    Some(Some(Err(e))) => {
                         return Err(e);
                     }
-                    None => {
+                    Some(None) => {
                         return Err(GatewayApiError::GatewayError(GatewayError::CustomError(
                             "Empty response from model".to_string(),
                         )));
                     }
+                    None => None,
+                };
+
+                let stream = futures::stream::iter(first.map(Ok)).chain(stream);
+                let stream = match executor_context.request_deadline {
+                    Some(deadline) => until_deadline(
+                        stream,
+                        deadline,
+                        Self::timeout_stop(executor_context, &span, &model_name),
+                    )
+                    .left_stream(),
+                    None => stream.right_stream(),
                 };
 
-                let model_name = model_name.clone();
-                let result = futures::stream::once(async { Ok(first) })
-                    .chain(stream)
-                    .then(move |delta| {
-                        let model_name = model_name.clone();
-                        async move { map_sso_event(delta, model_name) }
-                    })
-                    .chain(futures::stream::once(async {
-                        Ok::<_, GatewayApiError>(Bytes::from("data: [DONE]\n\n"))
-                    }));
-
-                Ok(builder.content_type("text/event-stream").streaming(result))
+                Left(
+                    stream
+                        .map(move |delta| {
+                            // Model permit is held until the stream is finished or dropped
+                            let _permit = &model_permit;
+                            delta
+                        })
+                        .boxed_local(),
+                )
             }
-            Right(completions_response) => Ok(builder.json(completions_response?)),
+            Right(completions_response) => Right(completions_response?),
+        };
+
+        Ok(RoutedResponse {
+            model_name,
+            headers,
+            response,
+        })
+    }
+
+    /// Issues one request per sampled temperature and returns all variants as choices
+    async fn execute_variants(
+        request: &ChatCompletionRequestWithTools<RoutingStrategy>,
+        sampling: &TemperatureSampling,
+        executor_context: &ExecutorContext,
+    ) -> Result<ChatCompletionResponse, GatewayApiError> {
+        sampling.validate()?;
+        if request.request.stream.unwrap_or(false) {
+            return Err(InvalidRequestError::new(
+                "stream",
+                "unsupported_value",
+                "temperature_sampling does not support streaming",
+            )
+            .into());
+        }
+
+        let temperatures = sampling.sample(&mut rand::rng());
+        let span = Span::current();
+        let variants = temperatures.into_iter().map(|temperature| {
+            let mut variant = request.clone();
+            variant.request.temperature = Some(temperature);
+            if let Some(extra) = variant.extra.as_mut() {
+                extra.temperature_sampling = None;
+            }
+
+            let span = span.clone();
+            async move {
+                match execute(
+                    &variant,
+                    executor_context,
+                    span.clone(),
+                    StreamCacheContext::default(),
+                    BasicCacheContext::default(),
+                )
+                .instrument(span)
+                .await?
+                {
+                    Right(response) => response,
+                    Left(_) => Err(GatewayApiError::CustomError(
+                        "Unexpected streaming response for variant".to_string(),
+                    )),
+                }
+            }
+        });
+
+        let responses = futures::future::try_join_all(variants).await?;
+        merge_variants(responses)
+            .ok_or_else(|| GatewayApiError::CustomError("No variants were generated".to_string()))
+    }
+
+    /// Generates the `n` choices in one call for providers supporting it, otherwise issues
+    /// one request per choice and returns their choices with summed usage. Tools run by the
+    /// gateway loop separately for every choice, and every choice may end with its own tool
+    /// calls for the client. Failed choices are left out unless every choice failed.
+    async fn execute_choices(
+        request: &ChatCompletionRequestWithTools<RoutingStrategy>,
+        choices: u32,
+        native: bool,
+        executor_context: &ExecutorContext,
+    ) -> Result<ChatCompletionResponse, GatewayApiError> {
+        let span = Span::current();
+        if native {
+            return Self::execute_single(request, executor_context, &span).await;
+        }
+
+        let mut single = request.clone();
+        single.request.n = None;
+
+        let results = futures::future::join_all(
+            (0..choices).map(|_| Self::execute_single(&single, executor_context, &span)),
+        )
+        .await;
+        let (merged, errors) = merge_choices(results)?;
+        if !errors.is_empty() {
+            tracing::warn!("{} of {choices} choices failed", errors.len());
+            executor_context.emit_custom(
+                &span,
+                "choices_failed",
+                serde_json::json!({
+                    "requested": choices,
+                    "failed": errors.len(),
+                    "errors": errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+                }),
+            );
+        }
+
+        Ok(merged)
+    }
+
+    /// Re-requests with the output so far as assistant prefill while a normally finished
+    /// output is shorter than `min_output.min_tokens`, concatenating the continued text
+    async fn execute_with_continuations(
+        request: &ChatCompletionRequestWithTools<RoutingStrategy>,
+        min_output: &MinOutputTokens,
+        executor_context: &ExecutorContext,
+    ) -> Result<ChatCompletionResponse, GatewayApiError> {
+        if request.request.stream.unwrap_or(false) {
+            return Err(InvalidRequestError::new(
+                "stream",
+                "unsupported_value",
+                "min_output does not support streaming",
+            )
+            .into());
+        }
+
+        let span = Span::current();
+        let mut current = request.clone();
+        if let Some(extra) = current.extra.as_mut() {
+            extra.min_output = None;
+        }
+
+        let mut merged = Self::execute_single(&current, executor_context, &span).await?;
+        let mut continuations = 0;
+        while continuations < min_output.max_continuations && min_output.needs_continuation(&merged)
+        {
+            let Some(output) = output_text(&merged) else {
+                break;
+            };
+            current = continuation_request(request, output);
+            let continuation = Self::execute_single(&current, executor_context, &span).await?;
+            let added_tokens = continuation.usage.completion_tokens;
+            append_continuation(&mut merged, continuation);
+            continuations += 1;
+
+            executor_context.emit_custom(
+                &span,
+                "output_continuation",
+                serde_json::json!({
+                    "continuation": continuations,
+                    "min_tokens": min_output.min_tokens,
+                    "added_tokens": added_tokens,
+                    "completion_tokens": merged.usage.completion_tokens,
+                }),
+            );
+
+            if added_tokens == 0 {
+                break;
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Validates the output against the requested JSON schema for providers without
+    /// structured output support, retrying once with the validation errors
+    async fn execute_with_output_schema(
+        request: &ChatCompletionRequestWithTools<RoutingStrategy>,
+        schema: &OutputSchema,
+        executor_context: &ExecutorContext,
+    ) -> Result<ChatCompletionResponse, GatewayApiError> {
+        if request.request.stream.unwrap_or(false) {
+            return Err(InvalidRequestError::new(
+                "stream",
+                "unsupported_value",
+                format!(
+                    "json_schema response_format does not support streaming for {}",
+                    request.request.model
+                ),
+            )
+            .into());
+        }
+
+        let span = Span::current();
+        let mut current = schema.instructed_request(request);
+        // Usage of rejected attempts is added to the returned response
+        let mut rejected_usage = ChatCompletionUsage::default();
+        for attempt in 1..=MAX_SCHEMA_ATTEMPTS {
+            let mut response = Self::execute_single(&current, executor_context, &span).await?;
+            add_usage(&mut response.usage, &rejected_usage);
+            let output = output_text(&response).unwrap_or_default().to_string();
+
+            let error = match schema.validate(&output) {
+                Ok(value) => {
+                    if let Some(choice) = response.choices.first_mut() {
+                        choice.message.content =
+                            Some(ChatCompletionContent::Text(value.to_string()));
+                    }
+                    return Ok(response);
+                }
+                Err(error) => error,
+            };
+
+            executor_context.emit_custom(
+                &span,
+                "schema_validation_failed",
+                serde_json::json!({
+                    "attempt": attempt,
+                    "error": error,
+                }),
+            );
+
+            if attempt == MAX_SCHEMA_ATTEMPTS {
+                return Err(GatewayApiError::SchemaValidation(error));
+            }
+            rejected_usage = response.usage;
+            current = corrective_request(&current, &output, &error);
+        }
+
+        unreachable!()
+    }
+
+    async fn execute_with_tool_emulation(
+        request: &ChatCompletionRequestWithTools<RoutingStrategy>,
+        executor_context: &ExecutorContext,
+    ) -> Result<ChatCompletionResponse, GatewayApiError> {
+        if request.request.stream.unwrap_or(false) {
+            return Err(InvalidRequestError::new(
+                "stream",
+                "unsupported_value",
+                "Tool emulation does not support streaming",
+            )
+            .into());
+        }
+
+        let span = Span::current();
+        Self::emit_tool_fallback(executor_context, &span, &request.request.model, None);
+
+        let tools = request.request.tools.clone().unwrap_or_default();
+        let mut response =
+            Self::execute_single(&emulation_request(request), executor_context, &span).await?;
+        for call in apply_tool_calls(&mut response, &tools) {
+            executor_context
+                .callbackhandler
+                .on_message(ModelEventWithDetails::new(
+                    ModelEvent::new(
+                        &span,
+                        ModelEventType::ToolStart(ToolStartEvent {
+                            tool_id: call.id,
+                            tool_name: call.function.name,
+                            input: call.function.arguments,
+                        }),
+                    )
+                    .with_request_id(executor_context.request_id.clone()),
+                    None,
+                ));
+        }
+
+        Ok(response)
+    }
+
+    fn emit_tool_fallback(
+        executor_context: &ExecutorContext,
+        span: &Span,
+        model_name: &str,
+        rerouted_to: Option<&String>,
+    ) {
+        executor_context.emit_custom(
+            span,
+            "tool_fallback",
+            serde_json::json!({
+                "model": model_name,
+                "strategy": if rerouted_to.is_some() { "reroute" } else { "emulate" },
+                "rerouted_to": rerouted_to,
+            }),
+        );
+    }
+
+    async fn execute_single(
+        request: &ChatCompletionRequestWithTools<RoutingStrategy>,
+        executor_context: &ExecutorContext,
+        span: &Span,
+    ) -> Result<ChatCompletionResponse, GatewayApiError> {
+        match execute(
+            request,
+            executor_context,
+            span.clone(),
+            StreamCacheContext::default(),
+            BasicCacheContext::default(),
+        )
+        .instrument(span.clone())
+        .await?
+        {
+            Right(response) => response,
+            Left(_) => Err(GatewayApiError::CustomError(
+                "Unexpected streaming response".to_string(),
+            )),
         }
     }
 
@@ -250,3 +898,219 @@ impl RoutedExecutor {
             .map_err(RoutedExecutorError::FailedToDeserializeRequestResult)
     }
 }
+
+/// SSE body of the streamed events, each already mapped to its data event. Starts with
+/// the request id and ends with `[DONE]`, with keep-alive comments as configured.
+pub fn event_stream<S>(
+    events: S,
+    executor_context: &ExecutorContext,
+) -> impl Stream<Item = Result<Bytes, GatewayApiError>> + 'static
+where
+    S: Stream<Item = Result<Bytes, GatewayApiError>> + Unpin + 'static,
+{
+    // SSE comment, ignored by clients that do not look for it
+    let request_id_event = executor_context
+        .request_id
+        .as_ref()
+        .map(|id| Ok(Bytes::from(format!(": request_id {id}\n\n"))));
+
+    let keep_alive = executor_context
+        .stream_keep_alive
+        .as_ref()
+        .map(|k| k.interval());
+    let events = match keep_alive {
+        Some(interval) => with_keep_alive(events, interval).left_stream(),
+        None => events.right_stream(),
+    };
+    futures::stream::iter(request_id_event)
+        .chain(events)
+        .chain(futures::stream::once(async {
+            Ok::<_, GatewayApiError>(Bytes::from("data: [DONE]\n\n"))
+        }))
+}
+
+/// Ends the stream with a timeout error once the deadline passes. The inner stream is
+/// dropped at that point, which closes the channel of the model task and stops it.
+fn until_deadline<S, T>(
+    stream: S,
+    deadline: Instant,
+    on_timeout: impl FnOnce(),
+) -> impl Stream<Item = Result<T, GatewayApiError>>
+where
+    S: Stream<Item = Result<T, GatewayApiError>> + Unpin,
+{
+    let sleep = Box::pin(tokio::time::sleep_until(deadline.into()));
+    futures::stream::unfold(Some((stream, sleep, on_timeout)), |state| async move {
+        let (mut stream, mut sleep, on_timeout) = state?;
+        tokio::select! {
+            item = stream.next() => Some((item?, Some((stream, sleep, on_timeout)))),
+            _ = &mut sleep => {
+                on_timeout();
+                Some((
+                    Err(GatewayApiError::Timeout("Request timeout exceeded".to_string())),
+                    None,
+                ))
+            }
+        }
+    })
+}
+
+/// Time limit of a single attempt, bounded by what is left until the router deadline
+fn attempt_timeout(
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    now: Instant,
+) -> Option<Duration> {
+    let remaining = deadline.map(|d| d.saturating_duration_since(now));
+    match (timeout, remaining) {
+        (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+        (timeout, remaining) => timeout.or(remaining),
+    }
+}
+
+/// Request retried after a content filter refusal and its strategy, `None` when the request
+/// did not opt in. The retry request has the option removed, so it is not retried again.
+fn content_filter_retry(
+    request: &ChatCompletionRequestWithTools<RoutingStrategy>,
+) -> Option<(
+    ChatCompletionRequestWithTools<RoutingStrategy>,
+    &'static str,
+)> {
+    let options = request.extra.as_ref()?.content_filter_retry.clone()?;
+
+    let mut retry_request = request.clone();
+    if let Some(extra) = retry_request.extra.as_mut() {
+        extra.content_filter_retry = None;
+    }
+
+    if let Some(model) = options.fallback_model {
+        retry_request.request.model = model;
+        Some((retry_request, "reroute"))
+    } else if options.soften {
+        retry_request.request.messages.insert(
+            0,
+            ChatCompletionMessage::new_text(
+                "system".to_string(),
+                CONTENT_FILTER_SOFTENING_INSTRUCTION.to_string(),
+            ),
+        );
+        Some((retry_request, "soften"))
+    } else {
+        None
+    }
+}
+
+/// Counts a model change by a tool reroute or downgrade, failing once a reroute cycle
+/// such as A to B to A would exceed `MAX_REROUTES`
+fn next_reroute(reroutes: usize, model_name: &str) -> Result<usize, GatewayApiError> {
+    if reroutes >= MAX_REROUTES {
+        return Err(GatewayApiError::GatewayError(GatewayError::CustomError(
+            format!("Max reroutes reached for {model_name}"),
+        )));
+    }
+    Ok(reroutes + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::{ChatCompletionRequest, ContentFilterRetry, Extra};
+
+    #[test]
+    fn test_content_filter_retry() {
+        let request = |options: Option<ContentFilterRetry>| ChatCompletionRequestWithTools {
+            request: ChatCompletionRequest {
+                model: "openai/gpt-4o".to_string(),
+                messages: vec![ChatCompletionMessage::new_text(
+                    "user".to_string(),
+                    "Hello".to_string(),
+                )],
+                ..Default::default()
+            },
+            mcp_servers: None,
+            router: None,
+            max_retries: None,
+            extra: Some(Extra {
+                content_filter_retry: options,
+                ..Default::default()
+            }),
+            fallbacks: None,
+            provider_specific: None,
+        };
+
+        assert!(content_filter_retry(&request(None)).is_none());
+        assert!(content_filter_retry(&request(Some(ContentFilterRetry::default()))).is_none());
+
+        let (retry, strategy) = content_filter_retry(&request(Some(ContentFilterRetry {
+            soften: true,
+            fallback_model: Some("anthropic/claude-3-5-haiku".to_string()),
+        })))
+        .unwrap();
+        assert_eq!(strategy, "reroute");
+        assert_eq!(retry.request.model, "anthropic/claude-3-5-haiku");
+        assert_eq!(retry.request.messages.len(), 1);
+        // The retry is not retried again, so fallback models do not chain
+        assert!(content_filter_retry(&retry).is_none());
+
+        let (retry, strategy) = content_filter_retry(&request(Some(ContentFilterRetry {
+            soften: true,
+            fallback_model: None,
+        })))
+        .unwrap();
+        assert_eq!(strategy, "soften");
+        assert_eq!(retry.request.model, "openai/gpt-4o");
+        assert_eq!(retry.request.messages[0].role, "system");
+        assert_eq!(retry.request.messages.len(), 2);
+    }
+
+    #[test]
+    fn test_attempt_timeout() {
+        let now = Instant::now();
+        let deadline = Some(now + Duration::from_millis(500));
+
+        assert_eq!(attempt_timeout(None, None, now), None);
+        assert_eq!(
+            attempt_timeout(Some(Duration::from_millis(200)), None, now),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            attempt_timeout(Some(Duration::from_millis(2000)), deadline, now),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            attempt_timeout(None, deadline, now + Duration::from_secs(1)),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_until_deadline_drops_stream() {
+        let (tx, rx) = tokio::sync::mpsc::channel::<Result<u32, GatewayApiError>>(1);
+        tx.send(Ok(1)).await.unwrap();
+
+        let timed_out = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = timed_out.clone();
+        let stream = until_deadline(
+            tokio_stream::wrappers::ReceiverStream::new(rx),
+            Instant::now() + Duration::from_millis(50),
+            move || flag.store(true, std::sync::atomic::Ordering::SeqCst),
+        );
+        let items: Vec<_> = stream.collect().await;
+
+        assert!(matches!(
+            items[..],
+            [Ok(1), Err(GatewayApiError::Timeout(_))]
+        ));
+        assert!(timed_out.load(std::sync::atomic::Ordering::SeqCst));
+        assert!(tx.is_closed());
+    }
+
+    #[test]
+    fn test_reroutes_are_bounded() {
+        let mut reroutes = 0;
+        for _ in 0..MAX_REROUTES {
+            reroutes = next_reroute(reroutes, "openai/gpt-4o").unwrap();
+        }
+        assert!(next_reroute(reroutes, "openai/gpt-4o").is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use crate::types::provider::InferenceModelProvider;
+
+/// Custom event carrying the backend `system_fingerprint` of a model response
+pub const SYSTEM_FINGERPRINT_EVENT: &str = "system_fingerprint";
+
+/// Providers that sample deterministically for a `seed`
+pub fn supports_seed(provider: &InferenceModelProvider) -> bool {
+    match provider {
+        InferenceModelProvider::OpenAI
+        | InferenceModelProvider::Proxy(_)
+        | InferenceModelProvider::Gemini => true,
+        InferenceModelProvider::Anthropic | InferenceModelProvider::Bedrock => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_gateway::provider::Provider;
+    use crate::models::{InferenceProvider, ModelMetadata};
+    use crate::types::engine::CompletionEngineParams;
+    use crate::types::gateway::ChatCompletionRequest;
+
+    #[test]
+    fn test_seed_forwarded() {
+        let model = ModelMetadata {
+            model: "gpt-4o-mini".to_string(),
+            inference_provider: InferenceProvider {
+                provider: InferenceModelProvider::OpenAI,
+                model_name: "gpt-4o-mini".to_string(),
+                endpoint: None,
+            },
+            ..Default::default()
+        };
+        let request = ChatCompletionRequest {
+            model: model.model.clone(),
+            seed: Some(42),
+            ..Default::default()
+        };
+        match Provider::get_completion_engine_for_model(&model, &request, None, None, None, None)
+            .unwrap()
+        {
+            CompletionEngineParams::OpenAi { params, .. } => assert_eq!(params.seed, Some(42)),
+            _ => panic!("expected openai params"),
+        }
+
+        assert!(supports_seed(&InferenceModelProvider::OpenAI));
+        assert!(!supports_seed(&InferenceModelProvider::Anthropic));
+    }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::InvalidRequestError;
+use crate::types::provider::InferenceModelProvider;
+use crate::GatewayApiError;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StopSequencesConfig {
+    #[serde(default)]
+    pub policy: StopSequencesPolicy,
+}
+
+/// Handling of requests with more stop sequences than the provider accepts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopSequencesPolicy {
+    /// Rejected with a 400
+    #[default]
+    Reject,
+    /// The first sequences within the limit are sent
+    Truncate,
+}
+
+/// Most stop sequences a provider accepts, `None` without a known limit
+pub fn max_stop_sequences(provider: &InferenceModelProvider) -> Option<usize> {
+    match provider {
+        InferenceModelProvider::OpenAI | InferenceModelProvider::Bedrock => Some(4),
+        InferenceModelProvider::Gemini => Some(5),
+        InferenceModelProvider::Anthropic | InferenceModelProvider::Proxy(_) => None,
+    }
+}
+
+/// Stop sequences sent to the provider
+#[derive(Debug, PartialEq)]
+pub struct NormalizedStop {
+    pub stop: Option<Vec<String>>,
+    /// Sequences dropped by the truncate policy
+    pub dropped: Vec<String>,
+}
+
+/// Drops empty and repeated sequences, which some providers reject, and applies the limit
+/// of the provider
+pub fn normalize_stop(
+    stop: Vec<String>,
+    provider: &InferenceModelProvider,
+    policy: StopSequencesPolicy,
+) -> Result<NormalizedStop, GatewayApiError> {
+    let mut sequences: Vec<String> = vec![];
+    for sequence in stop {
+        if !sequence.is_empty() && !sequences.contains(&sequence) {
+            sequences.push(sequence);
+        }
+    }
+
+    let mut dropped = vec![];
+    if let Some(max) = max_stop_sequences(provider) {
+        if sequences.len() > max {
+            if policy == StopSequencesPolicy::Reject {
+                return Err(InvalidRequestError::new(
+                    "stop",
+                    "invalid_value",
+                    format!(
+                        "{provider} accepts at most {max} stop sequences, got {}",
+                        sequences.len()
+                    ),
+                )
+                .into());
+            }
+            dropped = sequences.split_off(max);
+        }
+    }
+
+    Ok(NormalizedStop {
+        stop: (!sequences.is_empty()).then_some(sequences),
+        dropped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_gateway::provider::Provider;
+    use crate::models::{InferenceProvider, ModelMetadata};
+    use crate::types::engine::CompletionEngineParams;
+    use crate::types::gateway::ChatCompletionRequest;
+
+    fn stop(count: usize) -> Vec<String> {
+        (1..=count).map(|i| format!("END{i}")).collect()
+    }
+
+    #[test]
+    fn test_openai_limit() {
+        let provider = InferenceModelProvider::OpenAI;
+        let error = normalize_stop(stop(5), &provider, StopSequencesPolicy::Reject).unwrap_err();
+        assert!(matches!(&error, GatewayApiError::InvalidRequest(e) if e.param == "stop"));
+        assert_eq!(
+            error.to_string(),
+            "openai accepts at most 4 stop sequences, got 5"
+        );
+
+        let normalized = normalize_stop(stop(5), &provider, StopSequencesPolicy::Truncate).unwrap();
+        assert_eq!(normalized.stop, Some(stop(4)));
+        assert_eq!(normalized.dropped, vec!["END5".to_string()]);
+
+        // Repeated and empty sequences do not count against the limit
+        let mut repeated = stop(4);
+        repeated.extend(["END1".to_string(), String::new()]);
+        let normalized = normalize_stop(repeated, &provider, StopSequencesPolicy::Reject).unwrap();
+        assert_eq!(normalized.stop, Some(stop(4)));
+    }
+
+    #[test]
+    fn test_anthropic_stop_sequences() {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-3-5-sonnet-20240620",
+            "messages": [{"role": "user", "content": "Hello"}],
+            "stop": ["END1", "END2", "END3", "END4", "END5"],
+        }))
+        .unwrap();
+        let normalized = normalize_stop(
+            request.stop.unwrap(),
+            &InferenceModelProvider::Anthropic,
+            StopSequencesPolicy::Reject,
+        )
+        .unwrap();
+        assert_eq!(normalized.stop, Some(stop(5)));
+
+        let model = ModelMetadata {
+            model: "claude-3-5-sonnet-20240620".to_string(),
+            inference_provider: InferenceProvider {
+                provider: InferenceModelProvider::Anthropic,
+                model_name: "claude-3-5-sonnet-20240620".to_string(),
+                endpoint: None,
+            },
+            ..Default::default()
+        };
+        let request = ChatCompletionRequest {
+            model: model.model.clone(),
+            stop: normalized.stop,
+            ..Default::default()
+        };
+        match Provider::get_completion_engine_for_model(&model, &request, None, None, None, None)
+            .unwrap()
+        {
+            CompletionEngineParams::Anthropic { params, .. } => {
+                assert_eq!(params.stop_sequences.unwrap().len(), 5)
+            }
+            _ => panic!("expected anthropic params"),
+        }
+    }
+
+    #[test]
+    fn test_single_stop_string() {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o-mini",
+            "messages": [{"role": "user", "content": "Hello"}],
+            "stop": "END",
+        }))
+        .unwrap();
+        assert_eq!(request.stop, Some(vec!["END".to_string()]));
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+use either::Either::{Left, Right};
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::basic_executor::BasicCacheContext;
+use super::stream_executor::StreamCacheContext;
+use crate::executor::context::ExecutorContext;
+use crate::handler::chat::SSOChatEvent;
+use crate::types::gateway::{
+    ChatCompletionChoice, ChatCompletionContent, ChatCompletionLogprobs, ChatCompletionMessage,
+    ChatCompletionRequestWithTools, ChatCompletionResponse, ChatCompletionUsage,
+    CompletionModelUsage, StreamOptions, ToolCall,
+};
+use crate::GatewayApiError;
+
+/// Executes the request streaming from the provider and returns one buffered response.
+/// The usage chunk is always requested, the client did not ask for a stream.
+pub async fn execute_buffered<T: Serialize + DeserializeOwned + Debug + Clone>(
+    request_with_tools: &ChatCompletionRequestWithTools<T>,
+    executor_context: &ExecutorContext,
+    router_span: tracing::Span,
+    stream_cache_context: StreamCacheContext,
+    basic_cache_context: BasicCacheContext,
+) -> Result<ChatCompletionResponse, GatewayApiError> {
+    let mut request_with_tools = request_with_tools.clone();
+    request_with_tools.request.stream = Some(true);
+    request_with_tools.request.stream_options = Some(StreamOptions {
+        include_usage: true,
+    });
+
+    match super::execute(
+        &request_with_tools,
+        executor_context,
+        router_span,
+        stream_cache_context,
+        basic_cache_context,
+    )
+    .await?
+    {
+        Left(stream) => {
+            collect_stream_into_response(stream?, &request_with_tools.request.model).await
+        }
+        Right(response) => response,
+    }
+}
+
+/// Buffers a streamed response into a single response, so the provider can be called with
+/// streaming while the client receives one body. Tool call fragments are merged by their
+/// index, the usage of all chunks carrying one is summed and the finish reason is taken
+/// from the last chunk carrying it.
+pub async fn collect_stream_into_response<S>(
+    mut stream: S,
+    model: &str,
+) -> Result<ChatCompletionResponse, GatewayApiError>
+where
+    S: Stream<Item = Result<SSOChatEvent, GatewayApiError>> + Unpin,
+{
+    let mut content = String::new();
+    let mut tool_calls: BTreeMap<usize, ToolCall> = BTreeMap::new();
+    let mut logprobs = vec![];
+    let mut usage: Option<CompletionModelUsage> = None;
+    let mut finish_reason = None;
+
+    while let Some(event) = stream.next().await {
+        let (delta, chunk_usage, chunk_finish_reason) = event?;
+        if let Some(delta) = delta {
+            if let Some(c) = delta.content {
+                content.push_str(&c);
+            }
+            for fragment in delta.tool_calls.unwrap_or_default() {
+                merge_tool_call(&mut tool_calls, fragment);
+            }
+            if let Some(l) = delta.logprobs.and_then(|l| l.content) {
+                logprobs.extend(l);
+            }
+        }
+        if let Some(chunk_usage) = chunk_usage {
+            match &mut usage {
+                Some(total) => total.add(&chunk_usage),
+                None => usage = Some(chunk_usage),
+            }
+        }
+        if chunk_finish_reason.is_some() {
+            finish_reason = chunk_finish_reason;
+        }
+    }
+
+    let tool_calls: Vec<ToolCall> = tool_calls.into_values().collect();
+    let message = ChatCompletionMessage {
+        role: "assistant".to_string(),
+        content: (!content.is_empty() || tool_calls.is_empty())
+            .then_some(ChatCompletionContent::Text(content)),
+        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+        ..Default::default()
+    };
+
+    let is_cache_used = usage.as_ref().map(|u| u.is_cache_used);
+    let usage = usage
+        .map(|u| ChatCompletionUsage {
+            prompt_tokens: u.input_tokens as i32,
+            completion_tokens: u.output_tokens as i32,
+            total_tokens: u.total_tokens as i32,
+            prompt_tokens_details: u.prompt_tokens_details,
+            completion_tokens_details: u.completion_tokens_details,
+            cost: 0.0,
+        })
+        .unwrap_or_default();
+
+    Ok(ChatCompletionResponse {
+        id: Uuid::new_v4().to_string(),
+        object: "chat.completion".to_string(),
+        created: chrono::Utc::now().timestamp(),
+        model: model.to_string(),
+        choices: vec![ChatCompletionChoice {
+            index: 0,
+            message,
+            finish_reason: Some(finish_reason.unwrap_or_else(|| "stop".to_string())),
+            logprobs: (!logprobs.is_empty()).then_some(ChatCompletionLogprobs {
+                content: Some(logprobs),
+            }),
+        }],
+        usage,
+        is_cache_used,
+        citations: None,
+        confidence: None,
+        system_fingerprint: None,
+    })
+}
+
+/// Only the first fragment of a call carries its id and name, the following ones continue
+/// the JSON arguments
+fn merge_tool_call(tool_calls: &mut BTreeMap<usize, ToolCall>, fragment: ToolCall) {
+    let index = fragment.index.unwrap_or(0);
+    match tool_calls.get_mut(&index) {
+        Some(tool_call) => {
+            if tool_call.id.is_empty() {
+                tool_call.id = fragment.id;
+            }
+            if tool_call.function.name.is_empty() {
+                tool_call.function.name = fragment.function.name;
+            }
+            tool_call
+                .function
+                .arguments
+                .push_str(&fragment.function.arguments);
+        }
+        None => {
+            tool_calls.insert(
+                index,
+                ToolCall {
+                    index: Some(index),
+                    ..fragment
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::{ChatCompletionDelta, FunctionCall};
+
+    fn delta(content: Option<&str>, tool_calls: Option<Vec<ToolCall>>) -> SSOChatEvent {
+        (
+            Some(ChatCompletionDelta {
+                role: Some("assistant".to_string()),
+                content: content.map(|c| c.to_string()),
+                tool_calls,
+                logprobs: None,
+            }),
+            None,
+            None,
+        )
+    }
+
+    fn fragment(index: usize, id: &str, name: &str, arguments: &str) -> ToolCall {
+        ToolCall {
+            index: Some(index),
+            id: id.to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments: arguments.to_string(),
+            },
+        }
+    }
+
+    fn usage() -> CompletionModelUsage {
+        CompletionModelUsage {
+            input_tokens: 12,
+            output_tokens: 7,
+            total_tokens: 19,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_content_is_concatenated() {
+        let events = vec![
+            Ok(delta(Some("Hello"), None)),
+            Ok(delta(Some(", world"), None)),
+            Ok((None, Some(usage()), Some("stop".to_string()))),
+        ];
+        let response = collect_stream_into_response(futures::stream::iter(events), "gpt-4o")
+            .await
+            .unwrap();
+
+        let choice = &response.choices[0];
+        assert_eq!(
+            choice.message.content,
+            Some(ChatCompletionContent::Text("Hello, world".to_string()))
+        );
+        assert_eq!(choice.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(response.usage.total_tokens, 19);
+        assert_eq!(response.model, "gpt-4o");
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_fragments_are_merged_by_index() {
+        // Fragments of two parallel calls arrive interleaved
+        let events = vec![
+            Ok(delta(
+                None,
+                Some(vec![fragment(0, "call_1", "get_weather", "{\"city\":")]),
+            )),
+            Ok(delta(
+                None,
+                Some(vec![fragment(1, "call_2", "get_time", "")]),
+            )),
+            Ok(delta(
+                None,
+                Some(vec![fragment(1, "", "", "{\"tz\":\"UTC\"}")]),
+            )),
+            Ok(delta(None, Some(vec![fragment(0, "", "", "\"Paris\"}")]))),
+            Ok((None, Some(usage()), Some("tool_calls".to_string()))),
+        ];
+        let response = collect_stream_into_response(futures::stream::iter(events), "gpt-4o")
+            .await
+            .unwrap();
+
+        let message = &response.choices[0].message;
+        assert_eq!(message.content, None);
+        let tool_calls = message.tool_calls.as_ref().unwrap();
+        assert_eq!(
+            tool_calls,
+            &vec![
+                fragment(0, "call_1", "get_weather", "{\"city\":\"Paris\"}"),
+                fragment(1, "call_2", "get_time", "{\"tz\":\"UTC\"}"),
+            ]
+        );
+        assert_eq!(
+            response.choices[0].finish_reason.as_deref(),
+            Some("tool_calls")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_error_is_returned() {
+        let events = vec![
+            Ok(delta(Some("Hel"), None)),
+            Err(GatewayApiError::CustomError("connection reset".to_string())),
+        ];
+        let error = collect_stream_into_response(futures::stream::iter(events), "gpt-4o")
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "connection reset");
+    }
+    #[tokio::test]
+    async fn test_usage_is_summed_over_rounds() {
+        // A tool call round followed by the final answer, each reporting its usage
+        let events = vec![
+            Ok((None, Some(usage()), Some("tool_calls".to_string()))),
+            Ok(delta(Some("Sunny"), None)),
+            Ok((None, Some(usage()), Some("stop".to_string()))),
+        ];
+        let response = collect_stream_into_response(futures::stream::iter(events), "gpt-4o")
+            .await
+            .unwrap();
+
+        assert_eq!(response.usage.prompt_tokens, 24);
+        assert_eq!(response.usage.completion_tokens, 14);
+        assert_eq!(response.usage.total_tokens, 38);
+        assert_eq!(response.choices[0].finish_reason.as_deref(), Some("stop"));
+    }
+}
@@ -1,20 +1,26 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use crate::model::credentials_identifier;
+use crate::model::token_estimate::TokenizerFamily;
+use crate::model::types::LLMContentEvent;
 use crate::model::types::LLMFinishEvent;
+use crate::model::types::LLMStartEvent;
 use crate::model::types::ModelEvent;
+use crate::model::types::ModelToolCall;
 use futures::future::join;
+use futures::Stream;
 use futures::StreamExt;
 use futures::TryStreamExt;
 
 use crate::{
     model::{
         types::{ModelEventType, ModelFinishReason},
-        ModelInstance,
+        CredentialsIdent, ModelInstance,
     },
     types::{
         engine::ParentCompletionOptions,
-        gateway::{ChatCompletionDelta, FunctionCall, ToolCall},
+        gateway::{ChatCompletionDelta, CompletionModelUsage, FunctionCall, ToolCall},
         threads::Message,
     },
 };
@@ -22,8 +28,10 @@ use tokio_stream::wrappers::ReceiverStream;
 use tracing::Span;
 use tracing_futures::Instrument;
 
+use super::stream_transform::StreamTransformPipeline;
 use super::stream_wrapper::wrap_stream;
 use crate::executor::chat_completion::ChatCompletionStream;
+use crate::handler::chat::SSOChatEvent;
 use crate::handler::{CallbackHandlerFn, ModelEventWithDetails};
 use crate::types::engine::CompletionModelDefinition;
 use crate::types::engine::ParentDefinition;
@@ -35,6 +43,7 @@ pub struct StreamCacheContext {
     pub cached_events: Option<Vec<ModelEvent>>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn stream_chunks(
     completion_model_definition: CompletionModelDefinition,
     model: Box<dyn ModelInstance>,
@@ -43,6 +52,9 @@ pub async fn stream_chunks(
     tags: HashMap<String, String>,
     input_vars: HashMap<String, serde_json::Value>,
     cached_context: StreamCacheContext,
+    transforms: StreamTransformPipeline,
+    ordered_tool_calls: bool,
+    include_usage: bool,
 ) -> Result<ChatCompletionStream, GatewayApiError> {
     let parent_definition =
         ParentDefinition::CompletionModel(Box::new(completion_model_definition.clone()));
@@ -53,16 +65,39 @@ pub async fn stream_chunks(
     };
 
     let db_model = model_options.definition.get_db_model();
+    let credentials_ident = credentials_identifier(&completion_model_definition.model_params);
     let (outer_tx, rx) = tokio::sync::mpsc::channel(100);
 
     tokio::spawn(
         async move {
             let (tx, mut rx) = tokio::sync::mpsc::channel::<Option<ModelEvent>>(100);
+            let mut assistant_msg = String::new();
+            // Provider call in progress, the one to close when the client disconnects
+            let mut started = None;
+            // Start of the content of the call in progress in `assistant_msg`
+            let mut call_offset = 0;
             let forward_fut = async {
-                let mut assistant_msg = String::new();
                 while let Some(Some(mut msg)) = rx.recv().await {
-                    if let ModelEventType::LlmContent(event) = &mut msg.event {
-                        assistant_msg.push_str(event.content.as_str());
+                    match &mut msg.event {
+                        ModelEventType::LlmStart(event) => {
+                            started = Some(event.clone());
+                            call_offset = assistant_msg.len();
+                        }
+                        ModelEventType::LlmContent(event) => {
+                            assistant_msg.push_str(event.content.as_str())
+                        }
+                        ModelEventType::LlmStop(event) => {
+                            // Some providers do not report the usage of streamed calls
+                            if let (None, Some(start)) = (&event.usage, &started) {
+                                event.usage = Some(estimate_stream_usage(
+                                    start,
+                                    &assistant_msg[call_offset..],
+                                    &event.tool_calls,
+                                ));
+                            }
+                            started = None;
+                        }
+                        _ => {}
                     }
 
                     callback_handler.on_message(ModelEventWithDetails::new(
@@ -86,12 +121,41 @@ pub async fn stream_chunks(
                 .stream(input_vars, tx, messages, tags)
                 .instrument(Span::current());
 
-            let (result, _) = join(result_fut, forward_fut).await;
+            // A dropped response stream, e.g. on a request timeout or a client disconnect,
+            // stops the model call instead of leaving it running in the background
+            let result = tokio::select! {
+                (result, _) = join(result_fut, forward_fut) => Some(result),
+                _ = outer_tx.closed() => None,
+            };
+            let call_output = &assistant_msg[call_offset..];
+            let Some(result) = result else {
+                // The provider never reports the usage of the cancelled call, record an estimate
+                if let Some(start) = started {
+                    let stop = estimated_stop(
+                        &start,
+                        call_output,
+                        "client_disconnected",
+                        credentials_ident,
+                    );
+                    callback_handler.on_message(ModelEventWithDetails::new(
+                        ModelEvent::new(&Span::current(), ModelEventType::LlmStop(stop)),
+                        Some(db_model),
+                    ));
+                }
+                return;
+            };
             if let Err(e) = result {
-                outer_tx
-                    .send(Err(GatewayApiError::GatewayError(e)))
-                    .await
-                    .unwrap();
+                // A provider failing after the first token leaves the call without a finish
+                // event, record the usage of the partial response
+                if let Some(start) = started.filter(|_| !call_output.is_empty()) {
+                    let stop = estimated_stop(&start, call_output, "error", credentials_ident);
+                    callback_handler.on_message(ModelEventWithDetails::new(
+                        ModelEvent::new(&Span::current(), ModelEventType::LlmStop(stop)),
+                        Some(db_model),
+                    ));
+                }
+                // The receiver is gone if the client disconnected meanwhile
+                let _ = outer_tx.send(Err(GatewayApiError::GatewayError(e))).await;
             }
         }
         .in_current_span(),
@@ -103,94 +167,651 @@ pub async fn stream_chunks(
             async move {
                 if let Ok(event) = &e {
                     if let Some(events_sender) = events_sender {
-                        events_sender.send(Some(event.clone())).await.unwrap();
+                        if let Err(e) = events_sender.send(Some(event.clone())).await {
+                            tracing::warn!("Response cache stopped receiving events: {e}");
+                        }
                     }
                 }
 
                 e
             }
         })
-        .filter_map(|e: Result<ModelEvent, GatewayApiError>| async move {
+        .filter_map(move |e: Result<ModelEvent, GatewayApiError>| async move {
             e.map_or_else(
                 |e| Some(Err(e)),
-                |model_event| match model_event.event {
-                    ModelEventType::LlmContent(_)
-                    | ModelEventType::ToolStart(_)
-                    | ModelEventType::LlmStop(_) => Some(Ok(model_event)),
-                    _ => None,
+                |model_event| {
+                    is_streamed_event(&model_event.event, ordered_tool_calls)
+                        .then_some(Ok(model_event))
                 },
             )
         })
-        .then(move |e: Result<ModelEvent, GatewayApiError>| async move {
-            match e {
-                Ok(e) => match e.event {
-                    ModelEventType::LlmContent(content) => Ok((
-                        Some(ChatCompletionDelta {
-                            role: Some("assistant".to_string()),
-                            content: Some(content.content),
-                            tool_calls: None,
-                        }),
-                        None,
-                        None,
-                    )),
-                    ModelEventType::ToolStart(tool_call) => Ok((
-                        Some(ChatCompletionDelta {
-                            role: Some("assistant".to_string()),
-                            content: None,
-                            tool_calls: Some(vec![ToolCall {
-                                index: Some(0),
-                                id: tool_call.tool_id.clone(),
-                                r#type: "function".into(),
-                                function: FunctionCall {
-                                    name: tool_call.tool_name.clone(),
-                                    arguments: tool_call.input.clone(),
-                                },
-                            }]),
-                        }),
-                        None,
-                        None,
-                    )),
-                    ModelEventType::LlmStop(LLMFinishEvent {
-                        usage,
-                        finish_reason,
-                        tool_calls,
-                        ..
-                    }) => {
-                        let ev = match finish_reason {
-                            ModelFinishReason::ToolCalls => Some(ChatCompletionDelta {
-                                role: Some("assistant".to_string()),
-                                content: None,
-                                tool_calls: Some(
-                                    tool_calls
-                                        .into_iter()
-                                        .enumerate()
-                                        .map(|(index, tc)| ToolCall {
-                                            index: Some(index),
-                                            id: tc.tool_id.clone(),
-                                            r#type: "function".into(),
-                                            function: FunctionCall {
-                                                name: tc.tool_name.clone(),
-                                                arguments: tc.input.clone(),
-                                            },
-                                        })
-                                        .collect(),
-                                ),
-                            }),
-                            _ => None,
-                        };
-
-                        Ok((ev, usage, Some(finish_reason.to_string())))
+        .scan(transforms, |transforms, e| {
+            futures::future::ready(Some(apply_transforms(transforms, e)))
+        })
+        .flat_map(futures::stream::iter)
+        .filter(|e| {
+            let is_empty = matches!(
+                e,
+                Ok(ModelEvent {
+                    event: ModelEventType::LlmContent(content),
+                    ..
+                }) if content.content.is_empty()
+            );
+            futures::future::ready(!is_empty)
+        })
+        .scan(false, |tool_call_deltas_streamed, e| {
+            futures::future::ready(Some(map_event(e, tool_call_deltas_streamed)))
+        })
+        .filter_map(futures::future::ready);
+
+    Ok(wrap_stream(with_final_usage(
+        Box::pin(event_stream),
+        include_usage,
+    )))
+}
+
+/// Removes usage from the chunks and, with `include_usage`, sends the usage summed over
+/// all model calls as a final chunk, as OpenAI does for `stream_options.include_usage`.
+/// An error is the last item of the stream.
+fn with_final_usage<S>(
+    stream: S,
+    include_usage: bool,
+) -> impl Stream<Item = Result<SSOChatEvent, GatewayApiError>>
+where
+    S: Stream<Item = Result<SSOChatEvent, GatewayApiError>> + Unpin,
+{
+    futures::stream::unfold(
+        (stream, Some(None::<CompletionModelUsage>)),
+        move |(mut stream, total)| async move {
+            // Ends after the final usage chunk
+            let mut total = total?;
+            match stream.next().await {
+                Some(Ok((delta, usage, finish_reason))) => {
+                    if let Some(usage) = usage {
+                        match total.as_mut() {
+                            Some(total) => total.add(&usage),
+                            None => total = Some(usage),
+                        }
                     }
-                    _ => Err(GatewayApiError::CustomError(
-                        "Unsupported event".to_string(),
-                    )),
-                },
-                Err(e) => {
-                    tracing::error!("Error in event: {e}");
-                    Err(e)
+                    Some((Ok((delta, None, finish_reason)), (stream, Some(total))))
+                }
+                Some(Err(e)) => Some((Err(e), (stream, None))),
+                None => {
+                    let usage = total.filter(|_| include_usage)?;
+                    Some((Ok((None, Some(usage), None)), (stream, None)))
+                }
+            }
+        },
+    )
+}
+
+/// Maps a model event to a client chunk. Once tool call fragments were streamed, complete
+/// tool calls are not sent again, the finish chunk only carries the finish reason.
+fn map_event(
+    e: Result<ModelEvent, GatewayApiError>,
+    tool_call_deltas_streamed: &mut bool,
+) -> Option<Result<SSOChatEvent, GatewayApiError>> {
+    let e = match e {
+        Ok(e) => e,
+        Err(e) => {
+            tracing::error!("Error in event: {e}");
+            return Some(Err(e));
+        }
+    };
+
+    match e.event {
+        ModelEventType::LlmContent(content) => Some(Ok((
+            Some(ChatCompletionDelta {
+                role: Some("assistant".to_string()),
+                content: Some(content.content),
+                tool_calls: None,
+                logprobs: content.logprobs,
+            }),
+            None,
+            None,
+        ))),
+        ModelEventType::ToolCallDelta(delta) => {
+            *tool_call_deltas_streamed = true;
+            Some(Ok((
+                Some(ChatCompletionDelta {
+                    role: Some("assistant".to_string()),
+                    content: None,
+                    tool_calls: Some(vec![ToolCall {
+                        index: Some(delta.index),
+                        id: delta.tool_id.unwrap_or_default(),
+                        r#type: "function".into(),
+                        function: FunctionCall {
+                            name: delta.tool_name.unwrap_or_default(),
+                            arguments: delta.arguments,
+                        },
+                    }]),
+                    logprobs: None,
+                }),
+                None,
+                None,
+            )))
+        }
+        ModelEventType::ToolStart(_) if *tool_call_deltas_streamed => None,
+        ModelEventType::ToolStart(tool_call) => Some(Ok((
+            Some(ChatCompletionDelta {
+                role: Some("assistant".to_string()),
+                content: None,
+                tool_calls: Some(vec![ToolCall {
+                    index: Some(0),
+                    id: tool_call.tool_id.clone(),
+                    r#type: "function".into(),
+                    function: FunctionCall {
+                        name: tool_call.tool_name.clone(),
+                        arguments: tool_call.input.clone(),
+                    },
+                }]),
+                logprobs: None,
+            }),
+            None,
+            None,
+        ))),
+        ModelEventType::LlmStop(LLMFinishEvent {
+            usage,
+            finish_reason,
+            tool_calls,
+            ..
+        }) => {
+            let ev = match finish_reason {
+                ModelFinishReason::ToolCalls if !*tool_call_deltas_streamed => {
+                    Some(tool_calls_delta(&tool_calls))
+                }
+                _ => None,
+            };
+            *tool_call_deltas_streamed = false;
+
+            Some(Ok((ev, usage, Some(finish_reason.to_string()))))
+        }
+        _ => Some(Err(GatewayApiError::CustomError(
+            "Unsupported event".to_string(),
+        ))),
+    }
+}
+
+/// Finish event of a provider call that ended without one, cancelled by a client disconnect
+/// or failed mid-stream, with the usage estimated from the prompt and the content streamed
+/// so far
+fn estimated_stop(
+    start: &LLMStartEvent,
+    output: &str,
+    finish_reason: &str,
+    credentials_ident: CredentialsIdent,
+) -> LLMFinishEvent {
+    LLMFinishEvent {
+        provider_name: start.provider_name.clone(),
+        model_name: start.model_name.clone(),
+        output: Some(output.to_string()),
+        usage: Some(estimate_stream_usage(start, output, &[])),
+        finish_reason: ModelFinishReason::Other(finish_reason.to_string()),
+        tool_calls: vec![],
+        credentials_ident,
+        generation: None,
+    }
+}
+
+/// Usage of a streamed call estimated for the tokenizer family of the model
+fn estimate_stream_usage(
+    start: &LLMStartEvent,
+    output: &str,
+    tool_calls: &[ModelToolCall],
+) -> CompletionModelUsage {
+    let mut output = output.to_string();
+    for tool_call in tool_calls {
+        output.push_str(&tool_call.tool_name);
+        output.push_str(&tool_call.input);
+    }
+    TokenizerFamily::for_model(&start.provider_name, &start.model_name)
+        .estimate_usage(&start.input, &output)
+}
+
+/// Events forwarded to the client. With `ordered_tool_calls` incremental tool call
+/// events are skipped, tool calls are only sent with the finish event.
+fn is_streamed_event(event: &ModelEventType, ordered_tool_calls: bool) -> bool {
+    match event {
+        ModelEventType::LlmContent(_) | ModelEventType::LlmStop(_) => true,
+        ModelEventType::ToolStart(_) | ModelEventType::ToolCallDelta(_) => !ordered_tool_calls,
+        _ => false,
+    }
+}
+
+fn tool_calls_delta(tool_calls: &[ModelToolCall]) -> ChatCompletionDelta {
+    ChatCompletionDelta {
+        role: Some("assistant".to_string()),
+        content: None,
+        tool_calls: Some(
+            tool_calls
+                .iter()
+                .enumerate()
+                .map(|(index, tc)| ToolCall {
+                    index: Some(index),
+                    id: tc.tool_id.clone(),
+                    r#type: "function".into(),
+                    function: FunctionCall {
+                        name: tc.tool_name.clone(),
+                        arguments: tc.input.clone(),
+                    },
+                })
+                .collect(),
+        ),
+        logprobs: None,
+    }
+}
+
+fn apply_transforms(
+    transforms: &mut StreamTransformPipeline,
+    event: Result<ModelEvent, GatewayApiError>,
+) -> Vec<Result<ModelEvent, GatewayApiError>> {
+    if transforms.is_empty() {
+        return vec![event];
+    }
+
+    match event {
+        Ok(mut model_event) => match &mut model_event.event {
+            ModelEventType::LlmContent(content) => {
+                content.content = transforms.push(&content.content);
+                vec![Ok(model_event)]
+            }
+            ModelEventType::LlmStop(_) | ModelEventType::ToolStart(_) => {
+                let tail = transforms.finish();
+                let mut events = vec![];
+                if !tail.is_empty() {
+                    events.push(Ok(ModelEvent {
+                        event: ModelEventType::LlmContent(LLMContentEvent {
+                            content: tail,
+                            logprobs: None,
+                        }),
+                        ..model_event.clone()
+                    }));
                 }
+                events.push(Ok(model_event));
+                events
             }
+            _ => vec![Ok(model_event)],
+        },
+        Err(e) => vec![Err(e)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::types::{ToolCallDeltaEvent, ToolStartEvent};
+
+    fn tool_call(id: &str) -> ModelToolCall {
+        ModelToolCall {
+            tool_id: id.to_string(),
+            tool_name: format!("tool_{id}"),
+            input: "{}".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_disconnected_stop_estimates_usage() {
+        let start = LLMStartEvent {
+            provider_name: "openai".to_string(),
+            model_name: "gpt-4o".to_string(),
+            input: "a".repeat(400),
+        };
+
+        let stop = estimated_stop(
+            &start,
+            &"b".repeat(80),
+            "client_disconnected",
+            CredentialsIdent::Own,
+        );
+        // Counted with the o200k tokenizer family of gpt-4o
+        let usage = stop.usage.unwrap();
+        assert_eq!(usage.input_tokens, 90);
+        assert_eq!(usage.output_tokens, 18);
+        assert_eq!(usage.total_tokens, 108);
+        assert!(usage.is_estimated);
+        assert_eq!(stop.finish_reason.to_string(), "client_disconnected");
+        assert_eq!(stop.model_name, "gpt-4o");
+    }
+
+    #[test]
+    fn test_ordered_tool_calls_skip_incremental_events() {
+        let event = ModelEventType::ToolStart(ToolStartEvent {
+            tool_id: "call_1".to_string(),
+            tool_name: "search".to_string(),
+            input: "{}".to_string(),
         });
 
-    Ok(wrap_stream(event_stream))
+        assert!(is_streamed_event(&event, false));
+        assert!(!is_streamed_event(&event, true));
+    }
+
+    #[test]
+    fn test_tool_calls_delta_stable_order() {
+        let tool_calls = vec![tool_call("a"), tool_call("b"), tool_call("c")];
+
+        for _ in 0..10 {
+            let delta = tool_calls_delta(&tool_calls);
+            let calls = delta.tool_calls.unwrap();
+            assert_eq!(
+                calls
+                    .iter()
+                    .map(|c| (c.index, c.id.as_str()))
+                    .collect::<Vec<_>>(),
+                vec![(Some(0), "a"), (Some(1), "b"), (Some(2), "c")]
+            );
+        }
+    }
+
+    fn event(event: ModelEventType) -> Result<ModelEvent, GatewayApiError> {
+        Ok(ModelEvent::new(&Span::none(), event))
+    }
+
+    fn delta(index: usize, id: Option<&str>, arguments: &str) -> ModelEventType {
+        ModelEventType::ToolCallDelta(ToolCallDeltaEvent {
+            index,
+            tool_id: id.map(|id| id.to_string()),
+            tool_name: id.map(|id| format!("tool_{id}")),
+            arguments: arguments.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_parallel_tool_call_deltas() {
+        let events = vec![
+            delta(0, Some("a"), ""),
+            delta(1, Some("b"), ""),
+            delta(0, None, "{\"city\":"),
+            delta(1, None, "{\"q\":"),
+            delta(0, None, "\"Paris\"}"),
+            delta(1, None, "\"x\"}"),
+            ModelEventType::ToolStart(ToolStartEvent {
+                tool_id: "a".to_string(),
+                tool_name: "tool_a".to_string(),
+                input: "{\"city\":\"Paris\"}".to_string(),
+            }),
+            ModelEventType::LlmStop(LLMFinishEvent {
+                provider_name: "openai".to_string(),
+                model_name: "gpt-4o".to_string(),
+                output: None,
+                usage: None,
+                finish_reason: ModelFinishReason::ToolCalls,
+                tool_calls: vec![tool_call("a"), tool_call("b")],
+                credentials_ident: CredentialsIdent::Own,
+                generation: None,
+            }),
+        ];
+
+        let mut streamed = false;
+        let chunks: Vec<SSOChatEvent> = events
+            .into_iter()
+            .filter_map(|e| map_event(event(e), &mut streamed))
+            .map(|c| c.unwrap())
+            .collect();
+
+        // The complete tool call is not repeated after its fragments
+        assert_eq!(chunks.len(), 7);
+        let mut arguments = [String::new(), String::new()];
+        for (delta, _, _) in &chunks[..6] {
+            let call = &delta.as_ref().unwrap().tool_calls.as_ref().unwrap()[0];
+            arguments[call.index.unwrap()].push_str(&call.function.arguments);
+        }
+        assert_eq!(arguments[0], "{\"city\":\"Paris\"}");
+        assert_eq!(arguments[1], "{\"q\":\"x\"}");
+
+        let (delta, _, finish_reason) = &chunks[6];
+        assert!(delta.is_none());
+        assert_eq!(finish_reason.as_deref(), Some("tool_calls"));
+    }
+
+    #[tokio::test]
+    async fn test_final_usage_chunk() {
+        let usage = |input_tokens, output_tokens| CompletionModelUsage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+            ..Default::default()
+        };
+        let chunks = || {
+            futures::stream::iter(vec![
+                Ok((None, Some(usage(10, 2)), Some("tool_calls".to_string()))),
+                Ok((None, Some(usage(20, 5)), Some("stop".to_string()))),
+            ])
+        };
+
+        let events: Vec<_> = with_final_usage(chunks(), true).collect().await;
+        assert_eq!(events.len(), 3);
+        assert!(events[..2]
+            .iter()
+            .all(|e| matches!(e, Ok((_, None, Some(_))))));
+        let Ok((None, Some(total), None)) = &events[2] else {
+            panic!("expected a final usage chunk");
+        };
+        assert_eq!(
+            (total.input_tokens, total.output_tokens, total.total_tokens),
+            (30, 7, 37)
+        );
+
+        let events: Vec<_> = with_final_usage(chunks(), false).collect().await;
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| matches!(e, Ok((_, None, _)))));
+    }
+
+    /// Streams two tokens, then fails like a dropped provider connection
+    struct FailingModel;
+
+    #[async_trait::async_trait]
+    impl ModelInstance for FailingModel {
+        async fn invoke(
+            &self,
+            _input_vars: HashMap<String, serde_json::Value>,
+            _tx: tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+            _previous_messages: Vec<Message>,
+            _tags: HashMap<String, String>,
+        ) -> crate::GatewayResult<crate::types::gateway::ChatCompletionMessage> {
+            Err(crate::GatewayError::CustomError(
+                "FailingModel is only streamed".to_string(),
+            ))
+        }
+
+        async fn stream(
+            &self,
+            _input_vars: HashMap<String, serde_json::Value>,
+            tx: tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+            _previous_messages: Vec<Message>,
+            _tags: HashMap<String, String>,
+        ) -> crate::GatewayResult<()> {
+            let start = ModelEventType::LlmStart(LLMStartEvent {
+                provider_name: "openai".to_string(),
+                model_name: "gpt-4o".to_string(),
+                input: "a".repeat(40),
+            });
+            let content = |content: &str| {
+                ModelEventType::LlmContent(LLMContentEvent {
+                    content: content.to_string(),
+                    logprobs: None,
+                })
+            };
+            for event in [start, content("Hello "), content("wor")] {
+                tx.send(Some(ModelEvent::new(&Span::none(), event)))
+                    .await
+                    .unwrap();
+            }
+            Err(crate::GatewayError::CustomError(
+                "connection reset".to_string(),
+            ))
+        }
+    }
+
+    fn definition() -> CompletionModelDefinition {
+        use crate::types::engine::{
+            CompletionEngineParams, CompletionModelParams, Model, ModelTools, ModelType, Prompt,
+        };
+
+        CompletionModelDefinition {
+            name: "openai/gpt-4o".to_string(),
+            model_params: CompletionModelParams {
+                engine: CompletionEngineParams::OpenAi {
+                    params: Default::default(),
+                    execution_options: Default::default(),
+                    credentials: None,
+                    endpoint: None,
+                },
+                provider_name: "openai".to_string(),
+                prompt_name: None,
+            },
+            prompt: Prompt::empty(),
+            tools: ModelTools(vec![]),
+            db_model: Model {
+                name: "gpt-4o".to_string(),
+                description: None,
+                provider_name: "openai".to_string(),
+                prompt_name: None,
+                model_params: HashMap::new(),
+                tools: ModelTools(vec![]),
+                model_type: ModelType::Completions,
+                response_schema: None,
+                credentials: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mid_stream_error_frame() {
+        let (events_tx, mut events_rx) = tokio::sync::broadcast::channel(16);
+        let stream = stream_chunks(
+            definition(),
+            Box::new(FailingModel),
+            vec![],
+            Arc::new(CallbackHandlerFn(Some(events_tx), None)),
+            HashMap::new(),
+            HashMap::new(),
+            StreamCacheContext::default(),
+            StreamTransformPipeline::default(),
+            false,
+            true,
+        )
+        .await
+        .unwrap();
+
+        let frames: Vec<String> = stream
+            .map(|e| {
+                let bytes = crate::handler::chat::map_sso_event(e, "gpt-4o".to_string());
+                String::from_utf8(bytes.unwrap().to_vec()).unwrap()
+            })
+            .collect()
+            .await;
+
+        // Partial content, then the error as the last frame
+        assert_eq!(frames.len(), 3);
+        assert!(frames[0].contains("\"content\":\"Hello \""));
+        assert!(frames[1].contains("\"content\":\"wor\""));
+        let error: serde_json::Value =
+            serde_json::from_str(frames[2].strip_prefix("data: ").unwrap().trim()).unwrap();
+        assert_eq!(error["error"]["type"], "server_error");
+        assert!(error["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("connection reset"));
+
+        // The partial usage is recorded
+        let mut stop = None;
+        while let Ok(event) = events_rx.try_recv() {
+            if let ModelEventType::LlmStop(event) = event.event.event {
+                stop = Some(event);
+            }
+        }
+        let stop = stop.expect("expected a finish event");
+        assert_eq!(stop.finish_reason.to_string(), "error");
+        assert_eq!(stop.output.as_deref(), Some("Hello wor"));
+        assert_eq!(stop.usage.unwrap().input_tokens, 9);
+    }
+
+    /// Streams a complete response without reporting its usage
+    struct UsagelessModel;
+
+    #[async_trait::async_trait]
+    impl ModelInstance for UsagelessModel {
+        async fn invoke(
+            &self,
+            _input_vars: HashMap<String, serde_json::Value>,
+            _tx: tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+            _previous_messages: Vec<Message>,
+            _tags: HashMap<String, String>,
+        ) -> crate::GatewayResult<crate::types::gateway::ChatCompletionMessage> {
+            Err(crate::GatewayError::CustomError(
+                "UsagelessModel is only streamed".to_string(),
+            ))
+        }
+
+        async fn stream(
+            &self,
+            _input_vars: HashMap<String, serde_json::Value>,
+            tx: tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+            _previous_messages: Vec<Message>,
+            _tags: HashMap<String, String>,
+        ) -> crate::GatewayResult<()> {
+            let events = [
+                ModelEventType::LlmStart(LLMStartEvent {
+                    provider_name: "anthropic".to_string(),
+                    model_name: "claude-3-5-sonnet".to_string(),
+                    input: "a".repeat(70),
+                }),
+                ModelEventType::LlmContent(LLMContentEvent {
+                    content: "b".repeat(35),
+                    logprobs: None,
+                }),
+                ModelEventType::LlmStop(LLMFinishEvent {
+                    provider_name: "anthropic".to_string(),
+                    model_name: "claude-3-5-sonnet".to_string(),
+                    output: None,
+                    usage: None,
+                    finish_reason: ModelFinishReason::Stop,
+                    tool_calls: vec![],
+                    credentials_ident: CredentialsIdent::Own,
+                    generation: None,
+                }),
+            ];
+            for event in events {
+                tx.send(Some(ModelEvent::new(&Span::none(), event)))
+                    .await
+                    .unwrap();
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_usage_is_estimated() {
+        let (events_tx, mut events_rx) = tokio::sync::broadcast::channel(16);
+        let stream = stream_chunks(
+            definition(),
+            Box::new(UsagelessModel),
+            vec![],
+            Arc::new(CallbackHandlerFn(Some(events_tx), None)),
+            HashMap::new(),
+            HashMap::new(),
+            StreamCacheContext::default(),
+            StreamTransformPipeline::default(),
+            false,
+            true,
+        )
+        .await
+        .unwrap();
+        let events: Vec<_> = stream.collect().await;
+
+        // The client receives the estimate as the usage of the stream
+        let Some(Ok((None, Some(usage), None))) = events.last() else {
+            panic!("expected a final usage chunk");
+        };
+        assert_eq!((usage.input_tokens, usage.output_tokens), (20, 10));
+        assert!(usage.is_estimated);
+
+        let mut stop = None;
+        while let Ok(event) = events_rx.try_recv() {
+            if let ModelEventType::LlmStop(event) = event.event.event {
+                stop = Some(event);
+            }
+        }
+        assert!(stop.unwrap().usage.unwrap().is_estimated);
+    }
 }
@@ -0,0 +1,207 @@
+use crate::types::gateway::{ReplaceRule, StreamTransformDefinition};
+
+/// A rewrite applied to streamed content. Implementations consume as much of
+/// `buffer` as they can safely rewrite and leave the tail that could still be
+/// the start of a match. When `flush` is set the whole buffer must be consumed.
+pub trait StreamTransform: Send + Sync {
+    fn apply(&self, buffer: &mut String, flush: bool) -> String;
+}
+
+/// Replaces terms in the stream, scanning left to right with the first
+/// matching rule winning at each position.
+pub struct ReplaceTransform {
+    rules: Vec<ReplaceRule>,
+    case_insensitive: bool,
+}
+
+impl ReplaceTransform {
+    pub fn new(rules: Vec<ReplaceRule>, case_insensitive: bool) -> Self {
+        let rules = rules.into_iter().filter(|r| !r.from.is_empty()).collect();
+        Self {
+            rules,
+            case_insensitive,
+        }
+    }
+
+    /// Rewrites any case variant of `terms` to its canonical casing
+    pub fn casing(terms: Vec<String>) -> Self {
+        Self::new(
+            terms
+                .into_iter()
+                .map(|t| ReplaceRule {
+                    from: t.clone(),
+                    to: t,
+                })
+                .collect(),
+            true,
+        )
+    }
+
+    fn starts_with(&self, text: &str, pattern: &str) -> bool {
+        match text.get(..pattern.len()) {
+            Some(prefix) if self.case_insensitive => prefix.eq_ignore_ascii_case(pattern),
+            Some(prefix) => prefix == pattern,
+            None => false,
+        }
+    }
+
+    fn is_partial_match(&self, text: &str, pattern: &str) -> bool {
+        text.len() < pattern.len()
+            && match pattern.get(..text.len()) {
+                Some(prefix) if self.case_insensitive => prefix.eq_ignore_ascii_case(text),
+                Some(prefix) => prefix == text,
+                None => false,
+            }
+    }
+}
+
+impl StreamTransform for ReplaceTransform {
+    fn apply(&self, buffer: &mut String, flush: bool) -> String {
+        let mut output = String::with_capacity(buffer.len());
+        let mut position = 0;
+
+        'scan: while position < buffer.len() {
+            let rest = &buffer[position..];
+            for rule in &self.rules {
+                if self.starts_with(rest, &rule.from) {
+                    output.push_str(&rule.to);
+                    position += rule.from.len();
+                    continue 'scan;
+                }
+            }
+
+            if !flush
+                && self
+                    .rules
+                    .iter()
+                    .any(|r| self.is_partial_match(rest, &r.from))
+            {
+                break;
+            }
+
+            let ch = rest.chars().next().expect("rest is not empty");
+            output.push(ch);
+            position += ch.len_utf8();
+        }
+
+        buffer.drain(..position);
+        output
+    }
+}
+
+impl From<&StreamTransformDefinition> for Box<dyn StreamTransform> {
+    fn from(definition: &StreamTransformDefinition) -> Self {
+        match definition {
+            StreamTransformDefinition::Replace {
+                rules,
+                case_insensitive,
+            } => Box::new(ReplaceTransform::new(rules.clone(), *case_insensitive)),
+            StreamTransformDefinition::Casing { terms } => {
+                Box::new(ReplaceTransform::casing(terms.clone()))
+            }
+        }
+    }
+}
+
+/// Ordered chain of transforms. Each stage keeps its own held-back tail so a
+/// match spanning chunk boundaries is rewritten once the rest arrives.
+#[derive(Default)]
+pub struct StreamTransformPipeline {
+    stages: Vec<(Box<dyn StreamTransform>, String)>,
+}
+
+impl StreamTransformPipeline {
+    pub fn new(transforms: Vec<Box<dyn StreamTransform>>) -> Self {
+        Self {
+            stages: transforms.into_iter().map(|t| (t, String::new())).collect(),
+        }
+    }
+
+    pub fn from_definitions(definitions: &[StreamTransformDefinition]) -> Self {
+        Self::new(definitions.iter().map(Into::into).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.run(chunk.to_string(), false)
+    }
+
+    /// Releases everything still held back by the stages
+    pub fn finish(&mut self) -> String {
+        self.run(String::new(), true)
+    }
+
+    fn run(&mut self, mut text: String, flush: bool) -> String {
+        for (transform, buffer) in self.stages.iter_mut() {
+            buffer.push_str(&text);
+            text = transform.apply(buffer, flush);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(from: &str, to: &str) -> ReplaceRule {
+        ReplaceRule {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    fn run(pipeline: &mut StreamTransformPipeline, chunks: &[&str]) -> String {
+        let mut output: String = chunks.iter().map(|c| pipeline.push(c)).collect();
+        output.push_str(&pipeline.finish());
+        output
+    }
+
+    #[test]
+    fn test_replacement_spanning_two_chunks() {
+        let mut pipeline = StreamTransformPipeline::new(vec![Box::new(ReplaceTransform::new(
+            vec![rule("ChatGPT", "our assistant")],
+            false,
+        ))]);
+
+        assert_eq!(pipeline.push("Ask Cha"), "Ask ");
+        assert_eq!(pipeline.push("tGPT now"), "our assistant now");
+        assert_eq!(pipeline.finish(), "");
+    }
+
+    #[test]
+    fn test_partial_match_released_on_finish() {
+        let mut pipeline = StreamTransformPipeline::new(vec![Box::new(ReplaceTransform::new(
+            vec![rule("ChatGPT", "our assistant")],
+            false,
+        ))]);
+
+        assert_eq!(run(&mut pipeline, &["Hello Ch", "at"]), "Hello Chat");
+    }
+
+    #[test]
+    fn test_casing_spanning_chunks() {
+        let mut pipeline =
+            StreamTransformPipeline::new(vec![Box::new(ReplaceTransform::casing(vec![
+                "LangDB".to_string()
+            ]))]);
+
+        assert_eq!(
+            run(&mut pipeline, &["use lan", "gdb and LANG", "DB"]),
+            "use LangDB and LangDB"
+        );
+    }
+
+    #[test]
+    fn test_transforms_are_applied_in_order() {
+        let mut pipeline = StreamTransformPipeline::new(vec![
+            Box::new(ReplaceTransform::new(vec![rule("foo", "bar")], false)),
+            Box::new(ReplaceTransform::new(vec![rule("bar baz", "qux")], false)),
+        ]);
+
+        assert_eq!(run(&mut pipeline, &["f", "oo b", "az!"]), "qux!");
+    }
+}
@@ -1,7 +1,13 @@
 use crate::handler::chat::SSOChatEvent;
 use crate::GatewayApiError;
-use futures::Stream;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
 use std::pin::Pin;
+use std::time::Duration;
+
+/// SSE comment, ignored by clients as it carries no data
+const KEEP_ALIVE: &str = ": keep-alive\n\n";
 
 /// Type alias for the concrete stream type returned by chat completion functions
 pub type ChatCompletionStream =
@@ -14,3 +20,71 @@ where
 {
     Box::pin(stream)
 }
+
+/// Keeps streamed responses alive through proxies that drop idle connections while the
+/// model has not produced its first token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamKeepAliveConfig {
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+}
+
+fn default_interval_ms() -> u64 {
+    15_000
+}
+
+impl StreamKeepAliveConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.max(1))
+    }
+}
+
+/// Sends a keep-alive comment every `interval` until the stream yields its first item
+pub fn with_keep_alive<S>(
+    stream: S,
+    interval: Duration,
+) -> impl Stream<Item = Result<Bytes, GatewayApiError>>
+where
+    S: Stream<Item = Result<Bytes, GatewayApiError>> + Unpin,
+{
+    futures::stream::unfold((stream, false), move |(mut stream, started)| async move {
+        if started {
+            return stream.next().await.map(|item| (item, (stream, true)));
+        }
+        tokio::select! {
+            item = stream.next() => item.map(|item| (item, (stream, true))),
+            _ = tokio::time::sleep(interval) => {
+                Some((Ok(Bytes::from(KEEP_ALIVE)), (stream, false)))
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keep_alive_until_first_item() {
+        let events = futures::stream::iter([(100, "data: a\n\n"), (60, "data: b\n\n")]).then(
+            |(delay_ms, data)| async move {
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                Ok(Bytes::from(data))
+            },
+        );
+
+        let items: Vec<Bytes> = with_keep_alive(Box::pin(events), Duration::from_millis(20))
+            .map(|item| item.unwrap())
+            .collect()
+            .await;
+
+        // Comments only while waiting for the first item, none in the gap before the second
+        let (heartbeats, data) = items.split_at(items.len() - 2);
+        assert!(heartbeats.len() >= 2);
+        assert!(heartbeats.iter().all(|item| item == KEEP_ALIVE));
+        assert_eq!(
+            data,
+            [Bytes::from("data: a\n\n"), Bytes::from("data: b\n\n")]
+        );
+    }
+}
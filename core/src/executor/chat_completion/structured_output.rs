@@ -0,0 +1,191 @@
+use async_openai::types::ResponseFormat;
+use jsonschema::Validator;
+use serde_json::Value;
+
+use crate::types::gateway::{
+    ChatCompletionContent, ChatCompletionMessage, ChatCompletionRequest,
+    ChatCompletionRequestWithTools,
+};
+use crate::types::provider::InferenceModelProvider;
+
+const SCHEMA_INSTRUCTION: &str = "Reply with only a JSON value matching the JSON schema below, \
+without code fences or any other text.";
+
+/// Whether the provider constrains the output to the requested schema itself
+pub fn has_native_support(provider: &InferenceModelProvider) -> bool {
+    matches!(
+        provider,
+        InferenceModelProvider::OpenAI
+            | InferenceModelProvider::Gemini
+            | InferenceModelProvider::Proxy(_)
+    )
+}
+
+/// Schema of a request with `response_format: {type: "json_schema"}` that has to be
+/// enforced by the gateway, as the provider has no structured output support
+pub fn emulated_schema(
+    request: &ChatCompletionRequest,
+    provider: &InferenceModelProvider,
+) -> Result<Option<OutputSchema>, String> {
+    match &request.response_format {
+        Some(ResponseFormat::JsonSchema { json_schema }) if !has_native_support(provider) => {
+            let schema = json_schema
+                .schema
+                .clone()
+                .ok_or_else(|| format!("json_schema {} has no schema", json_schema.name))?;
+            OutputSchema::new(schema).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+pub struct OutputSchema {
+    schema: Value,
+    validator: Validator,
+}
+
+impl OutputSchema {
+    pub fn new(schema: Value) -> Result<Self, String> {
+        let validator =
+            jsonschema::validator_for(&schema).map_err(|e| format!("Invalid JSON schema: {e}"))?;
+        Ok(Self { schema, validator })
+    }
+
+    /// Parses the output, tolerating a markdown code fence, and checks it against the schema
+    pub fn validate(&self, output: &str) -> Result<Value, String> {
+        let value = serde_json::from_str::<Value>(strip_code_fence(output))
+            .map_err(|e| format!("response is not valid JSON: {e}"))?;
+        let errors = self
+            .validator
+            .iter_errors(&value)
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(value)
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// Describes the schema in the system prompt in place of `response_format`
+    pub fn instructed_request<T: Clone>(
+        &self,
+        request: &ChatCompletionRequestWithTools<T>,
+    ) -> ChatCompletionRequestWithTools<T> {
+        let mut instructed = request.clone();
+        instructed.request.response_format = None;
+
+        let instruction = format!(
+            "{SCHEMA_INSTRUCTION}\n\nSchema:\n{}",
+            serde_json::to_string_pretty(&self.schema).unwrap_or_default()
+        );
+        let messages = &mut instructed.request.messages;
+        // Providers without structured output only read the first system message
+        match messages.first_mut() {
+            Some(ChatCompletionMessage {
+                role,
+                content: Some(ChatCompletionContent::Text(text)),
+                ..
+            }) if role == "system" => {
+                text.push_str("\n\n");
+                text.push_str(&instruction);
+            }
+            _ => messages.insert(
+                0,
+                ChatCompletionMessage::new_text("system".to_string(), instruction),
+            ),
+        }
+        instructed
+    }
+}
+
+/// Follow up request with the invalid output and why it was rejected
+pub fn corrective_request<T: Clone>(
+    request: &ChatCompletionRequestWithTools<T>,
+    output: &str,
+    error: &str,
+) -> ChatCompletionRequestWithTools<T> {
+    let mut corrective = request.clone();
+    corrective.request.messages.extend([
+        ChatCompletionMessage::new_text("assistant".to_string(), output.to_string()),
+        ChatCompletionMessage::new_text(
+            "user".to_string(),
+            format!(
+                "Your reply does not match the JSON schema: {error}. Reply again with only \
+                 the corrected JSON."
+            ),
+        ),
+    ]);
+    corrective
+}
+
+fn strip_code_fence(output: &str) -> &str {
+    let output = output.trim();
+    output
+        .strip_prefix("```")
+        .and_then(|o| o.strip_suffix("```"))
+        .map(|o| o.strip_prefix("json").unwrap_or(o).trim())
+        .unwrap_or(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> OutputSchema {
+        OutputSchema::new(json!({
+            "type": "object",
+            "properties": {
+                "city": {"type": "string"},
+                "population": {"type": "integer"}
+            },
+            "required": ["city", "population"]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema = schema();
+        assert_eq!(
+            schema.validate(r#"{"city": "Paris", "population": 2100000}"#),
+            Ok(json!({"city": "Paris", "population": 2100000}))
+        );
+        assert!(schema
+            .validate("```json\n{\"city\": \"Paris\", \"population\": 1}\n```")
+            .is_ok());
+        assert!(schema.validate(r#"{"city": "Paris"}"#).is_err());
+        assert!(schema
+            .validate("The city is Paris")
+            .unwrap_err()
+            .starts_with("response is not valid JSON"));
+    }
+
+    #[test]
+    fn test_instructed_request() {
+        let mut request = ChatCompletionRequestWithTools::<()>::default();
+        request.request.messages = vec![
+            ChatCompletionMessage::new_text("system".to_string(), "Be brief.".to_string()),
+            ChatCompletionMessage::new_text("user".to_string(), "Largest city?".to_string()),
+        ];
+
+        let instructed = schema().instructed_request(&request);
+        assert_eq!(instructed.request.messages.len(), 2);
+        let system = instructed.request.messages[0]
+            .content
+            .as_ref()
+            .and_then(|c| c.as_string())
+            .unwrap();
+        assert!(system.starts_with("Be brief.\n\nReply with only a JSON value"));
+
+        request.request.messages.remove(0);
+        let instructed = schema().instructed_request(&request);
+        assert_eq!(instructed.request.messages.len(), 2);
+        assert_eq!(instructed.request.messages[0].role, "system");
+
+        let corrective = corrective_request(&instructed, "Paris", "not JSON");
+        assert_eq!(corrective.request.messages.len(), 4);
+        assert_eq!(corrective.request.messages[2].role, "assistant");
+    }
+}
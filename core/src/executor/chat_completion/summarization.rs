@@ -0,0 +1,211 @@
+use std::fmt::Debug;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::Span;
+
+use crate::executor::chat_completion::delegate::execute_nested;
+use crate::executor::context::ExecutorContext;
+use crate::handler::find_model_by_full_name;
+use crate::model::token_estimate::TokenizerFamily;
+use crate::routing::RoutingStrategy;
+use crate::types::gateway::{
+    ChatCompletionContent, ChatCompletionMessage, ChatCompletionRequest,
+    ChatCompletionRequestWithTools, ConversationSummarization,
+};
+use crate::GatewayApiError;
+
+const SUMMARY_INSTRUCTION: &str = "Summarize the conversation below so it can replace the \
+original messages. Keep facts, decisions, open questions, names and numbers. Write the summary \
+in the third person and do not add information that is not in the conversation.";
+
+pub fn estimate_tokens(messages: &[ChatCompletionMessage]) -> usize {
+    messages
+        .iter()
+        .map(|m| TokenizerFamily::Other.estimate(&message_text(m)) as usize + 1)
+        .sum()
+}
+
+pub(crate) fn message_text(message: &ChatCompletionMessage) -> String {
+    match &message.content {
+        Some(ChatCompletionContent::Text(text)) => text.clone(),
+        Some(ChatCompletionContent::Content(parts)) => parts
+            .iter()
+            .filter_map(|p| p.text.clone())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => String::new(),
+    }
+}
+
+/// Range of messages replaced by the summary. Leading system messages and the most recent
+/// messages are kept, and tool results stay next to the assistant message that called them.
+pub fn summarization_range(
+    messages: &[ChatCompletionMessage],
+    keep_recent: usize,
+) -> Option<std::ops::Range<usize>> {
+    let start = messages.iter().take_while(|m| m.role == "system").count();
+    let mut end = messages.len().saturating_sub(keep_recent);
+    while end > start && end < messages.len() && messages[end].role == "tool" {
+        end -= 1;
+    }
+
+    // A single message is not worth a summary
+    (end > start + 1).then_some(start..end)
+}
+
+/// Replaces older messages with a summary written by `summarization.model` when the
+/// conversation exceeds the configured share of the model context window
+pub async fn summarize_conversation<T: Serialize + DeserializeOwned + Debug + Clone>(
+    request_with_tools: &ChatCompletionRequestWithTools<T>,
+    executor_context: &ExecutorContext,
+) -> Result<Option<ChatCompletionRequestWithTools<T>>, GatewayApiError> {
+    let Some(summarization) = request_with_tools
+        .extra
+        .as_ref()
+        .and_then(|e| e.summarization.as_ref())
+    else {
+        return Ok(None);
+    };
+
+    let llm_model = find_model_by_full_name(
+        &request_with_tools.request.model,
+        &executor_context.provided_models,
+    )?;
+    let context_size = llm_model.limits.max_context_size as usize;
+    let messages = &request_with_tools.request.messages;
+    let estimated_tokens = estimate_tokens(messages);
+    if context_size == 0
+        || (estimated_tokens as f32) < context_size as f32 * summarization.threshold
+    {
+        return Ok(None);
+    }
+
+    let Some(range) = summarization_range(messages, summarization.keep_recent) else {
+        return Ok(None);
+    };
+
+    let summary_request = summary_request(summarization, &messages[range.clone()]);
+    let response = execute_nested(&summary_request, executor_context).await?;
+    let summary = response
+        .choices
+        .first()
+        .and_then(|c| c.message.content.as_ref())
+        .map(|c| match c {
+            ChatCompletionContent::Text(text) => text.clone(),
+            ChatCompletionContent::Content(_) => String::new(),
+        })
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| GatewayApiError::CustomError("Summarizer returned no content".into()))?;
+
+    executor_context.emit_custom(
+        &Span::current(),
+        "conversation_summarized",
+        serde_json::json!({
+            "model": summarization.model,
+            "summarized_messages": range.len(),
+            "estimated_tokens": estimated_tokens,
+            "usage": response.usage,
+        }),
+    );
+
+    let mut summarized = request_with_tools.clone();
+    summarized.request.messages.splice(
+        range,
+        [ChatCompletionMessage::new_text(
+            "system".to_string(),
+            format!("Summary of the earlier conversation:\n{summary}"),
+        )],
+    );
+
+    Ok(Some(summarized))
+}
+
+fn summary_request(
+    summarization: &ConversationSummarization,
+    messages: &[ChatCompletionMessage],
+) -> ChatCompletionRequestWithTools<RoutingStrategy> {
+    let transcript = messages
+        .iter()
+        .map(|m| format!("{}: {}", m.role, message_text(m)))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    ChatCompletionRequestWithTools {
+        request: ChatCompletionRequest {
+            model: summarization.model.clone(),
+            messages: vec![
+                ChatCompletionMessage::new_text("system".to_string(), SUMMARY_INSTRUCTION.into()),
+                ChatCompletionMessage::new_text("user".to_string(), transcript),
+            ],
+            stream: Some(false),
+            ..Default::default()
+        },
+        mcp_servers: None,
+        router: None,
+        max_retries: None,
+        extra: None,
+        fallbacks: None,
+        provider_specific: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str) -> ChatCompletionMessage {
+        ChatCompletionMessage::new_text(role.to_string(), "content".to_string())
+    }
+
+    #[test]
+    fn test_range_keeps_system_and_recent_messages() {
+        let messages = vec![
+            message("system"),
+            message("user"),
+            message("assistant"),
+            message("user"),
+            message("assistant"),
+            message("user"),
+        ];
+
+        assert_eq!(summarization_range(&messages, 2), Some(1..4));
+        assert_eq!(summarization_range(&messages, 4), None);
+    }
+
+    #[test]
+    fn test_range_keeps_tool_results_with_calls() {
+        let messages = vec![
+            message("user"),
+            message("assistant"),
+            message("user"),
+            message("assistant"),
+            message("tool"),
+            message("tool"),
+            message("user"),
+        ];
+
+        assert_eq!(summarization_range(&messages, 3), Some(0..3));
+    }
+
+    #[test]
+    fn test_range_without_recent_messages() {
+        let messages = vec![
+            message("system"),
+            message("user"),
+            message("assistant"),
+            message("tool"),
+        ];
+
+        assert_eq!(summarization_range(&messages, 0), Some(1..4));
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        let messages = vec![ChatCompletionMessage::new_text(
+            "user".to_string(),
+            "a".repeat(400),
+        )];
+        assert_eq!(estimate_tokens(&messages), 101);
+    }
+}
@@ -0,0 +1,186 @@
+use rand::Rng;
+
+use crate::error::InvalidRequestError;
+use crate::types::gateway::{
+    ChatCompletionResponse, ChatCompletionUsage, TemperatureDistribution, TemperatureSampling,
+};
+use crate::GatewayApiError;
+
+/// Upper bound of variants generated for a single request
+pub const MAX_VARIANTS: u32 = 16;
+
+impl TemperatureSampling {
+    pub fn validate(&self) -> Result<(), GatewayApiError> {
+        if self.variants == 0 || self.variants > MAX_VARIANTS {
+            return Err(InvalidRequestError::new(
+                "extra.temperature_sampling.variants",
+                "invalid_value",
+                format!("temperature_sampling.variants must be between 1 and {MAX_VARIANTS}"),
+            )
+            .into());
+        }
+
+        match &self.distribution {
+            TemperatureDistribution::Uniform { min, max } if min > max || *min < 0.0 => {
+                Err(InvalidRequestError::new(
+                    "extra.temperature_sampling.distribution",
+                    "invalid_value",
+                    "Invalid uniform temperature range",
+                )
+                .into())
+            }
+            TemperatureDistribution::Weighted { temperatures }
+                if temperatures.is_empty()
+                    || temperatures.iter().any(|t| t.weight < 0.0)
+                    || temperatures.iter().map(|t| t.weight).sum::<f64>() <= 0.0 =>
+            {
+                Err(InvalidRequestError::new(
+                    "extra.temperature_sampling.distribution",
+                    "invalid_value",
+                    "Weighted temperatures require positive weights",
+                )
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Vec<f32> {
+        (0..self.variants)
+            .map(|_| match &self.distribution {
+                TemperatureDistribution::Uniform { min, max } => {
+                    if min == max {
+                        *min
+                    } else {
+                        rng.random_range(*min..=*max)
+                    }
+                }
+                TemperatureDistribution::Weighted { temperatures } => {
+                    let total: f64 = temperatures.iter().map(|t| t.weight).sum();
+                    let mut rand_val = rng.random::<f64>() * total;
+                    temperatures
+                        .iter()
+                        .find(|t| {
+                            rand_val -= t.weight;
+                            rand_val < 0.0
+                        })
+                        .or(temperatures.last())
+                        .map(|t| t.temperature)
+                        .unwrap_or_default()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Combines variant responses into one, choices keep the order of the variants and usage
+/// is summed across all of them
+pub fn merge_variants(responses: Vec<ChatCompletionResponse>) -> Option<ChatCompletionResponse> {
+    let mut responses = responses.into_iter();
+    let mut merged = responses.next()?;
+    for response in responses {
+        merged.choices.extend(response.choices);
+        add_usage(&mut merged.usage, &response.usage);
+        merged.is_cache_used = match (merged.is_cache_used, response.is_cache_used) {
+            (Some(a), Some(b)) => Some(a && b),
+            _ => None,
+        };
+    }
+
+    for (index, choice) in merged.choices.iter_mut().enumerate() {
+        choice.index = index as i32;
+    }
+
+    Some(merged)
+}
+
+pub(crate) fn add_usage(total: &mut ChatCompletionUsage, usage: &ChatCompletionUsage) {
+    total.prompt_tokens += usage.prompt_tokens;
+    total.completion_tokens += usage.completion_tokens;
+    total.total_tokens += usage.total_tokens;
+    total.cost += usage.cost;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::{ChatCompletionChoice, ChatCompletionMessage, WeightedTemperature};
+
+    fn response(content: &str, cost: f64) -> ChatCompletionResponse {
+        ChatCompletionResponse {
+            id: "id".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "gpt-4o".to_string(),
+            choices: vec![ChatCompletionChoice {
+                index: 0,
+                message: ChatCompletionMessage::new_text(
+                    "assistant".to_string(),
+                    content.to_string(),
+                ),
+                finish_reason: Some("stop".to_string()),
+                logprobs: None,
+            }],
+            usage: ChatCompletionUsage {
+                prompt_tokens: 10,
+                completion_tokens: 5,
+                total_tokens: 15,
+                cost,
+                ..Default::default()
+            },
+            is_cache_used: None,
+            citations: None,
+            confidence: None,
+            system_fingerprint: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_variants_sums_usage() {
+        let merged = merge_variants(vec![response("a", 0.1), response("b", 0.2)]).unwrap();
+
+        assert_eq!(merged.choices.len(), 2);
+        assert_eq!(merged.choices[1].index, 1);
+        assert_eq!(merged.usage.prompt_tokens, 20);
+        assert_eq!(merged.usage.total_tokens, 30);
+        assert!((merged.usage.cost - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_sample_within_distribution() {
+        let mut rng = rand::rng();
+        let uniform = TemperatureSampling {
+            variants: 8,
+            distribution: TemperatureDistribution::Uniform { min: 0.5, max: 1.2 },
+        };
+        let temperatures = uniform.sample(&mut rng);
+        assert_eq!(temperatures.len(), 8);
+        assert!(temperatures.iter().all(|t| (0.5..=1.2).contains(t)));
+
+        let weighted = TemperatureSampling {
+            variants: 4,
+            distribution: TemperatureDistribution::Weighted {
+                temperatures: vec![
+                    WeightedTemperature {
+                        temperature: 0.2,
+                        weight: 0.0,
+                    },
+                    WeightedTemperature {
+                        temperature: 1.0,
+                        weight: 1.0,
+                    },
+                ],
+            },
+        };
+        assert_eq!(weighted.sample(&mut rng), vec![1.0; 4]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let sampling = TemperatureSampling {
+            variants: 0,
+            distribution: TemperatureDistribution::Uniform { min: 0.0, max: 1.0 },
+        };
+        assert!(sampling.validate().is_err());
+    }
+}
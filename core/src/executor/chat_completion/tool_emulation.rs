@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::{ModelCapability, ModelMetadata};
+use crate::types::gateway::{
+    ChatCompletionContent, ChatCompletionMessage, ChatCompletionRequestWithTools,
+    ChatCompletionResponse, ChatCompletionTool, FunctionCall, ToolCall,
+};
+
+const TOOL_INSTRUCTION: &str = "You can call the tools listed below. To call tools, reply \
+with only one JSON object per call, each on its own line, of the form {\"tool_call\": \
+{\"name\": \"<tool name>\", \"arguments\": {...}}} and nothing else. Otherwise answer normally.";
+
+/// Handling of requests with tools for models without tool support
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolSupportConfig {
+    /// Strategy by requested model name
+    #[serde(default)]
+    pub models: HashMap<String, ToolFallback>,
+    /// Strategy for models that are not listed
+    #[serde(default)]
+    pub default: Option<ToolFallback>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum ToolFallback {
+    /// Sends the request to a tool capable model instead
+    Reroute { model: String },
+    /// Describes the tools in the prompt and parses the JSON tool call from the output
+    Emulate,
+}
+
+impl ToolSupportConfig {
+    /// Strategy to use when the request has tools the model cannot call natively
+    pub fn fallback_for<T>(
+        &self,
+        request: &ChatCompletionRequestWithTools<T>,
+        llm_model: &ModelMetadata,
+    ) -> Option<&ToolFallback> {
+        let has_tools = request
+            .request
+            .tools
+            .as_ref()
+            .is_some_and(|t| !t.is_empty());
+        if !has_tools || llm_model.capabilities.contains(&ModelCapability::Tools) {
+            return None;
+        }
+
+        self.models
+            .get(&request.request.model)
+            .or(self.default.as_ref())
+    }
+}
+
+/// Moves the tools into a system instruction and rewrites earlier tool calls and
+/// results as plain text, so the request is accepted by models without tool support
+pub fn emulation_request<T: Clone>(
+    request: &ChatCompletionRequestWithTools<T>,
+) -> ChatCompletionRequestWithTools<T> {
+    let mut emulated = request.clone();
+    let tools = emulated.request.tools.take().unwrap_or_default();
+    emulated.request.tool_choice = None;
+
+    let definitions = tools
+        .iter()
+        .map(|t| {
+            serde_json::json!({
+                "name": t.function.name,
+                "description": t.function.description,
+                "parameters": t.function.parameters,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut messages = vec![ChatCompletionMessage::new_text(
+        "system".to_string(),
+        format!(
+            "{TOOL_INSTRUCTION}\n\nTools:\n{}",
+            serde_json::to_string_pretty(&definitions).unwrap_or_default()
+        ),
+    )];
+    for message in emulated.request.messages.drain(..) {
+        messages.push(match (message.role.as_str(), &message.tool_calls) {
+            ("assistant", Some(calls)) => {
+                let calls = calls
+                    .iter()
+                    .map(|c| {
+                        serde_json::json!({
+                            "tool_call": {
+                                "name": c.function.name,
+                                "arguments": serde_json::from_str::<Value>(&c.function.arguments)
+                                    .unwrap_or(Value::String(c.function.arguments.clone())),
+                            }
+                        })
+                        .to_string()
+                    })
+                    .collect::<Vec<_>>();
+                ChatCompletionMessage::new_text("assistant".to_string(), calls.join("\n"))
+            }
+            ("tool", _) => ChatCompletionMessage::new_text(
+                "user".to_string(),
+                format!(
+                    "Tool result: {}",
+                    message
+                        .content
+                        .as_ref()
+                        .and_then(|c| c.as_string())
+                        .unwrap_or_default()
+                ),
+            ),
+            _ => message,
+        });
+    }
+    emulated.request.messages = messages;
+    emulated
+}
+
+/// Parses the tool calls written by the model, only tools offered in the request are
+/// accepted. Output that is not entirely tool calls is left as text.
+pub fn parse_tool_calls(content: &str, tools: &[ChatCompletionTool]) -> Vec<ToolCall> {
+    let content = content.trim();
+    let content = content
+        .strip_prefix("```json")
+        .or_else(|| content.strip_prefix("```"))
+        .and_then(|c| c.strip_suffix("```"))
+        .unwrap_or(content);
+
+    let mut calls = vec![];
+    for (index, value) in serde_json::Deserializer::from_str(content)
+        .into_iter::<Value>()
+        .enumerate()
+    {
+        match value.ok().and_then(|v| parse_tool_call(&v, index, tools)) {
+            Some(call) => calls.push(call),
+            None => return vec![],
+        }
+    }
+    calls
+}
+
+fn parse_tool_call(value: &Value, index: usize, tools: &[ChatCompletionTool]) -> Option<ToolCall> {
+    let call = value.get("tool_call")?;
+    let name = call.get("name")?.as_str()?;
+    if !tools.iter().any(|t| t.function.name == name) {
+        return None;
+    }
+
+    let arguments = match call.get("arguments") {
+        Some(Value::String(arguments)) => arguments.clone(),
+        Some(arguments) => arguments.to_string(),
+        None => "{}".to_string(),
+    };
+    Some(ToolCall {
+        index: Some(index),
+        id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+        r#type: "function".to_string(),
+        function: FunctionCall {
+            name: name.to_string(),
+            arguments,
+        },
+    })
+}
+
+/// Turns tool calls written as text into a regular tool call response
+pub fn apply_tool_calls(
+    response: &mut ChatCompletionResponse,
+    tools: &[ChatCompletionTool],
+) -> Vec<ToolCall> {
+    let Some(choice) = response.choices.first_mut() else {
+        return vec![];
+    };
+    let Some(ChatCompletionContent::Text(content)) = &choice.message.content else {
+        return vec![];
+    };
+    let calls = parse_tool_calls(content, tools);
+    if calls.is_empty() {
+        return calls;
+    }
+
+    choice.message.content = None;
+    choice.message.tool_calls = Some(calls.clone());
+    choice.finish_reason = Some("tool_calls".to_string());
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::{ChatCompletionFunction, ChatCompletionRequest};
+
+    fn tools() -> Vec<ChatCompletionTool> {
+        vec![ChatCompletionTool {
+            tool_type: "function".to_string(),
+            function: ChatCompletionFunction {
+                name: "get_weather".to_string(),
+                description: Some("Current weather".to_string()),
+                parameters: serde_json::from_value(serde_json::json!({
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}}
+                }))
+                .unwrap(),
+            },
+        }]
+    }
+
+    #[test]
+    fn test_parse_tool_calls() {
+        let calls = parse_tool_calls(
+            "```json\n{\"tool_call\": {\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\"}}}\n```",
+            &tools(),
+        );
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, "{\"city\":\"Paris\"}");
+
+        let calls = parse_tool_calls(
+            "{\"tool_call\": {\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\"}}}\n\
+             {\"tool_call\": {\"name\": \"get_weather\", \"arguments\": {\"city\": \"Rome\"}}}",
+            &tools(),
+        );
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[1].index, Some(1));
+        assert_eq!(calls[1].function.arguments, "{\"city\":\"Rome\"}");
+
+        assert!(parse_tool_calls("It is sunny in Paris", &tools()).is_empty());
+        assert!(parse_tool_calls(
+            "{\"tool_call\": {\"name\": \"delete_files\", \"arguments\": {}}}",
+            &tools()
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_emulation_request_rewrites_tool_history() {
+        let request = ChatCompletionRequestWithTools::<()> {
+            request: ChatCompletionRequest {
+                messages: vec![
+                    ChatCompletionMessage::new_text("user".to_string(), "Weather?".to_string()),
+                    ChatCompletionMessage {
+                        role: "assistant".to_string(),
+                        tool_calls: Some(
+                            ["Paris", "Rome"]
+                                .into_iter()
+                                .enumerate()
+                                .map(|(i, city)| ToolCall {
+                                    index: Some(i),
+                                    id: format!("call_{i}"),
+                                    r#type: "function".to_string(),
+                                    function: FunctionCall {
+                                        name: "get_weather".to_string(),
+                                        arguments: format!("{{\"city\":\"{city}\"}}"),
+                                    },
+                                })
+                                .collect(),
+                        ),
+                        ..Default::default()
+                    },
+                    ChatCompletionMessage {
+                        role: "tool".to_string(),
+                        content: Some(ChatCompletionContent::Text("Sunny".to_string())),
+                        tool_call_id: Some("call_0".to_string()),
+                        ..Default::default()
+                    },
+                ],
+                tools: Some(tools()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let emulated = emulation_request(&request);
+        let messages = &emulated.request.messages;
+
+        assert!(emulated.request.tools.is_none());
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].role, "system");
+        assert!(messages.iter().all(|m| m.tool_calls.is_none()));
+        assert_eq!(messages[3].role, "user");
+        let calls = parse_tool_calls(
+            &messages[2].content.as_ref().unwrap().as_string().unwrap(),
+            &tools(),
+        );
+        assert_eq!(
+            calls
+                .iter()
+                .map(|c| c.function.arguments.as_str())
+                .collect::<Vec<_>>(),
+            ["{\"city\":\"Paris\"}", "{\"city\":\"Rome\"}"]
+        );
+    }
+}
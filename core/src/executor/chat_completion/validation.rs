@@ -0,0 +1,333 @@
+use std::collections::HashSet;
+
+use crate::error::InvalidRequestError;
+use crate::types::gateway::ChatCompletionRequest;
+
+const MAX_TOP_LOGPROBS: u8 = 20;
+
+/// Checks the invariants of the request that providers would otherwise reject with
+/// provider specific, often cryptic, errors
+pub fn validate_request(request: &ChatCompletionRequest) -> Result<(), InvalidRequestError> {
+    if request.messages.is_empty() {
+        return Err(InvalidRequestError::new(
+            "messages",
+            "empty_array",
+            "Invalid 'messages': empty array. Expected an array with minimum length 1.",
+        ));
+    }
+
+    check_range("temperature", request.temperature, 0.0, 2.0)?;
+    check_range("top_p", request.top_p, 0.0, 1.0)?;
+    check_range("presence_penalty", request.presence_penalty, -2.0, 2.0)?;
+    check_range("frequency_penalty", request.frequency_penalty, -2.0, 2.0)?;
+    check_min_tokens("max_tokens", request.max_tokens)?;
+    check_min_tokens("max_completion_tokens", request.max_completion_tokens)?;
+
+    if let Some(n) = request.n {
+        if n < 1 {
+            return Err(InvalidRequestError::new(
+                "n",
+                "integer_below_min_value",
+                format!("Invalid 'n': integer below minimum value. Expected a value >= 1, but got {n} instead."),
+            ));
+        }
+    }
+
+    if let Some(top_logprobs) = request.top_logprobs {
+        if top_logprobs > MAX_TOP_LOGPROBS {
+            return Err(InvalidRequestError::new(
+                "top_logprobs",
+                "integer_above_max_value",
+                format!("Invalid 'top_logprobs': integer above maximum value. Expected a value <= {MAX_TOP_LOGPROBS}, but got {top_logprobs} instead."),
+            ));
+        }
+        if request.logprobs != Some(true) {
+            return Err(InvalidRequestError::new(
+                "top_logprobs",
+                "invalid_value",
+                "Invalid 'top_logprobs': 'logprobs' must be set to true when 'top_logprobs' is set.",
+            ));
+        }
+    }
+
+    check_tool_messages(request)
+}
+
+fn check_range(
+    param: &str,
+    value: Option<f32>,
+    min: f32,
+    max: f32,
+) -> Result<(), InvalidRequestError> {
+    match value {
+        Some(value) if value.is_nan() || value < min => Err(InvalidRequestError::new(
+            param,
+            "decimal_below_min_value",
+            format!("Invalid '{param}': decimal below minimum value. Expected a value >= {min}, but got {value} instead."),
+        )),
+        Some(value) if value > max => Err(InvalidRequestError::new(
+            param,
+            "decimal_above_max_value",
+            format!("Invalid '{param}': decimal above maximum value. Expected a value <= {max}, but got {value} instead."),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn check_min_tokens(param: &str, value: Option<u32>) -> Result<(), InvalidRequestError> {
+    match value {
+        Some(0) => Err(InvalidRequestError::new(
+            param,
+            "integer_below_min_value",
+            format!("Invalid '{param}': integer below minimum value. Expected a value >= 1, but got 0 instead."),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// A `tool` message answers one of the tool calls of the closest preceding assistant
+/// message, only other tool messages may come in between
+fn check_tool_messages(request: &ChatCompletionRequest) -> Result<(), InvalidRequestError> {
+    let mut pending: Option<HashSet<&str>> = None;
+
+    for (index, message) in request.messages.iter().enumerate() {
+        match message.role.as_str() {
+            "tool" => {
+                let Some(ids) = &pending else {
+                    return Err(InvalidRequestError::new(
+                        format!("messages[{index}].role"),
+                        "invalid_value",
+                        "Invalid parameter: messages with role 'tool' must be a response to a preceding message with 'tool_calls'.",
+                    ));
+                };
+                let Some(tool_call_id) = &message.tool_call_id else {
+                    return Err(InvalidRequestError::new(
+                        format!("messages[{index}].tool_call_id"),
+                        "missing_required_parameter",
+                        format!("Missing required parameter: 'messages[{index}].tool_call_id'."),
+                    ));
+                };
+                if !ids.contains(tool_call_id.as_str()) {
+                    return Err(InvalidRequestError::new(
+                        format!("messages[{index}].tool_call_id"),
+                        "invalid_value",
+                        format!("Invalid parameter: 'tool_call_id' of '{tool_call_id}' not found in 'tool_calls' of the preceding assistant message."),
+                    ));
+                }
+            }
+            "assistant" => {
+                pending = message
+                    .tool_calls
+                    .as_ref()
+                    .filter(|calls| !calls.is_empty())
+                    .map(|calls| calls.iter().map(|c| c.id.as_str()).collect());
+            }
+            _ => pending = None,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::{ChatCompletionMessage, FunctionCall, ToolCall};
+
+    fn request(messages: Vec<ChatCompletionMessage>) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "openai/gpt-4o".to_string(),
+            messages,
+            ..Default::default()
+        }
+    }
+
+    fn user() -> ChatCompletionMessage {
+        ChatCompletionMessage::new_text("user".to_string(), "What's the weather?".to_string())
+    }
+
+    fn assistant_calling(ids: &[&str]) -> ChatCompletionMessage {
+        ChatCompletionMessage {
+            role: "assistant".to_string(),
+            tool_calls: Some(
+                ids.iter()
+                    .map(|id| ToolCall {
+                        id: id.to_string(),
+                        r#type: "function".to_string(),
+                        function: FunctionCall {
+                            name: "get_weather".to_string(),
+                            arguments: "{}".to_string(),
+                        },
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn tool(id: Option<&str>) -> ChatCompletionMessage {
+        ChatCompletionMessage {
+            tool_call_id: id.map(|id| id.to_string()),
+            ..ChatCompletionMessage::new_text("tool".to_string(), "Sunny".to_string())
+        }
+    }
+
+    fn error(request: &ChatCompletionRequest) -> (String, &'static str) {
+        let e = validate_request(request).unwrap_err();
+        (e.param, e.code)
+    }
+
+    #[test]
+    fn test_valid_request() {
+        let request = ChatCompletionRequest {
+            temperature: Some(2.0),
+            top_p: Some(0.0),
+            max_tokens: Some(1),
+            logprobs: Some(true),
+            top_logprobs: Some(20),
+            ..request(vec![
+                user(),
+                assistant_calling(&["call_1", "call_2"]),
+                tool(Some("call_2")),
+                tool(Some("call_1")),
+                user(),
+            ])
+        };
+        assert_eq!(validate_request(&request), Ok(()));
+    }
+
+    #[test]
+    fn test_empty_messages() {
+        assert_eq!(
+            error(&request(vec![])),
+            ("messages".to_string(), "empty_array")
+        );
+    }
+
+    #[test]
+    fn test_temperature_range() {
+        let e = validate_request(&ChatCompletionRequest {
+            temperature: Some(2.5),
+            ..request(vec![user()])
+        })
+        .unwrap_err();
+        assert_eq!(e.param, "temperature");
+        assert_eq!(e.code, "decimal_above_max_value");
+        assert!(e.message.contains("got 2.5"));
+
+        let e = validate_request(&ChatCompletionRequest {
+            temperature: Some(-0.1),
+            ..request(vec![user()])
+        })
+        .unwrap_err();
+        assert_eq!(e.code, "decimal_below_min_value");
+    }
+
+    #[test]
+    fn test_top_p_range() {
+        let request = ChatCompletionRequest {
+            top_p: Some(1.5),
+            ..request(vec![user()])
+        };
+        assert_eq!(
+            error(&request),
+            ("top_p".to_string(), "decimal_above_max_value")
+        );
+    }
+
+    #[test]
+    fn test_penalty_range() {
+        let request = ChatCompletionRequest {
+            frequency_penalty: Some(-3.0),
+            ..request(vec![user()])
+        };
+        assert_eq!(
+            error(&request),
+            ("frequency_penalty".to_string(), "decimal_below_min_value")
+        );
+    }
+
+    #[test]
+    fn test_zero_max_tokens() {
+        let request = ChatCompletionRequest {
+            max_completion_tokens: Some(0),
+            ..request(vec![user()])
+        };
+        assert_eq!(
+            error(&request),
+            (
+                "max_completion_tokens".to_string(),
+                "integer_below_min_value"
+            )
+        );
+    }
+
+    #[test]
+    fn test_top_logprobs() {
+        let request = ChatCompletionRequest {
+            logprobs: Some(true),
+            top_logprobs: Some(21),
+            ..request(vec![user()])
+        };
+        assert_eq!(
+            error(&request),
+            ("top_logprobs".to_string(), "integer_above_max_value")
+        );
+
+        let request = ChatCompletionRequest {
+            top_logprobs: Some(5),
+            ..request(vec![user()])
+        };
+        assert_eq!(
+            error(&request),
+            ("top_logprobs".to_string(), "invalid_value")
+        );
+    }
+
+    #[test]
+    fn test_tool_message_without_tool_calls() {
+        let orphan = request(vec![user(), tool(Some("call_1"))]);
+        assert_eq!(
+            error(&orphan),
+            ("messages[1].role".to_string(), "invalid_value")
+        );
+
+        // A user message in between ends the tool calls of the assistant
+        let interrupted = request(vec![
+            user(),
+            assistant_calling(&["call_1"]),
+            user(),
+            tool(Some("call_1")),
+        ]);
+        assert_eq!(
+            error(&interrupted),
+            ("messages[3].role".to_string(), "invalid_value")
+        );
+    }
+
+    #[test]
+    fn test_tool_message_without_tool_call_id() {
+        let request = request(vec![user(), assistant_calling(&["call_1"]), tool(None)]);
+        assert_eq!(
+            error(&request),
+            (
+                "messages[2].tool_call_id".to_string(),
+                "missing_required_parameter"
+            )
+        );
+    }
+
+    #[test]
+    fn test_tool_message_with_unknown_tool_call_id() {
+        let request = request(vec![
+            user(),
+            assistant_calling(&["call_1"]),
+            tool(Some("call_2")),
+        ]);
+        assert_eq!(
+            error(&request),
+            ("messages[2].tool_call_id".to_string(), "invalid_value")
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::executor::chat_completion::stream_wrapper::{wrap_stream, ChatCompletionStream};
+use crate::models::ModelMetadata;
+use crate::GatewayApiError;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failed calls that open the circuit of a deployment
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Time an open circuit rejects calls before a probe call is let through
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_cooldown_secs() -> u64 {
+    30
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// Change of the circuit of a deployment, traced as a `circuit_breaker` event
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct CircuitTransition {
+    pub deployment: String,
+    pub from: CircuitState,
+    pub to: CircuitState,
+}
+
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Instant,
+    /// Start of the probe call of a half open circuit
+    probe_started: Option<Instant>,
+}
+
+impl Default for Circuit {
+    fn default() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            opened_at: Instant::now(),
+            probe_started: None,
+        }
+    }
+}
+
+/// Passive health of the model deployments, shared by all requests. Calls to a deployment
+/// failing repeatedly are rejected without reaching the provider until a probe succeeds.
+pub struct CircuitBreakers {
+    config: CircuitBreakerConfig,
+    circuits: DashMap<String, Circuit>,
+}
+
+impl CircuitBreakers {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            circuits: DashMap::new(),
+        }
+    }
+
+    fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.config.cooldown_secs)
+    }
+
+    /// Whether a call to the deployment would be let through, without starting a probe
+    pub fn is_available(&self, deployment: &str) -> bool {
+        let Some(circuit) = self.circuits.get(deployment) else {
+            return true;
+        };
+
+        let now = Instant::now();
+        match circuit.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => now.duration_since(circuit.opened_at) >= self.cooldown(),
+            CircuitState::HalfOpen => circuit
+                .probe_started
+                .is_none_or(|started| now.duration_since(started) >= self.cooldown()),
+        }
+    }
+
+    /// Lets a call through to the deployment. Deployment resolution skips open circuits
+    /// while a sibling deployment is available, when none is an open circuit rejects the
+    /// call with a retryable error so the request moves on to its fallback models.
+    pub fn acquire(&self, deployment: &str) -> Result<Option<CircuitTransition>, GatewayApiError> {
+        let Some(mut circuit) = self.circuits.get_mut(deployment) else {
+            return Ok(None);
+        };
+
+        let now = Instant::now();
+        match circuit.state {
+            CircuitState::Closed => Ok(None),
+            CircuitState::Open if now.duration_since(circuit.opened_at) >= self.cooldown() => {
+                circuit.state = CircuitState::HalfOpen;
+                circuit.probe_started = Some(now);
+                Ok(Some(CircuitTransition {
+                    deployment: deployment.to_string(),
+                    from: CircuitState::Open,
+                    to: CircuitState::HalfOpen,
+                }))
+            }
+            // A probe abandoned before its outcome was recorded is replaced after a cooldown
+            CircuitState::HalfOpen
+                if circuit
+                    .probe_started
+                    .is_none_or(|started| now.duration_since(started) >= self.cooldown()) =>
+            {
+                circuit.probe_started = Some(now);
+                Ok(None)
+            }
+            CircuitState::Open | CircuitState::HalfOpen => {
+                Err(GatewayApiError::ServiceUnavailable(format!(
+                    "Deployment {deployment} is failing, calls are paused by its circuit breaker"
+                )))
+            }
+        }
+    }
+
+    /// Records the outcome of a call to the deployment
+    pub fn record(&self, deployment: &str, failed: bool) -> Option<CircuitTransition> {
+        let mut circuit = self.circuits.entry(deployment.to_string()).or_default();
+        let from = circuit.state;
+        if failed {
+            circuit.consecutive_failures += 1;
+            if from == CircuitState::HalfOpen
+                || circuit.consecutive_failures >= self.config.failure_threshold.max(1)
+            {
+                circuit.state = CircuitState::Open;
+                circuit.opened_at = Instant::now();
+            }
+        } else {
+            circuit.consecutive_failures = 0;
+            circuit.state = CircuitState::Closed;
+        }
+        circuit.probe_started = None;
+
+        (from != circuit.state).then(|| CircuitTransition {
+            deployment: deployment.to_string(),
+            from,
+            to: circuit.state,
+        })
+    }
+
+    /// Records the outcome of a streamed call on its first item
+    pub fn record_stream(
+        self: &Arc<Self>,
+        deployment: String,
+        stream: ChatCompletionStream,
+        on_transition: impl Fn(CircuitTransition) + Send + 'static,
+    ) -> ChatCompletionStream {
+        let mut pending = Some((self.clone(), deployment));
+        wrap_stream(stream.map(move |item| {
+            if let Some((breakers, deployment)) = pending.take() {
+                if let Some(transition) = breakers.record(&deployment, is_failure(&item)) {
+                    on_transition(transition);
+                }
+            }
+            item
+        }))
+    }
+}
+
+/// Failures of the deployment itself, invalid requests say nothing about its health
+pub fn is_failure<T>(result: &Result<T, GatewayApiError>) -> bool {
+    matches!(result, Err(e) if e.is_retryable())
+}
+
+/// Circuit of a model, each backing deployment has its own
+pub fn deployment_key(model: &ModelMetadata) -> String {
+    match &model.deployment {
+        Some(deployment) => deployment.id.clone(),
+        None => model.qualified_model_name(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breakers(cooldown_secs: u64) -> CircuitBreakers {
+        CircuitBreakers::new(CircuitBreakerConfig {
+            failure_threshold: 3,
+            cooldown_secs,
+        })
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breakers = breakers(60);
+        assert_eq!(breakers.record("openai/gpt-4o", true), None);
+        // A success resets the count
+        breakers.record("openai/gpt-4o", false);
+        breakers.record("openai/gpt-4o", true);
+        breakers.record("openai/gpt-4o", true);
+        assert!(breakers.acquire("openai/gpt-4o").unwrap().is_none());
+
+        let transition = breakers.record("openai/gpt-4o", true).unwrap();
+        assert_eq!(transition.to, CircuitState::Open);
+        for _ in 0..3 {
+            assert!(matches!(
+                breakers.acquire("openai/gpt-4o"),
+                Err(GatewayApiError::ServiceUnavailable(_))
+            ));
+        }
+        assert!(GatewayApiError::ServiceUnavailable(String::new()).is_retryable());
+
+        assert!(!breakers.is_available("openai/gpt-4o"));
+
+        // Other deployments are not affected
+        assert!(breakers.is_available("openai/gpt-4o-mini"));
+        assert!(breakers.acquire("openai/gpt-4o-mini").is_ok());
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breakers = breakers(0);
+        for _ in 0..3 {
+            breakers.record("gpt-4o-eu", true);
+        }
+
+        let transition = breakers.acquire("gpt-4o-eu").unwrap().unwrap();
+        assert_eq!(transition.to, CircuitState::HalfOpen);
+        // A failed probe opens the circuit again
+        let transition = breakers.record("gpt-4o-eu", true).unwrap();
+        assert_eq!(
+            (transition.from, transition.to),
+            (CircuitState::HalfOpen, CircuitState::Open)
+        );
+
+        assert!(breakers.is_available("gpt-4o-eu"));
+        breakers.acquire("gpt-4o-eu").unwrap();
+        let transition = breakers.record("gpt-4o-eu", false).unwrap();
+        assert_eq!(transition.to, CircuitState::Closed);
+        assert!(breakers.acquire("gpt-4o-eu").unwrap().is_none());
+    }
+}
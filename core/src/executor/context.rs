@@ -1,13 +1,43 @@
 use crate::types::guardrails::service::GuardrailsEvaluator;
 use crate::{
     error::GatewayError,
-    handler::{extract_tags, AvailableModels, CallbackHandlerFn},
-    types::{credentials::Credentials, gateway::CostCalculator},
+    handler::{extract_request_timeout, extract_tags, AvailableModels, CallbackHandlerFn},
+    types::{
+        credentials::{Credentials, ProviderCredentials},
+        gateway::CostCalculator,
+    },
 };
 use actix_web::{HttpMessage, HttpRequest};
+use std::time::Instant;
 use std::{collections::HashMap, sync::Arc};
+use tracing::Span;
 
+use super::chat_completion::backoff::RetryPolicy;
+use super::chat_completion::downgrade::DowngradeConfig;
+use super::chat_completion::idempotency::{IdempotencyKey, IdempotencyKeys};
+use super::chat_completion::moderation::Moderation;
+use super::chat_completion::quirks::QuirksConfig;
+use super::chat_completion::response_cache::ResponseCache;
+use super::chat_completion::retrieval::Retriever;
+use super::chat_completion::stop_sequences::StopSequencesConfig;
+use super::chat_completion::stream_wrapper::StreamKeepAliveConfig;
+use super::chat_completion::tool_emulation::ToolSupportConfig;
+use super::circuit_breaker::CircuitBreakers;
+use super::deployments::DeploymentSelector;
+use super::limiter::ModelConcurrencyLimiter;
+use super::provider_limiter::ProviderConcurrencyLimiter;
+use super::retry_budget::RetryBudget;
+use super::size_metrics::SizeMetrics;
+use super::spend_budget::{BudgetAccount, SpendBudget};
+use super::tag_routing::TagRoutingConfig;
+use super::user_hashing::UserHashingConfig;
 use super::ProvidersConfig;
+use crate::handler::middleware::key_rate_limit::TokenReservation;
+use crate::handler::middleware::memory_pressure::MemoryPressureMonitor;
+use crate::handler::middleware::request_id::RequestId;
+use crate::model::cost_accrual::CostAccrualConfig;
+use crate::model::response_validation::ResponseValidationConfig;
+use crate::model::token_timing::TokenTimingConfig;
 
 #[derive(Clone)]
 pub struct ExecutorContext {
@@ -17,8 +47,43 @@ pub struct ExecutorContext {
     pub tags: HashMap<String, String>,
     pub headers: HashMap<String, String>,
     pub key_credentials: Option<Credentials>,
+    /// Keys of the `X-Provider-Key-*` headers, selected by the provider of the model
+    pub provider_credentials: Option<ProviderCredentials>,
     pub providers_config: Option<ProvidersConfig>,
     pub evaluator_service: Arc<Box<dyn GuardrailsEvaluator>>,
+    pub model_limiter: Option<Arc<ModelConcurrencyLimiter>>,
+    pub provider_limiter: Option<Arc<ProviderConcurrencyLimiter>>,
+    pub user_hashing: Option<UserHashingConfig>,
+    pub retry_budget: Option<Arc<RetryBudget>>,
+    pub circuit_breakers: Option<Arc<CircuitBreakers>>,
+    pub size_metrics: Option<Arc<SizeMetrics>>,
+    pub response_validation: Option<ResponseValidationConfig>,
+    pub token_timing: Option<TokenTimingConfig>,
+    pub cost_accrual: Option<CostAccrualConfig>,
+    pub retriever: Option<Arc<dyn Retriever>>,
+    pub quirks: Option<QuirksConfig>,
+    pub tool_support: Option<ToolSupportConfig>,
+    pub downgrade: Option<DowngradeConfig>,
+    pub tag_routing: Option<TagRoutingConfig>,
+    pub memory_pressure: Option<Arc<MemoryPressureMonitor>>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub response_cache: Option<Arc<dyn ResponseCache>>,
+    pub deployment_selector: Option<Arc<DeploymentSelector>>,
+    pub moderation: Option<Arc<Moderation>>,
+    pub stream_keep_alive: Option<StreamKeepAliveConfig>,
+    pub stop_sequences: Option<StopSequencesConfig>,
+    pub idempotency: Option<Arc<IdempotencyKeys>>,
+    /// Set by the routed executor for the client request
+    pub idempotency_key: Option<IdempotencyKey>,
+    /// Tokens reserved by the API key rate limit, reconciled with the reported usage
+    pub token_reservation: Option<Arc<TokenReservation>>,
+    /// Monthly spend budget of the API key or tag of the request
+    pub spend_budget: Option<Arc<BudgetAccount>>,
+    pub request_id: Option<String>,
+    /// Time by which the request, including a streamed response, must be finished
+    pub request_deadline: Option<Instant>,
+    /// Number of delegated calls between this request and the client request
+    pub delegation_depth: usize,
 }
 
 // Implement Send + Sync since all fields are Send + Sync
@@ -41,7 +106,37 @@ impl ExecutorContext {
             .collect();
 
         let key_credentials = req.extensions().get::<Credentials>().cloned();
+        let provider_credentials = req.extensions().get::<ProviderCredentials>().cloned();
         let providers_config = req.app_data::<ProvidersConfig>().cloned();
+        let model_limiter = req.app_data::<Arc<ModelConcurrencyLimiter>>().cloned();
+        let provider_limiter = req.app_data::<Arc<ProviderConcurrencyLimiter>>().cloned();
+        let user_hashing = req.app_data::<UserHashingConfig>().cloned();
+        let retry_budget = req.app_data::<Arc<RetryBudget>>().cloned();
+        let circuit_breakers = req.app_data::<Arc<CircuitBreakers>>().cloned();
+        let size_metrics = req.app_data::<Arc<SizeMetrics>>().cloned();
+        let response_validation = req.app_data::<ResponseValidationConfig>().cloned();
+        let token_timing = req.app_data::<TokenTimingConfig>().cloned();
+        let cost_accrual = req.app_data::<CostAccrualConfig>().cloned();
+        let retriever = req.app_data::<Arc<dyn Retriever>>().cloned();
+        let quirks = req.app_data::<QuirksConfig>().cloned();
+        let tool_support = req.app_data::<ToolSupportConfig>().cloned();
+        let downgrade = req.app_data::<DowngradeConfig>().cloned();
+        let tag_routing = req.app_data::<TagRoutingConfig>().cloned();
+        let memory_pressure = req.app_data::<Arc<MemoryPressureMonitor>>().cloned();
+        let retry_policy = req.app_data::<RetryPolicy>().cloned();
+        let response_cache = req.app_data::<Arc<dyn ResponseCache>>().cloned();
+        let deployment_selector = req.app_data::<Arc<DeploymentSelector>>().cloned();
+        let moderation = req.app_data::<Arc<Moderation>>().cloned();
+        let stream_keep_alive = req.app_data::<StreamKeepAliveConfig>().cloned();
+        let stop_sequences = req.app_data::<StopSequencesConfig>().cloned();
+        let idempotency = req.app_data::<Arc<IdempotencyKeys>>().cloned();
+        let token_reservation = req.extensions().get::<Arc<TokenReservation>>().cloned();
+        let spend_budget = req
+            .app_data::<Arc<SpendBudget>>()
+            .and_then(|b| b.account(key_credentials.as_ref()))
+            .map(Arc::new);
+        let request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone());
+        let request_deadline = extract_request_timeout(req).map(|t| Instant::now() + t);
 
         Ok(Self {
             callbackhandler,
@@ -50,8 +145,63 @@ impl ExecutorContext {
             tags,
             headers,
             key_credentials,
+            provider_credentials,
             providers_config,
             evaluator_service,
+            model_limiter,
+            provider_limiter,
+            user_hashing,
+            retry_budget,
+            circuit_breakers,
+            size_metrics,
+            response_validation,
+            token_timing,
+            cost_accrual,
+            retriever,
+            quirks,
+            tool_support,
+            downgrade,
+            tag_routing,
+            memory_pressure,
+            retry_policy,
+            response_cache,
+            deployment_selector,
+            moderation,
+            stream_keep_alive,
+            stop_sequences,
+            idempotency,
+            idempotency_key: None,
+            token_reservation,
+            spend_budget,
+            request_id,
+            request_deadline,
+            delegation_depth: 0,
         })
     }
+
+    /// Sends a custom event of the request to the callbacks
+    pub fn emit_custom(&self, span: &Span, name: &str, value: serde_json::Value) {
+        self.callbackhandler
+            .on_custom(span, name, value, self.request_id.clone());
+    }
+
+    /// Withdraws a retry from the global retry budget. Exhaustion is logged and
+    /// reported as a custom event, the caller fails fast instead of retrying.
+    pub fn acquire_retry(&self, kind: &str) -> bool {
+        let Some(retry_budget) = &self.retry_budget else {
+            return true;
+        };
+
+        if retry_budget.try_retry() {
+            return true;
+        }
+
+        tracing::warn!("Retry budget exhausted, skipping {kind}");
+        self.emit_custom(
+            &Span::current(),
+            "retry_budget_exhausted",
+            serde_json::json!({ "kind": kind }),
+        );
+        false
+    }
 }
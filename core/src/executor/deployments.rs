@@ -0,0 +1,282 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tracing::Span;
+
+use super::circuit_breaker::{deployment_key, CircuitBreakers};
+use super::context::ExecutorContext;
+use crate::handler::find_models_by_full_name;
+use crate::model::error::ModelError;
+use crate::models::{DeploymentStrategy, ModelMetadata};
+use crate::types::gateway::Extra;
+use crate::GatewayApiError;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DeploymentsConfig {
+    #[serde(default)]
+    pub strategy: DeploymentStrategy,
+    /// Seeds the weighted random strategy, for reproducible routing
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// Spreads requests for a model name over the deployments registered under it
+pub struct DeploymentSelector {
+    strategy: DeploymentStrategy,
+    rng: Mutex<StdRng>,
+    /// Next round robin position per model name
+    positions: DashMap<String, usize>,
+    /// Sequence number of the last request sent to each deployment
+    last_used: DashMap<String, u64>,
+    sequence: AtomicU64,
+}
+
+impl DeploymentSelector {
+    pub fn new(config: DeploymentsConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+        Self {
+            strategy: config.strategy,
+            rng: Mutex::new(rng),
+            positions: DashMap::new(),
+            last_used: DashMap::new(),
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    pub fn strategy(&self) -> DeploymentStrategy {
+        self.strategy
+    }
+
+    /// Index of the candidate that serves the request
+    pub fn select(
+        &self,
+        model_name: &str,
+        candidates: &[ModelMetadata],
+        strategy: DeploymentStrategy,
+    ) -> usize {
+        if candidates.len() < 2 {
+            return 0;
+        }
+        let index = match strategy {
+            DeploymentStrategy::RoundRobin => {
+                let mut position = self.positions.entry(model_name.to_string()).or_default();
+                let index = *position % candidates.len();
+                *position = index + 1;
+                index
+            }
+            DeploymentStrategy::WeightedRandom => self.weighted(candidates),
+            DeploymentStrategy::LeastRecentlyUsed => candidates
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, m)| {
+                    self.last_used
+                        .get(&deployment_id(m))
+                        .map_or(0, |sequence| *sequence)
+                })
+                .map_or(0, |(index, _)| index),
+        };
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        self.last_used
+            .insert(deployment_id(&candidates[index]), sequence);
+        index
+    }
+
+    fn weighted(&self, candidates: &[ModelMetadata]) -> usize {
+        let weights = candidates
+            .iter()
+            .map(|m| m.deployment.as_ref().map_or(1.0, |d| d.weight.max(0.0)))
+            .collect::<Vec<_>>();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return 0;
+        }
+
+        let mut point = self.rng.lock().random_range(0.0..total);
+        for (index, weight) in weights.iter().enumerate() {
+            if point < *weight {
+                return index;
+            }
+            point -= weight;
+        }
+        weights.iter().rposition(|w| *w > 0.0).unwrap_or(0)
+    }
+}
+
+/// Identifies a deployment in events, defaults to its provider and model
+pub fn deployment_id(model: &ModelMetadata) -> String {
+    match &model.deployment {
+        Some(deployment) => deployment.id.clone(),
+        None => format!(
+            "{}/{}",
+            model.inference_provider.provider, model.inference_provider.model_name
+        ),
+    }
+}
+
+/// Finds the model for the request, picking one deployment when several share its name
+pub fn resolve_deployment(
+    executor_context: &ExecutorContext,
+    model_name: &str,
+    extra: Option<&Extra>,
+    span: &Span,
+) -> Result<ModelMetadata, GatewayApiError> {
+    let mut candidates = find_models_by_full_name(model_name, &executor_context.provided_models);
+    if candidates.is_empty() {
+        return Err(GatewayApiError::ModelError(Box::new(
+            ModelError::ModelNotFound(model_name.to_string()),
+        )));
+    }
+    if let Some(breakers) = &executor_context.circuit_breakers {
+        skip_open_circuits(&mut candidates, breakers);
+    }
+    let Some(selector) = executor_context
+        .deployment_selector
+        .as_ref()
+        .filter(|_| candidates.len() > 1)
+    else {
+        return Ok(candidates.swap_remove(0));
+    };
+
+    let strategy = extra
+        .and_then(|e| e.deployment_strategy)
+        .unwrap_or(selector.strategy());
+    let model = candidates.swap_remove(selector.select(model_name, &candidates, strategy));
+
+    executor_context.emit_custom(
+        span,
+        "deployment_selected",
+        serde_json::json!({
+            "model": model_name,
+            "deployment": deployment_id(&model),
+            "provider": model.inference_provider.provider.to_string(),
+            "strategy": strategy,
+        }),
+    );
+
+    Ok(model)
+}
+
+/// Drops the deployments with an open circuit, so traffic fails over to a healthy sibling.
+/// All are kept when every circuit is open, the request then moves on to its fallbacks.
+fn skip_open_circuits(candidates: &mut Vec<ModelMetadata>, breakers: &CircuitBreakers) {
+    if candidates
+        .iter()
+        .any(|m| breakers.is_available(&deployment_key(m)))
+    {
+        candidates.retain(|m| breakers.is_available(&deployment_key(m)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::circuit_breaker::CircuitBreakerConfig;
+    use crate::models::{Deployment, InferenceProvider};
+    use crate::types::provider::InferenceModelProvider;
+
+    fn deployments(weights: &[f64]) -> Vec<ModelMetadata> {
+        weights
+            .iter()
+            .enumerate()
+            .map(|(index, weight)| ModelMetadata {
+                model: "gpt-4o".to_string(),
+                inference_provider: InferenceProvider {
+                    provider: InferenceModelProvider::OpenAI,
+                    model_name: "gpt-4o".to_string(),
+                    endpoint: None,
+                },
+                deployment: Some(Deployment {
+                    id: format!("deployment-{index}"),
+                    weight: *weight,
+                    credentials: None,
+                }),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn selector(seed: u64) -> DeploymentSelector {
+        DeploymentSelector::new(DeploymentsConfig {
+            strategy: DeploymentStrategy::WeightedRandom,
+            seed: Some(seed),
+        })
+    }
+
+    #[test]
+    fn test_weighted_random() {
+        let candidates = deployments(&[3.0, 1.0, 0.0]);
+        let picks = |selector: &DeploymentSelector| {
+            (0..1000)
+                .map(|_| selector.select("gpt-4o", &candidates, DeploymentStrategy::WeightedRandom))
+                .collect::<Vec<_>>()
+        };
+
+        let first = picks(&selector(7));
+        assert_eq!(first, picks(&selector(7)));
+        let counts = [0, 1, 2].map(|i| first.iter().filter(|p| **p == i).count());
+        assert!((700..800).contains(&counts[0]), "{counts:?}");
+        assert_eq!(counts[2], 0);
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let candidates = deployments(&[1.0, 1.0, 1.0]);
+        let selector = selector(0);
+        let picks = (0..5)
+            .map(|_| selector.select("gpt-4o", &candidates, DeploymentStrategy::RoundRobin))
+            .collect::<Vec<_>>();
+        assert_eq!(picks, vec![0, 1, 2, 0, 1]);
+        assert_eq!(
+            selector.select("gpt-4o-mini", &candidates, DeploymentStrategy::RoundRobin),
+            0
+        );
+    }
+
+    #[test]
+    fn test_least_recently_used() {
+        let candidates = deployments(&[1.0, 1.0, 1.0]);
+        let selector = selector(0);
+        assert_eq!(
+            selector.select("gpt-4o", &candidates, DeploymentStrategy::RoundRobin),
+            0
+        );
+        assert_eq!(
+            selector.select("gpt-4o", &candidates, DeploymentStrategy::LeastRecentlyUsed),
+            1
+        );
+        assert_eq!(
+            selector.select("gpt-4o", &candidates, DeploymentStrategy::LeastRecentlyUsed),
+            2
+        );
+        assert_eq!(
+            selector.select("gpt-4o", &candidates, DeploymentStrategy::LeastRecentlyUsed),
+            0
+        );
+    }
+    #[test]
+    fn test_open_circuits_are_skipped() {
+        let breakers = CircuitBreakers::new(CircuitBreakerConfig {
+            failure_threshold: 1,
+            cooldown_secs: 60,
+        });
+        breakers.record("deployment-0", true);
+
+        let mut candidates = deployments(&[1.0, 1.0]);
+        skip_open_circuits(&mut candidates, &breakers);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(deployment_id(&candidates[0]), "deployment-1");
+
+        // Without a healthy deployment the circuit breaker rejects the call
+        breakers.record("deployment-1", true);
+        let mut candidates = deployments(&[1.0, 1.0]);
+        skip_open_circuits(&mut candidates, &breakers);
+        assert_eq!(candidates.len(), 2);
+    }
+}
@@ -0,0 +1,324 @@
+use std::time::Duration;
+
+use async_openai::types::Embedding;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::executor::embeddings::EmbeddingsResult;
+use crate::executor::ttl_cache::TtlCache;
+use crate::types::cache::{CacheFlushFilter, FlushableCache};
+use crate::types::gateway::Input;
+
+/// Caches provider vectors by input, so unchanged inputs skip the provider call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingCacheConfig {
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Vectors kept in memory, expired and then oldest entries are evicted first
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+}
+
+fn default_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_max_entries() -> usize {
+    100_000
+}
+
+/// Storage backend of cached vectors
+#[async_trait]
+pub trait EmbeddingCache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<Vec<f32>>;
+
+    async fn set(&self, key: String, model: &str, embedding: Vec<f32>);
+}
+
+#[derive(Clone)]
+struct CachedVector {
+    model: String,
+    embedding: Vec<f32>,
+}
+
+/// Default backend keeping vectors in process memory
+pub struct InMemoryEmbeddingCache {
+    config: EmbeddingCacheConfig,
+    entries: TtlCache<CachedVector>,
+}
+
+impl InMemoryEmbeddingCache {
+    pub fn new(config: EmbeddingCacheConfig) -> Self {
+        Self {
+            entries: TtlCache::new(config.max_entries),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingCache for InMemoryEmbeddingCache {
+    async fn get(&self, key: &str) -> Option<Vec<f32>> {
+        self.entries.get(key).map(|vector| vector.embedding)
+    }
+
+    async fn set(&self, key: String, model: &str, embedding: Vec<f32>) {
+        let vector = CachedVector {
+            model: model.to_string(),
+            embedding,
+        };
+        self.entries
+            .insert(key, vector, Duration::from_secs(self.config.ttl_secs));
+    }
+}
+
+impl FlushableCache for InMemoryEmbeddingCache {
+    fn kind(&self) -> &str {
+        "embedding"
+    }
+
+    fn flush(&self, filter: &CacheFlushFilter) -> usize {
+        self.entries
+            .retain(|key, vector| !filter.matches(key, &vector.model, &[]))
+    }
+}
+
+/// Key of a single input, surrounding whitespace does not change the vector
+pub fn embedding_cache_key(model: &str, dimensions: Option<u32>, input: &str) -> String {
+    let digest = Sha256::digest(input.trim().as_bytes());
+    match dimensions {
+        Some(dimensions) => format!("embedding:{model}:{dimensions}:{digest:x}"),
+        None => format!("embedding:{model}:{digest:x}"),
+    }
+}
+
+/// Inputs of a request split into cached vectors and the inputs still sent to the provider
+pub struct CachedInputs {
+    model: String,
+    keys: Vec<String>,
+    inputs: Vec<String>,
+    hits: Vec<Option<Vec<f32>>>,
+}
+
+impl CachedInputs {
+    pub async fn lookup(
+        cache: &dyn EmbeddingCache,
+        model: &str,
+        dimensions: Option<u32>,
+        input: &Input,
+    ) -> Self {
+        let inputs = match input {
+            Input::String(s) => vec![s.clone()],
+            Input::Array(v) => v.clone(),
+        };
+        let keys: Vec<_> = inputs
+            .iter()
+            .map(|i| embedding_cache_key(model, dimensions, i))
+            .collect();
+        let mut hits = Vec::with_capacity(keys.len());
+        for key in &keys {
+            hits.push(cache.get(key).await);
+        }
+
+        Self {
+            model: model.to_string(),
+            keys,
+            inputs,
+            hits,
+        }
+    }
+
+    /// Positions of the inputs missing from the cache, in request order
+    fn misses(&self) -> Vec<usize> {
+        (0..self.hits.len())
+            .filter(|i| self.hits[*i].is_none())
+            .collect()
+    }
+
+    /// Input of the provider request, `None` when every input is cached
+    pub fn missing_input(&self, input: &Input) -> Option<Input> {
+        let misses = self.misses();
+        match input {
+            _ if misses.is_empty() && !self.hits.is_empty() => None,
+            Input::String(_) => Some(input.clone()),
+            Input::Array(_) => Some(Input::Array(
+                misses.into_iter().map(|i| self.inputs[i].clone()).collect(),
+            )),
+        }
+    }
+
+    /// Stores the vectors returned for the missing inputs
+    pub async fn store(&self, cache: &dyn EmbeddingCache, result: &EmbeddingsResult) {
+        let misses = self.misses();
+        for data in &result.response.data {
+            if let Some(position) = misses.get(data.index as usize) {
+                cache
+                    .set(
+                        self.keys[*position].clone(),
+                        &self.model,
+                        data.embedding.clone(),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Moves the provider results back to the positions of their inputs and fills in the
+    /// cached vectors, which add nothing to the usage
+    pub fn merge(self, mut result: EmbeddingsResult) -> EmbeddingsResult {
+        let misses = self.misses();
+        let position = |index: u32| misses.get(index as usize).map_or(index, |p| *p as u32);
+
+        for data in result.response.data.iter_mut() {
+            data.index = position(data.index);
+        }
+        for error in result.errors.iter_mut() {
+            error.index = position(error.index);
+        }
+
+        let hits = self.hits.into_iter().enumerate();
+        result
+            .response
+            .data
+            .extend(hits.filter_map(|(index, embedding)| {
+                Some(Embedding {
+                    index: index as u32,
+                    object: "embedding".to_string(),
+                    embedding: embedding?,
+                })
+            }));
+        result.response.data.sort_by_key(|e| e.index);
+        result.errors.sort_by_key(|e| e.index);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::EmbeddingError;
+    use async_openai::types::{CreateEmbeddingResponse, EmbeddingUsage};
+
+    fn cache() -> InMemoryEmbeddingCache {
+        InMemoryEmbeddingCache::new(EmbeddingCacheConfig {
+            ttl_secs: 60,
+            max_entries: 100,
+        })
+    }
+
+    fn provider_result(vectors: Vec<(u32, f32)>, tokens: u32) -> EmbeddingsResult {
+        EmbeddingsResult {
+            response: CreateEmbeddingResponse {
+                object: "list".to_string(),
+                model: "text-embedding-3-small".to_string(),
+                data: vectors
+                    .into_iter()
+                    .map(|(index, value)| Embedding {
+                        index,
+                        object: "embedding".to_string(),
+                        embedding: vec![value],
+                    })
+                    .collect(),
+                usage: EmbeddingUsage {
+                    prompt_tokens: tokens,
+                    total_tokens: tokens,
+                },
+            },
+            errors: vec![],
+        }
+    }
+
+    fn array(inputs: &[&str]) -> Input {
+        Input::Array(inputs.iter().map(|i| i.to_string()).collect())
+    }
+
+    #[tokio::test]
+    async fn test_partial_hits_keep_input_order() {
+        let cache = cache();
+        let model = "openai/text-embedding-3-small";
+        for (input, value) in [("b", 1.0), ("d", 3.0)] {
+            cache
+                .set(embedding_cache_key(model, None, input), model, vec![value])
+                .await;
+        }
+
+        let input = array(&["a", "b", "c", "d", "e"]);
+        let cached = CachedInputs::lookup(&cache, model, None, &input).await;
+        let Some(Input::Array(missing)) = cached.missing_input(&input) else {
+            panic!("expected the missing inputs");
+        };
+        assert_eq!(missing, vec!["a", "c", "e"]);
+
+        // Providers may return the vectors out of order
+        let result = provider_result(vec![(2, 4.0), (0, 0.0), (1, 2.0)], 9);
+        cached.store(&cache, &result).await;
+        let merged = cached.merge(result);
+
+        assert_eq!(
+            merged
+                .response
+                .data
+                .iter()
+                .map(|e| (e.index, e.embedding[0]))
+                .collect::<Vec<_>>(),
+            vec![(0, 0.0), (1, 1.0), (2, 2.0), (3, 3.0), (4, 4.0)]
+        );
+        // Only the provider call is counted
+        assert_eq!(merged.response.usage.total_tokens, 9);
+
+        // The next request is served from the cache alone
+        let cached = CachedInputs::lookup(&cache, model, None, &input).await;
+        assert!(cached.missing_input(&input).is_none());
+        let merged = cached.merge(provider_result(vec![], 0));
+        assert_eq!(merged.response.data.len(), 5);
+        assert_eq!(merged.response.data[4].embedding, vec![4.0]);
+        assert_eq!(merged.response.usage.prompt_tokens, 0);
+    }
+
+    #[tokio::test]
+    async fn test_best_effort_errors_follow_inputs() {
+        let cache = cache();
+        let model = "text-embedding-3-small";
+        cache
+            .set(embedding_cache_key(model, Some(256), "a"), model, vec![0.0])
+            .await;
+
+        let input = array(&["a", "b", "c"]);
+        let cached = CachedInputs::lookup(&cache, model, Some(256), &input).await;
+        let mut result = provider_result(vec![(1, 2.0)], 2);
+        result.errors.push(EmbeddingError {
+            index: 0,
+            error: "input rejected".to_string(),
+        });
+
+        cached.store(&cache, &result).await;
+        let merged = cached.merge(result);
+        assert_eq!(merged.errors[0].index, 1);
+        assert_eq!(
+            merged
+                .response
+                .data
+                .iter()
+                .map(|e| e.index)
+                .collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+
+        // Failed inputs are not cached and other dimensions have their own entries
+        assert!(cache
+            .get(&embedding_cache_key(model, Some(256), "b"))
+            .await
+            .is_none());
+        assert!(cache
+            .get(&embedding_cache_key(model, None, "c"))
+            .await
+            .is_none());
+        assert_eq!(
+            cache
+                .get(&embedding_cache_key(model, Some(256), " c "))
+                .await,
+            Some(vec![2.0])
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_openai::types::{CreateEmbeddingResponse, Embedding, EmbeddingUsage};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::embed_mod::{Embed, OpenAIEmbed};
+use crate::error::GatewayError;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CoalescingConfig {
+    /// Time the first request of a batch waits for others to join
+    #[serde(default = "default_window_ms")]
+    pub window_ms: u64,
+    /// Batches are sent as soon as they reach this size
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+}
+
+fn default_window_ms() -> u64 {
+    10
+}
+
+fn default_max_batch_size() -> usize {
+    64
+}
+
+/// Requests are only batched with others for the same model, dimensions and credentials
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BatchKey {
+    pub model: String,
+    pub dimensions: Option<u32>,
+    pub endpoint: Option<String>,
+    pub api_key: Option<String>,
+}
+
+/// Vector of a single caller with its share of the batch usage
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchItem {
+    pub model: String,
+    pub embedding: Vec<f32>,
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl From<BatchItem> for CreateEmbeddingResponse {
+    fn from(item: BatchItem) -> Self {
+        CreateEmbeddingResponse {
+            object: "list".to_string(),
+            model: item.model,
+            data: vec![Embedding {
+                index: 0,
+                object: "embedding".to_string(),
+                embedding: item.embedding,
+            }],
+            usage: EmbeddingUsage {
+                prompt_tokens: item.prompt_tokens,
+                total_tokens: item.total_tokens,
+            },
+        }
+    }
+}
+
+struct PendingInput {
+    text: String,
+    sender: oneshot::Sender<Result<BatchItem, String>>,
+}
+
+struct PendingBatch {
+    id: u64,
+    embed: OpenAIEmbed,
+    inputs: Vec<PendingInput>,
+}
+
+/// Micro-batches single input embedding requests that arrive within a short window
+/// into one provider call, then hands every caller back its own vector.
+pub struct EmbeddingCoalescer {
+    config: CoalescingConfig,
+    pending: Arc<Mutex<HashMap<BatchKey, PendingBatch>>>,
+    next_id: AtomicU64,
+}
+
+impl EmbeddingCoalescer {
+    pub fn new(config: CoalescingConfig) -> Self {
+        Self {
+            config,
+            pending: Default::default(),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn embed(
+        &self,
+        key: BatchKey,
+        embed: &OpenAIEmbed,
+        text: String,
+    ) -> Result<BatchItem, GatewayError> {
+        let (sender, receiver) = oneshot::channel();
+        let input = PendingInput { text, sender };
+
+        let full_batch = {
+            let mut pending = self.pending.lock();
+            match pending.entry(key.clone()) {
+                Entry::Occupied(mut entry) => {
+                    entry.get_mut().inputs.push(input);
+                    (entry.get().inputs.len() >= self.config.max_batch_size).then(|| entry.remove())
+                }
+                Entry::Vacant(entry) => {
+                    let batch = PendingBatch {
+                        id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                        embed: embed.clone(),
+                        inputs: vec![input],
+                    };
+                    if self.config.max_batch_size <= 1 {
+                        Some(batch)
+                    } else {
+                        self.schedule_flush(key, batch.id);
+                        entry.insert(batch);
+                        None
+                    }
+                }
+            }
+        };
+
+        if let Some(batch) = full_batch {
+            tokio::spawn(flush(batch));
+        }
+
+        receiver
+            .await
+            .map_err(|_| GatewayError::CustomError("Embedding batch was dropped".to_string()))?
+            .map_err(GatewayError::CustomError)
+    }
+
+    fn schedule_flush(&self, key: BatchKey, id: u64) {
+        let pending = self.pending.clone();
+        let window = Duration::from_millis(self.config.window_ms);
+        tokio::spawn(async move {
+            tokio::time::sleep(window).await;
+            let batch = {
+                let mut pending = pending.lock();
+                // The batch may have been sent early after reaching the max size
+                match pending.get(&key) {
+                    Some(batch) if batch.id == id => pending.remove(&key),
+                    _ => None,
+                }
+            };
+            if let Some(batch) = batch {
+                flush(batch).await;
+            }
+        });
+    }
+}
+
+async fn flush(batch: PendingBatch) {
+    let PendingBatch { embed, inputs, .. } = batch;
+    let (texts, senders): (Vec<_>, Vec<_>) = inputs
+        .into_iter()
+        .map(|input| (input.text, input.sender))
+        .unzip();
+    let lengths: Vec<usize> = texts.iter().map(|t| t.len()).collect();
+
+    let results = match embed.invoke(texts.into(), None).await {
+        Ok(response) => split_response(response, &lengths),
+        Err(e) => vec![Err(e.to_string()); lengths.len()],
+    };
+
+    for (sender, result) in senders.into_iter().zip(results) {
+        // The caller may have gone away in the meantime
+        let _ = sender.send(result);
+    }
+}
+
+/// Assigns every input its vector by index and a share of the usage proportional to its length
+fn split_response(
+    response: CreateEmbeddingResponse,
+    lengths: &[usize],
+) -> Vec<Result<BatchItem, String>> {
+    let prompt_tokens = split_tokens(response.usage.prompt_tokens, lengths);
+    let total_tokens = split_tokens(response.usage.total_tokens, lengths);
+    let mut vectors: HashMap<u32, Vec<f32>> = response
+        .data
+        .into_iter()
+        .map(|e| (e.index, e.embedding))
+        .collect();
+
+    (0..lengths.len())
+        .map(|index| {
+            let embedding = vectors
+                .remove(&(index as u32))
+                .ok_or_else(|| format!("Embedding {index} is missing from the batch response"))?;
+            Ok(BatchItem {
+                model: response.model.clone(),
+                embedding,
+                prompt_tokens: prompt_tokens[index],
+                total_tokens: total_tokens[index],
+            })
+        })
+        .collect()
+}
+
+/// Splits tokens proportionally to the lengths, the last input takes the rounding remainder
+fn split_tokens(tokens: u32, lengths: &[usize]) -> Vec<u32> {
+    let total_length: usize = lengths.iter().sum();
+    if lengths.is_empty() {
+        return vec![];
+    }
+
+    let mut shares: Vec<u32> = lengths
+        .iter()
+        .map(|length| match total_length {
+            0 => tokens / lengths.len() as u32,
+            _ => (tokens as u64 * *length as u64 / total_length as u64) as u32,
+        })
+        .collect();
+    let assigned: u32 = shares.iter().sum();
+    if let Some(last) = shares.last_mut() {
+        *last += tokens - assigned;
+    }
+    shares
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_tokens_sums_to_total() {
+        let shares = split_tokens(10, &[1, 1, 1]);
+        assert_eq!(shares, vec![3, 3, 4]);
+        assert_eq!(split_tokens(12, &[10, 30, 20]), vec![2, 6, 4]);
+        assert_eq!(split_tokens(5, &[0, 0]), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_split_response_matches_indexes() {
+        let response = CreateEmbeddingResponse {
+            object: "list".to_string(),
+            model: "text-embedding-3-small".to_string(),
+            data: vec![
+                Embedding {
+                    index: 1,
+                    object: "embedding".to_string(),
+                    embedding: vec![2.0],
+                },
+                Embedding {
+                    index: 0,
+                    object: "embedding".to_string(),
+                    embedding: vec![1.0],
+                },
+            ],
+            usage: EmbeddingUsage {
+                prompt_tokens: 4,
+                total_tokens: 4,
+            },
+        };
+
+        let results = split_response(response, &[2, 2, 2]);
+
+        assert_eq!(results[0].as_ref().unwrap().embedding, vec![1.0]);
+        assert_eq!(results[1].as_ref().unwrap().embedding, vec![2.0]);
+        assert_eq!(results[0].as_ref().unwrap().prompt_tokens, 1);
+        assert!(results[2].is_err());
+    }
+}
@@ -1,20 +1,31 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Range;
 
 use crate::embed_mod::Embed;
 use crate::embed_mod::OpenAIEmbed;
-use crate::error::GatewayError;
-use crate::model::types::ModelEvent;
+use crate::embed_mod::ProviderEmbed;
+use crate::error::{GatewayError, InvalidRequestError};
+use crate::events::SPAN_OPENAI;
+use crate::executor::embedding_cache::{CachedInputs, EmbeddingCache, EmbeddingCacheConfig};
+use crate::executor::embedding_coalescing::{
+    BatchItem, BatchKey, CoalescingConfig, EmbeddingCoalescer,
+};
+use crate::model::types::{LLMFinishEvent, ModelEvent, ModelEventType, ModelFinishReason};
 use crate::models::ModelMetadata;
 use crate::types::credentials::ApiKeyCredentials;
 use crate::types::credentials::Credentials;
+use crate::types::gateway::CompletionModelUsage;
 use actix_web::HttpRequest;
-use async_openai::types::EmbeddingInput;
+use async_openai::types::{CreateEmbeddingResponse, Embedding, EmbeddingInput, EmbeddingUsage};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use tracing::Span;
 
 use crate::types::embed::OpenAiEmbeddingParams;
 use crate::types::{
     engine::{Model, ModelTools, ModelType},
-    gateway::{CreateEmbeddingRequest, Input},
+    gateway::{CreateEmbeddingRequest, EmbeddingError, Input},
 };
 use tracing_futures::Instrument;
 
@@ -23,21 +34,131 @@ use crate::handler::{CallbackHandlerFn, ModelEventWithDetails};
 use super::get_key_credentials;
 use super::ProvidersConfig;
 
+const BEST_EFFORT_CONCURRENCY: usize = 8;
+const BATCH_CONCURRENCY: usize = 4;
+/// OpenAI limits of a single embeddings request
+const DEFAULT_MAX_INPUTS_PER_REQUEST: usize = 2048;
+const DEFAULT_MAX_TOKENS_PER_REQUEST: usize = 300_000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct EmbeddingsConfig {
+    /// L2-normalize vectors returned for every model
+    #[serde(default)]
+    pub normalize: bool,
+    /// Models whose vectors are always L2-normalized
+    #[serde(default)]
+    pub normalize_models: HashSet<String>,
+    /// Model aliases that embed the input with several models and combine the vectors
+    #[serde(default)]
+    pub ensembles: HashMap<String, EmbeddingEnsemble>,
+    /// Micro-batches single input requests arriving within a short window
+    #[serde(default)]
+    pub coalescing: Option<CoalescingConfig>,
+    /// Larger input arrays are split into several provider requests, defaults to 2048
+    #[serde(default)]
+    pub max_inputs_per_request: Option<usize>,
+    /// Estimated token budget of a single provider request, defaults to 300000
+    #[serde(default)]
+    pub max_tokens_per_request: Option<usize>,
+    /// Skips the provider call for inputs embedded before
+    #[serde(default)]
+    pub cache: Option<EmbeddingCacheConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmbeddingEnsemble {
+    /// Underlying models, in the order their vectors are combined
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub combine: CombineStrategy,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CombineStrategy {
+    /// Concatenate vectors, the result has the sum of all dimensions
+    #[default]
+    Concat,
+    /// Element-wise average, all models must return the same dimensions
+    Mean,
+}
+
+impl EmbeddingsConfig {
+    pub fn should_normalize(&self, model: &str) -> bool {
+        self.normalize || self.normalize_models.contains(model)
+    }
+
+    fn batch_limits(config: Option<&Self>) -> (usize, usize) {
+        (
+            config
+                .and_then(|c| c.max_inputs_per_request)
+                .unwrap_or(DEFAULT_MAX_INPUTS_PER_REQUEST),
+            config
+                .and_then(|c| c.max_tokens_per_request)
+                .unwrap_or(DEFAULT_MAX_TOKENS_PER_REQUEST),
+        )
+    }
+}
+
+pub struct EmbeddingsResult {
+    pub response: CreateEmbeddingResponse,
+    /// Inputs that failed in best effort mode
+    pub errors: Vec<EmbeddingError>,
+}
+
 pub async fn handle_embeddings_invoke(
     mut request: CreateEmbeddingRequest,
     callback_handler: &CallbackHandlerFn,
     llm_model: &ModelMetadata,
     key_credentials: Option<&Credentials>,
     req: HttpRequest,
-) -> Result<async_openai::types::CreateEmbeddingResponse, GatewayError> {
+) -> Result<EmbeddingsResult, GatewayError> {
     let span = Span::current();
     request.model = llm_model.inference_provider.model_name.clone();
+    if let Some(dimensions) = &llm_model.dimensions {
+        request.dimensions = dimensions
+            .resolve(request.dimensions)
+            .map_err(|message| InvalidRequestError::new("dimensions", "invalid_value", message))?;
+    }
 
     let params = OpenAiEmbeddingParams {
         model: Some(llm_model.model.clone()),
         dimensions: request.dimensions,
     };
 
+    let normalize = should_normalize(&request, &req, &llm_model.model);
+
+    let embedding_cache = req.app_data::<Arc<dyn EmbeddingCache>>().cloned();
+    let mut cached = None;
+    if let Some(cache) = &embedding_cache {
+        let lookup = CachedInputs::lookup(
+            cache.as_ref(),
+            &llm_model.model,
+            request.dimensions,
+            &request.input,
+        )
+        .await;
+        match lookup.missing_input(&request.input) {
+            Some(input) => request.input = input,
+            None => {
+                let result = lookup.merge(EmbeddingsResult {
+                    response: CreateEmbeddingResponse {
+                        object: "list".to_string(),
+                        model: request.model.clone(),
+                        data: vec![],
+                        usage: EmbeddingUsage {
+                            prompt_tokens: 0,
+                            total_tokens: 0,
+                        },
+                    },
+                    errors: vec![],
+                });
+                return Ok(post_process(result, request.dimensions, normalize));
+            }
+        }
+        cached = Some(lookup);
+    }
+
     let input: EmbeddingInput = match &request.input {
         Input::String(s) => s.into(),
         Input::Array(vec) => vec.into(),
@@ -45,6 +166,7 @@ pub async fn handle_embeddings_invoke(
 
     let (tx, mut rx) = tokio::sync::mpsc::channel::<Option<ModelEvent>>(1000);
     let model_name = llm_model.model.clone();
+    let provider_name = llm_model.inference_provider.provider.to_string();
 
     let callback_handler = callback_handler.clone();
     tokio::spawn(async move {
@@ -54,7 +176,7 @@ pub async fn handle_embeddings_invoke(
                 Model {
                     name: model_name.clone(),
                     description: None,
-                    provider_name: "openai".to_string(),
+                    provider_name: provider_name.clone(),
                     prompt_name: None,
                     model_params: HashMap::new(),
                     tools: ModelTools(vec![]),
@@ -85,9 +207,704 @@ pub async fn handle_embeddings_invoke(
         _ => None,
     };
 
-    let embed = OpenAIEmbed::new(params, key.as_ref(), custom_endpoint.as_deref())?;
-    embed
-        .invoke(input, Some(tx.clone()))
-        .instrument(span.clone())
+    let embed = ProviderEmbed::new(
+        &llm_model.inference_provider.provider,
+        params,
+        request.input_type,
+        key.as_ref(),
+        custom_endpoint.as_deref(),
+    )?;
+    let coalescer = req.app_data::<Arc<EmbeddingCoalescer>>();
+    let result = match (coalescer, &request.input, &embed) {
+        (Some(coalescer), Input::String(text), ProviderEmbed::OpenAI(embed)) => {
+            let key = BatchKey {
+                model: llm_model.model.clone(),
+                dimensions: request.dimensions,
+                endpoint: custom_endpoint.clone(),
+                api_key: key.as_ref().map(|k| k.api_key.clone()),
+            };
+            coalescer
+                .embed(key, embed, text.clone())
+                .instrument(span.clone())
+                .await
+                .map(|item| {
+                    send_batch_item_usage(embed, &item, &span, &tx);
+                    item.into()
+                })
+        }
+        (_, Input::Array(inputs), _) => {
+            let (max_inputs, max_tokens) =
+                EmbeddingsConfig::batch_limits(req.app_data::<EmbeddingsConfig>());
+            let batches = batch_ranges(inputs, max_inputs, max_tokens);
+            if batches.len() > 1 {
+                invoke_batches(&embed, inputs, batches, &tx)
+                    .instrument(span.clone())
+                    .await
+            } else {
+                embed
+                    .invoke(input, Some(tx.clone()))
+                    .instrument(span.clone())
+                    .await
+            }
+        }
+        _ => {
+            embed
+                .invoke(input, Some(tx.clone()))
+                .instrument(span.clone())
+                .await
+        }
+    };
+
+    let result = match (result, &request.input) {
+        (Ok(response), _) => EmbeddingsResult {
+            response,
+            errors: vec![],
+        },
+        (Err(e), Input::Array(inputs)) if request.best_effort && inputs.len() > 1 => {
+            tracing::warn!("Batch embedding failed: {e}, embedding inputs one by one");
+            invoke_best_effort(&embed, inputs, &tx)
+                .instrument(span.clone())
+                .await?
+        }
+        (Err(e), _) => return Err(e),
+    };
+
+    // Raw provider vectors are cached, truncation and normalization apply on every request
+    let result = match (cached, &embedding_cache) {
+        (Some(cached), Some(cache)) => {
+            cached.store(cache.as_ref(), &result).await;
+            cached.merge(result)
+        }
+        _ => result,
+    };
+
+    Ok(post_process(result, request.dimensions, normalize))
+}
+
+fn post_process(
+    mut result: EmbeddingsResult,
+    dimensions: Option<u32>,
+    normalize: bool,
+) -> EmbeddingsResult {
+    for data in result.response.data.iter_mut() {
+        // Providers that ignore `dimensions` return full vectors, truncate them before normalizing
+        if let Some(dimensions) = dimensions {
+            data.embedding.truncate(dimensions as usize);
+        }
+        if normalize {
+            l2_normalize(&mut data.embedding);
+        }
+    }
+    result
+}
+
+/// Embeds the input with every model of the ensemble and combines the vectors per input.
+pub async fn handle_ensemble_embeddings_invoke(
+    request: CreateEmbeddingRequest,
+    alias: &str,
+    ensemble: &EmbeddingEnsemble,
+    callback_handler: &CallbackHandlerFn,
+    llm_models: &[ModelMetadata],
+    key_credentials: Option<&Credentials>,
+    req: HttpRequest,
+) -> Result<EmbeddingsResult, GatewayError> {
+    let results = futures::future::try_join_all(llm_models.iter().map(|llm_model| {
+        handle_embeddings_invoke(
+            request.clone(),
+            callback_handler,
+            llm_model,
+            key_credentials,
+            req.clone(),
+        )
+    }))
+    .await?;
+
+    let mut result = combine_embeddings(results, ensemble.combine)?;
+    result.response.model = alias.to_string();
+
+    if should_normalize(&request, &req, alias) {
+        for data in result.response.data.iter_mut() {
+            l2_normalize(&mut data.embedding);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Batched calls are sent without events, every caller reports its own share of the usage
+fn send_batch_item_usage(
+    embed: &OpenAIEmbed,
+    item: &BatchItem,
+    span: &Span,
+    tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+) {
+    let event = ModelEvent::new(
+        span,
+        ModelEventType::LlmStop(LLMFinishEvent {
+            provider_name: SPAN_OPENAI.to_string(),
+            model_name: item.model.clone(),
+            output: None,
+            usage: Some(CompletionModelUsage {
+                input_tokens: item.prompt_tokens,
+                output_tokens: 0,
+                total_tokens: item.total_tokens,
+                ..Default::default()
+            }),
+            finish_reason: ModelFinishReason::Stop,
+            tool_calls: vec![],
+            credentials_ident: embed.credentials_ident().clone(),
+            generation: None,
+        }),
+    );
+    if let Err(e) = tx.try_send(Some(event)) {
+        tracing::error!("Failed to report batched embedding usage: {e}");
+    }
+}
+
+fn should_normalize(request: &CreateEmbeddingRequest, req: &HttpRequest, model: &str) -> bool {
+    request.normalize
+        || req
+            .app_data::<EmbeddingsConfig>()
+            .is_some_and(|c| c.should_normalize(model))
+}
+
+/// Combines index-aligned results of the ensemble models. Inputs missing from any
+/// model are reported as errors, usage is summed across all models.
+fn combine_embeddings(
+    results: Vec<EmbeddingsResult>,
+    strategy: CombineStrategy,
+) -> Result<EmbeddingsResult, GatewayError> {
+    let mut usage = EmbeddingUsage {
+        prompt_tokens: 0,
+        total_tokens: 0,
+    };
+    let mut errors: BTreeMap<u32, EmbeddingError> = BTreeMap::new();
+    let mut vectors: Vec<HashMap<u32, Vec<f32>>> = Vec::with_capacity(results.len());
+    let mut indexes = BTreeSet::new();
+
+    for result in results {
+        usage.prompt_tokens += result.response.usage.prompt_tokens;
+        usage.total_tokens += result.response.usage.total_tokens;
+        for error in result.errors {
+            errors.entry(error.index).or_insert(error);
+        }
+        indexes.extend(result.response.data.iter().map(|e| e.index));
+        vectors.push(
+            result
+                .response
+                .data
+                .into_iter()
+                .map(|e| (e.index, e.embedding))
+                .collect(),
+        );
+    }
+
+    let mut data = vec![];
+    for index in indexes {
+        if errors.contains_key(&index) {
+            continue;
+        }
+
+        let parts: Option<Vec<Vec<f32>>> = vectors.iter_mut().map(|v| v.remove(&index)).collect();
+        let Some(parts) = parts else {
+            errors.insert(
+                index,
+                EmbeddingError {
+                    index,
+                    error: "Embedding is missing from one of the ensemble models".to_string(),
+                },
+            );
+            continue;
+        };
+
+        let embedding = match strategy {
+            CombineStrategy::Concat => parts.concat(),
+            CombineStrategy::Mean => {
+                let dimensions = parts[0].len();
+                if parts.iter().any(|p| p.len() != dimensions) {
+                    return Err(GatewayError::CustomError(
+                        "Mean ensemble requires models with equal embedding dimensions".to_string(),
+                    ));
+                }
+                let count = parts.len() as f32;
+                (0..dimensions)
+                    .map(|i| parts.iter().map(|p| p[i]).sum::<f32>() / count)
+                    .collect()
+            }
+        };
+
+        data.push(Embedding {
+            index,
+            object: "embedding".to_string(),
+            embedding,
+        });
+    }
+
+    if data.is_empty() {
+        if let Some(error) = errors.values().next() {
+            return Err(GatewayError::CustomError(error.error.clone()));
+        }
+    }
+
+    Ok(EmbeddingsResult {
+        response: CreateEmbeddingResponse {
+            object: "list".to_string(),
+            model: String::new(),
+            data,
+            usage,
+        },
+        errors: errors.into_values().collect(),
+    })
+}
+
+/// Scales the vector to unit length. Zero vectors are left unchanged.
+pub fn l2_normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+/// Splits the inputs into consecutive ranges within the per request limits. Tokens are
+/// estimated at four characters per token.
+fn batch_ranges(inputs: &[String], max_inputs: usize, max_tokens: usize) -> Vec<Range<usize>> {
+    let max_inputs = max_inputs.max(1);
+    let mut batches = vec![];
+    let mut start = 0;
+    let mut tokens = 0;
+
+    for (index, input) in inputs.iter().enumerate() {
+        let input_tokens = input.len() / 4 + 1;
+        if index > start && (index - start >= max_inputs || tokens + input_tokens > max_tokens) {
+            batches.push(start..index);
+            start = index;
+            tokens = 0;
+        }
+        tokens += input_tokens;
+    }
+
+    if start < inputs.len() {
+        batches.push(start..inputs.len());
+    }
+
+    batches
+}
+
+/// Embeds every batch with its own provider request, any failed batch fails the whole input
+async fn invoke_batches(
+    embed: &impl Embed,
+    inputs: &[String],
+    batches: Vec<Range<usize>>,
+    tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+) -> Result<CreateEmbeddingResponse, GatewayError> {
+    let responses = futures::stream::iter(batches)
+        .map(|batch| async move {
+            let response = embed
+                .invoke(inputs[batch.clone()].to_vec().into(), Some(tx.clone()))
+                .await?;
+            Ok::<_, GatewayError>((batch.start, response))
+        })
+        .buffered(BATCH_CONCURRENCY)
+        .collect::<Vec<_>>()
         .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(merge_batches(responses))
+}
+
+/// Offsets the indexes of every batch by its position in the input and sums the usage
+fn merge_batches(responses: Vec<(usize, CreateEmbeddingResponse)>) -> CreateEmbeddingResponse {
+    let mut data = vec![];
+    let mut usage = EmbeddingUsage {
+        prompt_tokens: 0,
+        total_tokens: 0,
+    };
+    let mut object = "list".to_string();
+    let mut model = String::new();
+
+    for (offset, response) in responses {
+        usage.prompt_tokens += response.usage.prompt_tokens;
+        usage.total_tokens += response.usage.total_tokens;
+        object = response.object;
+        model = response.model;
+        data.extend(response.data.into_iter().map(|mut e| {
+            e.index += offset as u32;
+            e
+        }));
+    }
+    data.sort_by_key(|e| e.index);
+
+    CreateEmbeddingResponse {
+        object,
+        model,
+        data,
+        usage,
+    }
+}
+
+async fn invoke_best_effort(
+    embed: &impl Embed,
+    inputs: &[String],
+    tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+) -> Result<EmbeddingsResult, GatewayError> {
+    let results: Vec<_> = futures::stream::iter(inputs.iter().enumerate())
+        .map(|(index, text)| async move {
+            (
+                index as u32,
+                embed.invoke(text.into(), Some(tx.clone())).await,
+            )
+        })
+        .buffered(BEST_EFFORT_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut data = vec![];
+    let mut errors = vec![];
+    let mut first_error = None;
+    let mut usage = EmbeddingUsage {
+        prompt_tokens: 0,
+        total_tokens: 0,
+    };
+    let mut object = "list".to_string();
+    let mut model = String::new();
+
+    for (index, result) in results {
+        match result {
+            Ok(response) => {
+                usage.prompt_tokens += response.usage.prompt_tokens;
+                usage.total_tokens += response.usage.total_tokens;
+                object = response.object;
+                model = response.model;
+                data.extend(response.data.into_iter().map(|mut e| {
+                    e.index = index;
+                    e
+                }));
+            }
+            Err(e) => {
+                errors.push(EmbeddingError {
+                    index,
+                    error: e.to_string(),
+                });
+                if first_error.is_none() {
+                    first_error = Some(e);
+                }
+            }
+        }
+    }
+
+    if data.is_empty() {
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+    }
+
+    Ok(EmbeddingsResult {
+        response: CreateEmbeddingResponse {
+            object,
+            model,
+            data,
+            usage,
+        },
+        errors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EmbeddingDimensions;
+    use crate::GatewayResult;
+    use futures::Stream;
+    use serde_json::Value;
+
+    fn norm(vector: &[f32]) -> f32 {
+        vector.iter().map(|v| v * v).sum::<f32>().sqrt()
+    }
+
+    fn embedding(values: Vec<f32>) -> EmbeddingsResult {
+        EmbeddingsResult {
+            response: CreateEmbeddingResponse {
+                object: "list".to_string(),
+                model: "text-embedding-3-small".to_string(),
+                data: vec![Embedding {
+                    index: 0,
+                    object: "embedding".to_string(),
+                    embedding: values,
+                }],
+                usage: EmbeddingUsage {
+                    prompt_tokens: 1,
+                    total_tokens: 1,
+                },
+            },
+            errors: vec![],
+        }
+    }
+
+    #[test]
+    fn test_resolve_dimensions() {
+        // text-embedding-3-small reduces its 1536 dimensions to any size
+        let reducible = EmbeddingDimensions {
+            default: 1536,
+            supported: vec![],
+            reducible: true,
+        };
+        assert_eq!(reducible.resolve(None), Ok(None));
+        assert_eq!(reducible.resolve(Some(256)), Ok(Some(256)));
+        assert_eq!(
+            reducible.resolve(Some(3072)).unwrap_err(),
+            "Invalid `dimensions` 3072, must be between 1 and 1536"
+        );
+
+        let listed = EmbeddingDimensions {
+            default: 1024,
+            supported: vec![256, 512, 1024],
+            reducible: true,
+        };
+        assert_eq!(listed.resolve(Some(512)), Ok(Some(512)));
+        assert_eq!(
+            listed.resolve(Some(300)).unwrap_err(),
+            "Invalid `dimensions` 300, must be one of 256, 512, 1024"
+        );
+
+        // The full size of a model without dimension reduction is sent without `dimensions`
+        let fixed = EmbeddingDimensions {
+            default: 1536,
+            supported: vec![],
+            reducible: false,
+        };
+        assert_eq!(fixed.resolve(Some(1536)), Ok(None));
+        assert!(fixed.resolve(Some(512)).is_err());
+    }
+
+    #[test]
+    fn test_post_process_truncates_and_normalizes() {
+        let result = post_process(embedding(vec![3.0, 4.0, 12.0]), Some(2), true);
+        assert_eq!(result.response.data[0].embedding, vec![0.6, 0.8]);
+
+        let result = post_process(embedding(vec![3.0, 4.0, 12.0]), None, false);
+        assert_eq!(result.response.data[0].embedding, vec![3.0, 4.0, 12.0]);
+    }
+
+    #[test]
+    fn test_l2_normalize_unit_norm() {
+        let mut vector = vec![3.0, 4.0, 12.0];
+        l2_normalize(&mut vector);
+        assert!((norm(&vector) - 1.0).abs() < 1e-6);
+        assert!((vector[0] - 3.0 / 13.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_l2_normalize_after_truncation() {
+        let mut vector = vec![0.6, 0.8, 5.0, 7.0];
+        vector.truncate(2);
+        l2_normalize(&mut vector);
+        assert!((norm(&vector) - 1.0).abs() < 1e-6);
+        assert_eq!(vector, vec![0.6, 0.8]);
+    }
+
+    #[test]
+    fn test_l2_normalize_zero_vector() {
+        let mut vector = vec![0.0; 4];
+        l2_normalize(&mut vector);
+        assert_eq!(vector, vec![0.0; 4]);
+    }
+
+    fn result(vectors: Vec<(u32, Vec<f32>)>, tokens: u32) -> EmbeddingsResult {
+        EmbeddingsResult {
+            response: CreateEmbeddingResponse {
+                object: "list".to_string(),
+                model: "model".to_string(),
+                data: vectors
+                    .into_iter()
+                    .map(|(index, embedding)| Embedding {
+                        index,
+                        object: "embedding".to_string(),
+                        embedding,
+                    })
+                    .collect(),
+                usage: EmbeddingUsage {
+                    prompt_tokens: tokens,
+                    total_tokens: tokens,
+                },
+            },
+            errors: vec![],
+        }
+    }
+
+    #[test]
+    fn test_combine_concat() {
+        let combined = combine_embeddings(
+            vec![
+                result(vec![(0, vec![1.0, 2.0]), (1, vec![3.0, 4.0])], 5),
+                result(vec![(0, vec![5.0, 6.0, 7.0]), (1, vec![8.0, 9.0, 10.0])], 7),
+            ],
+            CombineStrategy::Concat,
+        )
+        .unwrap();
+
+        assert_eq!(combined.response.data.len(), 2);
+        assert_eq!(
+            combined.response.data[0].embedding,
+            vec![1.0, 2.0, 5.0, 6.0, 7.0]
+        );
+        assert_eq!(combined.response.data[1].embedding.len(), 5);
+        assert_eq!(combined.response.usage.prompt_tokens, 12);
+        assert_eq!(combined.response.usage.total_tokens, 12);
+    }
+
+    #[test]
+    fn test_combine_mean() {
+        let combined = combine_embeddings(
+            vec![
+                result(vec![(0, vec![1.0, 2.0])], 1),
+                result(vec![(0, vec![3.0, 4.0])], 1),
+            ],
+            CombineStrategy::Mean,
+        )
+        .unwrap();
+
+        assert_eq!(combined.response.data[0].embedding, vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_combine_mean_dimension_mismatch() {
+        let combined = combine_embeddings(
+            vec![
+                result(vec![(0, vec![1.0, 2.0])], 1),
+                result(vec![(0, vec![3.0])], 1),
+            ],
+            CombineStrategy::Mean,
+        );
+
+        assert!(combined.is_err());
+    }
+
+    #[test]
+    fn test_combine_reports_missing_inputs() {
+        let combined = combine_embeddings(
+            vec![
+                result(vec![(0, vec![1.0]), (1, vec![2.0])], 1),
+                result(vec![(0, vec![3.0])], 1),
+            ],
+            CombineStrategy::Concat,
+        )
+        .unwrap();
+
+        assert_eq!(combined.response.data.len(), 1);
+        assert_eq!(combined.errors.len(), 1);
+        assert_eq!(combined.errors[0].index, 1);
+    }
+
+    #[test]
+    fn test_should_normalize() {
+        let config = EmbeddingsConfig {
+            normalize: false,
+            normalize_models: HashSet::from(["text-embedding-3-small".to_string()]),
+            ensembles: HashMap::new(),
+            coalescing: None,
+            max_inputs_per_request: None,
+            max_tokens_per_request: None,
+            cache: None,
+        };
+        assert!(config.should_normalize("text-embedding-3-small"));
+        assert!(!config.should_normalize("text-embedding-ada-002"));
+    }
+
+    #[test]
+    fn test_batch_ranges() {
+        let inputs: Vec<String> = (0..5).map(|i| format!("input {i}")).collect();
+        assert_eq!(batch_ranges(&inputs, 2, usize::MAX), vec![0..2, 2..4, 4..5]);
+        assert_eq!(batch_ranges(&inputs, 10, usize::MAX), vec![0..5]);
+        // Every input is estimated at 2 tokens
+        assert_eq!(batch_ranges(&inputs, 10, 5), vec![0..2, 2..4, 4..5]);
+        // An input over the token budget still gets its own batch
+        assert_eq!(
+            batch_ranges(&inputs, 10, 1),
+            vec![0..1, 1..2, 2..3, 3..4, 4..5]
+        );
+        assert!(batch_ranges(&[], 10, 10).is_empty());
+    }
+
+    #[test]
+    fn test_merge_batches_preserves_order() {
+        let merged = merge_batches(vec![
+            (0, result(vec![(1, vec![1.0]), (0, vec![0.0])], 3).response),
+            (2, result(vec![(0, vec![2.0]), (1, vec![3.0])], 4).response),
+            (4, result(vec![(0, vec![4.0])], 2).response),
+        ]);
+
+        assert_eq!(
+            merged
+                .data
+                .iter()
+                .map(|e| (e.index, e.embedding[0]))
+                .collect::<Vec<_>>(),
+            vec![(0, 0.0), (1, 1.0), (2, 2.0), (3, 3.0), (4, 4.0)]
+        );
+        assert_eq!(merged.usage.prompt_tokens, 9);
+        assert_eq!(merged.usage.total_tokens, 9);
+    }
+
+    /// Embeds a text as its length, failing for texts containing "fail"
+    struct LengthEmbed;
+
+    impl Embed for LengthEmbed {
+        async fn invoke(
+            &self,
+            input_text: EmbeddingInput,
+            _tx: Option<tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+        ) -> GatewayResult<CreateEmbeddingResponse> {
+            let EmbeddingInput::String(text) = input_text else {
+                unreachable!("best effort embeds one text per call")
+            };
+            if text.contains("fail") {
+                return Err(GatewayError::CustomError(format!("Failed to embed {text}")));
+            }
+
+            Ok(embedding(vec![text.len() as f32]).response)
+        }
+
+        async fn batched_invoke(
+            &self,
+            _inputs: impl Stream<Item = GatewayResult<(String, Vec<Value>)>>,
+        ) -> impl Stream<Item = GatewayResult<Vec<(Vec<f32>, Vec<Value>)>>> {
+            futures::stream::empty()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_best_effort_keeps_successful_inputs() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        let inputs = ["a", "fail", "abc"].map(String::from);
+
+        let result = invoke_best_effort(&LengthEmbed, &inputs, &tx)
+            .await
+            .unwrap();
+        assert_eq!(
+            result
+                .response
+                .data
+                .iter()
+                .map(|e| (e.index, e.embedding[0]))
+                .collect::<Vec<_>>(),
+            vec![(0, 1.0), (2, 3.0)]
+        );
+        assert_eq!(result.response.usage.total_tokens, 2);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].index, 1);
+        assert!(result.errors[0].error.contains("Failed to embed fail"));
+    }
+
+    #[tokio::test]
+    async fn test_best_effort_fails_without_embeddings() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        let inputs = ["fail 1", "fail 2"].map(String::from);
+
+        let error = invoke_best_effort(&LengthEmbed, &inputs, &tx)
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("fail 1"));
+    }
 }
@@ -25,6 +25,7 @@ use tracing::Span;
 use tracing_futures::Instrument;
 
 use super::get_key_credentials;
+use super::image_storage::{apply_response_format, ImageStore};
 use super::ProvidersConfig;
 
 pub async fn handle_image_generation(
@@ -100,12 +101,20 @@ pub async fn handle_image_generation(
     .await
     .map_err(|e| GatewayError::CustomError(e.to_string()))?;
 
-    let result = model
+    let mut result = model
         .create_new(&request, tx, tags.clone())
         .instrument(span.clone())
         .await?;
 
     let _stop_event = handle.await.unwrap();
 
+    let image_store = req.app_data::<Arc<dyn ImageStore>>();
+    apply_response_format(
+        &mut result,
+        request.response_format.as_ref(),
+        image_store.map(|s| s.as_ref()),
+    )
+    .await?;
+
     Ok(result)
 }
@@ -0,0 +1,183 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use bytes::Bytes;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::{GatewayError, InvalidRequestError};
+use crate::types::gateway::ImageResponseFormat;
+use crate::types::image::ImagesResponse;
+
+const FETCH_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageStorageConfig {
+    /// Address clients reach the gateway on, stored images are served under `/images/files`
+    pub public_url: String,
+    /// Images kept before the oldest is dropped
+    #[serde(default = "default_max_images")]
+    pub max_images: usize,
+}
+
+fn default_max_images() -> usize {
+    100
+}
+
+/// Storage of generated images returned as URLs when the provider only returned base64
+#[async_trait]
+pub trait ImageStore: Send + Sync {
+    /// Stores a PNG image and returns the URL it is served on
+    async fn store(&self, data: Bytes) -> Result<String, GatewayError>;
+
+    async fn get(&self, id: &str) -> Option<Bytes>;
+}
+
+/// Default store keeping the latest images in process memory
+pub struct InMemoryImageStore {
+    config: ImageStorageConfig,
+    images: Mutex<(HashMap<String, Bytes>, VecDeque<String>)>,
+}
+
+impl InMemoryImageStore {
+    pub fn new(config: ImageStorageConfig) -> Self {
+        Self {
+            config,
+            images: Mutex::new((HashMap::new(), VecDeque::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl ImageStore for InMemoryImageStore {
+    async fn store(&self, data: Bytes) -> Result<String, GatewayError> {
+        let id = Uuid::new_v4().to_string();
+        let mut guard = self.images.lock();
+        let (images, order) = &mut *guard;
+        images.insert(id.clone(), data);
+        order.push_back(id.clone());
+        while order.len() > self.config.max_images {
+            if let Some(oldest) = order.pop_front() {
+                images.remove(&oldest);
+            }
+        }
+
+        Ok(format!(
+            "{}/images/files/{id}",
+            self.config.public_url.trim_end_matches('/')
+        ))
+    }
+
+    async fn get(&self, id: &str) -> Option<Bytes> {
+        self.images.lock().0.get(id).cloned()
+    }
+}
+
+/// Converts the images to the format the client asked for, fetching URLs for base64 and
+/// storing base64 images for URLs
+pub async fn apply_response_format(
+    response: &mut ImagesResponse,
+    format: Option<&ImageResponseFormat>,
+    store: Option<&dyn ImageStore>,
+) -> Result<(), GatewayError> {
+    for image in response.data.iter_mut() {
+        match format {
+            Some(ImageResponseFormat::B64Json) if image.b64_json.is_none() => {
+                if let Some(url) = image.url.take() {
+                    let client = reqwest::Client::builder()
+                        .connect_timeout(FETCH_CONNECT_TIMEOUT)
+                        .timeout(FETCH_TIMEOUT)
+                        .build()?;
+                    let data = client
+                        .get(&url)
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .bytes()
+                        .await?;
+                    image.b64_json = Some(STANDARD.encode(data));
+                }
+            }
+            Some(ImageResponseFormat::Url) if image.url.is_none() => {
+                if let Some(b64_json) = image.b64_json.take() {
+                    let store = store.ok_or_else(|| {
+                        InvalidRequestError::new(
+                            "response_format",
+                            "invalid_value",
+                            "The model returns base64 images and no image storage is configured to serve them as URLs, use response_format b64_json",
+                        )
+                    })?;
+                    let data = STANDARD.decode(b64_json)?;
+                    image.url = Some(store.store(Bytes::from(data)).await?);
+                }
+            }
+            // Without a requested format the provider response is returned as is
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::image::Image;
+
+    fn response(b64_json: Option<&str>, url: Option<&str>) -> ImagesResponse {
+        ImagesResponse {
+            created: None,
+            data: vec![Image {
+                b64_json: b64_json.map(str::to_string),
+                url: url.map(str::to_string),
+                revised_prompt: None,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_base64_served_as_url() {
+        let store = InMemoryImageStore::new(ImageStorageConfig {
+            public_url: "http://localhost:8080/".to_string(),
+            max_images: 1,
+        });
+        let mut images = response(Some(&STANDARD.encode(b"png")), None);
+        apply_response_format(&mut images, Some(&ImageResponseFormat::Url), Some(&store))
+            .await
+            .unwrap();
+
+        let url = images.data[0].url.clone().unwrap();
+        assert!(images.data[0].b64_json.is_none());
+        let id = url
+            .strip_prefix("http://localhost:8080/images/files/")
+            .unwrap();
+        assert_eq!(store.get(id).await, Some(Bytes::from_static(b"png")));
+
+        // The oldest image is dropped once the store is full
+        store.store(Bytes::from_static(b"next")).await.unwrap();
+        assert_eq!(store.get(id).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_url_without_storage() {
+        // The client asked for a format this gateway cannot serve
+        let mut images = response(Some("cG5n"), None);
+        let error = apply_response_format(&mut images, Some(&ImageResponseFormat::Url), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, GatewayError::InvalidRequest(_)));
+
+        // Images already in the requested format are left as they are
+        let mut images = response(None, Some("https://cdn.example.com/image.png"));
+        apply_response_format(&mut images, Some(&ImageResponseFormat::Url), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            images.data[0].url.as_deref(),
+            Some("https://cdn.example.com/image.png")
+        );
+    }
+}
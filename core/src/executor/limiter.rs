@@ -0,0 +1,355 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::models::ModelMetadata;
+use crate::types::gateway::{CompletionModelUsage, CostCalculator, Usage};
+use crate::GatewayApiError;
+
+/// Tokens used to price a nominal request when weights are derived from cost
+const NOMINAL_REQUEST_TOKENS: u32 = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModelWeightsConfig {
+    /// Shared budget of concurrency units for all models
+    pub max_concurrency_units: u32,
+    /// Shared budget of request units per minute
+    #[serde(default)]
+    pub max_rpm_units: Option<u32>,
+    /// Explicit weights keyed by model name
+    #[serde(default)]
+    pub weights: HashMap<String, ModelWeight>,
+    /// Cost of a nominal request (1k input and 1k output tokens) that maps to weight 1.
+    /// Models without explicit weights are weighted by their cost relative to it.
+    #[serde(default)]
+    pub reference_cost: Option<f64>,
+    #[serde(default = "default_wait_timeout_ms")]
+    pub wait_timeout_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ModelWeight {
+    #[serde(default)]
+    pub concurrency: Option<u32>,
+    #[serde(default)]
+    pub rpm: Option<u32>,
+}
+
+fn default_wait_timeout_ms() -> u64 {
+    5000
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ModelUtilization {
+    pub concurrency_weight: u32,
+    pub rpm_weight: u32,
+    pub in_flight_units: u32,
+    pub rpm_units: u32,
+    /// Share of the global concurrency budget currently held by the model
+    pub utilization: f64,
+}
+
+struct RpmWindow {
+    started_at: DateTime<Utc>,
+    used: u32,
+    per_model: HashMap<String, u32>,
+}
+
+/// Allocates a shared concurrency and RPM budget between models, charging
+/// each request by the weight of its model so expensive models are capped earlier.
+pub struct ModelConcurrencyLimiter {
+    config: ModelWeightsConfig,
+    semaphore: Arc<Semaphore>,
+    in_flight: Arc<DashMap<String, u32>>,
+    resolved_weights: DashMap<String, ModelWeight>,
+    rpm_window: Mutex<RpmWindow>,
+}
+
+pub struct ModelPermit {
+    model: String,
+    units: u32,
+    in_flight: Arc<DashMap<String, u32>>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for ModelPermit {
+    fn drop(&mut self) {
+        if let Some(mut units) = self.in_flight.get_mut(&self.model) {
+            *units = units.saturating_sub(self.units);
+        }
+    }
+}
+
+impl ModelConcurrencyLimiter {
+    pub fn new(config: ModelWeightsConfig) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(config.max_concurrency_units as usize)),
+            config,
+            in_flight: Arc::new(DashMap::new()),
+            resolved_weights: DashMap::new(),
+            rpm_window: Mutex::new(RpmWindow {
+                started_at: Utc::now(),
+                used: 0,
+                per_model: HashMap::new(),
+            }),
+        }
+    }
+
+    async fn weight(
+        &self,
+        model: &ModelMetadata,
+        cost_calculator: &dyn CostCalculator,
+    ) -> ModelWeight {
+        let name = model.qualified_model_name();
+        if let Some(weight) = self.resolved_weights.get(&name) {
+            return weight.clone();
+        }
+
+        let configured = self
+            .config
+            .weights
+            .get(&name)
+            .or_else(|| self.config.weights.get(&model.model))
+            .cloned()
+            .unwrap_or_default();
+
+        let cost_weight = match (configured.concurrency, configured.rpm) {
+            (Some(_), Some(_)) => None,
+            _ => self.cost_weight(model, cost_calculator).await,
+        };
+
+        let max_units = self.config.max_concurrency_units.max(1);
+        let weight = ModelWeight {
+            concurrency: Some(
+                configured
+                    .concurrency
+                    .or(cost_weight)
+                    .unwrap_or(1)
+                    .clamp(1, max_units),
+            ),
+            rpm: Some(configured.rpm.or(cost_weight).unwrap_or(1).max(1)),
+        };
+
+        self.resolved_weights.insert(name, weight.clone());
+        weight
+    }
+
+    async fn cost_weight(
+        &self,
+        model: &ModelMetadata,
+        cost_calculator: &dyn CostCalculator,
+    ) -> Option<u32> {
+        let reference = self.config.reference_cost.filter(|c| *c > 0.0)?;
+        let usage = Usage::CompletionModelUsage(CompletionModelUsage {
+            input_tokens: NOMINAL_REQUEST_TOKENS,
+            output_tokens: NOMINAL_REQUEST_TOKENS,
+            total_tokens: NOMINAL_REQUEST_TOKENS * 2,
+            ..Default::default()
+        });
+
+        match cost_calculator
+            .calculate_cost(
+                &model.model,
+                &model.inference_provider.provider.to_string(),
+                &usage,
+            )
+            .await
+        {
+            Ok(result) => Some((result.cost / reference).ceil() as u32),
+            Err(e) => {
+                tracing::warn!("Failed to derive weight for {}: {e}", model.model);
+                None
+            }
+        }
+    }
+
+    /// Returns the start of the window charged, `None` without an RPM budget
+    fn charge_rpm(
+        &self,
+        model: &str,
+        units: u32,
+    ) -> Result<Option<DateTime<Utc>>, GatewayApiError> {
+        let Some(max_rpm_units) = self.config.max_rpm_units else {
+            return Ok(None);
+        };
+
+        let mut window = self.rpm_window.lock();
+        let now = Utc::now();
+        if now - window.started_at >= chrono::Duration::minutes(1) {
+            window.started_at = now;
+            window.used = 0;
+            window.per_model.clear();
+        }
+
+        if window.used + units > max_rpm_units {
+            return Err(GatewayApiError::TooManyRequests(format!(
+                "Request budget per minute exhausted for model {model}"
+            )));
+        }
+
+        window.used += units;
+        *window.per_model.entry(model.to_string()).or_default() += units;
+        Ok(Some(window.started_at))
+    }
+
+    /// Returns the units of a request that did not run, unless their window has ended
+    fn refund_rpm(&self, model: &str, units: u32, started_at: DateTime<Utc>) {
+        let mut window = self.rpm_window.lock();
+        if window.started_at != started_at {
+            return;
+        }
+
+        window.used = window.used.saturating_sub(units);
+        if let Some(used) = window.per_model.get_mut(model) {
+            *used = used.saturating_sub(units);
+        }
+    }
+
+    /// Waits for the weighted share of the model, failing with 429 when the
+    /// budget is not available in time. Units are returned when the permit is dropped.
+    pub async fn acquire(
+        &self,
+        model: &ModelMetadata,
+        cost_calculator: &dyn CostCalculator,
+    ) -> Result<ModelPermit, GatewayApiError> {
+        let name = model.qualified_model_name();
+        let weight = self.weight(model, cost_calculator).await;
+        let units = weight.concurrency.unwrap_or(1);
+        let rpm_units = weight.rpm.unwrap_or(1);
+
+        let rpm_window = self.charge_rpm(&name, rpm_units)?;
+
+        let permit = match tokio::time::timeout(
+            Duration::from_millis(self.config.wait_timeout_ms),
+            self.semaphore.clone().acquire_many_owned(units),
+        )
+        .await
+        {
+            Ok(permit) => permit.map_err(|e| GatewayApiError::CustomError(e.to_string()))?,
+            Err(_) => {
+                // The rejected request does not count against the minute
+                if let Some(started_at) = rpm_window {
+                    self.refund_rpm(&name, rpm_units, started_at);
+                }
+                return Err(GatewayApiError::TooManyRequests(format!(
+                    "Timed out waiting for concurrency budget for model {name}"
+                )));
+            }
+        };
+
+        *self.in_flight.entry(name.clone()).or_default() += units;
+
+        Ok(ModelPermit {
+            model: name,
+            units,
+            in_flight: self.in_flight.clone(),
+            _permit: permit,
+        })
+    }
+
+    pub fn utilization(&self) -> BTreeMap<String, ModelUtilization> {
+        let capacity = self.config.max_concurrency_units.max(1) as f64;
+        let window = self.rpm_window.lock();
+
+        self.resolved_weights
+            .iter()
+            .map(|entry| {
+                let in_flight_units = self.in_flight.get(entry.key()).map_or(0, |u| *u);
+                (
+                    entry.key().clone(),
+                    ModelUtilization {
+                        concurrency_weight: entry.concurrency.unwrap_or(1),
+                        rpm_weight: entry.rpm.unwrap_or(1),
+                        in_flight_units,
+                        rpm_units: window.per_model.get(entry.key()).copied().unwrap_or(0),
+                        utilization: in_flight_units as f64 / capacity,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::InferenceProvider;
+    use crate::types::gateway::{CostCalculationResult, CostCalculatorError};
+    use crate::types::provider::InferenceModelProvider;
+
+    struct NoCost;
+
+    #[async_trait::async_trait]
+    impl CostCalculator for NoCost {
+        async fn calculate_cost(
+            &self,
+            _model_name: &str,
+            _provider_name: &str,
+            _usage: &Usage,
+        ) -> Result<CostCalculationResult, CostCalculatorError> {
+            Err(CostCalculatorError::ModelNotFound)
+        }
+    }
+
+    fn model() -> ModelMetadata {
+        ModelMetadata {
+            model: "gpt-4o".to_string(),
+            inference_provider: InferenceProvider {
+                provider: InferenceModelProvider::OpenAI,
+                model_name: "gpt-4o".to_string(),
+                endpoint: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn limiter(max_rpm_units: u32) -> ModelConcurrencyLimiter {
+        ModelConcurrencyLimiter::new(ModelWeightsConfig {
+            max_concurrency_units: 2,
+            max_rpm_units: Some(max_rpm_units),
+            weights: HashMap::from([(
+                "gpt-4o".to_string(),
+                ModelWeight {
+                    concurrency: Some(2),
+                    rpm: Some(1),
+                },
+            )]),
+            reference_cost: None,
+            wait_timeout_ms: 10,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_rpm_budget() {
+        let limiter = limiter(2);
+        drop(limiter.acquire(&model(), &NoCost).await.unwrap());
+        drop(limiter.acquire(&model(), &NoCost).await.unwrap());
+
+        let error = limiter.acquire(&model(), &NoCost).await.err().unwrap();
+        assert!(error.to_string().contains("per minute"));
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_requests_are_refunded() {
+        let limiter = limiter(2);
+        let permit = limiter.acquire(&model(), &NoCost).await.unwrap();
+
+        // The model holds the whole concurrency budget, the waiting requests time out
+        for _ in 0..3 {
+            let error = limiter.acquire(&model(), &NoCost).await.err().unwrap();
+            assert!(error.to_string().contains("Timed out"));
+        }
+        let utilization = limiter.utilization();
+        assert_eq!(utilization["openai/gpt-4o"].rpm_units, 1);
+        assert_eq!(utilization["openai/gpt-4o"].in_flight_units, 2);
+
+        drop(permit);
+        assert!(limiter.acquire(&model(), &NoCost).await.is_ok());
+    }
+}
@@ -12,11 +12,30 @@ use crate::{
     },
 };
 
+pub mod audit_log;
+pub mod batches;
 pub mod chat_completion;
+pub mod circuit_breaker;
 pub mod context;
+pub mod deployments;
+pub mod embedding_cache;
+pub mod embedding_coalescing;
 pub mod embeddings;
 pub mod image_generation;
+pub mod image_storage;
+pub mod limiter;
+pub mod prometheus;
+pub mod provider_limiter;
+pub mod redaction;
+pub mod rerank;
 pub mod responses;
+pub mod retry_budget;
+pub mod size_metrics;
+pub mod spend_budget;
+pub mod tag_routing;
+pub mod transcription;
+pub mod ttl_cache;
+pub mod user_hashing;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ProvidersConfig(pub HashMap<String, ApiKeyCredentials>);
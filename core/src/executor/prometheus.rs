@@ -0,0 +1,501 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::handler::CallbackHandlerFn;
+use crate::model::types::{ModelEvent, ModelEventType};
+use crate::types::gateway::{CostCalculator, Usage};
+
+/// Name of the custom event reported by failed model calls
+pub const LLM_ERROR_EVENT: &str = "llm_error";
+
+/// Calls started without a finish or error event are dropped above this count, oldest
+/// first, e.g. streams abandoned by disconnecting clients
+const MAX_PENDING_CALLS: usize = 10_000;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrometheusConfig {
+    /// Upper bounds of the time to first token buckets in seconds
+    #[serde(default = "default_ttft_buckets")]
+    pub ttft_buckets: Vec<f64>,
+    /// Upper bounds of the request latency buckets in seconds
+    #[serde(default = "default_latency_buckets")]
+    pub latency_buckets: Vec<f64>,
+}
+
+impl Default for PrometheusConfig {
+    fn default() -> Self {
+        Self {
+            ttft_buckets: default_ttft_buckets(),
+            latency_buckets: default_latency_buckets(),
+        }
+    }
+}
+
+fn default_ttft_buckets() -> Vec<f64> {
+    vec![0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0]
+}
+
+fn default_latency_buckets() -> Vec<f64> {
+    vec![0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0]
+}
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    /// Cumulative counts, one per bound
+    counts: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Self {
+        Self {
+            counts: vec![0; bounds.len()],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn observe(&mut self, bounds: &[f64], value: f64) {
+        for (count, bound) in self.counts.iter_mut().zip(bounds) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ModelMetrics {
+    requests: u64,
+    errors: u64,
+    input_tokens: u64,
+    output_tokens: u64,
+    cost: f64,
+    ttft: Histogram,
+    latency: Histogram,
+}
+
+#[derive(Debug, Clone)]
+struct PendingCall {
+    trace_id: String,
+    provider: String,
+    model: String,
+    start: DateTime<Utc>,
+}
+
+/// Aggregates model events of all requests into Prometheus metrics per provider and model
+pub struct PrometheusMetrics {
+    config: PrometheusConfig,
+    models: DashMap<(String, String), ModelMetrics>,
+    /// Calls in flight keyed by the span of the provider call, the start, first token and
+    /// finish events of a call share it while calls of one trace run side by side
+    pending: DashMap<String, PendingCall>,
+}
+
+impl PrometheusMetrics {
+    pub fn new(config: PrometheusConfig) -> Self {
+        Self {
+            config,
+            models: DashMap::new(),
+            pending: DashMap::new(),
+        }
+    }
+
+    /// Subscribes to the events of the callback handler. The cost of finished calls is
+    /// calculated from their usage.
+    pub fn start(
+        config: PrometheusConfig,
+        cost_calculator: Arc<Box<dyn CostCalculator>>,
+        callback: &mut CallbackHandlerFn,
+    ) -> Arc<Self> {
+        let metrics = Arc::new(Self::new(config));
+        let mut events = callback.subscribe();
+
+        tokio::spawn({
+            let metrics = metrics.clone();
+            async move {
+                loop {
+                    let event = match events.recv().await {
+                        Ok(event) => event.event,
+                        Err(RecvError::Lagged(skipped)) => {
+                            tracing::warn!("Metrics skipped {skipped} model events");
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+
+                    let cost = match &event.event {
+                        ModelEventType::LlmStop(e) => match &e.usage {
+                            Some(usage) => cost_calculator
+                                .calculate_cost(
+                                    &e.model_name,
+                                    &e.provider_name,
+                                    &Usage::CompletionModelUsage(usage.clone()),
+                                )
+                                .await
+                                .map_err(|err| {
+                                    tracing::debug!("Metrics cost calculation failed: {err}")
+                                })
+                                .ok()
+                                .map(|c| c.cost),
+                            None => None,
+                        },
+                        _ => None,
+                    };
+                    metrics.observe(&event, cost);
+                }
+            }
+        });
+
+        metrics
+    }
+
+    pub fn observe(&self, event: &ModelEvent, cost: Option<f64>) {
+        match &event.event {
+            ModelEventType::LlmStart(e) => {
+                if self.pending.len() >= MAX_PENDING_CALLS {
+                    self.evict_pending();
+                }
+                self.pending.insert(
+                    event.span_id.clone(),
+                    PendingCall {
+                        trace_id: event.trace_id.clone(),
+                        provider: e.provider_name.clone(),
+                        model: e.model_name.clone(),
+                        start: event.timestamp,
+                    },
+                );
+                self.update(&e.provider_name, &e.model_name, |m| m.requests += 1);
+            }
+            ModelEventType::LlmFirstToken(_) => {
+                let Some(pending) = self.pending.get(&event.span_id) else {
+                    return;
+                };
+                let call = pending.value().clone();
+                drop(pending);
+                let ttft = seconds_between(call.start, event.timestamp);
+                let bounds = &self.config.ttft_buckets;
+                self.update(&call.provider, &call.model, |m| {
+                    m.ttft.observe(bounds, ttft)
+                });
+            }
+            ModelEventType::LlmStop(e) => {
+                let start = self.pending.remove(&event.span_id).map(|(_, c)| c.start);
+                // Responses replayed from the cache did not reach the provider
+                if e.usage.as_ref().is_some_and(|u| u.is_cache_used) {
+                    return;
+                }
+                let latency = match (&e.generation, start) {
+                    (Some(generation), _) => Some(generation.latency as f64 / 1_000_000.0),
+                    (None, Some(start)) => Some(seconds_between(start, event.timestamp)),
+                    (None, None) => None,
+                };
+                let bounds = &self.config.latency_buckets;
+                self.update(&e.provider_name, &e.model_name, |m| {
+                    if let Some(latency) = latency {
+                        m.latency.observe(bounds, latency);
+                    }
+                    if let Some(usage) = &e.usage {
+                        m.input_tokens += usage.input_tokens as u64;
+                        m.output_tokens += usage.output_tokens as u64;
+                    }
+                    m.cost += cost.unwrap_or(0.0);
+                });
+            }
+            ModelEventType::Custom(e) if e.name() == LLM_ERROR_EVENT => {
+                // Reported from the span of the model rather than of the provider call, so
+                // the calls of the model in the trace are no longer pending
+                let value = e.value();
+                let provider = value["provider_name"].as_str().unwrap_or_default();
+                let model = value["model_name"].as_str().unwrap_or_default();
+                self.pending.retain(|_, call| {
+                    !(call.trace_id == event.trace_id
+                        && call.provider == provider
+                        && call.model == model)
+                });
+                self.update(provider, model, |m| m.errors += 1);
+            }
+            _ => {}
+        }
+    }
+
+    /// Drops the oldest quarter of the pending calls, so a full map is not scanned on
+    /// every start
+    fn evict_pending(&self) {
+        let mut starts: Vec<DateTime<Utc>> = self.pending.iter().map(|c| c.start).collect();
+        starts.sort_unstable();
+        if let Some(cutoff) = starts.get(starts.len() / 4) {
+            self.pending.retain(|_, call| call.start > *cutoff);
+        }
+    }
+
+    fn update(&self, provider: &str, model: &str, f: impl FnOnce(&mut ModelMetrics)) {
+        let mut entry = self
+            .models
+            .entry((provider.to_string(), model.to_string()))
+            .or_insert_with(|| ModelMetrics {
+                requests: 0,
+                errors: 0,
+                input_tokens: 0,
+                output_tokens: 0,
+                cost: 0.0,
+                ttft: Histogram::new(&self.config.ttft_buckets),
+                latency: Histogram::new(&self.config.latency_buckets),
+            });
+        f(&mut entry);
+    }
+
+    /// Metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let models: BTreeMap<(String, String), ModelMetrics> = self
+            .models
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        let labels: Vec<(String, &ModelMetrics)> = models
+            .iter()
+            .map(|((provider, model), metrics)| {
+                (
+                    format!(
+                        "provider=\"{}\",model=\"{}\"",
+                        escape(provider),
+                        escape(model)
+                    ),
+                    metrics,
+                )
+            })
+            .collect();
+
+        let mut out = String::new();
+        let counters: [(&str, &str, fn(&ModelMetrics) -> f64); 5] = [
+            ("gateway_requests_total", "Model calls started", |m| {
+                m.requests as f64
+            }),
+            ("gateway_errors_total", "Model calls failed", |m| {
+                m.errors as f64
+            }),
+            ("gateway_input_tokens_total", "Input tokens", |m| {
+                m.input_tokens as f64
+            }),
+            ("gateway_output_tokens_total", "Output tokens", |m| {
+                m.output_tokens as f64
+            }),
+            ("gateway_cost_total", "Cost of finished model calls", |m| {
+                m.cost
+            }),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter");
+            for (labels, metrics) in &labels {
+                let _ = writeln!(out, "{name}{{{labels}}} {}", value(metrics));
+            }
+        }
+
+        let histograms: [(&str, &str, &[f64], fn(&ModelMetrics) -> &Histogram); 2] = [
+            (
+                "gateway_ttft_seconds",
+                "Time to the first token of streamed calls",
+                &self.config.ttft_buckets,
+                |m| &m.ttft,
+            ),
+            (
+                "gateway_latency_seconds",
+                "Duration of finished model calls",
+                &self.config.latency_buckets,
+                |m| &m.latency,
+            ),
+        ];
+        for (name, help, bounds, histogram) in histograms {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+            for (labels, metrics) in &labels {
+                let histogram = histogram(metrics);
+                for (bound, count) in bounds.iter().zip(&histogram.counts) {
+                    let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {count}");
+                }
+                let _ = writeln!(
+                    out,
+                    "{name}_bucket{{{labels},le=\"+Inf\"}} {}",
+                    histogram.count
+                );
+                let _ = writeln!(out, "{name}_sum{{{labels}}} {}", histogram.sum);
+                let _ = writeln!(out, "{name}_count{{{labels}}} {}", histogram.count);
+            }
+        }
+        out
+    }
+}
+
+fn seconds_between(start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
+    (end - start).num_microseconds().unwrap_or(0).max(0) as f64 / 1_000_000.0
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::types::{
+        CustomEvent, LLMFinishEvent, LLMFirstToken, LLMStartEvent, ModelFinishReason,
+    };
+    use crate::model::CredentialsIdent;
+    use crate::types::gateway::CompletionModelUsage;
+
+    fn event(span_id: &str, millis: i64, event: ModelEventType) -> ModelEvent {
+        ModelEvent {
+            span_id: span_id.to_string(),
+            trace_id: "trace".to_string(),
+            event,
+            timestamp: DateTime::from_timestamp_millis(millis).unwrap(),
+            request_id: None,
+        }
+    }
+
+    fn start() -> ModelEventType {
+        ModelEventType::LlmStart(LLMStartEvent {
+            provider_name: "openai".to_string(),
+            model_name: "gpt-4o".to_string(),
+            input: String::new(),
+        })
+    }
+
+    fn metrics() -> PrometheusMetrics {
+        PrometheusMetrics::new(PrometheusConfig {
+            ttft_buckets: vec![0.5, 1.0],
+            latency_buckets: vec![1.0, 5.0],
+        })
+    }
+
+    fn stop() -> ModelEventType {
+        ModelEventType::LlmStop(LLMFinishEvent {
+            provider_name: "openai".to_string(),
+            model_name: "gpt-4o".to_string(),
+            output: None,
+            usage: Some(CompletionModelUsage {
+                input_tokens: 100,
+                output_tokens: 20,
+                total_tokens: 120,
+                ..Default::default()
+            }),
+            finish_reason: ModelFinishReason::Stop,
+            tool_calls: vec![],
+            credentials_ident: CredentialsIdent::Own,
+            generation: None,
+        })
+    }
+
+    #[test]
+    fn test_events_are_aggregated() {
+        let metrics = metrics();
+        metrics.observe(&event("a", 0, start()), None);
+        metrics.observe(
+            &event("a", 300, ModelEventType::LlmFirstToken(LLMFirstToken {})),
+            None,
+        );
+        metrics.observe(&event("a", 2000, stop()), Some(0.25));
+
+        metrics.observe(&event("b", 0, start()), None);
+        metrics.observe(
+            &event(
+                "b",
+                100,
+                ModelEventType::Custom(CustomEvent::new(
+                    LLM_ERROR_EVENT.to_string(),
+                    serde_json::json!({"provider_name": "openai", "model_name": "gpt-4o"}),
+                )),
+            ),
+            None,
+        );
+
+        let out = metrics.render();
+        let labels = "provider=\"openai\",model=\"gpt-4o\"";
+        for line in [
+            format!("gateway_requests_total{{{labels}}} 2"),
+            format!("gateway_errors_total{{{labels}}} 1"),
+            format!("gateway_input_tokens_total{{{labels}}} 100"),
+            format!("gateway_output_tokens_total{{{labels}}} 20"),
+            format!("gateway_cost_total{{{labels}}} 0.25"),
+            format!("gateway_ttft_seconds_bucket{{{labels},le=\"0.5\"}} 1"),
+            format!("gateway_latency_seconds_bucket{{{labels},le=\"1\"}} 0"),
+            format!("gateway_latency_seconds_bucket{{{labels},le=\"5\"}} 1"),
+            format!("gateway_latency_seconds_bucket{{{labels},le=\"+Inf\"}} 1"),
+            format!("gateway_latency_seconds_sum{{{labels}}} 2"),
+        ] {
+            assert!(out.lines().any(|l| l == line), "missing {line} in\n{out}");
+        }
+        assert!(metrics.pending.is_empty());
+    }
+
+    #[test]
+    fn test_calls_of_one_trace() {
+        let metrics = metrics();
+        // Choices of one request run side by side in the same trace
+        metrics.observe(&event("a", 0, start()), None);
+        metrics.observe(&event("b", 1000, start()), None);
+        metrics.observe(&event("a", 500, stop()), None);
+        metrics.observe(&event("b", 7000, stop()), None);
+
+        let out = metrics.render();
+        let labels = "provider=\"openai\",model=\"gpt-4o\"";
+        for line in [
+            format!("gateway_latency_seconds_bucket{{{labels},le=\"1\"}} 1"),
+            format!("gateway_latency_seconds_bucket{{{labels},le=\"5\"}} 1"),
+            format!("gateway_latency_seconds_sum{{{labels}}} 6.5"),
+        ] {
+            assert!(out.lines().any(|l| l == line), "missing {line} in\n{out}");
+        }
+    }
+
+    #[test]
+    fn test_cached_responses_are_skipped() {
+        let metrics = metrics();
+        let mut cached = stop();
+        if let ModelEventType::LlmStop(e) = &mut cached {
+            e.usage.as_mut().unwrap().is_cache_used = true;
+        }
+        metrics.observe(&event("a", 0, start()), None);
+        metrics.observe(&event("a", 10, cached), Some(0.25));
+
+        let out = metrics.render();
+        let labels = "provider=\"openai\",model=\"gpt-4o\"";
+        assert!(out
+            .lines()
+            .any(|l| l == format!("gateway_input_tokens_total{{{labels}}} 0")));
+        assert!(out
+            .lines()
+            .any(|l| l == format!("gateway_cost_total{{{labels}}} 0")));
+        assert!(metrics.pending.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_pending_calls_are_evicted() {
+        let metrics = metrics();
+        for i in 0..MAX_PENDING_CALLS as i64 {
+            metrics.observe(&event(&i.to_string(), i, start()), None);
+        }
+        metrics.observe(&event("new", MAX_PENDING_CALLS as i64, start()), None);
+
+        assert!(metrics.pending.len() < MAX_PENDING_CALLS);
+        assert!(!metrics.pending.contains_key("0"));
+        assert!(metrics.pending.contains_key("9999"));
+        assert!(metrics.pending.contains_key("new"));
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::executor::chat_completion::stream_wrapper::{wrap_stream, ChatCompletionStream};
+use crate::types::engine::CompletionEngineParams;
+use crate::GatewayApiError;
+
+/// Caps the requests in flight to each provider account. Unlike rate limits, which count
+/// requests over time, this bounds the simultaneous calls the provider quotas allow.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProviderConcurrencyConfig {
+    /// In-flight requests per provider and API key
+    pub max_in_flight: usize,
+    /// Limits of single providers, keyed by provider name
+    #[serde(default)]
+    pub providers: HashMap<String, usize>,
+    /// Time a request waits for a free slot before it fails with 429
+    #[serde(default = "default_wait_timeout_ms")]
+    pub wait_timeout_ms: u64,
+}
+
+fn default_wait_timeout_ms() -> u64 {
+    5000
+}
+
+pub struct ProviderConcurrencyLimiter {
+    config: ProviderConcurrencyConfig,
+    /// Keyed by provider and a digest of the API key
+    semaphores: DashMap<(String, String), Arc<Semaphore>>,
+}
+
+impl ProviderConcurrencyLimiter {
+    pub fn new(config: ProviderConcurrencyConfig) -> Self {
+        Self {
+            config,
+            semaphores: DashMap::new(),
+        }
+    }
+
+    /// Waits for a slot of the provider account the engine calls. The slot is released
+    /// when the permit is dropped, whether the call succeeded or not.
+    pub async fn acquire(
+        &self,
+        provider: &str,
+        engine: &CompletionEngineParams,
+    ) -> Result<OwnedSemaphorePermit, GatewayApiError> {
+        self.acquire_key(provider, credentials_key(engine)).await
+    }
+
+    async fn acquire_key(
+        &self,
+        provider: &str,
+        key: String,
+    ) -> Result<OwnedSemaphorePermit, GatewayApiError> {
+        let limit = self
+            .config
+            .providers
+            .get(provider)
+            .copied()
+            .unwrap_or(self.config.max_in_flight)
+            .max(1);
+        let semaphore = self
+            .semaphores
+            .entry((provider.to_string(), key))
+            .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+            .clone();
+
+        tokio::time::timeout(
+            Duration::from_millis(self.config.wait_timeout_ms),
+            semaphore.acquire_owned(),
+        )
+        .await
+        .map_err(|_| {
+            GatewayApiError::TooManyRequests(format!(
+                "Timed out waiting for a free {provider} connection, {limit} requests are in flight"
+            ))
+        })?
+        .map_err(|e| GatewayApiError::CustomError(e.to_string()))
+    }
+}
+
+/// Keeps the permit until the stream is finished or dropped by a disconnecting client
+pub fn hold_permit(
+    stream: ChatCompletionStream,
+    permit: OwnedSemaphorePermit,
+) -> ChatCompletionStream {
+    wrap_stream(stream.map(move |e| {
+        let _permit = &permit;
+        e
+    }))
+}
+
+/// Digest of the API key of the engine, keys configured for the gateway share one entry
+fn credentials_key(engine: &CompletionEngineParams) -> String {
+    let key = match engine {
+        CompletionEngineParams::OpenAi { credentials, .. }
+        | CompletionEngineParams::Anthropic { credentials, .. }
+        | CompletionEngineParams::Gemini { credentials, .. }
+        | CompletionEngineParams::Proxy { credentials, .. } => {
+            credentials.as_ref().map(|c| c.api_key.as_str())
+        }
+        CompletionEngineParams::Bedrock { credentials, .. } => {
+            credentials.as_ref().map(|c| c.access_key.as_str())
+        }
+    };
+    match key {
+        Some(key) => format!("{:x}", Sha256::digest(key.as_bytes())),
+        None => "gateway".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_in_flight: usize) -> ProviderConcurrencyLimiter {
+        ProviderConcurrencyLimiter::new(ProviderConcurrencyConfig {
+            max_in_flight,
+            providers: HashMap::from([("anthropic".to_string(), 1)]),
+            wait_timeout_ms: 60_000,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_request_over_limit_waits_for_release() {
+        let limiter = Arc::new(limiter(2));
+        let first = limiter
+            .acquire_key("openai", "a".to_string())
+            .await
+            .unwrap();
+        let _second = limiter
+            .acquire_key("openai", "a".to_string())
+            .await
+            .unwrap();
+
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire_key("openai", "a".to_string()).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        // Other API keys of the provider are not affected
+        assert!(limiter.acquire_key("openai", "b".to_string()).await.is_ok());
+
+        drop(first);
+        let third = tokio::time::timeout(Duration::from_secs(1), waiting).await;
+        assert!(third.unwrap().unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_wait_timeout() {
+        let limiter = ProviderConcurrencyLimiter::new(ProviderConcurrencyConfig {
+            wait_timeout_ms: 10,
+            ..limiter(4).config
+        });
+        let _permit = limiter.acquire_key("anthropic", "a".to_string()).await;
+
+        let error = limiter
+            .acquire_key("anthropic", "a".to_string())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, GatewayApiError::TooManyRequests(_)));
+    }
+}
@@ -0,0 +1,284 @@
+use std::borrow::Cow;
+
+use regex::{Captures, NoExpand, Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::model::types::ModelEventType;
+
+/// Card numbers of 13 to 19 digits, optionally grouped by spaces or dashes. Matches are
+/// only masked when their check digit is valid.
+const CREDIT_CARD_PATTERN: &str = r"\b\d(?:[ -]?\d){12,18}\b";
+const EMAIL_PATTERN: &str = r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b";
+/// Provider keys and bearer tokens, `sk-...`, `AKIA...`, `ghp_...` and the like
+const SECRET_PATTERN: &str = r"\b(?:sk-[A-Za-z0-9_-]{16,}|AKIA[0-9A-Z]{16}|gh[pousr]_[A-Za-z0-9]{30,}|xox[abposr]-[A-Za-z0-9-]{10,}|AIza[0-9A-Za-z_-]{35})\b|(?i:bearer\s+)[A-Za-z0-9._~+/-]{20,}=*";
+
+/// Masks secrets in the copies of requests and responses kept for observability, the
+/// provider still receives the original content
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RedactionConfig {
+    /// Credit card numbers, emails and secret-looking tokens
+    #[serde(default = "default_builtin_patterns")]
+    pub builtin_patterns: bool,
+    /// Additional regular expressions
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    #[serde(default = "default_mask")]
+    pub mask: String,
+    /// Span attributes redacted before spans are written
+    #[serde(default = "default_attributes")]
+    pub attributes: Vec<String>,
+}
+
+fn default_builtin_patterns() -> bool {
+    true
+}
+
+fn default_mask() -> String {
+    "[REDACTED]".to_string()
+}
+
+fn default_attributes() -> Vec<String> {
+    ["request", "response", "input", "output"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid redaction pattern: {0}")]
+pub struct RedactionError(#[from] regex::Error);
+
+pub struct Redactor {
+    set: RegexSet,
+    patterns: Vec<Regex>,
+    /// Index of the credit card pattern, when the builtin patterns are used
+    card_pattern: Option<usize>,
+    mask: String,
+    attributes: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new(config: &RedactionConfig) -> Result<Self, RedactionError> {
+        let builtin = [CREDIT_CARD_PATTERN, EMAIL_PATTERN, SECRET_PATTERN];
+        let sources: Vec<&str> = builtin
+            .into_iter()
+            .filter(|_| config.builtin_patterns)
+            .chain(config.patterns.iter().map(String::as_str))
+            .collect();
+
+        Ok(Self {
+            set: RegexSet::new(&sources)?,
+            patterns: sources
+                .iter()
+                .map(|p| Regex::new(p))
+                .collect::<Result<_, _>>()?,
+            card_pattern: config.builtin_patterns.then_some(0),
+            mask: config.mask.clone(),
+            attributes: config.attributes.clone(),
+        })
+    }
+
+    /// Text without matches, the common case, is returned without copying
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let matches = self.set.matches(text);
+        if !matches.matched_any() {
+            return Cow::Borrowed(text);
+        }
+
+        let mut text = text.to_string();
+        for index in matches.iter() {
+            let pattern = &self.patterns[index];
+            text = if self.card_pattern == Some(index) {
+                pattern
+                    .replace_all(&text, |caps: &Captures| {
+                        if is_luhn_valid(&caps[0]) {
+                            self.mask.clone()
+                        } else {
+                            caps[0].to_string()
+                        }
+                    })
+                    .into_owned()
+            } else {
+                pattern
+                    .replace_all(&text, NoExpand(&self.mask))
+                    .into_owned()
+            };
+        }
+        Cow::Owned(text)
+    }
+
+    /// Redacts the content carried by a model event. Streamed content is redacted per
+    /// chunk, a secret split across chunks is masked in the output of the finish event.
+    pub fn redact_event(&self, event: &mut ModelEventType) {
+        match event {
+            ModelEventType::LlmStart(e) => self.redact_in_place(&mut e.input),
+            ModelEventType::LlmContent(e) => self.redact_in_place(&mut e.content),
+            ModelEventType::LlmStop(e) => {
+                if let Some(output) = &mut e.output {
+                    self.redact_in_place(output);
+                }
+                for tool_call in e.tool_calls.iter_mut() {
+                    self.redact_in_place(&mut tool_call.input);
+                }
+            }
+            ModelEventType::ToolStart(e) => self.redact_in_place(&mut e.input),
+            ModelEventType::ToolCallDelta(e) => self.redact_in_place(&mut e.arguments),
+            ModelEventType::ToolResult(e) => self.redact_in_place(&mut e.output),
+            // Custom events carry error messages and decisions that may quote the content
+            ModelEventType::Custom(e) => self.redact_value(e.value_mut()),
+            _ => {}
+        }
+    }
+
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => self.redact_in_place(text),
+            Value::Array(values) => values.iter_mut().for_each(|v| self.redact_value(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.redact_value(v)),
+            _ => {}
+        }
+    }
+
+    pub fn redact_attributes(&self, attributes: &mut Map<String, Value>) {
+        for name in &self.attributes {
+            if let Some(Value::String(value)) = attributes.get_mut(name) {
+                self.redact_in_place(value);
+            }
+        }
+    }
+
+    fn redact_in_place(&self, text: &mut String) {
+        let redacted = match self.redact(text) {
+            Cow::Owned(redacted) => redacted,
+            Cow::Borrowed(_) => return,
+        };
+        *text = redacted;
+    }
+}
+
+/// Checks the Luhn check digit of a card number, separators are ignored
+fn is_luhn_valid(number: &str) -> bool {
+    let sum: u32 = number
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .rev()
+        .enumerate()
+        .map(|(index, digit)| match (index % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::types::{
+        CustomEvent, LLMFinishEvent, LLMStartEvent, ModelFinishReason, ModelToolCall,
+    };
+    use crate::model::CredentialsIdent;
+
+    fn redactor(patterns: Vec<String>) -> Redactor {
+        Redactor::new(&RedactionConfig {
+            builtin_patterns: true,
+            patterns,
+            mask: default_mask(),
+            attributes: default_attributes(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_builtin_patterns() {
+        let redactor = redactor(vec![]);
+        assert_eq!(
+            redactor.redact("Card 4111 1111 1111 1111, mail jane.doe@example.com"),
+            "Card [REDACTED], mail [REDACTED]"
+        );
+        assert_eq!(
+            redactor.redact("key=sk-proj-abcdefghijklmnop1234 please"),
+            "key=[REDACTED] please"
+        );
+        assert!(matches!(
+            redactor.redact("Order 1234 ships in 2 days"),
+            Cow::Borrowed(_)
+        ));
+        // Long numbers without a valid check digit are not cards
+        assert_eq!(
+            redactor.redact("Tracking 1234 5678 9012 3456"),
+            "Tracking 1234 5678 9012 3456"
+        );
+    }
+
+    #[test]
+    fn test_event_is_redacted() {
+        let redactor = redactor(vec![r"EMP-\d{6}".to_string()]);
+        let start = LLMStartEvent {
+            provider_name: "openai".to_string(),
+            model_name: "gpt-4o".to_string(),
+            input: "Employee EMP-123456 asked about payroll".to_string(),
+        };
+        let mut event = ModelEventType::LlmStart(start.clone());
+        redactor.redact_event(&mut event);
+
+        let ModelEventType::LlmStart(redacted) = event else {
+            panic!("expected start event");
+        };
+        assert_eq!(redacted.input, "Employee [REDACTED] asked about payroll");
+        // Only the copy is redacted
+        assert_eq!(start.input, "Employee EMP-123456 asked about payroll");
+
+        let mut attributes = Map::new();
+        attributes.insert("request".to_string(), "EMP-654321".into());
+        attributes.insert("model_name".to_string(), "EMP-000001".into());
+        redactor.redact_attributes(&mut attributes);
+        assert_eq!(attributes["request"], "[REDACTED]");
+        assert_eq!(attributes["model_name"], "EMP-000001");
+    }
+    #[test]
+    fn test_tool_calls_and_custom_events_are_redacted() {
+        let redactor = redactor(vec![]);
+        let mut event = ModelEventType::LlmStop(LLMFinishEvent {
+            provider_name: "openai".to_string(),
+            model_name: "gpt-4o".to_string(),
+            output: None,
+            usage: None,
+            finish_reason: ModelFinishReason::ToolCalls,
+            tool_calls: vec![ModelToolCall {
+                tool_id: "call_1".to_string(),
+                tool_name: "send_mail".to_string(),
+                input: r#"{"to":"jane.doe@example.com"}"#.to_string(),
+            }],
+            credentials_ident: CredentialsIdent::Own,
+            generation: None,
+        });
+        redactor.redact_event(&mut event);
+        let ModelEventType::LlmStop(stop) = event else {
+            panic!("expected stop event");
+        };
+        assert_eq!(stop.tool_calls[0].input, r#"{"to":"[REDACTED]"}"#);
+
+        let mut event = ModelEventType::Custom(CustomEvent::new(
+            "llm_error".to_string(),
+            serde_json::json!({
+                "model_name": "gpt-4o",
+                "error": "Invalid API key sk-proj-abcdefghijklmnop1234",
+            }),
+        ));
+        redactor.redact_event(&mut event);
+        let ModelEventType::Custom(custom) = event else {
+            panic!("expected custom event");
+        };
+        assert_eq!(
+            custom.value(),
+            serde_json::json!({
+                "model_name": "gpt-4o",
+                "error": "Invalid API key [REDACTED]",
+            })
+        );
+    }
+}
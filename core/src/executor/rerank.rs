@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::error::GatewayError;
+use crate::events::{JsonValue, RecordResult, SPAN_MODEL_CALL};
+use crate::handler::{CallbackHandlerFn, ModelEventWithDetails};
+use crate::model::types::{ModelEvent, ModelEventType};
+use crate::models::ModelMetadata;
+use crate::rerank_mod::{rank, ProviderRerank, Rerank};
+use crate::types::credentials::{ApiKeyCredentials, Credentials};
+use crate::types::engine::{Model, ModelTools, ModelType};
+use crate::types::gateway::{
+    CostCalculator, CreateRerankRequest, CreateRerankResponse, RerankModelUsage, RerankResult,
+    RerankUsage, Usage,
+};
+use actix_web::HttpRequest;
+use tracing::info_span;
+use tracing_futures::Instrument;
+use valuable::Valuable;
+
+use super::get_key_credentials;
+use super::ProvidersConfig;
+
+pub async fn handle_rerank(
+    request: CreateRerankRequest,
+    callback_handler: &CallbackHandlerFn,
+    llm_model: &ModelMetadata,
+    key_credentials: Option<&Credentials>,
+    cost_calculator: Arc<Box<dyn CostCalculator>>,
+    tags: HashMap<String, String>,
+    req: HttpRequest,
+) -> Result<CreateRerankResponse, GatewayError> {
+    if request.documents.is_empty() {
+        return Ok(CreateRerankResponse {
+            object: "list".to_string(),
+            model: llm_model.model.clone(),
+            results: vec![],
+            usage: RerankUsage { documents: 0 },
+        });
+    }
+
+    let providers_config = req.app_data::<ProvidersConfig>().cloned();
+    let mut custom_endpoint = None;
+    let key = match get_key_credentials(
+        key_credentials,
+        providers_config.as_ref(),
+        &llm_model.inference_provider.provider.to_string(),
+    ) {
+        Some(Credentials::ApiKey(key)) => Some(key),
+        Some(Credentials::ApiKeyWithEndpoint {
+            api_key: key,
+            endpoint,
+        }) => {
+            custom_endpoint = Some(endpoint);
+            Some(ApiKeyCredentials { api_key: key })
+        }
+        _ => None,
+    };
+
+    let rerank = ProviderRerank::new(
+        &llm_model.inference_provider.provider,
+        &llm_model.inference_provider.model_name,
+        key.as_ref(),
+        custom_endpoint
+            .as_deref()
+            .or(llm_model.inference_provider.endpoint.as_deref()),
+    )?;
+
+    let model_name = llm_model.model.clone();
+    let provider_name = llm_model.inference_provider.provider.to_string();
+    let db_model = Model {
+        name: model_name.clone(),
+        description: None,
+        provider_name: provider_name.clone(),
+        prompt_name: None,
+        model_params: HashMap::new(),
+        tools: ModelTools(vec![]),
+        model_type: ModelType::Rerank,
+        response_schema: None,
+        credentials: key_credentials.cloned(),
+    };
+
+    let span = info_span!(
+        target: "langdb::user_tracing::models", SPAN_MODEL_CALL,
+        input = serde_json::to_string(&request)?,
+        model = model_name.clone(),
+        provider_name = provider_name.clone(),
+        output = tracing::field::Empty,
+        error = tracing::field::Empty,
+        cost = tracing::field::Empty,
+        usage = tracing::field::Empty,
+        tags = JsonValue(&serde_json::to_value(tags)?).as_value(),
+    );
+
+    let callback_handler = callback_handler.clone();
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Option<ModelEvent>>(1000);
+
+    let handle = tokio::spawn(
+        async move {
+            while let Some(Some(msg)) = rx.recv().await {
+                if let ModelEventType::RerankFinish(finish_event) = &msg.event {
+                    let s = tracing::Span::current();
+                    let u = RerankModelUsage {
+                        documents_count: finish_event.documents_count,
+                    };
+                    match cost_calculator
+                        .calculate_cost(
+                            &model_name,
+                            &provider_name,
+                            &Usage::RerankModelUsage(u.clone()),
+                        )
+                        .await
+                    {
+                        Ok(c) => {
+                            s.record("cost", serde_json::to_string(&c).unwrap());
+                        }
+                        Err(e) => {
+                            tracing::error!("Error calculating cost: {:?}", e);
+                        }
+                    };
+
+                    s.record("usage", serde_json::to_string(&u).unwrap());
+                }
+
+                callback_handler
+                    .on_message(ModelEventWithDetails::new(msg, Some(db_model.clone())));
+            }
+        }
+        .instrument(span.clone()),
+    );
+
+    let scores = async {
+        let scores = rerank
+            .rerank(&request.query, &request.documents, request.top_n, Some(tx))
+            .await;
+        let _ = scores
+            .as_ref()
+            .map(|scores| serde_json::to_value(scores).unwrap_or_default())
+            .as_ref()
+            .map(JsonValue)
+            .record();
+        scores
+    }
+    .instrument(span)
+    .await;
+
+    handle.await.unwrap();
+
+    let results = rank(scores?, request.top_n)
+        .into_iter()
+        .filter(|s| s.index < request.documents.len())
+        .map(|s| RerankResult {
+            index: s.index,
+            relevance_score: s.relevance_score,
+            document: request
+                .return_documents
+                .then(|| request.documents[s.index].clone()),
+        })
+        .collect();
+
+    Ok(CreateRerankResponse {
+        object: "list".to_string(),
+        model: llm_model.model.clone(),
+        results,
+        usage: RerankUsage {
+            documents: request.documents.len() as u32,
+        },
+    })
+}
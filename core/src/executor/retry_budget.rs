@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetryBudgetConfig {
+    /// Share of requests in the window that may be retried
+    #[serde(default = "default_ratio")]
+    pub ratio: f64,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    /// Retries always allowed in the window, so low traffic can still retry
+    #[serde(default = "default_min_retries")]
+    pub min_retries: u32,
+}
+
+fn default_ratio() -> f64 {
+    0.1
+}
+
+fn default_window_secs() -> u64 {
+    10
+}
+
+fn default_min_retries() -> u32 {
+    10
+}
+
+#[derive(Debug)]
+struct Bucket {
+    second: i64,
+    requests: u32,
+    retries: u32,
+}
+
+/// Caps retries and fallbacks to a share of the requests seen over a rolling window,
+/// so the gateway fails fast instead of amplifying load on a struggling provider.
+#[derive(Debug)]
+pub struct RetryBudget {
+    config: RetryBudgetConfig,
+    buckets: Mutex<VecDeque<Bucket>>,
+}
+
+impl RetryBudget {
+    pub fn new(config: RetryBudgetConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record_request(&self) {
+        self.record_request_at(Utc::now());
+    }
+
+    /// Withdraws one retry from the budget, returns false when the budget is exhausted
+    pub fn try_retry(&self) -> bool {
+        self.try_retry_at(Utc::now())
+    }
+
+    fn record_request_at(&self, now: DateTime<Utc>) {
+        let mut buckets = self.buckets.lock();
+        self.current(&mut buckets, now).requests += 1;
+    }
+
+    fn try_retry_at(&self, now: DateTime<Utc>) -> bool {
+        let mut buckets = self.buckets.lock();
+        self.evict(&mut buckets, now);
+        if !self.available(&buckets) {
+            return false;
+        }
+        self.current(&mut buckets, now).retries += 1;
+        true
+    }
+
+    fn available(&self, buckets: &VecDeque<Bucket>) -> bool {
+        let (requests, retries) = buckets
+            .iter()
+            .fold((0, 0), |(req, ret), b| (req + b.requests, ret + b.retries));
+        let allowed = (requests as f64 * self.config.ratio) as u32;
+        retries < allowed.max(self.config.min_retries)
+    }
+
+    fn evict(&self, buckets: &mut VecDeque<Bucket>, now: DateTime<Utc>) {
+        let window_start = (now - Duration::seconds(self.config.window_secs as i64)).timestamp();
+        while buckets.front().is_some_and(|b| b.second <= window_start) {
+            buckets.pop_front();
+        }
+    }
+
+    fn current<'a>(&self, buckets: &'a mut VecDeque<Bucket>, now: DateTime<Utc>) -> &'a mut Bucket {
+        self.evict(buckets, now);
+        let second = now.timestamp();
+        if buckets.back().is_none_or(|b| b.second != second) {
+            buckets.push_back(Bucket {
+                second,
+                requests: 0,
+                retries: 0,
+            });
+        }
+        buckets.back_mut().expect("bucket for current second")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(min_retries: u32) -> RetryBudget {
+        RetryBudget::new(RetryBudgetConfig {
+            ratio: 0.1,
+            window_secs: 10,
+            min_retries,
+        })
+    }
+
+    #[test]
+    fn test_retries_capped_by_ratio() {
+        let budget = budget(0);
+        let now = Utc::now();
+        for _ in 0..100 {
+            budget.record_request_at(now);
+        }
+
+        for _ in 0..10 {
+            assert!(budget.try_retry_at(now));
+        }
+        assert!(!budget.try_retry_at(now));
+    }
+
+    #[test]
+    fn test_min_retries_allowed_at_low_traffic() {
+        let budget = budget(2);
+        let now = Utc::now();
+        budget.record_request_at(now);
+
+        assert!(budget.try_retry_at(now));
+        assert!(budget.try_retry_at(now));
+        assert!(!budget.try_retry_at(now));
+    }
+
+    #[test]
+    fn test_budget_recovers_after_window() {
+        let budget = budget(1);
+        let now = Utc::now();
+        assert!(budget.try_retry_at(now));
+        assert!(!budget.try_retry_at(now + Duration::seconds(5)));
+        assert!(budget.try_retry_at(now + Duration::seconds(11)));
+    }
+}
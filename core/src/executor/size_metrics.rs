@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SizeMetricsConfig {
+    /// Upper bounds of the histogram buckets in bytes
+    #[serde(default = "default_buckets")]
+    pub buckets: Vec<u64>,
+}
+
+impl Default for SizeMetricsConfig {
+    fn default() -> Self {
+        Self {
+            buckets: default_buckets(),
+        }
+    }
+}
+
+fn default_buckets() -> Vec<u64> {
+    vec![
+        256,
+        1024,
+        4 * 1024,
+        16 * 1024,
+        64 * 1024,
+        256 * 1024,
+        1024 * 1024,
+        4 * 1024 * 1024,
+    ]
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Histogram {
+    /// Upper bounds of the buckets, values above the last bound are counted in `overflow`
+    pub bounds: Vec<u64>,
+    pub counts: Vec<u64>,
+    pub overflow: u64,
+    pub count: u64,
+    pub sum: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &[u64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len()],
+            overflow: 0,
+            count: 0,
+            sum: 0,
+        }
+    }
+
+    fn observe(&mut self, value: u64) {
+        match self.bounds.iter().position(|bound| value <= *bound) {
+            Some(bucket) => self.counts[bucket] += 1,
+            None => self.overflow += 1,
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ModelSizeMetrics {
+    pub request_bytes: Histogram,
+    pub response_bytes: Histogram,
+    pub streamed_responses: u64,
+    pub streamed_bytes: u64,
+    pub streamed_chunks: u64,
+}
+
+/// Wire-level sizes of provider requests and responses per provider and model
+pub struct SizeMetrics {
+    config: SizeMetricsConfig,
+    models: DashMap<(String, String), ModelSizeMetrics>,
+}
+
+impl SizeMetrics {
+    pub fn new(config: SizeMetricsConfig) -> Self {
+        Self {
+            config,
+            models: DashMap::new(),
+        }
+    }
+
+    fn update(&self, provider: &str, model: &str, f: impl FnOnce(&mut ModelSizeMetrics)) {
+        let mut entry = self
+            .models
+            .entry((provider.to_string(), model.to_string()))
+            .or_insert_with(|| ModelSizeMetrics {
+                request_bytes: Histogram::new(&self.config.buckets),
+                response_bytes: Histogram::new(&self.config.buckets),
+                streamed_responses: 0,
+                streamed_bytes: 0,
+                streamed_chunks: 0,
+            });
+        f(&mut entry);
+    }
+
+    pub fn record_request(&self, provider: &str, model: &str, bytes: usize) {
+        self.update(provider, model, |m| m.request_bytes.observe(bytes as u64));
+    }
+
+    pub fn record_response(&self, provider: &str, model: &str, bytes: usize) {
+        self.update(provider, model, |m| m.response_bytes.observe(bytes as u64));
+    }
+
+    /// Records a finished stream with its total content bytes and chunk count
+    pub fn record_stream(&self, provider: &str, model: &str, bytes: usize, chunks: u64) {
+        self.update(provider, model, |m| {
+            m.response_bytes.observe(bytes as u64);
+            m.streamed_responses += 1;
+            m.streamed_bytes += bytes as u64;
+            m.streamed_chunks += chunks;
+        });
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, BTreeMap<String, ModelSizeMetrics>> {
+        let mut providers: BTreeMap<String, BTreeMap<String, ModelSizeMetrics>> = BTreeMap::new();
+        for entry in self.models.iter() {
+            let (provider, model) = entry.key();
+            providers
+                .entry(provider.clone())
+                .or_default()
+                .insert(model.clone(), entry.value().clone());
+        }
+        providers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let metrics = SizeMetrics::new(SizeMetricsConfig {
+            buckets: vec![100, 1000],
+        });
+        metrics.record_request("openai", "gpt-4o", 50);
+        metrics.record_request("openai", "gpt-4o", 100);
+        metrics.record_request("openai", "gpt-4o", 500);
+        metrics.record_request("openai", "gpt-4o", 5000);
+
+        let snapshot = metrics.snapshot();
+        let request_bytes = &snapshot["openai"]["gpt-4o"].request_bytes;
+        assert_eq!(request_bytes.counts, vec![2, 1]);
+        assert_eq!(request_bytes.overflow, 1);
+        assert_eq!(request_bytes.count, 4);
+        assert_eq!(request_bytes.sum, 5650);
+    }
+
+    #[test]
+    fn test_stream_totals() {
+        let metrics = SizeMetrics::new(SizeMetricsConfig::default());
+        metrics.record_stream("anthropic", "claude-3-5-sonnet", 300, 12);
+        metrics.record_stream("anthropic", "claude-3-5-sonnet", 200, 8);
+
+        let snapshot = metrics.snapshot();
+        let model = &snapshot["anthropic"]["claude-3-5-sonnet"];
+        assert_eq!(model.streamed_responses, 2);
+        assert_eq!(model.streamed_bytes, 500);
+        assert_eq!(model.streamed_chunks, 20);
+        assert_eq!(model.response_bytes.count, 2);
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::GatewayError;
+use crate::model::token_estimate::estimate_usage;
+use crate::types::credentials::Credentials;
+use crate::types::gateway::{ChatCompletionRequest, CompletionModelUsage};
+use crate::usage::get_monthly_key;
+use crate::GatewayApiError;
+
+/// Monthly spend limits in dollars
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SpendBudgetConfig {
+    /// Budget of keys without an entry in `keys`
+    #[serde(default)]
+    pub default: Option<f64>,
+    /// Budgets by API key
+    #[serde(default)]
+    pub keys: HashMap<String, f64>,
+}
+
+/// Storage backend of the spent amounts
+#[async_trait]
+pub trait BudgetStore: Send + Sync {
+    /// Dollars spent under `key`
+    async fn spent(&self, key: &str) -> Result<f64, GatewayError>;
+
+    /// Adds `amount` dollars to the spend under `key`, a negative amount gives back
+    async fn debit(&self, key: &str, amount: f64) -> Result<(), GatewayError>;
+
+    /// Adds `amount` to the spend under `key` only when the spend stays within `limit`, in
+    /// one step so concurrent requests cannot all pass the same check
+    async fn reserve(&self, key: &str, amount: f64, limit: f64) -> Result<bool, GatewayError>;
+}
+
+/// Default backend keeping the spend in process memory
+#[derive(Default)]
+pub struct InMemoryBudgetStore {
+    spent: DashMap<String, f64>,
+}
+
+#[async_trait]
+impl BudgetStore for InMemoryBudgetStore {
+    async fn spent(&self, key: &str) -> Result<f64, GatewayError> {
+        Ok(self.spent.get(key).map_or(0.0, |s| *s))
+    }
+
+    async fn debit(&self, key: &str, amount: f64) -> Result<(), GatewayError> {
+        *self.spent.entry(key.to_string()).or_default() += amount;
+        Ok(())
+    }
+
+    async fn reserve(&self, key: &str, amount: f64, limit: f64) -> Result<bool, GatewayError> {
+        let mut spent = self.spent.entry(key.to_string()).or_default();
+        if *spent >= limit || *spent + amount > limit {
+            return Ok(false);
+        }
+        *spent += amount;
+        Ok(true)
+    }
+}
+
+pub struct SpendBudget {
+    config: SpendBudgetConfig,
+    store: Arc<dyn BudgetStore>,
+}
+
+impl SpendBudget {
+    pub fn new(config: SpendBudgetConfig, store: Arc<dyn BudgetStore>) -> Self {
+        Self { config, store }
+    }
+
+    /// Account charged for a request, `None` when no budget applies to it. The account is
+    /// identified by the API key only, values chosen by the client, like tags, would let a
+    /// client switch to a fresh budget.
+    pub fn account(&self, credentials: Option<&Credentials>) -> Option<BudgetAccount> {
+        let api_key = match credentials {
+            Some(Credentials::ApiKey(credentials)) => credentials.api_key.as_str(),
+            Some(Credentials::ApiKeyWithEndpoint { api_key, .. }) => api_key.as_str(),
+            _ => "anonymous",
+        };
+
+        let limit = self
+            .config
+            .keys
+            .get(api_key)
+            .or(self.config.default.as_ref())
+            .copied()?;
+
+        Some(BudgetAccount {
+            store: self.store.clone(),
+            key: get_monthly_key("budget", api_key),
+            limit,
+            reserved: Mutex::new(VecDeque::new()),
+        })
+    }
+}
+
+/// Spend of one API key in the current month, held for the duration of a request
+pub struct BudgetAccount {
+    store: Arc<dyn BudgetStore>,
+    key: String,
+    limit: f64,
+    /// Estimates reserved by the model calls of the request that did not report their cost
+    reserved: Mutex<VecDeque<f64>>,
+}
+
+impl BudgetAccount {
+    /// Reserves the estimated cost of a model call, rejecting the call when it does not fit
+    /// in the remaining budget. The reservation is replaced by the cost of the call once it
+    /// is known and given back when the request ends without it.
+    pub async fn reserve(&self, estimated_cost: f64) -> Result<(), GatewayApiError> {
+        if !self
+            .store
+            .reserve(&self.key, estimated_cost, self.limit)
+            .await?
+        {
+            let spent = self.store.spent(&self.key).await?;
+            return Err(GatewayApiError::BudgetExceeded(format!(
+                "Spend budget exceeded: estimated cost ${estimated_cost:.4}, remaining ${:.4} of ${:.2}",
+                (self.limit - spent).max(0.0),
+                self.limit
+            )));
+        }
+        self.reserved.lock().push_back(estimated_cost);
+        Ok(())
+    }
+
+    /// Charges the cost of a finished model call in place of its reservation
+    pub async fn debit(&self, cost: f64) {
+        let reserved = self.reserved.lock().pop_front().unwrap_or(0.0);
+        if let Err(e) = self.store.debit(&self.key, cost - reserved).await {
+            tracing::error!("Error debiting spend budget {}: {e}", self.key);
+        }
+    }
+}
+
+impl Drop for BudgetAccount {
+    fn drop(&mut self) {
+        let refund: f64 = self.reserved.get_mut().drain(..).sum();
+        if refund == 0.0 {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let store = self.store.clone();
+        let key = std::mem::take(&mut self.key);
+        runtime.spawn(async move {
+            if let Err(e) = store.debit(&key, -refund).await {
+                tracing::error!("Error releasing spend budget {key}: {e}");
+            }
+        });
+    }
+}
+
+/// Output limit assumed for models whose metadata has none
+pub const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 4096;
+
+/// Usage of a chat request before it runs. The output is assumed to use all of
+/// `max_tokens`, or of the model's output limit when the request sets none.
+pub fn estimate_request_usage(
+    request: &ChatCompletionRequest,
+    model_output_limit: u32,
+) -> CompletionModelUsage {
+    let input = serde_json::to_string(&request.messages).unwrap_or_default();
+    let mut usage = estimate_usage(&input, "");
+    usage.output_tokens = request.output_tokens_limit().unwrap_or(model_output_limit);
+    usage.total_tokens += usage.output_tokens;
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::credentials::ApiKeyCredentials;
+
+    fn budget() -> SpendBudget {
+        SpendBudget::new(
+            SpendBudgetConfig {
+                default: Some(1.0),
+                keys: HashMap::from([("research-key".to_string(), 10.0)]),
+            },
+            Arc::new(InMemoryBudgetStore::default()),
+        )
+    }
+
+    fn credentials(api_key: &str) -> Credentials {
+        Credentials::ApiKey(ApiKeyCredentials {
+            api_key: api_key.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_rejects_once_spent() {
+        let budget = budget();
+        let account = budget.account(Some(&credentials("key-a"))).unwrap();
+
+        assert!(account.reserve(0.6).await.is_ok());
+        account.debit(0.6).await;
+        assert!(matches!(
+            account.reserve(0.6).await,
+            Err(GatewayApiError::BudgetExceeded(_))
+        ));
+        assert!(account.reserve(0.3).await.is_ok());
+        account.debit(0.5).await;
+        assert!(account.reserve(0.0).await.is_err());
+
+        // Other keys have their own budget
+        let other = budget.account(Some(&credentials("key-b"))).unwrap();
+        assert!(other.reserve(0.6).await.is_ok());
+        assert_eq!(
+            budget
+                .account(Some(&credentials("research-key")))
+                .unwrap()
+                .limit,
+            10.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_reservations() {
+        let budget = budget();
+        let first = budget.account(Some(&credentials("key-a"))).unwrap();
+        let second = budget.account(Some(&credentials("key-a"))).unwrap();
+
+        // Both requests checked before either finished, only one fits
+        assert!(first.reserve(0.6).await.is_ok());
+        assert!(second.reserve(0.6).await.is_err());
+
+        // The cost replaces the reservation, the rest is given back
+        first.debit(0.2).await;
+        assert!(second.reserve(0.6).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unused_reservation_is_released() {
+        let budget = budget();
+        let account = budget.account(Some(&credentials("key-a"))).unwrap();
+        assert!(account.reserve(0.9).await.is_ok());
+        drop(account);
+        tokio::task::yield_now().await;
+
+        let account = budget.account(Some(&credentials("key-a"))).unwrap();
+        assert!(account.reserve(0.9).await.is_ok());
+    }
+
+    #[test]
+    fn test_estimate_uses_output_limit() {
+        let mut request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "Summarize the quarterly report"}],
+            "max_tokens": 500
+        }))
+        .unwrap();
+        let usage = estimate_request_usage(&request, 16_384);
+        assert!(usage.input_tokens > 0);
+        assert_eq!(usage.output_tokens, 500);
+        assert_eq!(usage.total_tokens, usage.input_tokens + 500);
+
+        request.max_tokens = None;
+        let usage = estimate_request_usage(&request, 16_384);
+        assert_eq!(usage.output_tokens, 16_384);
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Routes requests to another model by the tags of the `x-tags` header
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TagRoutingConfig {
+    /// Evaluated in order, the first matching rule wins
+    #[serde(default)]
+    pub rules: Vec<TagRoutingRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TagRoutingRule {
+    pub tag: String,
+    pub value: String,
+    /// Model used instead of the requested one
+    pub model: String,
+    /// Requested models the rule applies to, all models when empty
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+impl TagRoutingConfig {
+    /// First rule matching the tags of a request for `model`, requests without a match
+    /// keep the requested model
+    pub fn route(&self, model: &str, tags: &HashMap<String, String>) -> Option<&TagRoutingRule> {
+        self.rules.iter().find(|rule| {
+            tags.get(&rule.tag) == Some(&rule.value)
+                && (rule.models.is_empty() || rule.models.iter().any(|m| m == model))
+                && rule.model != model
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(tag: &str, value: &str, model: &str, models: &[&str]) -> TagRoutingRule {
+        TagRoutingRule {
+            tag: tag.to_string(),
+            value: value.to_string(),
+            model: model.to_string(),
+            models: models.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let config = TagRoutingConfig {
+            rules: vec![
+                rule("env", "staging", "openai/gpt-4o-mini", &["openai/gpt-4o"]),
+                rule("tier", "cheap", "anthropic/claude-3-5-haiku", &[]),
+                rule("tier", "cheap", "openai/gpt-4o-mini", &[]),
+            ],
+        };
+        let tags = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let both = tags(&[("env", "staging"), ("tier", "cheap")]);
+        assert_eq!(
+            config.route("openai/gpt-4o", &both).unwrap().model,
+            "openai/gpt-4o-mini"
+        );
+        // The staging rule is limited to gpt-4o
+        assert_eq!(
+            config.route("gemini/gemini-1.5-pro", &both).unwrap().model,
+            "anthropic/claude-3-5-haiku"
+        );
+        assert!(config
+            .route("openai/gpt-4o", &tags(&[("tier", "premium")]))
+            .is_none());
+        assert!(config.route("openai/gpt-4o", &HashMap::new()).is_none());
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::events::{JsonValue, RecordResult, SPAN_MODEL_CALL};
+use crate::handler::CallbackHandlerFn;
+use crate::handler::ModelEventWithDetails;
+use crate::model::openai_spec_client::openai_spec_client;
+use crate::model::transcription::OpenAITranscription;
+use crate::model::types::ModelEvent;
+use crate::models::ModelMetadata;
+use crate::types::audio::TranscriptionResponse;
+use crate::types::credentials::ApiKeyCredentials;
+use crate::types::gateway::{CreateTranscriptionRequest, TranscriptionModelUsage, Usage};
+use crate::types::provider::InferenceModelProvider;
+use crate::GatewayError;
+use crate::{
+    model::types::ModelEventType,
+    types::{
+        credentials::Credentials,
+        engine::{Model, ModelTools, ModelType},
+        gateway::CostCalculator,
+    },
+};
+use actix_web::HttpRequest;
+use tracing::info_span;
+use tracing_futures::Instrument;
+use valuable::Valuable;
+
+use super::get_key_credentials;
+use super::ProvidersConfig;
+
+pub async fn handle_transcription(
+    mut request: CreateTranscriptionRequest,
+    callback_handler: &CallbackHandlerFn,
+    llm_model: &ModelMetadata,
+    key_credentials: Option<&Credentials>,
+    cost_calculator: Arc<Box<dyn CostCalculator>>,
+    tags: HashMap<String, String>,
+    req: HttpRequest,
+) -> Result<TranscriptionResponse, GatewayError> {
+    request.model = llm_model.inference_provider.model_name.clone();
+    let provider = &llm_model.inference_provider.provider;
+
+    let providers_config = req.app_data::<ProvidersConfig>().cloned();
+    let key = get_key_credentials(
+        key_credentials,
+        providers_config.as_ref(),
+        &provider.to_string(),
+    );
+    let mut endpoint = llm_model.inference_provider.endpoint.clone();
+    let credentials = key.as_ref().and_then(|cred| match cred {
+        Credentials::ApiKey(key) => Some(key.clone()),
+        Credentials::ApiKeyWithEndpoint {
+            api_key,
+            endpoint: e,
+        } => {
+            endpoint = Some(e.clone());
+            Some(ApiKeyCredentials {
+                api_key: api_key.clone(),
+            })
+        }
+        _ => None,
+    });
+
+    let model = match provider {
+        InferenceModelProvider::OpenAI => {
+            OpenAITranscription::new(credentials.as_ref(), None, endpoint.as_deref())
+        }
+        InferenceModelProvider::Proxy(name) => {
+            let client = openai_spec_client(credentials.as_ref(), endpoint.as_deref(), name)?;
+            OpenAITranscription::new(credentials.as_ref(), Some(client), None)
+        }
+        InferenceModelProvider::Anthropic
+        | InferenceModelProvider::Gemini
+        | InferenceModelProvider::Bedrock => {
+            return Err(GatewayError::CustomError(format!(
+                "Unsupported provider: {}",
+                llm_model.inference_provider.model_name
+            )))
+        }
+    }?;
+
+    let provider_name = match provider {
+        InferenceModelProvider::Proxy(name) => name.clone(),
+        _ => provider.to_string(),
+    };
+
+    let db_model = Model {
+        name: llm_model.model.clone(),
+        description: None,
+        provider_name: provider_name.clone(),
+        prompt_name: None,
+        model_params: HashMap::new(),
+        tools: ModelTools(vec![]),
+        model_type: ModelType::Transcription,
+        response_schema: None,
+        credentials: key_credentials.cloned(),
+    };
+
+    let span = info_span!(
+        target: "langdb::user_tracing::models", SPAN_MODEL_CALL,
+        input = serde_json::to_string(&request)?,
+        model = llm_model.model.clone(),
+        provider_name = provider_name.clone(),
+        output = tracing::field::Empty,
+        error = tracing::field::Empty,
+        cost = tracing::field::Empty,
+        usage = tracing::field::Empty,
+        tags = JsonValue(&serde_json::to_value(tags.clone())?).as_value(),
+    );
+
+    let model_name = llm_model.model.clone();
+    let callback_handler = callback_handler.clone();
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Option<ModelEvent>>(1000);
+
+    let handle = tokio::spawn(
+        async move {
+            while let Some(Some(msg)) = rx.recv().await {
+                if let ModelEventType::TranscriptionFinish(finish_event) = &msg.event {
+                    let s = tracing::Span::current();
+                    let u = TranscriptionModelUsage {
+                        duration_seconds: finish_event.duration_seconds,
+                    };
+                    match cost_calculator
+                        .calculate_cost(
+                            &model_name,
+                            &provider_name,
+                            &Usage::TranscriptionModelUsage(u.clone()),
+                        )
+                        .await
+                    {
+                        Ok(c) => {
+                            s.record("cost", serde_json::to_string(&c).unwrap());
+                        }
+                        Err(e) => {
+                            tracing::error!("Error calculating cost: {:?}", e);
+                        }
+                    };
+
+                    s.record("usage", serde_json::to_string(&u).unwrap());
+                }
+
+                callback_handler
+                    .on_message(ModelEventWithDetails::new(msg, Some(db_model.clone())));
+            }
+        }
+        .instrument(span.clone()),
+    );
+
+    let result = async {
+        let result = model.transcribe(&request, tx, tags).await;
+        let _ = result.as_ref().map(|r| r.text.clone()).record();
+        result
+    }
+    .instrument(span)
+    .await;
+
+    handle.await.unwrap();
+
+    result
+}
@@ -0,0 +1,151 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+struct Entry<V> {
+    value: V,
+    /// Insertion order, used to evict the oldest entries
+    sequence: u64,
+    expires_at: Instant,
+}
+
+/// In-memory map whose entries expire after their lifetime. A full cache drops expired
+/// entries first and then the oldest tenth, so the scan is amortized over the following
+/// inserts.
+pub struct TtlCache<V> {
+    max_entries: usize,
+    entries: DashMap<String, Entry<V>>,
+    next_sequence: AtomicU64,
+}
+
+impl<V: Clone> TtlCache<V> {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries: max_entries.max(1),
+            entries: DashMap::new(),
+            next_sequence: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        let entry = self.entries.get(key)?;
+        if entry.expires_at <= Instant::now() {
+            drop(entry);
+            self.entries.remove(key);
+            return None;
+        }
+
+        Some(entry.value.clone())
+    }
+
+    pub fn insert(&self, key: String, value: V, ttl: Duration) {
+        if !self.entries.contains_key(&key) {
+            self.evict();
+        }
+
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                sequence: self.next_sequence.fetch_add(1, Ordering::Relaxed),
+                expires_at: Instant::now() + ttl,
+            },
+        );
+    }
+
+    /// Removes the entries `keep` rejects and returns how many were removed
+    pub fn retain(&self, mut keep: impl FnMut(&str, &V) -> bool) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|key, entry| keep(key, &entry.value));
+        before.saturating_sub(self.entries.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn evict(&self) {
+        if self.entries.len() < self.max_entries {
+            return;
+        }
+
+        let now = Instant::now();
+        self.entries.retain(|_, entry| entry.expires_at > now);
+        if self.entries.len() < self.max_entries {
+            return;
+        }
+
+        let mut sequences: Vec<u64> = self.entries.iter().map(|entry| entry.sequence).collect();
+        let remove = (sequences.len() + (self.max_entries / 10).max(1))
+            .saturating_sub(self.max_entries)
+            .min(sequences.len());
+        if remove == 0 {
+            return;
+        }
+        let (_, cutoff, _) = sequences.select_nth_unstable(remove - 1);
+        let cutoff = *cutoff;
+        self.entries.retain(|_, entry| entry.sequence > cutoff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_expired_entries() {
+        let cache = TtlCache::new(10);
+        cache.insert("a".to_string(), 1, TTL);
+        cache.insert("b".to_string(), 2, Duration::ZERO);
+
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_eviction_is_amortized() {
+        let cache = TtlCache::new(20);
+        for i in 0..20 {
+            cache.insert(i.to_string(), i, TTL);
+        }
+
+        // The oldest tenth makes room for the next inserts
+        cache.insert("20".to_string(), 20, TTL);
+        assert_eq!(cache.len(), 19);
+        assert_eq!(cache.get("0"), None);
+        assert_eq!(cache.get("1"), None);
+        assert_eq!(cache.get("2"), Some(2));
+
+        cache.insert("21".to_string(), 21, TTL);
+        assert_eq!(cache.len(), 20);
+    }
+
+    #[test]
+    fn test_expired_entries_are_evicted_first() {
+        let cache = TtlCache::new(2);
+        cache.insert("a".to_string(), 1, TTL);
+        cache.insert("b".to_string(), 2, Duration::ZERO);
+        cache.insert("c".to_string(), 3, TTL);
+
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+    }
+
+    #[test]
+    fn test_retain() {
+        let cache = TtlCache::new(10);
+        cache.insert("a".to_string(), 1, TTL);
+        cache.insert("b".to_string(), 2, TTL);
+
+        assert_eq!(cache.retain(|_, value| *value > 1), 1);
+        assert_eq!(cache.get("a"), None);
+    }
+}
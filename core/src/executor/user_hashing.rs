@@ -0,0 +1,84 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserHashAlgorithm {
+    #[default]
+    HmacSha256,
+    Sha256,
+}
+
+/// Replaces the `user` identifier forwarded to providers with a salted hash,
+/// so providers can track abuse per user without seeing the real id.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserHashingConfig {
+    #[serde(default)]
+    pub algorithm: UserHashAlgorithm,
+    pub salt: String,
+}
+
+impl UserHashingConfig {
+    pub fn hash(&self, user: &str) -> String {
+        match self.algorithm {
+            UserHashAlgorithm::HmacSha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(self.salt.as_bytes())
+                    .expect("HMAC accepts keys of any size");
+                mac.update(user.as_bytes());
+                format!("{:x}", mac.finalize().into_bytes())
+            }
+            UserHashAlgorithm::Sha256 => {
+                let mut hasher = Sha256::new();
+                hasher.update(self.salt.as_bytes());
+                hasher.update(user.as_bytes());
+                format!("{:x}", hasher.finalize())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(algorithm: UserHashAlgorithm, salt: &str) -> UserHashingConfig {
+        UserHashingConfig {
+            algorithm,
+            salt: salt.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        for algorithm in [UserHashAlgorithm::HmacSha256, UserHashAlgorithm::Sha256] {
+            let config = config(algorithm, "salt");
+            assert_eq!(config.hash("user-1"), config.hash("user-1"));
+            assert_ne!(config.hash("user-1"), config.hash("user-2"));
+            assert_ne!(config.hash("user-1"), "user-1");
+            assert_eq!(config.hash("user-1").len(), 64);
+        }
+    }
+
+    #[test]
+    fn test_hash_depends_on_salt_and_algorithm() {
+        let hmac = config(UserHashAlgorithm::HmacSha256, "salt");
+        assert_ne!(
+            hmac.hash("user-1"),
+            config(UserHashAlgorithm::HmacSha256, "other").hash("user-1")
+        );
+        assert_ne!(
+            hmac.hash("user-1"),
+            config(UserHashAlgorithm::Sha256, "salt").hash("user-1")
+        );
+    }
+
+    #[test]
+    fn test_sha256_known_value() {
+        // sha256("abc")
+        assert_eq!(
+            config(UserHashAlgorithm::Sha256, "a").hash("bc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
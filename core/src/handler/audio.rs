@@ -0,0 +1,84 @@
+use crate::error::InvalidRequestError;
+use crate::executor::transcription::handle_transcription;
+use crate::handler::record_map_err;
+use crate::handler::AvailableModels;
+use crate::handler::CallbackHandlerFn;
+use crate::model::transcription::audio_limits;
+use crate::types::gateway::CreateTranscriptionRequest;
+use crate::types::{credentials::Credentials, gateway::CostCalculator};
+use crate::GatewayApiError;
+use actix_multipart::Multipart;
+use actix_web::HttpMessage;
+use actix_web::{web, HttpRequest, HttpResponse};
+use tracing::Span;
+use tracing_futures::Instrument;
+
+use super::can_execute_llm_for_request;
+use super::extract_tags;
+use super::find_model_by_full_name;
+use super::form::MultipartForm;
+
+/// Largest field read from the form, providers may accept less
+const MAX_UPLOAD_BYTES: usize = 25 * 1024 * 1024;
+
+pub async fn create_transcription(
+    payload: Multipart,
+    models: web::Data<AvailableModels>,
+    req: HttpRequest,
+    cost_calculator: web::Data<Box<dyn CostCalculator>>,
+    callback_handler: web::Data<CallbackHandlerFn>,
+) -> Result<HttpResponse, GatewayApiError> {
+    can_execute_llm_for_request(&req).await?;
+
+    let mut form = MultipartForm::read(payload, MAX_UPLOAD_BYTES).await?;
+    let request = CreateTranscriptionRequest {
+        file: form.file("file")?,
+        model: form.text("model")?,
+        language: form.fields.remove("language"),
+        prompt: form.fields.remove("prompt"),
+        response_format: form.parse("response_format")?.unwrap_or_default(),
+        temperature: form.number("temperature")?,
+    };
+
+    let available_models = models.into_inner();
+    let llm_model = find_model_by_full_name(&request.model, &available_models)?;
+
+    let limits = audio_limits(&llm_model.inference_provider.provider).ok_or_else(|| {
+        InvalidRequestError::new(
+            "model",
+            "unsupported_value",
+            format!("Model {} does not support transcription", request.model),
+        )
+    })?;
+    limits
+        .validate(&request.file)
+        .map_err(|message| InvalidRequestError::new("file", "invalid_value", message))?;
+
+    let span = Span::or_current(tracing::info_span!(
+        target: "langdb::user_tracing::api_invoke",
+        "api_invoke",
+        request = tracing::field::Empty,
+        response = tracing::field::Empty,
+        error = tracing::field::Empty,
+        message_id = tracing::field::Empty,
+    ));
+    span.record("request", &serde_json::to_string(&request)?);
+
+    let tags = extract_tags(&req)?;
+
+    let key = req.extensions().get::<Credentials>().cloned();
+    let result = handle_transcription(
+        request,
+        callback_handler.get_ref(),
+        &llm_model,
+        key.as_ref(),
+        cost_calculator.into_inner(),
+        tags,
+        req,
+    )
+    .instrument(span.clone())
+    .await
+    .map_err(|e| record_map_err(e, span.clone()))?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
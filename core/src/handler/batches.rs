@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use bytes::Bytes;
+
+use crate::error::InvalidRequestError;
+use crate::executor::batches::{BatchRequestItem, Batches};
+use crate::executor::context::ExecutorContext;
+use crate::handler::middleware::key_rate_limit::KeyRateLimiter;
+use crate::types::credentials::Credentials;
+use crate::types::gateway::CostCalculator;
+use crate::types::guardrails::service::GuardrailsEvaluator;
+use crate::GatewayApiError;
+
+use super::{can_execute_llm_for_request, AvailableModels, CallbackHandlerFn};
+
+/// Largest JSONL upload accepted, far above the default payload limit of actix
+pub const MAX_BATCH_FILE_BYTES: usize = 100 * 1024 * 1024;
+
+fn batches(req: &HttpRequest) -> Result<&Arc<Batches>, GatewayApiError> {
+    req.app_data::<Arc<Batches>>().ok_or_else(|| {
+        InvalidRequestError::new("", "unsupported_value", "Batches are not enabled").into()
+    })
+}
+
+/// Accepts a JSONL upload with one chat request per line and answers with the batch
+/// before its requests run
+pub async fn create_batch(
+    body: Bytes,
+    callback_handler: web::Data<CallbackHandlerFn>,
+    req: HttpRequest,
+    provided_models: web::Data<AvailableModels>,
+    cost_calculator: web::Data<Box<dyn CostCalculator>>,
+    evaluator_service: web::Data<Box<dyn GuardrailsEvaluator>>,
+) -> Result<HttpResponse, GatewayApiError> {
+    can_execute_llm_for_request(&req).await?;
+    let batches = batches(&req)?;
+
+    let body = std::str::from_utf8(&body).map_err(|e| {
+        InvalidRequestError::new(
+            "file",
+            "invalid_value",
+            format!("Batch file is not UTF-8: {e}"),
+        )
+    })?;
+    let items = body
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str::<BatchRequestItem>(line).map_err(|e| {
+                let message = format!("Invalid batch line {}: {e}", index + 1);
+                InvalidRequestError::new("file", "invalid_value", message)
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let executor_context = ExecutorContext::new(
+        callback_handler.get_ref().clone(),
+        cost_calculator.into_inner(),
+        provided_models.get_ref().clone(),
+        &req,
+        evaluator_service.into_inner(),
+    )?;
+
+    let limiter = req.app_data::<Arc<KeyRateLimiter>>().cloned();
+    let batch = batches.submit(items, executor_context, limiter)?;
+    Ok(HttpResponse::Ok().json(batch))
+}
+
+/// Batch with the results of the requests finished so far, batches submitted with other
+/// API keys are not found
+pub async fn get_batch(
+    id: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, GatewayApiError> {
+    let credentials = req.extensions().get::<Credentials>().cloned();
+    Ok(match batches(&req)?.get(&id, credentials.as_ref()) {
+        Some(batch) => HttpResponse::Ok().json(batch),
+        None => HttpResponse::NotFound().finish(),
+    })
+}
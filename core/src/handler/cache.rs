@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::types::cache::{CacheFlushFilter, CacheRegistry};
+use crate::GatewayApiError;
+
+/// Credentials required by admin endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminConfig {
+    pub api_key: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheFlushRequest {
+    /// Cache kind to flush, all caches when empty
+    #[serde(default)]
+    pub cache: Option<String>,
+    #[serde(flatten)]
+    pub filter: CacheFlushFilter,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheFlushResponse {
+    pub invalidated: usize,
+    pub caches: HashMap<String, usize>,
+}
+
+pub(crate) fn check_admin_access(req: &HttpRequest) -> Result<(), GatewayApiError> {
+    let Some(config) = req.app_data::<AdminConfig>() else {
+        return Err(GatewayApiError::Forbidden(
+            "Admin endpoints are disabled".to_string(),
+        ));
+    };
+
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    match token {
+        Some(token) if token == config.api_key => Ok(()),
+        _ => Err(GatewayApiError::Unauthorized(
+            "Invalid admin api key".to_string(),
+        )),
+    }
+}
+
+pub async fn flush_cache(
+    request: Option<web::Json<CacheFlushRequest>>,
+    req: HttpRequest,
+) -> Result<HttpResponse, GatewayApiError> {
+    check_admin_access(&req)?;
+
+    let request = request.map(|r| r.into_inner()).unwrap_or_default();
+    let caches = req
+        .app_data::<CacheRegistry>()
+        .map(|registry| registry.flush(request.cache.as_deref(), &request.filter))
+        .unwrap_or_default();
+
+    tracing::info!("Cache flushed with filter {:?}: {:?}", request, caches);
+
+    Ok(HttpResponse::Ok().json(CacheFlushResponse {
+        invalidated: caches.values().sum(),
+        caches,
+    }))
+}
@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use crate::events::JsonValue;
 use crate::executor::context::ExecutorContext;
 use crate::routing::RoutingStrategy;
@@ -8,7 +6,7 @@ use crate::types::gateway::CompletionModelUsage;
 use crate::types::gateway::Extra;
 use crate::types::guardrails::service::GuardrailsEvaluator;
 use crate::usage::InMemoryStorage;
-use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
 use bytes::Bytes;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -21,6 +19,7 @@ use crate::types::gateway::{
 use tracing::Span;
 use tracing_futures::Instrument;
 
+use crate::handler::middleware::request_id::RequestId;
 use crate::handler::AvailableModels;
 use crate::handler::CallbackHandlerFn;
 use crate::GatewayApiError;
@@ -55,8 +54,13 @@ pub async fn create_chat_completion(
         thread_id = tracing::field::Empty,
         message_id = tracing::field::Empty,
         user = tracing::field::Empty,
+        request_id = tracing::field::Empty,
     ));
 
+    if let Some(request_id) = req.extensions().get::<RequestId>() {
+        span.record("request_id", &request_id.0);
+    }
+
     if let Some(Extra {
         user: Some(user), ..
     }) = &request.extra
@@ -85,6 +89,36 @@ pub async fn create_chat_completion(
         .await
 }
 
+fn usage_chunk(usage: &CompletionModelUsage, model_name: String) -> ChatCompletionChunk {
+    ChatCompletionChunk {
+        id: uuid::Uuid::new_v4().to_string(),
+        object: "chat.completion.chunk".to_string(),
+        created: chrono::Utc::now().timestamp(),
+        model: model_name,
+        choices: vec![],
+        usage: Some(ChatCompletionUsage {
+            prompt_tokens: usage.input_tokens as i32,
+            completion_tokens: usage.output_tokens as i32,
+            total_tokens: usage.total_tokens as i32,
+            prompt_tokens_details: usage.prompt_tokens_details.clone(),
+            completion_tokens_details: usage.completion_tokens_details.clone(),
+            cost: 0.0,
+        }),
+    }
+}
+
+/// Error frame in the shape OpenAI sends when a stream fails after it started
+fn stream_error(e: &GatewayApiError) -> serde_json::Value {
+    serde_json::json!({
+        "error": {
+            "message": e.to_string(),
+            "type": "server_error",
+            "param": null,
+            "code": e.is_content_filter().then_some("content_filter"),
+        }
+    })
+}
+
 pub fn map_sso_event(
     delta: Result<SSOChatEvent, GatewayApiError>,
     model_name: String,
@@ -104,6 +138,7 @@ pub fn map_sso_event(
                         content: None,
                         role: None,
                         tool_calls: None,
+                        logprobs: None,
                     },
                     finish_reason: Some(finish_reason.clone()),
                     logprobs: None,
@@ -112,25 +147,13 @@ pub fn map_sso_event(
             });
 
             if let Some(u) = &usage {
-                chunks.push(ChatCompletionChunk {
-                    id: uuid::Uuid::new_v4().to_string(),
-                    object: "chat.completion.chunk".to_string(),
-                    created: chrono::Utc::now().timestamp(),
-                    model: model_name.clone(),
-                    choices: vec![],
-                    usage: Some(ChatCompletionUsage {
-                        prompt_tokens: u.input_tokens as i32,
-                        completion_tokens: u.output_tokens as i32,
-                        total_tokens: u.total_tokens as i32,
-                        prompt_tokens_details: u.prompt_tokens_details.clone(),
-                        completion_tokens_details: u.completion_tokens_details.clone(),
-                        cost: 0.0,
-                    }),
-                });
+                chunks.push(usage_chunk(u, model_name.clone()));
             }
 
             Ok(chunks)
         }
+        // Final usage chunk of `stream_options.include_usage`
+        Ok((None, Some(usage), None)) => Ok(vec![usage_chunk(&usage, model_name.clone())]),
         Ok((delta, _, finish_reason)) => {
             let chunk = ChatCompletionChunk {
                 id: uuid::Uuid::new_v4().to_string(),
@@ -142,7 +165,7 @@ pub fn map_sso_event(
                         index: 0,
                         delta: d.clone(),
                         finish_reason,
-                        logprobs: None,
+                        logprobs: d.logprobs.clone(),
                     }]
                 }),
                 usage: None,
@@ -165,9 +188,7 @@ pub fn map_sso_event(
             }
         }
         Err(e) => {
-            let result = serde_json::to_string(&HashMap::from([("error", e.to_string())]))
-                .unwrap_or_else(|e| format!("{{\"error\": \"Failed to serialize chunk: {e}\"}}"));
-
+            let result = stream_error(&e).to_string();
             result_combined.push_str(&format!("data: {result}\n\n"));
         }
     }
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::error::InvalidRequestError;
+use crate::executor::chat_completion::idempotency::IDEMPOTENCY_KEY_HEADER;
+use crate::executor::chat_completion::routed_executor::{event_stream, RoutedExecutor};
+use crate::executor::context::ExecutorContext;
+use crate::handler::chat::SSOChatEvent;
+use crate::routing::RoutingStrategy;
+use crate::types::gateway::{
+    ChatCompletionChoice, ChatCompletionContent, ChatCompletionUsage, CompletionChoice,
+    CompletionRequest, CompletionResponse, CostCalculator,
+};
+use crate::types::guardrails::service::GuardrailsEvaluator;
+use crate::usage::InMemoryStorage;
+use crate::GatewayApiError;
+use actix_web::{web, HttpRequest, HttpResponse};
+use bytes::Bytes;
+use either::Either::{Left, Right};
+use futures::StreamExt;
+use tokio::sync::Mutex;
+use tracing::Span;
+use tracing_futures::Instrument;
+
+use super::can_execute_llm_for_request;
+use super::{AvailableModels, CallbackHandlerFn};
+
+pub async fn create_completion(
+    request: web::Json<CompletionRequest>,
+    callback_handler: web::Data<CallbackHandlerFn>,
+    req: HttpRequest,
+    provided_models: web::Data<AvailableModels>,
+    cost_calculator: web::Data<Box<dyn CostCalculator>>,
+    evaluator_service: web::Data<Box<dyn GuardrailsEvaluator>>,
+) -> Result<HttpResponse, GatewayApiError> {
+    can_execute_llm_for_request(&req).await?;
+
+    let request = request.into_inner();
+    let prompts = request.prompts();
+    let is_stream = request.stream.unwrap_or(false);
+    if is_stream && prompts.len() != 1 {
+        return Err(InvalidRequestError::new(
+            "stream",
+            "unsupported_value",
+            "Streaming is supported only for a single prompt",
+        )
+        .into());
+    }
+
+    let span = Span::or_current(tracing::info_span!(
+        target: "langdb::user_tracing::api_invoke",
+        "api_invoke",
+        request = tracing::field::Empty,
+        response = tracing::field::Empty,
+        error = tracing::field::Empty,
+        thread_id = tracing::field::Empty,
+        message_id = tracing::field::Empty,
+        user = tracing::field::Empty,
+    ));
+    span.record("request", &serde_json::to_string(&request)?);
+
+    let memory_storage = req.app_data::<Arc<Mutex<InMemoryStorage>>>().cloned();
+    let executor_context = ExecutorContext::new(
+        callback_handler.get_ref().clone(),
+        cost_calculator.into_inner(),
+        provided_models.get_ref().clone(),
+        &req,
+        evaluator_service.into_inner(),
+    )?;
+
+    let model_name = request.model.clone();
+    let echo = request.echo.unwrap_or(false);
+    let multiple_prompts = prompts.len() > 1;
+
+    let mut builder = None;
+    let mut choices = vec![];
+    let mut usage = ChatCompletionUsage::default();
+    for (index, prompt) in prompts.into_iter().enumerate() {
+        let chat_request = request.to_chat_request::<RoutingStrategy>(prompt.clone());
+        let executor_context = if multiple_prompts {
+            prompt_context(&executor_context, index)
+        } else {
+            executor_context.clone()
+        };
+        let routed = RoutedExecutor::new(chat_request)
+            .route(&executor_context, memory_storage.clone())
+            .instrument(span.clone())
+            .await?;
+        let builder = builder.get_or_insert_with(|| routed.response_builder());
+
+        match routed.response {
+            Left(events) => {
+                let id = uuid::Uuid::new_v4().to_string();
+                let mut prefix = echo.then_some(prompt);
+                let model_name = model_name.clone();
+                let events = events.map(move |delta| {
+                    map_legacy_sso_event(delta, &id, model_name.clone(), prefix.take())
+                });
+
+                return Ok(builder
+                    .content_type("text/event-stream")
+                    .streaming(event_stream(events, &executor_context)));
+            }
+            Right(mut response) => {
+                usage.prompt_tokens += response.usage.prompt_tokens;
+                usage.completion_tokens += response.usage.completion_tokens;
+                usage.total_tokens += response.usage.total_tokens;
+                usage.cost += response.usage.cost;
+
+                // Choices of a prompt follow the choices of the prompts before it
+                let offset = choices.len();
+                let echo_prompt = echo.then_some(prompt.as_str());
+                response.choices.sort_by_key(|c| c.index);
+                choices.extend(
+                    response
+                        .choices
+                        .into_iter()
+                        .map(|choice| completion_choice(choice, offset, echo_prompt)),
+                );
+            }
+        }
+    }
+
+    let mut builder = builder.unwrap_or_else(HttpResponse::Ok);
+    Ok(builder.json(CompletionResponse {
+        id: uuid::Uuid::new_v4().to_string(),
+        object: "text_completion".to_string(),
+        created: chrono::Utc::now().timestamp(),
+        model: model_name,
+        choices,
+        usage: Some(usage),
+    }))
+}
+
+/// Context of the prompt at `index`. Prompts run as separate chat requests, so each one is
+/// replayed under its own idempotency key.
+fn prompt_context(executor_context: &ExecutorContext, index: usize) -> ExecutorContext {
+    let mut executor_context = executor_context.clone();
+    if let Some(key) = executor_context.headers.get_mut(IDEMPOTENCY_KEY_HEADER) {
+        key.push_str(&format!(":{index}"));
+    }
+    executor_context
+}
+
+/// Legacy choice following the `offset` choices of earlier prompts, prefixed with the
+/// prompt when it is echoed
+fn completion_choice(
+    choice: ChatCompletionChoice,
+    offset: usize,
+    echo_prompt: Option<&str>,
+) -> CompletionChoice {
+    let text = match choice.message.content {
+        Some(ChatCompletionContent::Text(text)) => text,
+        Some(ChatCompletionContent::Content(parts)) => parts
+            .into_iter()
+            .filter_map(|p| p.text)
+            .collect::<Vec<_>>()
+            .join(""),
+        None => String::new(),
+    };
+
+    CompletionChoice {
+        text: format!("{}{text}", echo_prompt.unwrap_or_default()),
+        index: offset as i32 + choice.index,
+        logprobs: None,
+        finish_reason: choice.finish_reason,
+    }
+}
+
+/// Legacy completion chunk of a streamed event, all chunks of a response share its `id`
+fn map_legacy_sso_event(
+    delta: Result<SSOChatEvent, GatewayApiError>,
+    id: &str,
+    model_name: String,
+    prefix: Option<String>,
+) -> Result<Bytes, GatewayApiError> {
+    let chunk = match delta {
+        Ok((delta, usage, finish_reason)) => {
+            let text = delta.and_then(|d| d.content).unwrap_or_default();
+            let text = match prefix {
+                Some(prefix) => format!("{prefix}{text}"),
+                None => text,
+            };
+            let chunk = CompletionResponse {
+                id: id.to_string(),
+                object: "text_completion".to_string(),
+                created: chrono::Utc::now().timestamp(),
+                model: model_name,
+                choices: vec![CompletionChoice {
+                    text,
+                    index: 0,
+                    logprobs: None,
+                    finish_reason,
+                }],
+                usage: usage.map(|u| ChatCompletionUsage {
+                    prompt_tokens: u.input_tokens as i32,
+                    completion_tokens: u.output_tokens as i32,
+                    total_tokens: u.total_tokens as i32,
+                    prompt_tokens_details: u.prompt_tokens_details.clone(),
+                    completion_tokens_details: u.completion_tokens_details.clone(),
+                    cost: 0.0,
+                }),
+            };
+            serde_json::to_string(&chunk)
+                .unwrap_or_else(|e| format!("{{\"error\": \"Failed to serialize chunk: {e}\"}}"))
+        }
+        Err(e) => serde_json::to_string(&HashMap::from([("error", e.to_string())]))
+            .unwrap_or_else(|e| format!("{{\"error\": \"Failed to serialize chunk: {e}\"}}")),
+    };
+
+    Ok(Bytes::from(format!("data: {chunk}\n\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::{ChatCompletionDelta, ChatCompletionMessage, CompletionModelUsage};
+
+    #[test]
+    fn test_completion_choice() {
+        let choice = ChatCompletionChoice {
+            index: 0,
+            message: ChatCompletionMessage::new_text("assistant".to_string(), " world".to_string()),
+            finish_reason: Some("stop".to_string()),
+            logprobs: None,
+        };
+
+        let completion = completion_choice(choice.clone(), 2, None);
+        assert_eq!(completion.text, " world");
+        assert_eq!(completion.index, 2);
+        assert_eq!(completion.finish_reason.as_deref(), Some("stop"));
+
+        let second = ChatCompletionChoice {
+            index: 1,
+            ..choice.clone()
+        };
+        assert_eq!(completion_choice(second, 2, None).index, 3);
+
+        assert_eq!(
+            completion_choice(choice, 0, Some("Hello")).text,
+            "Hello world"
+        );
+    }
+
+    #[test]
+    fn test_stream_chunk() {
+        let delta = ChatCompletionDelta {
+            role: None,
+            content: Some(" world".to_string()),
+            tool_calls: None,
+            logprobs: None,
+        };
+        let usage = CompletionModelUsage {
+            input_tokens: 3,
+            output_tokens: 2,
+            total_tokens: 5,
+            ..Default::default()
+        };
+
+        let chunk = map_legacy_sso_event(
+            Ok((Some(delta), Some(usage), Some("stop".to_string()))),
+            "cmpl-1",
+            "gpt-4o-mini".to_string(),
+            Some("Hello".to_string()),
+        )
+        .unwrap();
+        let chunk = std::str::from_utf8(&chunk).unwrap();
+        let json: serde_json::Value = serde_json::from_str(
+            chunk
+                .strip_prefix("data: ")
+                .and_then(|c| c.strip_suffix("\n\n"))
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(json["id"], "cmpl-1");
+        assert_eq!(json["object"], "text_completion");
+        assert_eq!(json["choices"][0]["text"], "Hello world");
+        assert_eq!(json["choices"][0]["finish_reason"], "stop");
+        assert_eq!(json["usage"]["total_tokens"], 5);
+    }
+}
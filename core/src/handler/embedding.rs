@@ -1,4 +1,6 @@
-use crate::executor::embeddings::handle_embeddings_invoke;
+use crate::executor::embeddings::{
+    handle_embeddings_invoke, handle_ensemble_embeddings_invoke, EmbeddingsConfig, EmbeddingsResult,
+};
 use crate::types::credentials::Credentials;
 use actix_web::{web, HttpResponse};
 use actix_web::{HttpMessage, HttpRequest};
@@ -24,7 +26,10 @@ pub async fn embeddings_handler(
     can_execute_llm_for_request(&req).await?;
     let request = request.into_inner();
     let available_models = models.into_inner();
-    let llm_model = find_model_by_full_name(&request.model, &available_models)?;
+    let ensemble = req
+        .app_data::<EmbeddingsConfig>()
+        .and_then(|c| c.ensembles.get(&request.model))
+        .cloned();
     let key_credentials = req.extensions().get::<Credentials>().cloned();
 
     let span = Span::or_current(tracing::info_span!(
@@ -37,15 +42,55 @@ pub async fn embeddings_handler(
     ));
     span.record("request", &serde_json::to_string(&request)?);
 
-    let result = handle_embeddings_invoke(
-        request,
-        callback_handler.get_ref(),
-        &llm_model,
-        key_credentials.as_ref(),
-        req,
-    )
-    .instrument(span)
-    .await?;
+    let (model_name, provider_name, result) = match ensemble {
+        Some(ensemble) => {
+            let llm_models = ensemble
+                .models
+                .iter()
+                .map(|m| find_model_by_full_name(m, &available_models))
+                .collect::<Result<Vec<_>, _>>()?;
+            let alias = request.model.clone();
+            let result = handle_ensemble_embeddings_invoke(
+                request,
+                &alias,
+                &ensemble,
+                callback_handler.get_ref(),
+                &llm_models,
+                key_credentials.as_ref(),
+                req,
+            )
+            .instrument(span)
+            .await?;
+            (alias, "ensemble".to_string(), result)
+        }
+        None => {
+            let llm_model = find_model_by_full_name(&request.model, &available_models)?;
+            let result = handle_embeddings_invoke(
+                request,
+                callback_handler.get_ref(),
+                &llm_model,
+                key_credentials.as_ref(),
+                req,
+            )
+            .instrument(span)
+            .await?;
+            (
+                llm_model.model.clone(),
+                llm_model.inference_provider.provider.to_string(),
+                result,
+            )
+        }
+    };
+
+    let EmbeddingsResult {
+        response: result,
+        errors,
+    } = result;
+    let dimensions = result
+        .data
+        .first()
+        .map(|e| e.embedding.len())
+        .unwrap_or_default();
 
     let data = result
         .data
@@ -58,18 +103,17 @@ pub async fn embeddings_handler(
         .collect();
 
     Ok(HttpResponse::Ok()
-        .append_header(("X-Model-Name", llm_model.model.clone()))
-        .append_header((
-            "X-Provider-Name",
-            llm_model.inference_provider.provider.to_string(),
-        ))
+        .append_header(("X-Model-Name", model_name.clone()))
+        .append_header(("X-Provider-Name", provider_name))
+        .append_header(("X-Embedding-Dimensions", dimensions.to_string()))
         .json(CreateEmbeddingResponse {
             object: "list".into(),
             data,
-            model: llm_model.model.clone(),
+            model: model_name,
             usage: EmbeddingUsage {
                 prompt_tokens: result.usage.prompt_tokens,
                 total_tokens: result.usage.total_tokens,
             },
+            errors,
         }))
 }
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use crate::error::InvalidRequestError;
+use crate::types::gateway::FileUpload;
+use crate::GatewayApiError;
+use actix_multipart::{Multipart, MultipartError};
+use bytes::BytesMut;
+use futures::TryStreamExt;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Text fields and files of a multipart request
+#[derive(Default)]
+pub(crate) struct MultipartForm {
+    pub(crate) fields: HashMap<String, String>,
+    pub(crate) files: HashMap<String, FileUpload>,
+}
+
+impl MultipartForm {
+    /// Reads the whole form, rejecting any field larger than `max_bytes`
+    pub(crate) async fn read(
+        mut payload: Multipart,
+        max_bytes: usize,
+    ) -> Result<Self, GatewayApiError> {
+        let mut form = Self::default();
+        while let Some(mut field) = payload.try_next().await.map_err(multipart_error)? {
+            let Some(name) = field.name().map(str::to_string) else {
+                continue;
+            };
+            let file_name = field
+                .content_disposition()
+                .and_then(|d| d.get_filename())
+                .map(str::to_string);
+
+            let mut data = BytesMut::new();
+            while let Some(chunk) = field.try_next().await.map_err(multipart_error)? {
+                if data.len() + chunk.len() > max_bytes {
+                    return Err(InvalidRequestError::new(
+                        name.clone(),
+                        "invalid_value",
+                        format!("Field {name} exceeds {max_bytes} bytes"),
+                    )
+                    .into());
+                }
+                data.extend_from_slice(&chunk);
+            }
+
+            match file_name {
+                Some(file_name) => {
+                    form.files.insert(
+                        name,
+                        FileUpload {
+                            file_name,
+                            data: data.freeze(),
+                        },
+                    );
+                }
+                None => {
+                    let value = String::from_utf8(data.to_vec()).map_err(|_| {
+                        let message = format!("Field {name} is not valid UTF-8");
+                        InvalidRequestError::new(name.clone(), "invalid_value", message)
+                    })?;
+                    form.fields.insert(name, value);
+                }
+            }
+        }
+
+        Ok(form)
+    }
+
+    pub(crate) fn file(&mut self, name: &str) -> Result<FileUpload, GatewayApiError> {
+        self.files.remove(name).ok_or_else(|| missing(name, "file"))
+    }
+
+    pub(crate) fn text(&mut self, name: &str) -> Result<String, GatewayApiError> {
+        self.fields
+            .remove(name)
+            .ok_or_else(|| missing(name, "field"))
+    }
+
+    /// Form values are plain text, so they are parsed as JSON strings
+    pub(crate) fn parse<T: DeserializeOwned>(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<T>, GatewayApiError> {
+        self.fields
+            .remove(name)
+            .map(|value| serde_json::from_value(Value::String(value)).map_err(|e| invalid(name, e)))
+            .transpose()
+    }
+
+    /// Parses a numeric field with its `FromStr` implementation
+    pub(crate) fn number<T: std::str::FromStr>(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<T>, GatewayApiError>
+    where
+        T::Err: std::fmt::Display,
+    {
+        self.fields
+            .remove(name)
+            .map(|value| value.trim().parse::<T>())
+            .transpose()
+            .map_err(|e| invalid(name, e))
+    }
+}
+
+fn missing(name: &str, kind: &str) -> GatewayApiError {
+    let message = format!("Missing {name} {kind}");
+    InvalidRequestError::new(name, "missing_required_parameter", message).into()
+}
+
+fn invalid(name: &str, e: impl std::fmt::Display) -> GatewayApiError {
+    InvalidRequestError::new(name, "invalid_value", format!("Invalid {name} field: {e}")).into()
+}
+
+fn multipart_error(e: MultipartError) -> GatewayApiError {
+    InvalidRequestError::new("", "invalid_value", format!("Invalid multipart form: {e}")).into()
+}
@@ -1,18 +1,28 @@
+use crate::error::InvalidRequestError;
 use crate::executor::image_generation::handle_image_generation;
+use crate::executor::image_storage::ImageStore;
 use crate::handler::record_map_err;
 use crate::handler::AvailableModels;
 use crate::handler::CallbackHandlerFn;
-use crate::types::gateway::CreateImageRequest;
+use crate::types::gateway::{CreateImageRequest, FileUpload, ImageSource};
 use crate::types::{credentials::Credentials, gateway::CostCalculator};
 use crate::GatewayApiError;
+use actix_multipart::Multipart;
 use actix_web::HttpMessage;
 use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Arc;
 use tracing::Span;
 use tracing_futures::Instrument;
 
 use super::can_execute_llm_for_request;
 use super::extract_tags;
 use super::find_model_by_full_name;
+use super::form::MultipartForm;
+
+/// Largest file accepted in an image upload
+const MAX_UPLOAD_BYTES: usize = 25 * 1024 * 1024;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
 
 pub async fn create_image(
     request: web::Json<CreateImageRequest>,
@@ -23,7 +33,74 @@ pub async fn create_image(
 ) -> Result<HttpResponse, GatewayApiError> {
     can_execute_llm_for_request(&req).await?;
 
-    let request = request.into_inner();
+    handle_image_request(
+        request.into_inner(),
+        models,
+        req,
+        cost_calculator,
+        callback_handler,
+    )
+    .await
+}
+
+pub async fn edit_image(
+    payload: Multipart,
+    models: web::Data<AvailableModels>,
+    req: HttpRequest,
+    cost_calculator: web::Data<Box<dyn CostCalculator>>,
+    callback_handler: web::Data<CallbackHandlerFn>,
+) -> Result<HttpResponse, GatewayApiError> {
+    can_execute_llm_for_request(&req).await?;
+
+    let mut form = MultipartForm::read(payload, MAX_UPLOAD_BYTES).await?;
+    let image = form.file("image")?;
+    let mask = form.files.remove("mask");
+    validate_images(&image, mask.as_ref())?;
+
+    let request = image_request(form, ImageSource::Edit { image, mask })?;
+    handle_image_request(request, models, req, cost_calculator, callback_handler).await
+}
+
+pub async fn create_image_variation(
+    payload: Multipart,
+    models: web::Data<AvailableModels>,
+    req: HttpRequest,
+    cost_calculator: web::Data<Box<dyn CostCalculator>>,
+    callback_handler: web::Data<CallbackHandlerFn>,
+) -> Result<HttpResponse, GatewayApiError> {
+    can_execute_llm_for_request(&req).await?;
+
+    let mut form = MultipartForm::read(payload, MAX_UPLOAD_BYTES).await?;
+    let image = form.file("image")?;
+    validate_images(&image, None)?;
+
+    let request = image_request(form, ImageSource::Variation { image })?;
+    handle_image_request(request, models, req, cost_calculator, callback_handler).await
+}
+
+/// Serves images stored to answer requests with `response_format` set to `url`
+pub async fn get_stored_image(
+    id: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, GatewayApiError> {
+    let image = match req.app_data::<Arc<dyn ImageStore>>() {
+        Some(store) => store.get(&id).await,
+        None => None,
+    };
+
+    Ok(match image {
+        Some(data) => HttpResponse::Ok().content_type("image/png").body(data),
+        None => HttpResponse::NotFound().finish(),
+    })
+}
+
+async fn handle_image_request(
+    request: CreateImageRequest,
+    models: web::Data<AvailableModels>,
+    req: HttpRequest,
+    cost_calculator: web::Data<Box<dyn CostCalculator>>,
+    callback_handler: web::Data<CallbackHandlerFn>,
+) -> Result<HttpResponse, GatewayApiError> {
     let available_models = models.into_inner();
     let llm_model = find_model_by_full_name(&request.model, &available_models)?;
 
@@ -55,3 +132,166 @@ pub async fn create_image(
 
     Ok(HttpResponse::Ok().json(result))
 }
+
+fn image_request(
+    mut form: MultipartForm,
+    source: ImageSource,
+) -> Result<CreateImageRequest, GatewayApiError> {
+    let prompt = match &source {
+        ImageSource::Edit { .. } => form.text("prompt")?,
+        ImageSource::Variation { .. } => String::new(),
+    };
+
+    Ok(CreateImageRequest {
+        prompt,
+        model: form.text("model")?,
+        n: form.number("n")?,
+        quality: form.parse("quality")?,
+        response_format: form.parse("response_format")?,
+        size: form.parse("size")?,
+        style: None,
+        user: form.fields.remove("user"),
+        source: Some(source),
+    })
+}
+
+#[derive(Debug, PartialEq)]
+struct PngInfo {
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+}
+
+/// Reads the dimensions and transparency of a PNG file, returns None if it is not a PNG
+fn png_info(data: &[u8]) -> Option<PngInfo> {
+    let chunks = data.strip_prefix(PNG_SIGNATURE)?;
+    // IHDR is always the first chunk: width, height, bit depth, color type
+    if chunks.get(4..8)? != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(chunks.get(8..12)?.try_into().ok()?);
+    let height = u32::from_be_bytes(chunks.get(12..16)?.try_into().ok()?);
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    // Grayscale and truecolor with alpha carry an alpha channel, the other color types
+    // are transparent only with a tRNS chunk placed before the image data
+    let mut has_alpha = matches!(*chunks.get(17)?, 4 | 6);
+    let mut offset = 0;
+    while !has_alpha {
+        let Some(length) = chunks.get(offset..offset + 4) else {
+            break;
+        };
+        let length = u32::from_be_bytes(length.try_into().ok()?) as usize;
+        match chunks.get(offset + 4..offset + 8) {
+            Some(b"tRNS") => has_alpha = true,
+            Some(b"IDAT") | Some(b"IEND") | None => break,
+            Some(_) => {}
+        }
+        // Length, type, data and CRC
+        offset += length + 12;
+    }
+
+    Some(PngInfo {
+        width,
+        height,
+        has_alpha,
+    })
+}
+
+fn validate_images(image: &FileUpload, mask: Option<&FileUpload>) -> Result<(), GatewayApiError> {
+    let image_info = png_info(&image.data).ok_or_else(|| {
+        InvalidRequestError::new("image", "invalid_value", "image must be a PNG file")
+    })?;
+
+    if let Some(mask) = mask {
+        let mask_info = png_info(&mask.data).ok_or_else(|| {
+            InvalidRequestError::new("mask", "invalid_value", "mask must be a PNG file")
+        })?;
+        if !mask_info.has_alpha {
+            return Err(InvalidRequestError::new(
+                "mask",
+                "invalid_value",
+                "mask must have an alpha channel marking the areas to edit",
+            )
+            .into());
+        }
+        if (mask_info.width, mask_info.height) != (image_info.width, image_info.height) {
+            return Err(InvalidRequestError::new(
+                "mask",
+                "invalid_value",
+                format!(
+                    "mask must have the same dimensions as the image ({}x{})",
+                    image_info.width, image_info.height
+                ),
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn png(width: u32, height: u32, color_type: u8, transparency: bool) -> FileUpload {
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend(chunk(b"IHDR", &header));
+        if transparency {
+            data.extend(chunk(b"tRNS", &[0, 0]));
+        }
+        data.extend(chunk(b"IDAT", &[0; 8]));
+        data.extend(chunk(b"IEND", &[]));
+
+        FileUpload {
+            file_name: "image.png".to_string(),
+            data: data.into(),
+        }
+    }
+
+    #[test]
+    fn test_png_info() {
+        assert_eq!(
+            png_info(&png(512, 256, 6, false).data),
+            Some(PngInfo {
+                width: 512,
+                height: 256,
+                has_alpha: true,
+            })
+        );
+        assert!(!png_info(&png(512, 512, 2, false).data).unwrap().has_alpha);
+        assert!(png_info(&png(512, 512, 3, true).data).unwrap().has_alpha);
+        assert_eq!(png_info(b"\xff\xd8\xff\xe0 not a png"), None);
+        assert_eq!(png_info(&PNG_SIGNATURE[..4]), None);
+    }
+
+    #[test]
+    fn test_validate_images() {
+        let image = png(512, 512, 2, false);
+        assert!(validate_images(&image, None).is_ok());
+        assert!(validate_images(&image, Some(&png(512, 512, 6, false))).is_ok());
+        assert!(validate_images(&image, Some(&png(512, 512, 2, false))).is_err());
+        assert!(validate_images(&image, Some(&png(256, 256, 6, false))).is_err());
+
+        let jpeg = FileUpload {
+            file_name: "image.jpg".to_string(),
+            data: bytes::Bytes::from_static(b"\xff\xd8\xff\xe0"),
+        };
+        assert!(validate_images(&jpeg, None).is_err());
+    }
+}
@@ -0,0 +1,444 @@
+use crate::model::token_estimate::TokenizerFamily;
+use crate::types::credentials::Credentials;
+use actix_web::dev::forward_ready;
+use actix_web::http::header::{CONTENT_LENGTH, RETRY_AFTER};
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage, HttpResponse,
+};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct KeyRateLimitConfig {
+    /// Limits of keys without an entry in `keys`
+    #[serde(default)]
+    pub default: Option<KeyLimits>,
+    /// Limits by API key
+    #[serde(default)]
+    pub keys: HashMap<String, KeyLimits>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct KeyLimits {
+    pub requests_per_minute: Option<u64>,
+    /// Counted on the reported usage, a request is admitted against an estimate from its size
+    pub tokens_per_minute: Option<u64>,
+}
+
+/// Token bucket holding up to `capacity`, refilled continuously
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketRate {
+    pub capacity: f64,
+    pub refill_per_sec: f64,
+}
+
+impl BucketRate {
+    pub fn per_minute(limit: u64) -> Self {
+        Self {
+            capacity: limit as f64,
+            refill_per_sec: limit as f64 / 60.0,
+        }
+    }
+}
+
+/// Storage backend of the rate limit buckets
+#[async_trait]
+pub trait BucketStore: Send + Sync {
+    /// Takes `amount` from the bucket, or returns the wait until the bucket holds it
+    async fn take(&self, key: &str, rate: BucketRate, amount: f64) -> Result<(), Duration>;
+
+    /// Takes `amount` even when the bucket goes below zero, a negative amount gives back
+    async fn charge(&self, key: &str, rate: BucketRate, amount: f64);
+}
+
+struct Bucket {
+    level: f64,
+    updated: Instant,
+}
+
+/// Default backend keeping the buckets in process memory
+#[derive(Default)]
+pub struct InMemoryBucketStore {
+    buckets: DashMap<String, Bucket>,
+}
+
+impl InMemoryBucketStore {
+    fn take_at(
+        &self,
+        key: &str,
+        rate: BucketRate,
+        amount: f64,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut bucket = self.refilled(key, rate, now);
+        // A request larger than the bucket is admitted once the bucket is full
+        let needed = amount.min(rate.capacity);
+        if bucket.level < needed {
+            let wait = (needed - bucket.level) / rate.refill_per_sec.max(f64::MIN_POSITIVE);
+            return Err(Duration::from_secs_f64(wait.min(u32::MAX as f64)));
+        }
+        bucket.level -= amount;
+        Ok(())
+    }
+
+    fn charge_at(&self, key: &str, rate: BucketRate, amount: f64, now: Instant) {
+        let mut bucket = self.refilled(key, rate, now);
+        bucket.level = (bucket.level - amount).min(rate.capacity);
+    }
+
+    fn refilled(
+        &self,
+        key: &str,
+        rate: BucketRate,
+        now: Instant,
+    ) -> dashmap::mapref::one::RefMut<'_, String, Bucket> {
+        let mut bucket = self.buckets.entry(key.to_string()).or_insert(Bucket {
+            level: rate.capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.level = (bucket.level + elapsed * rate.refill_per_sec).min(rate.capacity);
+        bucket.updated = now;
+        bucket
+    }
+}
+
+#[async_trait]
+impl BucketStore for InMemoryBucketStore {
+    async fn take(&self, key: &str, rate: BucketRate, amount: f64) -> Result<(), Duration> {
+        self.take_at(key, rate, amount, Instant::now())
+    }
+
+    async fn charge(&self, key: &str, rate: BucketRate, amount: f64) {
+        self.charge_at(key, rate, amount, Instant::now())
+    }
+}
+
+pub struct KeyRateLimiter {
+    config: KeyRateLimitConfig,
+    store: Arc<dyn BucketStore>,
+}
+
+impl KeyRateLimiter {
+    pub fn new(config: KeyRateLimitConfig, store: Arc<dyn BucketStore>) -> Self {
+        Self { config, store }
+    }
+
+    fn limits(&self, api_key: &str) -> Option<KeyLimits> {
+        self.config
+            .keys
+            .get(api_key)
+            .or(self.config.default.as_ref())
+            .copied()
+    }
+
+    /// Admits the request against the buckets of its API key, returns the wait when a limit
+    /// is hit. Values chosen by the client, like tags, do not select the buckets, otherwise
+    /// a client could get fresh buckets by changing them.
+    pub async fn admit(
+        &self,
+        api_key: &str,
+        estimated_tokens: u64,
+    ) -> Result<Option<TokenReservation>, Duration> {
+        let Some(limits) = self.limits(api_key) else {
+            return Ok(None);
+        };
+
+        let requests = limits
+            .requests_per_minute
+            .map(|limit| (format!("requests:{api_key}"), BucketRate::per_minute(limit)));
+        if let Some((key, rate)) = &requests {
+            self.store.take(key, *rate, 1.0).await?;
+        }
+
+        let Some(limit) = limits.tokens_per_minute else {
+            return Ok(None);
+        };
+        let key = format!("tokens:{api_key}");
+        let rate = BucketRate::per_minute(limit);
+        let estimate = estimated_tokens as f64;
+        if let Err(wait) = self.store.take(&key, rate, estimate).await {
+            // The request was not sent, so it does not count against the request limit
+            if let Some((key, rate)) = &requests {
+                self.store.charge(key, *rate, -1.0).await;
+            }
+            return Err(wait);
+        }
+
+        Ok(Some(TokenReservation {
+            store: self.store.clone(),
+            key,
+            rate,
+            estimate,
+            used: AtomicU64::new(0),
+            recorded: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+        }))
+    }
+}
+
+/// Tokens taken for a request before it ran, corrected once every holder of the
+/// reservation is done with the request. Requests reporting usage are charged their usage,
+/// failed requests without usage get the estimate back and other requests, e.g. embeddings
+/// or cached responses, keep the estimate.
+pub struct TokenReservation {
+    store: Arc<dyn BucketStore>,
+    key: String,
+    rate: BucketRate,
+    estimate: f64,
+    used: AtomicU64,
+    recorded: AtomicBool,
+    failed: AtomicBool,
+}
+
+impl TokenReservation {
+    pub fn record(&self, tokens: u32) {
+        self.used.fetch_add(tokens as u64, Ordering::Relaxed);
+        self.recorded.store(true, Ordering::Relaxed);
+    }
+
+    /// Called with the outcome of the response, by the middleware or a batch item
+    pub fn settle(&self, success: bool) {
+        self.failed.store(!success, Ordering::Relaxed);
+    }
+
+    fn correction(&self) -> f64 {
+        if self.recorded.load(Ordering::Relaxed) {
+            self.used.load(Ordering::Relaxed) as f64 - self.estimate
+        } else if self.failed.load(Ordering::Relaxed) {
+            -self.estimate
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Drop for TokenReservation {
+    fn drop(&mut self) {
+        let correction = self.correction();
+        if correction == 0.0 {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let store = self.store.clone();
+        let key = std::mem::take(&mut self.key);
+        let rate = self.rate;
+        runtime.spawn(async move { store.charge(&key, rate, correction).await });
+    }
+}
+
+/// API key whose buckets limit a request
+pub fn limited_api_key(credentials: Option<&Credentials>) -> String {
+    match credentials {
+        Some(Credentials::ApiKey(credentials)) => credentials.api_key.clone(),
+        Some(Credentials::ApiKeyWithEndpoint { api_key, .. }) => api_key.clone(),
+        _ => "anonymous".to_string(),
+    }
+}
+
+fn api_key(req: &ServiceRequest) -> String {
+    limited_api_key(req.extensions().get::<Credentials>())
+}
+
+/// Prompt tokens estimated from the size of a request body
+pub fn estimate_body_tokens(length: u64) -> u64 {
+    (TokenizerFamily::Other.estimate_bytes(length as usize) as u64).max(1)
+}
+
+fn estimate_tokens(req: &ServiceRequest) -> u64 {
+    let length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or_default();
+    estimate_body_tokens(length)
+}
+
+fn rate_limited_error(wait: Duration) -> Error {
+    let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+    let response = HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, retry_after.to_string()))
+        .body("Rate limit of the API key exceeded");
+    actix_web::error::InternalError::from_response("Rate limit of the API key exceeded", response)
+        .into()
+}
+
+/// Enforces the request and token limits of the API key of a request
+pub struct KeyRateLimitMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for KeyRateLimitMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = KeyRateLimitMiddlewareService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(KeyRateLimitMiddlewareService {
+            service: service.into(),
+        }))
+    }
+}
+
+pub struct KeyRateLimitMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T> + 'static>>;
+
+impl<S, B> Service<ServiceRequest> for KeyRateLimitMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let mut reservation = None;
+            if let Some(limiter) = req.app_data::<Arc<KeyRateLimiter>>().cloned() {
+                let api_key = api_key(&req);
+                reservation = limiter
+                    .admit(&api_key, estimate_tokens(&req))
+                    .await
+                    .map_err(rate_limited_error)?
+                    .map(Arc::new);
+                if let Some(reservation) = &reservation {
+                    req.extensions_mut().insert(reservation.clone());
+                }
+            }
+
+            let response = service.call(req).await;
+            if let Some(reservation) = reservation {
+                let success = matches!(&response, Ok(r) if r.status().is_success());
+                reservation.settle(success);
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(limits: KeyLimits) -> KeyRateLimiter {
+        KeyRateLimiter::new(
+            KeyRateLimitConfig {
+                default: Some(limits),
+                keys: HashMap::from([(
+                    "team-a".to_string(),
+                    KeyLimits {
+                        requests_per_minute: Some(100),
+                        tokens_per_minute: None,
+                    },
+                )]),
+            },
+            Arc::new(InMemoryBucketStore::default()),
+        )
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let store = InMemoryBucketStore::default();
+        let rate = BucketRate::per_minute(60);
+        let start = Instant::now();
+
+        assert!(store.take_at("key", rate, 50.0, start).is_ok());
+        assert_eq!(
+            store.take_at("key", rate, 20.0, start),
+            Err(Duration::from_secs(10))
+        );
+        assert!(store
+            .take_at("key", rate, 20.0, start + Duration::from_secs(10))
+            .is_ok());
+
+        // Usage above the estimate leaves the bucket in debt
+        store.charge_at("key", rate, 30.0, start + Duration::from_secs(10));
+        assert_eq!(
+            store.take_at("key", rate, 1.0, start + Duration::from_secs(10)),
+            Err(Duration::from_secs(31))
+        );
+        assert!(store
+            .take_at("other", rate, 100.0, start + Duration::from_secs(10))
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_limits_by_key() {
+        let limiter = limiter(KeyLimits {
+            requests_per_minute: Some(1),
+            tokens_per_minute: None,
+        });
+
+        assert!(limiter.admit("key", 10).await.is_ok());
+        assert!(limiter.admit("key", 10).await.is_err());
+        assert!(limiter.admit("other-key", 10).await.is_ok());
+        for _ in 0..10 {
+            assert!(limiter.admit("team-a", 10).await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_reservation_reconciles_usage() {
+        let limiter = limiter(KeyLimits {
+            requests_per_minute: Some(10),
+            tokens_per_minute: Some(1000),
+        });
+
+        let reservation = limiter.admit("key", 600).await.unwrap().unwrap();
+        // The request limit is not charged when the token limit rejects the request
+        assert!(limiter.admit("key", 600).await.is_err());
+
+        reservation.record(100);
+        drop(reservation);
+        tokio::task::yield_now().await;
+        assert!(limiter.admit("key", 600).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_reservation_without_usage() {
+        let limits = KeyLimits {
+            requests_per_minute: None,
+            tokens_per_minute: Some(1000),
+        };
+
+        // A successful response without usage, e.g. embeddings, keeps its estimate
+        let succeeded = limiter(limits);
+        let reservation = succeeded.admit("key", 600).await.unwrap().unwrap();
+        reservation.settle(true);
+        drop(reservation);
+        tokio::task::yield_now().await;
+        assert!(succeeded.admit("key", 600).await.is_err());
+
+        // A failed one gives it back
+        let failed = limiter(limits);
+        let reservation = failed.admit("key", 600).await.unwrap().unwrap();
+        reservation.settle(false);
+        drop(reservation);
+        tokio::task::yield_now().await;
+        assert!(failed.admit("key", 600).await.is_ok());
+    }
+}
@@ -0,0 +1,320 @@
+use crate::types::cache::{CacheFlushFilter, CacheRegistry};
+use actix_web::dev::forward_ready;
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MemoryPressureConfig {
+    /// Resident memory in megabytes above which load is shed
+    pub threshold_mb: u64,
+    /// Share of the threshold the resident memory has to drop below to recover
+    #[serde(default = "default_recovery_ratio")]
+    pub recovery_ratio: f64,
+    #[serde(default = "default_check_interval_ms")]
+    pub check_interval_ms: u64,
+    /// Request header carrying the request priority
+    #[serde(default = "default_priority_header")]
+    pub priority_header: String,
+    /// Priorities rejected under pressure, requests without the header are not rejected
+    #[serde(default = "default_shed_priorities")]
+    pub shed_priorities: Vec<String>,
+    /// Flushes the registered caches when the pressure starts
+    #[serde(default = "default_shrink_caches")]
+    pub shrink_caches: bool,
+    /// Capacity of the model event buffer of new requests under pressure
+    #[serde(default = "default_reduced_buffer_size")]
+    pub reduced_buffer_size: usize,
+}
+
+fn default_recovery_ratio() -> f64 {
+    0.8
+}
+
+fn default_check_interval_ms() -> u64 {
+    1000
+}
+
+fn default_priority_header() -> String {
+    "x-priority".to_string()
+}
+
+fn default_shed_priorities() -> Vec<String> {
+    vec!["low".to_string()]
+}
+
+fn default_shrink_caches() -> bool {
+    true
+}
+
+fn default_reduced_buffer_size() -> usize {
+    64
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct MemoryPressureSnapshot {
+    pub under_pressure: bool,
+    pub resident_bytes: u64,
+    pub threshold_bytes: u64,
+    pub shed_requests: u64,
+    pub pressure_episodes: u64,
+}
+
+/// Samples the resident memory of the process and sheds load while it is above the threshold
+pub struct MemoryPressureMonitor {
+    config: MemoryPressureConfig,
+    caches: CacheRegistry,
+    under_pressure: AtomicBool,
+    resident_bytes: AtomicU64,
+    shed_requests: AtomicU64,
+    pressure_episodes: AtomicU64,
+}
+
+impl MemoryPressureMonitor {
+    pub fn new(config: MemoryPressureConfig, caches: CacheRegistry) -> Self {
+        Self {
+            config,
+            caches,
+            under_pressure: AtomicBool::new(false),
+            resident_bytes: AtomicU64::new(0),
+            shed_requests: AtomicU64::new(0),
+            pressure_episodes: AtomicU64::new(0),
+        }
+    }
+
+    /// Spawns the sampling task, memory is only sampled where `/proc/self/status` is available
+    pub fn start(self: &Arc<Self>) {
+        let monitor = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_millis(monitor.config.check_interval_ms));
+            loop {
+                interval.tick().await;
+                let Some(resident_bytes) = resident_memory_bytes() else {
+                    tracing::warn!(
+                        "Resident memory is not available, memory pressure monitor stopped"
+                    );
+                    return;
+                };
+                monitor.update(resident_bytes);
+            }
+        });
+    }
+
+    fn threshold_bytes(&self) -> u64 {
+        self.config.threshold_mb * 1024 * 1024
+    }
+
+    /// Records a memory sample and switches the pressure state with hysteresis
+    fn update(&self, resident_bytes: u64) {
+        self.resident_bytes.store(resident_bytes, Ordering::Relaxed);
+        let threshold = self.threshold_bytes();
+        let under_pressure = self.under_pressure.load(Ordering::Relaxed);
+
+        if !under_pressure && resident_bytes > threshold {
+            self.under_pressure.store(true, Ordering::Relaxed);
+            self.pressure_episodes.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                "Memory pressure: resident memory {resident_bytes} bytes above threshold {threshold} bytes, shedding load"
+            );
+            if self.config.shrink_caches {
+                let flushed = self.caches.flush(None, &CacheFlushFilter::default());
+                tracing::warn!("Memory pressure: flushed caches {flushed:?}");
+            }
+        } else if under_pressure
+            && (resident_bytes as f64) < threshold as f64 * self.config.recovery_ratio
+        {
+            self.under_pressure.store(false, Ordering::Relaxed);
+            tracing::info!(
+                "Memory pressure recovered: resident memory {resident_bytes} bytes, {} requests shed",
+                self.shed_requests.load(Ordering::Relaxed)
+            );
+        }
+    }
+
+    pub fn is_under_pressure(&self) -> bool {
+        self.under_pressure.load(Ordering::Relaxed)
+    }
+
+    /// Whether a request with the priority is rejected at the moment
+    pub fn should_shed(&self, priority: Option<&str>) -> bool {
+        self.is_under_pressure()
+            && priority.is_some_and(|p| {
+                self.config
+                    .shed_priorities
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(p.trim()))
+            })
+    }
+
+    /// Event buffer capacity for a new request
+    pub fn buffer_size(&self, default: usize) -> usize {
+        if self.is_under_pressure() {
+            default.min(self.config.reduced_buffer_size).max(1)
+        } else {
+            default
+        }
+    }
+
+    pub fn snapshot(&self) -> MemoryPressureSnapshot {
+        MemoryPressureSnapshot {
+            under_pressure: self.is_under_pressure(),
+            resident_bytes: self.resident_bytes.load(Ordering::Relaxed),
+            threshold_bytes: self.threshold_bytes(),
+            shed_requests: self.shed_requests.load(Ordering::Relaxed),
+            pressure_episodes: self.pressure_episodes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn resident_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_vm_rss(&status)
+}
+
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
+
+/// Rejects requests with a shed priority with 503 while the gateway is under memory pressure
+pub struct MemoryPressureMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for MemoryPressureMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = MemoryPressureMiddlewareService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MemoryPressureMiddlewareService {
+            service: service.into(),
+        }))
+    }
+}
+
+pub struct MemoryPressureMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T> + 'static>>;
+
+impl<S, B> Service<ServiceRequest> for MemoryPressureMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            if let Some(monitor) = req.app_data::<Arc<MemoryPressureMonitor>>() {
+                let priority = req
+                    .headers()
+                    .get(monitor.config.priority_header.as_str())
+                    .and_then(|v| v.to_str().ok());
+                if monitor.should_shed(priority) {
+                    let shed = monitor.shed_requests.fetch_add(1, Ordering::Relaxed) + 1;
+                    tracing::warn!(
+                        "Memory pressure: rejected {} request to {}, {shed} requests shed",
+                        priority.unwrap_or_default(),
+                        req.path()
+                    );
+                    return Err(actix_web::error::ErrorServiceUnavailable(
+                        "Gateway is under memory pressure, retry later",
+                    ));
+                }
+            }
+
+            service.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::cache::FlushableCache;
+
+    struct CountingCache(AtomicU64);
+
+    impl FlushableCache for CountingCache {
+        fn kind(&self) -> &str {
+            "response"
+        }
+
+        fn flush(&self, _filter: &CacheFlushFilter) -> usize {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            0
+        }
+    }
+
+    fn monitor(cache: Arc<CountingCache>) -> MemoryPressureMonitor {
+        MemoryPressureMonitor::new(
+            MemoryPressureConfig {
+                threshold_mb: 100,
+                recovery_ratio: default_recovery_ratio(),
+                check_interval_ms: default_check_interval_ms(),
+                priority_header: default_priority_header(),
+                shed_priorities: default_shed_priorities(),
+                shrink_caches: true,
+                reduced_buffer_size: 64,
+            },
+            CacheRegistry(vec![cache]),
+        )
+    }
+
+    #[test]
+    fn test_pressure_hysteresis() {
+        let cache = Arc::new(CountingCache(AtomicU64::new(0)));
+        let monitor = monitor(cache.clone());
+        let mb = 1024 * 1024;
+
+        monitor.update(90 * mb);
+        assert!(!monitor.should_shed(Some("low")));
+        assert_eq!(monitor.buffer_size(1000), 1000);
+
+        monitor.update(120 * mb);
+        monitor.update(110 * mb);
+        assert!(monitor.should_shed(Some("Low")));
+        assert!(!monitor.should_shed(Some("high")));
+        assert!(!monitor.should_shed(None));
+        assert_eq!(monitor.buffer_size(1000), 64);
+        assert_eq!(cache.0.load(Ordering::Relaxed), 1);
+
+        // Still above the recovery level
+        monitor.update(90 * mb);
+        assert!(monitor.is_under_pressure());
+
+        monitor.update(70 * mb);
+        assert!(!monitor.is_under_pressure());
+        assert_eq!(monitor.snapshot().pressure_episodes, 1);
+    }
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\tgateway\nVmPeak:\t  300000 kB\nVmRSS:\t  204800 kB\n";
+        assert_eq!(parse_vm_rss(status), Some(204800 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tgateway\n"), None);
+    }
+}
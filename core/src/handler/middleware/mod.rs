@@ -1 +1,5 @@
+pub mod key_rate_limit;
+pub mod memory_pressure;
+pub mod provider_keys;
 pub mod rate_limit;
+pub mod request_id;
@@ -0,0 +1,60 @@
+use actix_web::dev::forward_ready;
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage,
+};
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use crate::types::credentials::ProviderCredentials;
+
+/// Reads the `X-Provider-Key-*` headers into [`ProviderCredentials`] in request extensions,
+/// so a client can supply keys for several providers in one request
+pub struct ProviderKeysMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for ProviderKeysMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ProviderKeysMiddlewareService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ProviderKeysMiddlewareService {
+            service: service.into(),
+        }))
+    }
+}
+
+pub struct ProviderKeysMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ProviderKeysMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = S::Future;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let credentials = ProviderCredentials::from_headers(
+            req.headers()
+                .iter()
+                .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?))),
+        );
+        if let Some(credentials) = credentials {
+            req.extensions_mut().insert(credentials);
+        }
+
+        self.service.call(req)
+    }
+}
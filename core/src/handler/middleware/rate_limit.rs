@@ -1,8 +1,9 @@
 use crate::usage::{InMemoryStorage, LimitPeriod};
 use actix_web::dev::forward_ready;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
 use actix_web::{
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    Error,
+    Error, HttpResponse,
 };
 use serde::{Deserialize, Serialize};
 use std::future::{ready, Future, Ready};
@@ -19,7 +20,52 @@ pub struct RateLimiting {
     pub hourly: Option<u64>,
     pub daily: Option<u64>,
     pub monthly: Option<u64>,
+    /// Returns the state of the most restrictive limit in `x-ratelimit-*` response headers
+    #[serde(default)]
+    pub expose_headers: bool,
 }
+
+/// Usage of a single limit period after counting the current request
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitStatus {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_seconds: Option<i64>,
+}
+
+impl LimitStatus {
+    fn new(period: &LimitPeriod, limit: u64, current_calls: f64) -> Self {
+        Self {
+            limit,
+            remaining: (limit as f64 - current_calls).max(0.0) as u64,
+            reset_seconds: period.get_seconds_until_refresh(),
+        }
+    }
+
+    /// Picks the limit closest to exhaustion, the earliest reset wins a tie
+    fn most_restrictive(statuses: Vec<LimitStatus>) -> Option<LimitStatus> {
+        statuses
+            .into_iter()
+            .min_by_key(|s| (s.remaining, s.reset_seconds.unwrap_or(i64::MAX)))
+    }
+
+    fn insert_headers(&self, headers: &mut HeaderMap) {
+        let mut values = vec![
+            ("x-ratelimit-limit-requests", self.limit.to_string()),
+            ("x-ratelimit-remaining-requests", self.remaining.to_string()),
+        ];
+        if let Some(reset) = self.reset_seconds {
+            values.push(("x-ratelimit-reset-requests", format!("{reset}s")));
+        }
+
+        for (name, value) in values {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        }
+    }
+}
+
 pub struct RateLimitMiddleware;
 
 impl<S, B> Transform<S, ServiceRequest> for RateLimitMiddleware
@@ -62,24 +108,43 @@ where
 
         Box::pin(async move {
             let rate_limit_config = req.app_data::<Option<RateLimiting>>().cloned();
+            let mut exposed = None;
             if let Some(Some(rate_limit)) = rate_limit_config {
                 let storage = req
                     .app_data::<Arc<Mutex<InMemoryStorage>>>()
                     .unwrap()
                     .clone();
 
-                if let Some(hourly) = rate_limit.hourly {
-                    check_limit(storage.clone(), &LimitPeriod::Hour, hourly).await?;
-                }
-                if let Some(daily) = rate_limit.daily {
-                    check_limit(storage.clone(), &LimitPeriod::Day, daily).await?;
+                let mut statuses = vec![];
+                let periods = [
+                    (LimitPeriod::Hour, rate_limit.hourly),
+                    (LimitPeriod::Day, rate_limit.daily),
+                    (LimitPeriod::Month, rate_limit.monthly),
+                ];
+                for (period, limit) in periods {
+                    let Some(limit) = limit else {
+                        continue;
+                    };
+                    match check_limit(storage.clone(), &period, limit).await {
+                        Ok(status) => statuses.push(status),
+                        Err(status) => {
+                            return Err(limit_exceeded_error(
+                                Some(status).filter(|_| rate_limit.expose_headers),
+                            ));
+                        }
+                    }
                 }
-                if let Some(monthly) = rate_limit.monthly {
-                    check_limit(storage.clone(), &LimitPeriod::Month, monthly).await?;
+
+                if rate_limit.expose_headers {
+                    exposed = LimitStatus::most_restrictive(statuses);
                 }
             }
 
-            service.call(req).await
+            let mut response = service.call(req).await?;
+            if let Some(status) = exposed {
+                status.insert_headers(response.headers_mut());
+            }
+            Ok(response)
         })
     }
 }
@@ -88,18 +153,63 @@ async fn check_limit(
     storage: Arc<Mutex<InMemoryStorage>>,
     period: &LimitPeriod,
     limit: u64,
-) -> Result<(), Error> {
+) -> Result<LimitStatus, LimitStatus> {
     let current_calls = storage
         .lock()
         .await
         .increment_and_get_value(period, "default", API_CALLS, 1.0)
         .await;
 
+    let status = LimitStatus::new(period, limit, current_calls);
     if current_calls > limit as f64 {
-        Err(actix_web::error::ErrorTooManyRequests(
-            "API call limit exceeded",
-        ))
+        Err(status)
     } else {
-        Ok(())
+        Ok(status)
+    }
+}
+
+fn limit_exceeded_error(status: Option<LimitStatus>) -> Error {
+    let Some(status) = status else {
+        return actix_web::error::ErrorTooManyRequests("API call limit exceeded");
+    };
+
+    let mut response = HttpResponse::TooManyRequests().body("API call limit exceeded");
+    status.insert_headers(response.headers_mut());
+    actix_web::error::InternalError::from_response("API call limit exceeded", response).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_most_restrictive_limit() {
+        let hourly = LimitStatus {
+            limit: 100,
+            remaining: 40,
+            reset_seconds: Some(60),
+        };
+        let daily = LimitStatus {
+            limit: 1000,
+            remaining: 5,
+            reset_seconds: Some(3600),
+        };
+        assert_eq!(
+            LimitStatus::most_restrictive(vec![hourly.clone(), daily.clone()]),
+            Some(daily)
+        );
+        assert_eq!(LimitStatus::most_restrictive(vec![]), None);
+
+        let mut headers = HeaderMap::new();
+        hourly.insert_headers(&mut headers);
+        assert_eq!(headers.get("x-ratelimit-remaining-requests").unwrap(), "40");
+        assert_eq!(headers.get("x-ratelimit-reset-requests").unwrap(), "60s");
+    }
+
+    #[test]
+    fn test_remaining_is_not_negative() {
+        let status = LimitStatus::new(&LimitPeriod::Total, 10, 12.0);
+        assert_eq!(status.remaining, 0);
+        assert_eq!(status.reset_seconds, None);
     }
 }
@@ -0,0 +1,187 @@
+use actix_web::body::EitherBody;
+use actix_web::dev::forward_ready;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage,
+};
+use serde::{Deserialize, Serialize};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+pub const DEFAULT_REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RequestIdConfig {
+    /// Response header carrying the request id
+    #[serde(default = "default_header")]
+    pub header: String,
+}
+
+impl Default for RequestIdConfig {
+    fn default() -> Self {
+        Self {
+            header: default_header(),
+        }
+    }
+}
+
+fn default_header() -> String {
+    DEFAULT_REQUEST_ID_HEADER.to_string()
+}
+
+/// Gateway generated id of the current request, available in request extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+}
+
+/// Assigns every request an id and returns it in a response header, errors included
+pub struct RequestIdMiddleware {
+    header: HeaderName,
+}
+
+impl RequestIdMiddleware {
+    pub fn new(config: &RequestIdConfig) -> Self {
+        let header = HeaderName::try_from(config.header.as_str()).unwrap_or_else(|_| {
+            tracing::warn!(
+                "Invalid request id header {}, using {DEFAULT_REQUEST_ID_HEADER}",
+                config.header
+            );
+            HeaderName::from_static("x-request-id")
+        });
+
+        Self { header }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdMiddlewareService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddlewareService {
+            service: service.into(),
+            header: self.header.clone(),
+        }))
+    }
+}
+
+pub struct RequestIdMiddlewareService<S> {
+    service: Rc<S>,
+    header: HeaderName,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T> + 'static>>;
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let header = self.header.clone();
+
+        Box::pin(async move {
+            let request_id = RequestId::generate();
+            req.extensions_mut().insert(request_id.clone());
+            let http_request = req.request().clone();
+
+            let mut res = match service.call(req).await {
+                Ok(res) => res.map_into_left_body(),
+                Err(e) => ServiceResponse::from_err(e, http_request).map_into_right_body(),
+            };
+
+            if let Ok(value) = HeaderValue::from_str(&request_id.0) {
+                res.headers_mut().insert(header, value);
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App, HttpRequest, HttpResponse};
+
+    use super::*;
+
+    async fn echo_request_id(req: HttpRequest) -> Result<HttpResponse, Error> {
+        let request_id = req.extensions().get::<RequestId>().cloned().unwrap();
+        if req.path() == "/fail" {
+            return Err(actix_web::error::ErrorBadGateway("provider failed"));
+        }
+        Ok(HttpResponse::Ok().body(request_id.0))
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_header() {
+        let app = test::init_service(
+            App::new()
+                .wrap(RequestIdMiddleware::new(&RequestIdConfig::default()))
+                .default_service(web::to(echo_request_id)),
+        )
+        .await;
+
+        let mut ids = vec![];
+        for _ in 0..2 {
+            let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+            let header = res
+                .headers()
+                .get("x-request-id")
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
+            // The id in the request extensions is the one returned to the client
+            assert_eq!(test::read_body(res).await, header);
+            ids.push(header);
+        }
+        assert_ne!(ids[0], ids[1]);
+
+        let res =
+            test::call_service(&app, test::TestRequest::get().uri("/fail").to_request()).await;
+        assert_eq!(res.status(), 502);
+        assert!(res.headers().contains_key("x-request-id"));
+    }
+
+    #[actix_web::test]
+    async fn test_configured_header() {
+        for (header, expected) in [
+            ("X-Trace-Id", "x-trace-id"),
+            ("not a header", "x-request-id"),
+        ] {
+            let app = test::init_service(
+                App::new()
+                    .wrap(RequestIdMiddleware::new(&RequestIdConfig {
+                        header: header.to_string(),
+                    }))
+                    .default_service(web::to(echo_request_id)),
+            )
+            .await;
+
+            let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+            assert!(res.headers().contains_key(expected), "{header}");
+        }
+    }
+}
@@ -1,19 +1,28 @@
+pub mod audio;
+pub mod batches;
+pub mod cache;
 pub mod chat;
+pub mod completions;
 pub mod embedding;
+mod form;
 pub mod image;
 pub mod middleware;
 pub mod models;
+pub mod rerank;
 pub mod responses;
 
-use crate::model::types::ModelEvent;
+use crate::executor::redaction::Redactor;
+use crate::model::types::{CustomEvent, ModelEvent, ModelEventType};
 use crate::models::ModelMetadata;
 use crate::types::engine::Model;
 use crate::GatewayApiError;
 use crate::{error::GatewayError, model::error::ModelError};
 use actix_web::HttpRequest;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::Mutex;
+use tracing::Span;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AvailableModels(pub Vec<ModelMetadata>);
@@ -22,13 +31,31 @@ pub fn find_model_by_full_name(
     model_name: &str,
     provided_models: &AvailableModels,
 ) -> Result<ModelMetadata, GatewayApiError> {
+    let llm_model = find_models_by_full_name(model_name, provided_models)
+        .into_iter()
+        .next();
+
+    match llm_model {
+        Some(model) => Ok(model),
+        None => Err(GatewayApiError::ModelError(Box::new(
+            ModelError::ModelNotFound(model_name.to_string()),
+        ))),
+    }
+}
+
+/// All models matching the name, several when deployments share it
+pub fn find_models_by_full_name(
+    model_name: &str,
+    provided_models: &AvailableModels,
+) -> Vec<ModelMetadata> {
     let model_parts = model_name.split('/').collect::<Vec<&str>>();
-    let llm_model = if model_parts.len() == 1 {
+    if model_parts.len() == 1 {
         provided_models
             .0
             .iter()
-            .find(|m| m.model.to_lowercase() == model_name.to_lowercase())
+            .filter(|m| m.model.to_lowercase() == model_name.to_lowercase())
             .cloned()
+            .collect()
     } else if model_parts.len() == 2 {
         let model_name = model_parts.last().expect("2 elements in model parts");
         let provided_by = model_parts.first().expect("2 elements in model parts");
@@ -39,21 +66,15 @@ pub fn find_model_by_full_name(
         provided_models
             .0
             .iter()
-            .find(|m| {
+            .filter(|m| {
                 (m.model.to_lowercase() == model_name.to_lowercase()
                     || m.inference_provider.model_name == model_name.to_lowercase())
                     && m.inference_provider.provider.to_string() == *provided_by
             })
             .cloned()
+            .collect()
     } else {
-        None
-    };
-
-    match llm_model {
-        Some(model) => Ok(model),
-        None => Err(GatewayApiError::ModelError(Box::new(
-            ModelError::ModelNotFound(model_name.to_string()),
-        ))),
+        vec![]
     }
 }
 
@@ -81,6 +102,21 @@ pub fn extract_tags(req: &HttpRequest) -> Result<HashMap<String, String>, Gatewa
     .unwrap_or_default())
 }
 
+/// Header limiting the duration of the whole request, streams included, in milliseconds
+pub const REQUEST_TIMEOUT_HEADER: &str = "x-request-timeout-ms";
+
+pub fn extract_request_timeout(req: &HttpRequest) -> Option<Duration> {
+    let value = req.headers().get(REQUEST_TIMEOUT_HEADER)?;
+    let timeout = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    if timeout.is_none() {
+        tracing::warn!("Ignoring invalid {REQUEST_TIMEOUT_HEADER} header: {value:?}");
+    }
+    timeout.map(Duration::from_millis)
+}
+
 pub fn record_map_err(
     e: impl Into<GatewayApiError> + ToString,
     span: tracing::Span,
@@ -90,14 +126,56 @@ pub fn record_map_err(
 }
 
 #[derive(Clone, Default)]
-pub struct CallbackHandlerFn(pub Option<tokio::sync::broadcast::Sender<ModelEventWithDetails>>);
+pub struct CallbackHandlerFn(
+    pub Option<tokio::sync::broadcast::Sender<ModelEventWithDetails>>,
+    /// Applied to every event before it reaches the callbacks, never to the provider request
+    pub Option<Arc<Redactor>>,
+);
 
 impl CallbackHandlerFn {
-    pub fn on_message(&self, message: ModelEventWithDetails) {
+    pub fn with_redactor(mut self, redactor: Option<Arc<Redactor>>) -> Self {
+        self.1 = redactor;
+        self
+    }
+
+    pub fn on_message(&self, mut message: ModelEventWithDetails) {
         if let Some(sender) = self.0.clone() {
+            if let Some(redactor) = &self.1 {
+                redactor.redact_event(&mut message.event.event);
+            }
             let _ = sender.send(message);
         }
     }
+
+    /// Sends a custom event, traced under `span`
+    pub fn on_custom(
+        &self,
+        span: &Span,
+        name: &str,
+        value: serde_json::Value,
+        request_id: Option<String>,
+    ) {
+        self.on_message(ModelEventWithDetails::new(
+            ModelEvent::new(
+                span,
+                ModelEventType::Custom(CustomEvent::new(name.to_string(), value)),
+            )
+            .with_request_id(request_id),
+            None,
+        ));
+    }
+
+    /// Receiver of all following events, the channel is created if there is none yet
+    pub fn subscribe(&mut self) -> tokio::sync::broadcast::Receiver<ModelEventWithDetails> {
+        match &self.0 {
+            Some(sender) => sender.subscribe(),
+            None => {
+                let (sender, receiver) = tokio::sync::broadcast::channel(100);
+                self.0 = Some(sender);
+                receiver
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
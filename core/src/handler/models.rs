@@ -1,7 +1,13 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
+use crate::executor::audit_log::AuditLog;
+use crate::executor::limiter::ModelConcurrencyLimiter;
+use crate::executor::prometheus::PrometheusMetrics;
+use crate::executor::size_metrics::SizeMetrics;
+use crate::handler::middleware::memory_pressure::MemoryPressureMonitor;
 use crate::{models::ModelCapability, types::gateway::ChatModel};
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use serde::Serialize;
 
 use crate::GatewayApiError;
@@ -53,3 +59,45 @@ pub async fn list_gateway_pricing(
 ) -> Result<HttpResponse, GatewayApiError> {
     Ok(HttpResponse::Ok().json(models.into_inner().0.clone()))
 }
+
+pub async fn list_models_utilization(req: HttpRequest) -> Result<HttpResponse, GatewayApiError> {
+    let utilization = req
+        .app_data::<Arc<ModelConcurrencyLimiter>>()
+        .map(|limiter| limiter.utilization())
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok().json(utilization))
+}
+
+pub async fn list_size_metrics(req: HttpRequest) -> Result<HttpResponse, GatewayApiError> {
+    let metrics = req
+        .app_data::<Arc<SizeMetrics>>()
+        .map(|metrics| metrics.snapshot())
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok().json(metrics))
+}
+
+/// Metrics in the Prometheus text format, not found when metrics are not configured
+pub async fn prometheus_metrics(req: HttpRequest) -> Result<HttpResponse, GatewayApiError> {
+    let Some(metrics) = req.app_data::<Arc<PrometheusMetrics>>() else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let mut metrics = metrics.render();
+    if let Some(audit_log) = req.app_data::<Arc<AuditLog>>() {
+        metrics.push_str(&audit_log.render());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics))
+}
+
+pub async fn list_memory_metrics(req: HttpRequest) -> Result<HttpResponse, GatewayApiError> {
+    let metrics = req
+        .app_data::<Arc<MemoryPressureMonitor>>()
+        .map(|monitor| monitor.snapshot())
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok().json(metrics))
+}
@@ -10,12 +10,13 @@ pub mod llm_gateway;
 pub mod model;
 pub mod models;
 pub mod pricing;
+pub mod rerank_mod;
 pub mod responses;
 pub mod routing;
 pub mod telemetry;
 pub mod types;
 
-use crate::error::GatewayError;
+use crate::error::{GatewayError, InvalidRequestError};
 use crate::types::gateway::CostCalculatorError;
 use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
@@ -47,6 +48,15 @@ pub enum GatewayApiError {
     #[error("{0}")]
     CustomError(String),
 
+    #[error("{0}")]
+    TooManyRequests(String),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
     #[error(transparent)]
     CostCalculatorError(#[from] CostCalculatorError),
 
@@ -56,14 +66,56 @@ pub enum GatewayApiError {
     #[error("Token usage limit exceeded")]
     TokenUsageLimit,
 
+    #[error("{0}")]
+    BudgetExceeded(String),
+
+    #[error("{0}")]
+    UnprocessableEntity(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("Response is not cached")]
+    CacheMiss,
+
+    #[error("{0}")]
+    Timeout(String),
+
+    #[error("{0}")]
+    ServiceUnavailable(String),
+
+    #[error("Response does not match the requested JSON schema: {0}")]
+    SchemaValidation(String),
+
     #[error(transparent)]
     RouteError(#[from] routing::RouterError),
 
     #[error(transparent)]
     RoutedExecutorError(#[from] RoutedExecutorError),
+
+    #[error(transparent)]
+    InvalidRequest(#[from] InvalidRequestError),
 }
 
 impl GatewayApiError {
+    pub fn is_content_filter(&self) -> bool {
+        match self {
+            GatewayApiError::ModelError(e) => e.is_content_filter(),
+            GatewayApiError::GatewayError(GatewayError::ModelError(e)) => e.is_content_filter(),
+            _ => false,
+        }
+    }
+
+    /// Whether the request may succeed on another model
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            GatewayApiError::ModelError(e) => e.is_retryable(),
+            GatewayApiError::GatewayError(GatewayError::ModelError(e)) => e.is_retryable(),
+            GatewayApiError::Timeout(_) | GatewayApiError::ServiceUnavailable(_) => true,
+            _ => false,
+        }
+    }
+
     pub fn is_countable_error(&self) -> bool {
         !matches!(
             self,
@@ -80,6 +132,7 @@ impl actix_web::error::ResponseError for GatewayApiError {
         tracing::error!("API error: {:?}", self);
         match self {
             GatewayApiError::GatewayError(e) => e.error_response(),
+            GatewayApiError::InvalidRequest(e) => e.error_response(),
             e => {
                 let json_error = json!({
                     "error": e.to_string(),
@@ -97,11 +150,23 @@ impl actix_web::error::ResponseError for GatewayApiError {
             GatewayApiError::JsonParseError(_) => StatusCode::BAD_REQUEST,
             GatewayApiError::GatewayError(e) => e.status_code(),
             GatewayApiError::CustomError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            GatewayApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            GatewayApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            GatewayApiError::Forbidden(_) => StatusCode::FORBIDDEN,
             GatewayApiError::CostCalculatorError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             GatewayApiError::ModelError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             GatewayApiError::RouteError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             GatewayApiError::RoutedExecutorError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             GatewayApiError::TokenUsageLimit => StatusCode::BAD_REQUEST,
+            GatewayApiError::BudgetExceeded(_) => StatusCode::PAYMENT_REQUIRED,
+            GatewayApiError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            GatewayApiError::Conflict(_) => StatusCode::CONFLICT,
+            // No upstream call is made, so the miss must not read as an upstream timeout
+            GatewayApiError::CacheMiss => StatusCode::PRECONDITION_FAILED,
+            GatewayApiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            GatewayApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            GatewayApiError::SchemaValidation(_) => StatusCode::BAD_GATEWAY,
+            GatewayApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
         }
     }
 }
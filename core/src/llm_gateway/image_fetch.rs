@@ -0,0 +1,193 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use base64::Engine;
+
+use crate::error::InvalidRequestError;
+use crate::GatewayApiError;
+
+/// Largest linked image fetched to be sent inline
+pub const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fetches an image linked by a client into a base64 data URL. The URL comes from the
+/// request, so only public addresses are contacted, redirects are not followed and the
+/// body is read up to `MAX_IMAGE_BYTES`.
+pub async fn fetch_image(url: &str) -> Result<String, GatewayApiError> {
+    let error = |e: String| {
+        let message = format!("Failed to fetch image {url}: {e}");
+        GatewayApiError::from(InvalidRequestError::new(
+            "image_url",
+            "invalid_value",
+            message,
+        ))
+    };
+
+    let parsed = url::Url::parse(url).map_err(|e| error(e.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(error(format!("unsupported scheme {}", parsed.scheme())));
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| error("missing host".to_string()))?;
+    let port = parsed.port_or_known_default().unwrap_or(443);
+
+    // The client connects to the checked address, a second lookup could return another one
+    let address = public_address(host, port).await.map_err(error)?;
+    let client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(FETCH_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve(host.trim_start_matches('[').trim_end_matches(']'), address)
+        .build()
+        .map_err(|e| error(e.to_string()))?;
+
+    let mut response = client
+        .get(parsed)
+        .send()
+        .await
+        .map_err(|e| error(e.to_string()))?;
+    if response.status().is_redirection() {
+        return Err(error("redirects are not followed".to_string()));
+    }
+    response = response
+        .error_for_status()
+        .map_err(|e| error(e.to_string()))?;
+
+    let media_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_string())
+        .unwrap_or_default();
+    if !media_type.starts_with("image/") {
+        return Err(error(format!("unsupported content type {media_type:?}")));
+    }
+    if response
+        .content_length()
+        .is_some_and(|l| l > MAX_IMAGE_BYTES as u64)
+    {
+        return Err(error(format!("larger than {MAX_IMAGE_BYTES} bytes")));
+    }
+
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| error(e.to_string()))? {
+        if data.len() + chunk.len() > MAX_IMAGE_BYTES {
+            return Err(error(format!("larger than {MAX_IMAGE_BYTES} bytes")));
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(format!(
+        "data:{media_type};base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(data)
+    ))
+}
+
+/// First address of the host, rejected when any address of the host is not public
+async fn public_address(host: &str, port: u16) -> Result<SocketAddr, String> {
+    let addresses: Vec<SocketAddr> = match host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| e.to_string())?
+            .collect(),
+    };
+
+    if let Some(address) = addresses.iter().find(|a| !is_public(a.ip())) {
+        return Err(format!("address {} is not public", address.ip()));
+    }
+    addresses
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("no address found for {host}"))
+}
+
+/// Excludes loopback, private, link-local (cloud metadata endpoints), shared, reserved and
+/// documentation ranges
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Shared address space of carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && ip.octets()[2] == 0)
+        // Benchmarking
+        || (a == 198 && (b == 18 || b == 19))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local
+        || (first & 0xfe00) == 0xfc00
+        // Link-local
+        || (first & 0xffc0) == 0xfe80
+        // Documentation
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_public_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip} is not public");
+        }
+        for ip in ["93.184.216.34", "8.8.8.8", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip} is public");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metadata_endpoint_is_not_fetched() {
+        let error = fetch_image("http://169.254.169.254/latest/meta-data/")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, GatewayApiError::InvalidRequest(_)));
+        assert!(error.to_string().contains("not public"));
+
+        let error = fetch_image("file:///etc/passwd").await.unwrap_err();
+        assert!(error.to_string().contains("unsupported scheme"));
+    }
+}
@@ -1,15 +1,64 @@
 use std::collections::HashSet;
 
+use futures::future::try_join_all;
+
+use crate::error::InvalidRequestError;
+use crate::llm_gateway::image_fetch::fetch_image;
+use crate::model::error::ModelError;
+use crate::models::{ModelIOFormats, ModelMetadata};
 use crate::types::{
     gateway::{ChatCompletionContent, ChatCompletionMessage, ContentType},
     message::{MessageType, PromptMessage},
+    provider::InferenceModelProvider,
     threads::{
         AudioDetail, AudioFormat, Message, MessageContentPart, MessageContentPartOptions,
         MessageContentType,
     },
 };
 
-use crate::GatewayError;
+use crate::{GatewayApiError, GatewayError};
+
+/// Media type of base64 images sent without a data URL header
+const DEFAULT_IMAGE_MEDIA_TYPE: &str = "image/png";
+
+/// Image of a content part, linked or inline
+#[derive(Debug, PartialEq)]
+pub enum ImageSource<'a> {
+    Url(&'a str),
+    Base64 { media_type: &'a str, data: &'a str },
+}
+
+impl<'a> ImageSource<'a> {
+    /// Reads an `image_url` value: an http(s) link, a `data:` URL or bare base64 data
+    pub fn parse(value: &'a str) -> Self {
+        if let Some((header, data)) = value.strip_prefix("data:").and_then(|v| v.split_once(',')) {
+            let media_type = header
+                .split(';')
+                .next()
+                .filter(|m| !m.is_empty())
+                .unwrap_or(DEFAULT_IMAGE_MEDIA_TYPE);
+            return ImageSource::Base64 { media_type, data };
+        }
+        if value.starts_with("http://") || value.starts_with("https://") {
+            return ImageSource::Url(value);
+        }
+        ImageSource::Base64 {
+            media_type: DEFAULT_IMAGE_MEDIA_TYPE,
+            data: value,
+        }
+    }
+}
+
+/// Media type and base64 data of an image part, linked images are inlined by
+/// [`MessageMapper::inline_images`] before the provider request is built
+pub fn inline_image(value: &str) -> Result<(&str, &str), ModelError> {
+    match ImageSource::parse(value) {
+        ImageSource::Base64 { media_type, data } => Ok((media_type, data)),
+        ImageSource::Url(url) => Err(ModelError::CustomError(format!(
+            "Linked image {url} was not inlined before the provider request"
+        ))),
+    }
+}
 
 pub struct MessageMapper {}
 
@@ -176,6 +225,191 @@ impl MessageMapper {
         })
     }
 
+    pub fn has_image_content(messages: &[ChatCompletionMessage]) -> bool {
+        messages.iter().any(|m| match &m.content {
+            Some(ChatCompletionContent::Content(content)) => {
+                content.iter().any(|c| c.r#type == ContentType::ImageUrl)
+            }
+            _ => false,
+        })
+    }
+
+    /// Rejects image input for models not listing it, models without listed formats are
+    /// not checked
+    pub fn check_image_support(
+        messages: &[ChatCompletionMessage],
+        model: &ModelMetadata,
+    ) -> Result<(), GatewayApiError> {
+        let accepts_images = model.input_formats.is_empty()
+            || model
+                .input_formats
+                .iter()
+                .any(|f| matches!(f, ModelIOFormats::Image));
+        if !accepts_images && Self::has_image_content(messages) {
+            return Err(InvalidRequestError::new(
+                "messages",
+                "unsupported_value",
+                format!("Model {} does not accept image input", model.model),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Fetches linked images into base64 data URLs for providers that only take inline images
+    pub async fn inline_images(
+        messages: Vec<Message>,
+        provider: &InferenceModelProvider,
+    ) -> Result<Vec<Message>, GatewayApiError> {
+        if matches!(
+            provider,
+            InferenceModelProvider::OpenAI | InferenceModelProvider::Proxy(_)
+        ) {
+            return Ok(messages);
+        }
+
+        try_join_all(messages.into_iter().map(|mut message| async move {
+            for part in &mut message.content_array {
+                if part.r#type != MessageContentType::ImageUrl {
+                    continue;
+                }
+                if let ImageSource::Url(url) = ImageSource::parse(&part.value) {
+                    part.value = fetch_image(url).await?;
+                }
+            }
+            Ok::<_, GatewayApiError>(message)
+        }))
+        .await
+    }
+
+    pub fn has_linked_images(messages: &[ChatCompletionMessage]) -> bool {
+        messages.iter().any(|m| match &m.content {
+            Some(ChatCompletionContent::Content(content)) => content.iter().any(|c| {
+                c.image_url
+                    .as_ref()
+                    .is_some_and(|i| matches!(ImageSource::parse(&i.url), ImageSource::Url(_)))
+            }),
+            _ => false,
+        })
+    }
+
+    /// Fetches linked images of the request into data URLs, so requests tried on several
+    /// models fetch each image once
+    pub async fn inline_request_images(
+        messages: &mut [ChatCompletionMessage],
+    ) -> Result<(), GatewayApiError> {
+        for message in messages {
+            let Some(ChatCompletionContent::Content(content)) = &mut message.content else {
+                continue;
+            };
+            for image in content.iter_mut().filter_map(|c| c.image_url.as_mut()) {
+                if let ImageSource::Url(url) = ImageSource::parse(&image.url) {
+                    image.url = fetch_image(url).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adapts mapped messages to the conversation rules of the target provider
+    pub fn map_for_provider(
+        messages: Vec<Message>,
+        provider: &InferenceModelProvider,
+    ) -> Vec<Message> {
+        match provider {
+            InferenceModelProvider::Anthropic => Self::map_for_anthropic(messages),
+            _ => messages
+                .into_iter()
+                .map(Self::strip_cache_control)
+                .collect(),
+        }
+    }
+
+    /// Drops prompt caching breakpoints for providers without them. A text message turned
+    /// into a single content part only to carry the breakpoint becomes plain text again.
+    fn strip_cache_control(mut message: Message) -> Message {
+        if message.content.is_none() {
+            if let [part] = message.content_array.as_slice() {
+                if part.r#type == MessageContentType::Text && part.cache_control.is_some() {
+                    message.content = Some(part.value.clone());
+                    message.content_array.clear();
+                }
+            }
+        }
+
+        for part in &mut message.content_array {
+            part.cache_control = None;
+        }
+        message
+    }
+
+    /// Anthropic takes a single top level system prompt and rejects consecutive
+    /// user or assistant turns, so system messages are concatenated and moved first
+    /// and adjacent messages of the same role are collapsed
+    fn map_for_anthropic(messages: Vec<Message>) -> Vec<Message> {
+        let (system, conversation): (Vec<_>, Vec<_>) = messages
+            .into_iter()
+            .partition(|m| m.r#type == MessageType::SystemMessage);
+
+        let mut mapped: Vec<Message> = vec![];
+        for message in system.into_iter().chain(conversation) {
+            match mapped.last_mut() {
+                Some(last)
+                    if last.r#type == message.r#type
+                        && message.r#type != MessageType::ToolResult =>
+                {
+                    Self::merge_message(last, message)
+                }
+                _ => mapped.push(message),
+            }
+        }
+
+        mapped
+    }
+
+    fn merge_message(target: &mut Message, message: Message) {
+        if target.content_array.is_empty() && message.content_array.is_empty() {
+            let content = [target.content.take(), message.content]
+                .into_iter()
+                .flatten()
+                .filter(|c| !c.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            target.content = Some(content);
+        } else {
+            let mut parts = Self::content_parts(target);
+            parts.extend(Self::content_parts(&message));
+            target.content = None;
+            target.content_array = parts;
+        }
+
+        target.tool_calls = match (target.tool_calls.take(), message.tool_calls) {
+            (Some(mut calls), Some(other)) => {
+                calls.extend(other);
+                Some(calls)
+            }
+            (calls, other) => calls.or(other),
+        };
+    }
+
+    fn content_parts(message: &Message) -> Vec<MessageContentPart> {
+        if !message.content_array.is_empty() {
+            return message.content_array.clone();
+        }
+
+        message
+            .content
+            .iter()
+            .filter(|c| !c.is_empty())
+            .map(|c| MessageContentPart {
+                r#type: MessageContentType::Text,
+                value: c.clone(),
+                additional_options: None,
+                cache_control: None,
+            })
+            .collect()
+    }
+
     pub fn map_role_to_message_type(role: &str) -> MessageType {
         match role {
             "system" => MessageType::SystemMessage,
@@ -186,3 +420,246 @@ impl MessageMapper {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DefaultSystemPrompt, SystemPromptPolicy};
+    use crate::types::gateway::{FunctionCall, ToolCall};
+
+    fn message(role: &str, content: &str) -> Message {
+        MessageMapper::map_completions_message_to_langdb_message(
+            &ChatCompletionMessage::new_text(role.to_string(), content.to_string()),
+            "claude-3-5-sonnet",
+            "user",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_map_for_anthropic() {
+        let mut tool_call = message("assistant", "");
+        tool_call.tool_calls = Some(vec![ToolCall {
+            index: None,
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: "{}".to_string(),
+            },
+        }]);
+        let mut tool_result = message("tool", "Sunny");
+        tool_result.tool_call_id = Some("call_1".to_string());
+
+        let messages = vec![
+            message("system", "You are helpful."),
+            message("user", "Hi"),
+            message("system", "Answer briefly."),
+            message("user", "What is the weather?"),
+            tool_call,
+            tool_result,
+            message("user", "Thanks"),
+        ];
+
+        let mapped = MessageMapper::map_for_provider(messages, &InferenceModelProvider::Anthropic);
+        let types: Vec<_> = mapped.iter().map(|m| m.r#type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                MessageType::SystemMessage,
+                MessageType::HumanMessage,
+                MessageType::AIMessage,
+                MessageType::ToolResult,
+                MessageType::HumanMessage,
+            ]
+        );
+        assert_eq!(
+            mapped[0].content.as_deref(),
+            Some("You are helpful.\n\nAnswer briefly.")
+        );
+        assert_eq!(
+            mapped[1].content.as_deref(),
+            Some("Hi\n\nWhat is the weather?")
+        );
+        assert_eq!(mapped[2].tool_calls.as_ref().map(|c| c.len()), Some(1));
+    }
+
+    fn cached_message(role: &str, content: &str) -> Message {
+        let cache_control = serde_json::from_value(serde_json::json!({"type": "ephemeral"}));
+        MessageMapper::map_completions_message_to_langdb_message(
+            &ChatCompletionMessage {
+                cache_control: Some(cache_control.unwrap()),
+                ..ChatCompletionMessage::new_text(role.to_string(), content.to_string())
+            },
+            "claude-3-5-sonnet",
+            "user",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cache_control_breakpoints() {
+        let messages = vec![
+            cached_message("system", "Long instructions"),
+            message("user", "Hi"),
+        ];
+
+        let mapped =
+            MessageMapper::map_for_provider(messages.clone(), &InferenceModelProvider::Anthropic);
+        assert_eq!(mapped[0].content, None);
+        assert!(mapped[0].content_array[0].cache_control.is_some());
+
+        let mapped = MessageMapper::map_for_provider(messages, &InferenceModelProvider::OpenAI);
+        assert_eq!(mapped[0].content.as_deref(), Some("Long instructions"));
+        assert!(mapped[0].content_array.is_empty());
+    }
+
+    fn vision_message(urls: &[&str]) -> ChatCompletionMessage {
+        let mut content = vec![crate::types::gateway::Content {
+            r#type: ContentType::Text,
+            text: Some("What is in these images?".to_string()),
+            ..Default::default()
+        }];
+        content.extend(urls.iter().map(|url| crate::types::gateway::Content {
+            r#type: ContentType::ImageUrl,
+            image_url: Some(crate::types::gateway::ImageUrl {
+                url: url.to_string(),
+            }),
+            ..Default::default()
+        }));
+        ChatCompletionMessage {
+            content: Some(ChatCompletionContent::Content(content)),
+            ..ChatCompletionMessage::new_text("user".to_string(), String::new())
+        }
+    }
+
+    #[test]
+    fn test_image_source() {
+        assert_eq!(
+            ImageSource::parse("https://example.com/cat.jpg"),
+            ImageSource::Url("https://example.com/cat.jpg")
+        );
+        assert_eq!(
+            ImageSource::parse("data:image/jpeg;base64,/9j/4AAQ"),
+            ImageSource::Base64 {
+                media_type: "image/jpeg",
+                data: "/9j/4AAQ"
+            }
+        );
+        assert_eq!(
+            inline_image("iVBORw0KGgo").unwrap(),
+            ("image/png", "iVBORw0KGgo")
+        );
+        assert!(inline_image("https://example.com/cat.jpg").is_err());
+    }
+
+    #[test]
+    fn test_check_image_support() {
+        let messages = vec![vision_message(&["data:image/png;base64,iVBORw0KGgo"])];
+        let mut model = ModelMetadata {
+            model: "gpt-3.5-turbo".to_string(),
+            input_formats: vec![ModelIOFormats::Text],
+            ..Default::default()
+        };
+        assert!(matches!(
+            MessageMapper::check_image_support(&messages, &model),
+            Err(GatewayApiError::InvalidRequest(_))
+        ));
+
+        model.input_formats.push(ModelIOFormats::Image);
+        assert!(MessageMapper::check_image_support(&messages, &model).is_ok());
+
+        model.input_formats = vec![ModelIOFormats::Text];
+        let text = vec![ChatCompletionMessage::new_text(
+            "user".to_string(),
+            "Hi".to_string(),
+        )];
+        assert!(MessageMapper::check_image_support(&text, &model).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_inline_images_keeps_inline_data() {
+        let message = MessageMapper::map_completions_message_to_langdb_message(
+            &vision_message(&[
+                "data:image/webp;base64,UklGR",
+                "https://example.com/cat.jpg",
+            ]),
+            "claude-3-5-sonnet",
+            "user",
+        )
+        .unwrap();
+        assert_eq!(message.content_array.len(), 3);
+
+        // Providers taking links are sent the parts unchanged
+        let mapped =
+            MessageMapper::inline_images(vec![message.clone()], &InferenceModelProvider::OpenAI)
+                .await
+                .unwrap();
+        assert_eq!(
+            mapped[0].content_array[2].value,
+            "https://example.com/cat.jpg"
+        );
+
+        let mut message = message;
+        message.content_array.pop();
+        let mapped =
+            MessageMapper::inline_images(vec![message], &InferenceModelProvider::Anthropic)
+                .await
+                .unwrap();
+        assert_eq!(
+            mapped[0].content_array[1].value,
+            "data:image/webp;base64,UklGR"
+        );
+    }
+
+    #[test]
+    fn test_map_for_other_providers_is_unchanged() {
+        let messages = vec![message("user", "Hi"), message("user", "Again")];
+        let mapped = MessageMapper::map_for_provider(messages, &InferenceModelProvider::OpenAI);
+        assert_eq!(mapped.len(), 2);
+    }
+
+    #[test]
+    fn test_default_system_prompt() {
+        let map = |system_prompt: DefaultSystemPrompt| {
+            let mut messages = vec![
+                ChatCompletionMessage::new_text("user".to_string(), "Hi".to_string()),
+                ChatCompletionMessage::new_text("system".to_string(), "Be brief.".to_string()),
+            ];
+            system_prompt.apply(&mut messages);
+            let messages = messages
+                .iter()
+                .map(|m| {
+                    MessageMapper::map_completions_message_to_langdb_message(
+                        m,
+                        "claude-3-5-sonnet",
+                        "user",
+                    )
+                    .unwrap()
+                })
+                .collect();
+            MessageMapper::map_for_provider(messages, &InferenceModelProvider::Anthropic)
+        };
+
+        let mapped = map(DefaultSystemPrompt {
+            content: "Follow the safety rules.".to_string(),
+            policy: SystemPromptPolicy::Prepend,
+        });
+        assert_eq!(mapped[0].r#type, MessageType::SystemMessage);
+        assert_eq!(
+            mapped[0].content.as_deref(),
+            Some("Follow the safety rules.\n\nBe brief.")
+        );
+        assert_eq!(mapped[1].content.as_deref(), Some("Hi"));
+
+        let mapped = map(DefaultSystemPrompt {
+            content: "Follow the safety rules.".to_string(),
+            policy: SystemPromptPolicy::Override,
+        });
+        assert_eq!(mapped.len(), 2);
+        assert_eq!(
+            mapped[0].content.as_deref(),
+            Some("Follow the safety rules.")
+        );
+    }
+}
@@ -1,2 +1,3 @@
+pub mod image_fetch;
 pub mod message_mapper;
 pub mod provider;
@@ -5,7 +5,7 @@ use clust::messages::StopSequence;
 use crate::{
     models::ModelMetadata,
     types::{
-        credentials::{ApiKeyCredentials, Credentials},
+        credentials::{ApiKeyCredentials, Credentials, ProviderCredentials},
         engine::{
             AnthropicModelParams, BedrockModelParams, ClaudeModel, CompletionEngineParams,
             ExecutionOptions, GeminiModelParams, ImageGenerationEngineParams, OpenAiModelParams,
@@ -19,30 +19,79 @@ use crate::error::GatewayError;
 
 pub struct Provider {}
 
+/// Request field that carries the output token limit for OpenAI compatible models
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenLimitField {
+    MaxTokens,
+    MaxCompletionTokens,
+}
+
+/// OpenAI model families that reject `max_tokens`
+const MAX_COMPLETION_TOKENS_PREFIXES: [&str; 4] = ["o1", "o3", "o4", "gpt-5"];
+
+/// Resolves the token limit field from the parameters advertised in model metadata,
+/// falling back to the model family for OpenAI models.
+fn token_limit_field(model: &ModelMetadata) -> TokenLimitField {
+    if let Some(parameters) = model.parameters.as_ref().and_then(|p| p.as_object()) {
+        if parameters.contains_key("max_completion_tokens") {
+            return TokenLimitField::MaxCompletionTokens;
+        }
+        if parameters.contains_key("max_tokens") {
+            return TokenLimitField::MaxTokens;
+        }
+    }
+
+    let model_name = &model.inference_provider.model_name;
+    if model.inference_provider.provider == InferenceModelProvider::OpenAI
+        && MAX_COMPLETION_TOKENS_PREFIXES
+            .iter()
+            .any(|prefix| model_name.starts_with(prefix))
+    {
+        TokenLimitField::MaxCompletionTokens
+    } else {
+        TokenLimitField::MaxTokens
+    }
+}
+
 impl Provider {
     pub fn get_completion_engine_for_model(
         model: &ModelMetadata,
         request: &ChatCompletionRequest,
         credentials: Option<Credentials>,
+        provider_credentials: Option<&ProviderCredentials>,
         provider_specific: Option<&ProviderSpecificRequest>,
         execution_options: Option<ExecutionOptions>,
     ) -> Result<CompletionEngineParams, GatewayError> {
+        // Keys sent by the client for the provider of the model take precedence
+        let credentials = match provider_credentials {
+            Some(provider_credentials) => provider_credentials
+                .select(&model.inference_provider.provider.to_string(), credentials),
+            None => credentials,
+        };
+
         match model.inference_provider.provider {
             InferenceModelProvider::OpenAI | InferenceModelProvider::Proxy(_) => {
+                let (max_tokens, max_completion_tokens) = match token_limit_field(model) {
+                    TokenLimitField::MaxTokens => (request.output_tokens_limit(), None),
+                    TokenLimitField::MaxCompletionTokens => (None, request.output_tokens_limit()),
+                };
                 let params = OpenAiModelParams {
                     model: Some(model.inference_provider.model_name.clone()),
                     frequency_penalty: request.frequency_penalty,
                     logit_bias: request.logit_bias.clone(),
-                    logprobs: None,
-                    top_logprobs: None,
-                    max_tokens: request.max_tokens,
+                    logprobs: request.logprobs,
+                    top_logprobs: request.top_logprobs,
+                    max_tokens,
+                    max_completion_tokens,
                     presence_penalty: request.presence_penalty,
                     seed: request.seed,
+                    n: request.n.and_then(|n| u8::try_from(n).ok()),
                     stop: request.stop.clone(),
                     temperature: request.temperature,
                     top_p: request.top_p,
                     user: request.user.clone(),
                     response_format: request.response_format.clone(),
+                    tool_choice: request.tool_choice.clone(),
                 };
                 let mut custom_endpoint = None;
                 let api_key_credentials = credentials.and_then(|cred| match cred {
@@ -87,10 +136,11 @@ impl Provider {
                     execution_options: execution_options.unwrap_or_default(),
                     params: BedrockModelParams {
                         model_id: Some(model.inference_provider.model_name.clone()),
-                        max_tokens: request.max_tokens.map(|x| x as i32),
+                        max_tokens: request.output_tokens_limit().map(|x| x as i32),
                         temperature: request.temperature,
                         top_p: request.top_p,
                         stop_sequences: request.stop.clone(),
+                        tool_choice: request.tool_choice.clone(),
                         additional_parameters: HashMap::new(),
                     },
                     provider,
@@ -108,7 +158,7 @@ impl Provider {
                     execution_options: execution_options.unwrap_or_default(),
                     params: AnthropicModelParams {
                         model: Some(model.clone()),
-                        max_tokens: match request.max_tokens {
+                        max_tokens: match request.output_tokens_limit() {
                             Some(x) => Some(clust::messages::MaxTokens::new(x, model.model)?),
                             None => None,
                         },
@@ -135,6 +185,7 @@ impl Provider {
                                     budget_tokens: thinking.budget_tokens,
                                 })
                         }),
+                        tool_choice: request.tool_choice.clone(),
                     },
                 })
             }
@@ -148,7 +199,7 @@ impl Provider {
                     execution_options: execution_options.unwrap_or_default(),
                     params: GeminiModelParams {
                         model: Some(model.inference_provider.model_name.clone()),
-                        max_output_tokens: request.max_tokens.map(|x| x as i32),
+                        max_output_tokens: request.output_tokens_limit().map(|x| x as i32),
                         temperature: request.temperature,
                         top_p: request.top_p,
                         stop_sequences: request.stop.clone(),
@@ -164,6 +215,8 @@ impl Provider {
                         logprobs: None,
                         top_k: None,
                         response_format: request.response_format.clone(),
+                        safety_settings: request.safety_settings.clone(),
+                        tool_choice: request.tool_choice.clone(),
                     },
                 })
             }
@@ -227,3 +280,168 @@ fn get_anthropic_model(model_name: &str) -> &str {
         n => n,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::InferenceProvider;
+
+    fn model(provider: InferenceModelProvider, model_name: &str) -> ModelMetadata {
+        ModelMetadata {
+            model: model_name.to_string(),
+            inference_provider: InferenceProvider {
+                provider,
+                model_name: model_name.to_string(),
+                endpoint: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn openai_params(model: &ModelMetadata, request: &ChatCompletionRequest) -> OpenAiModelParams {
+        match Provider::get_completion_engine_for_model(model, request, None, None, None, None)
+            .unwrap()
+        {
+            CompletionEngineParams::OpenAi { params, .. }
+            | CompletionEngineParams::Proxy { params, .. } => params,
+            _ => panic!("expected openai compatible params"),
+        }
+    }
+
+    fn request(
+        max_tokens: Option<u32>,
+        max_completion_tokens: Option<u32>,
+    ) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            max_tokens,
+            max_completion_tokens,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reasoning_models_use_max_completion_tokens() {
+        for name in ["o1-mini", "o3", "o4-mini", "gpt-5"] {
+            let params = openai_params(
+                &model(InferenceModelProvider::OpenAI, name),
+                &request(Some(100), None),
+            );
+            assert_eq!(params.max_tokens, None, "{name}");
+            assert_eq!(params.max_completion_tokens, Some(100), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_legacy_openai_models_use_max_tokens() {
+        for name in ["gpt-4o", "gpt-4o-mini", "gpt-3.5-turbo"] {
+            let params = openai_params(
+                &model(InferenceModelProvider::OpenAI, name),
+                &request(None, Some(100)),
+            );
+            assert_eq!(params.max_tokens, Some(100), "{name}");
+            assert_eq!(params.max_completion_tokens, None, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_proxy_models_use_max_tokens() {
+        let params = openai_params(
+            &model(
+                InferenceModelProvider::Proxy("deepseek".to_string()),
+                "o1-like",
+            ),
+            &request(None, Some(100)),
+        );
+        assert_eq!(params.max_tokens, Some(100));
+        assert_eq!(params.max_completion_tokens, None);
+    }
+
+    #[test]
+    fn test_choices_forwarded() {
+        let request = ChatCompletionRequest {
+            n: Some(3),
+            ..request(None, None)
+        };
+        let params = openai_params(&model(InferenceModelProvider::OpenAI, "gpt-4o"), &request);
+        assert_eq!(params.n, Some(3));
+    }
+
+    #[test]
+    fn test_metadata_parameters_take_precedence() {
+        let mut metadata = model(
+            InferenceModelProvider::Proxy("openrouter".to_string()),
+            "openai/o3-mini",
+        );
+        metadata.parameters = Some(serde_json::json!({ "max_completion_tokens": {} }));
+        let params = openai_params(&metadata, &request(Some(100), None));
+        assert_eq!(params.max_tokens, None);
+        assert_eq!(params.max_completion_tokens, Some(100));
+    }
+
+    #[test]
+    fn test_gemini_accepts_max_completion_tokens() {
+        let request = request(None, Some(100));
+        match Provider::get_completion_engine_for_model(
+            &model(InferenceModelProvider::Gemini, "gemini-1.5-pro"),
+            &request,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+        {
+            CompletionEngineParams::Gemini { params, .. } => {
+                assert_eq!(params.max_output_tokens, Some(100))
+            }
+            _ => panic!("expected gemini params"),
+        }
+    }
+
+    #[test]
+    fn test_provider_key_selected_by_model_provider() {
+        let provider_credentials =
+            ProviderCredentials::from_headers([("x-provider-key-openai", "sk-openai")]).unwrap();
+        let request = request(Some(100), None);
+
+        match Provider::get_completion_engine_for_model(
+            &model(InferenceModelProvider::OpenAI, "gpt-4o"),
+            &request,
+            None,
+            Some(&provider_credentials),
+            None,
+            None,
+        )
+        .unwrap()
+        {
+            CompletionEngineParams::OpenAi { credentials, .. } => {
+                assert_eq!(credentials.unwrap().api_key, "sk-openai")
+            }
+            _ => panic!("expected openai params"),
+        }
+
+        // Providers without a header keep the configured key, or the environment key
+        let gemini = model(InferenceModelProvider::Gemini, "gemini-1.5-pro");
+        for configured in [None, Some("gemini-key")] {
+            match Provider::get_completion_engine_for_model(
+                &gemini,
+                &request,
+                configured.map(|api_key| {
+                    Credentials::ApiKey(ApiKeyCredentials {
+                        api_key: api_key.to_string(),
+                    })
+                }),
+                Some(&provider_credentials),
+                None,
+                None,
+            )
+            .unwrap()
+            {
+                CompletionEngineParams::Gemini { credentials, .. } => {
+                    assert_eq!(credentials.map(|c| c.api_key).as_deref(), configured)
+                }
+                _ => panic!("expected gemini params"),
+            }
+        }
+    }
+}
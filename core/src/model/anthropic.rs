@@ -2,20 +2,24 @@ use super::error::{AuthorizationError, ModelError};
 use super::tools::Tool;
 use super::types::{
     LLMContentEvent, LLMFinishEvent, LLMStartEvent, ModelEvent, ModelEventType, ModelFinishReason,
-    ModelToolCall, ToolStartEvent,
+    ModelToolCall, ToolCallDeltaEvent, ToolStartEvent,
 };
 use super::{CredentialsIdent, ModelInstance};
 use crate::error::GatewayError;
 use crate::events::JsonValue;
 use crate::events::SPAN_ANTHROPIC;
 use crate::events::{self, RecordResult};
+use crate::executor::chat_completion::backoff::ProviderRetries;
+use crate::llm_gateway::message_mapper::inline_image;
+use crate::model::async_trait;
 use crate::model::error::AnthropicError;
-use crate::model::handler::handle_tool_call;
+use crate::model::handler::{handle_tool_call, tool_error_content};
 use crate::model::types::LLMFirstToken;
-use crate::model::{async_trait, DEFAULT_MAX_RETRIES};
 use crate::types::credentials::ApiKeyCredentials;
 use crate::types::engine::{AnthropicModelParams, ExecutionOptions, Prompt};
-use crate::types::gateway::{ChatCompletionContent, ChatCompletionMessage, ToolCall};
+use crate::types::gateway::{
+    ChatCompletionContent, ChatCompletionMessage, ToolCall, ToolChoice, ToolChoiceMode,
+};
 use crate::types::gateway::{CompletionModelUsage, PromptTokensDetails};
 use crate::types::message::{MessageType, PromptMessage};
 use crate::types::threads::{InnerMessage, Message};
@@ -82,6 +86,18 @@ fn tool_definition(tool: &dyn Tool) -> clust::messages::ToolDefinition {
     }
 }
 
+/// Anthropic has no `required` mode, `any` forces a call to one of the tools
+fn map_tool_choice(choice: &ToolChoice) -> clust::messages::ToolChoice {
+    match choice {
+        ToolChoice::Mode(ToolChoiceMode::None) => clust::messages::ToolChoice::None,
+        ToolChoice::Mode(ToolChoiceMode::Auto) => clust::messages::ToolChoice::Auto,
+        ToolChoice::Mode(ToolChoiceMode::Required) => clust::messages::ToolChoice::Any,
+        ToolChoice::Function(choice) => clust::messages::ToolChoice::Tool {
+            name: choice.function.name.clone(),
+        },
+    }
+}
+
 #[derive(Clone)]
 pub struct AnthropicModel {
     params: AnthropicModelParams,
@@ -118,28 +134,20 @@ impl AnthropicModel {
         tools: &HashMap<String, Box<dyn Tool>>,
         tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
         tags: HashMap<String, String>,
-    ) -> Vec<ClustMessage> {
-        futures::future::join_all(function_calls.map(|tool_use| {
+    ) -> GatewayResult<Vec<ClustMessage>> {
+        futures::future::try_join_all(function_calls.map(|tool_use| {
             let tags_value = tags.clone();
             async move {
-                let tool_call = Self::map_tool_call(tool_use);
-                let tool_call = tool_call.map_err(|e| GatewayError::CustomError(e.to_string()));
-                let result = match tool_call {
-                    Ok(tool_call) => {
-                        let result =
-                            handle_tool_call(&tool_call, tools, tx, tags_value.clone()).await;
-                        match result {
-                            Ok(content) => ToolResult::success(tool_use.id.clone(), Some(content)),
-                            Err(e) => ToolResult::error(tool_use.id.clone(), Some(e.to_string())),
-                        }
-                    }
+                let tool_call = Self::map_tool_call(tool_use)?;
+                let result = match handle_tool_call(&tool_call, tools, tx, tags_value).await? {
+                    Ok(content) => ToolResult::success(tool_use.id.clone(), Some(content)),
                     Err(e) => {
                         tracing::error!("Error calling tool ({}): {}", tool_use.id, e);
-                        ToolResult::error(tool_use.id.clone(), Some(e.to_string()))
+                        ToolResult::error(tool_use.id.clone(), Some(tool_error_content(&e)))
                     }
                 };
 
-                ClustMessage::user(result)
+                Ok::<_, GatewayError>(ClustMessage::user(result))
             }
         }))
         .await
@@ -189,6 +197,12 @@ impl AnthropicModel {
             builder
         };
 
+        let after_tool_results = messages.last().is_some_and(|m| match &m.content {
+            Content::MultipleBlocks(blocks) => blocks
+                .iter()
+                .any(|b| matches!(b, ContentBlock::ToolResult(_))),
+            Content::SingleText(_) => false,
+        });
         let builder = builder.messages(messages.clone());
 
         let builder = match stream {
@@ -201,7 +215,14 @@ impl AnthropicModel {
                 tools.push(tool_definition(tool.deref()));
             }
 
-            builder.tools(tools)
+            let builder = builder.tools(tools);
+            match &model_params.tool_choice {
+                Some(choice) if after_tool_results => {
+                    builder.tool_choice(map_tool_choice(&choice.after_tool_results()))
+                }
+                Some(choice) => builder.tool_choice(map_tool_choice(choice)),
+                None => builder,
+            }
         } else {
             builder
         };
@@ -223,6 +244,7 @@ impl AnthropicModel {
         let mut tool_call_states: HashMap<u32, ToolUse> = HashMap::new();
         tokio::pin!(stream);
         let mut json_states: HashMap<u32, String> = HashMap::new();
+        let mut tool_indexes: HashMap<u32, usize> = HashMap::new();
         let mut usage = Usage {
             input_tokens: 0,
             output_tokens: 0,
@@ -251,6 +273,7 @@ impl AnthropicModel {
                                 &tracing::Span::current(),
                                 ModelEventType::LlmContent(LLMContentEvent {
                                     content: block.text,
+                                    logprobs: None,
                                 }),
                             )))
                             .await
@@ -261,12 +284,27 @@ impl AnthropicModel {
                                 &tracing::Span::current(),
                                 ModelEventType::LlmContent(LLMContentEvent {
                                     content: format!("thinking: {}", thinking.thinking),
+                                    logprobs: None,
                                 }),
                             )))
                             .await
                             .map_err(|e| GatewayError::CustomError(e.to_string()))?;
                         }
                         clust::messages::ContentBlockStart::ToolUseContentBlock(tool_use_block) => {
+                            // Tool calls are numbered separately from text blocks
+                            let index = tool_indexes.len();
+                            tool_indexes.insert(block.index, index);
+                            let _ = tx
+                                .send(Some(ModelEvent::new(
+                                    &tracing::Span::current(),
+                                    ModelEventType::ToolCallDelta(ToolCallDeltaEvent {
+                                        index,
+                                        tool_id: Some(tool_use_block.tool_use.id.clone()),
+                                        tool_name: Some(tool_use_block.tool_use.name.clone()),
+                                        arguments: String::new(),
+                                    }),
+                                )))
+                                .await;
                             tool_call_states.insert(block.index, tool_use_block.tool_use);
                             json_states.insert(block.index, String::new());
                         }
@@ -277,6 +315,7 @@ impl AnthropicModel {
                                 &tracing::Span::current(),
                                 ModelEventType::LlmContent(LLMContentEvent {
                                     content: delta.text,
+                                    logprobs: None,
                                 }),
                             )))
                             .await
@@ -287,6 +326,7 @@ impl AnthropicModel {
                                 &tracing::Span::current(),
                                 ModelEventType::LlmContent(LLMContentEvent {
                                     content: delta.thinking,
+                                    logprobs: None,
                                 }),
                             )))
                             .await
@@ -296,6 +336,19 @@ impl AnthropicModel {
                         clust::messages::ContentBlockDelta::InputJsonDeltaBlock(
                             input_json_block,
                         ) => {
+                            if let Some(index) = tool_indexes.get(&block.index) {
+                                let _ = tx
+                                    .send(Some(ModelEvent::new(
+                                        &tracing::Span::current(),
+                                        ModelEventType::ToolCallDelta(ToolCallDeltaEvent {
+                                            index: *index,
+                                            tool_id: None,
+                                            tool_name: None,
+                                            arguments: input_json_block.partial_json.clone(),
+                                        }),
+                                    )))
+                                    .await;
+                            }
                             json_states
                                 .entry(block.index)
                                 .and_modify(|v| {
@@ -442,6 +495,7 @@ impl AnthropicModel {
                                 finish_reason: ModelFinishReason::Stop,
                                 tool_calls: vec![],
                                 credentials_ident: self.credentials_ident.clone(),
+                                generation: None,
                             }),
                         )))
                         .await
@@ -488,6 +542,7 @@ impl AnthropicModel {
                                 finish_reason: ModelFinishReason::Stop,
                                 tool_calls: vec![],
                                 credentials_ident: self.credentials_ident.clone(),
+                                generation: None,
                             }),
                         )))
                         .await
@@ -541,41 +596,37 @@ impl AnthropicModel {
                 let tools_span = tracing::info_span!(target: target!(), events::SPAN_TOOLS, tool_calls=tool_calls_str, label=tool_runs.iter().map(|t| t.name.clone()).collect::<Vec<String>>().join(","));
                 tools_span.follows_from(span.id());
 
+                // Every model call of a tool calling loop reports its usage
+                tx.send(Some(ModelEvent::new(
+                    &span,
+                    ModelEventType::LlmStop(LLMFinishEvent {
+                        provider_name: SPAN_ANTHROPIC.to_string(),
+                        model_name: self
+                            .params
+                            .model
+                            .clone()
+                            .map(|m| m.to_string())
+                            .unwrap_or_default(),
+                        output: text_content.clone(),
+                        usage: Some(Self::map_usage(&response.usage)),
+                        finish_reason: ModelFinishReason::ToolCalls,
+                        tool_calls: tool_runs
+                            .iter()
+                            .map(|tool_call| ModelToolCall {
+                                tool_id: tool_call.id.clone(),
+                                tool_name: tool_call.name.clone(),
+                                input: serde_json::to_string(&tool_call.input).unwrap(),
+                            })
+                            .collect(),
+                        credentials_ident: self.credentials_ident.clone(),
+                        generation: None,
+                    }),
+                )))
+                .await
+                .map_err(|e| GatewayError::CustomError(e.to_string()))?;
+
                 let tool = self.tools.get(&tool_runs[0].name).unwrap();
                 if tool.stop_at_call() {
-                    let usage = Some(CompletionModelUsage {
-                        input_tokens: response.usage.input_tokens,
-                        output_tokens: response.usage.output_tokens,
-                        total_tokens: response.usage.input_tokens + response.usage.output_tokens,
-                        ..Default::default()
-                    });
-                    tx.send(Some(ModelEvent::new(
-                        &span,
-                        ModelEventType::LlmStop(LLMFinishEvent {
-                            provider_name: SPAN_ANTHROPIC.to_string(),
-                            model_name: self
-                                .params
-                                .model
-                                .clone()
-                                .map(|m| m.to_string())
-                                .unwrap_or_default(),
-                            output: text_content.clone(),
-                            usage,
-                            finish_reason: ModelFinishReason::ToolCalls,
-                            tool_calls: tool_runs
-                                .iter()
-                                .map(|tool_call| ModelToolCall {
-                                    tool_id: tool_call.id.clone(),
-                                    tool_name: tool_call.name.clone(),
-                                    input: serde_json::to_string(&tool_call.input).unwrap(),
-                                })
-                                .collect(),
-                            credentials_ident: self.credentials_ident.clone(),
-                        }),
-                    )))
-                    .await
-                    .map_err(|e| GatewayError::CustomError(e.to_string()))?;
-
                     Ok(InnerExecutionResult::Finish(ChatCompletionMessage {
                         role: "assistant".to_string(),
                         content: text_content.map(ChatCompletionContent::Text),
@@ -602,7 +653,7 @@ impl AnthropicModel {
                     let result_tool_calls =
                         Self::handle_tool_calls(tool_runs.iter(), &self.tools, tx, tags.clone())
                             .instrument(tools_span.clone())
-                            .await;
+                            .await?;
                     messages.extend(result_tool_calls);
 
                     let conversation_messages = [input_messages, messages].concat();
@@ -623,17 +674,14 @@ impl AnthropicModel {
         tags: HashMap<String, String>,
     ) -> GatewayResult<ChatCompletionMessage> {
         let mut calls = vec![(system_message, input_messages)];
-        let mut retries_left = self
-            .execution_options
-            .max_retries
-            .unwrap_or(DEFAULT_MAX_RETRIES);
+        let mut retries = ProviderRetries::new(&self.execution_options);
         while let Some((system_message, input_messages)) = calls.pop() {
             let input = serde_json::to_string(&input_messages)?;
             let call_span = create_model_span!(
                 SPAN_ANTHROPIC,
                 target!("chat"),
                 tags,
-                retries_left,
+                retries.retries_left(),
                 input = input,
                 system_prompt = field::Empty
             );
@@ -660,12 +708,10 @@ impl AnthropicModel {
                 }
                 Err(e) => {
                     call_span.record("error", e.to_string());
-                    if retries_left == 0 {
+                    if !retries.retry(&e, &call_span, tx).await {
                         return Err(e);
-                    } else {
-                        calls.push((Some(system_prompt), input_messages));
                     }
-                    retries_left -= 1;
+                    calls.push((Some(system_prompt), input_messages));
                 }
             }
         }
@@ -681,17 +727,14 @@ impl AnthropicModel {
         tags: HashMap<String, String>,
     ) -> GatewayResult<()> {
         let mut calls = vec![(system_message, input_messages)];
-        let mut retries_left = self
-            .execution_options
-            .max_retries
-            .unwrap_or(DEFAULT_MAX_RETRIES);
+        let mut retries = ProviderRetries::new(&self.execution_options);
         while let Some((system_message, input_messages)) = calls.pop() {
             let input = serde_json::to_string(&input_messages)?;
             let call_span = create_model_span!(
                 SPAN_ANTHROPIC,
                 target!("chat"),
                 tags,
-                retries_left,
+                retries.retries_left(),
                 input = input,
                 system_prompt = field::Empty
             );
@@ -718,12 +761,10 @@ impl AnthropicModel {
                 }
                 Err(e) => {
                     call_span.record("error", e.to_string());
-                    if retries_left == 0 {
+                    if !retries.retry(&e, &call_span, tx).await {
                         return Err(e);
-                    } else {
-                        calls.push((Some(system_prompt), input_messages));
                     }
-                    retries_left -= 1;
+                    calls.push((Some(system_prompt), input_messages));
                 }
             }
         }
@@ -732,10 +773,14 @@ impl AnthropicModel {
     }
 
     fn map_usage(usage: &Usage) -> CompletionModelUsage {
+        // Anthropic input tokens do not include cache reads and writes
+        let input_tokens = usage.input_tokens
+            + usage.cache_read_input_tokens.unwrap_or(0)
+            + usage.cache_creation_input_tokens.unwrap_or(0);
         CompletionModelUsage {
-            input_tokens: usage.input_tokens,
+            input_tokens,
             output_tokens: usage.output_tokens,
-            total_tokens: usage.input_tokens + usage.output_tokens,
+            total_tokens: input_tokens + usage.output_tokens,
             prompt_tokens_details: Some(PromptTokensDetails::new(
                 usage.cache_read_input_tokens,
                 usage.cache_creation_input_tokens,
@@ -827,6 +872,7 @@ impl AnthropicModel {
                     .iter()
                     .map(Self::map_tool_call)
                     .collect::<Result<Vec<ModelToolCall>, GatewayError>>()?,
+                generation: None,
             }),
         )))
         .await
@@ -875,7 +921,7 @@ impl AnthropicModel {
                     let result_tool_calls =
                         Self::handle_tool_calls(tool_calls.iter(), &self.tools, tx, tags.clone())
                             .instrument(tools_span.clone())
-                            .await;
+                            .await?;
                     messages.extend(result_tool_calls);
 
                     let conversation_messages = [input_messages, messages].concat();
@@ -893,57 +939,84 @@ impl AnthropicModel {
         // convert serde::Map into HashMap
         let mut messages: Vec<ClustMessage> = vec![];
 
-        let mut tool_results_remaining = 0;
-        let mut tool_calls_collected = vec![];
+        // Tool results are sent in the next user turn, together with its content
+        let mut tool_results = vec![];
 
         for m in messages_dto.iter() {
             match m.r#type {
                 MessageType::SystemMessage => {}
                 MessageType::AIMessage => {
+                    if !tool_results.is_empty() {
+                        messages.push(ClustMessage::user(Content::MultipleBlocks(std::mem::take(
+                            &mut tool_results,
+                        ))));
+                    }
+
                     if let Some(tool_calls) = &m.tool_calls {
-                        tool_results_remaining = tool_calls.len();
-                        tool_calls_collected = vec![];
+                        let mut blocks = text_blocks(m);
+                        for t in tool_calls {
+                            blocks.push(ContentBlock::ToolUse(ToolUseContentBlock::new(
+                                ToolUse::new(
+                                    t.id.clone(),
+                                    t.function.name.clone(),
+                                    serde_json::from_str(&t.function.arguments)?,
+                                ),
+                            )));
+                        }
 
-                        messages.push(ClustMessage::assistant(Content::MultipleBlocks(
-                            tool_calls
-                                .iter()
-                                .map(|t| {
-                                    Ok(ContentBlock::ToolUse(ToolUseContentBlock::new(
-                                        ToolUse::new(
-                                            t.id.clone(),
-                                            t.function.name.clone(),
-                                            serde_json::from_str(&t.function.arguments)?,
-                                        ),
-                                    )))
-                                })
-                                .collect::<Result<Vec<ContentBlock>, GatewayError>>()?,
-                        )));
-                    } else {
+                        messages.push(ClustMessage::assistant(Content::MultipleBlocks(blocks)));
+                    } else if m.content_array.is_empty() {
                         messages.push(ClustMessage::assistant(Content::SingleText(
                             m.content.clone().unwrap_or_default(),
                         )));
+                    } else {
+                        messages.push(ClustMessage::assistant(Content::MultipleBlocks(
+                            text_blocks(m),
+                        )));
                     }
                 }
                 MessageType::HumanMessage => {
-                    messages.push(construct_user_message(&m.clone().into()));
+                    if tool_results.is_empty() {
+                        messages.push(construct_user_message(&m.clone().into())?);
+                    } else {
+                        let mut blocks = std::mem::take(&mut tool_results);
+                        match InnerMessage::from(m.clone()) {
+                            InnerMessage::Text(text) if text.is_empty() => {}
+                            InnerMessage::Text(text) => {
+                                blocks.push(ContentBlock::Text(TextContentBlock::new(text)))
+                            }
+                            InnerMessage::Array(content_array) => {
+                                blocks.extend(user_content_blocks(&content_array)?)
+                            }
+                        }
+                        messages.push(ClustMessage::user(Content::MultipleBlocks(blocks)));
+                    }
                 }
                 MessageType::ToolResult => {
-                    tool_results_remaining -= 1;
-                    tool_calls_collected.push(ContentBlock::ToolResult(
-                        ToolResultContentBlock::new(ToolResult::success(
+                    // Tool result blocks take no breakpoint, the text of cached parts is kept
+                    let content = m.content.clone().or_else(|| {
+                        (!m.content_array.is_empty()).then(|| {
+                            m.content_array
+                                .iter()
+                                .map(|c| c.value.as_str())
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        })
+                    });
+                    tool_results.push(ContentBlock::ToolResult(ToolResultContentBlock::new(
+                        ToolResult::success(
                             m.tool_call_id.as_ref().expect("Missing tool call id"),
-                            m.content.clone(),
-                        )),
-                    ));
-                    if tool_results_remaining == 0 {
-                        messages.push(ClustMessage::user(Content::MultipleBlocks(
-                            tool_calls_collected.clone(),
-                        )));
-                    }
+                            content,
+                        ),
+                    )));
                 }
             }
         }
 
+        if !tool_results.is_empty() {
+            messages.push(ClustMessage::user(Content::MultipleBlocks(tool_results)));
+        }
+
         Ok(messages)
     }
 }
@@ -1003,23 +1076,7 @@ impl AnthropicModel {
                             message
                                 .content_array
                                 .iter()
-                                .map(|c| match &c.cache_control {
-                                    Some(cache_control) => {
-                                        let cache_control = clust::messages::CacheControl {
-                                            _type: clust::messages::CacheControlType::Ephemeral,
-                                            ttl: cache_control.ttl().map(|t| t.into()),
-                                        };
-                                        ContentBlock::Text(
-                                            TextContentBlock::new_with_cache_control(
-                                                c.value.clone(),
-                                                cache_control,
-                                            ),
-                                        )
-                                    }
-                                    None => {
-                                        ContentBlock::Text(TextContentBlock::new(c.value.clone()))
-                                    }
-                                })
+                                .map(|c| text_block(c.value.clone(), c.cache_control.as_ref()))
                                 .collect(),
                         )
                     }
@@ -1065,7 +1122,7 @@ fn map_chat_messages(
             } else {
                 InnerMessage::Text(Prompt::render(msg.clone(), variables))
             };
-            construct_user_message(&inner_message)
+            construct_user_message(&inner_message)?
         }
         _ => {
             return Err(GatewayError::CustomError(
@@ -1076,49 +1133,82 @@ fn map_chat_messages(
     Ok(message)
 }
 
-fn construct_user_message(m: &InnerMessage) -> ClustMessage {
+fn construct_user_message(m: &InnerMessage) -> Result<ClustMessage, ModelError> {
     let content = match m {
         crate::types::threads::InnerMessage::Text(text) => Content::SingleText(text.to_owned()),
         crate::types::threads::InnerMessage::Array(content_array) => {
-            let mut blocks = vec![];
-            for m in content_array {
-                let msg: ContentBlock = match m.r#type {
-                    crate::types::threads::MessageContentType::Text => {
-                        if let Some(cache_control) = &m.cache_control {
-                            let cache_control = clust::messages::CacheControl {
-                                _type: clust::messages::CacheControlType::Ephemeral,
-                                ttl: cache_control.ttl().map(|t| t.into()),
-                            };
-                            ContentBlock::Text(TextContentBlock::new_with_cache_control(
-                                m.value.clone(),
-                                cache_control.clone(),
-                            ))
-                        } else {
-                            ContentBlock::Text(TextContentBlock::new(m.value.clone()))
-                        }
-                    }
-                    crate::types::threads::MessageContentType::ImageUrl => {
-                        let url = m.value.clone();
-                        let base64_data = url
-                            .split_once(',')
-                            .map_or_else(|| url.as_str(), |(_, data)| data);
-                        ContentBlock::Image(ImageContentBlock::from(ImageContentSource::base64(
-                            clust::messages::ImageMediaType::Png,
-                            base64_data,
-                        )))
-                    }
-                    crate::types::threads::MessageContentType::InputAudio => {
-                        todo!()
-                    }
-                };
-                blocks.push(msg)
-            }
-
-            Content::MultipleBlocks(blocks)
+            Content::MultipleBlocks(user_content_blocks(content_array)?)
         }
     };
 
-    ClustMessage::user(content)
+    Ok(ClustMessage::user(content))
+}
+
+/// Text block, annotated with a prompt caching breakpoint when the part carries one
+fn text_block(
+    text: String,
+    cache_control: Option<&crate::types::gateway::CacheControl>,
+) -> ContentBlock {
+    match cache_control {
+        Some(cache_control) => {
+            let cache_control = clust::messages::CacheControl {
+                _type: clust::messages::CacheControlType::Ephemeral,
+                ttl: cache_control.ttl().map(|t| t.into()),
+            };
+            ContentBlock::Text(TextContentBlock::new_with_cache_control(
+                text,
+                cache_control,
+            ))
+        }
+        None => ContentBlock::Text(TextContentBlock::new(text)),
+    }
+}
+
+/// Text blocks of an assistant or tool message, the content array is used when the
+/// message carries cache breakpoints
+fn text_blocks(m: &Message) -> Vec<ContentBlock> {
+    match m.content.as_ref() {
+        Some(text) if !text.is_empty() => vec![text_block(text.clone(), None)],
+        _ => m
+            .content_array
+            .iter()
+            .filter(|c| c.r#type == crate::types::threads::MessageContentType::Text)
+            .map(|c| text_block(c.value.clone(), c.cache_control.as_ref()))
+            .collect(),
+    }
+}
+
+fn user_content_blocks(
+    content_array: &[crate::types::threads::MessageContentPart],
+) -> Result<Vec<ContentBlock>, ModelError> {
+    let mut blocks = vec![];
+    for m in content_array {
+        let msg: ContentBlock = match m.r#type {
+            crate::types::threads::MessageContentType::Text => {
+                text_block(m.value.clone(), m.cache_control.as_ref())
+            }
+            crate::types::threads::MessageContentType::ImageUrl => {
+                let (media_type, data) = inline_image(&m.value)?;
+                let media_type = match media_type {
+                    "image/jpeg" | "image/jpg" => clust::messages::ImageMediaType::Jpeg,
+                    "image/gif" => clust::messages::ImageMediaType::Gif,
+                    "image/webp" => clust::messages::ImageMediaType::Webp,
+                    _ => clust::messages::ImageMediaType::Png,
+                };
+                ContentBlock::Image(ImageContentBlock::from(ImageContentSource::base64(
+                    media_type, data,
+                )))
+            }
+            crate::types::threads::MessageContentType::InputAudio => {
+                return Err(ModelError::CustomError(
+                    "Audio input is not supported by Anthropic models".to_string(),
+                ));
+            }
+        };
+        blocks.push(msg)
+    }
+
+    Ok(blocks)
 }
 
 pub fn record_map_err(e: impl Into<GatewayError> + ToString, span: tracing::Span) -> GatewayError {
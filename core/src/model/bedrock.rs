@@ -5,17 +5,19 @@ use super::types::{
 use super::{CredentialsIdent, ModelInstance};
 use crate::error::GatewayError;
 use crate::events::{self, JsonValue, RecordResult, SPAN_BEDROCK};
-use crate::model::error::BedrockError;
-use crate::model::handler::handle_tool_call;
+use crate::executor::chat_completion::backoff::ProviderRetries;
+use crate::llm_gateway::message_mapper::inline_image;
+use crate::model::error::{BedrockError, CONTENT_FILTER_ERROR};
+use crate::model::handler::{handle_tool_call, tool_error_content};
 use crate::model::types::LLMFirstToken;
 use crate::model::Tool as LangdbTool;
-use crate::model::DEFAULT_MAX_RETRIES;
 use crate::models::BedrockMetaCompletionModel;
 use crate::types::aws::{get_shared_config, get_user_shared_config};
 use crate::types::credentials::AwsCredentials;
 use crate::types::engine::{BedrockModelParams, ExecutionOptions, Prompt};
 use crate::types::gateway::{
-    ChatCompletionContent, ChatCompletionMessage, CompletionModelUsage, ToolCall,
+    ChatCompletionContent, ChatCompletionMessage, CompletionModelUsage, PromptTokensDetails,
+    ToolCall, ToolChoice, ToolChoiceMode,
 };
 use crate::types::message::{MessageType, PromptMessage};
 use crate::types::provider::BedrockProvider;
@@ -29,10 +31,11 @@ use aws_sdk_bedrockruntime::operation::converse_stream::{self, ConverseStreamErr
 use aws_sdk_bedrockruntime::types::builders::ImageBlockBuilder;
 use aws_sdk_bedrockruntime::types::ConverseOutput::Message as MessageVariant;
 use aws_sdk_bedrockruntime::types::{
-    ContentBlock, ContentBlockDelta, ContentBlockStart, ConversationRole, ConverseOutput,
-    ConverseStreamOutput, InferenceConfiguration, Message, StopReason, SystemContentBlock,
-    TokenUsage, Tool, ToolConfiguration, ToolInputSchema, ToolResultBlock, ToolResultContentBlock,
-    ToolResultStatus, ToolSpecification, ToolUseBlock,
+    AnyToolChoice, ContentBlock, ContentBlockDelta, ContentBlockStart, ConversationRole,
+    ConverseOutput, ConverseStreamOutput, InferenceConfiguration, Message, SpecificToolChoice,
+    StopReason, SystemContentBlock, TokenUsage, Tool, ToolChoice as BedrockToolChoice,
+    ToolConfiguration, ToolInputSchema, ToolResultBlock, ToolResultContentBlock, ToolResultStatus,
+    ToolSpecification, ToolUseBlock,
 };
 use aws_sdk_bedrockruntime::Client;
 use aws_smithy_types::{Blob, Document};
@@ -319,42 +322,73 @@ impl BedrockModel {
         tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
         tags: HashMap<String, String>,
     ) -> GatewayResult<Message> {
-        let content = futures::future::join_all(tool_uses.iter().map(|tool| {
+        let content = futures::future::try_join_all(tool_uses.iter().map(|tool| {
             let tags_value = tags.clone();
             async move {
                 let tool_use_id = tool.tool_use_id.clone();
                 tracing::trace!("Calling tool ({tool_use_id}) {:?}", tool.name);
                 let tool_call = Self::map_tool_call(tool)?;
-                let result = handle_tool_call(&tool_call, tools, tx, tags_value.clone()).await;
+                let result = handle_tool_call(&tool_call, tools, tx, tags_value.clone()).await?;
                 tracing::trace!("Result ({tool_use_id}): {result:?}");
-                let content = result.unwrap_or_else(|err| err.to_string());
-                Ok(ContentBlock::ToolResult(
+                // A failed tool is reported to the model, the other results are kept
+                let (content, status) = match result {
+                    Ok(content) => (content, ToolResultStatus::Success),
+                    Err(err) => (tool_error_content(&err), ToolResultStatus::Error),
+                };
+                Ok::<_, GatewayError>(ContentBlock::ToolResult(
                     ToolResultBlock::builder()
                         .tool_use_id(tool_use_id.clone())
                         .content(ToolResultContentBlock::Text(content))
-                        .status(ToolResultStatus::Success)
+                        .status(status)
                         .build()
                         .unwrap(),
                 ))
             }
         }))
-        .await;
+        .await?;
 
-        let c = content
-            .into_iter()
-            .collect::<GatewayResult<Vec<ContentBlock>>>()?;
         Ok(Message::builder()
-            .set_content(Some(c))
+            .set_content(Some(content))
             .role(ConversationRole::User)
             .build()
             .unwrap())
     }
 
-    pub(crate) fn get_tools_config(&self) -> Result<Option<ToolConfiguration>, GatewayError> {
+    /// Tool specs and choice of the request. Bedrock has no `none` choice, so the
+    /// specs are left out for it unless the messages hold tool calls or results.
+    pub(crate) fn get_tools_config(
+        &self,
+        messages: &[Message],
+    ) -> Result<Option<ToolConfiguration>, GatewayError> {
         if self.tools.is_empty() {
             return Ok(None);
         }
 
+        let after_tool_results = messages.last().is_some_and(|m| {
+            m.content()
+                .iter()
+                .any(|c| matches!(c, ContentBlock::ToolResult(_)))
+        });
+        let tool_choice = match &self.params.tool_choice {
+            Some(choice) if after_tool_results => Some(choice.after_tool_results()),
+            choice => choice.clone(),
+        };
+        if omits_tools(tool_choice.as_ref(), messages) {
+            return Ok(None);
+        }
+        let tool_choice = match tool_choice {
+            Some(ToolChoice::Mode(ToolChoiceMode::Auto | ToolChoiceMode::None)) | None => None,
+            Some(ToolChoice::Mode(ToolChoiceMode::Required)) => {
+                Some(BedrockToolChoice::Any(AnyToolChoice::builder().build()))
+            }
+            Some(ToolChoice::Function(choice)) => Some(BedrockToolChoice::Tool(
+                SpecificToolChoice::builder()
+                    .name(choice.function.name)
+                    .build()
+                    .map_err(build_err)?,
+            )),
+        };
+
         let mut tools = vec![];
 
         for (name, tool) in self.tools.iter() {
@@ -379,6 +413,7 @@ impl BedrockModel {
 
         let config = ToolConfiguration::builder()
             .set_tools(Some(tools))
+            .set_tool_choice(tool_choice)
             .build()
             .map_err(build_err)?;
 
@@ -402,7 +437,7 @@ impl BedrockModel {
             .client
             .converse()
             .set_system(Some(system_messages.to_vec()))
-            .set_tool_config(self.get_tools_config()?)
+            .set_tool_config(self.get_tools_config(input_messages)?)
             .model_id(replace_version(&self.model_name))
             .set_messages(Some(input_messages.to_vec()))
             .additional_model_request_fields(Document::deserialize(
@@ -423,10 +458,7 @@ impl BedrockModel {
     ) -> GatewayResult<ChatCompletionMessage> {
         let mut calls = vec![input_messages];
 
-        let mut retries_left = self
-            .execution_options
-            .max_retries
-            .unwrap_or(DEFAULT_MAX_RETRIES);
+        let mut retries = ProviderRetries::new(&self.execution_options);
         while let Some(input_messages) = calls.pop() {
             let input = serde_json::json!({
                 "initial_messages": format!("{input_messages:?}"),
@@ -436,7 +468,7 @@ impl BedrockModel {
                 SPAN_BEDROCK,
                 target!("chat"),
                 tags,
-                retries_left,
+                retries.retries_left(),
                 input = JsonValue(&input).as_value(),
                 system_prompt = field::Empty
             );
@@ -453,12 +485,10 @@ impl BedrockModel {
                 }
                 Err(e) => {
                     span.record("error", e.to_string());
-                    if retries_left == 0 {
+                    if !retries.retry(&e, &span, tx).await {
                         return Err(e);
-                    } else {
-                        calls.push(input_messages);
                     }
-                    retries_left -= 1;
+                    calls.push(input_messages);
                 }
             }
         }
@@ -515,12 +545,7 @@ impl BedrockModel {
         match response.stop_reason {
             StopReason::EndTurn | StopReason::StopSequence => match response.output {
                 Some(MessageVariant(message)) => {
-                    let usage = response.usage.as_ref().map(|usage| CompletionModelUsage {
-                        input_tokens: usage.input_tokens as u32,
-                        output_tokens: usage.output_tokens as u32,
-                        total_tokens: usage.total_tokens as u32,
-                        ..Default::default()
-                    });
+                    let usage = Self::map_usage(response.usage.as_ref());
 
                     let output = match message.content.first() {
                         Some(ContentBlock::Text(message)) => Some(message.clone()),
@@ -542,6 +567,7 @@ impl BedrockModel {
                             finish_reason: ModelFinishReason::Stop,
                             tool_calls: vec![],
                             credentials_ident: self.credentials_ident.clone(),
+                            generation: None,
                         }),
                     )))
                     .await
@@ -622,39 +648,32 @@ impl BedrockModel {
                                     .collect::<Vec<String>>()
                                     .join(","),
                             );
-                            if tool.stop_at_call() {
-                                let usage =
-                                    response.usage.as_ref().map(|usage| CompletionModelUsage {
-                                        input_tokens: usage.input_tokens as u32,
-                                        output_tokens: usage.output_tokens as u32,
-                                        total_tokens: usage.total_tokens as u32,
-                                        ..Default::default()
-                                    });
-
-                                tx.send(Some(ModelEvent::new(
-                                    &span,
-                                    ModelEventType::LlmStop(LLMFinishEvent {
-                                        provider_name: SPAN_BEDROCK.to_string(),
-                                        model_name: self
-                                            .params
-                                            .model_id
-                                            .clone()
-                                            .map(|m| m.to_string())
-                                            .unwrap_or_default(),
-                                        output: content.clone(),
-                                        usage,
-                                        finish_reason: ModelFinishReason::ToolCalls,
-                                        tool_calls: tool_uses
-                                            .iter()
-                                            .map(Self::map_tool_call)
-                                            .collect::<Result<Vec<ModelToolCall>, GatewayError>>(
-                                        )?,
-                                        credentials_ident: self.credentials_ident.clone(),
-                                    }),
-                                )))
-                                .await
-                                .map_err(|e| GatewayError::CustomError(e.to_string()))?;
+                            // Every model call of a tool calling loop reports its usage
+                            tx.send(Some(ModelEvent::new(
+                                &span,
+                                ModelEventType::LlmStop(LLMFinishEvent {
+                                    provider_name: SPAN_BEDROCK.to_string(),
+                                    model_name: self
+                                        .params
+                                        .model_id
+                                        .clone()
+                                        .map(|m| m.to_string())
+                                        .unwrap_or_default(),
+                                    output: content.clone(),
+                                    usage: Self::map_usage(response.usage.as_ref()),
+                                    finish_reason: ModelFinishReason::ToolCalls,
+                                    tool_calls: tool_uses
+                                        .iter()
+                                        .map(Self::map_tool_call)
+                                        .collect::<Result<Vec<ModelToolCall>, GatewayError>>()?,
+                                    credentials_ident: self.credentials_ident.clone(),
+                                    generation: None,
+                                }),
+                            )))
+                            .await
+                            .map_err(|e| GatewayError::CustomError(e.to_string()))?;
 
+                            if tool.stop_at_call() {
                                 Ok(InnerExecutionResult::Finish(ChatCompletionMessage {
                                     role: "assistant".to_string(),
                                     tool_calls: Some(tool_calls),
@@ -720,7 +739,10 @@ impl BedrockModel {
                         Some(ContentBlockDelta::Text(t)) => {
                             tx.send(Some(ModelEvent::new(
                                 &Span::current(),
-                                ModelEventType::LlmContent(LLMContentEvent { content: t }),
+                                ModelEventType::LlmContent(LLMContentEvent {
+                                    content: t,
+                                    logprobs: None,
+                                }),
                             )))
                             .await
                             .unwrap();
@@ -806,11 +828,20 @@ impl BedrockModel {
         }
     }
     fn map_usage(usage: Option<&TokenUsage>) -> Option<CompletionModelUsage> {
-        usage.map(|u| CompletionModelUsage {
-            input_tokens: u.input_tokens as u32,
-            output_tokens: u.output_tokens as u32,
-            total_tokens: u.total_tokens as u32,
-            ..Default::default()
+        usage.map(|u| {
+            // Input tokens only count the part of the prompt that was not cached
+            let cached_tokens = u.cache_read_input_tokens().unwrap_or(0) as u32;
+            let cache_creation_tokens = u.cache_write_input_tokens().unwrap_or(0) as u32;
+            let is_prompt_cached = cached_tokens > 0 || cache_creation_tokens > 0;
+            CompletionModelUsage {
+                input_tokens: u.input_tokens as u32 + cached_tokens + cache_creation_tokens,
+                output_tokens: u.output_tokens as u32,
+                total_tokens: u.total_tokens as u32,
+                prompt_tokens_details: is_prompt_cached.then(|| {
+                    PromptTokensDetails::new(Some(cached_tokens), Some(cache_creation_tokens), None)
+                }),
+                ..Default::default()
+            }
         })
     }
 
@@ -823,10 +854,7 @@ impl BedrockModel {
     ) -> GatewayResult<()> {
         let mut calls = vec![input_messages];
 
-        let mut retries_left = self
-            .execution_options
-            .max_retries
-            .unwrap_or(DEFAULT_MAX_RETRIES);
+        let mut retries = ProviderRetries::new(&self.execution_options);
         while let Some(input_messages) = calls.pop() {
             let input = serde_json::json!({
                 "initial_messages": format!("{input_messages:?}"),
@@ -836,7 +864,7 @@ impl BedrockModel {
                 SPAN_BEDROCK,
                 target!("chat"),
                 tags,
-                retries_left,
+                retries.retries_left(),
                 input = JsonValue(&input).as_value(),
                 system_prompt = field::Empty
             );
@@ -846,7 +874,7 @@ impl BedrockModel {
                 .converse_stream()
                 .model_id(replace_version(&self.model_name))
                 .set_system(Some(system_messages.clone()))
-                .set_tool_config(self.get_tools_config()?)
+                .set_tool_config(self.get_tools_config(&input_messages)?)
                 .set_messages(Some(input_messages.clone()));
 
             let response = self
@@ -860,12 +888,10 @@ impl BedrockModel {
                 }
                 Err(e) => {
                     span.record("error", e.to_string());
-                    if retries_left == 0 {
+                    if !retries.retry(&e, &span, tx).await {
                         return Err(e);
-                    } else {
-                        calls.push(input_messages);
                     }
-                    retries_left -= 1;
+                    calls.push(input_messages);
                 }
             }
         }
@@ -929,6 +955,7 @@ impl BedrockModel {
                 finish_reason: trace_finish_reason.clone(),
                 tool_calls: tool_calls.clone(),
                 credentials_ident: self.credentials_ident.clone(),
+                generation: None,
             }),
         )))
         .await
@@ -988,7 +1015,7 @@ impl BedrockModel {
 
     pub fn handle_stop_reason(reason: StopReason) -> ModelError {
         let str = match reason {
-            StopReason::ContentFiltered => "Content filter blocked the completion",
+            StopReason::ContentFiltered => CONTENT_FILTER_ERROR,
             StopReason::GuardrailIntervened => "Guardrail intervened and stopped this execution",
             StopReason::MaxTokens => {
                 "the maximum number of tokens specified in the request was reached"
@@ -1042,16 +1069,21 @@ fn construct_human_message(m: &InnerMessage) -> Result<Message, ModelError> {
                         content_blocks.push(ContentBlock::Text(part.value.clone()));
                     }
                     crate::types::threads::MessageContentType::ImageUrl => {
-                        let url = part.value.clone();
-                        let base64_data = url
-                            .split_once(',')
-                            .map_or_else(|| url.as_str(), |(_, data)| data);
+                        let (media_type, base64_data) = inline_image(&part.value)?;
+                        let format = match media_type {
+                            "image/jpeg" | "image/jpg" => {
+                                aws_sdk_bedrockruntime::types::ImageFormat::Jpeg
+                            }
+                            "image/gif" => aws_sdk_bedrockruntime::types::ImageFormat::Gif,
+                            "image/webp" => aws_sdk_bedrockruntime::types::ImageFormat::Webp,
+                            _ => aws_sdk_bedrockruntime::types::ImageFormat::Png,
+                        };
 
                         let image_bytes = base64::engine::general_purpose::STANDARD
                             .decode(base64_data)
                             .map_err(|e| ModelError::CustomError(e.to_string()))?;
                         let image = ImageBlockBuilder::default()
-                            .format(aws_sdk_bedrockruntime::types::ImageFormat::Png)
+                            .format(format)
                             .source(aws_sdk_bedrockruntime::types::ImageSource::Bytes(
                                 Blob::new(image_bytes),
                             ))
@@ -1089,6 +1121,17 @@ fn replace_version(model: &str) -> String {
         .to_string()
 }
 
+/// `none` leaves the tool specs out, unless earlier turns hold tool calls or results,
+/// which Bedrock rejects without the specs
+fn omits_tools(tool_choice: Option<&ToolChoice>, messages: &[Message]) -> bool {
+    let has_tool_history = messages.iter().any(|m| {
+        m.content()
+            .iter()
+            .any(|c| matches!(c, ContentBlock::ToolUse(_) | ContentBlock::ToolResult(_)))
+    });
+    matches!(tool_choice, Some(ToolChoice::Mode(ToolChoiceMode::None))) && !has_tool_history
+}
+
 fn map_converse_stream_error(
     e: aws_smithy_runtime_api::client::result::SdkError<
         aws_sdk_bedrockruntime::operation::converse_stream::ConverseStreamError,
@@ -1105,3 +1148,53 @@ fn map_converse_stream_error(
         _ => ModelError::Bedrock(Box::new(e.into())),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: ConversationRole, content: ContentBlock) -> Message {
+        Message::builder()
+            .role(role)
+            .content(content)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_tool_choice_none_keeps_tool_history() {
+        let none = ToolChoice::Mode(ToolChoiceMode::None);
+        let question = message(
+            ConversationRole::User,
+            ContentBlock::Text("Weather in Paris?".to_string()),
+        );
+        assert!(omits_tools(Some(&none), std::slice::from_ref(&question)));
+        assert!(!omits_tools(None, std::slice::from_ref(&question)));
+
+        let history = [
+            question,
+            message(
+                ConversationRole::Assistant,
+                ContentBlock::ToolUse(
+                    ToolUseBlock::builder()
+                        .tool_use_id("call_1")
+                        .name("get_weather")
+                        .input(Document::Object(HashMap::new()))
+                        .build()
+                        .unwrap(),
+                ),
+            ),
+            message(
+                ConversationRole::User,
+                ContentBlock::ToolResult(
+                    ToolResultBlock::builder()
+                        .tool_use_id("call_1")
+                        .content(ToolResultContentBlock::Text("Sunny".to_string()))
+                        .build()
+                        .unwrap(),
+                ),
+            ),
+        ];
+        assert!(!omits_tools(Some(&none), &history));
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::token_estimate::TokenizerFamily;
+use crate::types::gateway::CompletionModelUsage;
+
+/// Emits running cost estimates while a response is streamed
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CostAccrualConfig {
+    /// Estimated output tokens between two accrual events
+    #[serde(default = "default_interval_tokens")]
+    pub interval_tokens: u32,
+}
+
+fn default_interval_tokens() -> u32 {
+    100
+}
+
+pub struct CostAccrual {
+    interval_tokens: u32,
+    family: TokenizerFamily,
+    input_tokens: u32,
+    output_chars: usize,
+    reported_tokens: u32,
+}
+
+impl CostAccrual {
+    pub fn new(config: &CostAccrualConfig) -> Self {
+        Self {
+            interval_tokens: config.interval_tokens.max(1),
+            family: TokenizerFamily::default(),
+            input_tokens: 0,
+            output_chars: 0,
+            reported_tokens: 0,
+        }
+    }
+
+    pub fn start(&mut self, input: &str, family: TokenizerFamily) {
+        self.family = family;
+        self.input_tokens = family.estimate(input);
+        self.output_chars = 0;
+        self.reported_tokens = 0;
+    }
+
+    /// Returns the running usage estimate once another interval of output tokens was streamed
+    pub fn record(&mut self, content: &str) -> Option<CompletionModelUsage> {
+        self.output_chars += content.len();
+        let output_tokens = self.family.estimate_bytes(self.output_chars);
+        if output_tokens < self.reported_tokens + self.interval_tokens {
+            return None;
+        }
+
+        self.reported_tokens = output_tokens - output_tokens % self.interval_tokens;
+        Some(CompletionModelUsage {
+            input_tokens: self.input_tokens,
+            output_tokens,
+            total_tokens: self.input_tokens + output_tokens,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accrual_every_interval() {
+        let mut accrual = CostAccrual::new(&CostAccrualConfig {
+            interval_tokens: 10,
+        });
+        accrual.start(&"a".repeat(80), TokenizerFamily::Other);
+
+        // 9 tokens
+        assert!(accrual.record(&"b".repeat(36)).is_none());
+        // 11 tokens
+        let usage = accrual.record(&"b".repeat(8)).unwrap();
+        assert_eq!(usage.input_tokens, 20);
+        assert_eq!(usage.output_tokens, 11);
+        assert_eq!(usage.total_tokens, 31);
+        // 19 tokens, still in the second interval
+        assert!(accrual.record(&"b".repeat(32)).is_none());
+        // 35 tokens, one event even though two intervals were crossed
+        assert_eq!(accrual.record(&"b".repeat(64)).unwrap().output_tokens, 35);
+        assert!(accrual.record("b").is_none());
+    }
+}
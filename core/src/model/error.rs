@@ -1,7 +1,11 @@
+use std::time::Duration;
+
 use async_openai::error::OpenAIError;
 use aws_sdk_bedrock::error::DisplayErrorContext;
 use thiserror::Error;
 
+pub const CONTENT_FILTER_ERROR: &str = "Content filter blocked the completion";
+
 #[derive(Error, Debug)]
 pub enum ModelError {
     #[error("Credentials for '{0}' are invalid or missing")]
@@ -13,6 +17,9 @@ pub enum ModelError {
     #[error("OpenAI tool not found: {0}")]
     ToolNotFoundError(String),
 
+    #[error("Invalid arguments for tool {0}: {1}")]
+    InvalidToolArguments(String, String),
+
     #[error("Stream error: {0:?}")]
     StreamError(String),
 
@@ -56,6 +63,123 @@ pub enum ModelError {
     ModelNotFound(String),
 }
 
+impl ModelError {
+    /// Returns true when the provider refused the request because of its content policy
+    pub fn is_content_filter(&self) -> bool {
+        match self {
+            ModelError::FinishError(message) => message == CONTENT_FILTER_ERROR,
+            ModelError::OpenAIApi(OpenAIError::ApiError(e)) => matches!(
+                e.code.as_deref(),
+                Some("content_filter") | Some("content_policy_violation")
+            ),
+            _ => false,
+        }
+    }
+
+    /// Returns true when the provider is rate limiting or temporarily unavailable (HTTP 429
+    /// and 503), so the same request is likely to succeed after waiting
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ModelError::OpenAIApi(OpenAIError::ApiError(e)) => {
+                matches!(
+                    e.r#type.as_deref(),
+                    Some("rate_limit_error" | "overloaded_error")
+                ) || e.code.as_deref() == Some("rate_limit_exceeded")
+            }
+            ModelError::Bedrock(e) => match e.as_ref() {
+                BedrockError::ValidationError(_) | BedrockError::AuthenticationError(_) => false,
+                e => is_transient_message(&e.to_string()),
+            },
+            ModelError::Anthropic(AnthropicError::ClustError(e)) => {
+                is_transient_message(&e.to_string())
+            }
+            ModelError::StreamError(message) => is_transient_message(message),
+            _ => false,
+        }
+    }
+
+    /// Returns true for failures another provider may not have: timeouts, rate limits,
+    /// server and connection errors. Invalid requests are never retryable.
+    pub fn is_retryable(&self) -> bool {
+        if self.is_transient() {
+            return true;
+        }
+        match self {
+            ModelError::OpenAIApi(OpenAIError::Reqwest(_))
+            | ModelError::OpenAIApi(OpenAIError::StreamError(_)) => true,
+            ModelError::OpenAIApi(OpenAIError::ApiError(e)) => {
+                matches!(e.r#type.as_deref(), Some("server_error" | "timeout_error"))
+                    || e.code.as_deref() == Some("server_error")
+            }
+            ModelError::Bedrock(e) => match e.as_ref() {
+                BedrockError::TimeoutError(_) => true,
+                BedrockError::ValidationError(_) | BedrockError::AuthenticationError(_) => false,
+                e => is_retryable_message(&e.to_string()),
+            },
+            ModelError::Anthropic(AnthropicError::ClustError(e)) => {
+                is_retryable_message(&e.to_string())
+            }
+            ModelError::StreamError(message) => is_retryable_message(message),
+            _ => false,
+        }
+    }
+
+    /// Wait requested by the provider, read from the error message since the SDKs
+    /// do not expose the Retry-After header
+    pub fn retry_after(&self) -> Option<Duration> {
+        parse_retry_after(&self.to_string())
+    }
+}
+
+/// Matches rate limit and unavailability messages without a typed status
+fn is_transient_message(message: &str) -> bool {
+    const TRANSIENT: [&str; 6] = [
+        "rate limit",
+        "rate_limit",
+        "too many requests",
+        "throttl",
+        "overloaded",
+        "service unavailable",
+    ];
+
+    let message = message.to_lowercase();
+    TRANSIENT.iter().any(|pattern| message.contains(pattern))
+}
+
+/// Matches provider error messages without a typed status
+fn is_retryable_message(message: &str) -> bool {
+    const RETRYABLE: [&str; 5] = [
+        "timeout",
+        "timed out",
+        "internal server error",
+        "bad gateway",
+        "connection",
+    ];
+
+    let lowercase = message.to_lowercase();
+    is_transient_message(message) || RETRYABLE.iter().any(|pattern| lowercase.contains(pattern))
+}
+
+/// Reads hints like "try again in 1.5s", "retry after 200ms" or "retry-after: 3"
+fn parse_retry_after(message: &str) -> Option<Duration> {
+    const PREFIXES: [&str; 3] = ["try again in ", "retry after ", "retry-after: "];
+
+    let message = message.to_lowercase();
+    let rest = PREFIXES
+        .iter()
+        .find_map(|prefix| message.find(prefix).map(|i| &message[i + prefix.len()..]))?;
+    let number_len = rest
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(rest.len());
+    let value = rest[..number_len].parse::<f64>().ok()?;
+    let seconds = if rest[number_len..].trim_start().starts_with("ms") {
+        value / 1000.0
+    } else {
+        value
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
 impl From<BedrockError> for ModelError {
     fn from(value: BedrockError) -> Self {
         ModelError::Bedrock(Box::new(value))
@@ -118,3 +242,49 @@ pub enum BedrockError {
         >,
     ),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retryable_errors() {
+        assert!(ModelError::StreamError("Connection reset by peer".to_string()).is_retryable());
+        assert!(
+            ModelError::from(BedrockError::CustomError("ThrottlingException".to_string()))
+                .is_retryable()
+        );
+        assert!(!ModelError::from(BedrockError::ValidationError(
+            "Request timed out".to_string()
+        ))
+        .is_retryable());
+        assert!(!ModelError::StreamError("Invalid JSON in response".to_string()).is_retryable());
+        assert!(!ModelError::CredentialsError("openai".to_string()).is_retryable());
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(ModelError::StreamError("429 Too Many Requests".to_string()).is_transient());
+        assert!(ModelError::StreamError("Service Unavailable".to_string()).is_transient());
+        assert!(!ModelError::StreamError("Connection reset by peer".to_string()).is_transient());
+        assert!(!ModelError::from(BedrockError::ValidationError(
+            "Too many requests".to_string()
+        ))
+        .is_transient());
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let error =
+            ModelError::StreamError("Rate limit reached. Please try again in 1.5s.".to_string());
+        assert_eq!(error.retry_after(), Some(Duration::from_millis(1500)));
+        let error = ModelError::StreamError("Throttled, retry after 200ms".to_string());
+        assert_eq!(error.retry_after(), Some(Duration::from_millis(200)));
+        let error = ModelError::StreamError("Retry-After: 3".to_string());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(3)));
+        assert_eq!(
+            ModelError::StreamError("Overloaded".to_string()).retry_after(),
+            None
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::model::response_validation::{gemini_response_violations, ResponseValidationConfig};
 use crate::{error::GatewayError, GatewayResult};
 
 use super::types::{
@@ -20,6 +21,8 @@ pub struct Client {
     api_key: String,
     /// Internal HTTP client.
     client: reqwest::Client,
+    /// Validates raw generation responses before they are mapped.
+    response_validation: Option<ResponseValidationConfig>,
 }
 
 enum Method {
@@ -31,14 +34,21 @@ impl Client {
         Self {
             api_key,
             client: reqwest::Client::new(),
+            response_validation: None,
         }
     }
 
+    pub fn with_response_validation(mut self, config: Option<ResponseValidationConfig>) -> Self {
+        self.response_validation = config;
+        self
+    }
+
     async fn make_request<T: serde::de::DeserializeOwned, P: Serialize>(
         &self,
         path: &str,
         payload: Option<P>,
         method: Method,
+        validation: Option<&ResponseValidationConfig>,
     ) -> GatewayResult<T> {
         let url = format!("{API_URL}{path}?key={}", self.api_key);
 
@@ -74,6 +84,9 @@ impl Client {
         }
 
         let text = resp.text().await?;
+        if let Some(validation) = validation {
+            validation.check("gemini", &text, gemini_response_violations)?;
+        }
         let response = serde_json::from_str::<T>(&text).map_err(|e| {
             tracing::error!(target: "gemini", "Response deserialize failed. Response: {text}");
             GatewayError::CustomError(e.to_string())
@@ -91,7 +104,8 @@ impl Client {
     }
     pub async fn models(&self) -> GatewayResult<ModelsResponse> {
         let url = "".to_string();
-        self.make_request(&url, None::<Value>, Method::Get).await
+        self.make_request(&url, None::<Value>, Method::Get, None)
+            .await
     }
     pub async fn count_tokens(
         &self,
@@ -99,7 +113,8 @@ impl Client {
         payload: CountTokensRequest,
     ) -> GatewayResult<CountTokensResponse> {
         let url = format!("/{model_name}:countTokens");
-        self.make_request(&url, Some(&payload), Method::Post).await
+        self.make_request(&url, Some(&payload), Method::Post, None)
+            .await
     }
 
     pub async fn invoke(
@@ -111,8 +126,13 @@ impl Client {
         tracing::debug!(target: "gemini", "Invoking model: {model_name} on {invoke_url} with payload: {:?}", payload);
         let span = tracing::Span::current();
         span.record("request", serde_json::to_string(&payload)?);
-        self.make_request(&invoke_url, Some(&payload), Method::Post)
-            .await
+        self.make_request(
+            &invoke_url,
+            Some(&payload),
+            Method::Post,
+            self.response_validation.as_ref(),
+        )
+        .await
     }
 
     pub async fn stream(
@@ -132,51 +152,64 @@ impl Client {
         // Delegate the request to the EventSource.
         let event_source =
             EventSource::new(request).map_err(|e| GatewayError::CustomError(e.to_string()))?;
+        let validation = self.response_validation.clone();
 
         Ok(futures::stream::unfold(
             event_source,
-            |mut event_source| async {
-                match event_source.next().await {
-                    Some(Ok(reqwest_eventsource::Event::Message(msg))) => {
-                        let chunk = match serde_json::from_str::<GenerateContentResponse>(&msg.data)
-                        {
-                            Ok(chunk) => chunk,
-                            Err(e) => {
-                                tracing::error!(target: "gemini", "{e:?}");
-                                return Some((
-                                    Err(GatewayError::CustomError(e.to_string())),
-                                    event_source,
-                                ));
-                            }
-                        };
-                        Some((Ok(Some(chunk)), event_source))
-                    }
-                    Some(Ok(reqwest_eventsource::Event::Open)) => {
-                        tracing::debug!(target: "gemini", "CONNECTION OPENED");
-                        Some((Ok(None), event_source))
-                    }
-                    Some(Err(Error::StreamEnded)) => None,
-                    Some(Err(e)) => {
-                        let err_str = e.to_string();
-                        let err_str = match e {
-                            reqwest_eventsource::Error::InvalidStatusCode(_, r) => {
-                                let status = r.status();
-                                let error = r.text().await.unwrap_or(err_str);
-
-                                tracing::error!(target: "gemini", "Gemini error: {error}");
-
-                                if status == StatusCode::NOT_FOUND {
-                                    "Gemini model not found".to_string()
-                                } else {
-                                    error
+            move |mut event_source| {
+                let validation = validation.clone();
+                async move {
+                    match event_source.next().await {
+                        Some(Ok(reqwest_eventsource::Event::Message(msg))) => {
+                            if let Some(validation) = &validation {
+                                if let Err(e) = validation.check(
+                                    "gemini",
+                                    &msg.data,
+                                    gemini_response_violations,
+                                ) {
+                                    return Some((Err(e), event_source));
                                 }
                             }
-                            _ => err_str,
-                        };
+                            let chunk =
+                                match serde_json::from_str::<GenerateContentResponse>(&msg.data) {
+                                    Ok(chunk) => chunk,
+                                    Err(e) => {
+                                        tracing::error!(target: "gemini", "{e:?}");
+                                        return Some((
+                                            Err(GatewayError::CustomError(e.to_string())),
+                                            event_source,
+                                        ));
+                                    }
+                                };
+                            Some((Ok(Some(chunk)), event_source))
+                        }
+                        Some(Ok(reqwest_eventsource::Event::Open)) => {
+                            tracing::debug!(target: "gemini", "CONNECTION OPENED");
+                            Some((Ok(None), event_source))
+                        }
+                        Some(Err(Error::StreamEnded)) => None,
+                        Some(Err(e)) => {
+                            let err_str = e.to_string();
+                            let err_str = match e {
+                                reqwest_eventsource::Error::InvalidStatusCode(_, r) => {
+                                    let status = r.status();
+                                    let error = r.text().await.unwrap_or(err_str);
+
+                                    tracing::error!(target: "gemini", "Gemini error: {error}");
+
+                                    if status == StatusCode::NOT_FOUND {
+                                        "Gemini model not found".to_string()
+                                    } else {
+                                        error
+                                    }
+                                }
+                                _ => err_str,
+                            };
 
-                        Some((Err(GatewayError::CustomError(err_str)), event_source))
+                            Some((Err(GatewayError::CustomError(err_str)), event_source))
+                        }
+                        _ => None,
                     }
-                    _ => None,
                 }
             },
         ))
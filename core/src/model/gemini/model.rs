@@ -1,4 +1,4 @@
-use super::super::error::ModelError;
+use super::super::error::{ModelError, CONTENT_FILTER_ERROR};
 use super::super::types::{
     LLMContentEvent, LLMFinishEvent, LLMStartEvent, ModelEvent, ModelEventType, ModelFinishReason,
     ModelToolCall,
@@ -14,17 +14,21 @@ use crate::error::GatewayError;
 use crate::events::JsonValue;
 use crate::events::SPAN_GEMINI;
 use crate::events::{self, RecordResult};
+use crate::executor::chat_completion::backoff::ProviderRetries;
+use crate::llm_gateway::message_mapper::inline_image;
 use crate::model::error::AuthorizationError;
 use crate::model::gemini::types::{
-    FunctionDeclaration, GenerationConfig, PartWithThought, Role, Tools,
+    FunctionDeclaration, GeminiSafetySetting, GenerationConfig, PartWithThought, Role, ToolConfig,
+    Tools,
 };
-use crate::model::handler::handle_tool_call;
+use crate::model::handler::{handle_tool_call, tool_error_content};
 use crate::model::types::LLMFirstToken;
-use crate::model::{async_trait, CredentialsIdent, DEFAULT_MAX_RETRIES};
+use crate::model::{async_trait, CredentialsIdent};
 use crate::types::credentials::ApiKeyCredentials;
 use crate::types::engine::{ExecutionOptions, GeminiModelParams, Prompt};
 use crate::types::gateway::{
-    ChatCompletionContent, ChatCompletionMessage, CompletionModelUsage, ToolCall,
+    ChatCompletionContent, ChatCompletionMessage, CompletionModelUsage, PromptTokensDetails,
+    ToolCall,
 };
 use crate::types::message::{MessageType, PromptMessage};
 use crate::types::threads::{AudioFormat, InnerMessage, Message, MessageContentPartOptions};
@@ -94,7 +98,8 @@ impl GeminiModel {
         prompt: Prompt,
         tools: HashMap<String, Box<dyn Tool>>,
     ) -> Result<Self, ModelError> {
-        let client = gemini_client(credentials)?;
+        let client = gemini_client(credentials)?
+            .with_response_validation(execution_options.response_validation.clone());
         Ok(Self {
             params,
             execution_options,
@@ -112,24 +117,26 @@ impl GeminiModel {
         tools: &HashMap<String, Box<dyn Tool>>,
         tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
         tags: HashMap<String, String>,
-    ) -> Vec<PartWithThought> {
-        futures::future::join_all(function_calls.map(|(name, args)| {
+    ) -> GatewayResult<Vec<PartWithThought>> {
+        futures::future::try_join_all(function_calls.map(|(name, args)| {
             let tags = tags.clone();
             async move {
                 tracing::trace!("Calling tool  {name:?}");
                 let tool_call = Self::map_tool_call(&(name.to_string(), args.clone()));
-                let result = handle_tool_call(&tool_call, tools, tx, tags.clone()).await;
+                let result = handle_tool_call(&tool_call, tools, tx, tags.clone()).await?;
                 tracing::trace!("Result ({name}): {result:?}");
-                let content = result
-                    .map(|r| r.to_string())
-                    .unwrap_or_else(|err| err.to_string());
-                Part::Text(content).into()
+                let content = result.unwrap_or_else(|err| tool_error_content(&err));
+                Ok::<_, GatewayError>(Part::Text(content).into())
             }
         }))
         .await
     }
 
-    fn build_request(&self, messages: Vec<Content>) -> GatewayResult<GenerateContentRequest> {
+    fn build_request(
+        &self,
+        system_instruction: Option<Content>,
+        messages: Vec<Content>,
+    ) -> GatewayResult<GenerateContentRequest> {
         let model_params = &self.params;
         let response_schema = match &model_params.response_format {
             Some(ResponseFormat::JsonSchema { json_schema }) => {
@@ -193,10 +200,30 @@ impl GeminiModel {
             }])
         };
 
+        let after_tool_results = messages.last().is_some_and(|c| {
+            c.parts
+                .iter()
+                .any(|p| matches!(p.part, Part::FunctionResponse { .. }))
+        });
+        let tool_config = match &model_params.tool_choice {
+            Some(_) if tools.is_none() => None,
+            Some(choice) if after_tool_results => {
+                Some(ToolConfig::from(&choice.after_tool_results()))
+            }
+            Some(choice) => Some(ToolConfig::from(choice)),
+            None => None,
+        };
+
         let request = GenerateContentRequest {
             contents: messages,
+            system_instruction,
             generation_config: Some(config),
             tools,
+            tool_config,
+            safety_settings: model_params
+                .safety_settings
+                .as_ref()
+                .map(|settings| settings.iter().map(GeminiSafetySetting::from).collect()),
         };
 
         Ok(request)
@@ -237,6 +264,7 @@ impl GeminiModel {
                                                 &Span::current(),
                                                 ModelEventType::LlmContent(LLMContentEvent {
                                                     content: text.to_owned(),
+                                                    logprobs: None,
                                                 }),
                                             )))
                                             .await;
@@ -377,43 +405,35 @@ impl GeminiModel {
                 label=label
             );
 
+            // Every model call of a tool calling loop reports its usage
+            tx.send(Some(ModelEvent::new(
+                &span,
+                ModelEventType::LlmStop(LLMFinishEvent {
+                    provider_name: SPAN_GEMINI.to_string(),
+                    model_name: self.params.model.clone().unwrap_or_default(),
+                    output: Some(text.clone()),
+                    usage: Self::map_usage(response.usage_metadata.as_ref()),
+                    finish_reason: ModelFinishReason::ToolCalls,
+                    tool_calls: calls
+                        .iter()
+                        .map(|(tool_name, params)| {
+                            Ok(ModelToolCall {
+                                tool_id: tool_name.clone(),
+                                tool_name: tool_name.clone(),
+                                input: serde_json::to_string(params)?,
+                            })
+                        })
+                        .collect::<Result<Vec<ModelToolCall>, GatewayError>>()?,
+                    credentials_ident: self.credentials_ident.clone(),
+                    generation: None,
+                }),
+            )))
+            .await
+            .map_err(|e| GatewayError::CustomError(e.to_string()))?;
+
             let tool = self.tools.get(&calls[0].0);
             if let Some(tool) = tool {
                 if tool.stop_at_call() {
-                    let usage = response
-                        .usage_metadata
-                        .as_ref()
-                        .map(|u| CompletionModelUsage {
-                            input_tokens: u.prompt_token_count as u32,
-                            output_tokens: (u.total_token_count - u.prompt_token_count) as u32,
-                            total_tokens: u.total_token_count as u32,
-                            ..Default::default()
-                        });
-                    let finish_reason = ModelFinishReason::ToolCalls;
-                    tx.send(Some(ModelEvent::new(
-                        &span,
-                        ModelEventType::LlmStop(LLMFinishEvent {
-                            provider_name: SPAN_GEMINI.to_string(),
-                            model_name: self.params.model.clone().unwrap_or_default(),
-                            output: Some(text.clone()),
-                            usage,
-                            finish_reason,
-                            tool_calls: calls
-                                .iter()
-                                .map(|(tool_name, params)| {
-                                    Ok(ModelToolCall {
-                                        tool_id: tool_name.clone(),
-                                        tool_name: tool_name.clone(),
-                                        input: serde_json::to_string(params)?,
-                                    })
-                                })
-                                .collect::<Result<Vec<ModelToolCall>, GatewayError>>()?,
-                            credentials_ident: self.credentials_ident.clone(),
-                        }),
-                    )))
-                    .await
-                    .map_err(|e| GatewayError::CustomError(e.to_string()))?;
-
                     return Ok(InnerExecutionResult::Finish(ChatCompletionMessage {
                         role: "assistant".to_string(),
                         content: if text.is_empty() {
@@ -446,7 +466,7 @@ impl GeminiModel {
             let tool_call_parts =
                 Self::handle_tool_calls(calls.iter(), &self.tools, tx, tags.clone())
                     .instrument(tools_span.clone())
-                    .await;
+                    .await?;
             let tools_messages = vec![Content {
                 role: Role::User,
                 parts: tool_call_parts,
@@ -459,15 +479,7 @@ impl GeminiModel {
 
         match finish_reason {
             Some(FinishReason::Stop) => {
-                let usage = response
-                    .usage_metadata
-                    .as_ref()
-                    .map(|u| CompletionModelUsage {
-                        input_tokens: u.prompt_token_count as u32,
-                        output_tokens: (u.total_token_count - u.prompt_token_count) as u32,
-                        total_tokens: u.total_token_count as u32,
-                        ..Default::default()
-                    });
+                let usage = Self::map_usage(response.usage_metadata.as_ref());
 
                 tx.send(Some(ModelEvent::new(
                     &span,
@@ -484,6 +496,7 @@ impl GeminiModel {
                         finish_reason: ModelFinishReason::Stop,
                         tool_calls: vec![],
                         credentials_ident: self.credentials_ident.clone(),
+                        generation: None,
                     }),
                 )))
                 .await
@@ -505,19 +518,18 @@ impl GeminiModel {
 
     async fn execute(
         &self,
+        system_instruction: Option<Content>,
         input_messages: Vec<Content>,
         tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
         tags: HashMap<String, String>,
     ) -> GatewayResult<ChatCompletionMessage> {
         let mut gemini_calls = vec![input_messages];
-        let mut retries_left = self
-            .execution_options
-            .max_retries
-            .unwrap_or(DEFAULT_MAX_RETRIES);
+        let mut retries = ProviderRetries::new(&self.execution_options);
         while let Some(call) = gemini_calls.pop() {
-            let span = create_model_span!(SPAN_GEMINI, target!("chat"), &tags, retries_left);
+            let span =
+                create_model_span!(SPAN_GEMINI, target!("chat"), &tags, retries.retries_left());
 
-            let request = self.build_request(call.clone())?;
+            let request = self.build_request(system_instruction.clone(), call.clone())?;
 
             span.record("input", serde_json::to_string(&request)?);
             span.record("request", serde_json::to_string(&request)?);
@@ -534,12 +546,10 @@ impl GeminiModel {
                 }
                 Err(e) => {
                     span.record("error", e.to_string());
-                    if retries_left == 0 {
+                    if !retries.retry(&e, &span, tx).await {
                         return Err(e);
-                    } else {
-                        gemini_calls.push(call);
                     }
-                    retries_left -= 1;
+                    gemini_calls.push(call);
                 }
             }
         }
@@ -552,6 +562,9 @@ impl GeminiModel {
                 "the maximum number of tokens specified in the request was reached".to_string(),
             )
             .into(),
+            Some(FinishReason::Safety) => {
+                ModelError::FinishError(CONTENT_FILTER_ERROR.to_string()).into()
+            }
             x => ModelError::FinishError(format!("{x:?}")).into(),
         }
     }
@@ -580,6 +593,9 @@ impl GeminiModel {
             input_tokens: u.prompt_token_count as u32,
             output_tokens: (u.total_token_count - u.prompt_token_count) as u32,
             total_tokens: u.total_token_count as u32,
+            prompt_tokens_details: u
+                .cached_content_token_count
+                .map(|cached| PromptTokensDetails::new(Some(cached as u32), None, None)),
             ..Default::default()
         })
     }
@@ -644,6 +660,7 @@ impl GeminiModel {
                 finish_reason: trace_finish_reason.clone(),
                 tool_calls: tool_calls.iter().map(Self::map_tool_call).collect(),
                 credentials_ident: self.credentials_ident.clone(),
+                generation: None,
             }),
         )))
         .await
@@ -698,7 +715,7 @@ impl GeminiModel {
             let tool_call_parts =
                 Self::handle_tool_calls(tool_calls.iter(), &self.tools, &tx, tags.clone())
                     .instrument(tools_span.clone())
-                    .await;
+                    .await?;
             let tools_messages = vec![Content {
                 role: Role::User,
                 parts: tool_call_parts,
@@ -719,20 +736,19 @@ impl GeminiModel {
 
     async fn execute_stream(
         &self,
+        system_instruction: Option<Content>,
         input_messages: Vec<Content>,
         tx: tokio::sync::mpsc::Sender<Option<ModelEvent>>,
         tags: HashMap<String, String>,
     ) -> GatewayResult<()> {
         let mut gemini_calls = vec![input_messages];
 
-        let mut retries_left = self
-            .execution_options
-            .max_retries
-            .unwrap_or(DEFAULT_MAX_RETRIES);
+        let mut retries = ProviderRetries::new(&self.execution_options);
         while let Some(call) = gemini_calls.pop() {
-            let span = create_model_span!(SPAN_GEMINI, target!("chat"), &tags, retries_left);
+            let span =
+                create_model_span!(SPAN_GEMINI, target!("chat"), &tags, retries.retries_left());
 
-            let request = self.build_request(call.clone())?;
+            let request = self.build_request(system_instruction.clone(), call.clone())?;
 
             span.record("input", serde_json::to_string(&request)?);
             span.record("request", serde_json::to_string(&request)?);
@@ -749,12 +765,10 @@ impl GeminiModel {
                 }
                 Err(e) => {
                     span.record("error", e.to_string());
-                    if retries_left == 0 {
+                    if !retries.retry(&e, &span, tx).await {
                         return Err(e);
-                    } else {
-                        gemini_calls.push(call);
                     }
-                    retries_left -= 1;
+                    gemini_calls.push(call);
                 }
             }
         }
@@ -770,9 +784,8 @@ impl GeminiModel {
         for m in messages_dto.iter() {
             let request_message = {
                 match m.r#type {
-                    MessageType::SystemMessage => {
-                        Some(Content::user(m.content.clone().unwrap_or_default()))
-                    }
+                    // Sent as the system instruction
+                    MessageType::SystemMessage => None,
 
                     MessageType::AIMessage => {
                         if let Some(tool_calls) = &m.tool_calls {
@@ -805,7 +818,7 @@ impl GeminiModel {
                             }
                         }
                     }
-                    MessageType::HumanMessage => Some(construct_user_message(&m.clone().into())),
+                    MessageType::HumanMessage => Some(construct_user_message(&m.clone().into())?),
                     MessageType::ToolResult => {
                         tool_results_remaining -= 1;
                         let content =
@@ -814,12 +827,7 @@ impl GeminiModel {
                             Part::FunctionResponse {
                                 name: m.tool_call_id.clone().unwrap_or_default(),
                                 response: Some(PartFunctionResponse {
-                                    fields: HashMap::from([(
-                                        "content
-                                        "
-                                        .to_string(),
-                                        content,
-                                    )]),
+                                    fields: HashMap::from([("content".to_string(), content)]),
                                 }),
                             }
                             .into(),
@@ -854,9 +862,10 @@ impl ModelInstance for GeminiModel {
         previous_messages: Vec<Message>,
         tags: HashMap<String, String>,
     ) -> GatewayResult<ChatCompletionMessage> {
-        let conversational_messages =
+        let (system_instruction, conversational_messages) =
             self.construct_messages(input_variables, previous_messages)?;
-        self.execute(conversational_messages, &tx, tags).await
+        self.execute(system_instruction, conversational_messages, &tx, tags)
+            .await
     }
 
     async fn stream(
@@ -866,28 +875,37 @@ impl ModelInstance for GeminiModel {
         previous_messages: Vec<Message>,
         tags: HashMap<String, String>,
     ) -> GatewayResult<()> {
-        let conversational_messages =
+        let (system_instruction, conversational_messages) =
             self.construct_messages(input_variables, previous_messages)?;
-        self.execute_stream(conversational_messages, tx, tags).await
+        self.execute_stream(system_instruction, conversational_messages, tx, tags)
+            .await
     }
 }
 
 impl GeminiModel {
+    /// Returns the system instruction and the conversation contents
     fn construct_messages(
         &self,
         input_variables: HashMap<String, Value>,
         previous_messages: Vec<Message>,
-    ) -> GatewayResult<Vec<Content>> {
+    ) -> GatewayResult<(Option<Content>, Vec<Content>)> {
         let mut conversational_messages = vec![];
-        let system_message = self
+        let system_instruction = self
             .prompt
             .messages
             .iter()
-            .find(|m| m.r#type == MessageType::SystemMessage)
-            .map(|message| map_chat_messages(message.to_owned(), &input_variables));
-        if let Some(system_message) = system_message {
-            conversational_messages.push(system_message?);
-        }
+            .filter(|m| m.r#type == MessageType::SystemMessage)
+            .map(|m| Prompt::render(m.msg.clone(), &input_variables))
+            .chain(
+                previous_messages
+                    .iter()
+                    .filter(|m| m.r#type == MessageType::SystemMessage)
+                    .filter_map(|m| m.content.clone()),
+            )
+            .collect::<Vec<_>>();
+        let system_instruction = (!system_instruction.is_empty())
+            .then(|| Content::user(system_instruction.join("\n\n")));
+
         let previous_messages = Self::map_previous_messages(previous_messages)?;
         conversational_messages.extend(previous_messages);
         let human_message = self
@@ -900,7 +918,7 @@ impl GeminiModel {
             conversational_messages.push(human_message?);
         }
 
-        Ok(conversational_messages)
+        Ok((system_instruction, conversational_messages))
     }
 }
 
@@ -927,7 +945,7 @@ fn map_chat_messages(
             } else {
                 InnerMessage::Text(Prompt::render(msg.clone(), variables))
             };
-            construct_user_message(&inner_message)
+            construct_user_message(&inner_message)?
         }
         MessageType::ToolResult => {
             todo!()
@@ -936,8 +954,8 @@ fn map_chat_messages(
     Ok(message)
 }
 
-fn construct_user_message(m: &InnerMessage) -> Content {
-    match m {
+fn construct_user_message(m: &InnerMessage) -> Result<Content, ModelError> {
+    Ok(match m {
         crate::types::threads::InnerMessage::Text(text) => Content::user(text.to_string()),
         crate::types::threads::InnerMessage::Array(content_array) => {
             let mut parts = vec![];
@@ -945,13 +963,10 @@ fn construct_user_message(m: &InnerMessage) -> Content {
                 let msg: Part = match m.r#type {
                     crate::types::threads::MessageContentType::Text => Part::Text(m.value.clone()),
                     crate::types::threads::MessageContentType::ImageUrl => {
-                        let url = m.value.clone();
-                        let base64_data = url
-                            .split_once(',')
-                            .map_or_else(|| url.as_str(), |(_, data)| data);
+                        let (media_type, data) = inline_image(&m.value)?;
                         Part::InlineData {
-                            mime_type: "image/png".to_string(),
-                            data: base64_data.to_string(),
+                            mime_type: media_type.to_string(),
+                            data: data.to_string(),
                         }
                     }
                     crate::types::threads::MessageContentType::InputAudio => {
@@ -977,7 +992,7 @@ fn construct_user_message(m: &InnerMessage) -> Content {
                 parts,
             }
         }
-    }
+    })
 }
 
 pub fn record_map_err(e: impl Into<GatewayError> + ToString, span: tracing::Span) -> GatewayError {
@@ -1173,3 +1188,66 @@ fn normalize_nullable_types(schema: Value) -> Value {
     normalize(&mut result);
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::gateway::FunctionCall;
+    use crate::types::threads::MessageContentType;
+
+    fn message(r#type: MessageType, content: &str, tool_call_id: Option<&str>) -> Message {
+        Message {
+            model_name: "gemini-1.5-pro".to_string(),
+            thread_id: None,
+            user_id: String::new(),
+            content_type: MessageContentType::Text,
+            content: Some(content.to_string()),
+            content_array: vec![],
+            r#type,
+            tool_call_id: tool_call_id.map(String::from),
+            tool_calls: None,
+        }
+    }
+
+    #[test]
+    fn test_system_messages_are_not_contents() {
+        let contents = GeminiModel::map_previous_messages(vec![
+            message(MessageType::SystemMessage, "Be brief", None),
+            message(MessageType::HumanMessage, "Hi", None),
+        ])
+        .unwrap();
+
+        assert_eq!(contents.len(), 1);
+        assert_eq!(contents[0].parts[0].part, Part::Text("Hi".to_string()));
+    }
+
+    #[test]
+    fn test_tool_result_is_function_response() {
+        let mut call = message(MessageType::AIMessage, "", None);
+        call.tool_calls = Some(vec![ToolCall {
+            index: Some(0),
+            id: "get_weather".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: "{\"city\":\"Paris\"}".to_string(),
+            },
+        }]);
+        let contents = GeminiModel::map_previous_messages(vec![
+            call,
+            message(MessageType::ToolResult, "18C", Some("get_weather")),
+        ])
+        .unwrap();
+
+        assert_eq!(contents.len(), 2);
+        assert_eq!(
+            contents[1].parts[0].part,
+            Part::FunctionResponse {
+                name: "get_weather".to_string(),
+                response: Some(PartFunctionResponse {
+                    fields: HashMap::from([("content".to_string(), Value::from("18C"))]),
+                }),
+            }
+        );
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
 use crate::types::gateway::FunctionParameters as FP;
+use crate::types::gateway::{
+    SafetyCategory, SafetySetting, SafetyThreshold, ToolChoice, ToolChoiceMode,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -17,8 +20,86 @@ pub struct CountTokensResponse {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GenerateContentRequest {
     pub contents: Vec<Content>,
+    /// System messages, which Gemini does not accept among the contents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<Content>,
     pub generation_config: Option<GenerationConfig>,
     pub tools: Option<Vec<Tools>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<ToolConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safety_settings: Option<Vec<GeminiSafetySetting>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GeminiSafetySetting {
+    pub category: String,
+    pub threshold: String,
+}
+
+impl From<&SafetySetting> for GeminiSafetySetting {
+    fn from(setting: &SafetySetting) -> Self {
+        let category = match setting.category {
+            SafetyCategory::Harassment => "HARM_CATEGORY_HARASSMENT",
+            SafetyCategory::HateSpeech => "HARM_CATEGORY_HATE_SPEECH",
+            SafetyCategory::SexuallyExplicit => "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+            SafetyCategory::DangerousContent => "HARM_CATEGORY_DANGEROUS_CONTENT",
+            SafetyCategory::CivicIntegrity => "HARM_CATEGORY_CIVIC_INTEGRITY",
+        };
+        let threshold = match setting.threshold {
+            SafetyThreshold::BlockNone => "BLOCK_NONE",
+            SafetyThreshold::BlockOnlyHigh => "BLOCK_ONLY_HIGH",
+            SafetyThreshold::BlockMediumAndAbove => "BLOCK_MEDIUM_AND_ABOVE",
+            SafetyThreshold::BlockLowAndAbove => "BLOCK_LOW_AND_ABOVE",
+            SafetyThreshold::Off => "OFF",
+        };
+
+        Self {
+            category: category.to_string(),
+            threshold: threshold.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ToolConfig {
+    pub function_calling_config: FunctionCallingConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FunctionCallingConfig {
+    pub mode: FunctionCallingMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_function_names: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FunctionCallingMode {
+    Auto,
+    Any,
+    None,
+}
+
+impl From<&ToolChoice> for ToolConfig {
+    fn from(choice: &ToolChoice) -> Self {
+        let (mode, allowed_function_names) = match choice {
+            ToolChoice::Mode(ToolChoiceMode::None) => (FunctionCallingMode::None, None),
+            ToolChoice::Mode(ToolChoiceMode::Auto) => (FunctionCallingMode::Auto, None),
+            ToolChoice::Mode(ToolChoiceMode::Required) => (FunctionCallingMode::Any, None),
+            ToolChoice::Function(choice) => (
+                FunctionCallingMode::Any,
+                Some(vec![choice.function.name.clone()]),
+            ),
+        };
+
+        Self {
+            function_calling_config: FunctionCallingConfig {
+                mode,
+                allowed_function_names,
+            },
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -181,6 +262,8 @@ pub struct UsageMetadata {
     pub candidates_token_count: Option<i32>,
     pub prompt_token_count: i32,
     pub total_token_count: i32,
+    /// Part of the prompt served from a cached content, included in `prompt_token_count`
+    pub cached_content_token_count: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
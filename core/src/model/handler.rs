@@ -7,6 +7,7 @@ use crate::{
 };
 
 use super::{
+    error::ModelError,
     types::{ModelEvent, ModelEventType, ModelToolCall, ToolResultEvent, ToolStartEvent},
     Tool,
 };
@@ -40,15 +41,24 @@ impl Injector for LlmToolCallCarrier<'_> {
     }
 }
 
+/// Tool message content of a failed tool call, so the model can tell it from a result
+pub(crate) fn tool_error_content(error: &GatewayError) -> String {
+    serde_json::json!({ "error": error.to_string() }).to_string()
+}
+
+/// Runs a tool call of the model. A malformed call, with an unknown tool or arguments
+/// that are not a JSON object, fails the request. An error raised by the tool itself is
+/// returned in the inner result, so it can be sent back to the model to recover from.
 pub(crate) async fn handle_tool_call(
     tool_use: &ModelToolCall,
     tools: &HashMap<String, Box<dyn Tool>>,
     tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
     mut tags: HashMap<String, String>,
-) -> GatewayResult<String> {
+) -> GatewayResult<GatewayResult<String>> {
     let tool_name = tool_use.tool_name.clone();
     let arguments = tool_use.input.clone();
-    let arguments_value = serde_json::from_str::<HashMap<String, Value>>(&arguments)?;
+    let arguments_value = serde_json::from_str::<HashMap<String, Value>>(&arguments)
+        .map_err(|e| ModelError::InvalidToolArguments(tool_name.clone(), e.to_string()))?;
     // let span = tracing::info_span!(
     //     target: target!("tool"),
     //     crate::events::SPAN_TOOL,
@@ -59,9 +69,7 @@ pub(crate) async fn handle_tool_call(
     // );
     let tool = tools
         .get(&tool_name)
-        .ok_or(GatewayError::CustomError(format!(
-            "Tool Not Found {tool_name}"
-        )))?;
+        .ok_or_else(|| ModelError::ToolNotFoundError(tool_name.clone()))?;
 
     async {
         tx.send(Some(ModelEvent::new(
@@ -85,7 +93,7 @@ pub(crate) async fn handle_tool_call(
         tx.send(Some(ModelEvent::new(
             &Span::current(),
             ModelEventType::ToolResult(ToolResultEvent {
-                tool_id: tool_name.clone(),
+                tool_id: tool_use.tool_id.clone(),
                 tool_name,
                 is_error: result.is_err(),
                 output: result
@@ -96,7 +104,7 @@ pub(crate) async fn handle_tool_call(
         )))
         .await
         .map_err(|e| GatewayError::CustomError(e.to_string()))?;
-        result
+        Ok(result)
     }
     // .instrument(span.or_current())
     .await
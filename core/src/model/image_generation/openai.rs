@@ -4,6 +4,7 @@ use crate::events::SPAN_OPENAI;
 use crate::model::error::ModelError;
 use async_openai::config::Config;
 use async_openai::{config::OpenAIConfig, Client};
+use reqwest::multipart::{Form, Part};
 
 use crate::model::types::ModelEventType;
 use crate::{
@@ -15,7 +16,10 @@ use crate::{
     },
     types::{
         credentials::ApiKeyCredentials,
-        gateway::{CreateImageRequest, ImageQuality, ImageResponseFormat, ImageSize, ImageStyle},
+        gateway::{
+            CreateImageRequest, FileUpload, ImageQuality, ImageResponseFormat, ImageSize,
+            ImageSource, ImageStyle,
+        },
         image::ImagesResponse,
     },
     GatewayResult,
@@ -107,6 +111,54 @@ impl OpenAIImageGeneration {
         })
     }
 
+    /// Builds the multipart body of an edit or variation request and returns it with the
+    /// endpoint path
+    fn image_form(
+        &self,
+        request: &CreateImageRequest,
+        source: &ImageSource,
+    ) -> GatewayResult<(&'static str, Form)> {
+        let mut form = Form::new().text("model", request.model.clone());
+        let (path, image) = match source {
+            ImageSource::Edit { image, mask } => {
+                form = form.text("prompt", request.prompt.clone());
+                if let Some(mask) = mask {
+                    form = form.part("mask", Self::png_part(mask)?);
+                }
+                ("edits", image)
+            }
+            ImageSource::Variation { image } => ("variations", image),
+        };
+        form = form.part("image", Self::png_part(image)?);
+
+        if let Some(n) = request.n {
+            form = form.text("n", n.to_string());
+        }
+        if let Some(size) = &request.size {
+            form = form.text("size", size.to_string());
+        }
+        if let Some(response_format) = &request.response_format {
+            form = form.text(
+                "response_format",
+                match response_format {
+                    ImageResponseFormat::Url => "url",
+                    ImageResponseFormat::B64Json => "b64_json",
+                },
+            );
+        }
+        if let Some(user) = &request.user {
+            form = form.text("user", user.clone());
+        }
+
+        Ok((path, form))
+    }
+
+    fn png_part(upload: &FileUpload) -> GatewayResult<Part> {
+        Ok(Part::bytes(upload.data.to_vec())
+            .file_name(upload.file_name.clone())
+            .mime_str("image/png")?)
+    }
+
     fn map_quality(
         &self,
         quality: Option<&ImageQuality>,
@@ -162,10 +214,19 @@ impl ImageGenerationModelInstance for OpenAIImageGeneration {
         let api_key: String = self.client.config().api_key().expose_secret().to_string();
 
         let reqwest_client = reqwest::Client::new();
-        let reqwest_result = reqwest_client
-            .post(format!("{api_base}/images/generations"))
+        let builder = match &request.source {
+            None => reqwest_client
+                .post(format!("{api_base}/images/generations"))
+                .json(&r),
+            Some(source) => {
+                let (path, form) = self.image_form(request, source)?;
+                reqwest_client
+                    .post(format!("{api_base}/images/{path}"))
+                    .multipart(form)
+            }
+        };
+        let reqwest_result = builder
             .header("Authorization", format!("Bearer {api_key}"))
-            .json(&r)
             .send()
             .await?;
 
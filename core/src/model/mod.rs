@@ -1,12 +1,15 @@
+use crate::error::GatewayError;
 use crate::events::{JsonValue, RecordResult, SPAN_MODEL_CALL};
 use crate::executor::context::ExecutorContext;
+use crate::executor::prometheus::LLM_ERROR_EVENT;
 use crate::model::bedrock::BedrockModel;
 use crate::model::cached::CachedModel;
 use crate::model::error::ModelError;
 use crate::types::engine::{CompletionEngineParams, CompletionModelParams};
 use crate::types::engine::{CompletionModelDefinition, ModelTools, ModelType};
 use crate::types::gateway::{
-    ChatCompletionContent, ChatCompletionMessage, ContentType, Extra, GuardOrName,
+    ChatCompletionContent, ChatCompletionMessage, CompletionModelUsage, ContentType,
+    CostCalculationResult, CostCalculator, CostCalculatorError, Extra, GuardOrName,
     GuardWithParameters, Usage,
 };
 use crate::types::guardrails::service::GuardrailsEvaluator;
@@ -17,16 +20,18 @@ use anthropic::AnthropicModel;
 use async_openai::config::OpenAIConfig;
 use async_openai::Client;
 use async_trait::async_trait;
+use cost_accrual::CostAccrual;
 use futures::future::join;
 use gemini::GeminiModel;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::fmt::Display;
+use token_estimate::TokenizerFamily;
 use tokio::sync::mpsc::{self, channel};
 use tools::Tool;
 use tracing::{info_span, Instrument};
-use types::{ModelEvent, ModelEventType};
+use types::{CustomEvent, GenerationMetrics, LLMFinishEvent, ModelEvent, ModelEventType};
 use valuable::Valuable;
 pub mod handler;
 use self::openai::OpenAIModel;
@@ -34,6 +39,7 @@ use crate::model::proxy::OpenAISpecModel;
 pub mod anthropic;
 pub mod bedrock;
 pub mod cached;
+pub mod cost_accrual;
 pub mod error;
 pub mod gemini;
 pub mod image_generation;
@@ -42,7 +48,11 @@ pub mod mcp_server;
 pub mod openai;
 pub mod openai_spec_client;
 pub mod proxy;
+pub mod response_validation;
+pub mod token_estimate;
+pub mod token_timing;
 pub mod tools;
+pub mod transcription;
 pub mod types;
 
 #[async_trait]
@@ -83,6 +93,37 @@ impl Display for ResponseCacheState {
     }
 }
 
+/// Cost of a finished completion. Completions replayed from the gateway response cache
+/// never reach the provider and cost nothing, provider prompt caching is priced by the
+/// cost calculator.
+async fn completion_cost(
+    cost_calculator: &dyn CostCalculator,
+    cache_hit: bool,
+    model_name: &str,
+    provider_name: &str,
+    usage: &CompletionModelUsage,
+) -> Result<CostCalculationResult, CostCalculatorError> {
+    if cache_hit {
+        return Ok(CostCalculationResult {
+            cost: 0.0,
+            per_input_token: 0.0,
+            per_cached_input_token: None,
+            per_cached_input_write_token: None,
+            per_output_token: 0.0,
+            per_image_cost: None,
+            is_cache_used: true,
+        });
+    }
+
+    cost_calculator
+        .calculate_cost(
+            model_name,
+            provider_name,
+            &Usage::CompletionModelUsage(usage.clone()),
+        )
+        .await
+}
+
 pub struct TracedModel<Inner: ModelInstance> {
     inner: Inner,
     definition: CompletionModelDefinition,
@@ -353,6 +394,22 @@ impl<Inner: ModelInstance> TracedModel<Inner> {
         let str = serde_json::to_string(&json!(input_vars))?;
         Ok(str)
     }
+
+    /// Sent instead of the finish event when the call fails
+    fn error_event(&self, span: &tracing::Span, error: &GatewayError) -> ModelEvent {
+        ModelEvent::new(
+            span,
+            ModelEventType::Custom(CustomEvent::new(
+                LLM_ERROR_EVENT.to_string(),
+                json!({
+                    "provider_name": self.definition.db_model.provider_name,
+                    "model_name": self.definition.db_model.name,
+                    "error": error.to_string(),
+                }),
+            )),
+        )
+        .with_request_id(self.executor_context.request_id.clone())
+    }
 }
 
 #[async_trait]
@@ -389,13 +446,19 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
             cost = tracing::field::Empty,
             usage = tracing::field::Empty,
             ttft = tracing::field::Empty,
+            latency = tracing::field::Empty,
+            tps = tracing::field::Empty,
             tags = JsonValue(&serde_json::to_value(tags.clone())?).as_value(),
-            cache = tracing::field::Empty
+            cache = tracing::field::Empty,
+            request_id = tracing::field::Empty
         );
 
         if let Some(state) = &self.response_cache_state {
             span.record("cache", state.to_string());
         }
+        if let Some(request_id) = &self.executor_context.request_id {
+            span.record("request_id", request_id);
+        }
 
         apply_guardrails(
             &self.initial_messages,
@@ -408,30 +471,73 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
         .await?;
 
         let cost_calculator = self.executor_context.cost_calculator.clone();
+        let cache_hit = matches!(self.response_cache_state, Some(ResponseCacheState::Hit));
+        let size_metrics = self.executor_context.size_metrics.clone();
+        let token_reservation = self.executor_context.token_reservation.clone();
+        let spend_budget = self.executor_context.spend_budget.clone();
+        let inference_model_name = self.definition.db_model.name.clone();
+        let request_id = self.executor_context.request_id.clone();
+        let error_tx = outer_tx.clone();
         tokio::spawn(
             async move {
                 let mut start_time = None;
-                while let Some(Some(msg)) = rx.recv().await {
+                let mut first_token_time = None;
+                while let Some(Some(mut msg)) = rx.recv().await {
+                    if msg.request_id.is_none() {
+                        msg.request_id = request_id.clone();
+                    }
+                    let mut generation = None;
                     match &msg.event {
-                        ModelEventType::LlmStart(_) => {
+                        ModelEventType::LlmStart(event) => {
                             start_time = Some(msg.timestamp.timestamp_micros() as u64);
+                            first_token_time = None;
+                            if let Some(metrics) = &size_metrics {
+                                metrics.record_request(
+                                    &provider_name,
+                                    &inference_model_name,
+                                    event.input.len(),
+                                );
+                            }
                         }
                         ModelEventType::LlmStop(llmfinish_event) => {
                             let current_span = tracing::Span::current();
+                            if let Some(metrics) = &size_metrics {
+                                metrics.record_response(
+                                    &provider_name,
+                                    &inference_model_name,
+                                    llmfinish_event.output.as_ref().map_or(0, |o| o.len()),
+                                );
+                            }
+                            generation = start_time.map(|start| {
+                                finish_generation(
+                                    &current_span,
+                                    start,
+                                    first_token_time,
+                                    msg.timestamp.timestamp_micros() as u64,
+                                    llmfinish_event,
+                                )
+                            });
                             if let Some(output) = &llmfinish_event.output {
                                 current_span
                                     .record("output", serde_json::to_string(output).unwrap());
                             }
                             if let Some(u) = &llmfinish_event.usage {
-                                match cost_calculator
-                                    .calculate_cost(
-                                        &model_name,
-                                        &provider_name,
-                                        &Usage::CompletionModelUsage(u.clone()),
-                                    )
-                                    .await
+                                if let Some(reservation) = &token_reservation {
+                                    reservation.record(u.total_tokens);
+                                }
+                                match completion_cost(
+                                    cost_calculator.as_ref().as_ref(),
+                                    cache_hit,
+                                    &model_name,
+                                    &provider_name,
+                                    u,
+                                )
+                                .await
                                 {
                                     Ok(c) => {
+                                        if let Some(account) = &spend_budget {
+                                            account.debit(c.cost).await;
+                                        }
                                         current_span
                                             .record("cost", serde_json::to_string(&c).unwrap());
                                     }
@@ -448,6 +554,7 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
                             }
                         }
                         ModelEventType::LlmFirstToken(_) => {
+                            first_token_time = Some(msg.timestamp.timestamp_micros() as u64);
                             if let Some(start_time) = start_time {
                                 let current_span = tracing::Span::current();
                                 current_span.record(
@@ -458,6 +565,9 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
                         }
                         _ => (),
                     }
+                    if let ModelEventType::LlmStop(llmfinish_event) = &mut msg.event {
+                        llmfinish_event.generation = generation;
+                    }
 
                     tracing::debug!(
                         "{} Received Model Event: {:?}",
@@ -475,6 +585,11 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
                 .inner
                 .invoke(input_vars, tx, previous_messages, tags)
                 .await;
+            if let Err(e) = &result {
+                let _ = error_tx
+                    .send(Some(self.error_event(&tracing::Span::current(), e)))
+                    .await;
+            }
             let _ = result
                 .as_ref()
                 .map(|r| match r.content.as_ref() {
@@ -494,8 +609,9 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
                 })
                 .record();
 
-            if let Ok(message) = &result {
-                apply_guardrails(
+            let mut result = result;
+            if let Ok(message) = &mut result {
+                let repaired = apply_guardrails(
                     &[message.clone()],
                     self.extra.as_ref(),
                     self.executor_context.evaluator_service.as_ref().as_ref(),
@@ -504,6 +620,10 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
                 )
                 .instrument(span.clone())
                 .await?;
+
+                if let Some(repaired) = repaired {
+                    message.content = Some(ChatCompletionContent::Text(repaired));
+                }
             }
 
             result
@@ -529,6 +649,11 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
         let model_name = self.definition.name.clone();
         let provider_name = self.definition.db_model.provider_name.clone();
         let cost_calculator = self.executor_context.cost_calculator.clone();
+        let cache_hit = matches!(self.response_cache_state, Some(ResponseCacheState::Hit));
+        let size_metrics = self.executor_context.size_metrics.clone();
+        let token_reservation = self.executor_context.token_reservation.clone();
+        let spend_budget = self.executor_context.spend_budget.clone();
+        let inference_model_name = self.definition.db_model.name.clone();
 
         let span = info_span!(
             target: "langdb::user_tracing::models",
@@ -546,12 +671,18 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
             usage = tracing::field::Empty,
             tags = JsonValue(&serde_json::to_value(tags.clone())?).as_value(),
             ttft = tracing::field::Empty,
-            cache = tracing::field::Empty
+            latency = tracing::field::Empty,
+            tps = tracing::field::Empty,
+            cache = tracing::field::Empty,
+            request_id = tracing::field::Empty
         );
 
         if let Some(state) = &self.response_cache_state {
             span.record("cache", state.to_string());
         }
+        if let Some(request_id) = &self.executor_context.request_id {
+            span.record("request_id", request_id);
+        }
 
         apply_guardrails(
             &self.initial_messages,
@@ -567,19 +698,99 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
             let (tx, mut rx) = channel(outer_tx.max_capacity());
             let mut output = String::new();
             let mut start_time = None;
+            let mut first_token_time = None;
+            let mut streamed_bytes = 0;
+            let mut streamed_chunks = 0;
+            let mut token_timings = self
+                .executor_context
+                .token_timing
+                .as_ref()
+                .and_then(|c| c.sample(&mut rand::rng()));
+            let mut cost_accrual = self
+                .executor_context
+                .cost_accrual
+                .as_ref()
+                .map(CostAccrual::new);
             let result = join(
                 self.inner
                     .stream(input_vars, tx, previous_messages, tags.clone()),
                 async {
-                    while let Some(Some(msg)) = rx.recv().await {
+                    while let Some(Some(mut msg)) = rx.recv().await {
+                        if msg.request_id.is_none() {
+                            msg.request_id = self.executor_context.request_id.clone();
+                        }
+                        let mut generation = None;
                         match &msg.event {
-                            ModelEventType::LlmStart(_event) => {
+                            ModelEventType::LlmStart(event) => {
                                 start_time = Some(msg.timestamp.timestamp_micros() as u64);
+                                first_token_time = None;
+                                streamed_bytes = 0;
+                                streamed_chunks = 0;
+                                if let Some(timings) = token_timings.as_mut() {
+                                    timings.start(msg.timestamp);
+                                }
+                                if let Some(accrual) = cost_accrual.as_mut() {
+                                    accrual.start(
+                                        &event.input,
+                                        TokenizerFamily::for_model(
+                                            &event.provider_name,
+                                            &event.model_name,
+                                        ),
+                                    );
+                                }
+                                if let Some(metrics) = &size_metrics {
+                                    metrics.record_request(
+                                        &provider_name,
+                                        &inference_model_name,
+                                        event.input.len(),
+                                    );
+                                }
                             }
                             ModelEventType::LlmContent(event) => {
                                 output.push_str(event.content.as_str());
+                                streamed_bytes += event.content.len();
+                                streamed_chunks += 1;
+                                if let Some(timings) = token_timings.as_mut() {
+                                    timings.record(msg.timestamp);
+                                }
+                                let running_usage = cost_accrual
+                                    .as_mut()
+                                    .and_then(|accrual| accrual.record(&event.content));
+                                if let Some(usage) = running_usage {
+                                    let cost = completion_cost(
+                                        cost_calculator.as_ref().as_ref(),
+                                        cache_hit,
+                                        &model_name,
+                                        &provider_name,
+                                        &usage,
+                                    )
+                                    .await;
+                                    match cost {
+                                        Ok(c) => {
+                                            let event = ModelEvent::new(
+                                                &tracing::Span::current(),
+                                                ModelEventType::Custom(CustomEvent::new(
+                                                    "cost_accrual".to_string(),
+                                                    serde_json::json!({
+                                                        "estimated_usage": usage,
+                                                        "estimated_cost": c.cost,
+                                                    }),
+                                                )),
+                                            )
+                                            .with_request_id(msg.request_id.clone());
+                                            outer_tx.send(Some(event)).await.unwrap();
+                                        }
+                                        Err(e) => {
+                                            tracing::error!(
+                                                "Error calculating running cost: {:?}",
+                                                e
+                                            );
+                                        }
+                                    }
+                                }
                             }
                             ModelEventType::LlmFirstToken(_) => {
+                                first_token_time = Some(msg.timestamp.timestamp_micros() as u64);
                                 if let Some(start_time) = start_time {
                                     let current_span = tracing::Span::current();
                                     current_span.record(
@@ -589,19 +800,54 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
                                 }
                             }
                             ModelEventType::LlmStop(llmfinish_event) => {
+                                if let Some(metrics) = &size_metrics {
+                                    metrics.record_stream(
+                                        &provider_name,
+                                        &inference_model_name,
+                                        streamed_bytes,
+                                        streamed_chunks,
+                                    );
+                                }
+                                if let Some(timings) = &token_timings {
+                                    let event = ModelEvent::new(
+                                        &tracing::Span::current(),
+                                        ModelEventType::Custom(CustomEvent::new(
+                                            "token_timing".to_string(),
+                                            timings.summary(),
+                                        )),
+                                    )
+                                    .with_request_id(msg.request_id.clone());
+                                    outer_tx.send(Some(event)).await.unwrap();
+                                }
                                 let s = tracing::Span::current();
                                 s.record("output", serde_json::to_string(&output).unwrap());
+                                generation = start_time.map(|start| {
+                                    finish_generation(
+                                        &s,
+                                        start,
+                                        first_token_time,
+                                        msg.timestamp.timestamp_micros() as u64,
+                                        llmfinish_event,
+                                    )
+                                });
                                 if let Some(u) = &llmfinish_event.usage {
-                                    let cost = cost_calculator
-                                        .calculate_cost(
-                                            &model_name,
-                                            &provider_name,
-                                            &Usage::CompletionModelUsage(u.clone()),
-                                        )
-                                        .await;
+                                    if let Some(reservation) = &token_reservation {
+                                        reservation.record(u.total_tokens);
+                                    }
+                                    let cost = completion_cost(
+                                        cost_calculator.as_ref().as_ref(),
+                                        cache_hit,
+                                        &model_name,
+                                        &provider_name,
+                                        u,
+                                    )
+                                    .await;
 
                                     match cost {
                                         Ok(c) => {
+                                            if let Some(account) = &spend_budget {
+                                                account.debit(c.cost).await;
+                                            }
                                             s.record("cost", serde_json::to_string(&c).unwrap());
                                         }
                                         Err(e) => {
@@ -613,6 +859,9 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
                             }
                             _ => {}
                         }
+                        if let ModelEventType::LlmStop(llmfinish_event) = &mut msg.event {
+                            llmfinish_event.generation = generation;
+                        }
                         outer_tx.send(Some(msg)).await.unwrap();
                     }
                 },
@@ -629,12 +878,32 @@ impl<Inner: ModelInstance> ModelInstance for TracedModel<Inner> {
                 Ok(()) => span.record("output", output),
                 Err(ref e) => span.record("error", tracing::field::display(e)),
             };
+            if let Err(e) = &result {
+                let _ = outer_tx.send(Some(self.error_event(&span, e))).await;
+            }
             result
         }
         .await
     }
 }
 
+/// Records latency and throughput of a finished call on its span
+fn finish_generation(
+    span: &tracing::Span,
+    start: u64,
+    first_token: Option<u64>,
+    end: u64,
+    event: &LLMFinishEvent,
+) -> GenerationMetrics {
+    let output_tokens = event.usage.as_ref().map_or(0, |u| u.output_tokens);
+    let generation = GenerationMetrics::new(start, first_token, end, output_tokens);
+    span.record("latency", generation.latency);
+    if let Some(tps) = generation.tps {
+        span.record("tps", tps);
+    }
+    generation
+}
+
 pub fn credentials_identifier(model_params: &CompletionModelParams) -> CredentialsIdent {
     let langdb_creds = match &model_params.engine {
         CompletionEngineParams::Bedrock { credentials, .. } => credentials.is_none(),
@@ -666,17 +935,20 @@ impl Display for CredentialsIdent {
     }
 }
 
+/// Returns the repaired output when a schema guard fixed malformed JSON
 pub async fn apply_guardrails(
     messages: &[ChatCompletionMessage],
     extra: Option<&Extra>,
     evaluator: &dyn GuardrailsEvaluator,
     executor_context: &ExecutorContext,
     guard_stage: GuardStage,
-) -> Result<(), GuardError> {
+) -> Result<Option<String>, GuardError> {
     let Some(Extra { guards, .. }) = extra else {
-        return Ok(());
+        return Ok(None);
     };
 
+    let mut repaired_output = None;
+
     for guard in guards {
         let (guard_id, parameters) = match guard {
             GuardOrName::GuardId(guard_id) => (guard_id, None),
@@ -704,9 +976,15 @@ pub async fn apply_guardrails(
             {
                 return Err(GuardError::GuardNotPassed(guard_id.clone(), result));
             }
+            GuardResult::Json {
+                repaired: Some(repaired),
+                ..
+            } => {
+                repaired_output = Some(repaired);
+            }
             _ => {}
         }
     }
 
-    Ok(())
+    Ok(repaired_output)
 }
@@ -1,47 +1,54 @@
-use super::error::{AuthorizationError, ModelError};
+use super::error::{AuthorizationError, ModelError, CONTENT_FILTER_ERROR};
 use super::tools::Tool;
 use super::types::{
-    LLMContentEvent, LLMFinishEvent, LLMStartEvent, ModelEvent, ModelEventType, ModelFinishReason,
-    ModelToolCall,
+    CustomEvent, LLMContentEvent, LLMFinishEvent, LLMStartEvent, ModelEvent, ModelEventType,
+    ModelFinishReason, ModelToolCall, ToolCallDeltaEvent,
 };
 use super::{CredentialsIdent, ModelInstance};
 use crate::error::GatewayError;
 use crate::events::JsonValue;
 use crate::events::SPAN_OPENAI;
 use crate::events::{self, RecordResult};
-use crate::model::handler::handle_tool_call;
+use crate::executor::chat_completion::backoff::ProviderRetries;
+use crate::executor::chat_completion::choices::ADDITIONAL_CHOICES_EVENT;
+use crate::executor::chat_completion::confidence::TOKEN_LOGPROBS_EVENT;
+use crate::executor::chat_completion::seed::SYSTEM_FINGERPRINT_EVENT;
+use crate::model::async_trait;
+use crate::model::handler::{handle_tool_call, tool_error_content};
 use crate::model::types::LLMFirstToken;
-use crate::model::{async_trait, DEFAULT_MAX_RETRIES};
 use crate::types::credentials::ApiKeyCredentials;
 use crate::types::engine::{ExecutionOptions, OpenAiModelParams, Prompt};
 use crate::types::gateway::CompletionModelUsage;
-use crate::types::gateway::{ChatCompletionContent, ChatCompletionMessage, ToolCall};
+use crate::types::gateway::{
+    ChatCompletionChoice, ChatCompletionContent, ChatCompletionLogprobs, ChatCompletionMessage,
+    ChatCompletionTokenLogprob, ToolCall, ToolChoice, ToolChoiceMode, TopLogprob,
+};
 use crate::types::message::{MessageType, PromptMessage};
 use crate::types::threads::{InnerMessage, Message};
 use crate::{create_model_span, GatewayResult};
 use async_openai::config::Config;
 use async_openai::config::{AzureConfig, OpenAIConfig};
 use async_openai::error::OpenAIError;
+use async_openai::types::{ChatChoice, ChatCompletionRequestToolMessageArgs, CompletionUsage};
 use async_openai::types::{
-    ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk,
-    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
-    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessage,
-    ChatCompletionRequestToolMessageContent, ChatCompletionRequestUserMessageArgs,
-    ChatCompletionRequestUserMessageContentPart, ChatCompletionTool, ChatCompletionToolArgs,
-    ChatCompletionToolChoiceOption, ChatCompletionToolType, CreateChatCompletionRequest,
-    CreateChatCompletionRequestArgs, FinishReason, FunctionCall, FunctionCallStream,
-    FunctionObject,
+    ChatChoiceLogprobs, ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk,
+    ChatCompletionNamedToolChoice, ChatCompletionRequestAssistantMessageArgs,
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+    ChatCompletionRequestToolMessage, ChatCompletionRequestToolMessageContent,
+    ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContentPart,
+    ChatCompletionTool, ChatCompletionToolArgs, ChatCompletionToolChoiceOption,
+    ChatCompletionToolType, CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
+    FinishReason, FunctionCall, FunctionCallStream, FunctionName, FunctionObject,
 };
 use async_openai::types::{
     ChatCompletionRequestMessageContentPartImage, CreateChatCompletionStreamResponse, ImageUrl,
 };
-use async_openai::types::{ChatCompletionRequestToolMessageArgs, CompletionUsage};
 use async_openai::types::{ChatCompletionRequestUserMessageContent, ChatCompletionStreamOptions};
 use async_openai::Client;
 use futures::Stream;
 use futures::StreamExt;
 use serde_json::Value;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use tracing::field;
 use tracing::Instrument;
@@ -269,8 +276,9 @@ impl<C: Config> OpenAIModel<C> {
         tools: &HashMap<String, Box<dyn Tool>>,
         tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
         tags: HashMap<String, String>,
-    ) -> HashMap<String, String> {
-        let result = futures::future::join_all(function_calls.map(|tool_call| {
+    ) -> GatewayResult<Vec<(String, String)>> {
+        // Calls run concurrently, results keep the order of the tool calls
+        futures::future::try_join_all(function_calls.map(|tool_call| {
             let tags_value = tags.clone();
             async move {
                 let id = tool_call.id.clone();
@@ -278,20 +286,16 @@ impl<C: Config> OpenAIModel<C> {
                 tracing::trace!("Calling tool ({id}) {function:?}");
 
                 let tool_call = Self::map_tool_call(tool_call);
-                let result = handle_tool_call(&tool_call, tools, tx, tags_value).await;
+                let result = handle_tool_call(&tool_call, tools, tx, tags_value).await?;
                 tracing::trace!("Result ({id}): {result:?}");
-                let content = result.unwrap_or_else(|err| err.to_string());
-                (id, content)
+                let content = result.unwrap_or_else(|err| tool_error_content(&err));
+                Ok::<_, GatewayError>((id, content))
             }
         }))
-        .await;
-
-        HashMap::from_iter(result)
+        .await
     }
 
-    fn map_tool_call_results(
-        results: HashMap<String, String>,
-    ) -> Vec<ChatCompletionRequestMessage> {
+    fn map_tool_call_results(results: Vec<(String, String)>) -> Vec<ChatCompletionRequestMessage> {
         results
             .into_iter()
             .map(|(id, content)| {
@@ -339,6 +343,9 @@ impl<C: Config> OpenAIModel<C> {
         if let Some(max_tokens) = model_params.max_tokens {
             builder.max_tokens(max_tokens);
         }
+        if let Some(max_completion_tokens) = model_params.max_completion_tokens {
+            builder.max_completion_tokens(max_completion_tokens);
+        }
         if let Some(temperature) = model_params.temperature {
             builder.temperature(temperature);
         }
@@ -351,6 +358,14 @@ impl<C: Config> OpenAIModel<C> {
             builder.top_logprobs(top_logprobs);
         }
 
+        if let Some(seed) = model_params.seed {
+            builder.seed(seed);
+        }
+
+        if let Some(n) = model_params.n.filter(|_| !stream) {
+            builder.n(n);
+        }
+
         if let Some(user) = &model_params.user {
             builder.user(user.clone());
         }
@@ -364,9 +379,16 @@ impl<C: Config> OpenAIModel<C> {
             .messages(messages)
             .stream(stream);
         if !self.tools.is_empty() {
+            let after_tool_results =
+                matches!(messages.last(), Some(ChatCompletionRequestMessage::Tool(_)));
+            let tool_choice = match &model_params.tool_choice {
+                Some(choice) if after_tool_results => map_tool_choice(&choice.after_tool_results()),
+                Some(choice) => map_tool_choice(choice),
+                None => ChatCompletionToolChoiceOption::Auto,
+            };
             builder
                 .tools(chat_completion_tools)
-                .tool_choice(ChatCompletionToolChoiceOption::Auto);
+                .tool_choice(tool_choice);
         }
 
         if stream {
@@ -387,8 +409,11 @@ impl<C: Config> OpenAIModel<C> {
         FinishReason,
         Vec<ChatCompletionMessageToolCall>,
         Option<async_openai::types::CompletionUsage>,
+        String,
     )> {
-        let mut tool_call_states: HashMap<u32, ChatCompletionMessageToolCall> = HashMap::new();
+        // Keyed by index so parallel tool calls are returned in a stable order
+        let mut tool_call_states: BTreeMap<u32, ChatCompletionMessageToolCall> = BTreeMap::new();
+        let mut content = String::new();
         while let Some(result) = stream.next().await {
             match result {
                 Ok(mut response) => {
@@ -414,6 +439,7 @@ impl<C: Config> OpenAIModel<C> {
                                 reason,
                                 tool_call_states.into_values().collect(),
                                 Some(usage),
+                                content,
                             ));
                         }
 
@@ -432,6 +458,7 @@ impl<C: Config> OpenAIModel<C> {
                             else {
                                 continue;
                             };
+                            let is_new = !tool_call_states.contains_key(&index);
                             let state = tool_call_states.entry(index).or_insert_with(|| {
                                 ChatCompletionMessageToolCall {
                                     id: id.unwrap(),
@@ -442,18 +469,30 @@ impl<C: Config> OpenAIModel<C> {
                                     },
                                 }
                             });
-                            if let Some(arguments) = arguments {
-                                state.function.arguments.push_str(&arguments);
-                            }
+                            let arguments = arguments.unwrap_or_default();
+                            state.function.arguments.push_str(&arguments);
+                            let _ = tx
+                                .send(Some(ModelEvent::new(
+                                    &Span::current(),
+                                    ModelEventType::ToolCallDelta(ToolCallDeltaEvent {
+                                        index: index as usize,
+                                        tool_id: is_new.then(|| state.id.clone()),
+                                        tool_name: is_new.then(|| state.function.name.clone()),
+                                        arguments,
+                                    }),
+                                )))
+                                .await;
                         }
                     }
 
-                    if let Some(content) = &chat_choice.delta.content {
+                    if let Some(delta) = &chat_choice.delta.content {
+                        content.push_str(delta);
                         let _ = tx
                             .send(Some(ModelEvent::new(
                                 &Span::current(),
                                 ModelEventType::LlmContent(LLMContentEvent {
-                                    content: content.to_owned(),
+                                    content: delta.to_owned(),
+                                    logprobs: chat_choice.logprobs.as_ref().map(map_logprobs),
                                 }),
                             )))
                             .await;
@@ -467,7 +506,12 @@ impl<C: Config> OpenAIModel<C> {
                                 usage = Some(u);
                             }
                         }
-                        return Ok((*reason, tool_call_states.into_values().collect(), usage));
+                        return Ok((
+                            *reason,
+                            tool_call_states.into_values().collect(),
+                            usage,
+                            content,
+                        ));
                     }
                 }
                 Err(err) => {
@@ -529,11 +573,36 @@ impl<C: Config> OpenAIModel<C> {
         .instrument(span.clone().or_current())
         .await?;
 
+        if let Some(system_fingerprint) = &response.system_fingerprint {
+            tx.send(Some(ModelEvent::new(
+                &span,
+                ModelEventType::Custom(CustomEvent::new(
+                    SYSTEM_FINGERPRINT_EVENT.to_string(),
+                    serde_json::json!(system_fingerprint),
+                )),
+            )))
+            .await
+            .map_err(|e| GatewayError::CustomError(e.to_string()))?;
+        }
+
         let choices = response.choices;
         if choices.is_empty() {
             return Err(custom_err("No Choices").into());
         }
-        // always take 1 since we put n = 1 in request
+        // The first choice continues the call, the others of a request with `n` are final
+        if choices.len() > 1 {
+            let additional: Vec<ChatCompletionChoice> =
+                choices[1..].iter().map(map_choice).collect();
+            tx.send(Some(ModelEvent::new(
+                &span,
+                ModelEventType::Custom(CustomEvent::new(
+                    ADDITIONAL_CHOICES_EVENT.to_string(),
+                    serde_json::to_value(additional)?,
+                )),
+            )))
+            .await
+            .map_err(|e| GatewayError::CustomError(e.to_string()))?;
+        }
         let first_choice = choices[0].to_owned();
 
         let mut finish_reason = first_choice.finish_reason;
@@ -551,6 +620,14 @@ impl<C: Config> OpenAIModel<C> {
 
                 let content = first_choice.message.content;
 
+                if let Some(content) = content.as_ref() {
+                    if self.stop_sentinel_reached(&span, content, tx).await? {
+                        return self
+                            .finish_with_content(&span, content, response.usage.as_ref(), tx)
+                            .await;
+                    }
+                }
+
                 let label = map_tool_names_to_labels(&tool_calls);
                 let tools_span = tracing::info_span!(
                     target: target!(),
@@ -561,30 +638,32 @@ impl<C: Config> OpenAIModel<C> {
                 );
                 tools_span.follows_from(span.id());
 
+                // Every model call of a tool calling loop reports its usage
+                let finish_reason = Self::map_finish_reason(
+                    &finish_reason.expect("Finish reason is already checked"),
+                );
+                tx.send(Some(ModelEvent::new(
+                    &span,
+                    ModelEventType::LlmStop(LLMFinishEvent {
+                        provider_name: SPAN_OPENAI.to_string(),
+                        model_name: self.params.model.clone().unwrap_or_default(),
+                        output: content.clone(),
+                        usage: Self::map_usage(response.usage.as_ref()),
+                        finish_reason,
+                        tool_calls: tool_calls.iter().map(Self::map_tool_call).collect(),
+                        credentials_ident: self.credentials_ident.clone(),
+                        generation: None,
+                    }),
+                )))
+                .await
+                .map_err(|e| GatewayError::CustomError(e.to_string()))?;
+
                 let tool_name = tool_calls[0].function.name.clone();
                 let tool = self
                     .tools
                     .get(tool_name.as_str())
                     .unwrap_or_else(|| panic!("Tool {tool_name} not found checked"));
                 if tool.stop_at_call() {
-                    let finish_reason = Self::map_finish_reason(
-                        &finish_reason.expect("Finish reason is already checked"),
-                    );
-                    tx.send(Some(ModelEvent::new(
-                        &span,
-                        ModelEventType::LlmStop(LLMFinishEvent {
-                            provider_name: SPAN_OPENAI.to_string(),
-                            model_name: self.params.model.clone().unwrap_or_default(),
-                            output: content.clone(),
-                            usage: Self::map_usage(response.usage.as_ref()),
-                            finish_reason,
-                            tool_calls: tool_calls.iter().map(Self::map_tool_call).collect(),
-                            credentials_ident: self.credentials_ident.clone(),
-                        }),
-                    )))
-                    .await
-                    .map_err(|e| GatewayError::CustomError(e.to_string()))?;
-
                     Ok(InnerExecutionResult::Finish(ChatCompletionMessage {
                         role: "assistant".to_string(),
                         content: content.map(ChatCompletionContent::Text),
@@ -618,7 +697,7 @@ impl<C: Config> OpenAIModel<C> {
                     let result_tool_calls =
                         Self::handle_tool_calls(tool_calls.iter(), &self.tools, tx, tags.clone())
                             .instrument(tools_span.clone())
-                            .await;
+                            .await?;
                     tools_span.record(
                         "tool_results",
                         JsonValue(&serde_json::to_value(&result_tool_calls)?).as_value(),
@@ -637,6 +716,15 @@ impl<C: Config> OpenAIModel<C> {
                 );
                 let message_content = first_choice.message.content;
                 if let Some(content) = &message_content {
+                    if self.stop_sentinel_reached(&span, content, tx).await? {
+                        return self
+                            .finish_with_content(&span, content, response.usage.as_ref(), tx)
+                            .await;
+                    }
+
+                    self.send_token_logprobs(&span, first_choice.logprobs.as_ref(), tx)
+                        .await?;
+
                     tx.send(Some(ModelEvent::new(
                         &span,
                         ModelEventType::LlmStop(LLMFinishEvent {
@@ -647,6 +735,7 @@ impl<C: Config> OpenAIModel<C> {
                             finish_reason,
                             tool_calls: vec![],
                             credentials_ident: self.credentials_ident.clone(),
+                            generation: None,
                         }),
                     )))
                     .await
@@ -676,17 +765,14 @@ impl<C: Config> OpenAIModel<C> {
         tags: HashMap<String, String>,
     ) -> GatewayResult<ChatCompletionMessage> {
         let mut openai_calls = vec![input_messages];
-        let mut retries_left = self
-            .execution_options
-            .max_retries
-            .unwrap_or(DEFAULT_MAX_RETRIES);
+        let mut retries = ProviderRetries::new(&self.execution_options);
         while let Some(messages) = openai_calls.pop() {
             let input = serde_json::to_string(&messages)?;
             let span = create_model_span!(
                 SPAN_OPENAI,
                 target!("chat"),
                 tags,
-                retries_left,
+                retries.retries_left(),
                 input = input
             );
 
@@ -700,18 +786,101 @@ impl<C: Config> OpenAIModel<C> {
                 }
                 Err(e) => {
                     span.record("error", e.to_string());
-                    if retries_left == 0 {
+                    if !retries.retry(&e, &span, tx).await {
                         return Err(e);
-                    } else {
-                        openai_calls.push(messages);
                     }
-                    retries_left -= 1;
+                    openai_calls.push(messages);
                 }
             }
         }
         unreachable!();
     }
 
+    /// Checks the output for the configured stop sentinel and reports when it ends the loop
+    async fn stop_sentinel_reached(
+        &self,
+        span: &Span,
+        content: &str,
+        tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+    ) -> GatewayResult<bool> {
+        let Some(stop_sentinel) = &self.execution_options.stop_sentinel else {
+            return Ok(false);
+        };
+        if !stop_sentinel.is_reached(content) {
+            return Ok(false);
+        }
+
+        tx.send(Some(ModelEvent::new(
+            span,
+            ModelEventType::Custom(CustomEvent::new(
+                "stop_sentinel".to_string(),
+                serde_json::json!({ "sentinel": stop_sentinel.sentinel }),
+            )),
+        )))
+        .await
+        .map_err(|e| GatewayError::CustomError(e.to_string()))?;
+
+        Ok(true)
+    }
+
+    /// Reports the output token log probabilities when they were requested
+    async fn send_token_logprobs(
+        &self,
+        span: &Span,
+        logprobs: Option<&ChatChoiceLogprobs>,
+        tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+    ) -> GatewayResult<()> {
+        let Some(logprobs) = logprobs.filter(|l| l.content.is_some()) else {
+            return Ok(());
+        };
+
+        tx.send(Some(ModelEvent::new(
+            span,
+            ModelEventType::Custom(CustomEvent::new(
+                TOKEN_LOGPROBS_EVENT.to_string(),
+                serde_json::to_value(map_logprobs(logprobs))?,
+            )),
+        )))
+        .await
+        .map_err(|e| GatewayError::CustomError(e.to_string()))
+    }
+
+    /// Finishes the loop with the content, skipping any tool calls of the response
+    async fn finish_with_content(
+        &self,
+        span: &Span,
+        content: &str,
+        usage: Option<&CompletionUsage>,
+        tx: &tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+    ) -> GatewayResult<InnerExecutionResult> {
+        let content = match &self.execution_options.stop_sentinel {
+            Some(stop_sentinel) => stop_sentinel.apply(content),
+            None => content.to_string(),
+        };
+
+        tx.send(Some(ModelEvent::new(
+            span,
+            ModelEventType::LlmStop(LLMFinishEvent {
+                provider_name: SPAN_OPENAI.to_string(),
+                model_name: self.params.model.clone().unwrap_or_default(),
+                output: Some(content.clone()),
+                usage: Self::map_usage(usage),
+                finish_reason: ModelFinishReason::Stop,
+                tool_calls: vec![],
+                credentials_ident: self.credentials_ident.clone(),
+                generation: None,
+            }),
+        )))
+        .await
+        .map_err(|e| GatewayError::CustomError(e.to_string()))?;
+
+        Ok(InnerExecutionResult::Finish(ChatCompletionMessage {
+            role: "assistant".to_string(),
+            content: Some(ChatCompletionContent::Text(content)),
+            ..Default::default()
+        }))
+    }
+
     fn handle_finish_reason(finish_reason: Option<FinishReason>) -> GatewayError {
         match finish_reason {
             Some(FinishReason::Length) => ModelError::FinishError(
@@ -719,7 +888,7 @@ impl<C: Config> OpenAIModel<C> {
             )
             .into(),
             Some(FinishReason::ContentFilter) => {
-                ModelError::FinishError("Content filter blocked the completion".to_string()).into()
+                ModelError::FinishError(CONTENT_FILTER_ERROR.to_string()).into()
             }
             x => ModelError::FinishError(format!("{x:?}")).into(),
         }
@@ -785,11 +954,16 @@ impl<C: Config> OpenAIModel<C> {
             .create_stream(request)
             .await
             .map_err(ModelError::OpenAIApi)?;
-        let (finish_reason, tool_calls, usage) = self
+        let (mut finish_reason, mut tool_calls, usage, content) = self
             .process_stream(stream, tx, first_response_received)
             .instrument(span.clone())
             .await?;
 
+        if self.stop_sentinel_reached(&span, &content, tx).await? {
+            finish_reason = FinishReason::Stop;
+            tool_calls.clear();
+        }
+
         let trace_finish_reason = Self::map_finish_reason(&finish_reason);
         tx.send(Some(ModelEvent::new(
             &span,
@@ -801,6 +975,7 @@ impl<C: Config> OpenAIModel<C> {
                 finish_reason: trace_finish_reason.clone(),
                 tool_calls: tool_calls.iter().map(Self::map_tool_call).collect(),
                 credentials_ident: self.credentials_ident.clone(),
+                generation: None,
             }),
         )))
         .await
@@ -857,7 +1032,7 @@ impl<C: Config> OpenAIModel<C> {
                     let result_tool_calls =
                         Self::handle_tool_calls(tool_calls.iter(), &self.tools, tx, tags.clone())
                             .instrument(tools_span.clone())
-                            .await;
+                            .await?;
                     tools_span.record(
                         "tool_results",
                         JsonValue(&serde_json::to_value(&result_tool_calls)?).as_value(),
@@ -881,17 +1056,14 @@ impl<C: Config> OpenAIModel<C> {
         tags: HashMap<String, String>,
     ) -> GatewayResult<()> {
         let mut openai_calls = vec![input_messages];
-        let mut retries_left = self
-            .execution_options
-            .max_retries
-            .unwrap_or(DEFAULT_MAX_RETRIES);
+        let mut retries = ProviderRetries::new(&self.execution_options);
         while let Some(input_messages) = openai_calls.pop() {
             let input = serde_json::to_string(&input_messages)?;
             let span = create_model_span!(
                 SPAN_OPENAI,
                 target!("chat"),
                 tags,
-                retries_left,
+                retries.retries_left(),
                 input = input
             );
 
@@ -915,12 +1087,10 @@ impl<C: Config> OpenAIModel<C> {
                 }
                 Err(e) => {
                     span.record("error", e.to_string());
-                    if retries_left == 0 {
+                    if !retries.retry(&e, &span, tx).await {
                         return Err(e);
-                    } else {
-                        openai_calls.push(input_messages);
                     }
-                    retries_left -= 1;
+                    openai_calls.push(input_messages);
                 }
             }
         }
@@ -1171,3 +1341,265 @@ fn map_tool_names_to_labels(tool_calls: &[ChatCompletionMessageToolCall]) -> Str
         .collect::<Vec<String>>()
         .join(",")
 }
+
+fn map_tool_choice(choice: &ToolChoice) -> ChatCompletionToolChoiceOption {
+    match choice {
+        ToolChoice::Mode(ToolChoiceMode::None) => ChatCompletionToolChoiceOption::None,
+        ToolChoice::Mode(ToolChoiceMode::Auto) => ChatCompletionToolChoiceOption::Auto,
+        ToolChoice::Mode(ToolChoiceMode::Required) => ChatCompletionToolChoiceOption::Required,
+        ToolChoice::Function(choice) => {
+            ChatCompletionToolChoiceOption::Named(ChatCompletionNamedToolChoice {
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionName {
+                    name: choice.function.name.clone(),
+                },
+            })
+        }
+    }
+}
+
+/// Converts a choice after the first to the gateway response layout
+fn map_choice(choice: &ChatChoice) -> ChatCompletionChoice {
+    ChatCompletionChoice {
+        index: choice.index as i32,
+        message: ChatCompletionMessage::new_text(
+            "assistant".to_string(),
+            choice.message.content.clone().unwrap_or_default(),
+        ),
+        finish_reason: choice
+            .finish_reason
+            .as_ref()
+            .and_then(|r| serde_json::to_value(r).ok())
+            .and_then(|r| r.as_str().map(String::from)),
+        logprobs: choice.logprobs.as_ref().map(map_logprobs),
+    }
+}
+
+/// Converts provider log probabilities to the gateway response layout
+fn map_logprobs(logprobs: &ChatChoiceLogprobs) -> ChatCompletionLogprobs {
+    ChatCompletionLogprobs {
+        content: logprobs.content.as_ref().map(|content| {
+            content
+                .iter()
+                .map(|t| ChatCompletionTokenLogprob {
+                    token: t.token.clone(),
+                    logprob: t.logprob,
+                    bytes: t.bytes.clone(),
+                    top_logprobs: t
+                        .top_logprobs
+                        .iter()
+                        .map(|top| TopLogprob {
+                            token: top.token.clone(),
+                            logprob: top.logprob,
+                            bytes: top.bytes.clone(),
+                        })
+                        .collect(),
+                })
+                .collect()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::Barrier;
+
+    use super::*;
+    use crate::types::engine::StopSentinel;
+    use crate::types::gateway::FunctionParameters;
+
+    /// Waits for all other calls on the barrier, so it only finishes when run concurrently
+    struct BarrierTool {
+        barrier: Arc<Barrier>,
+        fail: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl Tool for BarrierTool {
+        fn name(&self) -> String {
+            "barrier".to_string()
+        }
+
+        fn description(&self) -> String {
+            String::new()
+        }
+
+        fn get_function_parameters(&self) -> Option<FunctionParameters> {
+            None
+        }
+
+        async fn run(
+            &self,
+            input: HashMap<String, Value>,
+            _tags: HashMap<String, String>,
+        ) -> GatewayResult<Value> {
+            self.barrier.wait().await;
+            if self.fail {
+                return Err(GatewayError::CustomError("tool failed".to_string()));
+            }
+            Ok(input["value"].clone())
+        }
+    }
+
+    fn tool_call(id: &str, name: &str, value: &str) -> ChatCompletionMessageToolCall {
+        ChatCompletionMessageToolCall {
+            id: id.to_string(),
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments: serde_json::json!({ "value": value }).to_string(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls() {
+        let barrier = Arc::new(Barrier::new(2));
+        let mut tools: HashMap<String, Box<dyn Tool>> = HashMap::new();
+        for (name, fail) in [("ok", false), ("failing", true)] {
+            tools.insert(
+                name.to_string(),
+                Box::new(BarrierTool {
+                    barrier: barrier.clone(),
+                    fail,
+                }),
+            );
+        }
+        let calls = [
+            tool_call("call_1", "failing", "a"),
+            tool_call("call_2", "ok", "b"),
+        ];
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+        let results = tokio::time::timeout(
+            Duration::from_secs(5),
+            OpenAIModel::<OpenAIConfig>::handle_tool_calls(
+                calls.iter(),
+                &tools,
+                &tx,
+                HashMap::new(),
+            ),
+        )
+        .await
+        .expect("tool calls should not run one after the other")
+        .unwrap();
+
+        // The failed tool is reported to the model next to the other result
+        assert_eq!(
+            results,
+            vec![
+                (
+                    "call_1".to_string(),
+                    r#"{"error":"Custom Error: tool failed"}"#.to_string()
+                ),
+                ("call_2".to_string(), r#""b""#.to_string()),
+            ]
+        );
+
+        // Malformed calls from the model fail the request
+        let mut invalid_arguments = tool_call("call_3", "ok", "c");
+        invalid_arguments.function.arguments = "not json".to_string();
+        for call in [tool_call("call_4", "missing", "d"), invalid_arguments] {
+            let result = OpenAIModel::<OpenAIConfig>::handle_tool_calls(
+                [call].iter(),
+                &tools,
+                &tx,
+                HashMap::new(),
+            )
+            .await;
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn test_map_tool_choice() {
+        let choices = [
+            (
+                ToolChoice::Mode(ToolChoiceMode::None),
+                serde_json::json!("none"),
+            ),
+            (
+                ToolChoice::Mode(ToolChoiceMode::Required),
+                serde_json::json!("required"),
+            ),
+            (
+                serde_json::from_value(serde_json::json!({
+                    "type": "function",
+                    "function": {"name": "get_weather"}
+                }))
+                .unwrap(),
+                serde_json::json!({"type": "function", "function": {"name": "get_weather"}}),
+            ),
+        ];
+        for (choice, expected) in choices {
+            assert_eq!(
+                serde_json::to_value(map_tool_choice(&choice)).unwrap(),
+                expected
+            );
+        }
+    }
+    #[tokio::test]
+    async fn test_stop_sentinel_finishes_loop() {
+        let model = OpenAIModel::new(
+            OpenAiModelParams {
+                model: Some("gpt-4o-mini".to_string()),
+                ..Default::default()
+            },
+            None,
+            ExecutionOptions {
+                stop_sentinel: Some(StopSentinel {
+                    sentinel: "<DONE>".to_string(),
+                    strip: true,
+                }),
+                ..Default::default()
+            },
+            Prompt::new("test".to_string(), String::new()),
+            HashMap::new(),
+            Some(Client::new()),
+            None,
+        )
+        .unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let span = Span::current();
+
+        assert!(!model
+            .stop_sentinel_reached(&span, "Looking it up", &tx)
+            .await
+            .unwrap());
+        assert!(model
+            .stop_sentinel_reached(&span, "Found it <DONE>", &tx)
+            .await
+            .unwrap());
+        let Some(Some(event)) = rx.recv().await else {
+            panic!("sentinel event expected");
+        };
+        assert!(
+            matches!(event.event, ModelEventType::Custom(custom) if custom.name() == "stop_sentinel")
+        );
+
+        // Tool calls of the response are skipped and the sentinel is stripped
+        let result = model
+            .finish_with_content(&span, "Found it <DONE>", None, &tx)
+            .await
+            .unwrap();
+        let InnerExecutionResult::Finish(message) = result else {
+            panic!("the loop should finish");
+        };
+        assert_eq!(
+            message.content,
+            Some(ChatCompletionContent::Text("Found it".to_string()))
+        );
+        let Some(Some(event)) = rx.recv().await else {
+            panic!("stop event expected");
+        };
+        let ModelEventType::LlmStop(stop) = event.event else {
+            panic!("stop event expected");
+        };
+        assert_eq!(stop.output.as_deref(), Some("Found it"));
+        assert_eq!(stop.finish_reason, ModelFinishReason::Stop);
+        assert!(stop.tool_calls.is_empty());
+    }
+}
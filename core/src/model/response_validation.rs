@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::GatewayError;
+use crate::GatewayResult;
+
+const GEMINI_PART_FIELDS: [&str; 9] = [
+    "text",
+    "inlineData",
+    "fileData",
+    "functionCall",
+    "functionResponse",
+    "executableCode",
+    "codeExecutionResult",
+    "thought",
+    "thoughtSignature",
+];
+
+/// Strict validation of raw provider responses against the shape the gateway maps from
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default)]
+pub struct ResponseValidationConfig {
+    /// Providers to validate, every provider when empty
+    #[serde(default)]
+    pub providers: Vec<String>,
+    /// Fail the request on a mismatch instead of only logging it
+    #[serde(default)]
+    pub reject: bool,
+}
+
+impl ResponseValidationConfig {
+    pub fn applies_to(&self, provider: &str) -> bool {
+        self.providers.is_empty() || self.providers.iter().any(|p| p == provider)
+    }
+
+    /// Logs the raw response when it does not match the expected shape
+    pub fn check(
+        &self,
+        provider: &str,
+        raw: &str,
+        violations: impl FnOnce(&Value) -> Vec<String>,
+    ) -> GatewayResult<()> {
+        if !self.applies_to(provider) {
+            return Ok(());
+        }
+
+        let violations = match serde_json::from_str::<Value>(raw) {
+            Ok(value) => violations(&value),
+            Err(e) => vec![format!("response is not valid JSON: {e}")],
+        };
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        let violations = violations.join("; ");
+        tracing::error!(
+            target: "response_validation",
+            provider,
+            violations,
+            raw_response = raw,
+            "Unexpected {provider} response shape"
+        );
+
+        if self.reject {
+            return Err(GatewayError::CustomError(format!(
+                "Unexpected {provider} response shape: {violations}"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Shape of Gemini `generateContent` responses and stream chunks
+pub fn gemini_response_violations(value: &Value) -> Vec<String> {
+    let mut violations = vec![];
+    let Some(candidates) = value.get("candidates").and_then(Value::as_array) else {
+        violations.push("candidates is not an array".to_string());
+        return violations;
+    };
+    if candidates.is_empty() {
+        violations.push("candidates is empty".to_string());
+    }
+
+    for (i, candidate) in candidates.iter().enumerate() {
+        // Blocked or truncated candidates may come without content
+        let finished_early = candidate
+            .get("finishReason")
+            .and_then(Value::as_str)
+            .is_some_and(|reason| reason != "STOP");
+        let Some(content) = candidate.get("content") else {
+            if !finished_early {
+                violations.push(format!("candidates[{i}].content is missing"));
+            }
+            continue;
+        };
+
+        match content.get("parts").and_then(Value::as_array) {
+            Some(parts) => {
+                for (j, part) in parts.iter().enumerate() {
+                    let known = part
+                        .as_object()
+                        .is_some_and(|p| GEMINI_PART_FIELDS.iter().any(|f| p.contains_key(*f)));
+                    if !known {
+                        violations.push(format!(
+                            "candidates[{i}].content.parts[{j}] has no known field"
+                        ));
+                    }
+                }
+            }
+            None if !finished_early => {
+                violations.push(format!("candidates[{i}].content.parts is not an array"))
+            }
+            None => {}
+        }
+    }
+
+    if let Some(usage) = value.get("usageMetadata") {
+        if !usage.is_object() {
+            violations.push("usageMetadata is not an object".to_string());
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_gemini_response_violations() {
+        let valid = json!({
+            "candidates": [{
+                "content": {"parts": [{"text": "Hello"}], "role": "model"},
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": {"promptTokenCount": 3}
+        });
+        assert!(gemini_response_violations(&valid).is_empty());
+
+        let blocked = json!({"candidates": [{"finishReason": "SAFETY"}]});
+        assert!(gemini_response_violations(&blocked).is_empty());
+
+        let changed = json!({
+            "candidates": [{"content": {"parts": [{"body": "Hello"}]}}]
+        });
+        assert_eq!(
+            gemini_response_violations(&changed),
+            vec!["candidates[0].content.parts[0] has no known field"]
+        );
+
+        let missing = json!({"results": []});
+        assert_eq!(
+            gemini_response_violations(&missing),
+            vec!["candidates is not an array"]
+        );
+    }
+
+    #[test]
+    fn test_check_respects_mode() {
+        let raw = r#"{"results": []}"#;
+        let log_only = ResponseValidationConfig::default();
+        assert!(log_only
+            .check("gemini", raw, gemini_response_violations)
+            .is_ok());
+
+        let strict = ResponseValidationConfig {
+            providers: vec!["gemini".to_string()],
+            reject: true,
+        };
+        assert!(strict
+            .check("gemini", raw, gemini_response_violations)
+            .is_err());
+        assert!(strict
+            .check("openai", raw, gemini_response_violations)
+            .is_ok());
+    }
+}
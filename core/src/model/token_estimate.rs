@@ -0,0 +1,123 @@
+use crate::types::gateway::CompletionModelUsage;
+
+/// Heuristic token counts. The gateway bundles no tokenizer vocabularies, so a count is the
+/// text length divided by the average bytes per token of the tokenizer family on English
+/// text. Counts are for budgets, limits and usage the provider did not report, billing of
+/// completed calls uses the provider usage.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TokenizerFamily {
+    /// GPT-4o, GPT-4.1 and the o-series
+    O200k,
+    /// GPT-4 and GPT-3.5
+    Cl100k,
+    Claude,
+    Gemini,
+    Llama,
+    /// Used when the model is unknown
+    #[default]
+    Other,
+}
+
+impl TokenizerFamily {
+    pub fn for_model(provider: &str, model: &str) -> Self {
+        let model = model.to_lowercase();
+        let model = model.rsplit('/').next().unwrap_or_default();
+        if model.contains("claude") {
+            Self::Claude
+        } else if model.contains("gemini") || model.contains("gemma") {
+            Self::Gemini
+        } else if model.contains("llama") {
+            Self::Llama
+        } else if ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4"]
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+        {
+            Self::O200k
+        } else if model.starts_with("gpt-") {
+            Self::Cl100k
+        } else {
+            match provider {
+                "anthropic" => Self::Claude,
+                "gemini" => Self::Gemini,
+                "openai" => Self::O200k,
+                _ => Self::Other,
+            }
+        }
+    }
+
+    fn bytes_per_token(self) -> f64 {
+        match self {
+            Self::O200k => 4.4,
+            Self::Cl100k => 4.0,
+            Self::Claude => 3.5,
+            Self::Gemini => 4.0,
+            Self::Llama => 3.8,
+            Self::Other => 4.0,
+        }
+    }
+
+    pub fn estimate(self, text: &str) -> u32 {
+        self.estimate_bytes(text.len())
+    }
+
+    /// Estimate of a text known only by its size
+    pub fn estimate_bytes(self, bytes: usize) -> u32 {
+        (bytes as f64 / self.bytes_per_token()) as u32
+    }
+
+    pub fn estimate_usage(self, input: &str, output: &str) -> CompletionModelUsage {
+        let input_tokens = self.estimate(input);
+        let output_tokens = self.estimate(output);
+        CompletionModelUsage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+            is_estimated: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Usage estimate of a model whose tokenizer is unknown
+pub fn estimate_usage(input: &str, output: &str) -> CompletionModelUsage {
+    TokenizerFamily::Other.estimate_usage(input, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenizer_family() {
+        assert_eq!(
+            TokenizerFamily::for_model("openai", "gpt-4o-mini"),
+            TokenizerFamily::O200k
+        );
+        assert_eq!(
+            TokenizerFamily::for_model("openai", "gpt-3.5-turbo"),
+            TokenizerFamily::Cl100k
+        );
+        assert_eq!(
+            TokenizerFamily::for_model("bedrock", "anthropic.claude-3-haiku-20240307-v1:0"),
+            TokenizerFamily::Claude
+        );
+        assert_eq!(
+            TokenizerFamily::for_model("openrouter", "meta-llama/llama-3.1-70b-instruct"),
+            TokenizerFamily::Llama
+        );
+        assert_eq!(
+            TokenizerFamily::for_model("mistral", "mistral-large"),
+            TokenizerFamily::Other
+        );
+
+        let usage = TokenizerFamily::Claude.estimate_usage(&"a".repeat(70), &"b".repeat(35));
+        assert_eq!((usage.input_tokens, usage.output_tokens), (20, 10));
+        assert!(usage.is_estimated);
+    }
+
+    #[test]
+    fn test_estimate_bytes() {
+        assert_eq!(TokenizerFamily::Other.estimate_bytes(400), 100);
+        assert_eq!(TokenizerFamily::Other.estimate(&"a".repeat(7)), 1);
+    }
+}
@@ -0,0 +1,144 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Records the arrival time of streamed tokens for a sample of requests,
+/// reported as a single event when the stream ends
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenTimingConfig {
+    /// Share of streamed requests that record token timings
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f64,
+    /// Gaps kept per stream, later chunks only count towards the summary
+    #[serde(default = "default_max_samples")]
+    pub max_samples: usize,
+}
+
+fn default_sample_rate() -> f64 {
+    0.01
+}
+
+fn default_max_samples() -> usize {
+    1024
+}
+
+impl TokenTimingConfig {
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<TokenTimings> {
+        (rng.random::<f64>() < self.sample_rate).then(|| TokenTimings::new(self.max_samples))
+    }
+}
+
+pub struct TokenTimings {
+    max_samples: usize,
+    start: Option<DateTime<Utc>>,
+    last: Option<DateTime<Utc>>,
+    /// Microseconds between consecutive chunks
+    gaps_us: Vec<u64>,
+    chunks: usize,
+    total_gap_us: u64,
+    max_gap_us: u64,
+}
+
+impl TokenTimings {
+    pub fn new(max_samples: usize) -> Self {
+        Self {
+            max_samples,
+            start: None,
+            last: None,
+            gaps_us: vec![],
+            chunks: 0,
+            total_gap_us: 0,
+            max_gap_us: 0,
+        }
+    }
+
+    pub fn start(&mut self, timestamp: DateTime<Utc>) {
+        *self = Self::new(self.max_samples);
+        self.start = Some(timestamp);
+    }
+
+    pub fn record(&mut self, timestamp: DateTime<Utc>) {
+        self.chunks += 1;
+        if let Some(last) = self.last {
+            let gap = (timestamp - last)
+                .num_microseconds()
+                .unwrap_or_default()
+                .max(0) as u64;
+            self.total_gap_us += gap;
+            self.max_gap_us = self.max_gap_us.max(gap);
+            if self.gaps_us.len() < self.max_samples {
+                self.gaps_us.push(gap);
+            }
+        }
+        self.last = Some(timestamp);
+    }
+
+    pub fn summary(&self) -> Value {
+        let mut sorted = self.gaps_us.clone();
+        sorted.sort_unstable();
+        let gaps = self.chunks.saturating_sub(1);
+
+        serde_json::json!({
+            "chunks": self.chunks,
+            "duration_us": match (self.start, self.last) {
+                (Some(start), Some(last)) => (last - start).num_microseconds(),
+                _ => None,
+            },
+            "mean_gap_us": if gaps > 0 { self.total_gap_us / gaps as u64 } else { 0 },
+            "p50_gap_us": percentile(&sorted, 0.5),
+            "p90_gap_us": percentile(&sorted, 0.9),
+            "p99_gap_us": percentile(&sorted, 0.99),
+            "max_gap_us": self.max_gap_us,
+            "gaps_us": self.gaps_us,
+            "truncated": gaps > self.gaps_us.len(),
+        })
+    }
+}
+
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[rank]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_summary_of_gaps() {
+        let start = Utc::now();
+        let mut timings = TokenTimings::new(2);
+        timings.start(start);
+        for ms in [100, 110, 130, 190] {
+            timings.record(start + Duration::milliseconds(ms));
+        }
+
+        let summary = timings.summary();
+        assert_eq!(summary["chunks"], 4);
+        assert_eq!(summary["duration_us"], 190_000);
+        assert_eq!(summary["mean_gap_us"], 30_000);
+        assert_eq!(summary["max_gap_us"], 60_000);
+        assert_eq!(summary["gaps_us"], serde_json::json!([10_000, 20_000]));
+        assert_eq!(summary["truncated"], true);
+    }
+
+    #[test]
+    fn test_sample_rate_bounds() {
+        let mut rng = rand::rng();
+        let never = TokenTimingConfig {
+            sample_rate: 0.0,
+            max_samples: 10,
+        };
+        let always = TokenTimingConfig {
+            sample_rate: 1.0,
+            max_samples: 10,
+        };
+        assert!(never.sample(&mut rng).is_none());
+        assert!(always.sample(&mut rng).is_some());
+    }
+}
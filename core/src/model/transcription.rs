@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use async_openai::config::{Config, OpenAIConfig};
+use async_openai::Client;
+use reqwest::multipart::{Form, Part};
+use secrecy::ExposeSecret;
+use tracing::field;
+use valuable::Valuable;
+
+use crate::error::GatewayError;
+use crate::events::{JsonValue, SPAN_OPENAI};
+use crate::model::error::ModelError;
+use crate::model::image_generation::openai::OpenAIReqwestError;
+use crate::model::openai::openai_client;
+use crate::model::types::{ModelEvent, ModelEventType, TranscriptionFinishEvent};
+use crate::model::CredentialsIdent;
+use crate::types::audio::TranscriptionResponse;
+use crate::types::credentials::ApiKeyCredentials;
+use crate::types::gateway::{CreateTranscriptionRequest, FileUpload, TranscriptionResponseFormat};
+use crate::types::provider::InferenceModelProvider;
+use crate::GatewayResult;
+
+/// Upload limits of a transcription API
+pub struct AudioLimits {
+    pub max_file_bytes: usize,
+    /// Accepted containers, as file extensions
+    pub formats: &'static [&'static str],
+}
+
+const WHISPER_LIMITS: AudioLimits = AudioLimits {
+    max_file_bytes: 25 * 1024 * 1024,
+    formats: &[
+        "flac", "m4a", "mp3", "mp4", "mpeg", "mpga", "oga", "ogg", "wav", "webm",
+    ],
+};
+
+/// Limits of the provider's transcription API, `None` when it has none
+pub fn audio_limits(provider: &InferenceModelProvider) -> Option<&'static AudioLimits> {
+    match provider {
+        InferenceModelProvider::OpenAI | InferenceModelProvider::Proxy(_) => Some(&WHISPER_LIMITS),
+        InferenceModelProvider::Anthropic
+        | InferenceModelProvider::Gemini
+        | InferenceModelProvider::Bedrock => None,
+    }
+}
+
+impl AudioLimits {
+    /// Checks the size of the upload and its container, judged by the file extension
+    pub fn validate(&self, file: &FileUpload) -> Result<(), String> {
+        let extension = file
+            .file_name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_lowercase())
+            .unwrap_or_default();
+        if !self.formats.contains(&extension.as_str()) {
+            return Err(format!(
+                "Unsupported audio format '{extension}', expected one of: {}",
+                self.formats.join(", ")
+            ));
+        }
+        if file.data.len() > self.max_file_bytes {
+            return Err(format!("Audio file exceeds {} bytes", self.max_file_bytes));
+        }
+        Ok(())
+    }
+}
+
+/// Client of a Whisper compatible `/audio/transcriptions` API
+pub struct OpenAITranscription {
+    client: Client<OpenAIConfig>,
+    credentials_ident: CredentialsIdent,
+}
+
+impl OpenAITranscription {
+    pub fn new(
+        credentials: Option<&ApiKeyCredentials>,
+        client: Option<Client<OpenAIConfig>>,
+        endpoint: Option<&str>,
+    ) -> Result<Self, ModelError> {
+        Ok(OpenAITranscription {
+            credentials_ident: credentials
+                .map(|_c| CredentialsIdent::Own)
+                .unwrap_or(CredentialsIdent::Langdb),
+            client: match client {
+                Some(client) => client,
+                None => openai_client(credentials, endpoint)?,
+            },
+        })
+    }
+
+    fn form(request: &CreateTranscriptionRequest) -> Form {
+        let file =
+            Part::bytes(request.file.data.to_vec()).file_name(request.file.file_name.clone());
+        // The duration priced per minute is only returned in the verbose format
+        let mut form = Form::new()
+            .text("model", request.model.clone())
+            .text("response_format", "verbose_json")
+            .part("file", file);
+
+        if let Some(language) = &request.language {
+            form = form.text("language", language.clone());
+        }
+        if let Some(prompt) = &request.prompt {
+            form = form.text("prompt", prompt.clone());
+        }
+        if let Some(temperature) = request.temperature {
+            form = form.text("temperature", temperature.to_string());
+        }
+        form
+    }
+
+    pub async fn transcribe(
+        &self,
+        request: &CreateTranscriptionRequest,
+        tx: tokio::sync::mpsc::Sender<Option<ModelEvent>>,
+        tags: HashMap<String, String>,
+    ) -> GatewayResult<TranscriptionResponse> {
+        let input = serde_json::to_string(request)?;
+        let call_span = tracing::info_span!(target: "langdb::user_tracing::models::openai::transcription", SPAN_OPENAI, input = input, output = field::Empty, error = field::Empty, usage = field::Empty, tags = JsonValue(&serde_json::to_value(tags.clone()).unwrap_or_default()).as_value());
+
+        let api_base = self.client.config().api_base().to_string();
+        let api_key: String = self.client.config().api_key().expose_secret().to_string();
+
+        let reqwest_result = reqwest::Client::new()
+            .post(format!("{api_base}/audio/transcriptions"))
+            .header("Authorization", format!("Bearer {api_key}"))
+            .multipart(Self::form(request))
+            .send()
+            .await?;
+
+        if reqwest_result.status().is_success() {
+            let result = reqwest_result.json::<TranscriptionResponse>().await?;
+            let duration_seconds = result.duration.unwrap_or_default();
+            call_span.record("output", result.text.as_str());
+
+            tx.send(Some(ModelEvent::new(
+                &call_span,
+                ModelEventType::TranscriptionFinish(TranscriptionFinishEvent {
+                    model_name: request.model.clone(),
+                    duration_seconds,
+                    credentials_ident: self.credentials_ident.clone(),
+                }),
+            )))
+            .await
+            .unwrap();
+
+            Ok(match request.response_format {
+                TranscriptionResponseFormat::Json => result.into_text_only(),
+                TranscriptionResponseFormat::VerboseJson => result,
+            })
+        } else {
+            let r: OpenAIReqwestError = reqwest_result.json().await.map_err(|e| {
+                call_span.record("error", e.to_string());
+                GatewayError::CustomError(format!("Failed to transcribe audio: {e}"))
+            })?;
+            call_span.record("error", r.error.message.as_str());
+            Err(GatewayError::CustomError(format!(
+                "Failed to transcribe audio: {}",
+                r.error.message
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload(file_name: &str, len: usize) -> FileUpload {
+        FileUpload {
+            file_name: file_name.to_string(),
+            data: bytes::Bytes::from(vec![0; len]),
+        }
+    }
+
+    #[test]
+    fn test_validate_audio() {
+        let limits = audio_limits(&InferenceModelProvider::OpenAI).unwrap();
+        assert!(limits.validate(&upload("meeting.MP3", 1024)).is_ok());
+        assert!(limits.validate(&upload("meeting.webm", 1024)).is_ok());
+        assert!(limits
+            .validate(&upload("meeting.aiff", 1024))
+            .unwrap_err()
+            .starts_with("Unsupported audio format 'aiff'"));
+        assert!(limits.validate(&upload("meeting", 1024)).is_err());
+        assert!(limits
+            .validate(&upload("meeting.wav", 25 * 1024 * 1024 + 1))
+            .is_err());
+        assert!(audio_limits(&InferenceModelProvider::Anthropic).is_none());
+    }
+
+    #[test]
+    fn test_text_only_response() {
+        let response: TranscriptionResponse = serde_json::from_value(serde_json::json!({
+            "task": "transcribe",
+            "language": "english",
+            "duration": 8.47,
+            "text": "The beach was a popular spot.",
+            "segments": [{"id": 0, "start": 0.0, "end": 3.2}]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(response.into_text_only()).unwrap(),
+            serde_json::json!({"text": "The beach was a popular spot."})
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::types::gateway::{CompletionModelUsage, ImageSize};
+use crate::types::gateway::{ChatCompletionLogprobs, CompletionModelUsage, ImageSize};
 use chrono::{DateTime, Utc};
 use opentelemetry::trace::TraceContextExt;
 use serde::{Deserialize, Serialize};
@@ -31,6 +31,10 @@ impl CustomEvent {
     pub fn value(&self) -> serde_json::Value {
         self.value.clone()
     }
+
+    pub fn value_mut(&mut self) -> &mut serde_json::Value {
+        &mut self.value
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -45,8 +49,11 @@ pub enum ModelEventType {
     LlmContent(LLMContentEvent),
     LlmStop(LLMFinishEvent),
     ToolStart(ToolStartEvent),
+    ToolCallDelta(ToolCallDeltaEvent),
     ToolResult(ToolResultEvent),
     ImageGenerationFinish(ImageGenerationFinishEvent),
+    TranscriptionFinish(TranscriptionFinishEvent),
+    RerankFinish(RerankFinishEvent),
     Custom(CustomEvent),
 }
 impl ModelEventType {
@@ -59,8 +66,11 @@ impl ModelEventType {
             ModelEventType::LlmContent(_) => "llm_content",
             ModelEventType::LlmStop(_) => "llm_stop",
             ModelEventType::ToolStart(_) => "tool_start",
+            ModelEventType::ToolCallDelta(_) => "tool_call_delta",
             ModelEventType::ToolResult(_) => "tool_result",
             ModelEventType::ImageGenerationFinish(_) => "image_generation_finish",
+            ModelEventType::TranscriptionFinish(_) => "transcription_finish",
+            ModelEventType::RerankFinish(_) => "rerank_finish",
             ModelEventType::LlmFirstToken(_) => "llm_first_token",
             ModelEventType::Custom(_) => "custom",
         }
@@ -73,6 +83,9 @@ pub struct ModelEvent {
     pub trace_id: String,
     pub event: ModelEventType,
     pub timestamp: DateTime<Utc>,
+    /// Gateway generated id of the request that produced the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl ModelEvent {
@@ -82,13 +95,21 @@ impl ModelEvent {
             timestamp: Utc::now(),
             span_id: span.context().span().span_context().span_id().to_string(),
             trace_id: span.context().span().span_context().trace_id().to_string(),
+            request_id: None,
         }
     }
+
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LLMContentEvent {
     pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<ChatCompletionLogprobs>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -113,6 +134,31 @@ pub struct LLMFinishEvent {
     pub finish_reason: ModelFinishReason,
     pub tool_calls: Vec<ModelToolCall>,
     pub credentials_ident: CredentialsIdent,
+    /// Set by the traced model once the call finished
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation: Option<GenerationMetrics>,
+}
+
+/// Speed of a model call, measured on the event timestamps like `ttft`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GenerationMetrics {
+    /// Microseconds from the start of the call to its end
+    pub latency: u64,
+    /// Completion tokens per second after the first token, `None` without generation time
+    pub tps: Option<f64>,
+}
+
+impl GenerationMetrics {
+    /// Times are event timestamps in microseconds. Calls without a first token event count
+    /// their generation from the start.
+    pub fn new(start: u64, first_token: Option<u64>, end: u64, output_tokens: u32) -> Self {
+        let generation_time = end.saturating_sub(first_token.unwrap_or(start));
+        Self {
+            latency: end.saturating_sub(start),
+            tps: (generation_time > 0)
+                .then(|| output_tokens as f64 * 1_000_000.0 / generation_time as f64),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -154,6 +200,16 @@ pub struct ToolStartEvent {
     pub input: String,
 }
 
+/// Fragment of a streamed tool call. The first fragment of a call carries its id and name,
+/// `index` identifies the call among parallel tool calls of the same turn.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolCallDeltaEvent {
+    pub index: usize,
+    pub tool_id: Option<String>,
+    pub tool_name: Option<String>,
+    pub arguments: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ToolResultEvent {
     pub tool_id: String,
@@ -179,6 +235,21 @@ pub struct ImageGenerationFinishEvent {
     pub credentials_ident: CredentialsIdent,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TranscriptionFinishEvent {
+    pub model_name: String,
+    pub duration_seconds: f64,
+    pub credentials_ident: CredentialsIdent,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RerankFinishEvent {
+    pub provider_name: String,
+    pub model_name: String,
+    pub documents_count: u32,
+    pub credentials_ident: CredentialsIdent,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RunStartEvent {
     pub run_id: String,
@@ -198,3 +269,23 @@ pub struct RunErrorEvent {
     pub message: String,
     pub code: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generation_metrics() {
+        // 50 tokens in the half second after the first token
+        let generation = GenerationMetrics::new(1_000_000, Some(1_200_000), 1_700_000, 50);
+        assert_eq!(generation.latency, 700_000);
+        assert_eq!(generation.tps, Some(100.0));
+
+        let generation = GenerationMetrics::new(1_000_000, None, 1_500_000, 0);
+        assert_eq!(generation.tps, Some(0.0));
+
+        // Nothing generated after the first token
+        let generation = GenerationMetrics::new(1_000_000, Some(1_200_000), 1_200_000, 1);
+        assert_eq!(generation.tps, None);
+    }
+}
@@ -2,6 +2,7 @@ use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 
+use crate::types::gateway::ChatCompletionMessage;
 use crate::types::provider::{CompletionModelPrice, InferenceModelProvider, ModelPrice};
 
 use std::str::FromStr;
@@ -214,6 +215,8 @@ pub enum ModelType {
     Completions,
     Embeddings,
     ImageGeneration,
+    Transcription,
+    Rerank,
 }
 
 impl FromStr for ModelType {
@@ -223,6 +226,8 @@ impl FromStr for ModelType {
             "completions" => Ok(ModelType::Completions),
             "embeddings" => Ok(ModelType::Embeddings),
             "image_generation" => Ok(ModelType::ImageGeneration),
+            "transcription" => Ok(ModelType::Transcription),
+            "rerank" => Ok(ModelType::Rerank),
             _ => Ok(ModelType::Completions),
         }
     }
@@ -234,6 +239,8 @@ impl Display for ModelType {
             ModelType::Completions => write!(f, "completions"),
             ModelType::Embeddings => write!(f, "embeddings"),
             ModelType::ImageGeneration => write!(f, "image_generation"),
+            ModelType::Transcription => write!(f, "transcription"),
+            ModelType::Rerank => write!(f, "rerank"),
         }
     }
 }
@@ -241,12 +248,15 @@ impl Display for ModelType {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Limits {
     pub max_context_size: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
 }
 
 impl Limits {
     pub fn new(limit: u32) -> Self {
         Self {
             max_context_size: limit,
+            max_output_tokens: None,
         }
     }
 }
@@ -276,6 +286,126 @@ pub struct ModelMetadata {
     pub benchmark_info: Option<serde_json::Value>,
     #[serde(default)]
     pub virtual_model_id: Option<String>,
+    /// Set on each of several backing deployments registered under the same model name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<Deployment>,
+    /// System message added to every chat request of the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<DefaultSystemPrompt>,
+    /// Vector sizes of an embedding model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<EmbeddingDimensions>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DefaultSystemPrompt {
+    pub content: String,
+    #[serde(default)]
+    pub policy: SystemPromptPolicy,
+}
+
+/// How the default system prompt is combined with the system messages of the client
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemPromptPolicy {
+    /// Sent first, ahead of the system messages of the client
+    #[default]
+    Prepend,
+    /// Replaces the system messages of the client
+    Override,
+}
+
+impl DefaultSystemPrompt {
+    pub fn apply(&self, messages: &mut Vec<ChatCompletionMessage>) {
+        if self.policy == SystemPromptPolicy::Override {
+            messages.retain(|m| m.role != "system");
+        }
+        messages.insert(
+            0,
+            ChatCompletionMessage::new_text("system".to_string(), self.content.clone()),
+        );
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EmbeddingDimensions {
+    /// Size of the vectors returned without `dimensions`
+    pub default: u32,
+    /// Sizes the vectors can be reduced to, any size up to `default` when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub supported: Vec<u32>,
+    /// Models without dimension reduction reject the `dimensions` parameter
+    #[serde(default = "default_reducible")]
+    pub reducible: bool,
+}
+
+fn default_reducible() -> bool {
+    true
+}
+
+impl EmbeddingDimensions {
+    /// `dimensions` to send to the provider for the requested size. The full size is sent
+    /// as no `dimensions` at all to models that can not reduce their vectors.
+    pub fn resolve(&self, requested: Option<u32>) -> Result<Option<u32>, String> {
+        let Some(requested) = requested else {
+            return Ok(None);
+        };
+
+        if !self.reducible {
+            if requested == self.default {
+                return Ok(None);
+            }
+            return Err(format!(
+                "Model does not support reducing dimensions, `dimensions` must be {}",
+                self.default
+            ));
+        }
+
+        if self.supported.is_empty() && !(1..=self.default).contains(&requested) {
+            return Err(format!(
+                "Invalid `dimensions` {requested}, must be between 1 and {}",
+                self.default
+            ));
+        }
+        if !self.supported.is_empty() && !self.supported.contains(&requested) {
+            let supported = self
+                .supported
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>();
+            return Err(format!(
+                "Invalid `dimensions` {requested}, must be one of {}",
+                supported.join(", ")
+            ));
+        }
+
+        Ok(Some(requested))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Deployment {
+    pub id: String,
+    /// Relative share of requests with the weighted random strategy
+    #[serde(default = "default_deployment_weight")]
+    pub weight: f64,
+    /// Entry of the `providers` config with the credentials, the provider entry when unset
+    #[serde(default)]
+    pub credentials: Option<String>,
+}
+
+fn default_deployment_weight() -> f64 {
+    1.0
+}
+
+/// How a deployment is picked among the models sharing a name
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentStrategy {
+    #[default]
+    RoundRobin,
+    WeightedRandom,
+    LeastRecentlyUsed,
 }
 
 impl Default for ModelMetadata {
@@ -304,6 +434,9 @@ impl Default for ModelMetadata {
             parameters: None,
             virtual_model_id: None,
             benchmark_info: None,
+            deployment: None,
+            system_prompt: None,
+            dimensions: None,
         }
     }
 }
@@ -1,11 +1,41 @@
 use crate::types::{
     gateway::{
         CompletionModelUsage, CostCalculationResult, ImageCostCalculationResult,
-        ImageGenerationModelUsage,
+        ImageGenerationModelUsage, RerankModelUsage, TranscriptionModelUsage,
     },
-    provider::ImageGenerationPrice,
+    provider::{AudioModelPrice, ImageGenerationPrice, RerankModelPrice},
 };
 
+pub fn calculate_audio_price(
+    p: &AudioModelPrice,
+    usage: &TranscriptionModelUsage,
+) -> CostCalculationResult {
+    CostCalculationResult {
+        cost: p.per_minute * usage.duration_seconds / 60.0,
+        per_input_token: 0.0,
+        per_output_token: 0.0,
+        per_cached_input_token: None,
+        per_cached_input_write_token: None,
+        is_cache_used: false,
+        per_image_cost: None,
+    }
+}
+
+pub fn calculate_rerank_price(
+    p: &RerankModelPrice,
+    usage: &RerankModelUsage,
+) -> CostCalculationResult {
+    CostCalculationResult {
+        cost: p.per_document * usage.documents_count as f64,
+        per_input_token: 0.0,
+        per_output_token: 0.0,
+        per_cached_input_token: None,
+        per_cached_input_write_token: None,
+        is_cache_used: false,
+        per_image_cost: None,
+    }
+}
+
 pub fn calculate_image_price(
     p: &ImageGenerationPrice,
     usage: &ImageGenerationModelUsage,
@@ -75,18 +105,9 @@ pub fn calculate_tokens_cost(
         cost_per_output_token /= 100.0;
     }
 
-    let cached_tokens = usage
-        .prompt_tokens_details
-        .as_ref()
-        .map_or(0, |p| p.cached_tokens());
-    let cached_input_write_tokens = usage
-        .prompt_tokens_details
-        .as_ref()
-        .map_or(0, |p| p.cache_creation_tokens());
-    let not_cached_input_tokens = usage
-        .input_tokens
-        .saturating_sub(cached_tokens)
-        .saturating_sub(cached_input_write_tokens);
+    let cached_tokens = usage.cached_input_tokens();
+    let cached_input_write_tokens = usage.cache_creation_tokens();
+    let not_cached_input_tokens = usage.uncached_input_tokens();
 
     let cached_input_token_cost = cost_per_cached_input_token.unwrap_or(cost_per_input_token);
     let cached_input_write_token_cost =
@@ -123,6 +144,7 @@ mod tests {
             prompt_tokens_details: None,
             completion_tokens_details: None,
             is_cache_used: false,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0; // $0.001 per input token
@@ -163,6 +185,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(0), Some(0), None)),
             completion_tokens_details: None,
             is_cache_used: true,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -203,6 +226,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(300), Some(0), None)),
             completion_tokens_details: None,
             is_cache_used: true,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -244,6 +268,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(300), Some(100), None)),
             completion_tokens_details: None,
             is_cache_used: true,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -285,6 +310,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(300), Some(0), None)),
             completion_tokens_details: None,
             is_cache_used: true,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -326,6 +352,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(300), Some(0), None)),
             completion_tokens_details: None,
             is_cache_used: false,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -367,6 +394,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(300), Some(100), None)),
             completion_tokens_details: None,
             is_cache_used: false,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -408,6 +436,7 @@ mod tests {
             prompt_tokens_details: None,
             completion_tokens_details: None,
             is_cache_used: false,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -446,6 +475,7 @@ mod tests {
             )),
             completion_tokens_details: None,
             is_cache_used: false,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -486,6 +516,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(300), Some(100), None)),
             completion_tokens_details: None,
             is_cache_used: true,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -529,6 +560,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(1000), Some(0), None)),
             completion_tokens_details: None,
             is_cache_used: false,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -570,6 +602,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(1000), Some(0), None)),
             completion_tokens_details: None,
             is_cache_used: false,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -611,6 +644,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(300), Some(400), None)),
             completion_tokens_details: None,
             is_cache_used: false,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -652,6 +686,7 @@ mod tests {
             prompt_tokens_details: Some(PromptTokensDetails::new(Some(300), Some(100), None)),
             completion_tokens_details: None,
             is_cache_used: true,
+            is_estimated: false,
         };
 
         let cost_per_input_token = 1.0;
@@ -684,4 +719,38 @@ mod tests {
         assert!(result.is_cache_used);
         assert_eq!(result.per_image_cost, None);
     }
+
+    #[test]
+    fn test_calculate_audio_price() {
+        let price = AudioModelPrice {
+            per_minute: 0.006,
+            valid_from: None,
+        };
+        let usage = TranscriptionModelUsage {
+            duration_seconds: 90.0,
+        };
+
+        let result = calculate_audio_price(&price, &usage);
+
+        // 1.5 minutes * $0.006 per minute
+        assert!((result.cost - 0.009).abs() < 1e-10);
+        assert_eq!(result.per_input_token, 0.0);
+        assert_eq!(result.per_output_token, 0.0);
+    }
+
+    #[test]
+    fn test_calculate_rerank_price() {
+        let price = RerankModelPrice {
+            per_document: 0.00002,
+            valid_from: None,
+        };
+        let usage = RerankModelUsage {
+            documents_count: 50,
+        };
+
+        let result = calculate_rerank_price(&price, &usage);
+
+        assert!((result.cost - 0.001).abs() < 1e-10);
+        assert!(!result.is_cache_used);
+    }
 }
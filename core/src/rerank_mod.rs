@@ -0,0 +1,162 @@
+pub mod cohere;
+pub mod voyage;
+
+use crate::embed_mod::native_model;
+use crate::model::error::ModelError;
+use crate::model::types::{ModelEvent, ModelEventType, RerankFinishEvent};
+use crate::model::CredentialsIdent;
+use crate::types::credentials::ApiKeyCredentials;
+use crate::types::provider::InferenceModelProvider;
+use crate::GatewayError;
+use crate::GatewayResult;
+use cohere::CohereRerank;
+use serde::{Deserialize, Serialize};
+use tracing::Span;
+use voyage::VoyageRerank;
+
+/// Relevance of a document to the query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankScore {
+    /// Position of the document in the request
+    pub index: usize,
+    pub relevance_score: f64,
+}
+
+#[allow(async_fn_in_trait)]
+pub trait Rerank: Sync + Send {
+    /// Scores the documents against the query, providers may return them in any order
+    async fn rerank(
+        &self,
+        query: &str,
+        documents: &[String],
+        top_n: Option<usize>,
+        tx: Option<tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+    ) -> GatewayResult<Vec<RerankScore>>;
+}
+
+/// Rerank client of the model's inference provider, only Cohere and Voyage have a rerank API
+pub enum ProviderRerank {
+    Cohere(CohereRerank),
+    Voyage(VoyageRerank),
+}
+
+impl ProviderRerank {
+    pub fn new(
+        provider: &InferenceModelProvider,
+        model: &str,
+        credentials: Option<&ApiKeyCredentials>,
+        endpoint: Option<&str>,
+    ) -> Result<Self, GatewayError> {
+        match provider {
+            InferenceModelProvider::Proxy(name) if name == "cohere" => Ok(Self::Cohere(
+                CohereRerank::new(&native_model(name, Some(model))?, credentials, endpoint)?,
+            )),
+            InferenceModelProvider::Proxy(name) if name == "voyage" => Ok(Self::Voyage(
+                VoyageRerank::new(&native_model(name, Some(model))?, credentials, endpoint)?,
+            )),
+            _ => Err(ModelError::CustomError(format!(
+                "Reranking is not supported by provider {provider}"
+            ))
+            .into()),
+        }
+    }
+}
+
+impl Rerank for ProviderRerank {
+    async fn rerank(
+        &self,
+        query: &str,
+        documents: &[String],
+        top_n: Option<usize>,
+        tx: Option<tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+    ) -> GatewayResult<Vec<RerankScore>> {
+        match self {
+            Self::Cohere(rerank) => rerank.rerank(query, documents, top_n, tx).await,
+            Self::Voyage(rerank) => rerank.rerank(query, documents, top_n, tx).await,
+        }
+    }
+}
+
+/// Sorts the scores by descending relevance and keeps the `top_n` best
+pub fn rank(mut scores: Vec<RerankScore>, top_n: Option<usize>) -> Vec<RerankScore> {
+    scores.sort_by(|a, b| {
+        b.relevance_score
+            .total_cmp(&a.relevance_score)
+            .then(a.index.cmp(&b.index))
+    });
+    if let Some(top_n) = top_n {
+        scores.truncate(top_n);
+    }
+    scores
+}
+
+/// Reports the documents scored by a provider call, reranking is priced per document
+async fn send_usage(
+    tx: Option<&tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+    provider_name: &str,
+    model_name: &str,
+    documents_count: usize,
+    credentials_ident: &CredentialsIdent,
+) {
+    let Some(tx) = tx else {
+        return;
+    };
+
+    let _ = tx
+        .send(Some(ModelEvent::new(
+            &Span::current(),
+            ModelEventType::RerankFinish(RerankFinishEvent {
+                provider_name: provider_name.to_string(),
+                model_name: model_name.to_string(),
+                documents_count: documents_count as u32,
+                credentials_ident: credentials_ident.clone(),
+            }),
+        )))
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scores(values: &[f64]) -> Vec<RerankScore> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(index, relevance_score)| RerankScore {
+                index,
+                relevance_score: *relevance_score,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rank() {
+        let ranked = rank(scores(&[0.2, 0.9, 0.5, 0.9]), None);
+        assert_eq!(
+            ranked.iter().map(|s| s.index).collect::<Vec<_>>(),
+            vec![1, 3, 2, 0]
+        );
+
+        let ranked = rank(scores(&[0.2, 0.9, 0.5]), Some(2));
+        assert_eq!(
+            ranked.iter().map(|s| s.index).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(rank(scores(&[0.2]), Some(5)).len(), 1);
+    }
+
+    #[test]
+    fn test_model_is_required() {
+        let credentials = ApiKeyCredentials {
+            api_key: "key".to_string(),
+        };
+        let provider = InferenceModelProvider::Proxy("cohere".to_string());
+
+        let error = ProviderRerank::new(&provider, " ", Some(&credentials), None)
+            .err()
+            .unwrap();
+        assert!(matches!(error, GatewayError::InvalidRequest(_)));
+        assert!(ProviderRerank::new(&provider, "rerank-v3.5", Some(&credentials), None).is_ok());
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use tracing::{field, Instrument};
+
+use super::{send_usage, Rerank, RerankScore};
+use crate::embed_mod::NativeClient;
+use crate::events::SPAN_VOYAGE;
+use crate::model::error::ModelError;
+use crate::model::types::ModelEvent;
+use crate::types::credentials::ApiKeyCredentials;
+use crate::GatewayResult;
+
+const API_URL: &str = "https://api.voyageai.com/v1";
+
+#[derive(Serialize)]
+struct RerankRequest<'a> {
+    model: &'a str,
+    query: &'a str,
+    documents: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct RerankResponse {
+    data: Vec<RerankScore>,
+}
+
+pub struct VoyageRerank {
+    model: String,
+    client: NativeClient,
+}
+
+impl VoyageRerank {
+    pub fn new(
+        model: &str,
+        credentials: Option<&ApiKeyCredentials>,
+        endpoint: Option<&str>,
+    ) -> Result<Self, ModelError> {
+        Ok(Self {
+            model: model.to_string(),
+            client: NativeClient::new(API_URL, "LANGDB_VOYAGE_API_KEY", credentials, endpoint)?,
+        })
+    }
+}
+
+impl Rerank for VoyageRerank {
+    async fn rerank(
+        &self,
+        query: &str,
+        documents: &[String],
+        top_n: Option<usize>,
+        tx: Option<tokio::sync::mpsc::Sender<Option<ModelEvent>>>,
+    ) -> GatewayResult<Vec<RerankScore>> {
+        let request = RerankRequest {
+            model: &self.model,
+            query,
+            documents,
+            top_k: top_n,
+        };
+        let call_span = tracing::info_span!(target: "langdb::user_tracing::models::voyage::rerank", SPAN_VOYAGE, input = serde_json::to_string(&request)?, output = field::Empty, error = field::Empty);
+
+        async {
+            let response: RerankResponse = self.client.post("rerank", &request).await?;
+
+            send_usage(
+                tx.as_ref(),
+                SPAN_VOYAGE,
+                &self.model,
+                documents.len(),
+                self.client.credentials_ident(),
+            )
+            .await;
+
+            Ok(response.data)
+        }
+        .instrument(call_span)
+        .await
+    }
+}
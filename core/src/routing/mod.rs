@@ -76,6 +76,14 @@ pub enum RoutingStrategy {
     Optimized {
         metric: strategy::metric::MetricSelector,
     },
+    /// Prefers targets in the caller region, then the lowest measured latency
+    Region {
+        /// Region of each target, in the order of `targets`
+        target_regions: Vec<String>,
+        /// Request header carrying the caller region
+        #[serde(default = "strategy::region::default_region_header")]
+        region_header: String,
+    },
 }
 
 impl Display for RoutingStrategy {
@@ -85,6 +93,7 @@ impl Display for RoutingStrategy {
             RoutingStrategy::Percentage { .. } => write!(f, "Percentage"),
             RoutingStrategy::Random => write!(f, "Random"),
             RoutingStrategy::Optimized { .. } => write!(f, "Optimized"),
+            RoutingStrategy::Region { .. } => write!(f, "Region"),
         }
     }
 }
@@ -125,7 +134,7 @@ impl RouteStrategy for LlmRouter {
         &self,
         _request: ChatCompletionRequest,
         _available_models: &AvailableModels,
-        _headers: HashMap<String, String>,
+        headers: HashMap<String, String>,
         metrics_repository: &M,
     ) -> Result<Targets, RouterError> {
         match &self.strategy {
@@ -198,6 +207,20 @@ impl RouteStrategy for LlmRouter {
                     serde_json::Value::String(model),
                 )])])
             }
+            RoutingStrategy::Region {
+                target_regions,
+                region_header,
+            } => {
+                let metrics = metrics_repository.get_metrics().await?;
+                strategy::region::route(
+                    &self.targets,
+                    target_regions,
+                    region_header,
+                    &headers,
+                    &metrics,
+                    self.metrics_duration.as_ref(),
+                )
+            }
         }
     }
 }
@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 
 use crate::{
     routing::{MetricsDuration, RouterError},
-    usage::{Metrics, ProviderMetrics},
+    usage::{Metrics, ModelMetrics, ProviderMetrics},
 };
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Default, Clone)]
@@ -43,6 +43,41 @@ impl MetricSelector {
     }
 }
 
+/// Best value of the metric for the model, across all providers when none is specified
+pub fn model_metric_value(
+    model: &str,
+    metrics: &BTreeMap<String, ProviderMetrics>,
+    metric: &MetricSelector,
+    metrics_duration: Option<&MetricsDuration>,
+) -> Option<f64> {
+    let period_value = |model_metrics: &ModelMetrics| {
+        let period_metrics = match metrics_duration {
+            Some(MetricsDuration::Total) | None => &model_metrics.metrics.total,
+            Some(MetricsDuration::LastHour) => &model_metrics.metrics.last_hour,
+            Some(MetricsDuration::Last15Minutes) => &model_metrics.metrics.last_15_minutes,
+        };
+        metric.get_value(period_metrics)
+    };
+
+    let values = match model.split_once('/') {
+        Some((provider, model_name)) => metrics
+            .get(provider)
+            .and_then(|p| p.models.get(model_name))
+            .and_then(&period_value)
+            .into_iter()
+            .collect::<Vec<_>>(),
+        None => metrics
+            .values()
+            .filter_map(|p| p.models.get(model).and_then(&period_value))
+            .collect(),
+    };
+
+    let minimize = metric.get_optimization_direction() == MetricOptimizationDirection::Minimize;
+    values
+        .into_iter()
+        .reduce(|a, b| if minimize { a.min(b) } else { a.max(b) })
+}
+
 pub async fn route(
     models: &[String],
     metrics: &BTreeMap<String, ProviderMetrics>,
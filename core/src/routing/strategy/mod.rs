@@ -1,4 +1,5 @@
 pub mod metric;
+pub mod region;
 // pub mod script;
 
 pub use metric::MetricSelector;
@@ -0,0 +1,177 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::routing::strategy::metric::{model_metric_value, MetricSelector};
+use crate::routing::{MetricsDuration, RouterError, Target, Targets};
+use crate::usage::ProviderMetrics;
+
+pub const DEFAULT_REGION_HEADER: &str = "x-region";
+
+pub fn default_region_header() -> String {
+    DEFAULT_REGION_HEADER.to_string()
+}
+
+/// Orders targets deployed in the caller region first, then by latency measured from
+/// recent requests. Targets without latency keep their configured order at the end of
+/// their group, the whole list is returned so the following targets act as fallbacks.
+pub fn route(
+    targets: &[Target],
+    target_regions: &[String],
+    region_header: &str,
+    headers: &HashMap<String, String>,
+    metrics: &BTreeMap<String, ProviderMetrics>,
+    metrics_duration: Option<&MetricsDuration>,
+) -> Result<Targets, RouterError> {
+    if target_regions.len() != targets.len() {
+        return Err(RouterError::MetricRouterError(format!(
+            "Expected {} target regions, got {}",
+            targets.len(),
+            target_regions.len()
+        )));
+    }
+
+    let caller_region = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(region_header))
+        .map(|(_, value)| value.as_str());
+
+    let mut ranked: Vec<(bool, f64, &Target)> = targets
+        .iter()
+        .zip(target_regions)
+        .map(|(target, region)| {
+            let remote = caller_region.is_some_and(|caller| !caller.eq_ignore_ascii_case(region));
+            let latency = target
+                .get("model")
+                .and_then(|m| m.as_str())
+                .and_then(|model| {
+                    model_metric_value(model, metrics, &MetricSelector::Latency, metrics_duration)
+                })
+                .unwrap_or(f64::MAX);
+            (remote, latency, target)
+        })
+        .collect();
+
+    // Stable sort, equal targets keep the configured order
+    ranked.sort_by(|(remote_a, latency_a, _), (remote_b, latency_b, _)| {
+        remote_a.cmp(remote_b).then(latency_a.total_cmp(latency_b))
+    });
+
+    Ok(ranked
+        .into_iter()
+        .map(|(_, _, target)| target.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage::{Metrics, ModelMetrics, TimeMetrics};
+
+    fn target(model: &str) -> Target {
+        HashMap::from([("model".to_string(), serde_json::json!(model))])
+    }
+
+    fn provider(models: &[(&str, f64)]) -> ProviderMetrics {
+        ProviderMetrics {
+            models: models
+                .iter()
+                .map(|(model, latency)| {
+                    let metrics = Metrics {
+                        latency: Some(*latency),
+                        ..Default::default()
+                    };
+                    (
+                        model.to_string(),
+                        ModelMetrics {
+                            metrics: TimeMetrics {
+                                total: metrics.clone(),
+                                last_15_minutes: metrics.clone(),
+                                last_hour: metrics,
+                            },
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn models(targets: &Targets) -> Vec<&str> {
+        targets
+            .iter()
+            .map(|t| t["model"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_region_affinity_then_latency() {
+        let targets = vec![
+            target("azure-us/gpt-4o"),
+            target("azure-eu/gpt-4o"),
+            target("openai/gpt-4o"),
+        ];
+        let regions = vec!["us".to_string(), "eu".to_string(), "us".to_string()];
+        let metrics = BTreeMap::from([
+            ("azure-us".to_string(), provider(&[("gpt-4o", 900.0)])),
+            ("azure-eu".to_string(), provider(&[("gpt-4o", 300.0)])),
+            ("openai".to_string(), provider(&[("gpt-4o", 500.0)])),
+        ]);
+
+        let headers = HashMap::from([("x-region".to_string(), "US".to_string())]);
+        let routed = route(
+            &targets,
+            &regions,
+            DEFAULT_REGION_HEADER,
+            &headers,
+            &metrics,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            models(&routed),
+            vec!["openai/gpt-4o", "azure-us/gpt-4o", "azure-eu/gpt-4o"]
+        );
+
+        // Without a caller region only latency counts
+        let routed = route(
+            &targets,
+            &regions,
+            DEFAULT_REGION_HEADER,
+            &HashMap::new(),
+            &metrics,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            models(&routed),
+            vec!["azure-eu/gpt-4o", "openai/gpt-4o", "azure-us/gpt-4o"]
+        );
+    }
+
+    #[test]
+    fn test_targets_without_metrics_keep_order() {
+        let targets = vec![target("a/model"), target("b/model")];
+        let regions = vec!["eu".to_string(), "eu".to_string()];
+        let routed = route(
+            &targets,
+            &regions,
+            DEFAULT_REGION_HEADER,
+            &HashMap::new(),
+            &BTreeMap::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(models(&routed), vec!["a/model", "b/model"]);
+    }
+
+    #[test]
+    fn test_region_count_mismatch() {
+        let result = route(
+            &[target("a/model")],
+            &[],
+            DEFAULT_REGION_HEADER,
+            &HashMap::new(),
+            &BTreeMap::new(),
+            None,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+const ALGORITHM: &str = "AES-256-GCM";
+const KEY_LEN: usize = 32;
+
+/// Envelope encryption of sensitive span attributes before they are written to the trace store.
+/// Each value is sealed with a fresh data key, which is itself sealed with the tenant key.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TraceEncryptionConfig {
+    /// Base64 encoded 256 bit key per tenant, tenants without a key are written as is
+    pub keys: HashMap<String, String>,
+    #[serde(default = "default_encrypted_attributes")]
+    pub attributes: Vec<String>,
+}
+
+fn default_encrypted_attributes() -> Vec<String> {
+    ["request", "response", "input", "output"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum EncryptionError {
+    #[error("Invalid encryption key for tenant {0}")]
+    InvalidKey(String),
+
+    #[error("No encryption key for tenant {0}")]
+    MissingKey(String),
+
+    #[error("Malformed encrypted value: {0}")]
+    Malformed(String),
+
+    #[error("Encryption operation failed")]
+    Crypto,
+}
+
+/// Stored in place of an encrypted attribute
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EncryptedValue {
+    pub alg: String,
+    pub tenant: String,
+    pub wrapped_key: String,
+    pub key_nonce: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+pub struct TraceEncryption {
+    keys: HashMap<String, LessSafeKey>,
+    attributes: Vec<String>,
+    rng: SystemRandom,
+}
+
+impl TraceEncryption {
+    pub fn new(config: &TraceEncryptionConfig) -> Result<Self, EncryptionError> {
+        let keys = config
+            .keys
+            .iter()
+            .map(|(tenant, key)| {
+                let key = STANDARD
+                    .decode(key.trim())
+                    .map_err(|_| EncryptionError::InvalidKey(tenant.clone()))?;
+                Ok((tenant.clone(), aead_key(&key, tenant)?))
+            })
+            .collect::<Result<_, EncryptionError>>()?;
+
+        Ok(Self {
+            keys,
+            attributes: config.attributes.clone(),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Replaces the configured attributes with their encrypted envelope.
+    /// Attributes that fail to encrypt are removed rather than written in plain text.
+    pub fn encrypt_attributes(&self, tenant: &str, attributes: &mut Map<String, Value>) {
+        if !self.keys.contains_key(tenant) {
+            return;
+        }
+
+        for name in &self.attributes {
+            let Some(value) = attributes.remove(name) else {
+                continue;
+            };
+            match self.encrypt(tenant, &value) {
+                Ok(encrypted) => {
+                    attributes.insert(name.clone(), encrypted);
+                }
+                Err(e) => tracing::error!("Dropping span attribute {name}: {e}"),
+            }
+        }
+    }
+
+    pub fn encrypt(&self, tenant: &str, value: &Value) -> Result<Value, EncryptionError> {
+        let tenant_key = self
+            .keys
+            .get(tenant)
+            .ok_or_else(|| EncryptionError::MissingKey(tenant.to_string()))?;
+
+        let mut data_key = [0u8; KEY_LEN];
+        self.rng
+            .fill(&mut data_key)
+            .map_err(|_| EncryptionError::Crypto)?;
+
+        let plaintext = serde_json::to_vec(value).map_err(|_| EncryptionError::Crypto)?;
+        let (nonce, ciphertext) = self.seal(&aead_key(&data_key, tenant)?, tenant, plaintext)?;
+        let (key_nonce, wrapped_key) = self.seal(tenant_key, tenant, data_key.to_vec())?;
+
+        let envelope = EncryptedValue {
+            alg: ALGORITHM.to_string(),
+            tenant: tenant.to_string(),
+            wrapped_key: STANDARD.encode(wrapped_key),
+            key_nonce: STANDARD.encode(key_nonce),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        serde_json::to_value(envelope).map_err(|_| EncryptionError::Crypto)
+    }
+
+    /// Opens an envelope produced by [`TraceEncryption::encrypt`]. Only meant for
+    /// tooling that holds the tenant key, the gateway itself never decrypts.
+    pub fn decrypt(&self, value: &Value) -> Result<Value, EncryptionError> {
+        let envelope: EncryptedValue = serde_json::from_value(value.clone())
+            .map_err(|e| EncryptionError::Malformed(e.to_string()))?;
+        if envelope.alg != ALGORITHM {
+            return Err(EncryptionError::Malformed(format!(
+                "Unsupported algorithm {}",
+                envelope.alg
+            )));
+        }
+
+        let tenant = envelope.tenant.as_str();
+        let tenant_key = self
+            .keys
+            .get(tenant)
+            .ok_or_else(|| EncryptionError::MissingKey(tenant.to_string()))?;
+
+        let data_key = open(
+            tenant_key,
+            tenant,
+            &decode(&envelope.key_nonce)?,
+            decode(&envelope.wrapped_key)?,
+        )?;
+        let plaintext = open(
+            &aead_key(&data_key, tenant)?,
+            tenant,
+            &decode(&envelope.nonce)?,
+            decode(&envelope.ciphertext)?,
+        )?;
+
+        serde_json::from_slice(&plaintext).map_err(|e| EncryptionError::Malformed(e.to_string()))
+    }
+
+    fn seal(
+        &self,
+        key: &LessSafeKey,
+        tenant: &str,
+        mut in_out: Vec<u8>,
+    ) -> Result<([u8; NONCE_LEN], Vec<u8>), EncryptionError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| EncryptionError::Crypto)?;
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(tenant.as_bytes()),
+            &mut in_out,
+        )
+        .map_err(|_| EncryptionError::Crypto)?;
+        Ok((nonce, in_out))
+    }
+}
+
+fn aead_key(key: &[u8], tenant: &str) -> Result<LessSafeKey, EncryptionError> {
+    if key.len() != KEY_LEN {
+        return Err(EncryptionError::InvalidKey(tenant.to_string()));
+    }
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| EncryptionError::InvalidKey(tenant.to_string()))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn open(
+    key: &LessSafeKey,
+    tenant: &str,
+    nonce: &[u8],
+    mut in_out: Vec<u8>,
+) -> Result<Vec<u8>, EncryptionError> {
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| EncryptionError::Malformed("Invalid nonce".to_string()))?;
+    let len = key
+        .open_in_place(nonce, Aad::from(tenant.as_bytes()), &mut in_out)
+        .map_err(|_| EncryptionError::Crypto)?
+        .len();
+    in_out.truncate(len);
+    Ok(in_out)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, EncryptionError> {
+    STANDARD
+        .decode(value)
+        .map_err(|e| EncryptionError::Malformed(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn encryption() -> TraceEncryption {
+        TraceEncryption::new(&TraceEncryptionConfig {
+            keys: HashMap::from([("tenant-a".to_string(), STANDARD.encode([7u8; KEY_LEN]))]),
+            attributes: default_encrypted_attributes(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_encrypt_attributes_round_trip() {
+        let encryption = encryption();
+        let mut attributes = Map::new();
+        attributes.insert("request".to_string(), json!("{\"prompt\":\"secret\"}"));
+        attributes.insert("model_name".to_string(), json!("gpt-4o"));
+
+        encryption.encrypt_attributes("tenant-a", &mut attributes);
+
+        assert_eq!(attributes["model_name"], json!("gpt-4o"));
+        assert!(!attributes["request"].to_string().contains("secret"));
+        assert_eq!(
+            encryption.decrypt(&attributes["request"]).unwrap(),
+            json!("{\"prompt\":\"secret\"}")
+        );
+    }
+
+    #[test]
+    fn test_tenant_without_key_is_unchanged() {
+        let mut attributes = Map::new();
+        attributes.insert("output".to_string(), json!("plain"));
+
+        encryption().encrypt_attributes("tenant-b", &mut attributes);
+
+        assert_eq!(attributes["output"], json!("plain"));
+    }
+
+    #[test]
+    fn test_decrypt_rejects_other_tenant() {
+        let encryption = encryption();
+        let mut envelope = encryption.encrypt("tenant-a", &json!("secret")).unwrap();
+        envelope["tenant"] = json!("tenant-b");
+
+        assert!(matches!(
+            encryption.decrypt(&envelope),
+            Err(EncryptionError::MissingKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_key_length() {
+        let result = TraceEncryption::new(&TraceEncryptionConfig {
+            keys: HashMap::from([("tenant-a".to_string(), STANDARD.encode([1u8; 16]))]),
+            attributes: vec![],
+        });
+        assert!(matches!(result, Err(EncryptionError::InvalidKey(_))));
+    }
+}
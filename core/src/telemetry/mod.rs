@@ -1,6 +1,9 @@
 #[cfg(feature = "database")]
 pub mod database;
+pub mod encryption;
 
+use crate::executor::redaction::Redactor;
+use crate::telemetry::encryption::TraceEncryption;
 use crate::types::GatewayTenant;
 use std::collections::HashMap;
 use std::str::FromStr;
@@ -82,6 +85,8 @@ pub(crate) struct SpanWriter {
     pub(crate) receiver: tokio::sync::mpsc::Receiver<Span>,
     pub(crate) buf: Vec<Vec<Value>>,
     pub(crate) finished_traces: Vec<TraceId>,
+    pub(crate) encryption: Option<TraceEncryption>,
+    pub(crate) redactor: Option<Arc<Redactor>>,
 }
 
 impl SpanWriter {
@@ -95,7 +100,7 @@ impl SpanWriter {
             start_time_unix_nano,
             end_time_unix_nano,
             kind: span_kind,
-            attributes,
+            mut attributes,
             tenant_id,
             project_id,
             thread_id,
@@ -105,6 +110,13 @@ impl SpanWriter {
         if parent_span_id.is_none() {
             self.finished_traces.push(trace_id);
         }
+        // Redacted first, encrypted values can not be inspected
+        if let Some(redactor) = &self.redactor {
+            redactor.redact_attributes(&mut attributes);
+        }
+        if let (Some(encryption), Some(tenant_id)) = (&self.encryption, &tenant_id) {
+            encryption.encrypt_attributes(tenant_id, &mut attributes);
+        }
         self.buf.push(vec![
             trace_id_uuid(trace_id).to_string().into(),
             parent_trace_id.map_or(Value::Null, |trace_id| {
@@ -209,6 +221,8 @@ impl TraceServiceImpl {
         project_trace_senders: Arc<ProjectTraceMap>,
         transport: Box<dyn SpanWriterTransport>,
         tenant_resolver: Box<dyn TraceTenantResolver>,
+        encryption: Option<TraceEncryption>,
+        redactor: Option<Arc<Redactor>>,
     ) -> Self {
         let (sender, receiver) = mpsc::channel(1000);
         let writer = SpanWriter {
@@ -216,6 +230,8 @@ impl TraceServiceImpl {
             receiver,
             finished_traces: Default::default(),
             buf: Default::default(),
+            encryption,
+            redactor,
         };
         tokio::spawn(writer.run());
         Self {
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TranscriptionResponse {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Length of the audio in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segments: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub words: Option<Vec<Value>>,
+}
+
+impl TranscriptionResponse {
+    /// Drops everything but the text, as returned for the `json` response format
+    pub fn into_text_only(self) -> Self {
+        Self {
+            text: self.text,
+            ..Default::default()
+        }
+    }
+}
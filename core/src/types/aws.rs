@@ -4,11 +4,16 @@ use aws_sdk_bedrock::config::Credentials;
 use super::credentials::AwsCredentials;
 
 pub async fn get_user_shared_config(credentials: AwsCredentials) -> aws_config::ConfigLoader {
-    let region = Region::new(std::env::var("AWS_DEFAULT_REGION").unwrap_or("us-east-1".into()));
+    let region = Region::new(
+        credentials
+            .region
+            .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+            .unwrap_or("us-east-1".into()),
+    );
     let credentials = Credentials::new(
         credentials.access_key,
         credentials.access_secret,
-        None,              // optional session token
+        credentials.session_token,
         None,              // optional expiration time
         "langdb-provider", // optional provider name
     );
@@ -27,3 +27,172 @@ pub enum ResponseCacheAdapter {
 pub struct DistanceCacheOptions {
     pub min_similarity: f32,
 }
+
+/// Per-request cache behavior read from the `Cache-Control` request header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CacheDirectives {
+    /// Cached responses are not used (`no-cache`, `refresh`)
+    pub skip_read: bool,
+    /// The response is not written to the cache (`no-store`)
+    pub skip_write: bool,
+    /// The request fails instead of calling the model on a cache miss (`only-if-cached`)
+    pub only_if_cached: bool,
+}
+
+impl CacheDirectives {
+    pub const HEADER: &'static str = "cache-control";
+
+    /// Parses comma separated directives, unknown directives are ignored
+    pub fn parse(header: &str) -> Self {
+        let mut directives = Self::default();
+        for directive in header.split(',').map(|d| d.trim().to_ascii_lowercase()) {
+            match directive.as_str() {
+                "no-cache" | "refresh" => directives.skip_read = true,
+                "no-store" => directives.skip_write = true,
+                "only-if-cached" => directives.only_if_cached = true,
+                _ => {}
+            }
+        }
+        directives
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Selects cache entries to invalidate. Empty filter matches every entry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheFlushFilter {
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+}
+
+impl CacheFlushFilter {
+    pub fn matches(&self, key: &str, model: &str, tags: &[String]) -> bool {
+        self.model.as_ref().is_none_or(|m| m == model)
+            && self.tag.as_ref().is_none_or(|t| tags.contains(t))
+            && self.key_prefix.as_ref().is_none_or(|p| key.starts_with(p))
+    }
+}
+
+/// Cache that can be invalidated at runtime through the admin API
+pub trait FlushableCache: Send + Sync {
+    /// Cache kind reported in flush results, e.g. `response`, `semantic` or `embedding`
+    fn kind(&self) -> &str;
+
+    /// Removes matching entries and returns how many were invalidated
+    fn flush(&self, filter: &CacheFlushFilter) -> usize;
+}
+
+#[derive(Clone, Default)]
+pub struct CacheRegistry(pub Vec<std::sync::Arc<dyn FlushableCache>>);
+
+impl CacheRegistry {
+    pub fn flush(
+        &self,
+        kind: Option<&str>,
+        filter: &CacheFlushFilter,
+    ) -> std::collections::HashMap<String, usize> {
+        let mut flushed = std::collections::HashMap::new();
+        for cache in self
+            .0
+            .iter()
+            .filter(|c| kind.is_none_or(|kind| c.kind() == kind))
+        {
+            *flushed.entry(cache.kind().to_string()).or_default() += cache.flush(filter);
+        }
+        flushed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct TestCache {
+        kind: &'static str,
+        entries: Mutex<Vec<(String, String, Vec<String>)>>,
+    }
+
+    impl FlushableCache for TestCache {
+        fn kind(&self) -> &str {
+            self.kind
+        }
+
+        fn flush(&self, filter: &CacheFlushFilter) -> usize {
+            let mut entries = self.entries.lock().unwrap();
+            let before = entries.len();
+            entries.retain(|(key, model, tags)| !filter.matches(key, model, tags));
+            before - entries.len()
+        }
+    }
+
+    fn cache(kind: &'static str) -> Arc<TestCache> {
+        Arc::new(TestCache {
+            kind,
+            entries: Mutex::new(vec![
+                (
+                    "chat:1".to_string(),
+                    "gpt-4o".to_string(),
+                    vec!["a".to_string()],
+                ),
+                ("chat:2".to_string(), "gpt-4o-mini".to_string(), vec![]),
+                (
+                    "emb:1".to_string(),
+                    "gpt-4o".to_string(),
+                    vec!["b".to_string()],
+                ),
+            ]),
+        })
+    }
+
+    #[test]
+    fn test_cache_directives() {
+        assert!(CacheDirectives::parse("max-age=0").is_default());
+        assert_eq!(
+            CacheDirectives::parse("No-Cache, no-store"),
+            CacheDirectives {
+                skip_read: true,
+                skip_write: true,
+                only_if_cached: false,
+            }
+        );
+
+        let refresh = CacheDirectives::parse("refresh");
+        assert!(refresh.skip_read && !refresh.skip_write);
+        assert!(CacheDirectives::parse("only-if-cached").only_if_cached);
+    }
+
+    #[test]
+    fn test_flush_all() {
+        let registry = CacheRegistry(vec![cache("response"), cache("embedding")]);
+        let flushed = registry.flush(None, &CacheFlushFilter::default());
+        assert_eq!(flushed.get("response"), Some(&3));
+        assert_eq!(flushed.get("embedding"), Some(&3));
+    }
+
+    #[test]
+    fn test_flush_selective() {
+        let registry = CacheRegistry(vec![cache("response"), cache("embedding")]);
+        let filter = CacheFlushFilter {
+            model: Some("gpt-4o".to_string()),
+            key_prefix: Some("chat:".to_string()),
+            ..Default::default()
+        };
+        let flushed = registry.flush(Some("response"), &filter);
+        assert_eq!(flushed.get("response"), Some(&1));
+        assert_eq!(flushed.get("embedding"), None);
+
+        let filter = CacheFlushFilter {
+            tag: Some("b".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(registry.flush(None, &filter).get("embedding"), Some(&1));
+    }
+}
@@ -19,6 +19,47 @@ pub enum Credentials {
     LangDb,
 }
 
+/// Header prefix of client supplied provider keys, e.g. `X-Provider-Key-OpenAI`
+pub const PROVIDER_KEY_HEADER_PREFIX: &str = "x-provider-key-";
+
+/// Client credentials by provider name, read from the `X-Provider-Key-*` headers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProviderCredentials(pub HashMap<String, Credentials>);
+
+impl ProviderCredentials {
+    /// Collects the provider keys of the headers, `None` when the request has none.
+    /// Values holding a JSON object are parsed as credentials, e.g. AWS keys for Bedrock.
+    pub fn from_headers<'a>(headers: impl IntoIterator<Item = (&'a str, &'a str)>) -> Option<Self> {
+        let credentials: HashMap<_, _> = headers
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let name = name.to_ascii_lowercase();
+                let provider = name.strip_prefix(PROVIDER_KEY_HEADER_PREFIX)?.to_string();
+                let value = value.trim();
+                let credentials = match value.starts_with('{') {
+                    true => serde_json::from_str(value).ok()?,
+                    false => Credentials::ApiKey(ApiKeyCredentials {
+                        api_key: value.to_string(),
+                    }),
+                };
+                Some((provider, credentials))
+            })
+            .collect();
+
+        (!credentials.is_empty()).then_some(Self(credentials))
+    }
+
+    /// Credentials of the provider, falling back to `fallback` when the client sent no key
+    /// for it. `None` leaves the provider client to its environment key, which fails the
+    /// request when it is not set either.
+    pub fn select(&self, provider: &str, fallback: Option<Credentials>) -> Option<Credentials> {
+        self.0
+            .get(&provider.to_ascii_lowercase())
+            .cloned()
+            .or(fallback)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct IntegrationCredentials {
     pub secrets: HashMap<String, Value>,
@@ -35,14 +76,20 @@ pub struct ApiKeyCredentials {
 #[serde(deny_unknown_fields)]
 pub struct AwsCredentials {
     pub access_key: String,
+    #[serde(alias = "secret_key")]
     pub access_secret: String,
-    // Defaults tp us-east-1
+    /// Set for temporary credentials, e.g. from an assumed role
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_token: Option<String>,
+    // Defaults to AWS_DEFAULT_REGION, then us-east-1
     pub region: Option<String>,
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::types::credentials::{ApiKeyCredentials, Credentials};
+    use crate::types::credentials::{
+        ApiKeyCredentials, AwsCredentials, Credentials, ProviderCredentials,
+    };
 
     #[test]
     fn test_serialization() {
@@ -61,4 +108,57 @@ mod tests {
         let deserialized: Credentials = serde_json::from_str(&serialized).unwrap();
         assert_eq!(credentials, deserialized);
     }
+
+    #[test]
+    fn test_aws_session_credentials() {
+        let credentials: Credentials = serde_json::from_value(serde_json::json!({
+            "access_key": "AKIA",
+            "secret_key": "secret",
+            "session_token": "token",
+            "region": "eu-west-1"
+        }))
+        .unwrap();
+        assert_eq!(
+            credentials,
+            Credentials::Aws(AwsCredentials {
+                access_key: "AKIA".to_string(),
+                access_secret: "secret".to_string(),
+                session_token: Some("token".to_string()),
+                region: Some("eu-west-1".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_provider_credentials_from_headers() {
+        let credentials = ProviderCredentials::from_headers([
+            ("x-provider-key-openai", "sk-openai"),
+            ("X-Provider-Key-Anthropic", " sk-ant "),
+            (
+                "x-provider-key-bedrock",
+                r#"{"access_key": "AKIA", "secret_key": "secret", "region": null}"#,
+            ),
+            ("authorization", "Bearer gateway-key"),
+        ])
+        .unwrap();
+
+        assert_eq!(credentials.0.len(), 3);
+        assert_eq!(
+            credentials.select("anthropic", None),
+            Some(Credentials::ApiKey(ApiKeyCredentials {
+                api_key: "sk-ant".to_string()
+            }))
+        );
+        assert!(matches!(
+            credentials.select("bedrock", None).unwrap(),
+            Credentials::Aws(_)
+        ));
+        assert_eq!(credentials.select("gemini", None), None);
+        assert_eq!(
+            credentials.select("gemini", Some(Credentials::LangDb)),
+            Some(Credentials::LangDb)
+        );
+
+        assert!(ProviderCredentials::from_headers([("authorization", "Bearer key")]).is_none());
+    }
 }
@@ -11,6 +11,24 @@ pub struct OpenAiEmbeddingParams {
     pub dimensions: Option<u16>,
 }
 
+/// Purpose of the embedded text, for providers that embed queries and documents differently
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingInputType {
+    SearchQuery,
+    SearchDocument,
+    Classification,
+    Clustering,
+}
+
+/// Parameters of providers with their own embeddings API
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NativeEmbeddingParams {
+    pub model: String,
+    pub dimensions: Option<u16>,
+    pub input_type: Option<EmbeddingInputType>,
+}
+
 fn validate_openai_embedding_params(it: &OpenAiEmbeddingParams) -> Result<(), ValidationError> {
     if let Some(dimensions) = it.dimensions {
         if let Some(ref model) = it.model {
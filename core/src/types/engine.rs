@@ -1,6 +1,10 @@
 use std::borrow::Cow;
+use std::sync::Arc;
 use std::{collections::HashMap, fmt::Display, ops::Deref, str::FromStr};
 
+use crate::executor::chat_completion::backoff::RetryPolicy;
+use crate::executor::retry_budget::RetryBudget;
+use crate::model::response_validation::ResponseValidationConfig;
 use crate::types::json::JsonStringCond;
 use async_openai::types::ResponseFormat;
 use clust::messages as claude;
@@ -17,6 +21,7 @@ use super::message::MessageType;
 use super::message::PromptMessage;
 use super::{
     credentials::{ApiKeyCredentials, AwsCredentials},
+    gateway::{SafetySetting, ToolChoice},
     provider::BedrockProvider,
 };
 use serde::de::Error;
@@ -182,9 +187,40 @@ impl CompletionModelDefinition {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct ExecutionOptions {
     pub max_retries: Option<u32>,
+    pub stop_sentinel: Option<StopSentinel>,
+    pub response_validation: Option<ResponseValidationConfig>,
+    /// Backoff of transient provider errors, set by the gateway
+    #[serde(skip)]
+    pub retry_policy: Option<RetryPolicy>,
+    /// Shared budget every retried provider call is charged to
+    #[serde(skip)]
+    pub retry_budget: Option<Arc<RetryBudget>>,
+}
+
+/// Ends the agent loop as soon as the model outputs `sentinel`, even when tools were called
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StopSentinel {
+    pub sentinel: String,
+    /// Remove the sentinel from the returned content
+    #[serde(default)]
+    pub strip: bool,
+}
+
+impl StopSentinel {
+    pub fn is_reached(&self, content: &str) -> bool {
+        !self.sentinel.is_empty() && content.contains(&self.sentinel)
+    }
+
+    pub fn apply(&self, content: &str) -> String {
+        if self.strip {
+            content.replace(&self.sentinel, "").trim().to_string()
+        } else {
+            content.to_string()
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, PartialEq)]
@@ -310,6 +346,10 @@ pub enum ModelType {
     Routing,
     #[serde(rename = "image_generation", alias = "ImageGeneration")]
     ImageGeneration,
+    #[serde(rename = "transcription", alias = "Transcription")]
+    Transcription,
+    #[serde(rename = "rerank", alias = "Rerank")]
+    Rerank,
 }
 
 impl FromStr for ModelType {
@@ -326,6 +366,8 @@ impl Display for ModelType {
             ModelType::Embedding => write!(f, "embedding"),
             ModelType::Routing => write!(f, "routing"),
             ModelType::ImageGeneration => write!(f, "image_generation"),
+            ModelType::Transcription => write!(f, "transcription"),
+            ModelType::Rerank => write!(f, "rerank"),
         }
     }
 }
@@ -495,6 +537,11 @@ pub struct OpenAiModelParams {
     /// The total length of input tokens and generated tokens is limited by the model's context length. [Example Python code](https://cookbook.openai.com/examples/how_to_count_tokens_with_tiktoken) for counting tokens.
     pub max_tokens: Option<u32>,
 
+    /// An upper bound for the number of tokens that can be generated, including reasoning tokens.
+    /// Newer OpenAI models accept this instead of `max_tokens`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
+
     /// Number between -2.0 and 2.0. Positive values penalize new tokens based on whether they appear in the text so far, increasing the model's likelihood to talk about new topics.
     ///
     /// [See more information about frequency and presence penalties.](https://platform.openai.com/docs/api-reference/parameter-details)
@@ -508,6 +555,10 @@ pub struct OpenAiModelParams {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub seed: Option<i64>,
 
+    /// How many chat completion choices to generate for each input message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<u8>,
+
     /// Up to 4 sequences where the API will stop generating further tokens.
     #[serde_as(as = "Option<OneOrMany<_>>")]
     #[serde(alias = "stop_sequences")]
@@ -535,6 +586,10 @@ pub struct OpenAiModelParams {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub response_format: Option<ResponseFormat>,
+
+    /// Controls which (if any) tool is called by the model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Validate)]
@@ -685,6 +740,10 @@ pub struct BedrockModelParams {
     /// A list of stop sequences. A stop sequence is a sequence of characters that causes the model to stop generating the response.
     #[serde(alias = "stop")]
     pub stop_sequences: Option<Vec<String>>,
+    /// Which (if any) tool the model must call. Converse has no mode preventing tool calls,
+    /// so `none` leaves the tools out of the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
     #[serde(flatten)]
     pub additional_parameters: HashMap<String, Value>,
 }
@@ -773,6 +832,9 @@ pub struct AnthropicModelParams {
     pub top_k: Option<claude::TopK>,
 
     pub thinking: Option<claude::Thinking>,
+    /// How the model should use the provided tools.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -792,6 +854,10 @@ pub struct GeminiModelParams {
     pub response_logprobs: Option<bool>,
     pub logprobs: Option<i32>,
     pub response_format: Option<ResponseFormat>,
+    #[serde(default)]
+    pub safety_settings: Option<Vec<SafetySetting>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -924,3 +990,25 @@ pub struct ImageGenerationModelDefinition {
     pub engine: ImageGenerationEngineParams,
     pub db_model: Model,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_sentinel() {
+        let mut stop_sentinel = StopSentinel {
+            sentinel: "<DONE>".to_string(),
+            strip: false,
+        };
+        assert!(stop_sentinel.is_reached("Answer: 42 <DONE>"));
+        assert!(!stop_sentinel.is_reached("Answer: 42"));
+        assert_eq!(stop_sentinel.apply("42 <DONE>"), "42 <DONE>");
+
+        stop_sentinel.strip = true;
+        assert_eq!(stop_sentinel.apply("42 <DONE>"), "42");
+
+        stop_sentinel.sentinel = String::new();
+        assert!(!stop_sentinel.is_reached("Answer: 42"));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::model::tools::Tool;
+use crate::models::DeploymentStrategy;
 use crate::types::cache::ResponseCacheOptions;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -10,7 +11,8 @@ use thiserror::Error;
 pub use async_openai::types::ResponseFormat as OpenaiResponseFormat;
 pub use async_openai::types::ResponseFormatJsonSchema;
 
-use super::engine::ModelTool;
+use super::embed::EmbeddingInputType;
+use super::engine::{ModelTool, StopSentinel};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ChatCompletionRequest {
@@ -25,11 +27,18 @@ pub struct ChatCompletionRequest {
     pub n: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// A single sequence or a list
+    #[serde(
+        default,
+        deserialize_with = "deserialize_stop",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub stop: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub presence_penalty: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub frequency_penalty: Option<f32>,
@@ -41,6 +50,10 @@ pub struct ChatCompletionRequest {
     pub response_format: Option<async_openai::types::ResponseFormat>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u8>,
     // Keeping functions for backward compatibility
     #[serde(skip_serializing_if = "Option::is_none")]
     pub functions: Option<Vec<ChatCompletionFunction>>,
@@ -50,9 +63,23 @@ pub struct ChatCompletionRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<ChatCompletionTool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_choice: Option<Value>,
+    pub tool_choice: Option<ToolChoice>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream_options: Option<StreamOptions>,
+    /// Safety thresholds per harm category, ignored by providers without configurable safety
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safety_settings: Option<Vec<SafetySetting>>,
+}
+
+fn deserialize_stop<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let stop = Option::<Input>::deserialize(deserializer)?;
+    Ok(stop.map(|stop| match stop {
+        Input::String(s) => vec![s],
+        Input::Array(v) => v,
+    }))
 }
 
 impl ChatCompletionRequest {
@@ -60,6 +87,11 @@ impl ChatCompletionRequest {
         self.model = model;
         self
     }
+
+    /// Output token limit sent either as `max_tokens` or `max_completion_tokens`
+    pub fn output_tokens_limit(&self) -> Option<u32> {
+        self.max_tokens.or(self.max_completion_tokens)
+    }
 }
 
 impl Hash for ChatCompletionRequest {
@@ -74,7 +106,7 @@ pub struct Thinking {
     pub budget_tokens: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Extra {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<RequestUser>,
@@ -87,6 +119,209 @@ pub struct Extra {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub variables: Option<HashMap<String, serde_json::Value>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_filter_retry: Option<ContentFilterRetry>,
+
+    /// Ordered rewrites applied to streamed content
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<StreamTransformDefinition>,
+
+    /// Skip incremental tool call deltas and emit all tool calls, ordered by index, at the end of the stream
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ordered_tool_calls: bool,
+
+    /// Stops the agent loop when the model outputs the sentinel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sentinel: Option<StopSentinel>,
+
+    /// Models exposed to the model as tools, each call re-enters the gateway with a nested request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delegates: Vec<DelegateDefinition>,
+
+    /// Generates several variants, each with a temperature sampled from the distribution
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature_sampling: Option<TemperatureSampling>,
+
+    /// Approximate frequency and presence penalties with an instruction for providers without them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub emulate_penalties: bool,
+
+    /// Summarizes older messages once the conversation nears the context limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summarization: Option<ConversationSummarization>,
+
+    /// Continues generation while a normally finished output is shorter than the minimum
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_output: Option<MinOutputTokens>,
+
+    /// Injects context from the configured retriever before the last user message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retrieval: Option<RetrievalOptions>,
+
+    /// Keeps the requested model when a downgrade policy would route to a cheaper one
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disable_downgrade: bool,
+
+    /// Requests token log probabilities and returns their aggregate as `confidence`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<ConfidenceOptions>,
+
+    /// Overrides the configured strategy for picking among deployments of the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deployment_strategy: Option<DeploymentStrategy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalOptions {
+    #[serde(default = "default_retrieval_top_k")]
+    pub top_k: usize,
+    /// Return the retrieved sources as `citations` on non streaming responses
+    #[serde(default)]
+    pub citations: bool,
+}
+
+fn default_retrieval_top_k() -> usize {
+    4
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidenceMethod {
+    /// Mean log probability of the output tokens
+    #[default]
+    MeanLogprob,
+    /// Log probability of the least likely output token
+    MinLogprob,
+    /// Exponential of the negative mean log probability, lower is more confident
+    Perplexity,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfidenceOptions {
+    #[serde(default)]
+    pub method: ConfidenceMethod,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConfidenceScore {
+    pub method: ConfidenceMethod,
+    pub score: f64,
+    /// Number of tokens the score is computed from
+    pub tokens: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Citation {
+    /// Number the context was given in the prompt
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinOutputTokens {
+    /// Completion tokens expected before the output is accepted
+    pub min_tokens: u32,
+    /// Upper bound of continuation requests
+    #[serde(default = "default_max_continuations")]
+    pub max_continuations: u32,
+}
+
+fn default_max_continuations() -> u32 {
+    2
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummarization {
+    /// Model that writes the summary
+    pub model: String,
+    /// Fraction of the context window that triggers summarization
+    #[serde(default = "default_summarization_threshold")]
+    pub threshold: f32,
+    /// Most recent messages kept verbatim
+    #[serde(default = "default_keep_recent")]
+    pub keep_recent: usize,
+}
+
+fn default_summarization_threshold() -> f32 {
+    0.8
+}
+
+fn default_keep_recent() -> usize {
+    6
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemperatureSampling {
+    pub variants: u32,
+    pub distribution: TemperatureDistribution,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TemperatureDistribution {
+    Uniform {
+        min: f32,
+        max: f32,
+    },
+    Weighted {
+        temperatures: Vec<WeightedTemperature>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightedTemperature {
+    pub temperature: f32,
+    pub weight: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DelegateDefinition {
+    /// Tool name shown to the calling model
+    pub name: String,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// System prompt of the nested request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    /// Output token limit of each nested call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamTransformDefinition {
+    Replace {
+        rules: Vec<ReplaceRule>,
+        #[serde(default)]
+        case_insensitive: bool,
+    },
+    Casing {
+        terms: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaceRule {
+    pub from: String,
+    pub to: String,
+}
+
+/// Opt-in handling for requests refused by a provider content filter.
+/// The request is retried at most once, either on `fallback_model` or
+/// with a softening system instruction prepended.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ContentFilterRetry {
+    #[serde(default)]
+    pub soften: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_model: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -137,6 +372,45 @@ pub struct ProviderSpecificRequest {
     pub top_k: Option<u32>,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyCategory {
+    #[serde(alias = "HARM_CATEGORY_HARASSMENT")]
+    Harassment,
+    #[serde(alias = "HARM_CATEGORY_HATE_SPEECH")]
+    HateSpeech,
+    #[serde(alias = "HARM_CATEGORY_SEXUALLY_EXPLICIT")]
+    SexuallyExplicit,
+    #[serde(alias = "HARM_CATEGORY_DANGEROUS_CONTENT")]
+    DangerousContent,
+    #[serde(alias = "HARM_CATEGORY_CIVIC_INTEGRITY")]
+    CivicIntegrity,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyThreshold {
+    #[serde(alias = "BLOCK_NONE")]
+    BlockNone,
+    #[serde(alias = "BLOCK_ONLY_HIGH")]
+    BlockOnlyHigh,
+    #[default]
+    #[serde(alias = "BLOCK_MEDIUM_AND_ABOVE")]
+    BlockMediumAndAbove,
+    #[serde(alias = "BLOCK_LOW_AND_ABOVE")]
+    BlockLowAndAbove,
+    #[serde(alias = "OFF")]
+    Off,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SafetySetting {
+    pub category: SafetyCategory,
+    #[serde(default)]
+    pub threshold: SafetyThreshold,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RequestUser {
     #[serde(alias = "user_id")]
@@ -161,6 +435,10 @@ pub struct DynamicRouter<T> {
     pub targets: Vec<HashMap<String, serde_json::Value>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// Time limit in milliseconds for all targets together, a target can limit its own
+    /// attempt with a `timeout_ms` key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline_ms: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -386,6 +664,54 @@ pub struct ChatCompletionTool {
     pub function: ChatCompletionFunction,
 }
 
+/// Whether and which tool the model must call, `"auto"`, `"none"`, `"required"` or
+/// `{"type": "function", "function": {"name": ...}}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ToolChoice {
+    Mode(ToolChoiceMode),
+    Function(NamedToolChoice),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolChoiceMode {
+    None,
+    Auto,
+    Required,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NamedToolChoice {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: ToolChoiceFunction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolChoiceFunction {
+    pub name: String,
+}
+
+impl ToolChoice {
+    /// Function the model is forced to call
+    pub fn function_name(&self) -> Option<&str> {
+        match self {
+            ToolChoice::Function(choice) => Some(&choice.function.name),
+            ToolChoice::Mode(_) => None,
+        }
+    }
+
+    /// Choice of a call continuing after tool results. Forcing a tool call again would
+    /// never let the model answer, so forced choices fall back to `auto`.
+    pub fn after_tool_results(&self) -> ToolChoice {
+        match self {
+            ToolChoice::Mode(ToolChoiceMode::None) => self.clone(),
+            _ => ToolChoice::Mode(ToolChoiceMode::Auto),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatCompletionResponse {
     pub id: String,
@@ -396,6 +722,14 @@ pub struct ChatCompletionResponse {
     pub usage: ChatCompletionUsage,
     #[serde(skip_serializing)]
     pub is_cache_used: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub citations: Option<Vec<Citation>>,
+    /// Aggregate of the output token log probabilities, absent for providers without them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<ConfidenceScore>,
+    /// Backend configuration of the provider, a change can alter the outputs for a `seed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -403,6 +737,30 @@ pub struct ChatCompletionChoice {
     pub index: i32,
     pub message: ChatCompletionMessage,
     pub finish_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<ChatCompletionLogprobs>,
+}
+
+/// Output token log probabilities, returned when the request sets `logprobs`
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ChatCompletionLogprobs {
+    pub content: Option<Vec<ChatCompletionTokenLogprob>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatCompletionTokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+    pub bytes: Option<Vec<u8>>,
+    /// Most likely tokens at this position, as many as `top_logprobs`
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f32,
+    pub bytes: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -475,7 +833,7 @@ pub struct ChatCompletionChunkChoice {
     pub index: i32,
     pub delta: ChatCompletionDelta,
     pub finish_reason: Option<String>,
-    pub logprobs: Option<String>,
+    pub logprobs: Option<ChatCompletionLogprobs>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -486,6 +844,9 @@ pub struct ChatCompletionDelta {
     pub content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
+    /// Log probabilities of the delta tokens, sent on the chunk choice
+    #[serde(skip)]
+    pub logprobs: Option<ChatCompletionLogprobs>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -493,6 +854,108 @@ pub struct StreamOptions {
     pub include_usage: bool,
 }
 
+/// Legacy `/completions` request. Prompts are executed as a single user message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionRequest {
+    pub model: String,
+    pub prompt: Input,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Input>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<HashMap<String, serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub echo: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extra: Option<Extra>,
+}
+
+impl CompletionRequest {
+    pub fn prompts(&self) -> Vec<String> {
+        match &self.prompt {
+            Input::String(prompt) => vec![prompt.clone()],
+            Input::Array(prompts) => prompts.clone(),
+        }
+    }
+
+    pub fn to_chat_request<T>(&self, prompt: String) -> ChatCompletionRequestWithTools<T> {
+        ChatCompletionRequestWithTools {
+            request: ChatCompletionRequest {
+                model: self.model.clone(),
+                messages: vec![ChatCompletionMessage::new_text("user".to_string(), prompt)],
+                temperature: self.temperature,
+                top_p: self.top_p,
+                n: None,
+                stream: self.stream,
+                stop: self.stop.clone().map(|stop| match stop {
+                    Input::String(s) => vec![s],
+                    Input::Array(v) => v,
+                }),
+                max_tokens: self.max_tokens,
+                max_completion_tokens: None,
+                presence_penalty: self.presence_penalty,
+                frequency_penalty: self.frequency_penalty,
+                logit_bias: self.logit_bias.clone(),
+                user: self.user.clone(),
+                response_format: None,
+                seed: self.seed,
+                logprobs: None,
+                top_logprobs: None,
+                functions: None,
+                function_call: None,
+                tools: None,
+                tool_choice: None,
+                stream_options: self.stream_options.clone(),
+                safety_settings: None,
+            },
+            mcp_servers: None,
+            router: None,
+            max_retries: self.max_retries,
+            extra: self.extra.clone(),
+            fallbacks: None,
+            provider_specific: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<CompletionChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ChatCompletionUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionChoice {
+    pub text: String,
+    pub index: i32,
+    pub logprobs: Option<Value>,
+    pub finish_reason: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct CompletionModelUsage {
     pub input_tokens: u32,
@@ -503,6 +966,66 @@ pub struct CompletionModelUsage {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub completion_tokens_details: Option<CompletionTokensDetails>,
     pub is_cache_used: bool,
+    /// Counted by the gateway because the provider did not report the usage
+    #[serde(default)]
+    pub is_estimated: bool,
+}
+
+impl CompletionModelUsage {
+    /// Input tokens read from the provider prompt cache, billed at the cached rate
+    pub fn cached_input_tokens(&self) -> u32 {
+        self.prompt_tokens_details
+            .as_ref()
+            .map_or(0, |p| p.cached_tokens())
+    }
+
+    /// Input tokens written to the provider prompt cache, billed at the cache write rate
+    pub fn cache_creation_tokens(&self) -> u32 {
+        self.prompt_tokens_details
+            .as_ref()
+            .map_or(0, |p| p.cache_creation_tokens())
+    }
+
+    /// Input tokens billed at the full rate
+    pub fn uncached_input_tokens(&self) -> u32 {
+        self.input_tokens
+            .saturating_sub(self.cached_input_tokens())
+            .saturating_sub(self.cache_creation_tokens())
+    }
+
+    /// Adds the usage of another model call of the same request
+    pub fn add(&mut self, other: &CompletionModelUsage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.total_tokens += other.total_tokens;
+        self.prompt_tokens_details = match (
+            self.prompt_tokens_details.take(),
+            &other.prompt_tokens_details,
+        ) {
+            (Some(a), Some(b)) => Some(PromptTokensDetails {
+                cached_tokens: a.cached_tokens + b.cached_tokens,
+                cache_creation_tokens: a.cache_creation_tokens + b.cache_creation_tokens,
+                audio_tokens: a.audio_tokens + b.audio_tokens,
+            }),
+            (a, b) => a.or_else(|| b.clone()),
+        };
+        self.completion_tokens_details = match (
+            self.completion_tokens_details.take(),
+            &other.completion_tokens_details,
+        ) {
+            (Some(a), Some(b)) => Some(CompletionTokensDetails {
+                accepted_prediction_tokens: a.accepted_prediction_tokens
+                    + b.accepted_prediction_tokens,
+                audio_tokens: a.audio_tokens + b.audio_tokens,
+                reasoning_tokens: a.reasoning_tokens + b.reasoning_tokens,
+                rejected_prediction_tokens: a.rejected_prediction_tokens
+                    + b.rejected_prediction_tokens,
+            }),
+            (a, b) => a.or_else(|| b.clone()),
+        };
+        self.is_cache_used &= other.is_cache_used;
+        self.is_estimated |= other.is_estimated;
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -513,6 +1036,16 @@ pub struct ImageGenerationModelUsage {
     pub steps_count: u8,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TranscriptionModelUsage {
+    pub duration_seconds: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RerankModelUsage {
+    pub documents_count: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PromptTokensDetails {
     cached_tokens: u32,
@@ -609,6 +1142,8 @@ pub enum ImageCostCalculationResult {
 pub enum Usage {
     CompletionModelUsage(CompletionModelUsage),
     ImageGenerationModelUsage(ImageGenerationModelUsage),
+    TranscriptionModelUsage(TranscriptionModelUsage),
+    RerankModelUsage(RerankModelUsage),
 }
 
 #[async_trait::async_trait]
@@ -637,6 +1172,15 @@ pub struct CreateEmbeddingRequest {
     pub dimensions: Option<u16>,
     #[serde(default)]
     pub encoding_format: EncodingFormat,
+    /// Return successful embeddings with per-input errors instead of failing the whole request
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub best_effort: bool,
+    /// L2-normalize returned vectors
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub normalize: bool,
+    /// Whether the input is a search query or a document, used by Cohere and Voyage models
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_type: Option<EmbeddingInputType>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -645,6 +1189,14 @@ pub struct CreateEmbeddingResponse {
     pub data: Vec<EmbeddingData>,
     pub model: String,
     pub usage: EmbeddingUsage,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<EmbeddingError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingError {
+    pub index: u32,
+    pub error: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -654,6 +1206,42 @@ pub struct EmbeddingData {
     pub index: u32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateRerankRequest {
+    pub model: String,
+    pub query: String,
+    pub documents: Vec<String>,
+    /// Only the most relevant documents are returned, all of them by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_n: Option<usize>,
+    /// Include the text of every document in the results
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub return_documents: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateRerankResponse {
+    pub object: String,
+    pub model: String,
+    /// Sorted by descending relevance
+    pub results: Vec<RerankResult>,
+    pub usage: RerankUsage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankResult {
+    /// Position of the document in the request
+    pub index: usize,
+    pub relevance_score: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankUsage {
+    pub documents: u32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddingUsage {
     pub prompt_tokens: u32,
@@ -685,6 +1273,50 @@ pub struct CreateImageRequest {
     pub style: Option<ImageStyle>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
+    /// Uploaded images for edits and variations, generations have none
+    #[serde(skip)]
+    pub source: Option<ImageSource>,
+}
+
+/// File uploaded through a multipart form
+#[derive(Debug, Clone)]
+pub struct FileUpload {
+    pub file_name: String,
+    pub data: bytes::Bytes,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateTranscriptionRequest {
+    pub model: String,
+    /// Audio to transcribe, sent to the provider as is
+    #[serde(skip)]
+    pub file: FileUpload,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    pub response_format: TranscriptionResponseFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptionResponseFormat {
+    #[default]
+    Json,
+    VerboseJson,
+}
+
+#[derive(Debug, Clone)]
+pub enum ImageSource {
+    Edit {
+        image: FileUpload,
+        mask: Option<FileUpload>,
+    },
+    Variation {
+        image: FileUpload,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -838,6 +1470,31 @@ impl From<CacheControlTtl> for clust::messages::CacheTtl {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_completion_request_to_chat() {
+        let request: CompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "openai/gpt-4o-mini",
+            "prompt": ["Say hello", "Say bye"],
+            "max_tokens": 16,
+            "stop": "\n",
+            "seed": 7,
+        }))
+        .unwrap();
+        assert_eq!(request.prompts(), vec!["Say hello", "Say bye"]);
+
+        let chat = request.to_chat_request::<()>("Say bye".to_string());
+        assert_eq!(chat.request.model, "openai/gpt-4o-mini");
+        assert_eq!(chat.request.messages.len(), 1);
+        assert_eq!(chat.request.messages[0].role, "user");
+        assert_eq!(
+            chat.request.messages[0].content,
+            Some(ChatCompletionContent::Text("Say bye".to_string()))
+        );
+        assert_eq!(chat.request.max_tokens, Some(16));
+        assert_eq!(chat.request.stop, Some(vec!["\n".to_string()]));
+        assert_eq!(chat.request.seed, Some(7));
+    }
+
     #[test]
     fn test_contents() {
         let content = ChatCompletionContent::Content(vec![
@@ -991,4 +1648,83 @@ mod tests {
         assert_eq!(cache_control.r#type, CacheControlType::Ephemeral);
         assert_eq!(cache_control.ttl, Some(CacheControlTtl::OneHour));
     }
+
+    #[test]
+    fn test_safety_settings_deserialization() {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gemini-1.5-pro",
+            "safety_settings": [
+                {"category": "hate_speech", "threshold": "block_none"},
+                {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_ONLY_HIGH"},
+                {"category": "harassment"}
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            request.safety_settings,
+            Some(vec![
+                SafetySetting {
+                    category: SafetyCategory::HateSpeech,
+                    threshold: SafetyThreshold::BlockNone,
+                },
+                SafetySetting {
+                    category: SafetyCategory::DangerousContent,
+                    threshold: SafetyThreshold::BlockOnlyHigh,
+                },
+                SafetySetting {
+                    category: SafetyCategory::Harassment,
+                    threshold: SafetyThreshold::BlockMediumAndAbove,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_safety_settings_reject_unknown_category() {
+        let request = serde_json::from_value::<ChatCompletionRequest>(serde_json::json!({
+            "model": "gemini-1.5-pro",
+            "safety_settings": [{"category": "violence", "threshold": "block_none"}]
+        }));
+
+        assert!(request.is_err());
+    }
+
+    #[test]
+    fn test_completion_usage_cache_breakdown() {
+        let usage = CompletionModelUsage {
+            input_tokens: 1000,
+            output_tokens: 50,
+            total_tokens: 1050,
+            prompt_tokens_details: Some(PromptTokensDetails::new(Some(800), Some(100), None)),
+            ..Default::default()
+        };
+
+        assert_eq!(usage.cached_input_tokens(), 800);
+        assert_eq!(usage.cache_creation_tokens(), 100);
+        assert_eq!(usage.uncached_input_tokens(), 100);
+        assert_eq!(CompletionModelUsage::default().uncached_input_tokens(), 0);
+    }
+
+    #[test]
+    fn test_tool_choice() {
+        let required: ToolChoice = serde_json::from_value(serde_json::json!("required")).unwrap();
+        assert_eq!(required, ToolChoice::Mode(ToolChoiceMode::Required));
+        assert_eq!(required.function_name(), None);
+
+        let named: ToolChoice = serde_json::from_value(serde_json::json!({
+            "type": "function",
+            "function": {"name": "get_weather"}
+        }))
+        .unwrap();
+        assert_eq!(named.function_name(), Some("get_weather"));
+        assert!(serde_json::from_value::<ToolChoice>(serde_json::json!("any")).is_err());
+
+        // Forced choices are dropped once tool results are sent back to the model
+        let auto = ToolChoice::Mode(ToolChoiceMode::Auto);
+        assert_eq!(required.after_tool_results(), auto);
+        assert_eq!(named.after_tool_results(), auto);
+        let none = ToolChoice::Mode(ToolChoiceMode::None);
+        assert_eq!(none.after_tool_results(), none);
+    }
 }
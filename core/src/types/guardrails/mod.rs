@@ -34,6 +34,9 @@ pub enum GuardError {
 
     #[error("Guard '{0}' not passed")]
     GuardNotPassed(String, GuardResult),
+
+    #[error("Content blocked: {0}")]
+    ContentBlocked(String),
 }
 
 impl ResponseError for GuardError {
@@ -45,6 +48,7 @@ impl ResponseError for GuardError {
             GuardError::GuardNotPassed(_, _) => {
                 crate::types::http::status::GuardValidationFailed::status_code()
             }
+            GuardError::ContentBlocked(_) => http::StatusCode::BAD_REQUEST,
         }
     }
 
@@ -74,6 +78,12 @@ impl ResponseError for GuardError {
                 };
                 guard_error.error_response()
             }
+            GuardError::ContentBlocked(reason) => {
+                HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "Content blocked",
+                    "reason": reason
+                }))
+            }
         }
     }
 }
@@ -116,7 +126,13 @@ pub enum GuardResult {
         confidence: Option<f64>,
     },
     /// Structured JSON result
-    Json { schema: Value, passed: bool },
+    Json {
+        schema: Value,
+        passed: bool,
+        /// Repaired response text when the original output was not valid JSON
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        repaired: Option<String>,
+    },
 }
 
 /// Base guard configuration shared by all guard types
@@ -142,6 +158,9 @@ pub enum Guard {
         #[serde(flatten)]
         config: GuardConfig,
         user_defined_schema: Value,
+        /// Attempt to repair malformed JSON before validating it
+        #[serde(default)]
+        repair_json: bool,
     },
     /// LLM-based guard that uses another LLM as a judge
     LlmJudge {
@@ -1,3 +1,4 @@
+pub mod audio;
 pub mod aws;
 pub mod cache;
 pub mod credentials;
@@ -94,6 +94,9 @@ impl std::fmt::Display for InferenceModelProvider {
 pub enum ModelPrice {
     Completion(CompletionModelPrice),
     Embedding(EmbeddingModelPrice),
+    // Before ImageGeneration, whose fields are all optional and would match any price
+    Audio(AudioModelPrice),
+    Rerank(RerankModelPrice),
     ImageGeneration(ImageGenerationPrice),
 }
 
@@ -112,6 +115,18 @@ pub struct CompletionModelPrice {
     pub valid_from: Option<NaiveDate>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioModelPrice {
+    pub per_minute: f64,
+    pub valid_from: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankModelPrice {
+    pub per_document: f64,
+    pub valid_from: Option<NaiveDate>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageGenerationPrice {
     pub type_prices: Option<HashMap<String, HashMap<String, f64>>>,
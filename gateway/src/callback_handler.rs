@@ -5,21 +5,23 @@ use tokio::sync::Mutex;
 use chrono::{DateTime, Utc};
 use langdb_core::usage::InMemoryStorage;
 use langdb_core::{
-    handler::CallbackHandlerFn, model::types::ModelEventType,
-    types::gateway::ImageGenerationModelUsage,
+    handler::CallbackHandlerFn,
+    model::types::ModelEventType,
+    types::gateway::{ImageGenerationModelUsage, RerankModelUsage, TranscriptionModelUsage},
 };
 
-use crate::{cost::GatewayCostCalculator, usage::update_usage};
+use crate::{cost::GatewayCostCalculator, sla::SlaTracker, usage::update_usage};
 
 pub fn init_callback_handler(
     storage: Arc<Mutex<InMemoryStorage>>,
     calculator: GatewayCostCalculator,
+    mut sla_tracker: Option<SlaTracker>,
 ) -> CallbackHandlerFn {
     let (tx, mut rx) = tokio::sync::broadcast::channel(100);
     let start_times = Arc::new(Mutex::new(HashMap::<String, DateTime<Utc>>::new()));
     let ttft_times = Arc::new(Mutex::new(HashMap::<String, i64>::new()));
 
-    let callback_handler = CallbackHandlerFn(Some(tx));
+    let callback_handler = CallbackHandlerFn(Some(tx), None);
 
     tokio::spawn({
         let start_times = start_times.clone();
@@ -92,6 +94,19 @@ pub fn init_callback_handler(
                                 (duration, ttft)
                             };
 
+                            if let Some(tracker) = sla_tracker.as_mut() {
+                                let breaches = tracker.record(
+                                    &model_name,
+                                    &finish_event.provider_name,
+                                    model_event.event.timestamp,
+                                    duration.map(|d| d as u64),
+                                    ttft.map(|t| t as u64),
+                                );
+                                for breach in &breaches {
+                                    tracker.fire_hooks(breach, &storage).await;
+                                }
+                            }
+
                             if let Some(model) = &model_event.model {
                                 let result = update_usage(
                                     storage.clone(),
@@ -139,6 +154,52 @@ pub fn init_callback_handler(
                                 }
                             }
                         }
+                        ModelEventType::RerankFinish(finish_event) => {
+                            if let Some(model) = &model_event.model {
+                                let result = update_usage(
+                                    storage.clone(),
+                                    &calculator,
+                                    &finish_event.model_name,
+                                    &model.provider_name,
+                                    Some(&langdb_core::types::gateway::Usage::RerankModelUsage(
+                                        RerankModelUsage {
+                                            documents_count: finish_event.documents_count,
+                                        },
+                                    )),
+                                    None,
+                                    None,
+                                )
+                                .await;
+
+                                if let Err(e) = result {
+                                    tracing::error!("Error setting model usage: {e}");
+                                }
+                            }
+                        }
+                        ModelEventType::TranscriptionFinish(finish_event) => {
+                            if let Some(model) = &model_event.model {
+                                let result = update_usage(
+                                    storage.clone(),
+                                    &calculator,
+                                    &finish_event.model_name,
+                                    &model.provider_name,
+                                    Some(
+                                        &langdb_core::types::gateway::Usage::TranscriptionModelUsage(
+                                            TranscriptionModelUsage {
+                                                duration_seconds: finish_event.duration_seconds,
+                                            },
+                                        ),
+                                    ),
+                                    None,
+                                    None,
+                                )
+                                .await;
+
+                                if let Err(e) = result {
+                                    tracing::error!("Error setting model usage: {e}");
+                                }
+                            }
+                        }
                         _ => {}
                     }
                 }
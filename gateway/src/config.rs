@@ -1,7 +1,41 @@
 use crate::cli;
 use crate::session::Credentials;
+use crate::sla::SlaConfig;
+use langdb_core::executor::audit_log::AuditLogConfig;
+use langdb_core::executor::batches::BatchConfig;
+use langdb_core::executor::chat_completion::backoff::RetryPolicy;
+use langdb_core::executor::chat_completion::downgrade::DowngradeConfig;
+use langdb_core::executor::chat_completion::idempotency::IdempotencyConfig;
+use langdb_core::executor::chat_completion::moderation::ModerationConfig;
+use langdb_core::executor::chat_completion::quirks::QuirksConfig;
+use langdb_core::executor::chat_completion::response_cache::ResponseCacheConfig;
+use langdb_core::executor::chat_completion::retrieval::RetrieverConfig;
+use langdb_core::executor::chat_completion::stop_sequences::StopSequencesConfig;
+use langdb_core::executor::chat_completion::stream_wrapper::StreamKeepAliveConfig;
+use langdb_core::executor::chat_completion::tool_emulation::ToolSupportConfig;
+use langdb_core::executor::circuit_breaker::CircuitBreakerConfig;
+use langdb_core::executor::deployments::DeploymentsConfig;
+use langdb_core::executor::embeddings::EmbeddingsConfig;
+use langdb_core::executor::image_storage::ImageStorageConfig;
+use langdb_core::executor::limiter::ModelWeightsConfig;
+use langdb_core::executor::prometheus::PrometheusConfig;
+use langdb_core::executor::provider_limiter::ProviderConcurrencyConfig;
+use langdb_core::executor::redaction::RedactionConfig;
+use langdb_core::executor::retry_budget::RetryBudgetConfig;
+use langdb_core::executor::size_metrics::SizeMetricsConfig;
+use langdb_core::executor::spend_budget::SpendBudgetConfig;
+use langdb_core::executor::tag_routing::TagRoutingConfig;
+use langdb_core::executor::user_hashing::UserHashingConfig;
 use langdb_core::executor::ProvidersConfig;
+use langdb_core::handler::cache::AdminConfig;
+use langdb_core::handler::middleware::key_rate_limit::KeyRateLimitConfig;
+use langdb_core::handler::middleware::memory_pressure::MemoryPressureConfig;
 use langdb_core::handler::middleware::rate_limit::RateLimiting;
+use langdb_core::handler::middleware::request_id::RequestIdConfig;
+use langdb_core::model::cost_accrual::CostAccrualConfig;
+use langdb_core::model::response_validation::ResponseValidationConfig;
+use langdb_core::model::token_timing::TokenTimingConfig;
+use langdb_core::telemetry::encryption::TraceEncryptionConfig;
 use langdb_core::types::credentials::ApiKeyCredentials;
 use langdb_core::types::guardrails::Guard;
 use minijinja::Environment;
@@ -45,6 +79,74 @@ pub struct Config {
     pub providers: Option<ProvidersConfig>,
     #[serde(default)]
     pub guards: Option<HashMap<String, Guard>>,
+    #[serde(default)]
+    pub sla: Option<SlaConfig>,
+    #[serde(default)]
+    pub model_weights: Option<ModelWeightsConfig>,
+    #[serde(default)]
+    pub embeddings: Option<EmbeddingsConfig>,
+    #[serde(default)]
+    pub user_hashing: Option<UserHashingConfig>,
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    #[serde(default)]
+    pub retry_budget: Option<RetryBudgetConfig>,
+    #[serde(default)]
+    pub size_metrics: Option<SizeMetricsConfig>,
+    #[serde(default)]
+    pub response_validation: Option<ResponseValidationConfig>,
+    #[serde(default)]
+    pub token_timing: Option<TokenTimingConfig>,
+    #[serde(default)]
+    pub cost_accrual: Option<CostAccrualConfig>,
+    #[serde(default)]
+    pub retriever: Option<RetrieverConfig>,
+    #[serde(default)]
+    pub quirks: Option<QuirksConfig>,
+    #[serde(default)]
+    pub tool_support: Option<ToolSupportConfig>,
+    #[serde(default)]
+    pub downgrade: Option<DowngradeConfig>,
+    #[serde(default)]
+    pub memory_pressure: Option<MemoryPressureConfig>,
+    #[serde(default)]
+    pub retry_policy: Option<RetryPolicy>,
+    #[serde(default)]
+    pub response_cache: Option<ResponseCacheConfig>,
+    #[serde(default)]
+    pub request_id: Option<RequestIdConfig>,
+    #[serde(default)]
+    pub trace_encryption: Option<TraceEncryptionConfig>,
+    #[serde(default)]
+    pub deployments: Option<DeploymentsConfig>,
+    #[serde(default)]
+    pub key_rate_limit: Option<KeyRateLimitConfig>,
+    #[serde(default)]
+    pub moderation: Option<ModerationConfig>,
+    #[serde(default)]
+    pub stream_keep_alive: Option<StreamKeepAliveConfig>,
+    #[serde(default)]
+    pub spend_budget: Option<SpendBudgetConfig>,
+    #[serde(default)]
+    pub image_storage: Option<ImageStorageConfig>,
+    #[serde(default)]
+    pub idempotency: Option<IdempotencyConfig>,
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    #[serde(default)]
+    pub batches: Option<BatchConfig>,
+    #[serde(default)]
+    pub stop_sequences: Option<StopSequencesConfig>,
+    #[serde(default)]
+    pub audit_log: Option<AuditLogConfig>,
+    #[serde(default)]
+    pub redaction: Option<RedactionConfig>,
+    #[serde(default)]
+    pub provider_concurrency: Option<ProviderConcurrencyConfig>,
+    #[serde(default)]
+    pub tag_routing: Option<TagRoutingConfig>,
+    #[serde(default)]
+    pub prometheus: Option<PrometheusConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -1,6 +1,8 @@
 use langdb_core::{
     models::ModelMetadata,
-    pricing::calculator::{calculate_image_price, calculate_tokens_cost},
+    pricing::calculator::{
+        calculate_audio_price, calculate_image_price, calculate_rerank_price, calculate_tokens_cost,
+    },
     types::{
         gateway::{CostCalculationResult, CostCalculator, CostCalculatorError, Usage},
         provider::ModelPrice,
@@ -60,6 +62,24 @@ impl CostCalculator for GatewayCostCalculator {
                         ))
                     }
                 }
+                langdb_core::types::gateway::Usage::TranscriptionModelUsage(usage) => {
+                    if let Some(ModelPrice::Audio(p)) = &price {
+                        Ok(calculate_audio_price(p, usage))
+                    } else {
+                        Err(CostCalculatorError::CalculationError(
+                            "Audio model pricing are not set".to_string(),
+                        ))
+                    }
+                }
+                langdb_core::types::gateway::Usage::RerankModelUsage(usage) => {
+                    if let Some(ModelPrice::Rerank(p)) = &price {
+                        Ok(calculate_rerank_price(p, usage))
+                    } else {
+                        Err(CostCalculatorError::CalculationError(
+                            "Rerank model pricing are not set".to_string(),
+                        ))
+                    }
+                }
                 langdb_core::types::gateway::Usage::CompletionModelUsage(usage) => {
                     let (input_price, cached_input_price, cached_input_write_price, output_price) =
                         match price {
@@ -71,7 +91,9 @@ impl CostCalculator for GatewayCostCalculator {
                                     c.per_output_token,
                                 ),
                                 ModelPrice::Embedding(c) => (c.per_input_token, None, None, 0.0),
-                                ModelPrice::ImageGeneration(_) => {
+                                ModelPrice::Audio(_)
+                                | ModelPrice::Rerank(_)
+                                | ModelPrice::ImageGeneration(_) => {
                                     return Err(CostCalculatorError::CalculationError(
                                         "Model pricing not supported".to_string(),
                                     ))
@@ -90,7 +90,9 @@ impl GuardrailsService {
         executor_context: &ExecutorContext,
     ) -> Result<TracedGuard, String> {
         let evaluator = match &guard {
-            Guard::Schema { .. } => Box::new(SchemaEvaluator {}) as Box<dyn Evaluator>,
+            Guard::Schema { .. } => Box::new(SchemaEvaluator::new(
+                executor_context.callbackhandler.clone(),
+            )) as Box<dyn Evaluator>,
             Guard::LlmJudge { .. } => {
                 let factory = GuardModelFactory::new(executor_context.clone());
                 let evaluator = LlmJudgeEvaluator::new(
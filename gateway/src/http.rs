@@ -5,6 +5,7 @@ use crate::guardrails::GuardrailsService;
 use crate::limit::GatewayLimitChecker;
 use crate::middleware::trace_logger::TraceLogger;
 use crate::otel::DummyTraceWritterTransport;
+use crate::sla::SlaTracker;
 use actix_cors::Cors;
 use actix_web::Scope as ActixScope;
 use actix_web::{
@@ -16,19 +17,71 @@ use actix_web::{
 use futures::{future::try_join, Future, TryFutureExt};
 use langdb_core::database::clickhouse::ClickhouseHttp;
 use langdb_core::database::DatabaseTransportClone;
+use langdb_core::executor::audit_log::{AuditLog, JsonlFileSink};
+use langdb_core::executor::batches::Batches;
+use langdb_core::executor::chat_completion::backoff::RetryPolicy;
+use langdb_core::executor::chat_completion::downgrade::DowngradeConfig;
+use langdb_core::executor::chat_completion::idempotency::IdempotencyKeys;
+use langdb_core::executor::chat_completion::moderation::Moderation;
+use langdb_core::executor::chat_completion::quirks::QuirksConfig;
+use langdb_core::executor::chat_completion::response_cache::{
+    InMemoryResponseCache, ResponseCache,
+};
+use langdb_core::executor::chat_completion::retrieval::{HttpRetriever, Retriever};
+use langdb_core::executor::chat_completion::stop_sequences::StopSequencesConfig;
+use langdb_core::executor::chat_completion::stream_wrapper::StreamKeepAliveConfig;
+use langdb_core::executor::chat_completion::tool_emulation::ToolSupportConfig;
+use langdb_core::executor::circuit_breaker::CircuitBreakers;
+use langdb_core::executor::deployments::DeploymentSelector;
+use langdb_core::executor::embedding_cache::{EmbeddingCache, InMemoryEmbeddingCache};
+use langdb_core::executor::embedding_coalescing::EmbeddingCoalescer;
+use langdb_core::executor::embeddings::EmbeddingsConfig;
+use langdb_core::executor::image_storage::{ImageStore, InMemoryImageStore};
+use langdb_core::executor::limiter::ModelConcurrencyLimiter;
+use langdb_core::executor::prometheus::PrometheusMetrics;
+use langdb_core::executor::provider_limiter::ProviderConcurrencyLimiter;
+use langdb_core::executor::redaction::{RedactionError, Redactor};
+use langdb_core::executor::retry_budget::RetryBudget;
+use langdb_core::executor::size_metrics::SizeMetrics;
+use langdb_core::executor::spend_budget::{InMemoryBudgetStore, SpendBudget};
+use langdb_core::executor::tag_routing::TagRoutingConfig;
+use langdb_core::executor::user_hashing::UserHashingConfig;
 use langdb_core::executor::ProvidersConfig;
+use langdb_core::handler::audio::create_transcription;
+use langdb_core::handler::batches::{create_batch, get_batch, MAX_BATCH_FILE_BYTES};
+use langdb_core::handler::cache::{flush_cache, AdminConfig};
 use langdb_core::handler::chat::create_chat_completion;
+use langdb_core::handler::completions::create_completion;
 use langdb_core::handler::embedding::embeddings_handler;
-use langdb_core::handler::image::create_image;
+use langdb_core::handler::image::{
+    create_image, create_image_variation, edit_image, get_stored_image,
+};
+use langdb_core::handler::middleware::key_rate_limit::{
+    InMemoryBucketStore, KeyRateLimitMiddleware, KeyRateLimiter,
+};
+use langdb_core::handler::middleware::memory_pressure::{
+    MemoryPressureMiddleware, MemoryPressureMonitor,
+};
+use langdb_core::handler::middleware::provider_keys::ProviderKeysMiddleware;
 use langdb_core::handler::middleware::rate_limit::{RateLimitMiddleware, RateLimiting};
-use langdb_core::handler::models::list_gateway_models;
+use langdb_core::handler::middleware::request_id::{RequestIdConfig, RequestIdMiddleware};
+use langdb_core::handler::models::{
+    list_gateway_models, list_memory_metrics, list_models_utilization, list_size_metrics,
+    prometheus_metrics,
+};
+use langdb_core::handler::rerank::rerank_handler;
 use langdb_core::handler::{AvailableModels, CallbackHandlerFn, LimitCheckWrapper};
+use langdb_core::model::cost_accrual::CostAccrualConfig;
+use langdb_core::model::response_validation::ResponseValidationConfig;
+use langdb_core::model::token_timing::TokenTimingConfig;
 use langdb_core::models::ModelMetadata;
 use langdb_core::telemetry::database::DatabaseSpanWritter;
+use langdb_core::telemetry::encryption::{EncryptionError, TraceEncryption};
 use langdb_core::telemetry::DummyTraceTenantResolver;
 use langdb_core::telemetry::ProjectTraceMap;
 use langdb_core::telemetry::SpanWriterTransport;
 use langdb_core::telemetry::{TraceServiceImpl, TraceServiceServer};
+use langdb_core::types::cache::{CacheRegistry, FlushableCache};
 use langdb_core::types::gateway::CostCalculator;
 use langdb_core::types::guardrails::service::GuardrailsEvaluator;
 use langdb_core::types::guardrails::Guard;
@@ -55,6 +108,10 @@ pub enum ServerError {
     Tonic(#[from] tonic::transport::Error),
     #[error(transparent)]
     AddrParseError(#[from] std::net::AddrParseError),
+    #[error(transparent)]
+    TraceEncryption(#[from] EncryptionError),
+    #[error(transparent)]
+    Redaction(#[from] RedactionError),
 }
 
 #[derive(Clone, Debug)]
@@ -113,11 +170,149 @@ impl ApiServer {
         let server_config = self.clone();
 
         let cost_calculator = GatewayCostCalculator::new(models.clone());
-        let callback = if let Some(storage) = &storage {
-            init_callback_handler(storage.clone(), cost_calculator.clone())
+        let mut callback = if let Some(storage) = &storage {
+            init_callback_handler(
+                storage.clone(),
+                cost_calculator.clone(),
+                self.config.sla.clone().map(SlaTracker::new),
+            )
         } else {
-            CallbackHandlerFn(None)
+            CallbackHandlerFn(None, None)
         };
+        let audit_log = self.config.audit_log.clone().map(|audit_log| {
+            let sink = Arc::new(JsonlFileSink::new(audit_log.path.clone()));
+            AuditLog::start(audit_log, sink, &mut callback)
+        });
+        let prometheus = self.config.prometheus.clone().map(|config| {
+            let calculator = Box::new(cost_calculator.clone()) as Box<dyn CostCalculator>;
+            PrometheusMetrics::start(config, Arc::new(calculator), &mut callback)
+        });
+
+        let redactor = self
+            .config
+            .redaction
+            .as_ref()
+            .map(Redactor::new)
+            .transpose()?
+            .map(Arc::new);
+        // Events are redacted by the callback handler, spans by the trace writer
+        let callback = callback.with_redactor(redactor.clone());
+
+        let model_limiter = self
+            .config
+            .model_weights
+            .clone()
+            .map(|c| Arc::new(ModelConcurrencyLimiter::new(c)));
+
+        let provider_limiter = self
+            .config
+            .provider_concurrency
+            .clone()
+            .map(|c| Arc::new(ProviderConcurrencyLimiter::new(c)));
+
+        let retry_budget = self
+            .config
+            .retry_budget
+            .clone()
+            .map(|c| Arc::new(RetryBudget::new(c)));
+
+        let size_metrics = self
+            .config
+            .size_metrics
+            .clone()
+            .map(|c| Arc::new(SizeMetrics::new(c)));
+
+        let response_cache = self
+            .config
+            .response_cache
+            .clone()
+            .map(|c| Arc::new(InMemoryResponseCache::new(c)));
+        let embedding_cache = self
+            .config
+            .embeddings
+            .as_ref()
+            .and_then(|c| c.cache.clone())
+            .map(|c| Arc::new(InMemoryEmbeddingCache::new(c)));
+        let cache_registry = CacheRegistry(
+            response_cache
+                .iter()
+                .map(|c| c.clone() as Arc<dyn FlushableCache>)
+                .chain(
+                    embedding_cache
+                        .iter()
+                        .map(|c| c.clone() as Arc<dyn FlushableCache>),
+                )
+                .collect(),
+        );
+
+        let memory_pressure = self.config.memory_pressure.clone().map(|c| {
+            let monitor = Arc::new(MemoryPressureMonitor::new(c, cache_registry.clone()));
+            monitor.start();
+            monitor
+        });
+
+        let embedding_coalescer = self
+            .config
+            .embeddings
+            .as_ref()
+            .and_then(|c| c.coalescing.clone())
+            .map(|c| Arc::new(EmbeddingCoalescer::new(c)));
+
+        let retriever = self
+            .config
+            .retriever
+            .clone()
+            .map(|c| Arc::new(HttpRetriever::new(c)) as Arc<dyn Retriever>);
+
+        let deployment_selector = self
+            .config
+            .deployments
+            .clone()
+            .map(|c| Arc::new(DeploymentSelector::new(c)));
+
+        let moderation = self
+            .config
+            .moderation
+            .clone()
+            .map(|c| Arc::new(Moderation::from_config(c)));
+
+        let key_rate_limiter = self.config.key_rate_limit.clone().map(|c| {
+            Arc::new(KeyRateLimiter::new(
+                c,
+                Arc::new(InMemoryBucketStore::default()),
+            ))
+        });
+
+        let spend_budget = self.config.spend_budget.clone().map(|c| {
+            Arc::new(SpendBudget::new(
+                c,
+                Arc::new(InMemoryBudgetStore::default()),
+            ))
+        });
+
+        let image_store = self
+            .config
+            .image_storage
+            .clone()
+            .map(|c| Arc::new(InMemoryImageStore::new(c)) as Arc<dyn ImageStore>);
+
+        let idempotency = self
+            .config
+            .idempotency
+            .clone()
+            .map(|c| Arc::new(IdempotencyKeys::new(c)));
+
+        let circuit_breakers = self
+            .config
+            .circuit_breaker
+            .clone()
+            .map(|c| Arc::new(CircuitBreakers::new(c)));
+
+        let batches = self
+            .config
+            .batches
+            .clone()
+            .map(|c| Arc::new(Batches::new(c)));
 
         let server = HttpServer::new(move || {
             let limit_checker = if let Some(storage) = storage.clone() {
@@ -147,6 +342,42 @@ impl ApiServer {
                 limit_checker.clone(),
                 server_config.config.rate_limit.clone(),
                 providers_config,
+                model_limiter.clone(),
+                server_config.config.embeddings.clone(),
+                embedding_coalescer.clone(),
+                server_config.config.user_hashing.clone(),
+                server_config.config.admin.clone(),
+                retry_budget.clone(),
+                size_metrics.clone(),
+                server_config.config.response_validation.clone(),
+                server_config.config.token_timing.clone(),
+                server_config.config.cost_accrual.clone(),
+                retriever.clone(),
+                server_config.config.quirks.clone(),
+                server_config.config.tool_support.clone(),
+                server_config.config.downgrade.clone(),
+                memory_pressure.clone(),
+                server_config.config.retry_policy.clone(),
+                response_cache.clone().map(|c| c as Arc<dyn ResponseCache>),
+                cache_registry.clone(),
+                server_config.config.request_id.clone().unwrap_or_default(),
+                deployment_selector.clone(),
+                key_rate_limiter.clone(),
+                moderation.clone(),
+                server_config.config.stream_keep_alive.clone(),
+                spend_budget.clone(),
+                image_store.clone(),
+                embedding_cache
+                    .clone()
+                    .map(|c| c as Arc<dyn EmbeddingCache>),
+                idempotency.clone(),
+                circuit_breakers.clone(),
+                batches.clone(),
+                server_config.config.stop_sequences.clone(),
+                provider_limiter.clone(),
+                server_config.config.tag_routing.clone(),
+                prometheus.clone(),
+                audit_log.clone(),
             )
         })
         .bind((self.config.http.host.as_str(), self.config.http.port))?
@@ -161,10 +392,19 @@ impl ApiServer {
             None => Box::new(DummyTraceWritterTransport {}) as Box<dyn SpanWriterTransport>,
         };
 
+        let encryption = server_config
+            .config
+            .trace_encryption
+            .as_ref()
+            .map(TraceEncryption::new)
+            .transpose()?;
+
         let trace_service = TraceServiceServer::new(TraceServiceImpl::new(
             Arc::new(ProjectTraceMap::new()),
             writer,
             Box::new(DummyTraceTenantResolver),
+            encryption,
+            redactor,
         ));
         let tonic_server = tonic::transport::Server::builder()
             .add_service(trace_service)
@@ -191,6 +431,40 @@ impl ApiServer {
         limit_checker: Option<LimitCheckWrapper>,
         rate_limit: Option<RateLimiting>,
         providers: Option<ProvidersConfig>,
+        model_limiter: Option<Arc<ModelConcurrencyLimiter>>,
+        embeddings: Option<EmbeddingsConfig>,
+        embedding_coalescer: Option<Arc<EmbeddingCoalescer>>,
+        user_hashing: Option<UserHashingConfig>,
+        admin: Option<AdminConfig>,
+        retry_budget: Option<Arc<RetryBudget>>,
+        size_metrics: Option<Arc<SizeMetrics>>,
+        response_validation: Option<ResponseValidationConfig>,
+        token_timing: Option<TokenTimingConfig>,
+        cost_accrual: Option<CostAccrualConfig>,
+        retriever: Option<Arc<dyn Retriever>>,
+        quirks: Option<QuirksConfig>,
+        tool_support: Option<ToolSupportConfig>,
+        downgrade: Option<DowngradeConfig>,
+        memory_pressure: Option<Arc<MemoryPressureMonitor>>,
+        retry_policy: Option<RetryPolicy>,
+        response_cache: Option<Arc<dyn ResponseCache>>,
+        cache_registry: CacheRegistry,
+        request_id: RequestIdConfig,
+        deployment_selector: Option<Arc<DeploymentSelector>>,
+        key_rate_limiter: Option<Arc<KeyRateLimiter>>,
+        moderation: Option<Arc<Moderation>>,
+        stream_keep_alive: Option<StreamKeepAliveConfig>,
+        spend_budget: Option<Arc<SpendBudget>>,
+        image_store: Option<Arc<dyn ImageStore>>,
+        embedding_cache: Option<Arc<dyn EmbeddingCache>>,
+        idempotency: Option<Arc<IdempotencyKeys>>,
+        circuit_breakers: Option<Arc<CircuitBreakers>>,
+        batches: Option<Arc<Batches>>,
+        stop_sequences: Option<StopSequencesConfig>,
+        provider_limiter: Option<Arc<ProviderConcurrencyLimiter>>,
+        tag_routing: Option<TagRoutingConfig>,
+        prometheus: Option<Arc<PrometheusMetrics>>,
+        audit_log: Option<Arc<AuditLog>>,
     ) -> App<
         impl ServiceFactory<
             ServiceRequest,
@@ -211,6 +485,134 @@ impl ApiServer {
             service = service.app_data(providers.clone());
         }
 
+        if let Some(model_limiter) = model_limiter {
+            service = service.app_data(model_limiter);
+        }
+
+        if let Some(embeddings) = embeddings {
+            service = service.app_data(embeddings);
+        }
+
+        if let Some(embedding_coalescer) = embedding_coalescer {
+            service = service.app_data(embedding_coalescer);
+        }
+
+        if let Some(user_hashing) = user_hashing {
+            service = service.app_data(user_hashing);
+        }
+
+        if let Some(admin) = admin {
+            service = service.app_data(admin);
+        }
+
+        if let Some(retry_budget) = retry_budget {
+            service = service.app_data(retry_budget);
+        }
+
+        if let Some(size_metrics) = size_metrics {
+            service = service.app_data(size_metrics);
+        }
+
+        if let Some(response_validation) = response_validation {
+            service = service.app_data(response_validation);
+        }
+
+        if let Some(token_timing) = token_timing {
+            service = service.app_data(token_timing);
+        }
+
+        if let Some(cost_accrual) = cost_accrual {
+            service = service.app_data(cost_accrual);
+        }
+
+        if let Some(retriever) = retriever {
+            service = service.app_data(retriever);
+        }
+
+        if let Some(quirks) = quirks {
+            service = service.app_data(quirks);
+        }
+
+        if let Some(tool_support) = tool_support {
+            service = service.app_data(tool_support);
+        }
+
+        if let Some(downgrade) = downgrade {
+            service = service.app_data(downgrade);
+        }
+
+        if let Some(memory_pressure) = memory_pressure {
+            service = service.app_data(memory_pressure);
+        }
+
+        if let Some(retry_policy) = retry_policy {
+            service = service.app_data(retry_policy);
+        }
+
+        if let Some(response_cache) = response_cache {
+            service = service.app_data(response_cache);
+        }
+
+        if let Some(deployment_selector) = deployment_selector {
+            service = service.app_data(deployment_selector);
+        }
+
+        if let Some(key_rate_limiter) = key_rate_limiter {
+            service = service.app_data(key_rate_limiter);
+        }
+
+        if let Some(moderation) = moderation {
+            service = service.app_data(moderation);
+        }
+
+        if let Some(stream_keep_alive) = stream_keep_alive {
+            service = service.app_data(stream_keep_alive);
+        }
+
+        if let Some(spend_budget) = spend_budget {
+            service = service.app_data(spend_budget);
+        }
+
+        if let Some(image_store) = image_store {
+            service = service.app_data(image_store);
+        }
+
+        if let Some(embedding_cache) = embedding_cache {
+            service = service.app_data(embedding_cache);
+        }
+
+        if let Some(idempotency) = idempotency {
+            service = service.app_data(idempotency);
+        }
+
+        if let Some(circuit_breakers) = circuit_breakers {
+            service = service.app_data(circuit_breakers);
+        }
+
+        if let Some(batches) = batches {
+            service = service.app_data(batches);
+        }
+
+        if let Some(stop_sequences) = stop_sequences {
+            service = service.app_data(stop_sequences);
+        }
+
+        if let Some(provider_limiter) = provider_limiter {
+            service = service.app_data(provider_limiter);
+        }
+
+        if let Some(tag_routing) = tag_routing {
+            service = service.app_data(tag_routing);
+        }
+
+        if let Some(prometheus) = prometheus {
+            service = service.app_data(prometheus);
+        }
+
+        if let Some(audit_log) = audit_log {
+            service = service.app_data(audit_log);
+        }
+
         let guardrails_service = Box::new(GuardrailsService::new(guards.unwrap_or_default()))
             as Box<dyn GuardrailsEvaluator>;
         app.wrap(TraceLogger)
@@ -223,8 +625,13 @@ impl ApiServer {
                         Box::new(cost_calculator) as Box<dyn CostCalculator>
                     ))
                     .app_data(rate_limit)
+                    .app_data(cache_registry)
                     .app_data(Data::new(guardrails_service))
-                    .wrap(RateLimitMiddleware),
+                    .wrap(KeyRateLimitMiddleware)
+                    .wrap(RateLimitMiddleware)
+                    .wrap(MemoryPressureMiddleware)
+                    .wrap(ProviderKeysMiddleware)
+                    .wrap(RequestIdMiddleware::new(&request_id)),
             )
             .wrap(cors)
     }
@@ -242,8 +649,31 @@ impl ApiServer {
     fn attach_gateway_routes(scope: ActixScope) -> ActixScope {
         scope
             .route("/chat/completions", web::post().to(create_chat_completion))
+            .route("/completions", web::post().to(create_completion))
             .route("/models", web::get().to(list_gateway_models))
+            .route(
+                "/models/utilization",
+                web::get().to(list_models_utilization),
+            )
+            .route("/metrics", web::get().to(prometheus_metrics))
+            .route("/metrics/sizes", web::get().to(list_size_metrics))
+            .route("/metrics/memory", web::get().to(list_memory_metrics))
             .route("/embeddings", web::post().to(embeddings_handler))
+            .route("/rerank", web::post().to(rerank_handler))
             .route("/images/generations", web::post().to(create_image))
+            .route("/images/edits", web::post().to(edit_image))
+            .route("/images/variations", web::post().to(create_image_variation))
+            .route("/images/files/{id}", web::get().to(get_stored_image))
+            .route(
+                "/audio/transcriptions",
+                web::post().to(create_transcription),
+            )
+            .service(
+                web::resource("/batches")
+                    .app_data(web::PayloadConfig::new(MAX_BATCH_FILE_BYTES))
+                    .route(web::post().to(create_batch)),
+            )
+            .route("/batches/{id}", web::get().to(get_batch))
+            .route("/admin/cache/flush", web::post().to(flush_cache))
     }
 }
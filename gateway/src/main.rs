@@ -19,6 +19,7 @@ mod middleware;
 mod otel;
 mod run;
 mod session;
+mod sla;
 mod tracing;
 mod tui;
 mod usage;
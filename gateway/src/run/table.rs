@@ -48,6 +48,12 @@ fn get_price(price: ModelPrice) -> String {
         ModelPrice::Embedding(embedding_model_price) => {
             format!("${:.2}/1M", embedding_model_price.per_input_token)
         }
+        ModelPrice::Audio(audio_model_price) => {
+            format!("${:.4}/min", audio_model_price.per_minute)
+        }
+        ModelPrice::Rerank(rerank_model_price) => {
+            format!("${:.4}/document", rerank_model_price.per_document)
+        }
         ModelPrice::ImageGeneration(image_generation_price) => {
             if let Some(p) = image_generation_price.mp_price {
                 format!("${p:.2}/image")
@@ -0,0 +1,327 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use langdb_core::usage::{InMemoryStorage, LimitPeriod};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+pub const SLA_BREACHES: &str = "sla_breaches";
+
+/// Limit of a webhook call, a slow receiver must not hold the breach reports
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SlaConfig {
+    /// Threshold applied to models without an explicit entry
+    #[serde(default)]
+    pub default: Option<SlaThreshold>,
+    /// Per model thresholds, keyed by model name
+    #[serde(default)]
+    pub models: HashMap<String, SlaThreshold>,
+    #[serde(default = "default_hooks")]
+    pub hooks: Vec<SlaHook>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SlaThreshold {
+    pub p95_latency_ms: Option<u64>,
+    pub p95_ttft_ms: Option<u64>,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    #[serde(default = "default_min_samples")]
+    pub min_samples: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum SlaHook {
+    Log,
+    Webhook { url: String },
+    Metric,
+}
+
+fn default_hooks() -> Vec<SlaHook> {
+    vec![SlaHook::Log]
+}
+
+fn default_window_secs() -> u64 {
+    300
+}
+
+fn default_min_samples() -> usize {
+    20
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SlaMetric {
+    Latency,
+    Ttft,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SlaBreach {
+    pub model_name: String,
+    pub provider_name: String,
+    pub metric: SlaMetric,
+    pub p95_ms: u64,
+    pub threshold_ms: u64,
+    pub window_secs: u64,
+    pub samples: usize,
+}
+
+struct Sample {
+    timestamp: DateTime<Utc>,
+    latency: Option<u64>,
+    ttft: Option<u64>,
+}
+
+/// Keeps a rolling window of latency samples per model and reports
+/// when the p95 crosses the configured threshold.
+pub struct SlaTracker {
+    config: SlaConfig,
+    samples: HashMap<String, VecDeque<Sample>>,
+    breached: HashSet<(String, SlaMetric)>,
+    /// Shared by all webhook calls, so their connections are pooled
+    client: reqwest::Client,
+}
+
+impl SlaTracker {
+    pub fn new(config: SlaConfig) -> Self {
+        Self {
+            config,
+            samples: HashMap::new(),
+            breached: HashSet::new(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Reports the breach with every configured hook
+    pub(crate) async fn fire_hooks(
+        &self,
+        breach: &SlaBreach,
+        storage: &Arc<Mutex<InMemoryStorage>>,
+    ) {
+        fire_hooks(&self.config.hooks, &self.client, breach, storage).await
+    }
+
+    fn threshold(&self, model_name: &str) -> Option<&SlaThreshold> {
+        self.config
+            .models
+            .get(model_name)
+            .or(self.config.default.as_ref())
+    }
+
+    /// Records a finished request and returns breaches that started with this sample.
+    /// A breach is reported once and re-armed only after the p95 recovers.
+    pub fn record(
+        &mut self,
+        model_name: &str,
+        provider_name: &str,
+        timestamp: DateTime<Utc>,
+        latency: Option<u64>,
+        ttft: Option<u64>,
+    ) -> Vec<SlaBreach> {
+        let Some(threshold) = self.threshold(model_name).cloned() else {
+            return vec![];
+        };
+
+        let samples = self.samples.entry(model_name.to_string()).or_default();
+        samples.push_back(Sample {
+            timestamp,
+            latency,
+            ttft,
+        });
+
+        let window_start = timestamp - Duration::seconds(threshold.window_secs as i64);
+        while samples.front().is_some_and(|s| s.timestamp < window_start) {
+            samples.pop_front();
+        }
+
+        let checks = [
+            (SlaMetric::Latency, threshold.p95_latency_ms),
+            (SlaMetric::Ttft, threshold.p95_ttft_ms),
+        ];
+
+        let mut breaches = vec![];
+        for (metric, limit) in checks {
+            let Some(limit) = limit else {
+                continue;
+            };
+
+            let values: Vec<u64> = samples
+                .iter()
+                .filter_map(|s| match metric {
+                    SlaMetric::Latency => s.latency,
+                    SlaMetric::Ttft => s.ttft,
+                })
+                .collect();
+
+            let count = values.len();
+            if count < threshold.min_samples {
+                continue;
+            }
+
+            let Some(p95) = percentile(values, 0.95) else {
+                continue;
+            };
+
+            let key = (model_name.to_string(), metric);
+            if p95 > limit {
+                if self.breached.insert(key) {
+                    breaches.push(SlaBreach {
+                        model_name: model_name.to_string(),
+                        provider_name: provider_name.to_string(),
+                        metric,
+                        p95_ms: p95,
+                        threshold_ms: limit,
+                        window_secs: threshold.window_secs,
+                        samples: count,
+                    });
+                }
+            } else {
+                self.breached.remove(&key);
+            }
+        }
+
+        breaches
+    }
+}
+
+fn percentile(mut values: Vec<u64>, p: f64) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let rank = ((values.len() as f64) * p).ceil() as usize;
+    values.get(rank.saturating_sub(1)).copied()
+}
+
+async fn fire_hooks(
+    hooks: &[SlaHook],
+    client: &reqwest::Client,
+    breach: &SlaBreach,
+    storage: &Arc<Mutex<InMemoryStorage>>,
+) {
+    for hook in hooks {
+        match hook {
+            SlaHook::Log => {
+                tracing::warn!(
+                    target: "gateway::sla",
+                    "SLA breached for {}/{}: p95 {:?} {}ms > {}ms over {}s ({} samples)",
+                    breach.provider_name,
+                    breach.model_name,
+                    breach.metric,
+                    breach.p95_ms,
+                    breach.threshold_ms,
+                    breach.window_secs,
+                    breach.samples
+                );
+            }
+            SlaHook::Webhook { url } => {
+                let url = url.clone();
+                let breach = breach.clone();
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let result = client
+                        .post(&url)
+                        .timeout(WEBHOOK_TIMEOUT)
+                        .json(&breach)
+                        .send()
+                        .await;
+                    if let Err(e) = result {
+                        tracing::error!(target: "gateway::sla", "Failed to call SLA webhook {url}: {e}");
+                    }
+                });
+            }
+            SlaHook::Metric => {
+                let identifier = format!("{}:{}", breach.provider_name, breach.model_name);
+                storage
+                    .lock()
+                    .await
+                    .increment_and_get_value(&LimitPeriod::Total, &identifier, SLA_BREACHES, 1.0)
+                    .await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> SlaTracker {
+        SlaTracker::new(SlaConfig {
+            default: Some(SlaThreshold {
+                p95_latency_ms: Some(100),
+                p95_ttft_ms: None,
+                window_secs: 60,
+                min_samples: 2,
+            }),
+            models: HashMap::new(),
+            hooks: vec![],
+        })
+    }
+
+    #[test]
+    fn test_breach_reported_once() {
+        let mut tracker = tracker();
+        let now = Utc::now();
+        let mut record = |latency: u64, seconds: i64| {
+            tracker.record(
+                "gpt-4o",
+                "openai",
+                now + Duration::seconds(seconds),
+                Some(latency),
+                None,
+            )
+        };
+
+        assert!(record(500, 0).is_empty());
+        let breaches = record(500, 1);
+        assert_eq!(breaches.len(), 1);
+        assert_eq!(breaches[0].metric, SlaMetric::Latency);
+        assert_eq!(breaches[0].p95_ms, 500);
+        assert!(record(500, 2).is_empty());
+
+        // The old samples leave the window and the p95 recovers, which re-arms the breach
+        assert!(record(50, 100).is_empty());
+        assert!(record(50, 101).is_empty());
+        assert_eq!(record(500, 102).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_does_not_block() {
+        let breach = SlaBreach {
+            model_name: "gpt-4o".to_string(),
+            provider_name: "openai".to_string(),
+            metric: SlaMetric::Latency,
+            p95_ms: 500,
+            threshold_ms: 100,
+            window_secs: 60,
+            samples: 2,
+        };
+        let storage = Arc::new(Mutex::new(InMemoryStorage::new()));
+        let tracker = SlaTracker::new(SlaConfig {
+            hooks: vec![
+                // Non-routable address, the call only ends with the timeout
+                SlaHook::Webhook {
+                    url: "http://10.255.255.1/sla".to_string(),
+                },
+                SlaHook::Metric,
+            ],
+            ..Default::default()
+        });
+
+        let started = std::time::Instant::now();
+        tracker.fire_hooks(&breach, &storage).await;
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+
+        let storage = storage.lock().await;
+        assert_eq!(
+            storage.get_value(&LimitPeriod::Total, "openai:gpt-4o", SLA_BREACHES),
+            Some(1.0)
+        );
+    }
+}
@@ -91,7 +91,9 @@ pub(crate) async fn update_usage(
 
                 tracing::debug!(target:"gateway::usage", metrics = %serde_yaml::to_string(&metrics).unwrap());
             }
-            Usage::ImageGenerationModelUsage(_) => {}
+            Usage::ImageGenerationModelUsage(_)
+            | Usage::TranscriptionModelUsage(_)
+            | Usage::RerankModelUsage(_) => {}
         }
     }
 
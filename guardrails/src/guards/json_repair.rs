@@ -0,0 +1,145 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use serde_json::Value;
+
+/// Repairs common defects of model generated JSON: markdown code fences, trailing commas,
+/// unquoted keys, single quoted strings, Python literals and truncated output.
+/// Returns the parsed value only when the repaired text is valid JSON.
+pub fn repair_json(text: &str) -> Option<Value> {
+    let text = strip_code_fence(text.trim());
+    let start = text.find(['{', '['])?;
+
+    let mut out = String::with_capacity(text.len());
+    let mut stack = vec![];
+    let mut expect_key = false;
+    let mut pending_key = false;
+    let mut chars = text[start..].chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                let closed = read_string(c, &mut chars, &mut out);
+                pending_key = expect_key;
+                expect_key = false;
+                if !closed {
+                    break;
+                }
+            }
+            '{' | '[' => {
+                stack.push(c);
+                out.push(c);
+                expect_key = c == '{';
+            }
+            '}' | ']' => {
+                let open = if c == '}' { '{' } else { '[' };
+                // Unbalanced closing brackets are dropped
+                if stack.last() != Some(&open) {
+                    continue;
+                }
+                trim_trailing_comma(&mut out);
+                stack.pop();
+                out.push(c);
+                expect_key = false;
+                if stack.is_empty() {
+                    break;
+                }
+            }
+            ',' => {
+                out.push(c);
+                expect_key = stack.last() == Some(&'{');
+            }
+            ':' => {
+                out.push(c);
+                expect_key = false;
+                pending_key = false;
+            }
+            c if c.is_alphabetic() || c == '_' || c == '$' => {
+                let mut word = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_alphanumeric() || matches!(next, '_' | '-' | '$') {
+                        word.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                if expect_key {
+                    out.push('"');
+                    out.push_str(&word);
+                    out.push('"');
+                    expect_key = false;
+                    pending_key = true;
+                } else {
+                    out.push_str(match word.as_str() {
+                        "True" => "true",
+                        "False" => "false",
+                        "None" => "null",
+                        word => word,
+                    });
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    // Close whatever a truncated response left open
+    let trimmed_len = out.trim_end().len();
+    out.truncate(trimmed_len);
+    trim_trailing_comma(&mut out);
+    if pending_key {
+        out.push(':');
+    }
+    if out.ends_with(':') {
+        out.push_str("null");
+    }
+    while let Some(open) = stack.pop() {
+        trim_trailing_comma(&mut out);
+        out.push(if open == '{' { '}' } else { ']' });
+    }
+
+    serde_json::from_str(&out).ok()
+}
+
+fn strip_code_fence(text: &str) -> &str {
+    let Some(rest) = text.strip_prefix("```") else {
+        return text;
+    };
+    // Skip the language tag
+    let rest = rest.split_once('\n').map_or("", |(_, body)| body);
+    rest.trim_end().strip_suffix("```").unwrap_or(rest)
+}
+
+/// Copies a string literal as a double quoted JSON string, returns false if it is unterminated
+fn read_string(quote: char, chars: &mut Peekable<Chars>, out: &mut String) -> bool {
+    out.push('"');
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\'') => out.push('\''),
+                Some(escaped) => {
+                    out.push('\\');
+                    out.push(escaped);
+                }
+                None => break,
+            },
+            '"' if quote == '\'' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c if c == quote => {
+                out.push('"');
+                return true;
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    false
+}
+
+fn trim_trailing_comma(out: &mut String) {
+    let trimmed = out.trim_end();
+    if trimmed.ends_with(',') {
+        out.truncate(trimmed.len() - 1);
+    }
+}
@@ -1,5 +1,6 @@
 pub mod config;
 pub mod dataset;
+pub mod json_repair;
 pub mod llm_judge;
 pub mod partner;
 pub mod partners;
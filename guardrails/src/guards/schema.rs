@@ -1,9 +1,35 @@
 use jsonschema::{Draft, Validator};
+use langdb_core::handler::{CallbackHandlerFn, ModelEventWithDetails};
+use langdb_core::model::types::{CustomEvent, ModelEvent, ModelEventType};
 use langdb_core::types::gateway::ChatCompletionMessage;
 use langdb_core::types::guardrails::{evaluator::Evaluator, Guard, GuardResult};
 use serde_json::Value;
+use tracing::Span;
 
-pub struct SchemaEvaluator;
+use super::json_repair::repair_json;
+
+pub struct SchemaEvaluator {
+    pub callback_handler: CallbackHandlerFn,
+}
+
+impl SchemaEvaluator {
+    pub fn new(callback_handler: CallbackHandlerFn) -> Self {
+        Self { callback_handler }
+    }
+
+    fn on_repair(&self, guard: &Guard, repaired: bool) {
+        self.callback_handler.on_message(ModelEventWithDetails::new(
+            ModelEvent::new(
+                &Span::current(),
+                ModelEventType::Custom(CustomEvent::new(
+                    "json_repair".to_string(),
+                    serde_json::json!({ "guard_id": guard.id(), "repaired": repaired }),
+                )),
+            ),
+            None,
+        ));
+    }
+}
 
 #[async_trait::async_trait]
 impl Evaluator for SchemaEvaluator {
@@ -15,50 +41,66 @@ impl Evaluator for SchemaEvaluator {
         let text = self.messages_to_text(messages)?;
         if let Guard::Schema {
             user_defined_schema,
+            repair_json: repair,
             ..
         } = &guard
         {
             // Try to parse the text as JSON
             let json_result = serde_json::from_str::<Value>(&text);
 
-            match json_result {
-                Ok(json_value) => {
-                    // Compile the schema
-                    let compiled_schema = match Validator::options()
-                        .with_draft(Draft::Draft7)
-                        .build(user_defined_schema)
-                    {
-                        Ok(schema) => schema,
-                        Err(e) => {
-                            return Err(format!("Invalid schema definition: {e}"));
-                        }
-                    };
-
-                    let json_value_clone = json_value.clone();
-                    // Validate against the schema
-                    let validation_result = compiled_schema.validate(&json_value_clone);
-                    match validation_result {
-                        Ok(_) => Ok(GuardResult::Json {
-                            schema: json_value,
-                            passed: true,
-                        }),
-                        Err(error) => {
-                            let error_message = error.to_string();
-
-                            Ok(GuardResult::Text {
-                                text: error_message,
-                                passed: false,
-                                confidence: Some(1.0),
-                            })
-                        }
+            let (json_value, repaired) = match json_result {
+                Ok(json_value) => (json_value, false),
+                Err(e) if *repair => match repair_json(&text) {
+                    Some(json_value) => {
+                        self.on_repair(guard, true);
+                        (json_value, true)
                     }
-                }
+                    None => {
+                        self.on_repair(guard, false);
+                        tracing::error!("Invalid response JSON, repair failed: {}", e);
+                        return Ok(GuardResult::Text {
+                            text: e.to_string(),
+                            passed: false,
+                            confidence: Some(1.0),
+                        });
+                    }
+                },
                 Err(e) => {
                     tracing::error!("Invalid response JSON: {}", e);
-                    Ok(GuardResult::Text {
+                    return Ok(GuardResult::Text {
                         text: e.to_string(),
                         passed: false,
                         confidence: Some(1.0),
+                    });
+                }
+            };
+
+            // Compile the schema
+            let compiled_schema = match Validator::options()
+                .with_draft(Draft::Draft7)
+                .build(user_defined_schema)
+            {
+                Ok(schema) => schema,
+                Err(e) => {
+                    return Err(format!("Invalid schema definition: {e}"));
+                }
+            };
+
+            // Validate against the schema
+            let validation_result = compiled_schema.validate(&json_value);
+            match validation_result {
+                Ok(_) => Ok(GuardResult::Json {
+                    repaired: repaired.then(|| json_value.to_string()),
+                    schema: json_value,
+                    passed: true,
+                }),
+                Err(error) => {
+                    let error_message = error.to_string();
+
+                    Ok(GuardResult::Text {
+                        text: error_message,
+                        passed: false,
+                        confidence: Some(1.0),
                     })
                 }
             }
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use crate::guards::config::load_guards_from_yaml;
+use crate::guards::json_repair::repair_json;
 use crate::guards::llm_judge::LlmJudgeEvaluator;
 use langdb_core::model::types::ModelEvent;
 use langdb_core::model::ModelInstance;
@@ -11,7 +12,7 @@ use langdb_core::types::guardrails::evaluator::Evaluator;
 use langdb_core::types::guardrails::{Guard, GuardAction, GuardStage};
 use langdb_core::types::threads::Message;
 use langdb_core::GatewayResult;
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use super::llm_judge::GuardModelInstanceFactory;
 
@@ -103,6 +104,36 @@ fn test_load_guards_from_yaml() {
     }
 }
 
+#[test]
+fn test_repair_json() {
+    let cases = [
+        (
+            r#"{"name": "test", "tags": ["a", "b",],}"#,
+            json!({"name": "test", "tags": ["a", "b"]}),
+        ),
+        (
+            r#"{name: 'O"Brien', active: True}"#,
+            json!({"name": "O\"Brien", "active": true}),
+        ),
+        ("```json\n{\"count\": 2}\n```", json!({"count": 2})),
+        (
+            r#"Here you go: {"items": [{"id": 1}, {"id": 2"#,
+            json!({"items": [{"id": 1}, {"id": 2}]}),
+        ),
+        (
+            r#"{"text": "truncated respo"#,
+            json!({"text": "truncated respo"}),
+        ),
+        (r#"{"a": 1, "b"#, json!({"a": 1, "b": null})),
+    ];
+
+    for (input, expected) in cases {
+        assert_eq!(repair_json(input), Some(expected), "input: {input}");
+    }
+
+    assert_eq!(repair_json("no json here"), None);
+}
+
 #[tokio::test]
 async fn test_guard_evaluation() {
     // Load default guards